@@ -0,0 +1,83 @@
+// Copyright (c) 2023 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use tquic::frame;
+use tquic::frame::Frame;
+use tquic::ranges::RangeSet;
+use tquic::PacketType;
+
+/// Prepare ranges to be acknowledged with the given number of gaps.
+fn new_ack_ranges(count: u64) -> RangeSet {
+    let mut ranges = RangeSet::new(count as usize);
+    for i in 0..count {
+        ranges.insert(i * 10..i * 10 + 5);
+    }
+    ranges
+}
+
+pub fn ack_decode_benchmark(c: &mut Criterion) {
+    let mut buf = vec![0; 4096];
+    for count in [1, 16, 64, 256] {
+        let ranges = new_ack_ranges(count);
+        let len = frame::encode_ack_frame(100, &ranges, None, &mut buf).unwrap();
+        let data = Bytes::copy_from_slice(&buf[..len]);
+
+        c.bench_function(&format!("ack frame decode {} ranges", count), |b| {
+            b.iter(|| Frame::from_bytes(&mut data.clone(), PacketType::OneRTT).unwrap())
+        });
+    }
+}
+
+pub fn ack_ranges_benchmark(c: &mut Criterion) {
+    // Track packet numbers received in order.
+    c.bench_function("ack ranges in order", |b| {
+        b.iter(|| {
+            let mut ranges = RangeSet::new(68);
+            for pkt_num in 0..1000 {
+                ranges.add_elem(pkt_num);
+            }
+            ranges
+        })
+    });
+
+    // Track packet numbers received with a gap every ten packets.
+    c.bench_function("ack ranges with gaps", |b| {
+        b.iter(|| {
+            let mut ranges = RangeSet::new(68);
+            for pkt_num in (0..1000).filter(|n| n % 10 != 0) {
+                ranges.add_elem(pkt_num);
+            }
+            ranges
+        })
+    });
+
+    // Track packet numbers received in reverse order.
+    c.bench_function("ack ranges reordered", |b| {
+        b.iter(|| {
+            let mut ranges = RangeSet::new(68);
+            for pkt_num in (0..1000).rev() {
+                ranges.add_elem(pkt_num);
+            }
+            ranges
+        })
+    });
+}
+
+criterion_group!(benches, ack_decode_benchmark, ack_ranges_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,297 @@
+// Copyright (c) 2023 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+
+use bytes::Bytes;
+use bytes::BytesMut;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use criterion::Throughput;
+
+use tquic::BlockedEvent;
+use tquic::Config;
+use tquic::Connection;
+use tquic::Endpoint;
+use tquic::PacketInfo;
+use tquic::PacketSendHandler;
+use tquic::PersistentCongestionEvent;
+use tquic::StreamEvent;
+use tquic::TlsConfig;
+use tquic::TransportHandler;
+
+/// An in-memory link which holds the datagrams sent by an endpoint until
+/// they are delivered to the peer.
+#[derive(Default)]
+struct Link {
+    queue: RefCell<VecDeque<(Vec<u8>, PacketInfo)>>,
+}
+
+impl Link {
+    /// Deliver all the datagrams on the link to the given endpoint. If
+    /// `reorder` is true, each batch of datagrams is delivered in reverse
+    /// order to exercise the stream reassembly.
+    fn deliver(&self, endpoint: &mut Endpoint, reorder: bool) {
+        let mut pkts: Vec<_> = self.queue.borrow_mut().drain(..).collect();
+        if reorder {
+            pkts.reverse();
+        }
+        for (mut buf, mut info) in pkts {
+            info.time = Instant::now();
+            let _ = endpoint.recv(&mut buf, &info);
+        }
+    }
+}
+
+impl PacketSendHandler for Link {
+    fn on_packets_send(&self, pkts: &[(Vec<u8>, PacketInfo)]) -> tquic::Result<usize> {
+        self.queue.borrow_mut().extend(pkts.iter().cloned());
+        Ok(pkts.len())
+    }
+}
+
+/// The client writes the data on a stream and waits for the echo.
+struct ClientHandler {
+    data: Bytes,
+    written: usize,
+    received: usize,
+    stream_id: Option<u64>,
+    done: Rc<Cell<bool>>,
+}
+
+impl ClientHandler {
+    fn write(&mut self, conn: &mut Connection) {
+        let stream_id = match self.stream_id {
+            Some(v) => v,
+            None => return,
+        };
+        if self.written < self.data.len() {
+            let data = self.data.slice(self.written..);
+            if let Ok(written) = conn.stream_write(stream_id, data, true) {
+                self.written += written;
+            }
+        }
+    }
+}
+
+impl TransportHandler for ClientHandler {
+    fn on_conn_created(&mut self, _conn: &mut Connection) {}
+
+    fn on_conn_established(&mut self, conn: &mut Connection) {
+        self.stream_id = conn.stream_bidi_new(1, false).ok();
+        self.write(conn);
+    }
+
+    fn on_conn_closed(&mut self, _conn: &mut Connection) {}
+
+    fn on_stream_created(&mut self, _conn: &mut Connection, _stream_id: u64) {}
+
+    fn on_stream_readable(&mut self, conn: &mut Connection, stream_id: u64) {
+        let mut buf = vec![0; 16384];
+        while let Ok((len, fin)) = conn.stream_read(stream_id, &mut buf) {
+            self.received += len;
+            if fin {
+                assert_eq!(self.received, self.data.len());
+                let _ = conn.close(true, 0, b"");
+                self.done.set(true);
+                return;
+            }
+            if len == 0 {
+                return;
+            }
+        }
+    }
+
+    fn on_stream_writable(&mut self, conn: &mut Connection, _stream_id: u64) {
+        self.write(conn);
+    }
+
+    fn on_stream_closed(&mut self, _conn: &mut Connection, _stream_id: u64) {}
+
+    fn on_new_token(&mut self, _conn: &mut Connection, _token: Vec<u8>) {}
+
+    fn on_ping_acked(&mut self, _conn: &mut Connection, _tag: u64, _rtt: Duration) {}
+
+    fn on_flow_control_blocked(&mut self, _conn: &mut Connection, _event: BlockedEvent) {}
+
+    fn on_target_bitrate_updated(&mut self, _conn: &mut Connection, _bitrate: u64) {}
+
+    fn on_persistent_congestion(
+        &mut self,
+        _conn: &mut Connection,
+        _event: PersistentCongestionEvent,
+    ) {
+    }
+
+    fn on_stream_event(&mut self, _conn: &mut Connection, _stream_id: u64, _event: StreamEvent) {}
+}
+
+/// The server echoes the data received on each stream.
+#[derive(Default)]
+struct ServerHandler {
+    buf: BytesMut,
+    fin: bool,
+}
+
+impl ServerHandler {
+    fn write(&mut self, conn: &mut Connection, stream_id: u64) {
+        let cap = conn.stream_capacity(stream_id).unwrap_or(0);
+        let len = cmp::min(cap, self.buf.len());
+        if len == 0 && !(self.fin && self.buf.is_empty()) {
+            return;
+        }
+        let data = self.buf.split_to(len).freeze();
+        let fin = self.fin && self.buf.is_empty();
+        let _ = conn.stream_write(stream_id, data, fin);
+    }
+}
+
+impl TransportHandler for ServerHandler {
+    fn on_conn_created(&mut self, _conn: &mut Connection) {}
+
+    fn on_conn_established(&mut self, _conn: &mut Connection) {}
+
+    fn on_conn_closed(&mut self, _conn: &mut Connection) {}
+
+    fn on_stream_created(&mut self, _conn: &mut Connection, _stream_id: u64) {}
+
+    fn on_stream_readable(&mut self, conn: &mut Connection, stream_id: u64) {
+        let mut buf = vec![0; 16384];
+        while let Ok((len, fin)) = conn.stream_read(stream_id, &mut buf) {
+            self.buf.extend_from_slice(&buf[..len]);
+            self.fin |= fin;
+            if fin || len == 0 {
+                break;
+            }
+        }
+        self.write(conn, stream_id);
+    }
+
+    fn on_stream_writable(&mut self, conn: &mut Connection, stream_id: u64) {
+        self.write(conn, stream_id);
+    }
+
+    fn on_stream_closed(&mut self, _conn: &mut Connection, _stream_id: u64) {}
+
+    fn on_new_token(&mut self, _conn: &mut Connection, _token: Vec<u8>) {}
+
+    fn on_ping_acked(&mut self, _conn: &mut Connection, _tag: u64, _rtt: Duration) {}
+
+    fn on_flow_control_blocked(&mut self, _conn: &mut Connection, _event: BlockedEvent) {}
+
+    fn on_target_bitrate_updated(&mut self, _conn: &mut Connection, _bitrate: u64) {}
+
+    fn on_persistent_congestion(
+        &mut self,
+        _conn: &mut Connection,
+        _event: PersistentCongestionEvent,
+    ) {
+    }
+
+    fn on_stream_event(&mut self, _conn: &mut Connection, _stream_id: u64, _event: StreamEvent) {}
+}
+
+fn new_config(is_server: bool) -> Config {
+    let mut conf = Config::new().unwrap();
+    conf.set_initial_max_data(1024 * 1024 * 10);
+    conf.set_initial_max_stream_data_bidi_local(1024 * 1024 * 10);
+    conf.set_initial_max_stream_data_bidi_remote(1024 * 1024 * 10);
+    conf.set_initial_max_streams_bidi(100);
+    conf.set_max_connection_window(1024 * 1024 * 10);
+    conf.set_max_stream_window(1024 * 1024 * 10);
+    conf.set_max_ack_delay(1);
+    conf.set_max_handshake_timeout(0);
+    conf.set_reset_token_key([1u8; 64]);
+
+    let application_protos = vec![b"bench".to_vec()];
+    let tls_config = if !is_server {
+        TlsConfig::new_client_config(application_protos, false).unwrap()
+    } else {
+        TlsConfig::new_server_config(
+            "src/tls/testdata/cert.crt",
+            "src/tls/testdata/cert.key",
+            application_protos,
+            false,
+        )
+        .unwrap()
+    };
+    conf.set_tls_config(tls_config);
+    conf
+}
+
+/// Establish a connection and echo the data over the in-memory links.
+fn echo(data: &Bytes, reorder: bool) {
+    let cli_addr: SocketAddr = "127.0.0.1:8443".parse().unwrap();
+    let srv_addr: SocketAddr = "127.0.0.1:443".parse().unwrap();
+    let cli_link = Rc::new(Link::default());
+    let srv_link = Rc::new(Link::default());
+    let done = Rc::new(Cell::new(false));
+
+    let cli_hdl = Box::new(ClientHandler {
+        data: data.clone(),
+        written: 0,
+        received: 0,
+        stream_id: None,
+        done: done.clone(),
+    });
+    let mut client = Endpoint::new(
+        Box::new(new_config(false)),
+        false,
+        cli_hdl,
+        cli_link.clone(),
+    );
+    let srv_hdl = Box::<ServerHandler>::default();
+    let mut server = Endpoint::new(Box::new(new_config(true)), true, srv_hdl, srv_link.clone());
+
+    client
+        .connect(cli_addr, srv_addr, Some("example.org"), None, None, None)
+        .unwrap();
+    while !done.get() {
+        client.process_connections().unwrap();
+        cli_link.deliver(&mut server, reorder);
+        server.process_connections().unwrap();
+        srv_link.deliver(&mut client, reorder);
+
+        let now = Instant::now();
+        client.on_timeout(now);
+        server.on_timeout(now);
+    }
+}
+
+pub fn echo_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("echo");
+    group.sample_size(10);
+    for size in [64 * 1024, 1024 * 1024] {
+        let data = Bytes::from(vec![0x1; size]);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(format!("echo {} bytes in order", size), |b| {
+            b.iter(|| echo(&data, false))
+        });
+        group.bench_function(format!("echo {} bytes reordered", size), |b| {
+            b.iter(|| echo(&data, true))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, echo_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,90 @@
+// Copyright (c) 2023 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use tquic::frame;
+use tquic::frame::Frame;
+use tquic::ranges::RangeSet;
+
+/// Prepare ranges to be acknowledged with the given number of gaps.
+fn new_ack_ranges(count: u64) -> RangeSet {
+    let mut ranges = RangeSet::new(count as usize);
+    for i in 0..count {
+        ranges.insert(i * 10..i * 10 + 5);
+    }
+    ranges
+}
+
+pub fn ack_encode_benchmark(c: &mut Criterion) {
+    let mut buf = vec![0; 1500];
+    for count in [1, 16, 64] {
+        let ranges = new_ack_ranges(count);
+
+        // Create a frame with a copy of the ranges, and then encode it.
+        c.bench_function(&format!("ack frame encode {} ranges", count), |b| {
+            b.iter(|| {
+                let frame = Frame::Ack {
+                    ack_delay: 100,
+                    ack_ranges: ranges.clone(),
+                    ecn_counts: None,
+                };
+                frame.to_bytes(&mut buf).unwrap()
+            })
+        });
+
+        // Encode the ranges into the buffer directly.
+        c.bench_function(
+            &format!("ack frame encode {} ranges in place", count),
+            |b| b.iter(|| frame::encode_ack_frame(100, &ranges, None, &mut buf).unwrap()),
+        );
+    }
+}
+
+pub fn frames_vec_benchmark(c: &mut Criterion) {
+    // Allocate a vector of frames for each packet.
+    c.bench_function("packet frames alloc", |b| {
+        b.iter(|| {
+            let mut frames = Vec::new();
+            for _ in 0..4 {
+                frames.push(Frame::Ping {
+                    pmtu_probe: None,
+                    probe: None,
+                });
+            }
+            frames
+        })
+    });
+
+    // Reuse the vector of frames of a retired packet.
+    let mut recycled = Vec::new();
+    c.bench_function("packet frames reuse", |b| {
+        b.iter(|| {
+            let mut frames: Vec<Frame> = std::mem::take(&mut recycled);
+            for _ in 0..4 {
+                frames.push(Frame::Ping {
+                    pmtu_probe: None,
+                    probe: None,
+                });
+            }
+            frames.clear();
+            recycled = frames;
+        })
+    });
+}
+
+criterion_group!(benches, ack_encode_benchmark, frames_vec_benchmark);
+criterion_main!(benches);
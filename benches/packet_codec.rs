@@ -0,0 +1,76 @@
+// Copyright (c) 2023 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use tquic::frame::Frame;
+use tquic::ConnectionId;
+use tquic::PacketHeader;
+use tquic::PacketType;
+
+fn new_header(pkt_type: PacketType) -> PacketHeader {
+    PacketHeader {
+        pkt_type,
+        version: 1,
+        dcid: ConnectionId::new(&[0xa; 8]),
+        scid: ConnectionId::new(&[0xb; 8]),
+        pkt_num_len: 4,
+        pkt_num: 100,
+        token: None,
+        key_phase: false,
+    }
+}
+
+pub fn header_benchmark(c: &mut Criterion) {
+    let mut buf = vec![0; 1500];
+    for pkt_type in [PacketType::Initial, PacketType::OneRTT] {
+        let hdr = new_header(pkt_type);
+        c.bench_function(&format!("packet header encode {:?}", pkt_type), |b| {
+            b.iter(|| hdr.to_bytes(&mut buf).unwrap())
+        });
+
+        let len = hdr.to_bytes(&mut buf).unwrap();
+        c.bench_function(&format!("packet header decode {:?}", pkt_type), |b| {
+            b.iter(|| PacketHeader::from_bytes(&buf[..len], 8).unwrap())
+        });
+    }
+}
+
+pub fn stream_frame_benchmark(c: &mut Criterion) {
+    let mut buf = vec![0; 1500];
+    for size in [64, 1200] {
+        let frame = Frame::Stream {
+            stream_id: 4,
+            offset: 1024 * 1024,
+            length: size,
+            fin: false,
+            data: Bytes::from(vec![0x1; size]),
+        };
+        c.bench_function(&format!("stream frame encode {} bytes", size), |b| {
+            b.iter(|| frame.to_bytes(&mut buf).unwrap())
+        });
+
+        let len = frame.to_bytes(&mut buf).unwrap();
+        let data = Bytes::copy_from_slice(&buf[..len]);
+        c.bench_function(&format!("stream frame decode {} bytes", size), |b| {
+            b.iter(|| Frame::from_bytes(&mut data.clone(), PacketType::OneRTT).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, header_benchmark, stream_frame_benchmark);
+criterion_main!(benches);
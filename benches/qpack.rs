@@ -0,0 +1,53 @@
+// Copyright (c) 2023 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use tquic::h3::qpack::QpackDecoder;
+use tquic::h3::qpack::QpackEncoder;
+use tquic::h3::Header;
+
+fn new_request_headers() -> Vec<Header> {
+    vec![
+        Header::new(b":method", b"GET"),
+        Header::new(b":scheme", b"https"),
+        Header::new(b":authority", b"example.org"),
+        Header::new(b":path", b"/index.html?query=tquic"),
+        Header::new(b"user-agent", b"tquic-bench/1.0"),
+        Header::new(b"accept", b"*/*"),
+        Header::new(b"accept-encoding", b"gzip, deflate, br"),
+        Header::new(b"x-request-id", b"0123456789abcdef"),
+    ]
+}
+
+pub fn qpack_benchmark(c: &mut Criterion) {
+    let headers = new_request_headers();
+    let mut buf = vec![0; 1024];
+
+    let mut encoder = QpackEncoder::new();
+    c.bench_function("qpack encode request headers", |b| {
+        b.iter(|| encoder.encode(&headers, &mut buf).unwrap())
+    });
+
+    let len = encoder.encode(&headers, &mut buf).unwrap();
+    let mut decoder = QpackDecoder::new();
+    c.bench_function("qpack decode request headers", |b| {
+        b.iter(|| decoder.decode(&buf[..len], u64::MAX).unwrap())
+    });
+}
+
+criterion_group!(benches, qpack_benchmark);
+criterion_main!(benches);
@@ -42,6 +42,9 @@ fuzz_target!(|data: &[u8]| {
         src: remote,
         dst: local,
         time: Instant::now(),
+        ecn: tquic::Ecn::NotEct,
+        dscp: 0,
+        segment_size: 0,
     };
 
     let mut conn = tquic::Connection::new_client(
@@ -0,0 +1,40 @@
+// Copyright (c) 2023 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use tquic::frame::Frame;
+use tquic::PacketType;
+
+fuzz_target!(|data: &[u8]| {
+    for pkt_type in [
+        PacketType::Initial,
+        PacketType::ZeroRTT,
+        PacketType::Handshake,
+        PacketType::OneRTT,
+    ] {
+        // Decode frames until the payload is exhausted or malformed.
+        let mut buf = bytes::Bytes::copy_from_slice(data);
+        while !buf.is_empty() {
+            match Frame::from_bytes(&mut buf, pkt_type) {
+                Ok((_, len)) => {
+                    let _ = buf.split_to(len);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+});
@@ -20,12 +20,42 @@
  */
 #define QUIC_VERSION_V1 1
 
+/**
+ * The QUIC Version 2
+ * See RFC 9369
+ */
+#define QUIC_VERSION_V2 1798521807
+
 /**
  * The Connection ID MUST NOT exceed 20 bytes in QUIC version 1.
  * See RFC 9000 Section 17.2
  */
 #define MAX_CID_LEN 20
 
+/**
+ * Type of flow control blocking.
+ */
+typedef enum quic_blocked_type {
+  /**
+   * The connection is blocked by the connection-level flow control limit
+   * of the peer.
+   */
+  QUIC_BLOCKED_TYPE_DATA_BLOCKED,
+  /**
+   * The stream is blocked by the stream-level flow control limit of the
+   * peer.
+   */
+  QUIC_BLOCKED_TYPE_STREAM_DATA_BLOCKED,
+  /**
+   * The peer is blocked by the local connection-level flow control limit.
+   */
+  QUIC_BLOCKED_TYPE_PEER_DATA_BLOCKED,
+  /**
+   * The peer is blocked by the local stream-level flow control limit.
+   */
+  QUIC_BLOCKED_TYPE_PEER_STREAM_DATA_BLOCKED,
+} quic_blocked_type;
+
 /**
  * Available congestion control algorithms.
  */
@@ -62,8 +92,89 @@ typedef enum quic_congestion_control_algorithm {
    * It is intended to be used for testing and experiments.
    */
   QUIC_CONGESTION_CONTROL_ALGORITHM_DUMMY,
+  /**
+   * Prague is a scalable congestion control algorithm for L4S, which
+   * responds to ECN-CE marks in proportion to their extent instead of
+   * treating them like loss. ECN should be enabled for it to take effect.
+   * (Experimental)
+   */
+  QUIC_CONGESTION_CONTROL_ALGORITHM_PRAGUE,
+  /**
+   * Hybrid runs the delay-based COPA normally, and falls back to the
+   * loss-based CUBIC while competing with buffer-filling flows.
+   * (Experimental)
+   */
+  QUIC_CONGESTION_CONTROL_ALGORITHM_HYBRID,
+  /**
+   * LEDBAT++ is a scavenger congestion control algorithm for background
+   * transfers, which keeps the queueing delay below a target and yields to
+   * other flows sharing the bottleneck.
+   * (Experimental)
+   */
+  QUIC_CONGESTION_CONTROL_ALGORITHM_LEDBAT,
+  /**
+   * NADA is a rate-based congestion control algorithm for real-time
+   * interactive media, which reacts to the queueing delay and the loss and
+   * produces a target bitrate for the media encoder.
+   * (Experimental)
+   */
+  QUIC_CONGESTION_CONTROL_ALGORITHM_NADA,
+  /**
+   * BALIA is a coupled congestion control algorithm for multipath
+   * connections. The window of each path is adjusted according to the
+   * sending rates of all the paths, so that the connection is no more
+   * aggressive than a single-path flow at a shared bottleneck. It behaves
+   * like Reno on a single path.
+   * (Experimental)
+   */
+  QUIC_CONGESTION_CONTROL_ALGORITHM_BALIA,
 } quic_congestion_control_algorithm;
 
+/**
+ * The policy of using ECN on a path.
+ */
+typedef enum quic_ecn_policy {
+  /**
+   * The packets are not marked with ECN codepoints.
+   */
+  QUIC_ECN_POLICY_DISABLED,
+  /**
+   * The path is tested before the packets are marked with ECT(0), and the
+   * endpoint stops marking packets if the validation fails.
+   * See RFC 9000 Section 13.4.2
+   */
+  QUIC_ECN_POLICY_TEST_THEN_USE,
+  /**
+   * The packets are always marked with ECT(0) regardless of the result of
+   * the validation.
+   */
+  QUIC_ECN_POLICY_ALWAYS,
+} quic_ecn_policy;
+
+/**
+ * The content of the probe packets sent upon a probe timeout (PTO) in the
+ * Application Data packet number space. The probes in the Initial and
+ * Handshake packet number spaces always retransmit the unacknowledged data.
+ * See RFC 9002 Section 6.2.4.
+ */
+typedef enum quic_pto_probe_strategy {
+  /**
+   * The oldest unacknowledged data is retransmitted in the probes, followed
+   * by new data if there is any room left.
+   */
+  QUIC_PTO_PROBE_STRATEGY_OLDEST_UNACKED,
+  /**
+   * New data is sent in the probes if available, else a PING frame is sent.
+   * The unacknowledged data is retransmitted after it is declared lost.
+   */
+  QUIC_PTO_PROBE_STRATEGY_NEW_DATA,
+  /**
+   * Only a PING frame is sent in the probes, which elicits an
+   * acknowledgment quickly at the cost of the smallest probe size.
+   */
+  QUIC_PTO_PROBE_STRATEGY_PING,
+} quic_pto_probe_strategy;
+
 /**
  * Available multipath scheduling algorithms.
  */
@@ -108,6 +219,53 @@ typedef enum quic_shutdown {
   QUIC_SHUTDOWN_WRITE = 1,
 } quic_shutdown;
 
+/**
+ * Type of stream events subscribed by the application.
+ */
+typedef enum quic_stream_event_type {
+  /**
+   * The stream is reset by the peer.
+   */
+  QUIC_STREAM_EVENT_TYPE_RESET,
+  /**
+   * The peer asks to stop sending on the stream.
+   */
+  QUIC_STREAM_EVENT_TYPE_STOP_SENDING,
+  /**
+   * The sent data is acknowledged up to the subscribed offset.
+   */
+  QUIC_STREAM_EVENT_TYPE_ACKED,
+} quic_stream_event_type;
+
+/**
+ * Available stream scheduling policies.
+ */
+typedef enum quic_stream_scheduling_policy {
+  /**
+   * Streams with lower urgency are scheduled first. Within the same
+   * urgency, non-incremental streams are sent one by one in the order of
+   * their stream IDs before the incremental streams, which share the
+   * bandwidth in a round-robin fashion. See RFC 9218.
+   */
+  QUIC_STREAM_SCHEDULING_POLICY_PRIORITY,
+  /**
+   * Streams are sent one by one in the order of their urgency and stream
+   * IDs, regardless of the incremental flag.
+   */
+  QUIC_STREAM_SCHEDULING_POLICY_SEQUENTIAL,
+  /**
+   * All streams share the bandwidth in a round-robin fashion, regardless
+   * of their urgency and incremental flag.
+   */
+  QUIC_STREAM_SCHEDULING_POLICY_ROUND_ROBIN,
+  /**
+   * Streams share the bandwidth in proportion to the weights derived from
+   * their urgency, regardless of the incremental flag. A stream with the
+   * urgency `u` is given the weight of `8 - min(u, 7)`.
+   */
+  QUIC_STREAM_SCHEDULING_POLICY_WEIGHTED_FAIR,
+} quic_stream_scheduling_policy;
+
 /**
  * Configurations about QUIC endpoint.
  */
@@ -150,6 +308,15 @@ typedef struct http3_headers_t http3_headers_t;
 
 typedef struct quic_tls_config_t quic_tls_config_t;
 
+/**
+ * The callback which decides whether the lost data of a stream is retransmitted.
+ */
+typedef bool (*quic_stream_loss_callback_t)(void *ctx,
+                                            uint64_t stream_id,
+                                            uint64_t offset,
+                                            size_t length,
+                                            uint64_t *err);
+
 typedef struct quic_tls_config_select_methods_t {
   struct quic_tls_config_t *(*get_default)(void *ctx);
   struct quic_tls_config_t *(*select)(void *ctx, const uint8_t *server_name, size_t server_name_len);
@@ -199,6 +366,52 @@ typedef struct quic_transport_methods_t {
    * is optional.
    */
   void (*on_new_token)(void *tctx, struct quic_conn_t *conn, const uint8_t *token, size_t token_len);
+  /**
+   * Called when the Ping frame sent by `quic_conn_ping_with_tag()` is
+   * acknowledged by the peer, with the RTT in microseconds measured for
+   * the probe. This callback is optional.
+   */
+  void (*on_ping_acked)(void *tctx, struct quic_conn_t *conn, uint64_t tag, uint64_t rtt);
+  /**
+   * Called when the connection is blocked by the flow control limits of
+   * the peer, or the peer reports that it is blocked by the local limits.
+   * The `stream_id` is only meaningful for stream-level blocking. This
+   * callback is optional.
+   */
+  void (*on_flow_control_blocked)(void *tctx,
+                                  struct quic_conn_t *conn,
+                                  enum quic_blocked_type blocked_type,
+                                  uint64_t stream_id,
+                                  uint64_t limit);
+  /**
+   * Called when the target bitrate in bits per second for the media
+   * encoder is updated by a congestion controller designed for real-time
+   * media, such as NADA. This callback is optional.
+   */
+  void (*on_target_bitrate_updated)(void *tctx, struct quic_conn_t *conn, uint64_t bitrate);
+  /**
+   * Called when a persistent congestion is declared on a path, with the
+   * duration in microseconds of the congestion period, the number of
+   * packets lost in the period, and the resulting congestion window in
+   * bytes. This callback is optional.
+   */
+  void (*on_persistent_congestion)(void *tctx,
+                                   struct quic_conn_t *conn,
+                                   uint64_t duration,
+                                   uint64_t lost_packets,
+                                   uint64_t congestion_window);
+  /**
+   * Called when a stream event subscribed by `quic_stream_wantreset()`,
+   * `quic_stream_wantstopsending()` or `quic_stream_wantacked()` occurs.
+   * The `value` is the error code for the reset and stop sending events,
+   * or the acknowledged offset for the acked event. This callback is
+   * optional.
+   */
+  void (*on_stream_event)(void *tctx,
+                          struct quic_conn_t *conn,
+                          uint64_t stream_id,
+                          enum quic_stream_event_type event_type,
+                          uint64_t value);
 } quic_transport_methods_t;
 
 typedef void *quic_transport_context_t;
@@ -213,6 +426,18 @@ typedef struct quic_packet_out_spec_t {
   socklen_t src_addr_len;
   const void *dst_addr;
   socklen_t dst_addr_len;
+  uint8_t ecn;
+  uint8_t dscp;
+  /**
+   * The delay in nanoseconds before the packet should be released, which
+   * is non-zero only if pacing offload is enabled.
+   */
+  uint64_t release_delay;
+  /**
+   * The size of each UDP datagram if the buffer contains multiple
+   * datagrams to be sent with UDP_SEGMENT, otherwise zero.
+   */
+  size_t segment_size;
 } quic_packet_out_spec_t;
 
 typedef struct quic_packet_send_methods_t {
@@ -264,6 +489,7 @@ typedef struct quic_packet_info_t {
   socklen_t src_len;
   const struct sockaddr *dst;
   socklen_t dst_len;
+  uint8_t ecn;
 } quic_packet_info_t;
 
 typedef struct quic_path_address_t {
@@ -341,6 +567,24 @@ typedef struct quic_path_stats_t {
    * Total duration of congestion windowlimited events in microseconds.
    */
   uint64_t cwnd_limited_duration;
+  /**
+   * Total application limited events, during which there is no data to
+   * send from the application.
+   */
+  uint64_t app_limited_count;
+  /**
+   * Total duration of application limited events in microseconds.
+   */
+  uint64_t app_limited_duration;
+  /**
+   * Total flow control limited events, during which sending is blocked by
+   * the flow control of the peer.
+   */
+  uint64_t flow_control_limited_count;
+  /**
+   * Total duration of flow control limited events in microseconds.
+   */
+  uint64_t flow_control_limited_duration;
   /**
    * Minimum roundtrip time in microseconds.
    */
@@ -365,6 +609,76 @@ typedef struct quic_path_stats_t {
    * Pacing rate estimated by congestion control algorithm.
    */
   uint64_t pacing_rate;
+  /**
+   * Whether the path has been validated as ECN capable.
+   */
+  bool ecn_capable;
+  /**
+   * Total number of packets with the ECN-CE codepoint reported by the peer.
+   */
+  uint64_t ecn_ce_count;
+  /**
+   * Total number of times the processing of ACK ranges is deferred since
+   * there are too many ranges to process at once.
+   */
+  uint64_t ack_deferred_count;
+  /**
+   * Total number of ACK ranges dropped since too many ranges are pending.
+   */
+  uint64_t ack_ranges_dropped;
+  /**
+   * Bandwidth estimate in bytes per second, smoothed from the delivery
+   * rate samples of the path. It is independent of the congestion control
+   * algorithm, and could be used for adaptive bitrate decisions.
+   */
+  uint64_t bandwidth;
+  /**
+   * Confidence of the bandwidth estimate in percent, from `0` to `100`.
+   * It grows with the number of samples, and drops with the variation of
+   * the samples.
+   */
+  uint8_t bandwidth_confidence;
+  /**
+   * The latest delivery rate sample in bytes per second.
+   */
+  uint64_t delivery_rate;
+  /**
+   * Total number of packets acknowledged after being declared lost.
+   */
+  uint64_t spurious_lost_count;
+  /**
+   * The current packet threshold of loss detection.
+   */
+  uint64_t packet_threshold;
+  /**
+   * Total number of congestion events detected as spurious, whose window
+   * reductions are undone.
+   */
+  uint64_t spurious_congestion_count;
+  /**
+   * Total number of probe timeouts.
+   */
+  uint64_t pto_count;
+  /**
+   * Total bytes of stream data retransmitted on the path.
+   */
+  uint64_t retrans_stream_bytes;
+  /**
+   * Total number of persistent congestions declared.
+   */
+  uint64_t persistent_congestion_count;
+  /**
+   * The number of packets lost in the Initial packet number space.
+   */
+  uint64_t initial_lost_count;
+  /**
+   * The number of packets lost in the Handshake packet number space.
+   */
+  uint64_t handshake_lost_count;
+  /**
+   * The number of packets lost in the Application Data packet number space.
+   */
+  uint64_t app_data_lost_count;
 } quic_path_stats_t;
 
 /**
@@ -395,6 +709,47 @@ typedef struct quic_conn_stats_t {
    * Total number of bytes lost on the connection.
    */
   uint64_t lost_bytes;
+  /**
+   * Estimated memory in bytes used by the connection, which is updated
+   * each time the connection is processed by the endpoint.
+   */
+  uint64_t memory_usage;
+  /**
+   * Number of times the send-side was blocked by the connection-level
+   * flow control.
+   */
+  uint64_t data_blocked_count;
+  /**
+   * Total duration in microseconds the send-side was blocked by the
+   * connection-level flow control.
+   */
+  uint64_t data_blocked_duration;
+  /**
+   * Number of times the streams were blocked by the stream-level flow
+   * control.
+   */
+  uint64_t stream_data_blocked_count;
+  /**
+   * Total duration in microseconds the streams were blocked by the
+   * stream-level flow control.
+   */
+  uint64_t stream_data_blocked_duration;
+  /**
+   * Total number of MAX_DATA frames sent.
+   */
+  uint64_t max_data_sent_count;
+  /**
+   * Total number of MAX_DATA frames received.
+   */
+  uint64_t max_data_recv_count;
+  /**
+   * Total number of MAX_STREAM_DATA frames sent.
+   */
+  uint64_t max_stream_data_sent_count;
+  /**
+   * Total number of MAX_STREAM_DATA frames received.
+   */
+  uint64_t max_stream_data_recv_count;
 } quic_conn_stats_t;
 
 typedef struct http3_methods_t {
@@ -470,11 +825,42 @@ void quic_config_free(struct quic_config_t *config);
  */
 void quic_config_set_max_idle_timeout(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Set the acceptable QUIC versions in the order of preference.
+ * The default value is `[QUIC_VERSION_V1]`.
+ */
+int quic_config_set_versions(struct quic_config_t *config,
+                             const uint32_t *versions,
+                             size_t versions_len);
+
 /**
  * Set handshake timeout in milliseconds. Zero turns the timeout off.
  */
 void quic_config_set_max_handshake_timeout(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Set the keep-alive interval in milliseconds. A PING frame is sent if
+ * the connection has been quiet for the interval, which is capped to
+ * half of the idle timeout. Zero turns keep-alive off.
+ * The default value is 0.
+ */
+void quic_config_set_keep_alive_interval(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Enable adaptive keep-alive. (Experimental)
+ * The client learns the NAT binding timeout by binary search over quiet
+ * intervals, and sends keep-alives at the longest interval found to keep
+ * the binding. A PATH_CHALLENGE frame is sent after each quiet interval,
+ * and the binding is considered lost if the server then reports a changed
+ * client address, so the search requires the server to provide observed
+ * addresses, see `quic_config_enable_address_discovery()`. Otherwise,
+ * keep-alives are sent at a conservative interval of 15 seconds. The
+ * keep-alive interval, if set, is used as the upper bound of the search.
+ * It does not take effect on the server.
+ * The default value is false.
+ */
+void quic_config_enable_adaptive_keep_alive(struct quic_config_t *config, bool v);
+
 /**
  * Set the `max_udp_payload_size` transport parameter in bytes. It limits
  * the size of UDP payloads that the endpoint is willing to receive.
@@ -487,6 +873,38 @@ void quic_config_set_recv_udp_payload_size(struct quic_config_t *config, uint16_
  */
 void enable_dplpmtud(struct quic_config_t *config, bool v);
 
+/**
+ * Enable Explicit Congestion Notification. The outgoing packets are
+ * marked with ECT(0) if the path is validated as ECN capable, and the
+ * application should set the ECN codepoint of the outgoing packets and
+ * report the ECN codepoint of the incoming packets via `PacketInfo`.
+ * The default value is false.
+ */
+void quic_config_enable_ecn(struct quic_config_t *config, bool v);
+
+/**
+ * Set the policy of using Explicit Congestion Notification on the paths.
+ * The default value is `QUIC_ECN_POLICY_DISABLED`.
+ */
+void quic_config_set_ecn_policy(struct quic_config_t *config,
+                                enum quic_ecn_policy v);
+
+/**
+ * Set the number of ECN validation failures on a path before falling back
+ * to not marking the packets.
+ * The default value is 1.
+ */
+void quic_config_set_ecn_max_failures(struct quic_config_t *config,
+                                      uint32_t v);
+
+/**
+ * Set whether to test new paths of a connection for ECN capability if
+ * the ECN validation has failed on another path.
+ * The default value is true.
+ */
+void quic_config_enable_ecn_retest_on_migration(struct quic_config_t *config,
+                                                bool v);
+
 /**
  * Set the maximum outgoing UDP payload size in bytes.
  * It corresponds to the maximum datagram size that DPLPMTUD tries to discovery.
@@ -533,6 +951,22 @@ void quic_config_set_initial_max_streams_bidi(struct quic_config_t *config, uint
  */
 void quic_config_set_initial_max_streams_uni(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Set the ceiling of the number of concurrent bidirectional streams that the
+ * peer is allowed to open. If the peer consumes the stream credits quickly, the
+ * limit is doubled each time up to the ceiling.
+ * The default value is `0`, which means the limit is not auto-tuned.
+ */
+void quic_config_set_max_concurrent_streams_bidi(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Set the ceiling of the number of concurrent unidirectional streams that the
+ * peer is allowed to open. If the peer consumes the stream credits quickly, the
+ * limit is doubled each time up to the ceiling.
+ * The default value is `0`, which means the limit is not auto-tuned.
+ */
+void quic_config_set_max_concurrent_streams_uni(struct quic_config_t *config, uint64_t v);
+
 /**
  * Set the `ack_delay_exponent` transport parameter.
  */
@@ -543,6 +977,23 @@ void quic_config_set_ack_delay_exponent(struct quic_config_t *config, uint64_t v
  */
 void quic_config_set_max_ack_delay(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Enable the ACK frequency extension, which allows the peer to control the
+ * acknowledgement behavior of the endpoint. (Experimental)
+ * The default value is false.
+ */
+void quic_config_enable_ack_frequency(struct quic_config_t *config, bool v);
+
+/**
+ * Request the peer to send an acknowledgement after receiving at most
+ * `threshold` ack-eliciting packets, and to delay an acknowledgement by at
+ * most `max_ack_delay` milliseconds, with an ACK_FREQUENCY frame once the
+ * ACK frequency extension is negotiated. (Experimental)
+ */
+void quic_config_set_ack_frequency(struct quic_config_t *config,
+                                   uint64_t threshold,
+                                   uint64_t max_ack_delay);
+
 /**
  * Set congestion control algorithm that the connection would use.
  */
@@ -561,12 +1012,41 @@ void quic_config_set_initial_congestion_window(struct quic_config_t *config, uin
  */
 void quic_config_set_min_congestion_window(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Set the initial congestion window in bytes, which takes precedence over
+ * the initial congestion window in packets. The value 0 means the initial
+ * congestion window is set in packets.
+ * The default value is 0.
+ */
+void quic_config_set_initial_congestion_window_bytes(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Set the minimal congestion window in bytes, which takes precedence over
+ * the minimal congestion window in packets. The value 0 means the minimal
+ * congestion window is set in packets.
+ * The default value is 0.
+ */
+void quic_config_set_min_congestion_window_bytes(struct quic_config_t *config, uint64_t v);
+
 /**
  * Set the threshold for slow start in packets.
  * The default value is the maximum value of u64.
  */
 void quic_config_set_slow_start_thresh(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Enable HyStart++ in CUBIC slow start.
+ * The default value is true.
+ */
+void quic_config_enable_cubic_hystart(struct quic_config_t *config, bool v);
+
+/**
+ * Enable Proportional Rate Reduction (RFC 6937) in the loss-based
+ * congestion controllers, i.e. CUBIC and Prague.
+ * The default value is false.
+ */
+void quic_config_enable_prr(struct quic_config_t *config, bool v);
+
 /**
  * Set the minimum duration for BBR ProbeRTT state in milliseconds.
  * The default value is 200 milliseconds.
@@ -612,6 +1092,24 @@ void quic_config_set_copa_steady_delta(struct quic_config_t *config, double v);
  */
 void quic_config_enable_copa_use_standing_rtt(struct quic_config_t *config, bool v);
 
+/**
+ * Set the target queueing delay in milliseconds for LEDBAT.
+ * The default value is 60 milliseconds.
+ */
+void quic_config_set_ledbat_target_delay(struct quic_config_t *config, uint64_t millis);
+
+/**
+ * Set the minimum target bitrate in bits per second for NADA.
+ * The default value is 150000 bits per second.
+ */
+void quic_config_set_nada_min_bitrate(struct quic_config_t *config, uint64_t bps);
+
+/**
+ * Set the maximum target bitrate in bits per second for NADA.
+ * The default value is 1500000 bits per second.
+ */
+void quic_config_set_nada_max_bitrate(struct quic_config_t *config, uint64_t bps);
+
 /**
  * Set the initial RTT in milliseconds. The default value is 333ms.
  * The configuration should be changed with caution. Setting a value less than the default
@@ -619,6 +1117,16 @@ void quic_config_enable_copa_use_standing_rtt(struct quic_config_t *config, bool
  */
 void quic_config_set_initial_rtt(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Set the probe timeout before the first RTT sample is taken, as a multiple
+ * of the initial RTT. A smaller value retransmits the Initial and Handshake
+ * data earlier. Values less than 1.0 will be ignored, and values greater than
+ * 100.0 will be capped.
+ * By default, the timeout is derived from the initial RTT, which is equivalent
+ * to 3.0.
+ */
+void quic_config_set_initial_pto_factor(struct quic_config_t *config, double v);
+
 /**
  * Enable pacing to smooth the flow of packets sent onto the network.
  * The default value is true.
@@ -627,10 +1135,58 @@ void quic_config_enable_pacing(struct quic_config_t *config, bool v);
 
 /**
  * Set clock granularity used by the pacer.
- * The default value is 10 milliseconds.
+ * The default value is 1 milliseconds.
  */
 void quic_config_set_pacing_granularity(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Set the minimum number of packets that the pacer may release back to back.
+ * The default value is 10.
+ */
+void quic_config_set_pacing_min_burst(struct quic_config_t *config, uint64_t packets);
+
+/**
+ * Set the maximum number of packets that the pacer may release back to back.
+ * The default value is 128.
+ */
+void quic_config_set_pacing_max_burst(struct quic_config_t *config, uint64_t packets);
+
+/**
+ * Set the upper limit of the pacing rate in bytes per second, which caps
+ * the sending rate of each path regardless of the congestion controller.
+ * Pacing is always applied if the limit is set. The value `0` means no
+ * limit. The default value is `0`.
+ */
+void quic_config_set_max_pacing_rate(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Enable offloading pacing to the kernel or hardware, e.g. via SO_TXTIME
+ * with the ETF qdisc. The outgoing packets are not delayed by the pacer,
+ * and the delay to release each packet is exported in `release_delay` of
+ * `quic_packet_out_spec_t`. It takes effect only if pacing is enabled.
+ * The default value is false.
+ */
+void quic_config_enable_pacing_offload(struct quic_config_t *config, bool v);
+
+/**
+ * Enable Careful Resume for the server. The congestion window of a
+ * connection is jump-started with the path capacity saved in the address
+ * token, which is sent by `quic_conn_send_capacity_token()` in a previous
+ * connection. The saved capacity is used only if the RTT of the path is
+ * similar, and it is validated before use.
+ * The default value is false.
+ */
+void quic_config_enable_careful_resume(struct quic_config_t *config, bool v);
+
+/**
+ * Set the delay in microseconds for coalescing small stream data. The
+ * stream data that is not enough to fill a packet is held for at most
+ * the delay before being sent, so that data written by multiple small
+ * writes can be packed into fewer packets. The value `0` means the data
+ * is sent immediately. The default value is `0`.
+ */
+void quic_config_set_send_coalescing_delay(struct quic_config_t *config, uint64_t v);
+
 /**
  * Set the linear factor for calculating the probe timeout.
  * The endpoint do not backoff the first `v` consecutive probe timeouts.
@@ -648,6 +1204,56 @@ void quic_config_set_pto_linear_factor(struct quic_config_t *config, uint64_t v)
  */
 void quic_config_set_max_pto(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Enable adapting the reordering thresholds of loss detection. When a packet
+ * is acknowledged after being declared lost, the packet and time thresholds
+ * are increased to the observed reordering.
+ * The default value is false.
+ */
+void quic_config_enable_adaptive_reordering(struct quic_config_t *config, bool v);
+
+/**
+ * Set the initial time threshold of loss detection, as a multiplier of the RTT.
+ * Values less than 1.0 will be ignored.
+ * The default value is 1.125.
+ */
+void quic_config_set_time_reordering_threshold(struct quic_config_t *config, double v);
+
+/**
+ * Set the multiplier of the probe timeout upon each backoff.
+ * The value 1 disables the exponential backoff. Values less than 1 will be ignored.
+ * The default value is 2.
+ */
+void quic_config_set_pto_multiplier(struct quic_config_t *config, uint32_t v);
+
+/**
+ * Set the upper limit of the consecutive probe timeout backoffs.
+ * The default value is unlimited.
+ */
+void quic_config_set_max_pto_backoff(struct quic_config_t *config, uint32_t v);
+
+/**
+ * Set the packet threshold of loss detection.
+ * Values less than 1 will be ignored.
+ * The default value is 3.
+ */
+void quic_config_set_packet_reordering_threshold(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Set the content of the probe packets sent upon a probe timeout in the
+ * Application Data packet number space.
+ * The default value is QUIC_PTO_PROBE_STRATEGY_OLDEST_UNACKED.
+ */
+void quic_config_set_pto_probe_strategy(struct quic_config_t *config,
+                                        enum quic_pto_probe_strategy v);
+
+/**
+ * Enable sending the two probe packets of a path on different paths if
+ * multipath is enabled.
+ * The default value is false.
+ */
+void quic_config_enable_pto_probes_across_paths(struct quic_config_t *config, bool v);
+
 /**
  * Set the `active_connection_id_limit` transport parameter.
  */
@@ -666,6 +1272,15 @@ void quic_config_enable_multipath(struct quic_config_t *config, bool enabled);
 void quic_config_set_multipath_algorithm(struct quic_config_t *config,
                                          enum quic_multipath_algorithm v);
 
+/**
+ * Set the weights of paths indexed by path id, which are used by the
+ * RoundRobin scheduler. A path with weight N is selected N times in a row
+ * before moving to the next path. Paths without a weight are given 1.
+ */
+void quic_config_set_multipath_path_weights(struct quic_config_t *config,
+                                            const uint32_t *weights,
+                                            size_t weights_len);
+
 /**
  * Set the maximum size of the connection flow control window.
  * The default value is MAX_CONNECTION_WINDOW (15 MB).
@@ -679,11 +1294,84 @@ void quic_config_set_max_connection_window(struct quic_config_t *config, uint64_
  */
 void quic_config_set_max_stream_window(struct quic_config_t *config, uint64_t v);
 
+/**
+ * Set the number of RTTs without a flow control window update after which
+ * an auto-tuned receive window is halved. By default, the window is never
+ * shrunk below its initial size. `0` disables window decay.
+ * The default value is `0`.
+ */
+void quic_config_set_window_decay_rtts(struct quic_config_t *config, uint32_t v);
+
+/**
+ * Set the floor of the receive windows when they decay.
+ * The default value is `0`, which means the initial window sizes are used as
+ * the floors.
+ */
+void quic_config_set_window_decay_floor(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Set the target interval between flow control window updates, in units of
+ * srtt. If window updates occur within the interval, the window is grown by
+ * `flow_control_autotune_factor`. The value is capped at `100.0`, and NaN
+ * will be ignored.
+ * The default value is `2.0`.
+ */
+void quic_config_set_flow_control_autotune_interval_rtts(struct quic_config_t *config, double v);
+
+/**
+ * Set the growth factor of the flow control window when auto-tuning.
+ * The value should not be less than `1.0`.
+ * The default value is `2.0`.
+ */
+void quic_config_set_flow_control_autotune_factor(struct quic_config_t *config, double v);
+
+/**
+ * Enable allocating the stream receive windows dynamically from the connection
+ * receive window. If enabled, the credit advertised in a MAX_STREAM_DATA frame is
+ * limited to the connection credit that has not been allocated to other streams,
+ * but no less than a fair share of the connection window.
+ * The default value is `false`.
+ */
+void quic_config_enable_stream_window_sharing(struct quic_config_t *config, bool v);
+
+/**
+ * Set the policy of scheduling the data of sendable streams into packets.
+ * The default value is StreamSchedulingPolicy::Priority
+ */
+void quic_config_set_stream_scheduling_policy(struct quic_config_t *config,
+                                              enum quic_stream_scheduling_policy v);
+
+/**
+ * Set the maximum size of data received in out-of-order CRYPTO frames
+ * that can be buffered for each encryption level. The connection is
+ * closed with a CRYPTO_BUFFER_EXCEEDED error if the peer sends more.
+ * The value should not be less than 4096.
+ * The default value is 65536.
+ */
+void quic_config_set_max_crypto_buffer_size(struct quic_config_t *config, uint64_t v);
+
 /**
  * Set the Maximum number of concurrent connections.
  */
 void quic_config_set_max_concurrent_conns(struct quic_config_t *config, uint32_t v);
 
+/**
+ * Set the maximum memory in bytes used by all the connections of an endpoint.
+ * If it is exceeded, the endpoint refuses new connections and closes the
+ * connection using the most memory.
+ * The default value is `0`, which means no limit.
+ */
+void quic_config_set_max_memory_usage(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Set the maximum sum in bytes of the receiver connection flow control windows
+ * of all the connections of an endpoint. When the budget is tight, window
+ * autotuning is suppressed, and the growth of the windows advertised in
+ * MAX_DATA frames is limited to the remaining budget.
+ * The default value is `0`, which means no limit.
+ */
+void quic_config_set_max_recv_window_budget(struct quic_config_t *config, uint64_t v);
+
 /**
  * Set the key for reset token generation. The token_key_len should be not less
  * than 64.
@@ -701,7 +1389,8 @@ void quic_config_set_address_token_lifetime(struct quic_config_t *config, uint64
 
 /**
  * Set the key for address token generation. It also enables retry.
- * The token_key_len should be a multiple of 16.
+ * The token_key_len should be a multiple of 16. The first key is used for
+ * generating new tokens, and all of the keys are used for validating tokens.
  * Applicable to Server only.
  */
 int quic_config_set_address_token_key(struct quic_config_t *config,
@@ -720,6 +1409,32 @@ void quic_config_enable_retry(struct quic_config_t *config, bool enabled);
  */
 void quic_config_enable_stateless_reset(struct quic_config_t *config, bool enabled);
 
+/**
+ * Set the maximum number of stateless packets (Version Negotiation, Retry
+ * and Stateless Reset) sent to a source address per second.
+ * The value 0 means no limit. Setting a limit also drops Retry packets
+ * beyond it, which may delay the clients behind a shared NAT address.
+ * The default value is `0`.
+ * Applicable to Endpoint only.
+ */
+void quic_config_set_stateless_response_rate(struct quic_config_t *config, uint32_t v);
+
+/**
+ * Set the maximum number of peer hosts whose path properties are cached
+ * by the endpoint. A new connection to a cached host inherits the RTT and
+ * congestion window estimates of the previous connections.
+ * The default value is `0`, which means the cache is disabled.
+ * Applicable to Endpoint only.
+ */
+void quic_config_set_path_cache_size(struct quic_config_t *config, uintptr_t v);
+
+/**
+ * Set the lifetime in seconds of the cached path properties.
+ * The default value is `600`.
+ * Applicable to Endpoint only.
+ */
+void quic_config_set_path_cache_lifetime(struct quic_config_t *config, uint64_t seconds);
+
 /**
  * Set the length of source cid. The length should not be greater than 20.
  * Applicable to Endpoint only.
@@ -740,6 +1455,16 @@ void quic_config_set_anti_amplification_factor(struct quic_config_t *config, uin
  */
 void quic_config_set_send_batch_size(struct quic_config_t *config, uint16_t v);
 
+/**
+ * Set the maximum number of UDP datagrams of the same size written to a
+ * single packet buffer. If it is greater than 1, the buffer should be sent
+ * with UDP_SEGMENT according to `segment_size` of `quic_packet_out_spec_t`.
+ * The value is capped to 64.
+ * Applicable to Endpoint only.
+ * The default value is 1.
+ */
+void quic_config_set_max_send_segments(struct quic_config_t *config, uint16_t v);
+
 /**
  * Set the buffer size for disordered zerortt packets on the server.
  * The default value is `1000`. A value of 0 will be treated as default value.
@@ -761,6 +1486,59 @@ void quic_config_set_max_undecryptable_packets(struct quic_config_t *config, uin
  */
 void quic_config_enable_encryption(struct quic_config_t *config, bool v);
 
+/**
+ * Set whether to advertise the grease_quic_bit transport parameter, which
+ * allows the peer to set the QUIC Bit of packets to an unpredictable value.
+ * The default value is false.
+ */
+void quic_config_enable_grease_quic_bit(struct quic_config_t *config, bool v);
+
+/**
+ * Set whether to use the latency spin bit, which allows on-path
+ * observers to measure the RTT of the connection. Even if enabled, the
+ * spin bit is disabled on a random selection of one in every 16
+ * connections.
+ * The default value is false.
+ */
+void quic_config_enable_spin_bit(struct quic_config_t *config, bool v);
+
+/**
+ * Set the DSCP codepoint of the outgoing packets, which allows network
+ * level prioritization of the traffic. Only the lower 6 bits are used.
+ * The default value is `0` (Default Forwarding).
+ */
+void quic_config_set_dscp(struct quic_config_t *config, uint8_t v);
+
+/**
+ * Set the average interval in packets between the packet numbers which are
+ * skipped on purpose in the application data space, for detecting the
+ * optimistic ACK attack. The value 0 disables skipping packet numbers.
+ * The default value is 0.
+ */
+void quic_config_set_packet_number_skip_interval(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Set the granularity in milliseconds for coalescing the timers of a
+ * connection. The value 0 disables coalescing.
+ * The default value is 0.
+ */
+void quic_config_set_timer_granularity(struct quic_config_t *config, uint64_t v);
+
+/**
+ * Enable the BDP_FRAME extension, which allows the server to share the
+ * measured path capacity with the client. (Experimental)
+ * The default value is false.
+ */
+void quic_config_enable_bdp_frame(struct quic_config_t *config, bool v);
+
+/**
+ * Set whether to report the observed addresses to the peer, and whether to
+ * receive the observed addresses from the peer, with the address discovery
+ * extension. (Experimental)
+ * The default value is false for both.
+ */
+void quic_config_enable_address_discovery(struct quic_config_t *config, bool provide, bool receive);
+
 /**
  * Create a new TlsConfig.
  * The caller is responsible for the memory of the TlsConfig and should properly
@@ -999,15 +1777,70 @@ void quic_conn_application_proto(struct quic_conn_t *conn, const uint8_t **out,
 void quic_conn_server_name(struct quic_conn_t *conn, const uint8_t **out, size_t *out_len);
 
 /**
- * Return the session data used by resumption.
+ * Return the session data used by resumption and 0-RTT. It is an opaque blob
+ * which bundles the session ticket, the transport parameters of the server,
+ * and the negotiated version and application protocol.
  */
 void quic_conn_session(struct quic_conn_t *conn, const uint8_t **out, size_t *out_len);
 
+/**
+ * Copy the session data bundled with the current BDP estimate of the
+ * connection into the given buffer. Passing it to `quic_endpoint_connect()` for
+ * the next connection to the same peer avoids slow window ramp-up.
+ * Return the number of bytes copied, `0` if no session data is available, or
+ * a negative error code if the buffer is too short.
+ */
+ssize_t quic_conn_session_with_bdp(struct quic_conn_t *conn, uint8_t *out, size_t out_len);
+
 /**
  * Return details why 0-RTT was accepted or rejected.
  */
 int quic_conn_early_data_reason(struct quic_conn_t *conn, const uint8_t **out, size_t *out_len);
 
+/**
+ * Share the path capacity measured on the connection with the client in a
+ * BDP_FRAME frame. Applicable to Server only.
+ */
+int quic_conn_send_bdp_frame(struct quic_conn_t *conn);
+
+/**
+ * Request the peer to send an ACK frame immediately with an IMMEDIATE_ACK
+ * frame. The ACK frequency extension should be supported by the peer.
+ */
+int quic_conn_send_immediate_ack(struct quic_conn_t *conn);
+
+/**
+ * Issue an address token carrying the capacity of the active path to the
+ * client in a NEW_TOKEN frame, which could be used for Careful Resume in a
+ * future connection. Applicable to Server only.
+ */
+int quic_conn_send_capacity_token(struct quic_conn_t *conn);
+
+/**
+ * Set the upper limit of the pacing rate in bytes per second for the
+ * connection, which caps the sending rate of each path regardless of the
+ * congestion controller. The value `0` means no limit.
+ */
+void quic_conn_set_max_pacing_rate(struct quic_conn_t *conn, uint64_t v);
+
+/**
+ * Switch the congestion control algorithm of the connection. The RTT
+ * samples of each path are kept, and the congestion window is migrated to
+ * the new congestion controller if the old one has left slow start.
+ */
+void quic_conn_set_congestion_control_algorithm(struct quic_conn_t *conn,
+                                                enum quic_congestion_control_algorithm v);
+
+/**
+ * Get the path capacity shared by the server in a BDP_FRAME frame. The
+ * `lifetime` is in seconds and the `saved_rtt` is in microseconds.
+ * Return false if the server has not shared it. Applicable to Client only.
+ */
+bool quic_conn_peer_bdp(struct quic_conn_t *conn,
+                        uint64_t *lifetime,
+                        uint64_t *saved_capacity,
+                        uint64_t *saved_rtt);
+
 /**
  * Send a Ping frame on the active path(s) for keep-alive.
  */
@@ -1023,6 +1856,40 @@ int quic_conn_ping_path(struct quic_conn_t *conn,
                         const struct sockaddr *remote,
                         socklen_t remote_len);
 
+/**
+ * Send a Ping frame carrying the given tag on the active path. The RTT
+ * measured for the probe is reported by the `on_ping_acked` callback.
+ */
+int quic_conn_ping_with_tag(struct quic_conn_t *conn, uint64_t tag);
+
+/**
+ * Set the policy of using ECN on the specified path, and restart the ECN
+ * validation on the path.
+ */
+int quic_conn_set_path_ecn_policy(struct quic_conn_t *conn,
+                                  const struct sockaddr *local,
+                                  socklen_t local_len,
+                                  const struct sockaddr *remote,
+                                  socklen_t remote_len,
+                                  enum quic_ecn_policy policy);
+
+/**
+ * Set the DSCP codepoint of the outgoing packets of the connection.
+ * Only the lower 6 bits are used.
+ */
+void quic_conn_set_dscp(struct quic_conn_t *conn, uint8_t dscp);
+
+/**
+ * Set the DSCP codepoint of the outgoing packets on the specified path.
+ * Only the lower 6 bits are used.
+ */
+int quic_conn_set_path_dscp(struct quic_conn_t *conn,
+                            const struct sockaddr *local,
+                            socklen_t local_len,
+                            const struct sockaddr *remote,
+                            socklen_t remote_len,
+                            uint8_t dscp);
+
 /**
  * Add a new path on the client connection.
  */
@@ -1072,6 +1939,14 @@ bool quic_conn_path_iter_next(struct quic_path_address_iter_t *iter, struct quic
  */
 bool quic_conn_active_path(const struct quic_conn_t *conn, struct quic_path_address_t *a);
 
+/**
+ * Return the local address observed by the peer on the active path, which is
+ * reported with the address discovery extension.
+ */
+bool quic_conn_observed_addr(const struct quic_conn_t *conn,
+                             struct sockaddr_storage *addr,
+                             socklen_t *addr_len);
+
 /**
  * Return the latest statistics about the specified path.
  */
@@ -1196,11 +2071,52 @@ int quic_conn_close(struct quic_conn_t *conn,
  */
 int quic_stream_wantwrite(struct quic_conn_t *conn, uint64_t stream_id, bool want);
 
+/**
+ * Set want write flag for a stream with a writable threshold. The stream is
+ * reported writable only when at least `bytes` bytes of send credit, limited by
+ * both flow control and the congestion window, is available. Setting `bytes`
+ * to `0` removes the threshold.
+ */
+int quic_stream_wantwrite_threshold(struct quic_conn_t *conn, uint64_t stream_id, size_t bytes);
+
+/**
+ * Pause or resume the advertisement of the stream-level flow control limit.
+ * While paused, no MAX_STREAM_DATA frame is sent for the stream regardless of
+ * how much data has been read.
+ */
+int quic_stream_set_flow_control_paused(struct quic_conn_t *conn, uint64_t stream_id, bool paused);
+
+/**
+ * Pause or resume the advertisement of the connection-level flow control
+ * limit. While paused, no MAX_DATA frame is sent regardless of how much data
+ * has been read.
+ */
+void quic_conn_set_flow_control_paused(struct quic_conn_t *conn, bool paused);
+
 /**
  * Set want read flag for a stream.
  */
 int quic_stream_wantread(struct quic_conn_t *conn, uint64_t stream_id, bool want);
 
+/**
+ * Subscribe to or unsubscribe from the reset event of a stream. The
+ * application is notified once a RESET_STREAM frame is received.
+ */
+int quic_stream_wantreset(struct quic_conn_t *conn, uint64_t stream_id, bool want);
+
+/**
+ * Subscribe to or unsubscribe from the stop sending event of a stream. The
+ * application is notified once a STOP_SENDING frame is received.
+ */
+int quic_stream_wantstopsending(struct quic_conn_t *conn, uint64_t stream_id, bool want);
+
+/**
+ * Subscribe to the acknowledgement of the data sent on a stream. The
+ * application is notified once the data is acknowledged contiguously up to
+ * `offset`. Setting `offset` to `0` removes the subscription.
+ */
+int quic_stream_wantacked(struct quic_conn_t *conn, uint64_t stream_id, uint64_t offset);
+
 /**
  * Read data from a stream.
  */
@@ -1264,6 +2180,15 @@ int quic_stream_set_priority(struct quic_conn_t *conn,
                              uint8_t urgency,
                              bool incremental);
 
+/**
+ * Set the receive-side flow control window and maximum window for a stream.
+ * Credit that has already been issued to the peer is never withdrawn.
+ */
+int quic_stream_set_recv_window(struct quic_conn_t *conn,
+                                uint64_t stream_id,
+                                uint64_t window,
+                                uint64_t max_window);
+
 /**
  * Return the stream’s send capacity in bytes.
  */
@@ -1284,6 +2209,17 @@ int quic_stream_set_context(struct quic_conn_t *conn, uint64_t stream_id, void *
  */
 void *quic_stream_context(struct quic_conn_t *conn, uint64_t stream_id);
 
+/**
+ * Set the callback which decides whether the lost data of a stream is
+ * retransmitted. The callback returns true to retransmit the data, or false
+ * to reset the send-side of the stream with the error code written to `err`.
+ * A null callback restores the default, which always retransmits.
+ */
+int quic_stream_set_loss_callback(struct quic_conn_t *conn,
+                                  uint64_t stream_id,
+                                  quic_stream_loss_callback_t callback,
+                                  void *ctx);
+
 /**
  * Extract the header form, version and destination connection id from the
  * QUIC packet.
@@ -0,0 +1,416 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(unused_variables)]
+
+use std::time::Duration;
+use std::time::Instant;
+
+use super::CongestionController;
+use super::CongestionStats;
+use super::CoupledPaths;
+use crate::connection::rtt::RttEstimator;
+use crate::connection::space::SentPacket;
+use crate::RecoveryConfig;
+
+/// The upper bound of the multiplicative decrease factor on packet loss.
+const MAX_DECREASE_FACTOR: f64 = 1.5;
+
+/// BALIA Configuration.
+#[derive(Debug)]
+pub struct BaliaConfig {
+    /// Minimal congestion window in bytes.
+    min_congestion_window: u64,
+
+    /// Initial congestion window in bytes.
+    initial_congestion_window: u64,
+
+    /// The threshold for slow start in bytes.
+    slow_start_thresh: u64,
+
+    /// Max datagram size in bytes.
+    max_datagram_size: u64,
+}
+
+impl BaliaConfig {
+    pub fn from(conf: &RecoveryConfig) -> Self {
+        let max_datagram_size = conf.max_datagram_size as u64;
+        let min_congestion_window = conf.min_cwnd();
+        let initial_congestion_window = conf.initial_cwnd();
+        let slow_start_thresh = conf.slow_start_thresh.saturating_mul(max_datagram_size);
+
+        Self {
+            min_congestion_window,
+            initial_congestion_window,
+            slow_start_thresh,
+            max_datagram_size,
+        }
+    }
+}
+
+impl Default for BaliaConfig {
+    fn default() -> Self {
+        Self {
+            min_congestion_window: 2 * crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            initial_congestion_window: 10 * crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            slow_start_thresh: u64::MAX,
+            max_datagram_size: crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+        }
+    }
+}
+
+/// BALIA (Balanced Linked Adaptation) is a coupled congestion controller for
+/// multipath connections. The window increase of each path depends on the
+/// sending rates of all the paths of the connection, so that the connection
+/// takes no more capacity than a single-path flow at a shared bottleneck, and
+/// the traffic is shifted to the less congested paths. The window decrease
+/// on loss is larger on the paths with a lower sending rate. On a single path
+/// it behaves like Reno.
+///
+/// See draft-walid-mptcp-congestion-control-04.
+#[derive(Debug)]
+pub struct Balia {
+    /// Configuration.
+    config: BaliaConfig,
+
+    /// Congestion window in bytes.
+    cwnd: u64,
+
+    /// Slow start threshold in bytes.
+    ssthresh: u64,
+
+    /// The fraction of the window increase in bytes, which is carried over
+    /// to the next ACK.
+    cwnd_inc: f64,
+
+    /// The latest smoothed RTT of the path.
+    srtt: Duration,
+
+    /// The sending rates of the other paths of the connection.
+    others: CoupledPaths,
+
+    /// The time of the last congestion window reduction.
+    recovery_start_time: Option<Instant>,
+
+    /// Congestion statistics.
+    stats: CongestionStats,
+}
+
+impl Balia {
+    pub fn new(config: BaliaConfig) -> Self {
+        let cwnd = config.initial_congestion_window;
+        let ssthresh = config.slow_start_thresh;
+
+        Self {
+            config,
+            cwnd,
+            ssthresh,
+            cwnd_inc: 0.0,
+            srtt: Duration::ZERO,
+            others: CoupledPaths::default(),
+            recovery_start_time: None,
+            stats: Default::default(),
+        }
+    }
+
+    /// Return the sending rate of the path in bytes per second.
+    fn sending_rate(&self) -> f64 {
+        self.cwnd as f64 / self.srtt.as_secs_f64()
+    }
+
+    /// Return the ratio of the highest sending rate among the paths to the
+    /// sending rate of the path, which is not less than 1.
+    fn alpha(&self) -> f64 {
+        if self.srtt.is_zero() || self.cwnd == 0 {
+            return 1.0;
+        }
+        (self.others.max_rate / self.sending_rate()).max(1.0)
+    }
+
+    /// Return the window increase in bytes for the acked bytes in congestion
+    /// avoidance:
+    ///  w_r += (x_r / rtt_r) / (sum(x))^2 * ((1 + a_r) / 2) * ((4 + a_r) / 5)
+    /// where x is the sending rate and a_r is alpha. Since x_r = w_r / rtt_r,
+    /// the first term equals 1 / (w_r * (sum(x) / x_r)^2).
+    fn cwnd_increment(&self, acked_bytes: u64) -> f64 {
+        let mss = self.config.max_datagram_size as f64;
+        let cwnd = self.cwnd.max(1) as f64;
+        let (ratio, alpha) = if self.srtt.is_zero() {
+            (1.0, 1.0)
+        } else {
+            (
+                1.0 + self.others.sum_rate / self.sending_rate(),
+                self.alpha(),
+            )
+        };
+
+        acked_bytes as f64 * mss / (cwnd * ratio * ratio)
+            * ((1.0 + alpha) / 2.0)
+            * ((4.0 + alpha) / 5.0)
+    }
+}
+
+impl CongestionController for Balia {
+    fn name(&self) -> &str {
+        "BALIA"
+    }
+
+    fn on_sent(&mut self, now: Instant, packet: &mut SentPacket, bytes_in_flight: u64) {
+        let sent_bytes = packet.sent_size as u64;
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_sent_in_total = self.stats.bytes_sent_in_total.saturating_add(sent_bytes);
+        if self.in_slow_start() {
+            self.stats.bytes_sent_in_slow_start = self
+                .stats
+                .bytes_sent_in_slow_start
+                .saturating_add(sent_bytes);
+        }
+    }
+
+    fn begin_ack(&mut self, now: Instant, bytes_in_flight: u64) {
+        // Do nothing.
+    }
+
+    fn on_ack(
+        &mut self,
+        packet: &mut SentPacket,
+        now: Instant,
+        app_limited: bool,
+        rtt: &RttEstimator,
+        bytes_in_flight: u64,
+    ) {
+        let acked_bytes = packet.sent_size as u64;
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_acked_in_total =
+            self.stats.bytes_acked_in_total.saturating_add(acked_bytes);
+        if self.in_slow_start() {
+            self.stats.bytes_acked_in_slow_start = self
+                .stats
+                .bytes_acked_in_slow_start
+                .saturating_add(acked_bytes);
+        }
+        self.srtt = rtt.smoothed_rtt();
+
+        if app_limited || self.in_recovery(packet.time_sent) {
+            return;
+        }
+
+        if self.in_slow_start() {
+            self.cwnd = self.cwnd.saturating_add(acked_bytes);
+            return;
+        }
+
+        // Coupled increase in congestion avoidance, by max datagram size.
+        let mss = self.config.max_datagram_size as f64;
+        self.cwnd_inc += self.cwnd_increment(acked_bytes);
+        if self.cwnd_inc >= mss {
+            let inc = (self.cwnd_inc / mss).floor() * mss;
+            self.cwnd = self.cwnd.saturating_add(inc as u64);
+            self.cwnd_inc -= inc;
+        }
+    }
+
+    fn end_ack(&mut self) {
+        // Do nothing.
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        packet: &SentPacket,
+        is_persistent_congestion: bool,
+        lost_bytes: u64,
+        bytes_in_flight: u64,
+    ) {
+        self.stats.bytes_lost_in_total = self.stats.bytes_lost_in_total.saturating_add(lost_bytes);
+        self.stats.bytes_in_flight = bytes_in_flight;
+        if self.in_slow_start() {
+            self.stats.bytes_lost_in_slow_start = self
+                .stats
+                .bytes_lost_in_slow_start
+                .saturating_add(lost_bytes);
+        }
+
+        if is_persistent_congestion {
+            self.recovery_start_time = Some(now);
+            self.cwnd = self.minimal_window();
+            self.cwnd_inc = 0.0;
+            return;
+        }
+
+        if self.in_recovery(packet.time_sent) {
+            return;
+        }
+
+        // Coupled decrease at most once per round trip:
+        //  w_r -= (w_r / 2) * min(a_r, 1.5)
+        self.recovery_start_time = Some(now);
+        let factor = self.alpha().min(MAX_DECREASE_FACTOR) / 2.0;
+        self.cwnd = ((self.cwnd as f64 * (1.0 - factor)) as u64).max(self.minimal_window());
+        self.ssthresh = self.cwnd;
+        self.cwnd_inc = 0.0;
+    }
+
+    fn on_coupled_update(&mut self, others: &CoupledPaths) {
+        self.others = *others;
+    }
+
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        self.cwnd = cwnd.max(self.minimal_window());
+        self.ssthresh = self.cwnd;
+        self.cwnd_inc = 0.0;
+    }
+
+    fn in_slow_start(&self) -> bool {
+        self.cwnd < self.ssthresh
+    }
+
+    fn in_recovery(&self, sent_time: Instant) -> bool {
+        self.recovery_start_time.is_some_and(|t| sent_time <= t)
+    }
+
+    fn congestion_window(&self) -> u64 {
+        self.cwnd.max(self.config.min_congestion_window)
+    }
+
+    fn initial_window(&self) -> u64 {
+        self.config.initial_congestion_window
+    }
+
+    fn minimal_window(&self) -> u64 {
+        self.config.min_congestion_window
+    }
+
+    fn stats(&self) -> &CongestionStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_packet(pkt_num: u64, time_sent: Instant) -> SentPacket {
+        SentPacket {
+            pkt_num,
+            ack_eliciting: true,
+            in_flight: true,
+            sent_size: 1200,
+            time_sent,
+            ..SentPacket::default()
+        }
+    }
+
+    fn new_balia() -> Balia {
+        let conf = RecoveryConfig {
+            initial_congestion_window: 10,
+            min_congestion_window: 2,
+            max_datagram_size: 1200,
+            ..RecoveryConfig::default()
+        };
+        Balia::new(BaliaConfig::from(&conf))
+    }
+
+    /// Ack a round of packets, i.e. a full congestion window.
+    fn ack_round(b: &mut Balia, now: Instant, rtt: &RttEstimator) {
+        let n = b.congestion_window() / 1200;
+        b.begin_ack(now, 0);
+        for i in 0..n {
+            let mut pkt = new_packet(i, now - rtt.smoothed_rtt() / 2);
+            b.on_ack(&mut pkt, now, false, rtt, 0);
+        }
+        b.end_ack();
+    }
+
+    /// Enter congestion avoidance with a halved window.
+    fn enter_congestion_avoidance(b: &mut Balia, now: Instant) {
+        let pkt = new_packet(0, now);
+        b.on_congestion_event(now, &pkt, false, 1200, 0);
+        assert_eq!(b.in_slow_start(), false);
+    }
+
+    #[test]
+    fn balia_init() {
+        let b = new_balia();
+        assert_eq!(b.name(), "BALIA");
+        assert_eq!(b.congestion_window(), 1200 * 10);
+        assert_eq!(b.initial_window(), 1200 * 10);
+        assert_eq!(b.minimal_window(), 1200 * 2);
+        assert_eq!(b.in_slow_start(), true);
+        assert_eq!(b.in_recovery(Instant::now()), false);
+        assert_eq!(b.pacing_rate(), None);
+    }
+
+    #[test]
+    fn balia_single_path() {
+        let mut b = new_balia();
+        let mut rtt = RttEstimator::new(Duration::from_millis(100));
+        rtt.update(Duration::ZERO, Duration::from_millis(100));
+        let mut now = Instant::now();
+
+        // The window is halved on loss and increased by one datagram per
+        // round trip, as Reno.
+        enter_congestion_avoidance(&mut b, now);
+        assert_eq!(b.congestion_window(), 1200 * 5);
+        for i in 0..2 {
+            now += Duration::from_millis(100);
+            ack_round(&mut b, now, &rtt);
+            assert_eq!(b.congestion_window(), 1200 * (6 + i));
+        }
+        assert_eq!(b.stats().bytes_lost_in_total, 1200);
+
+        // Persistent congestion
+        let pkt = new_packet(0, now);
+        b.on_congestion_event(now, &pkt, true, 1200, 0);
+        assert_eq!(b.congestion_window(), b.minimal_window());
+    }
+
+    #[test]
+    fn balia_coupled_paths() {
+        let mut rtt = RttEstimator::new(Duration::from_millis(100));
+        rtt.update(Duration::ZERO, Duration::from_millis(100));
+        let mut now = Instant::now();
+
+        // Two paths with the same window and RTT. Each path increases its
+        // window by about a quarter of a datagram per round trip, so that the
+        // aggregated increase is less than a single-path flow.
+        let mut b = new_balia();
+        enter_congestion_avoidance(&mut b, now);
+        let cwnd = b.congestion_window();
+        let rate = cwnd as f64 / 0.1;
+        b.on_coupled_update(&CoupledPaths {
+            sum_rate: rate,
+            max_rate: rate,
+        });
+        for _ in 0..4 {
+            now += Duration::from_millis(100);
+            ack_round(&mut b, now, &rtt);
+        }
+        assert_eq!(b.congestion_window(), cwnd + 1200);
+
+        // The window of the path with a lower rate is reduced more on loss.
+        let mut b = new_balia();
+        b.srtt = Duration::from_millis(100);
+        let cwnd = b.congestion_window();
+        let rate = cwnd as f64 / 0.1;
+        b.on_coupled_update(&CoupledPaths {
+            sum_rate: rate * 4.0,
+            max_rate: rate * 4.0,
+        });
+        enter_congestion_avoidance(&mut b, now);
+        assert_eq!(b.congestion_window(), cwnd / 4);
+    }
+}
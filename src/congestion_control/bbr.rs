@@ -72,10 +72,8 @@ pub struct BbrConfig {
 impl BbrConfig {
     pub fn from(conf: &RecoveryConfig) -> Self {
         let max_datagram_size = conf.max_datagram_size as u64;
-        let min_cwnd = conf.min_congestion_window.saturating_mul(max_datagram_size);
-        let initial_cwnd = conf
-            .initial_congestion_window
-            .saturating_mul(max_datagram_size);
+        let min_cwnd = conf.min_cwnd();
+        let initial_cwnd = conf.initial_cwnd();
 
         Self {
             min_cwnd,
@@ -257,7 +255,8 @@ pub struct Bbr {
     /// BBR.BtlBwFilter: The max filter used to estimate BBR.BtlBw.
     btlbwfilter: MinMax,
 
-    /// Delivery rate estimator.
+    /// Snapshot of the delivery rate estimator of the path, which is updated
+    /// upon each rate sample.
     delivery_rate_estimator: DeliveryRateEstimator,
 
     /// BBR.RTprop: BBR's estimated two-way round-trip propagation delay of path,
@@ -634,12 +633,12 @@ impl Bbr {
     /// to its target operating point of rate balance and a full pipe.
     ///
     /// See draft-cardwell-iccrg-bbr-congestion-control-00 Section 4.3.4.4.
-    fn handle_restart_from_idle(&mut self, bytes_in_flight: u64) {
+    fn handle_restart_from_idle(&mut self, bytes_in_flight: u64, is_app_limited: bool) {
         // If the flow's BBR.state is ProbeBW, and the flow is
         // application-limited, and there are no packets in flight currently,
         // then at the moment the flow sends one or more packets BBR sets
         // BBR.pacing_rate to exactly BBR.BtlBw.
-        if bytes_in_flight == 0 && self.delivery_rate_estimator.is_app_limited() {
+        if bytes_in_flight == 0 && is_app_limited {
             self.is_idle_restart = true;
 
             if self.state == BbrStateMachine::ProbeBW {
@@ -947,13 +946,10 @@ impl CongestionController for Bbr {
     }
 
     fn on_sent(&mut self, now: Instant, packet: &mut SentPacket, bytes_in_flight: u64) {
-        self.delivery_rate_estimator.on_packet_sent(
-            packet,
+        self.handle_restart_from_idle(
             self.stats.bytes_in_flight,
-            self.stats.bytes_lost_in_total,
+            packet.rate_sample_state.is_app_limited,
         );
-
-        self.handle_restart_from_idle(self.stats.bytes_in_flight);
         self.stats.bytes_in_flight += packet.sent_size as u64;
     }
 
@@ -974,9 +970,6 @@ impl CongestionController for Bbr {
         _rtt: &RttEstimator,
         bytes_in_flight: u64,
     ) {
-        // Update rate sample by each ack packet.
-        self.delivery_rate_estimator.update_rate_sample(packet);
-
         // Update stats.
         self.stats.bytes_in_flight = self
             .stats
@@ -1004,10 +997,15 @@ impl CongestionController for Bbr {
             .max(packet.rate_sample_state.delivered);
     }
 
-    fn end_ack(&mut self) {
-        // Generate rate sample.
-        self.delivery_rate_estimator.generate_rate_sample();
+    fn on_rate_sample(&mut self, estimator: &DeliveryRateEstimator) {
+        self.delivery_rate_estimator.clone_from(estimator);
+    }
 
+    fn is_rate_limited(&self) -> bool {
+        self.state == BbrStateMachine::ProbeRTT
+    }
+
+    fn end_ack(&mut self) {
         // Check if exit recovery
         if self.in_recovery && !self.in_recovery(self.ack_state.last_ack_packet_sent_time) {
             self.exit_recovery();
@@ -1047,6 +1045,25 @@ impl CongestionController for Bbr {
         }
     }
 
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        // The window over BBR.RTprop is taken as a bandwidth sample, so that
+        // the window is not reduced to the target cwnd of a lower estimate.
+        if self.rtprop != Duration::MAX {
+            let bw = (cwnd as f64 / self.rtprop.as_secs_f64()) as u64;
+            self.btlbwfilter.update_max(self.round.round_count, bw);
+            self.btlbw = self.btlbwfilter.get();
+        }
+        self.cwnd = cwnd.max(self.config.min_cwnd);
+
+        // The pipe is considered to be filled, and BBR leaves Startup.
+        self.full_pipe.is_filled_pipe = true;
+        self.full_pipe.full_bw = self.btlbw;
+        if self.state == BbrStateMachine::Startup || self.state == BbrStateMachine::Drain {
+            self.enter_probe_bw(now);
+        }
+        self.set_pacing_rate_with_gain(self.pacing_gain);
+    }
+
     fn congestion_window(&self) -> u64 {
         self.cwnd.max(self.config.min_cwnd)
     }
@@ -1071,6 +1088,26 @@ impl CongestionController for Bbr {
         self.state == BbrStateMachine::Startup
     }
 
+    fn bandwidth(&self) -> Option<u64> {
+        Some(self.btlbw)
+    }
+
+    fn min_rtt(&self) -> Option<Duration> {
+        if self.rtprop == Duration::MAX {
+            return None;
+        }
+        Some(self.rtprop)
+    }
+
+    fn state(&self) -> &str {
+        match self.state {
+            BbrStateMachine::Startup => "Startup",
+            BbrStateMachine::Drain => "Drain",
+            BbrStateMachine::ProbeBW => "ProbeBW",
+            BbrStateMachine::ProbeRTT => "ProbeRTT",
+        }
+    }
+
     fn stats(&self) -> &CongestionStats {
         &self.stats
     }
@@ -1078,5 +1115,38 @@ impl CongestionController for Bbr {
 
 #[cfg(test)]
 mod tests {
-    // todo: unit test case
+    use super::*;
+
+    #[test]
+    fn bbr_careful_resume() {
+        let now = Instant::now();
+        let mut bbr = Bbr::new(BbrConfig::default());
+        bbr.rtprop = Duration::from_millis(125);
+        assert!(bbr.in_slow_start());
+
+        // The window is used as a bandwidth sample over BBR.RTprop, and BBR
+        // continues in ProbeBW
+        let cwnd = 1200 * 1000;
+        bbr.on_careful_resume(now, cwnd);
+        assert_eq!(bbr.congestion_window(), cwnd);
+        assert_eq!(bbr.bandwidth(), Some(cwnd * 8));
+        assert!(bbr.is_filled_pipe());
+        assert!(!bbr.in_slow_start());
+        assert_eq!(bbr.state(), "ProbeBW");
+        assert_eq!(
+            bbr.pacing_rate(),
+            Some((bbr.pacing_gain * (cwnd * 8) as f64) as u64)
+        );
+
+        // The window is kept by the target cwnd
+        bbr.set_cwnd();
+        assert_eq!(bbr.congestion_window(), cwnd);
+
+        // The window is reduced upon a safe retreat, but not below the minimum
+        bbr.on_careful_resume(now, cwnd / 2);
+        assert_eq!(bbr.congestion_window(), cwnd / 2);
+        assert_eq!(bbr.bandwidth(), Some(cwnd * 8));
+        bbr.on_careful_resume(now, 0);
+        assert_eq!(bbr.congestion_window(), bbr.minimal_window());
+    }
 }
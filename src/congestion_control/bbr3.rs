@@ -85,10 +85,8 @@ pub struct Bbr3Config {
 impl Bbr3Config {
     pub fn from(conf: &RecoveryConfig) -> Self {
         let max_datagram_size = conf.max_datagram_size as u64;
-        let min_cwnd = conf.min_congestion_window.saturating_mul(max_datagram_size);
-        let initial_cwnd = conf
-            .initial_congestion_window
-            .saturating_mul(max_datagram_size);
+        let min_cwnd = conf.min_cwnd();
+        let initial_cwnd = conf.initial_cwnd();
 
         Self {
             min_cwnd,
@@ -499,7 +497,8 @@ pub struct Bbr3 {
     /// Whether a roundtrip in ProbeRTT state ends.
     probe_rtt_round_done: bool,
 
-    /// Delivery rate estimator.
+    /// Snapshot of the delivery rate estimator of the path, which is updated
+    /// upon each rate sample.
     delivery_rate_estimator: DeliveryRateEstimator,
 
     /// Accumulate information from a single ACK/SACK.
@@ -1315,11 +1314,16 @@ impl Bbr3 {
     }
 
     /// See <https://www.ietf.org/archive/id/draft-cardwell-iccrg-bbr-congestion-control-02.html#name-restarting-from-idle>.
-    fn handle_restart_from_idle(&mut self, now: Instant, bytes_in_flight: u64) {
+    fn handle_restart_from_idle(
+        &mut self,
+        now: Instant,
+        bytes_in_flight: u64,
+        is_app_limited: bool,
+    ) {
         // When restarting from idle, BBR leaves its cwnd as-is and paces
         // packets at exactly BBR.BtlBw, aiming to return as quickly as possible
         // to its target operating point of rate balance and a full pipe.
-        if bytes_in_flight == 0 && self.is_app_limited() {
+        if bytes_in_flight == 0 && is_app_limited {
             self.idle_restart = true;
             self.extra_acked_interval_start = Some(now);
 
@@ -1473,7 +1477,10 @@ impl Bbr3 {
 
         // Inflight at transmit.
         self.ack_state.tx_in_flight = packet.rate_sample_state.tx_in_flight;
-        self.ack_state.lost = self.stats.bytes_lost_in_total - packet.rate_sample_state.lost;
+        self.ack_state.lost = self
+            .stats
+            .bytes_lost_in_total
+            .saturating_sub(packet.rate_sample_state.lost);
         self.delivery_rate_estimator
             .set_app_limited(packet.rate_sample_state.is_app_limited);
 
@@ -1846,13 +1853,11 @@ impl CongestionController for Bbr3 {
     }
 
     fn on_sent(&mut self, now: Instant, packet: &mut SentPacket, bytes_in_flight: u64) {
-        self.delivery_rate_estimator.on_packet_sent(
-            packet,
+        self.handle_restart_from_idle(
+            now,
             self.stats.bytes_in_flight,
-            self.stats.bytes_lost_in_total,
+            packet.rate_sample_state.is_app_limited,
         );
-
-        self.handle_restart_from_idle(now, self.stats.bytes_in_flight);
         self.stats.bytes_in_flight += packet.sent_size as u64;
     }
 
@@ -1875,9 +1880,6 @@ impl CongestionController for Bbr3 {
         _rtt: &RttEstimator,
         bytes_in_flight: u64,
     ) {
-        // Update rate sample by each ack packet.
-        self.delivery_rate_estimator.update_rate_sample(packet);
-
         // Update stats.
         self.stats.bytes_in_flight = self
             .stats
@@ -1905,12 +1907,17 @@ impl CongestionController for Bbr3 {
             .max(packet.rate_sample_state.delivered);
     }
 
+    fn on_rate_sample(&mut self, estimator: &DeliveryRateEstimator) {
+        self.delivery_rate_estimator.clone_from(estimator);
+    }
+
+    fn is_rate_limited(&self) -> bool {
+        self.state == State::ProbeRTT
+    }
+
     fn end_ack(&mut self) {
         let bytes_in_flight: u64 = self.stats.bytes_in_flight;
 
-        // Generate rate sample.
-        self.delivery_rate_estimator.generate_rate_sample();
-
         // Check if exit recovery
         if self.in_recovery && !self.in_recovery(self.ack_state.last_ack_packet_sent_time) {
             self.exit_recovery();
@@ -1953,9 +1960,57 @@ impl CongestionController for Bbr3 {
         }
     }
 
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        // The window over BBR.min_rtt is taken as a bandwidth sample, so that
+        // the window is not reduced to the inflight bounds of a lower
+        // estimate.
+        if self.min_rtt != Duration::MAX {
+            let bw = (cwnd as f64 / self.min_rtt.as_secs_f64()) as u64;
+            self.max_bw_filter.bw[1] = bw.max(self.max_bw_filter.bw[1]);
+            self.max_bw = self.max_bw_filter.max_bw();
+            self.bound_bw_for_model();
+        }
+        self.cwnd = cwnd.max(self.config.min_cwnd);
+        if self.inflight_hi != u64::MAX {
+            self.inflight_hi = self.inflight_hi.max(self.cwnd);
+        }
+
+        // The pipe is considered to be filled, and BBR leaves Startup.
+        self.full_pipe.is_filled_pipe = true;
+        self.full_pipe.full_bw = self.max_bw;
+        if self.state == State::Startup || self.state == State::Drain {
+            self.enter_probe_bw(now);
+            self.update_gains();
+        }
+        self.set_pacing_rate();
+    }
+
     fn pacing_rate(&self) -> Option<u64> {
         Some(self.pacing_rate)
     }
+
+    fn bandwidth(&self) -> Option<u64> {
+        Some(self.bw)
+    }
+
+    fn min_rtt(&self) -> Option<Duration> {
+        if self.min_rtt == Duration::MAX {
+            return None;
+        }
+        Some(self.min_rtt)
+    }
+
+    fn state(&self) -> &str {
+        match self.state {
+            State::Startup => "Startup",
+            State::Drain => "Drain",
+            State::ProbeBwDown => "ProbeBwDown",
+            State::ProbeBwCruise => "ProbeBwCruise",
+            State::ProbeBwRefill => "ProbeBwRefill",
+            State::ProbeBwUp => "ProbeBwUp",
+            State::ProbeRTT => "ProbeRTT",
+        }
+    }
 }
 
 #[cfg(test)]
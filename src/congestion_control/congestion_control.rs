@@ -17,6 +17,9 @@
 use core::str::FromStr;
 use std::any::Any;
 use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use std::time::Instant;
 
 use crate::connection::rtt::RttEstimator;
@@ -24,6 +27,8 @@ use crate::connection::space::SentPacket;
 use crate::Error;
 use crate::RecoveryConfig;
 use crate::Result;
+pub use balia::Balia;
+pub use balia::BaliaConfig;
 pub use bbr::Bbr;
 pub use bbr::BbrConfig;
 pub use bbr3::Bbr3;
@@ -33,10 +38,25 @@ pub use copa::CopaConfig;
 pub use copa::COPA_DELTA;
 pub use cubic::Cubic;
 pub use cubic::CubicConfig;
+pub use delivery_rate::DeliveryRateEstimator;
 pub use dummy::Dummy;
 pub use dummy::DummyConfig;
+pub use hybrid::Hybrid;
+pub use hybrid::HybridConfig;
 pub use hystart_plus_plus::HystartPlusPlus;
+pub use ledbat::Ledbat;
+pub use ledbat::LedbatConfig;
+pub use ledbat::LEDBAT_TARGET_DELAY;
+pub use nada::Nada;
+pub use nada::NadaConfig;
+pub use nada::NADA_MAX_BITRATE;
+pub use nada::NADA_MIN_BITRATE;
 pub use pacing::Pacer;
+pub use pacing::MAX_BURST_PACKET_NUM;
+pub use pacing::MIN_BURST_PACKET_NUM;
+pub use prague::Prague;
+pub use prague::PragueConfig;
+use prr::Prr;
 
 /// Available congestion control algorithms.
 #[repr(C)]
@@ -69,6 +89,37 @@ pub enum CongestionControlAlgorithm {
     /// Dummy is a simple congestion controller with a static congestion window.
     /// It is intended to be used for testing and experiments.
     Dummy,
+
+    /// Prague is a scalable congestion control algorithm for L4S, which
+    /// responds to ECN-CE marks in proportion to their extent instead of
+    /// treating them like loss. ECN should be enabled for it to take effect.
+    /// (Experimental)
+    Prague,
+
+    /// Hybrid runs the delay-based COPA normally, and falls back to the
+    /// loss-based CUBIC while competing with buffer-filling flows.
+    /// (Experimental)
+    Hybrid,
+
+    /// LEDBAT++ is a scavenger congestion control algorithm for background
+    /// transfers, which keeps the queueing delay below a target and yields to
+    /// other flows sharing the bottleneck.
+    /// (Experimental)
+    Ledbat,
+
+    /// NADA is a rate-based congestion control algorithm for real-time
+    /// interactive media, which reacts to the queueing delay and the loss and
+    /// produces a target bitrate for the media encoder.
+    /// (Experimental)
+    Nada,
+
+    /// BALIA is a coupled congestion control algorithm for multipath
+    /// connections. The window of each path is adjusted according to the
+    /// sending rates of all the paths, so that the connection is no more
+    /// aggressive than a single-path flow at a shared bottleneck. It behaves
+    /// like Reno on a single path.
+    /// (Experimental)
+    Balia,
 }
 
 impl FromStr for CongestionControlAlgorithm {
@@ -85,12 +136,34 @@ impl FromStr for CongestionControlAlgorithm {
             Ok(CongestionControlAlgorithm::Copa)
         } else if algor.eq_ignore_ascii_case("dummy") {
             Ok(CongestionControlAlgorithm::Dummy)
+        } else if algor.eq_ignore_ascii_case("prague") {
+            Ok(CongestionControlAlgorithm::Prague)
+        } else if algor.eq_ignore_ascii_case("hybrid") {
+            Ok(CongestionControlAlgorithm::Hybrid)
+        } else if algor.eq_ignore_ascii_case("ledbat") {
+            Ok(CongestionControlAlgorithm::Ledbat)
+        } else if algor.eq_ignore_ascii_case("nada") {
+            Ok(CongestionControlAlgorithm::Nada)
+        } else if algor.eq_ignore_ascii_case("balia") {
+            Ok(CongestionControlAlgorithm::Balia)
         } else {
             Err(Error::InvalidConfig("unknown".into()))
         }
     }
 }
 
+/// The aggregated sending rates of the other paths of a multipath connection,
+/// which are used by the coupled congestion controllers. The sending rate of
+/// a path is the congestion window divided by the smoothed RTT.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CoupledPaths {
+    /// The sum of the sending rates of the other paths in bytes per second.
+    pub sum_rate: f64,
+
+    /// The highest sending rate of the other paths in bytes per second.
+    pub max_rate: f64,
+}
+
 /// Congestion control statistics.
 #[derive(Debug, Default, Clone)]
 pub struct CongestionStats {
@@ -114,9 +187,32 @@ pub struct CongestionStats {
 
     /// Total bytes lost.
     pub bytes_lost_in_total: u64,
+
+    /* Note: the following fields are lazily updated from Recovery */
+    /// Name of the congestion control algorithm.
+    pub algorithm: String,
+
+    /// Current state of the congestion control algorithm.
+    pub state: String,
+
+    /// Congestion window in bytes.
+    pub cwnd: u64,
+
+    /// Pacing rate in bytes per second.
+    pub pacing_rate: u64,
+
+    /// Bottleneck bandwidth estimated by the congestion control algorithm in
+    /// bytes per second. It is zero if not estimated.
+    pub bandwidth: u64,
+
+    /// Minimum roundtrip time in microseconds.
+    pub min_rtt: u64,
 }
 
 /// Congestion control interfaces shared by different algorithms.
+///
+/// It can also be implemented by applications to provide a custom congestion
+/// control algorithm, see `CongestionControllerFactory`.
 pub trait CongestionController {
     /// Name of congestion control algorithm.
     fn name(&self) -> &str;
@@ -150,6 +246,72 @@ pub trait CongestionController {
         bytes_in_flight: u64,
     );
 
+    /// Callback for a spurious congestion event, which is detected once all
+    /// the packets declared lost in the last congestion event are
+    /// acknowledged later. The congestion controller may undo the window
+    /// reduction of the event.
+    /// See RFC 9002 Section 7.6 and RFC 4015
+    fn on_spurious_congestion_event(&mut self, now: Instant) {}
+
+    /// Check if the congestion controller is scalable as specified by L4S.
+    /// If true, the packets are marked with ECT(1) instead of ECT(0), and the
+    /// ECN feedback is passed to `on_ecn_feedback()` instead of treating
+    /// ECN-CE marks as a congestion event.
+    /// See RFC 9330 and RFC 9331
+    fn is_l4s(&self) -> bool {
+        false
+    }
+
+    /// Callback for the ECN feedback of an ACK frame, which newly acknowledges
+    /// `ecn_acked` packets marked with ECT, and reports `ce_marked` packets
+    /// that were marked with ECN-CE. The `packet` is the largest acked packet.
+    fn on_ecn_feedback(
+        &mut self,
+        now: Instant,
+        packet: &SentPacket,
+        ecn_acked: u64,
+        ce_marked: u64,
+        bytes_in_flight: u64,
+    ) {
+    }
+
+    /// Callback for Careful Resume, which sets the congestion window to `cwnd`
+    /// once the saved capacity is validated, or to a half of the validated
+    /// capacity if congestion is detected during validation. The congestion
+    /// controller should continue in congestion avoidance.
+    /// See draft-ietf-tsvwg-careful-resume
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {}
+
+    /// Set the congestion window to `cwnd` when the congestion controller
+    /// takes over from another one, such as the mode switch of the hybrid
+    /// congestion controller. The congestion controller should continue in
+    /// congestion avoidance. By default, it is handled in the same way as
+    /// `on_careful_resume()`.
+    fn set_congestion_window(&mut self, now: Instant, cwnd: u64) {
+        self.on_careful_resume(now, cwnd);
+    }
+
+    /// Callback for the coupled congestion control of a multipath connection,
+    /// with the sending rates of the other paths. It is called after an ACK
+    /// frame is processed. The congestion controllers that are not coupled
+    /// ignore it.
+    fn on_coupled_update(&mut self, others: &CoupledPaths) {}
+
+    /// Callback for the delivery rate sample of an ACK frame. The delivery rate
+    /// estimator is maintained for each path and shared by all congestion
+    /// controllers. It is called after the newly acked packets are processed
+    /// by `on_ack()`, and before the packets are detected lost.
+    /// See draft-cheng-iccrg-delivery-rate-estimation
+    fn on_rate_sample(&mut self, estimator: &DeliveryRateEstimator) {}
+
+    /// Check if the sending rate is deliberately reduced by the congestion
+    /// controller, such as in the ProbeRTT state of BBR. If true, the path is
+    /// marked as application limited, so that the low rate samples are not
+    /// regarded as the bandwidth of the path.
+    fn is_rate_limited(&self) -> bool {
+        false
+    }
+
     /// Check if in slow start.
     fn in_slow_start(&self) -> bool {
         true
@@ -169,6 +331,33 @@ pub trait CongestionController {
         None
     }
 
+    /// Current target bitrate of the media encoder in bits per second.
+    /// If CCA is not designed for real-time media, return None.
+    fn target_bitrate(&self) -> Option<u64> {
+        None
+    }
+
+    /// Current bottleneck bandwidth estimated by CCA in bytes per second.
+    /// If CCA does not estimate bandwidth, return None.
+    fn bandwidth(&self) -> Option<u64> {
+        None
+    }
+
+    /// Current minimum RTT tracked by CCA.
+    /// If CCA does not track minimum RTT, return None.
+    fn min_rtt(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Name of the current state of CCA, such as "Startup" or "ProbeBW".
+    fn state(&self) -> &str {
+        if self.in_slow_start() {
+            "SlowStart"
+        } else {
+            "CongestionAvoidance"
+        }
+    }
+
     /// Initial congestion window.
     fn initial_window(&self) -> u64;
 
@@ -185,14 +374,51 @@ impl fmt::Debug for dyn CongestionController {
     }
 }
 
+/// Factory of user-provided congestion controllers.
+///
+/// A new congestion controller is built for each path of a connection. It
+/// takes precedence over the `congestion_control_algorithm` setting.
+pub trait CongestionControllerFactory: fmt::Debug + Send + Sync {
+    /// Build a congestion controller for a new path.
+    fn build(&self, conf: &RecoveryConfig) -> Box<dyn CongestionController>;
+}
+
+/// Selector of the congestion control algorithm for each server connection.
+pub trait CongestionControlSelector: Send + Sync {
+    /// Select the congestion control algorithm and its parameters for a new
+    /// connection by updating `conf`, which is initialized with the endpoint
+    /// settings. It is called once the ClientHello of the client is
+    /// processed, so that the server name and the negotiated application
+    /// protocol are known.
+    ///
+    /// Note that the ECN codepoint used for the connection follows the
+    /// selected congestion controller.
+    fn select(
+        &self,
+        peer_addr: SocketAddr,
+        server_name: Option<&str>,
+        alpn: &[u8],
+        conf: &mut RecoveryConfig,
+    );
+}
+
 /// Build a congestion controller.
 pub fn build_congestion_controller(conf: &RecoveryConfig) -> Box<dyn CongestionController> {
+    if let Some(factory) = &conf.congestion_controller_factory {
+        return factory.build(conf);
+    }
+
     match conf.congestion_control_algorithm {
         CongestionControlAlgorithm::Cubic => Box::new(Cubic::new(CubicConfig::from(conf))),
         CongestionControlAlgorithm::Bbr => Box::new(Bbr::new(BbrConfig::from(conf))),
         CongestionControlAlgorithm::Bbr3 => Box::new(Bbr3::new(Bbr3Config::from(conf))),
         CongestionControlAlgorithm::Copa => Box::new(Copa::new(CopaConfig::from(conf))),
         CongestionControlAlgorithm::Dummy => Box::new(Dummy::new(DummyConfig::from(conf))),
+        CongestionControlAlgorithm::Prague => Box::new(Prague::new(PragueConfig::from(conf))),
+        CongestionControlAlgorithm::Hybrid => Box::new(Hybrid::new(HybridConfig::from(conf))),
+        CongestionControlAlgorithm::Ledbat => Box::new(Ledbat::new(LedbatConfig::from(conf))),
+        CongestionControlAlgorithm::Nada => Box::new(Nada::new(NadaConfig::from(conf))),
+        CongestionControlAlgorithm::Balia => Box::new(Balia::new(BaliaConfig::from(conf))),
     }
 }
 
@@ -223,6 +449,21 @@ mod tests {
             ("dummy", Ok(CongestionControlAlgorithm::Dummy)),
             ("Dummy", Ok(CongestionControlAlgorithm::Dummy)),
             ("DUMMY", Ok(CongestionControlAlgorithm::Dummy)),
+            ("prague", Ok(CongestionControlAlgorithm::Prague)),
+            ("Prague", Ok(CongestionControlAlgorithm::Prague)),
+            ("PRAGUE", Ok(CongestionControlAlgorithm::Prague)),
+            ("hybrid", Ok(CongestionControlAlgorithm::Hybrid)),
+            ("Hybrid", Ok(CongestionControlAlgorithm::Hybrid)),
+            ("HYBRID", Ok(CongestionControlAlgorithm::Hybrid)),
+            ("ledbat", Ok(CongestionControlAlgorithm::Ledbat)),
+            ("Ledbat", Ok(CongestionControlAlgorithm::Ledbat)),
+            ("LEDBAT", Ok(CongestionControlAlgorithm::Ledbat)),
+            ("nada", Ok(CongestionControlAlgorithm::Nada)),
+            ("Nada", Ok(CongestionControlAlgorithm::Nada)),
+            ("NADA", Ok(CongestionControlAlgorithm::Nada)),
+            ("balia", Ok(CongestionControlAlgorithm::Balia)),
+            ("Balia", Ok(CongestionControlAlgorithm::Balia)),
+            ("BALIA", Ok(CongestionControlAlgorithm::Balia)),
             ("cubci", Err(Error::InvalidConfig("unknown".into()))),
         ];
 
@@ -266,16 +507,114 @@ mod tests {
         let cc = build_congestion_controller(&config.recovery);
         assert_eq!(cc.name(), "COPA");
 
+        config.set_congestion_control_algorithm(CongestionControlAlgorithm::Prague);
+        let cc = build_congestion_controller(&config.recovery);
+        assert_eq!(cc.name(), "PRAGUE");
+        assert_eq!(cc.is_l4s(), true);
+
+        config.set_congestion_control_algorithm(CongestionControlAlgorithm::Hybrid);
+        let cc = build_congestion_controller(&config.recovery);
+        assert_eq!(cc.name(), "HYBRID");
+
+        config.set_congestion_control_algorithm(CongestionControlAlgorithm::Ledbat);
+        let cc = build_congestion_controller(&config.recovery);
+        assert_eq!(cc.name(), "LEDBAT");
+        assert_eq!(cc.target_bitrate(), None);
+
+        config.set_congestion_control_algorithm(CongestionControlAlgorithm::Nada);
+        let cc = build_congestion_controller(&config.recovery);
+        assert_eq!(cc.name(), "NADA");
+        assert_eq!(cc.target_bitrate(), Some(NADA_MIN_BITRATE));
+
+        config.set_congestion_control_algorithm(CongestionControlAlgorithm::Balia);
+        let cc = build_congestion_controller(&config.recovery);
+        assert_eq!(cc.name(), "BALIA");
+
+        Ok(())
+    }
+
+    #[test]
+    fn congestion_control_window_in_bytes() -> Result<()> {
+        let mut config = Config::new()?;
+        config.set_initial_congestion_window(20);
+        config.set_min_congestion_window(4);
+        assert_eq!(config.recovery.initial_cwnd(), 20 * 1200);
+        assert_eq!(config.recovery.min_cwnd(), 4 * 1200);
+
+        // The windows in bytes take precedence over the windows in packets.
+        config.set_initial_congestion_window_bytes(100_000);
+        config.set_min_congestion_window_bytes(3_000);
+        assert_eq!(config.recovery.initial_cwnd(), 100_000);
+        assert_eq!(config.recovery.min_cwnd(), 3_000);
+
+        let cases = [
+            CongestionControlAlgorithm::Cubic,
+            CongestionControlAlgorithm::Bbr,
+            CongestionControlAlgorithm::Bbr3,
+            CongestionControlAlgorithm::Copa,
+            CongestionControlAlgorithm::Prague,
+            CongestionControlAlgorithm::Hybrid,
+            CongestionControlAlgorithm::Ledbat,
+            CongestionControlAlgorithm::Nada,
+            CongestionControlAlgorithm::Balia,
+        ];
+        for algor in cases {
+            config.set_congestion_control_algorithm(algor);
+            let cc = build_congestion_controller(&config.recovery);
+            assert_eq!(cc.initial_window(), 100_000, "{}", cc.name());
+            assert_eq!(cc.minimal_window(), 3_000, "{}", cc.name());
+        }
+
+        // The initial window is not less than the minimal window.
+        config.set_min_congestion_window_bytes(200_000);
+        assert_eq!(config.recovery.initial_cwnd(), 200_000);
+
+        // The windows in packets are used again once the windows in bytes
+        // are reset.
+        config.set_initial_congestion_window_bytes(0);
+        config.set_min_congestion_window_bytes(0);
+        assert_eq!(config.recovery.initial_cwnd(), 20 * 1200);
+        assert_eq!(config.recovery.min_cwnd(), 4 * 1200);
+
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct TestFactory;
+
+    impl CongestionControllerFactory for TestFactory {
+        fn build(&self, conf: &RecoveryConfig) -> Box<dyn CongestionController> {
+            Box::new(Dummy::new(DummyConfig::from(conf)))
+        }
+    }
+
+    #[test]
+    fn congestion_control_factory() -> Result<()> {
+        let mut config = Config::new()?;
+        config.set_congestion_controller_factory(Arc::new(TestFactory));
+
+        // The factory takes precedence over the algorithm setting.
+        config.set_congestion_control_algorithm(CongestionControlAlgorithm::Cubic);
+        let cc = build_congestion_controller(&config.recovery);
+        assert_eq!(cc.name(), "DUMMY");
         Ok(())
     }
 }
 
+mod balia;
 mod bbr;
 mod bbr3;
 mod copa;
 mod cubic;
 mod delivery_rate;
 mod dummy;
+mod hybrid;
 mod hystart_plus_plus;
+mod ledbat;
 mod minmax;
+mod nada;
 mod pacing;
+mod prague;
+mod prr;
+#[cfg(test)]
+mod simulator;
@@ -89,10 +89,8 @@ pub struct CopaConfig {
 impl CopaConfig {
     pub fn from(conf: &RecoveryConfig) -> Self {
         let max_datagram_size = conf.max_datagram_size as u64;
-        let min_cwnd = conf.min_congestion_window.saturating_mul(max_datagram_size);
-        let initial_cwnd = conf
-            .initial_congestion_window
-            .saturating_mul(max_datagram_size);
+        let min_cwnd = conf.min_cwnd();
+        let initial_cwnd = conf.initial_cwnd();
         let initial_rtt = Some(conf.initial_rtt);
 
         Self {
@@ -626,6 +624,22 @@ impl CongestionController for Copa {
         self.slow_start
     }
 
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        self.cwnd = cwnd.max(self.config.min_cwnd);
+        self.slow_start = false;
+        self.velocity = Velocity::default();
+    }
+
+    fn state(&self) -> &str {
+        if self.slow_start {
+            return "SlowStart";
+        }
+        match self.mode {
+            CompetingMode::Default => "Default",
+            CompetingMode::Competitive => "Competitive",
+        }
+    }
+
     fn stats(&self) -> &CongestionStats {
         &self.stats
     }
@@ -21,6 +21,7 @@ use log::*;
 use super::CongestionController;
 use super::CongestionStats;
 use super::HystartPlusPlus;
+use super::Prr;
 use crate::connection::rtt::RttEstimator;
 use crate::connection::space::SentPacket;
 use crate::RecoveryConfig;
@@ -78,6 +79,9 @@ pub struct CubicConfig {
     /// Enable fast convergence, default to true.
     fast_convergence_enabled: bool,
 
+    /// Enable Proportional Rate Reduction during recovery, default to false.
+    prr_enabled: bool,
+
     /// Initial rtt.
     initial_rtt: Option<Duration>,
 }
@@ -85,10 +89,8 @@ pub struct CubicConfig {
 impl CubicConfig {
     pub fn from(conf: &RecoveryConfig) -> Self {
         let max_datagram_size = conf.max_datagram_size as u64;
-        let min_congestion_window = conf.min_congestion_window.saturating_mul(max_datagram_size);
-        let initial_congestion_window = conf
-            .initial_congestion_window
-            .saturating_mul(max_datagram_size);
+        let min_congestion_window = conf.min_cwnd();
+        let initial_congestion_window = conf.initial_cwnd();
         let slow_start_thresh = conf.slow_start_thresh.saturating_mul(max_datagram_size);
         let initial_rtt = Some(conf.initial_rtt);
 
@@ -100,8 +102,9 @@ impl CubicConfig {
             slow_start_thresh,
             initial_rtt,
             max_datagram_size,
-            hystart_enabled: true,
+            hystart_enabled: conf.cubic_hystart_enabled,
             fast_convergence_enabled: true,
+            prr_enabled: conf.prr_enabled,
         }
     }
 
@@ -141,6 +144,12 @@ impl CubicConfig {
         self
     }
 
+    /// Enable Proportional Rate Reduction.
+    fn enable_prr(&mut self, enable: bool) -> &mut Self {
+        self.prr_enabled = enable;
+        self
+    }
+
     /// Update max datagram size.
     fn set_max_datagram_size(&mut self, max_datagram_size: u64) -> &mut Self {
         self.max_datagram_size = max_datagram_size;
@@ -160,10 +169,24 @@ impl Default for CubicConfig {
             max_datagram_size: crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
             hystart_enabled: true,
             fast_convergence_enabled: true,
+            prr_enabled: false,
         }
     }
 }
 
+/// The state of Cubic saved upon a congestion event.
+#[derive(Debug, Clone, Copy)]
+struct CubicPriorState {
+    cwnd: u64,
+    ssthresh: u64,
+    w_max: f64,
+    k: f64,
+    alpha: f64,
+    w_est: f64,
+    cwnd_inc: u64,
+    recovery_epoch_start: Option<Instant>,
+}
+
 /// Cubic congestion control algorithm.
 ///
 /// See <https://www.rfc-editor.org/rfc/rfc9438.html>.
@@ -175,6 +198,9 @@ pub struct Cubic {
     /// Hystart++ object.
     hystart: HystartPlusPlus,
 
+    /// Proportional Rate Reduction during recovery.
+    prr: Prr,
+
     /// Congestion window in bytes.
     cwnd: u64,
 
@@ -200,6 +226,10 @@ pub struct Cubic {
     /// Time of the last recovery event starts.
     recovery_epoch_start: Option<Instant>,
 
+    /// The state before the window is reduced in the last congestion event,
+    /// which is restored if the event turns out to be spurious.
+    prior: Option<CubicPriorState>,
+
     /// Sent time of the last packet.
     last_sent_time: Option<Instant>,
 
@@ -223,10 +253,12 @@ impl Cubic {
         );
         let pacing_rate = (initial_cwnd as f64 / initial_rtt.as_secs_f64()) as u64;
         let hystart_enabled = config.hystart_enabled;
+        let prr_enabled = config.prr_enabled;
         let alpha = 3.0 * (1.0 - config.beta) / (1.0 + config.beta);
         Self {
             config,
             hystart: HystartPlusPlus::new(hystart_enabled),
+            prr: Prr::new(prr_enabled),
             cwnd: initial_cwnd,
             ssthresh,
             w_max: 0_f64,
@@ -235,6 +267,7 @@ impl Cubic {
             w_est: 0_f64,
             cwnd_inc: 0_u64,
             recovery_epoch_start: None,
+            prior: None,
             last_sent_time: None,
             stats: Default::default(),
             pacing_rate,
@@ -296,6 +329,7 @@ impl CongestionController for Cubic {
 
         // Statistics.
         let sent_bytes = packet.sent_size as u64;
+        self.prr.on_sent(sent_bytes, bytes_in_flight);
 
         self.stats.bytes_in_flight = bytes_in_flight;
         self.stats.bytes_sent_in_total = self.stats.bytes_sent_in_total.saturating_add(sent_bytes);
@@ -309,7 +343,7 @@ impl CongestionController for Cubic {
     }
 
     fn begin_ack(&mut self, now: Instant, bytes_in_flight: u64) {
-        // Do nothing.
+        self.prr.begin_ack(bytes_in_flight);
     }
 
     fn on_ack(
@@ -334,6 +368,13 @@ impl CongestionController for Cubic {
                 .saturating_add(acked_bytes);
         }
 
+        // Recovery ends once a packet sent after the start of recovery is
+        // acked.
+        self.prr.on_ack(acked_bytes, bytes_in_flight);
+        if !self.in_recovery(sent_time) {
+            self.prr.on_recovery_end();
+        }
+
         if app_limited || self.in_recovery(sent_time) || rtt.smoothed_rtt().is_zero() {
             return;
         }
@@ -429,6 +470,9 @@ impl CongestionController for Cubic {
                 self.ssthresh = self.cwnd;
             }
         }
+
+        self.prr
+            .end_ack(self.ssthresh, self.config.max_datagram_size);
     }
 
     fn on_congestion_event(
@@ -452,10 +496,23 @@ impl CongestionController for Cubic {
 
         let sent_time = packet.time_sent;
 
+        self.prr.on_lost(bytes_in_flight);
         if self.in_recovery(sent_time) {
             return;
         }
 
+        // Save the state for undoing the window reduction.
+        self.prior = Some(CubicPriorState {
+            cwnd: self.cwnd,
+            ssthresh: self.ssthresh,
+            w_max: self.w_max,
+            k: self.k,
+            alpha: self.alpha,
+            w_est: self.w_est,
+            cwnd_inc: self.cwnd_inc,
+            recovery_epoch_start: self.recovery_epoch_start,
+        });
+
         // Enter recovery mode.
         self.recovery_epoch_start = Some(now);
 
@@ -485,12 +542,15 @@ impl CongestionController for Cubic {
         self.alpha = ALPHA;
 
         self.hystart.on_congestion_event();
+        self.prr
+            .on_recovery_start(bytes_in_flight.saturating_add(lost_bytes));
 
         // See <https://www.rfc-editor.org/rfc/rfc9002#section-7.6.2>.
         // When persistent congestion is declared, the sender's congestion
         // window MUST be reduced to the minimum congestion window.
         if is_persistent_congestion {
             self.recovery_epoch_start = None;
+            self.prior = None;
             self.w_max = self.cwnd as f64;
             self.ssthresh = self
                 .config
@@ -498,9 +558,38 @@ impl CongestionController for Cubic {
                 .max((self.cwnd as f64 * self.config.beta) as u64);
             self.cwnd_inc = 0;
             self.cwnd = self.config.min_congestion_window;
+            self.prr.on_recovery_end();
         }
     }
 
+    fn on_spurious_congestion_event(&mut self, now: Instant) {
+        let Some(prior) = self.prior.take() else {
+            return;
+        };
+
+        // Restore the window and the state of the window increase function
+        // before the spurious congestion event.
+        self.cwnd = self.cwnd.max(prior.cwnd);
+        self.ssthresh = self.ssthresh.max(prior.ssthresh);
+        self.w_max = prior.w_max;
+        self.k = prior.k;
+        self.alpha = prior.alpha;
+        self.w_est = prior.w_est;
+        self.cwnd_inc = prior.cwnd_inc;
+        self.recovery_epoch_start = prior.recovery_epoch_start;
+        self.prr.on_recovery_end();
+    }
+
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        self.cwnd = cwnd.max(self.config.min_congestion_window);
+        self.ssthresh = self.cwnd;
+        self.cwnd_inc = 0;
+        self.prr.on_recovery_end();
+
+        // Start a new epoch of congestion avoidance with the given window.
+        self.recovery_epoch_start = None;
+    }
+
     fn in_slow_start(&self) -> bool {
         self.cwnd < self.ssthresh
     }
@@ -510,7 +599,8 @@ impl CongestionController for Cubic {
     }
 
     fn congestion_window(&self) -> u64 {
-        self.cwnd.max(self.config.min_congestion_window)
+        let cwnd = self.prr.congestion_window().unwrap_or(self.cwnd);
+        cwnd.max(self.config.min_congestion_window)
     }
 
     fn initial_window(&self) -> u64 {
@@ -535,6 +625,17 @@ mod tests {
     use super::*;
     use crate::packet;
 
+    #[test]
+    fn cubic_config_hystart() {
+        let mut conf = RecoveryConfig::default();
+        assert!(CubicConfig::from(&conf).hystart_enabled);
+
+        conf.cubic_hystart_enabled = false;
+        let cubic = Cubic::new(CubicConfig::from(&conf));
+        assert!(!cubic.config.hystart_enabled);
+        assert!(!cubic.hystart.enabled());
+    }
+
     #[test]
     fn cubic_calc_k() {
         let cubic_cfg = CubicConfig::default();
@@ -699,6 +800,107 @@ mod tests {
         assert!(cubic.cwnd >= cubic.ssthresh);
     }
 
+    #[test]
+    fn cubic_prr() {
+        let mut cubic_cfg = CubicConfig::default();
+        cubic_cfg.enable_prr(true);
+        let mut cubic = Cubic::new(cubic_cfg);
+        let now = Instant::now();
+        let rtt = RttEstimator::new(Duration::from_millis(20));
+        let pkt_size: u64 = 1200;
+        let cwnd = cubic.congestion_window();
+
+        // Send a full window of packets.
+        let mut pkts: Vec<SentPacket> = Vec::new();
+        for n in 0..cwnd / pkt_size {
+            let mut pkt = SentPacket {
+                pkt_num: n,
+                time_sent: now,
+                ack_eliciting: true,
+                in_flight: true,
+                sent_size: pkt_size as usize,
+                ..SentPacket::default()
+            };
+            cubic.on_sent(now, &mut pkt, n * pkt_size);
+            pkts.push(pkt);
+        }
+
+        // The window is reduced, but it does not drop below the bytes in
+        // flight at once.
+        let time_lost = now + Duration::from_millis(10);
+        cubic.begin_ack(time_lost, cwnd);
+        cubic.on_congestion_event(time_lost, &pkts[0], false, pkt_size, cwnd - pkt_size);
+        cubic.end_ack();
+        let ssthresh = (cwnd as f64 * cubic.config.beta) as u64;
+        assert_eq!(cubic.ssthresh, ssthresh);
+        assert_eq!(cubic.congestion_window(), cwnd - pkt_size);
+
+        // A new packet is allowed to be sent in proportion to the delivered
+        // bytes.
+        let mut ack_time = now + Duration::from_millis(20);
+        cubic.begin_ack(ack_time, cwnd - pkt_size);
+        cubic.on_ack(&mut pkts[1], ack_time, false, &rtt, cwnd - 2 * pkt_size);
+        cubic.end_ack();
+        let snd_cnt = pkt_size * ssthresh / cwnd;
+        assert_eq!(cubic.congestion_window(), cwnd - 2 * pkt_size + snd_cnt);
+
+        let mut pkt = SentPacket {
+            pkt_num: pkts.len() as u64,
+            time_sent: ack_time,
+            ack_eliciting: true,
+            in_flight: true,
+            sent_size: pkt_size as usize,
+            ..SentPacket::default()
+        };
+        cubic.on_sent(ack_time, &mut pkt, cwnd - 2 * pkt_size);
+        assert_eq!(cubic.congestion_window(), cwnd - pkt_size);
+
+        // Recovery ends once the packet sent in recovery is acked.
+        ack_time += Duration::from_millis(20);
+        cubic.begin_ack(ack_time, cwnd - pkt_size);
+        cubic.on_ack(&mut pkt, ack_time, false, &rtt, cwnd - 2 * pkt_size);
+        cubic.end_ack();
+        assert_eq!(cubic.prr.congestion_window(), None);
+        assert!(cubic.congestion_window() >= ssthresh);
+    }
+
+    #[test]
+    fn cubic_spurious_congestion_event() {
+        let cubic_cfg = CubicConfig::default();
+        let mut cubic = Cubic::new(cubic_cfg);
+        let now = Instant::now();
+        let pkt = SentPacket {
+            pkt_num: 0,
+            time_sent: now,
+            ack_eliciting: true,
+            in_flight: true,
+            sent_size: 1200,
+            ..SentPacket::default()
+        };
+
+        // Nothing to undo.
+        let cwnd = cubic.congestion_window();
+        let ssthresh = cubic.ssthresh;
+        cubic.on_spurious_congestion_event(now);
+        assert_eq!(cubic.congestion_window(), cwnd);
+
+        // The window reduction is undone.
+        let time_lost = now + Duration::from_millis(10);
+        cubic.on_congestion_event(time_lost, &pkt, false, 1200, 0);
+        assert!(cubic.congestion_window() < cwnd);
+        assert_eq!(cubic.in_recovery(now), true);
+
+        cubic.on_spurious_congestion_event(time_lost);
+        assert_eq!(cubic.congestion_window(), cwnd);
+        assert_eq!(cubic.ssthresh, ssthresh);
+        assert_eq!(cubic.in_recovery(now), false);
+
+        // The window reduction upon persistent congestion is not undone.
+        cubic.on_congestion_event(time_lost, &pkt, true, 1200, 0);
+        cubic.on_spurious_congestion_event(time_lost);
+        assert_eq!(cubic.congestion_window(), cubic.minimal_window());
+    }
+
     #[test]
     fn cubic_in_recovery() {
         let cubic_cfg = CubicConfig::default();
@@ -729,6 +931,9 @@ mod tests {
         cubic_config.enable_fast_convergence(true);
         assert_eq!(cubic_config.fast_convergence_enabled, true);
 
+        cubic_config.enable_prr(true);
+        assert_eq!(cubic_config.prr_enabled, true);
+
         cubic_config.set_initial_congestion_window(initial_cwnd);
         assert_eq!(cubic_config.initial_congestion_window, initial_cwnd);
 
@@ -15,6 +15,9 @@
 //! A generic algorithm for a transport protocol sender to estimate the current
 //! delivery rate of its data on the fly.
 //!
+//! The estimator is maintained for each path by the loss recovery, and the
+//! rate samples are shared by all congestion controllers.
+//!
 //! See
 //! <https://datatracker.ietf.org/doc/html/draft-cheng-iccrg-delivery-rate-estimation-02>.
 
@@ -28,7 +31,7 @@ use crate::connection::space::{RateSamplePacketState, SentPacket};
 ///
 /// See
 /// <https://datatracker.ietf.org/doc/html/draft-cheng-iccrg-delivery-rate-estimation-02#section-3.1.3>.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct RateSample {
     /// rs.delivery_rate: The delivery rate sample (in most cases rs.delivered / rs.interval).
     delivery_rate: u64,
@@ -62,7 +65,7 @@ struct RateSample {
 /// Delivery rate estimator.
 ///
 /// <https://datatracker.ietf.org/doc/html/draft-cheng-iccrg-delivery-rate-estimation-02#section-3.1.1>.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DeliveryRateEstimator {
     /// C.delivered: The total amount of data (measured in octets or in packets) delivered
     /// so far over the lifetime of the transport connection. This does not include pure ACK packets.
@@ -76,6 +79,10 @@ pub struct DeliveryRateEstimator {
     /// holds the send time of most recently sent packet.
     first_sent_time: Instant,
 
+    /// C.lost: The total amount of data lost so far over the lifetime of the
+    /// transport connection.
+    lost: u64,
+
     /// C.app_limited: The index of the last transmitted packet marked as application-limited,
     /// or 0 if the connection is not currently application-limited.
     last_app_limited_pkt_num: u64,
@@ -203,6 +210,11 @@ impl DeliveryRateEstimator {
             / self.rate_sample.interval.as_micros() as u64;
     }
 
+    /// Upon packets being marked as lost.
+    pub fn on_packet_lost(&mut self, lost_bytes: u64) {
+        self.lost = self.lost.saturating_add(lost_bytes);
+    }
+
     /// Set app limited status and record the latest packet num as end of app limited mode.
     pub fn set_app_limited(&mut self, is_app_limited: bool) {
         self.last_app_limited_pkt_num = if is_app_limited {
@@ -223,6 +235,11 @@ impl DeliveryRateEstimator {
         self.delivered
     }
 
+    /// C.lost.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
     /// rs.delivered.
     pub fn sample_delivered(&self) -> u64 {
         self.rate_sample.delivered
@@ -238,6 +255,11 @@ impl DeliveryRateEstimator {
         self.rate_sample.delivery_rate
     }
 
+    /// rs.interval.
+    pub fn sample_interval(&self) -> Duration {
+        self.rate_sample.interval
+    }
+
     /// Get rate sample rtt.
     pub fn sample_rtt(&self) -> Duration {
         self.rate_sample.rtt
@@ -256,6 +278,7 @@ impl Default for DeliveryRateEstimator {
             delivered: 0,
             delivered_time: now,
             first_sent_time: now,
+            lost: 0,
             last_app_limited_pkt_num: 0,
             largest_acked_pkt_num: 0,
             last_sent_pkt_num: 0,
@@ -463,4 +486,32 @@ mod tests {
         );
         assert_eq!(rate_estimator.is_sample_app_limited(), false);
     }
+
+    #[test]
+    fn delivery_rate_on_packet_lost() {
+        let mut rate_estimator = DeliveryRateEstimator::default();
+        let now = Instant::now();
+        assert_eq!(rate_estimator.lost(), 0);
+
+        rate_estimator.on_packet_lost(1200);
+        rate_estimator.on_packet_lost(240);
+        assert_eq!(rate_estimator.lost(), 1440);
+
+        // The snapshot of C.lost is recorded in the sent packet.
+        let mut pkt = SentPacket {
+            pkt_num: 1,
+            time_sent: now,
+            sent_size: 240,
+            ..SentPacket::default()
+        };
+        let lost = rate_estimator.lost();
+        rate_estimator.on_packet_sent(&mut pkt, 0, lost);
+        assert_eq!(pkt.rate_sample_state.lost, 1440);
+
+        // A clone shares nothing with the estimator.
+        let snapshot = rate_estimator.clone();
+        rate_estimator.on_packet_lost(1200);
+        assert_eq!(snapshot.lost(), 1440);
+        assert_eq!(rate_estimator.lost(), 2640);
+    }
 }
@@ -31,10 +31,7 @@ pub struct DummyConfig {
 
 impl DummyConfig {
     pub fn from(conf: &RecoveryConfig) -> Self {
-        let max_datagram_size = conf.max_datagram_size as u64;
-        let initial_congestion_window = conf
-            .initial_congestion_window
-            .saturating_mul(max_datagram_size);
+        let initial_congestion_window = conf.initial_cwnd();
 
         Self {
             initial_congestion_window,
@@ -50,13 +47,17 @@ impl Default for DummyConfig {
     }
 }
 
-/// Dummy is a simple congestion controller with a static congestion window.
+/// Dummy is a simple congestion controller with a static congestion window,
+/// which is only changed by Careful Resume.
 /// It is intended to be used for testing and experiments.
 #[derive(Debug)]
 pub struct Dummy {
     /// Congestion window in bytes.
     cwnd: u64,
 
+    /// Initial congestion window in bytes.
+    initial_cwnd: u64,
+
     /// Congestion statistics.
     stats: CongestionStats,
 }
@@ -65,6 +66,7 @@ impl Dummy {
     pub fn new(conf: DummyConfig) -> Self {
         Self {
             cwnd: conf.initial_congestion_window,
+            initial_cwnd: conf.initial_congestion_window,
             stats: Default::default(),
         }
     }
@@ -115,6 +117,10 @@ impl CongestionController for Dummy {
         self.stats.bytes_in_flight = bytes_in_flight;
     }
 
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        self.cwnd = cwnd.max(self.initial_cwnd);
+    }
+
     fn in_slow_start(&self) -> bool {
         false
     }
@@ -128,11 +134,11 @@ impl CongestionController for Dummy {
     }
 
     fn initial_window(&self) -> u64 {
-        self.cwnd
+        self.initial_cwnd
     }
 
     fn minimal_window(&self) -> u64 {
-        self.cwnd
+        self.initial_cwnd
     }
 
     fn stats(&self) -> &CongestionStats {
@@ -217,4 +223,23 @@ mod tests {
         assert_eq!(d.stats().bytes_in_flight, 0);
         assert_eq!(d.stats().bytes_lost_in_total, 1400);
     }
+
+    #[test]
+    fn dummy_careful_resume() {
+        let conf = RecoveryConfig {
+            initial_congestion_window: 10,
+            max_datagram_size: 1200,
+            ..RecoveryConfig::default()
+        };
+        let mut d = Dummy::new(DummyConfig::from(&conf));
+        let now = Instant::now();
+
+        d.on_careful_resume(now, 1200 * 100);
+        assert_eq!(d.congestion_window(), 1200 * 100);
+        assert_eq!(d.initial_window(), 1200 * 10);
+
+        // The window is not reduced below the initial window
+        d.on_careful_resume(now, 1200);
+        assert_eq!(d.congestion_window(), 1200 * 10);
+    }
 }
@@ -0,0 +1,463 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(unused_variables)]
+
+use std::time::Duration;
+use std::time::Instant;
+
+use log::*;
+
+use super::CongestionController;
+use super::CongestionStats;
+use super::Copa;
+use super::CopaConfig;
+use super::Cubic;
+use super::CubicConfig;
+use crate::connection::rtt::RttEstimator;
+use crate::connection::space::SentPacket;
+use crate::RecoveryConfig;
+
+/// The queueing delay, as a fraction of the minimum RTT, above which the
+/// bottleneck queue is considered not drained in a round.
+const QUEUEING_DELAY_THRESHOLD: f64 = 0.25;
+
+/// The number of consecutive rounds without draining the queue in the
+/// delay-based mode, after which buffer-filling competing flows are assumed.
+/// A delay-based controller alone drains the queue within a few rounds.
+const COMPETITION_ROUNDS: u64 = 8;
+
+/// The number of consecutive rounds with a drained queue in the loss-based
+/// mode, after which the competing flows are assumed to be gone.
+const NO_COMPETITION_ROUNDS: u64 = 2;
+
+/// Hybrid Configuration.
+#[derive(Debug, Default)]
+pub struct HybridConfig {
+    /// Configuration of the delay-based controller.
+    copa: CopaConfig,
+
+    /// Configuration of the loss-based controller.
+    cubic: CubicConfig,
+}
+
+impl HybridConfig {
+    pub fn from(conf: &RecoveryConfig) -> Self {
+        Self {
+            copa: CopaConfig::from(conf),
+            cubic: CubicConfig::from(conf),
+        }
+    }
+}
+
+/// The operating mode of the hybrid controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HybridMode {
+    /// Use the delay-based controller, which keeps the queueing delay low.
+    Delay,
+
+    /// Use the loss-based controller to compete with buffer-filling flows.
+    Loss,
+}
+
+/// Hybrid is a congestion controller which normally runs the delay-based COPA
+/// and falls back to the loss-based CUBIC once it detects competition with
+/// buffer-filling flows, which is seen as a bottleneck queue that is not
+/// drained for several rounds. It switches back to COPA once the queue is
+/// drained again, and the congestion window is handed over on each switch.
+#[derive(Debug)]
+pub struct Hybrid {
+    /// The delay-based controller.
+    delay: Copa,
+
+    /// The loss-based controller.
+    loss: Cubic,
+
+    /// The active controller.
+    mode: HybridMode,
+
+    /// The minimum RTT observed on the path.
+    min_rtt: Duration,
+
+    /// The minimum RTT sample in the current round.
+    round_min_rtt: Duration,
+
+    /// The round ends when a packet sent after the one with the number is
+    /// acked.
+    round_end_pkt_num: Option<u64>,
+
+    /// The largest packet number sent.
+    last_sent_pkt_num: u64,
+
+    /// The largest packet number acked in the current ACK frame.
+    largest_acked_pkt_num: u64,
+
+    /// The time the current ACK frame is processed.
+    ack_time: Instant,
+
+    /// The number of consecutive rounds with the queue drained, or not
+    /// drained in the delay-based mode.
+    rounds_in_state: u64,
+
+    /// Congestion statistics.
+    stats: CongestionStats,
+}
+
+impl Hybrid {
+    pub fn new(config: HybridConfig) -> Self {
+        Self {
+            delay: Copa::new(config.copa),
+            loss: Cubic::new(config.cubic),
+            mode: HybridMode::Delay,
+            min_rtt: Duration::ZERO,
+            round_min_rtt: Duration::ZERO,
+            round_end_pkt_num: None,
+            last_sent_pkt_num: 0,
+            largest_acked_pkt_num: 0,
+            ack_time: Instant::now(),
+            rounds_in_state: 0,
+            stats: Default::default(),
+        }
+    }
+
+    /// Return the active controller.
+    fn active(&self) -> &dyn CongestionController {
+        match self.mode {
+            HybridMode::Delay => &self.delay,
+            HybridMode::Loss => &self.loss,
+        }
+    }
+
+    /// Return the active controller.
+    fn active_mut(&mut self) -> &mut dyn CongestionController {
+        match self.mode {
+            HybridMode::Delay => &mut self.delay,
+            HybridMode::Loss => &mut self.loss,
+        }
+    }
+
+    /// Switch to the given mode and hand over the congestion window.
+    fn switch_mode(&mut self, now: Instant, mode: HybridMode) {
+        let cwnd = self.active().congestion_window();
+        trace!(
+            "{}. switch from {:?} to {:?} mode, cwnd={}",
+            self.name(),
+            self.mode,
+            mode,
+            cwnd
+        );
+
+        self.mode = mode;
+        self.rounds_in_state = 0;
+        self.active_mut().set_congestion_window(now, cwnd);
+    }
+
+    /// Check the bottleneck queue at the end of each round, and switch the
+    /// mode if competing flows are detected or gone.
+    fn update_mode(&mut self, now: Instant) {
+        if self
+            .round_end_pkt_num
+            .is_some_and(|n| self.largest_acked_pkt_num <= n)
+        {
+            return;
+        }
+        self.round_end_pkt_num = Some(self.last_sent_pkt_num);
+        if self.round_min_rtt.is_zero() {
+            return;
+        }
+
+        let threshold = self.min_rtt.mul_f64(1.0 + QUEUEING_DELAY_THRESHOLD);
+        let drained = self.round_min_rtt <= threshold;
+        self.round_min_rtt = Duration::ZERO;
+
+        match self.mode {
+            HybridMode::Delay => {
+                // The queue is not drained in slow start yet.
+                if drained || self.delay.in_slow_start() {
+                    self.rounds_in_state = 0;
+                    return;
+                }
+                self.rounds_in_state += 1;
+                if self.rounds_in_state >= COMPETITION_ROUNDS {
+                    self.switch_mode(now, HybridMode::Loss);
+                }
+            }
+            HybridMode::Loss => {
+                if !drained {
+                    self.rounds_in_state = 0;
+                    return;
+                }
+                self.rounds_in_state += 1;
+                if self.rounds_in_state >= NO_COMPETITION_ROUNDS {
+                    self.switch_mode(now, HybridMode::Delay);
+                }
+            }
+        }
+    }
+}
+
+impl CongestionController for Hybrid {
+    fn name(&self) -> &str {
+        "HYBRID"
+    }
+
+    fn on_sent(&mut self, now: Instant, packet: &mut SentPacket, bytes_in_flight: u64) {
+        let sent_bytes = packet.sent_size as u64;
+        self.last_sent_pkt_num = self.last_sent_pkt_num.max(packet.pkt_num);
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_sent_in_total = self.stats.bytes_sent_in_total.saturating_add(sent_bytes);
+        if self.in_slow_start() {
+            self.stats.bytes_sent_in_slow_start = self
+                .stats
+                .bytes_sent_in_slow_start
+                .saturating_add(sent_bytes);
+        }
+
+        self.active_mut().on_sent(now, packet, bytes_in_flight);
+    }
+
+    fn begin_ack(&mut self, now: Instant, bytes_in_flight: u64) {
+        self.largest_acked_pkt_num = 0;
+        self.ack_time = now;
+        self.active_mut().begin_ack(now, bytes_in_flight);
+    }
+
+    fn on_ack(
+        &mut self,
+        packet: &mut SentPacket,
+        now: Instant,
+        app_limited: bool,
+        rtt: &RttEstimator,
+        bytes_in_flight: u64,
+    ) {
+        let acked_bytes = packet.sent_size as u64;
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_acked_in_total =
+            self.stats.bytes_acked_in_total.saturating_add(acked_bytes);
+        if self.in_slow_start() {
+            self.stats.bytes_acked_in_slow_start = self
+                .stats
+                .bytes_acked_in_slow_start
+                .saturating_add(acked_bytes);
+        }
+
+        self.largest_acked_pkt_num = self.largest_acked_pkt_num.max(packet.pkt_num);
+        let latest_rtt = rtt.latest_rtt();
+        if !latest_rtt.is_zero() {
+            if self.min_rtt.is_zero() || latest_rtt < self.min_rtt {
+                self.min_rtt = latest_rtt;
+            }
+            if self.round_min_rtt.is_zero() || latest_rtt < self.round_min_rtt {
+                self.round_min_rtt = latest_rtt;
+            }
+        }
+
+        self.active_mut()
+            .on_ack(packet, now, app_limited, rtt, bytes_in_flight);
+    }
+
+    fn end_ack(&mut self) {
+        self.active_mut().end_ack();
+        self.update_mode(self.ack_time);
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        packet: &SentPacket,
+        is_persistent_congestion: bool,
+        lost_bytes: u64,
+        bytes_in_flight: u64,
+    ) {
+        self.stats.bytes_lost_in_total = self.stats.bytes_lost_in_total.saturating_add(lost_bytes);
+        self.stats.bytes_in_flight = bytes_in_flight;
+        if self.in_slow_start() {
+            self.stats.bytes_lost_in_slow_start = self
+                .stats
+                .bytes_lost_in_slow_start
+                .saturating_add(lost_bytes);
+        }
+
+        self.active_mut().on_congestion_event(
+            now,
+            packet,
+            is_persistent_congestion,
+            lost_bytes,
+            bytes_in_flight,
+        );
+    }
+
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        self.active_mut().on_careful_resume(now, cwnd);
+    }
+
+    fn set_congestion_window(&mut self, now: Instant, cwnd: u64) {
+        self.active_mut().set_congestion_window(now, cwnd);
+    }
+
+    fn in_slow_start(&self) -> bool {
+        self.active().in_slow_start()
+    }
+
+    fn in_recovery(&self, sent_time: Instant) -> bool {
+        self.active().in_recovery(sent_time)
+    }
+
+    fn congestion_window(&self) -> u64 {
+        self.active().congestion_window()
+    }
+
+    fn pacing_rate(&self) -> Option<u64> {
+        self.active().pacing_rate()
+    }
+
+    fn min_rtt(&self) -> Option<Duration> {
+        if self.min_rtt.is_zero() {
+            return None;
+        }
+        Some(self.min_rtt)
+    }
+
+    fn state(&self) -> &str {
+        match self.mode {
+            HybridMode::Delay => "DelayBased",
+            HybridMode::Loss => "LossBased",
+        }
+    }
+
+    fn initial_window(&self) -> u64 {
+        self.active().initial_window()
+    }
+
+    fn minimal_window(&self) -> u64 {
+        self.active().minimal_window()
+    }
+
+    fn stats(&self) -> &CongestionStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_packet(pkt_num: u64, time_sent: Instant) -> SentPacket {
+        SentPacket {
+            pkt_num,
+            ack_eliciting: true,
+            in_flight: true,
+            sent_size: 1200,
+            time_sent,
+            ..SentPacket::default()
+        }
+    }
+
+    fn new_hybrid() -> Hybrid {
+        let conf = RecoveryConfig {
+            initial_congestion_window: 10,
+            min_congestion_window: 2,
+            max_datagram_size: 1200,
+            ..RecoveryConfig::default()
+        };
+        Hybrid::new(HybridConfig::from(&conf))
+    }
+
+    /// Run a round with one packet, which is acked with the given RTT.
+    fn run_round(h: &mut Hybrid, pkt_num: u64, now: Instant, rtt: Duration) -> Instant {
+        let mut pkt = new_packet(pkt_num, now);
+        h.on_sent(now, &mut pkt, 1200);
+
+        let now = now + rtt;
+        let mut rtt_estimator = RttEstimator::new(rtt);
+        rtt_estimator.update(Duration::ZERO, rtt);
+        h.begin_ack(now, 1200);
+        h.on_ack(&mut pkt, now, false, &rtt_estimator, 0);
+        h.end_ack();
+        now
+    }
+
+    #[test]
+    fn hybrid_init() {
+        let h = new_hybrid();
+        assert_eq!(h.name(), "HYBRID");
+        assert_eq!(h.mode, HybridMode::Delay);
+        assert_eq!(h.state(), "DelayBased");
+        assert_eq!(h.congestion_window(), 1200 * 10);
+        assert_eq!(h.initial_window(), 1200 * 10);
+        assert_eq!(h.minimal_window(), 1200 * 2);
+        assert_eq!(h.in_slow_start(), true);
+        assert_eq!(h.min_rtt(), None);
+    }
+
+    #[test]
+    fn hybrid_switch_mode() {
+        let mut h = new_hybrid();
+        let base_rtt = Duration::from_millis(50);
+        let queued_rtt = Duration::from_millis(100);
+        let mut now = Instant::now();
+        let mut pkt_num = 0;
+
+        now = run_round(&mut h, pkt_num, now, base_rtt);
+        pkt_num += 1;
+        assert_eq!(h.min_rtt(), Some(base_rtt));
+
+        // Leave slow start with the given window.
+        h.delay.on_careful_resume(now, 1200 * 100);
+        now = run_round(&mut h, pkt_num, now, base_rtt);
+        pkt_num += 1;
+        assert_eq!(h.in_slow_start(), false);
+
+        // The queue is not drained for several rounds.
+        for _ in 0..COMPETITION_ROUNDS - 1 {
+            now = run_round(&mut h, pkt_num, now, queued_rtt);
+            pkt_num += 1;
+            assert_eq!(h.mode, HybridMode::Delay);
+        }
+        now = run_round(&mut h, pkt_num, now, queued_rtt);
+        pkt_num += 1;
+        assert_eq!(h.mode, HybridMode::Loss);
+        assert_eq!(h.state(), "LossBased");
+        assert_eq!(h.congestion_window(), h.delay.congestion_window());
+        assert_eq!(h.in_slow_start(), false);
+
+        // Loss is handled by the loss-based controller.
+        let cwnd = h.congestion_window();
+        let pkt = new_packet(pkt_num, now);
+        h.on_congestion_event(now + queued_rtt, &pkt, false, 1200, 0);
+        assert!(h.congestion_window() < cwnd);
+        assert_eq!(h.stats().bytes_lost_in_total, 1200);
+
+        // The queue is drained after the competing flows are gone.
+        now = run_round(&mut h, pkt_num, now, queued_rtt);
+        pkt_num += 1;
+        for _ in 0..NO_COMPETITION_ROUNDS - 1 {
+            now = run_round(&mut h, pkt_num, now, base_rtt);
+            pkt_num += 1;
+            assert_eq!(h.mode, HybridMode::Loss);
+        }
+        run_round(&mut h, pkt_num, now, base_rtt);
+        assert_eq!(h.mode, HybridMode::Delay);
+        assert_eq!(h.congestion_window(), h.loss.congestion_window());
+        assert_eq!(h.in_slow_start(), false);
+        assert_eq!(h.stats().bytes_acked_in_total, 1200 * (pkt_num + 1));
+
+        // The window is set on the active controller.
+        h.set_congestion_window(now, 1200 * 50);
+        assert_eq!(h.congestion_window(), 1200 * 50);
+        assert_eq!(h.delay.congestion_window(), 1200 * 50);
+    }
+}
@@ -0,0 +1,575 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(unused_variables)]
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use log::*;
+
+use super::CongestionController;
+use super::CongestionStats;
+use crate::connection::rtt::RttEstimator;
+use crate::connection::space::SentPacket;
+use crate::RecoveryConfig;
+
+/// The default target queueing delay.
+/// See draft-irtf-iccrg-ledbat-plus-plus Section 4.1
+pub const LEDBAT_TARGET_DELAY: Duration = Duration::from_millis(60);
+
+/// The upper bound of the reciprocal of the window increase gain.
+const MAX_GAIN_RECIPROCAL: f64 = 16.0;
+
+/// The multiplicative decrease constant for the delay above the target.
+const DECREASE_CONSTANT: f64 = 1.0;
+
+/// The number of recent RTT samples for filtering the current delay.
+const CURRENT_DELAY_SAMPLES: usize = 4;
+
+/// Slow start is exited once the queueing delay exceeds the fraction of the
+/// target delay.
+const SLOW_START_EXIT_FRACTION: f64 = 0.75;
+
+/// The number of round trips the congestion window is frozen during a
+/// periodic slowdown.
+const SLOWDOWN_RTTS: u32 = 2;
+
+/// The interval between two slowdowns, in the multiple of the duration of
+/// the last slowdown.
+const SLOWDOWN_INTERVAL_FACTOR: u32 = 9;
+
+/// LEDBAT Configuration.
+#[derive(Debug)]
+pub struct LedbatConfig {
+    /// Minimal congestion window in bytes.
+    min_congestion_window: u64,
+
+    /// Initial congestion window in bytes.
+    initial_congestion_window: u64,
+
+    /// Max datagram size in bytes.
+    max_datagram_size: u64,
+
+    /// The target queueing delay.
+    target_delay: Duration,
+}
+
+impl LedbatConfig {
+    pub fn from(conf: &RecoveryConfig) -> Self {
+        let max_datagram_size = conf.max_datagram_size as u64;
+        let min_congestion_window = conf.min_cwnd();
+        let initial_congestion_window = conf.initial_cwnd();
+
+        Self {
+            min_congestion_window,
+            initial_congestion_window,
+            max_datagram_size,
+            target_delay: conf.ledbat_target_delay,
+        }
+    }
+}
+
+impl Default for LedbatConfig {
+    fn default() -> Self {
+        Self {
+            min_congestion_window: 2 * crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            initial_congestion_window: 10 * crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            max_datagram_size: crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            target_delay: LEDBAT_TARGET_DELAY,
+        }
+    }
+}
+
+/// LEDBAT state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LedbatState {
+    /// Slow start with the reduced gain.
+    SlowStart,
+
+    /// Congestion avoidance driven by the queueing delay.
+    CongestionAvoidance,
+
+    /// The congestion window is frozen at the minimum, so that the queue of
+    /// the bottleneck is drained and the base delay can be measured.
+    Slowdown,
+}
+
+/// LEDBAT++ is a scavenger congestion controller for background transfers.
+/// It keeps the queueing delay below a target, and yields to other flows
+/// sharing the bottleneck once the delay builds up. Compared to LEDBAT, it
+/// uses a reduced window increase gain, a multiplicative decrease and
+/// periodic slowdowns, which solves the latecomer advantage and the base
+/// delay drift.
+///
+/// See RFC 6817 and draft-irtf-iccrg-ledbat-plus-plus.
+#[derive(Debug)]
+pub struct Ledbat {
+    /// Configuration.
+    config: LedbatConfig,
+
+    /// Congestion window in bytes.
+    cwnd: u64,
+
+    /// Slow start threshold in bytes.
+    ssthresh: u64,
+
+    /// Current state.
+    state: LedbatState,
+
+    /// The recent RTT samples, and the minimum is the current delay.
+    current_delays: VecDeque<Duration>,
+
+    /// The minimum RTT observed on the path.
+    base_delay: Duration,
+
+    /// The latest smoothed RTT.
+    srtt: Duration,
+
+    /// The start time of the current slowdown.
+    slowdown_start_time: Option<Instant>,
+
+    /// The time of the next slowdown.
+    next_slowdown_time: Option<Instant>,
+
+    /// The time of the last congestion window reduction on loss.
+    recovery_start_time: Option<Instant>,
+
+    /// Congestion statistics.
+    stats: CongestionStats,
+}
+
+impl Ledbat {
+    pub fn new(config: LedbatConfig) -> Self {
+        let cwnd = config.initial_congestion_window;
+
+        Self {
+            config,
+            cwnd,
+            ssthresh: u64::MAX,
+            state: LedbatState::SlowStart,
+            current_delays: VecDeque::with_capacity(CURRENT_DELAY_SAMPLES),
+            base_delay: Duration::ZERO,
+            srtt: Duration::ZERO,
+            slowdown_start_time: None,
+            next_slowdown_time: None,
+            recovery_start_time: None,
+            stats: Default::default(),
+        }
+    }
+
+    /// Update the current delay and the base delay with the RTT sample.
+    fn update_delay(&mut self, rtt: &RttEstimator) {
+        let latest_rtt = rtt.latest_rtt();
+        if self.current_delays.len() >= CURRENT_DELAY_SAMPLES {
+            self.current_delays.pop_front();
+        }
+        self.current_delays.push_back(latest_rtt);
+        if self.base_delay.is_zero() || latest_rtt < self.base_delay {
+            self.base_delay = latest_rtt;
+        }
+        self.srtt = rtt.smoothed_rtt();
+    }
+
+    /// Return the queueing delay estimated from the current delay and the
+    /// base delay.
+    fn queueing_delay(&self) -> Duration {
+        let current_delay = self
+            .current_delays
+            .iter()
+            .min()
+            .copied()
+            .unwrap_or(self.base_delay);
+        current_delay.saturating_sub(self.base_delay)
+    }
+
+    /// Return the window increase gain, which is smaller on paths with a
+    /// small base delay relative to the target delay.
+    /// See draft-irtf-iccrg-ledbat-plus-plus Section 4.2
+    fn gain(&self) -> f64 {
+        let base_delay = self.base_delay.max(Duration::from_micros(1));
+        let reciprocal = (2.0 * self.config.target_delay.as_secs_f64() / base_delay.as_secs_f64())
+            .ceil()
+            .clamp(1.0, MAX_GAIN_RECIPROCAL);
+        1.0 / reciprocal
+    }
+
+    /// Exit slow start and schedule the next slowdown.
+    fn exit_slow_start(&mut self, now: Instant) {
+        self.state = LedbatState::CongestionAvoidance;
+        self.ssthresh = self.cwnd;
+
+        // The initial slowdown starts two round trips after the initial slow
+        // start, and the next one starts after nine times the duration of the
+        // last slowdown.
+        let interval = match self.slowdown_start_time.take() {
+            Some(t) => now.saturating_duration_since(t) * SLOWDOWN_INTERVAL_FACTOR,
+            None => self.srtt * SLOWDOWN_RTTS,
+        };
+        self.next_slowdown_time = Some(now + interval);
+    }
+
+    /// Start or end the periodic slowdown.
+    /// See draft-irtf-iccrg-ledbat-plus-plus Section 4.4
+    fn update_slowdown(&mut self, now: Instant) {
+        match self.state {
+            LedbatState::Slowdown => {
+                let end_time = self
+                    .slowdown_start_time
+                    .map(|t| t + self.srtt * SLOWDOWN_RTTS);
+                if end_time.is_some_and(|t| now >= t) {
+                    // Ramp up to the window before the slowdown.
+                    self.state = LedbatState::SlowStart;
+                }
+            }
+            LedbatState::CongestionAvoidance => {
+                if self.next_slowdown_time.is_some_and(|t| now >= t) {
+                    trace!("{}. start slowdown, cwnd={}", self.name(), self.cwnd);
+                    self.state = LedbatState::Slowdown;
+                    self.ssthresh = self.cwnd;
+                    self.cwnd = self.config.min_congestion_window;
+                    self.slowdown_start_time = Some(now);
+                    self.next_slowdown_time = None;
+                }
+            }
+            LedbatState::SlowStart => (),
+        }
+    }
+}
+
+impl CongestionController for Ledbat {
+    fn name(&self) -> &str {
+        "LEDBAT"
+    }
+
+    fn on_sent(&mut self, now: Instant, packet: &mut SentPacket, bytes_in_flight: u64) {
+        let sent_bytes = packet.sent_size as u64;
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_sent_in_total = self.stats.bytes_sent_in_total.saturating_add(sent_bytes);
+        if self.in_slow_start() {
+            self.stats.bytes_sent_in_slow_start = self
+                .stats
+                .bytes_sent_in_slow_start
+                .saturating_add(sent_bytes);
+        }
+    }
+
+    fn begin_ack(&mut self, now: Instant, bytes_in_flight: u64) {
+        // Do nothing.
+    }
+
+    fn on_ack(
+        &mut self,
+        packet: &mut SentPacket,
+        now: Instant,
+        app_limited: bool,
+        rtt: &RttEstimator,
+        bytes_in_flight: u64,
+    ) {
+        let acked_bytes = packet.sent_size as u64;
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_acked_in_total =
+            self.stats.bytes_acked_in_total.saturating_add(acked_bytes);
+        if self.in_slow_start() {
+            self.stats.bytes_acked_in_slow_start = self
+                .stats
+                .bytes_acked_in_slow_start
+                .saturating_add(acked_bytes);
+        }
+
+        self.update_delay(rtt);
+        self.update_slowdown(now);
+        if app_limited || self.in_recovery(packet.time_sent) {
+            return;
+        }
+
+        let gain = self.gain();
+        let queueing_delay = self.queueing_delay();
+        let target_delay = self.config.target_delay;
+        match self.state {
+            LedbatState::SlowStart => {
+                let exit_delay = target_delay.mul_f64(SLOW_START_EXIT_FRACTION);
+                if queueing_delay > exit_delay || self.cwnd >= self.ssthresh {
+                    self.exit_slow_start(now);
+                    return;
+                }
+                let inc = (gain * acked_bytes as f64) as u64;
+                self.cwnd = self.cwnd.saturating_add(inc.max(1)).min(self.ssthresh);
+            }
+            LedbatState::CongestionAvoidance => {
+                // W += GAIN - CONSTANT * W * (delay / target - 1) per round trip,
+                // and the decrease is at most a half of the window.
+                let off_target = queueing_delay.as_secs_f64()
+                    / target_delay.max(Duration::from_micros(1)).as_secs_f64()
+                    - 1.0;
+                let mss = self.config.max_datagram_size as f64;
+                let cwnd = self.cwnd as f64;
+                let delta = (gain * mss / cwnd - DECREASE_CONSTANT * off_target.max(0.0)).max(-0.5)
+                    * acked_bytes as f64;
+                self.cwnd = ((cwnd + delta) as u64).max(self.config.min_congestion_window);
+            }
+            LedbatState::Slowdown => (),
+        }
+    }
+
+    fn end_ack(&mut self) {
+        // Do nothing.
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        packet: &SentPacket,
+        is_persistent_congestion: bool,
+        lost_bytes: u64,
+        bytes_in_flight: u64,
+    ) {
+        self.stats.bytes_lost_in_total = self.stats.bytes_lost_in_total.saturating_add(lost_bytes);
+        self.stats.bytes_in_flight = bytes_in_flight;
+        if self.in_slow_start() {
+            self.stats.bytes_lost_in_slow_start = self
+                .stats
+                .bytes_lost_in_slow_start
+                .saturating_add(lost_bytes);
+        }
+
+        if self.in_recovery(packet.time_sent) {
+            return;
+        }
+        self.recovery_start_time = Some(now);
+
+        if is_persistent_congestion {
+            self.cwnd = self.config.min_congestion_window;
+        } else if self.state != LedbatState::Slowdown {
+            self.cwnd = (self.cwnd / 2).max(self.config.min_congestion_window);
+        }
+        if self.state == LedbatState::SlowStart {
+            self.exit_slow_start(now);
+        }
+        self.ssthresh = self.cwnd;
+    }
+
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        self.cwnd = cwnd.max(self.config.min_congestion_window);
+        if self.state == LedbatState::SlowStart {
+            self.exit_slow_start(now);
+        }
+        self.ssthresh = self.cwnd;
+    }
+
+    fn in_slow_start(&self) -> bool {
+        self.state == LedbatState::SlowStart
+    }
+
+    fn in_recovery(&self, sent_time: Instant) -> bool {
+        self.recovery_start_time.is_some_and(|t| sent_time <= t)
+    }
+
+    fn congestion_window(&self) -> u64 {
+        self.cwnd.max(self.config.min_congestion_window)
+    }
+
+    fn min_rtt(&self) -> Option<Duration> {
+        if self.base_delay.is_zero() {
+            return None;
+        }
+        Some(self.base_delay)
+    }
+
+    fn state(&self) -> &str {
+        match self.state {
+            LedbatState::SlowStart => "SlowStart",
+            LedbatState::CongestionAvoidance => "CongestionAvoidance",
+            LedbatState::Slowdown => "Slowdown",
+        }
+    }
+
+    fn initial_window(&self) -> u64 {
+        self.config.initial_congestion_window
+    }
+
+    fn minimal_window(&self) -> u64 {
+        self.config.min_congestion_window
+    }
+
+    fn stats(&self) -> &CongestionStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_packet(pkt_num: u64, time_sent: Instant) -> SentPacket {
+        SentPacket {
+            pkt_num,
+            ack_eliciting: true,
+            in_flight: true,
+            sent_size: 1200,
+            time_sent,
+            ..SentPacket::default()
+        }
+    }
+
+    fn new_ledbat() -> Ledbat {
+        let conf = RecoveryConfig {
+            initial_congestion_window: 10,
+            min_congestion_window: 2,
+            max_datagram_size: 1200,
+            ledbat_target_delay: Duration::from_millis(60),
+            ..RecoveryConfig::default()
+        };
+        Ledbat::new(LedbatConfig::from(&conf))
+    }
+
+    /// Send a packet which is acked with the given RTT.
+    fn send_and_ack(
+        l: &mut Ledbat,
+        pkt_num: u64,
+        now: Instant,
+        rtt: &mut RttEstimator,
+        latest_rtt: Duration,
+    ) -> Instant {
+        let mut pkt = new_packet(pkt_num, now);
+        l.on_sent(now, &mut pkt, 1200);
+
+        let now = now + latest_rtt;
+        rtt.update(Duration::ZERO, latest_rtt);
+        l.begin_ack(now, 1200);
+        l.on_ack(&mut pkt, now, false, rtt, 0);
+        l.end_ack();
+        now
+    }
+
+    #[test]
+    fn ledbat_init() {
+        let l = new_ledbat();
+        assert_eq!(l.name(), "LEDBAT");
+        assert_eq!(l.congestion_window(), 1200 * 10);
+        assert_eq!(l.initial_window(), 1200 * 10);
+        assert_eq!(l.minimal_window(), 1200 * 2);
+        assert_eq!(l.in_slow_start(), true);
+        assert_eq!(l.state(), "SlowStart");
+        assert_eq!(l.min_rtt(), None);
+        assert_eq!(l.pacing_rate(), None);
+    }
+
+    #[test]
+    fn ledbat_gain() {
+        let mut l = new_ledbat();
+        l.base_delay = Duration::from_millis(10);
+        assert_eq!(l.gain(), 1.0 / 12.0);
+        l.base_delay = Duration::from_millis(1);
+        assert_eq!(l.gain(), 1.0 / 16.0);
+        l.base_delay = Duration::from_millis(200);
+        assert_eq!(l.gain(), 1.0);
+    }
+
+    #[test]
+    fn ledbat_slow_start_and_yield() {
+        let mut l = new_ledbat();
+        let mut rtt = RttEstimator::new(Duration::from_millis(200));
+        let base_rtt = Duration::from_millis(200);
+        let mut now = Instant::now();
+
+        // Slow start with the gain of 1 on the path with a long base delay.
+        now = send_and_ack(&mut l, 0, now, &mut rtt, base_rtt);
+        assert_eq!(l.congestion_window(), 1200 * 11);
+        assert_eq!(l.min_rtt(), Some(base_rtt));
+
+        // Exit slow start once the queueing delay builds up, which is the
+        // minimum of the recent samples.
+        now = send_and_ack(
+            &mut l,
+            1,
+            now,
+            &mut rtt,
+            base_rtt + Duration::from_millis(50),
+        );
+        assert_eq!(l.congestion_window(), 1200 * 12);
+        assert_eq!(l.in_slow_start(), true);
+        for i in 2..2 + CURRENT_DELAY_SAMPLES as u64 {
+            now = send_and_ack(
+                &mut l,
+                i,
+                now,
+                &mut rtt,
+                base_rtt + Duration::from_millis(50),
+            );
+        }
+        assert_eq!(l.in_slow_start(), false);
+        assert_eq!(l.state(), "CongestionAvoidance");
+        assert!(l.next_slowdown_time.is_some());
+
+        // Skip the periodic slowdown.
+        l.next_slowdown_time = None;
+
+        // The window is reduced if the queueing delay is above the target.
+        let cwnd = l.congestion_window();
+        for i in 10..10 + CURRENT_DELAY_SAMPLES as u64 {
+            now = send_and_ack(
+                &mut l,
+                i,
+                now,
+                &mut rtt,
+                base_rtt + Duration::from_millis(120),
+            );
+        }
+        assert!(l.congestion_window() < cwnd);
+
+        // The window is halved on loss.
+        let cwnd = l.congestion_window();
+        let pkt = new_packet(20, now);
+        l.on_congestion_event(now, &pkt, false, 1200, 0);
+        assert_eq!(l.congestion_window(), (cwnd / 2).max(l.minimal_window()));
+        assert_eq!(l.stats().bytes_lost_in_total, 1200);
+    }
+
+    #[test]
+    fn ledbat_slowdown() {
+        let mut l = new_ledbat();
+        let mut rtt = RttEstimator::new(Duration::from_millis(200));
+        let base_rtt = Duration::from_millis(200);
+        let mut now = send_and_ack(&mut l, 0, Instant::now(), &mut rtt, base_rtt);
+
+        // The initial slowdown starts two round trips after slow start.
+        l.on_careful_resume(now, 1200 * 20);
+        assert_eq!(l.in_slow_start(), false);
+        now = send_and_ack(&mut l, 1, now, &mut rtt, base_rtt);
+        assert_eq!(l.state(), "CongestionAvoidance");
+        now = send_and_ack(&mut l, 2, now, &mut rtt, base_rtt);
+        assert_eq!(l.state(), "Slowdown");
+        assert_eq!(l.congestion_window(), l.minimal_window());
+
+        // The window is frozen for two round trips.
+        now = send_and_ack(&mut l, 3, now, &mut rtt, base_rtt);
+        assert_eq!(l.congestion_window(), l.minimal_window());
+        now = send_and_ack(&mut l, 4, now, &mut rtt, base_rtt);
+        assert_eq!(l.state(), "SlowStart");
+
+        // Ramp up to the window before the slowdown.
+        let mut pkt_num = 5;
+        while l.in_slow_start() {
+            now = send_and_ack(&mut l, pkt_num, now, &mut rtt, base_rtt);
+            pkt_num += 1;
+        }
+        assert_eq!(l.congestion_window(), l.ssthresh);
+        assert!(l.congestion_window() >= 1200 * 20);
+        assert!(l.next_slowdown_time.is_some());
+    }
+}
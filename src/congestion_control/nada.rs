@@ -0,0 +1,555 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(unused_variables)]
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use log::*;
+
+use super::CongestionController;
+use super::CongestionStats;
+use super::DeliveryRateEstimator;
+use crate::connection::rtt::RttEstimator;
+use crate::connection::space::SentPacket;
+use crate::RecoveryConfig;
+
+/// The default minimum target bitrate in bits per second.
+/// See RFC 8698 Section 6.3
+pub const NADA_MIN_BITRATE: u64 = 150_000;
+
+/// The default maximum target bitrate in bits per second.
+/// See RFC 8698 Section 6.3
+pub const NADA_MAX_BITRATE: u64 = 1_500_000;
+
+/// The target feedback interval, at which the target bitrate is updated.
+const FEEDBACK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The window for measuring the loss ratio.
+const LOSS_WINDOW: Duration = Duration::from_millis(500);
+
+/// The threshold of the queueing delay for the accelerated ramp-up.
+const QUEUEING_DELAY_EPSILON: Duration = Duration::from_millis(10);
+
+/// The delay of the minimum filter of the queueing delay.
+const FILTER_DELAY: Duration = Duration::from_millis(120);
+
+/// The upper bound of the rate increase ratio in the accelerated ramp-up.
+const MAX_RAMP_UP_RATIO: f64 = 0.5;
+
+/// The upper bound of the queueing delay caused by the accelerated ramp-up.
+const RAMP_UP_QUEUEING_DELAY_BOUND: Duration = Duration::from_millis(50);
+
+/// The reference congestion level, and the priority weight of the flow.
+const REFERENCE_CONGESTION: f64 = 0.010;
+const PRIORITY: f64 = 1.0;
+
+/// The scaling and the damping parameters of the gradual rate update.
+const KAPPA: f64 = 0.5;
+const ETA: f64 = 2.0;
+const TAU: f64 = 0.5;
+
+/// The delay penalty for the reference loss ratio.
+const LOSS_PENALTY: f64 = 0.010;
+const REFERENCE_LOSS_RATIO: f64 = 0.01;
+
+/// The number of recent RTT samples for filtering the queueing delay.
+const QUEUEING_DELAY_SAMPLES: usize = 15;
+
+/// The congestion window in the multiples of the BDP of the target bitrate,
+/// which leaves room for the bursts of video frames.
+const CWND_GAIN: f64 = 2.0;
+
+/// NADA Configuration.
+#[derive(Debug)]
+pub struct NadaConfig {
+    /// Minimal congestion window in bytes.
+    min_congestion_window: u64,
+
+    /// Initial congestion window in bytes.
+    initial_congestion_window: u64,
+
+    /// Minimum target bitrate in bits per second.
+    min_bitrate: u64,
+
+    /// Maximum target bitrate in bits per second.
+    max_bitrate: u64,
+}
+
+impl NadaConfig {
+    pub fn from(conf: &RecoveryConfig) -> Self {
+        let min_bitrate = conf.nada_min_bitrate.max(1);
+
+        Self {
+            min_congestion_window: conf.min_cwnd(),
+            initial_congestion_window: conf.initial_cwnd(),
+            min_bitrate,
+            max_bitrate: conf.nada_max_bitrate.max(min_bitrate),
+        }
+    }
+}
+
+impl Default for NadaConfig {
+    fn default() -> Self {
+        Self {
+            min_congestion_window: 2 * crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            initial_congestion_window: 10 * crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            min_bitrate: NADA_MIN_BITRATE,
+            max_bitrate: NADA_MAX_BITRATE,
+        }
+    }
+}
+
+/// NADA rate update mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NadaMode {
+    /// The rate is increased quickly while the path is not congested.
+    AcceleratedRampUp,
+
+    /// The rate is adjusted gradually according to the congestion level.
+    GradualUpdate,
+}
+
+/// NADA (Network-Assisted Dynamic Adaptation) is a rate-based congestion
+/// controller for real-time interactive media. It derives an aggregated
+/// congestion signal from the queueing delay and the loss ratio, and updates
+/// the target bitrate of the media encoder once per feedback interval. The
+/// packets are paced at the target bitrate, and the congestion window only
+/// bounds the bursts of media frames.
+///
+/// The queueing delay is estimated from the RTT samples instead of the
+/// one-way delay, and ECN marks are not used.
+///
+/// See RFC 8698.
+#[derive(Debug)]
+pub struct Nada {
+    /// Configuration.
+    config: NadaConfig,
+
+    /// Current mode.
+    mode: NadaMode,
+
+    /// The target bitrate in bits per second.
+    target_bitrate: f64,
+
+    /// The latest receiving rate in bits per second, which is the delivery
+    /// rate of the path.
+    receiving_rate: f64,
+
+    /// The aggregated congestion signal in seconds of the last update.
+    prev_congestion: f64,
+
+    /// The recent RTT samples, and the minimum is the current delay.
+    current_delays: VecDeque<Duration>,
+
+    /// The minimum RTT observed on the path.
+    base_delay: Duration,
+
+    /// The latest smoothed RTT.
+    srtt: Duration,
+
+    /// The smoothed loss ratio.
+    loss_ratio: f64,
+
+    /// The bytes acked since the last update.
+    acked_bytes: u64,
+
+    /// The bytes lost since the last update.
+    lost_bytes: u64,
+
+    /// The time of the last loss.
+    last_loss_time: Option<Instant>,
+
+    /// The time of the last congested sample, whose queueing delay is above
+    /// the threshold of the accelerated ramp-up.
+    last_congested_time: Option<Instant>,
+
+    /// The time of the last update of the target bitrate.
+    last_update_time: Option<Instant>,
+
+    /// The time of processing the current ACK frame.
+    ack_time: Instant,
+
+    /// Congestion statistics.
+    stats: CongestionStats,
+}
+
+impl Nada {
+    pub fn new(config: NadaConfig) -> Self {
+        let target_bitrate = config.min_bitrate as f64;
+
+        Self {
+            config,
+            mode: NadaMode::AcceleratedRampUp,
+            target_bitrate,
+            receiving_rate: 0.0,
+            prev_congestion: 0.0,
+            current_delays: VecDeque::with_capacity(QUEUEING_DELAY_SAMPLES),
+            base_delay: Duration::ZERO,
+            srtt: Duration::ZERO,
+            loss_ratio: 0.0,
+            acked_bytes: 0,
+            lost_bytes: 0,
+            last_loss_time: None,
+            last_congested_time: None,
+            last_update_time: None,
+            ack_time: Instant::now(),
+            stats: Default::default(),
+        }
+    }
+
+    /// Update the current delay and the base delay with the RTT sample.
+    fn update_delay(&mut self, rtt: &RttEstimator, now: Instant) {
+        let latest_rtt = rtt.latest_rtt();
+        if self.current_delays.len() >= QUEUEING_DELAY_SAMPLES {
+            self.current_delays.pop_front();
+        }
+        self.current_delays.push_back(latest_rtt);
+        if self.base_delay.is_zero() || latest_rtt < self.base_delay {
+            self.base_delay = latest_rtt;
+        }
+        self.srtt = rtt.smoothed_rtt();
+
+        if self.queueing_delay() >= QUEUEING_DELAY_EPSILON {
+            self.last_congested_time = Some(now);
+        }
+    }
+
+    /// Return the queueing delay estimated from the current delay and the
+    /// base delay.
+    fn queueing_delay(&self) -> Duration {
+        let current_delay = self
+            .current_delays
+            .iter()
+            .min()
+            .copied()
+            .unwrap_or(self.base_delay);
+        current_delay.saturating_sub(self.base_delay)
+    }
+
+    /// Return the aggregated congestion signal in seconds.
+    /// See RFC 8698 Section 4.2
+    fn congestion_signal(&self) -> f64 {
+        let loss = self.loss_ratio / REFERENCE_LOSS_RATIO;
+        self.queueing_delay().as_secs_f64() + LOSS_PENALTY * loss * loss
+    }
+
+    /// Update the rate update mode. The accelerated ramp-up is used if no
+    /// loss or congested sample is seen in the recent loss window.
+    fn update_mode(&mut self, now: Instant) {
+        let recent = |t: Option<Instant>| t.is_some_and(|t| now < t + LOSS_WINDOW);
+        self.mode = if recent(self.last_loss_time) || recent(self.last_congested_time) {
+            NadaMode::GradualUpdate
+        } else {
+            NadaMode::AcceleratedRampUp
+        };
+    }
+
+    /// Update the target bitrate once per feedback interval.
+    /// See RFC 8698 Section 4.3
+    fn update_target_bitrate(&mut self, now: Instant) {
+        let last_update_time = match self.last_update_time {
+            Some(t) => t,
+            None => {
+                self.last_update_time = Some(now);
+                return;
+            }
+        };
+        let delta = now.saturating_duration_since(last_update_time);
+        if delta < FEEDBACK_INTERVAL {
+            return;
+        }
+        self.last_update_time = Some(now);
+
+        // Smooth the loss ratio over the loss window.
+        let total = self.acked_bytes + self.lost_bytes;
+        if total > 0 {
+            let ratio = self.lost_bytes as f64 / total as f64;
+            let gain = (delta.as_secs_f64() / LOSS_WINDOW.as_secs_f64()).min(1.0);
+            self.loss_ratio += gain * (ratio - self.loss_ratio);
+        }
+        self.acked_bytes = 0;
+        self.lost_bytes = 0;
+
+        self.update_mode(now);
+        let congestion = self.congestion_signal();
+        let min_bitrate = self.config.min_bitrate as f64;
+        let max_bitrate = self.config.max_bitrate as f64;
+        let rate = self.target_bitrate;
+        let rate = match self.mode {
+            NadaMode::AcceleratedRampUp => {
+                let ratio = (RAMP_UP_QUEUEING_DELAY_BOUND.as_secs_f64()
+                    / (self.srtt + FEEDBACK_INTERVAL + FILTER_DELAY).as_secs_f64())
+                .min(MAX_RAMP_UP_RATIO);
+                rate.max((1.0 + ratio) * self.receiving_rate)
+            }
+            NadaMode::GradualUpdate => {
+                // The delta is capped, so that a long feedback gap does not
+                // cause an excessive change.
+                let delta = delta.min(2 * FEEDBACK_INTERVAL).as_secs_f64();
+                let offset = congestion - PRIORITY * REFERENCE_CONGESTION * max_bitrate / rate;
+                let diff = congestion - self.prev_congestion;
+                rate - KAPPA * (delta / TAU) * (offset / TAU) * rate
+                    - KAPPA * ETA * (diff / TAU) * rate
+            }
+        };
+        self.target_bitrate = rate.clamp(min_bitrate, max_bitrate);
+        self.prev_congestion = congestion;
+        trace!(
+            "{}. mode={:?} congestion={:.4} target_bitrate={}",
+            self.name(),
+            self.mode,
+            congestion,
+            self.target_bitrate as u64
+        );
+    }
+}
+
+impl CongestionController for Nada {
+    fn name(&self) -> &str {
+        "NADA"
+    }
+
+    fn on_sent(&mut self, now: Instant, packet: &mut SentPacket, bytes_in_flight: u64) {
+        let sent_bytes = packet.sent_size as u64;
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_sent_in_total = self.stats.bytes_sent_in_total.saturating_add(sent_bytes);
+    }
+
+    fn begin_ack(&mut self, now: Instant, bytes_in_flight: u64) {
+        self.ack_time = now;
+    }
+
+    fn on_ack(
+        &mut self,
+        packet: &mut SentPacket,
+        now: Instant,
+        app_limited: bool,
+        rtt: &RttEstimator,
+        bytes_in_flight: u64,
+    ) {
+        let acked_bytes = packet.sent_size as u64;
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_acked_in_total =
+            self.stats.bytes_acked_in_total.saturating_add(acked_bytes);
+        self.acked_bytes = self.acked_bytes.saturating_add(acked_bytes);
+        self.update_delay(rtt, now);
+    }
+
+    fn on_rate_sample(&mut self, estimator: &DeliveryRateEstimator) {
+        self.receiving_rate = estimator.delivery_rate() as f64 * 8.0;
+    }
+
+    fn end_ack(&mut self) {
+        self.update_target_bitrate(self.ack_time);
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        packet: &SentPacket,
+        is_persistent_congestion: bool,
+        lost_bytes: u64,
+        bytes_in_flight: u64,
+    ) {
+        self.stats.bytes_lost_in_total = self.stats.bytes_lost_in_total.saturating_add(lost_bytes);
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.lost_bytes = self.lost_bytes.saturating_add(lost_bytes);
+        self.last_loss_time = Some(now);
+        self.mode = NadaMode::GradualUpdate;
+
+        if is_persistent_congestion {
+            self.target_bitrate = self.config.min_bitrate as f64;
+        }
+    }
+
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        if self.srtt.is_zero() {
+            return;
+        }
+        let rate = cwnd as f64 * 8.0 / self.srtt.as_secs_f64();
+        self.target_bitrate = rate.clamp(
+            self.config.min_bitrate as f64,
+            self.config.max_bitrate as f64,
+        );
+        self.mode = NadaMode::GradualUpdate;
+    }
+
+    fn in_slow_start(&self) -> bool {
+        self.mode == NadaMode::AcceleratedRampUp
+    }
+
+    fn congestion_window(&self) -> u64 {
+        if self.srtt.is_zero() {
+            return self.config.initial_congestion_window;
+        }
+        let bdp = self.target_bitrate / 8.0 * self.srtt.as_secs_f64();
+        ((CWND_GAIN * bdp) as u64).max(self.config.min_congestion_window)
+    }
+
+    fn pacing_rate(&self) -> Option<u64> {
+        Some((self.target_bitrate / 8.0) as u64)
+    }
+
+    fn target_bitrate(&self) -> Option<u64> {
+        Some(self.target_bitrate as u64)
+    }
+
+    fn min_rtt(&self) -> Option<Duration> {
+        if self.base_delay.is_zero() {
+            return None;
+        }
+        Some(self.base_delay)
+    }
+
+    fn state(&self) -> &str {
+        match self.mode {
+            NadaMode::AcceleratedRampUp => "AcceleratedRampUp",
+            NadaMode::GradualUpdate => "GradualUpdate",
+        }
+    }
+
+    fn initial_window(&self) -> u64 {
+        self.config.initial_congestion_window
+    }
+
+    fn minimal_window(&self) -> u64 {
+        self.config.min_congestion_window
+    }
+
+    fn stats(&self) -> &CongestionStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_nada() -> Nada {
+        let conf = RecoveryConfig {
+            initial_congestion_window: 10,
+            min_congestion_window: 2,
+            max_datagram_size: 1200,
+            nada_min_bitrate: 150_000,
+            nada_max_bitrate: 1_500_000,
+            ..RecoveryConfig::default()
+        };
+        Nada::new(NadaConfig::from(&conf))
+    }
+
+    /// Ack a packet with the given RTT, and a delivery rate sample in bits
+    /// per second.
+    fn ack(n: &mut Nada, now: Instant, rtt: &mut RttEstimator, latest_rtt: Duration, rate: f64) {
+        let mut pkt = SentPacket {
+            ack_eliciting: true,
+            in_flight: true,
+            sent_size: 1200,
+            time_sent: now - latest_rtt,
+            ..SentPacket::default()
+        };
+        rtt.update(Duration::ZERO, latest_rtt);
+        n.begin_ack(now, 0);
+        n.on_ack(&mut pkt, now, false, rtt, 0);
+        n.receiving_rate = rate;
+        n.end_ack();
+    }
+
+    #[test]
+    fn nada_init() {
+        let n = new_nada();
+        assert_eq!(n.name(), "NADA");
+        assert_eq!(n.congestion_window(), 1200 * 10);
+        assert_eq!(n.initial_window(), 1200 * 10);
+        assert_eq!(n.minimal_window(), 1200 * 2);
+        assert_eq!(n.in_slow_start(), true);
+        assert_eq!(n.state(), "AcceleratedRampUp");
+        assert_eq!(n.target_bitrate(), Some(150_000));
+        assert_eq!(n.pacing_rate(), Some(150_000 / 8));
+        assert_eq!(n.min_rtt(), None);
+    }
+
+    #[test]
+    fn nada_ramp_up() {
+        let mut n = new_nada();
+        let mut rtt = RttEstimator::new(Duration::from_millis(80));
+        let base_rtt = Duration::from_millis(80);
+        let mut now = Instant::now();
+
+        // The target bitrate is updated once per feedback interval, and
+        // it is increased in proportion to the receiving rate.
+        ack(&mut n, now, &mut rtt, base_rtt, 150_000.0);
+        assert_eq!(n.target_bitrate(), Some(150_000));
+        now += Duration::from_millis(50);
+        ack(&mut n, now, &mut rtt, base_rtt, 150_000.0);
+        assert_eq!(n.target_bitrate(), Some(150_000));
+        now += Duration::from_millis(50);
+        ack(&mut n, now, &mut rtt, base_rtt, 150_000.0);
+        // The ratio is 50ms / (80ms + 100ms + 120ms).
+        let rate = n.target_bitrate().unwrap();
+        assert!(rate > 174_000 && rate <= 175_000);
+        assert_eq!(n.in_slow_start(), true);
+
+        // The target bitrate is capped.
+        for _ in 0..100 {
+            now += FEEDBACK_INTERVAL;
+            let rate = n.target_bitrate as f64;
+            ack(&mut n, now, &mut rtt, base_rtt, rate);
+        }
+        assert_eq!(n.target_bitrate(), Some(1_500_000));
+        assert_eq!(n.min_rtt(), Some(base_rtt));
+        assert_eq!(
+            n.congestion_window(),
+            (2.0 * 1_500_000.0 / 8.0 * 0.08) as u64
+        );
+    }
+
+    #[test]
+    fn nada_gradual_update() {
+        let mut n = new_nada();
+        let mut rtt = RttEstimator::new(Duration::from_millis(80));
+        let base_rtt = Duration::from_millis(80);
+        let mut now = Instant::now();
+        n.target_bitrate = 1_000_000.0;
+        ack(&mut n, now, &mut rtt, base_rtt, 1_000_000.0);
+
+        // The target bitrate is reduced once the queueing delay builds up.
+        for _ in 0..QUEUEING_DELAY_SAMPLES * 2 {
+            now += FEEDBACK_INTERVAL;
+            let latest_rtt = base_rtt + Duration::from_millis(100);
+            ack(&mut n, now, &mut rtt, latest_rtt, 150_000.0);
+        }
+        assert_eq!(n.state(), "GradualUpdate");
+        assert!(n.target_bitrate() < Some(1_000_000));
+
+        // The target bitrate is reduced on loss, but not below the minimum.
+        let pkt = SentPacket::default();
+        for _ in 0..100 {
+            now += FEEDBACK_INTERVAL;
+            n.on_congestion_event(now, &pkt, false, 1200, 0);
+            ack(&mut n, now, &mut rtt, base_rtt, 150_000.0);
+        }
+        assert_eq!(n.target_bitrate(), Some(150_000));
+        assert_eq!(n.stats().bytes_lost_in_total, 1200 * 100);
+
+        // The target bitrate is reset upon persistent congestion.
+        n.target_bitrate = 1_000_000.0;
+        n.on_congestion_event(now, &pkt, true, 1200, 0);
+        assert_eq!(n.target_bitrate(), Some(150_000));
+    }
+}
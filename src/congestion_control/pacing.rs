@@ -22,15 +22,15 @@ use crate::RecoveryConfig;
 /// from now, or it should be blocked
 const PACING_GRANULARITY: Duration = Duration::from_millis(1);
 
-/// The lower bound of burst packet number.
+/// The default lower bound of burst packet number.
 ///
 /// A lower bound is necessary to enable GSO and to avoid extremely small capacity.
-const MIN_BURST_PACKET_NUM: u64 = 10;
+pub const MIN_BURST_PACKET_NUM: u64 = 10;
 
-/// The upper bound of burst packet number.
+/// The default upper bound of burst packet number.
 ///
 /// Used to restrict capacity. An extremely large capacity is meaningless.
-const MAX_BURST_PACKET_NUM: u64 = 128;
+pub const MAX_BURST_PACKET_NUM: u64 = 128;
 
 /// A simple token-bucket pacer
 ///
@@ -54,6 +54,12 @@ pub struct Pacer {
 
     /// Pacing granularity
     granularity: Duration,
+
+    /// The lower bound of burst packet number.
+    min_burst: u64,
+
+    /// The upper bound of burst packet number.
+    max_burst: u64,
 }
 
 impl Pacer {
@@ -73,6 +79,8 @@ impl Pacer {
             last_cwnd: cwnd,
             last_sched_time: now,
             granularity,
+            min_burst: MIN_BURST_PACKET_NUM,
+            max_burst: MAX_BURST_PACKET_NUM,
         };
         pacer.update_capacity(cwnd, srtt, mtu);
         pacer.tokens = pacer.capacity;
@@ -81,15 +89,23 @@ impl Pacer {
 
     /// Build a pacer controller.
     pub fn build_pacer_controller(conf: &RecoveryConfig) -> Self {
-        Pacer::new(
-            conf.enable_pacing,
+        let mut pacer = Pacer::new(
+            conf.enable_pacing || conf.max_pacing_rate.is_some(),
             conf.initial_rtt,
-            conf.initial_congestion_window
-                .saturating_mul(conf.max_datagram_size as u64),
+            conf.initial_cwnd(),
             conf.max_datagram_size as u64,
             Instant::now(),
             conf.pacing_granularity,
-        )
+        );
+        pacer.min_burst = conf.pacing_min_burst.max(1);
+        pacer.max_burst = conf.pacing_max_burst.max(pacer.min_burst);
+        pacer.update_capacity(
+            conf.initial_cwnd(),
+            conf.initial_rtt,
+            conf.max_datagram_size as u64,
+        );
+        pacer.tokens = pacer.capacity;
+        pacer
     }
 
     /// check whether pacing is enabled
@@ -97,6 +113,11 @@ impl Pacer {
         self.enabled
     }
 
+    /// Enable or disable pacing.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
     /// Consume tokens after a packet is actually sent.
     /// Called after schedule and send operations.
     pub fn on_sent(&mut self, bytes_sent: u64) {
@@ -140,7 +161,8 @@ impl Pacer {
             .tokens
             .saturating_add((pacing_rate as u128 * elapsed.as_nanos() / 1_000_000_000) as u64)
             .min(self.capacity);
-        self.last_sched_time = now;
+        // The last schedule time may be in the future if pacing is offloaded.
+        self.last_sched_time = self.last_sched_time.max(now);
 
         if bytes_to_send <= self.tokens {
             return None;
@@ -152,13 +174,37 @@ impl Pacer {
         Some(self.last_sched_time + Duration::from_nanos(time_to_wait))
     }
 
+    /// Return the time to release the packet to send if pacing is offloaded
+    /// to the kernel or hardware, e.g. via SO_TXTIME.
+    ///
+    /// The packet is sent without waiting, so the tokens refilled until the
+    /// release time are consumed in advance. Return None if the packet can be
+    /// released immediately.
+    pub fn release_time(
+        &mut self,
+        bytes_to_send: u64,
+        pacing_rate: u64,
+        srtt: Duration,
+        cwnd: u64,
+        mtu: u64,
+        now: Instant,
+    ) -> Option<Instant> {
+        let time = self.schedule(bytes_to_send, pacing_rate, srtt, cwnd, mtu, now)?;
+        self.tokens = bytes_to_send;
+        self.last_sched_time = time;
+        Some(time)
+    }
+
     fn update_capacity(&mut self, cwnd: u64, srtt: Duration, mtu: u64) {
         // Note: the bound operation would limit the average pacing rate to
-        //   [MIN_BURST_PACKET_NUM * mtu / srtt, MAX_BURST_PACKET_NUM * mtu / srtt]
+        //   [min_burst * mtu / srtt, max_burst * mtu / srtt]
         // the minimal pacing rate may be too large in some cases.
         let capacity =
             (cwnd as u128 * self.granularity.as_nanos() / srtt.as_nanos().max(1_000_000)) as u64;
-        self.capacity = capacity.clamp(MIN_BURST_PACKET_NUM * mtu, MAX_BURST_PACKET_NUM * mtu)
+        self.capacity = capacity.clamp(
+            self.min_burst.saturating_mul(mtu),
+            self.max_burst.saturating_mul(mtu),
+        )
     }
 }
 
@@ -195,6 +241,33 @@ mod tests {
         assert_eq!(p.capacity, MAX_BURST_PACKET_NUM * mtu);
     }
 
+    #[test]
+    fn pacer_burst_bounds() {
+        let mut conf = RecoveryConfig {
+            enable_pacing: true,
+            pacing_min_burst: 2,
+            pacing_max_burst: 4,
+            ..RecoveryConfig::default()
+        };
+        let mtu = conf.max_datagram_size as u64;
+        let srtt = Duration::from_millis(1);
+
+        // The initial capacity is bounded by the configured minimum.
+        let mut p = Pacer::build_pacer_controller(&conf);
+        assert_eq!(p.capacity, 2 * mtu);
+        assert_eq!(p.capacity, p.tokens);
+
+        // The capacity is bounded by the configured maximum.
+        p.update_capacity(200 * mtu, srtt, mtu);
+        assert_eq!(p.capacity, 4 * mtu);
+
+        // The maximum should not be less than the minimum.
+        conf.pacing_min_burst = 20;
+        let p = Pacer::build_pacer_controller(&conf);
+        assert_eq!(p.capacity, 20 * mtu);
+        assert_eq!(p.max_burst, 20);
+    }
+
     #[test]
     fn pacer_disabled() {
         let srtt = Duration::from_millis(1);
@@ -304,4 +377,29 @@ mod tests {
         p.on_sent(bytes_to_send);
         assert_eq!(p.tokens, 0);
     }
+
+    #[test]
+    fn pacer_release_time() {
+        let srtt = Duration::from_millis(1);
+        let mtu: u64 = 1000;
+        let cwnd: u64 = 10 * mtu;
+        let now = Instant::now();
+        let pacing_rate: u64 = 1000000;
+        let mut p = Pacer::new(true, srtt, cwnd, mtu, now, PACING_GRANULARITY);
+
+        // Packets are released immediately until the tokens are used up.
+        for _ in 0..10 {
+            assert_eq!(p.release_time(mtu, pacing_rate, srtt, cwnd, mtu, now), None);
+            p.on_sent(mtu);
+        }
+
+        // Later packets are not blocked, and released at the pacing rate.
+        for i in 1..=3 {
+            assert_eq!(
+                p.release_time(mtu, pacing_rate, srtt, cwnd, mtu, now),
+                Some(now + Duration::from_millis(i))
+            );
+            p.on_sent(mtu);
+        }
+    }
 }
@@ -0,0 +1,476 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(unused_variables)]
+
+use std::time::Instant;
+
+use super::CongestionController;
+use super::CongestionStats;
+use super::Prr;
+use crate::connection::rtt::RttEstimator;
+use crate::connection::space::SentPacket;
+use crate::RecoveryConfig;
+
+/// The gain of the moving average of the fraction of ECN-CE marked packets.
+/// See RFC 8257 Section 3.3
+const ALPHA_GAIN: f64 = 1.0 / 16.0;
+
+/// The multiplicative decrease factor on packet loss.
+const LOSS_REDUCTION: f64 = 0.5;
+
+/// Prague Configuration.
+#[derive(Debug)]
+pub struct PragueConfig {
+    /// Minimal congestion window in bytes.
+    min_congestion_window: u64,
+
+    /// Initial congestion window in bytes.
+    initial_congestion_window: u64,
+
+    /// The threshold for slow start in bytes.
+    slow_start_thresh: u64,
+
+    /// Max datagram size in bytes.
+    max_datagram_size: u64,
+
+    /// Enable Proportional Rate Reduction on packet loss.
+    prr_enabled: bool,
+}
+
+impl PragueConfig {
+    pub fn from(conf: &RecoveryConfig) -> Self {
+        let max_datagram_size = conf.max_datagram_size as u64;
+        let min_congestion_window = conf.min_cwnd();
+        let initial_congestion_window = conf.initial_cwnd();
+        let slow_start_thresh = conf.slow_start_thresh.saturating_mul(max_datagram_size);
+
+        Self {
+            min_congestion_window,
+            initial_congestion_window,
+            slow_start_thresh,
+            max_datagram_size,
+            prr_enabled: conf.prr_enabled,
+        }
+    }
+}
+
+impl Default for PragueConfig {
+    fn default() -> Self {
+        Self {
+            min_congestion_window: 2 * crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            initial_congestion_window: 10 * crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            slow_start_thresh: u64::MAX,
+            max_datagram_size: crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64,
+            prr_enabled: false,
+        }
+    }
+}
+
+/// Prague is a scalable congestion controller for L4S. The packets are marked
+/// with ECT(1), and the congestion window is reduced in proportion to the
+/// fraction of packets marked with ECN-CE, similar to DCTCP. Packet loss is
+/// still handled as in Reno.
+///
+/// See RFC 9330, RFC 8257 and draft-briscoe-iccrg-prague-congestion-control.
+#[derive(Debug)]
+pub struct Prague {
+    /// Configuration.
+    config: PragueConfig,
+
+    /// Congestion window in bytes.
+    cwnd: u64,
+
+    /// Slow start threshold in bytes.
+    ssthresh: u64,
+
+    /// Bytes acked in congestion avoidance, used for the additive increase.
+    bytes_acked_in_ca: u64,
+
+    /// The moving average of the fraction of ECN-CE marked packets.
+    alpha: f64,
+
+    /// The number of ECT marked packets acked in the current round.
+    round_ecn_acked: u64,
+
+    /// The number of ECN-CE marked packets reported in the current round.
+    round_ce_marked: u64,
+
+    /// The round ends when a packet sent after the one with the number is
+    /// acked.
+    round_end_pkt_num: Option<u64>,
+
+    /// The largest packet number sent.
+    last_sent_pkt_num: u64,
+
+    /// The time of the last congestion window reduction.
+    recovery_start_time: Option<Instant>,
+
+    /// Proportional Rate Reduction on packet loss.
+    prr: Prr,
+
+    /// Congestion statistics.
+    stats: CongestionStats,
+}
+
+impl Prague {
+    pub fn new(config: PragueConfig) -> Self {
+        let cwnd = config.initial_congestion_window;
+        let ssthresh = config.slow_start_thresh;
+        let prr = Prr::new(config.prr_enabled);
+
+        Self {
+            config,
+            cwnd,
+            ssthresh,
+            bytes_acked_in_ca: 0,
+            alpha: 1.0,
+            round_ecn_acked: 0,
+            round_ce_marked: 0,
+            round_end_pkt_num: None,
+            last_sent_pkt_num: 0,
+            recovery_start_time: None,
+            prr,
+            stats: Default::default(),
+        }
+    }
+
+    /// Reduce the congestion window by the given factor, at most once per
+    /// round trip.
+    fn reduce_cwnd(&mut self, now: Instant, factor: f64) {
+        self.recovery_start_time = Some(now);
+        self.cwnd = ((self.cwnd as f64 * (1.0 - factor)) as u64).max(self.minimal_window());
+        self.ssthresh = self.cwnd;
+        self.bytes_acked_in_ca = 0;
+    }
+
+    /// Update alpha with the ECN feedback at the end of each round.
+    /// See RFC 8257 Section 3.3
+    fn update_alpha(&mut self, pkt_num: u64) {
+        if self.round_end_pkt_num.is_some_and(|n| pkt_num <= n) {
+            return;
+        }
+
+        if self.round_ecn_acked > 0 {
+            let frac = self.round_ce_marked as f64 / self.round_ecn_acked as f64;
+            self.alpha = (1.0 - ALPHA_GAIN) * self.alpha + ALPHA_GAIN * frac.min(1.0);
+        }
+        self.round_ecn_acked = 0;
+        self.round_ce_marked = 0;
+        self.round_end_pkt_num = Some(self.last_sent_pkt_num);
+    }
+}
+
+impl CongestionController for Prague {
+    fn name(&self) -> &str {
+        "PRAGUE"
+    }
+
+    fn on_sent(&mut self, now: Instant, packet: &mut SentPacket, bytes_in_flight: u64) {
+        let sent_bytes = packet.sent_size as u64;
+        self.last_sent_pkt_num = self.last_sent_pkt_num.max(packet.pkt_num);
+        self.prr.on_sent(sent_bytes, bytes_in_flight);
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_sent_in_total = self.stats.bytes_sent_in_total.saturating_add(sent_bytes);
+        if self.in_slow_start() {
+            self.stats.bytes_sent_in_slow_start = self
+                .stats
+                .bytes_sent_in_slow_start
+                .saturating_add(sent_bytes);
+        }
+    }
+
+    fn begin_ack(&mut self, now: Instant, bytes_in_flight: u64) {
+        self.prr.begin_ack(bytes_in_flight);
+    }
+
+    fn on_ack(
+        &mut self,
+        packet: &mut SentPacket,
+        now: Instant,
+        app_limited: bool,
+        rtt: &RttEstimator,
+        bytes_in_flight: u64,
+    ) {
+        let acked_bytes = packet.sent_size as u64;
+
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.stats.bytes_acked_in_total =
+            self.stats.bytes_acked_in_total.saturating_add(acked_bytes);
+        if self.in_slow_start() {
+            self.stats.bytes_acked_in_slow_start = self
+                .stats
+                .bytes_acked_in_slow_start
+                .saturating_add(acked_bytes);
+        }
+
+        self.prr.on_ack(acked_bytes, bytes_in_flight);
+        if !self.in_recovery(packet.time_sent) {
+            self.prr.on_recovery_end();
+        }
+
+        if app_limited || self.in_recovery(packet.time_sent) {
+            return;
+        }
+
+        if self.in_slow_start() {
+            self.cwnd = self.cwnd.saturating_add(acked_bytes);
+            return;
+        }
+
+        // Additive increase by one datagram per round trip.
+        self.bytes_acked_in_ca = self.bytes_acked_in_ca.saturating_add(acked_bytes);
+        if self.bytes_acked_in_ca >= self.cwnd {
+            self.bytes_acked_in_ca -= self.cwnd;
+            self.cwnd = self.cwnd.saturating_add(self.config.max_datagram_size);
+        }
+    }
+
+    fn end_ack(&mut self) {
+        self.prr
+            .end_ack(self.ssthresh, self.config.max_datagram_size);
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        packet: &SentPacket,
+        is_persistent_congestion: bool,
+        lost_bytes: u64,
+        bytes_in_flight: u64,
+    ) {
+        self.stats.bytes_lost_in_total = self.stats.bytes_lost_in_total.saturating_add(lost_bytes);
+        self.stats.bytes_in_flight = bytes_in_flight;
+        if self.in_slow_start() {
+            self.stats.bytes_lost_in_slow_start = self
+                .stats
+                .bytes_lost_in_slow_start
+                .saturating_add(lost_bytes);
+        }
+
+        if is_persistent_congestion {
+            self.recovery_start_time = Some(now);
+            self.cwnd = self.minimal_window();
+            self.bytes_acked_in_ca = 0;
+            self.prr.on_recovery_end();
+            return;
+        }
+
+        self.prr.on_lost(bytes_in_flight);
+        if self.in_recovery(packet.time_sent) {
+            return;
+        }
+        self.reduce_cwnd(now, LOSS_REDUCTION);
+        self.prr
+            .on_recovery_start(bytes_in_flight.saturating_add(lost_bytes));
+    }
+
+    fn is_l4s(&self) -> bool {
+        true
+    }
+
+    fn on_ecn_feedback(
+        &mut self,
+        now: Instant,
+        packet: &SentPacket,
+        ecn_acked: u64,
+        ce_marked: u64,
+        bytes_in_flight: u64,
+    ) {
+        self.stats.bytes_in_flight = bytes_in_flight;
+        self.round_ecn_acked = self.round_ecn_acked.saturating_add(ecn_acked);
+        self.round_ce_marked = self.round_ce_marked.saturating_add(ce_marked);
+        self.update_alpha(packet.pkt_num);
+
+        if ce_marked == 0 || self.in_recovery(packet.time_sent) {
+            return;
+        }
+        self.reduce_cwnd(now, self.alpha / 2.0);
+    }
+
+    fn on_careful_resume(&mut self, now: Instant, cwnd: u64) {
+        self.cwnd = cwnd.max(self.minimal_window());
+        self.ssthresh = self.cwnd;
+        self.bytes_acked_in_ca = 0;
+        self.prr.on_recovery_end();
+    }
+
+    fn in_slow_start(&self) -> bool {
+        self.cwnd < self.ssthresh
+    }
+
+    fn in_recovery(&self, sent_time: Instant) -> bool {
+        self.recovery_start_time.is_some_and(|t| sent_time <= t)
+    }
+
+    fn congestion_window(&self) -> u64 {
+        let cwnd = self.prr.congestion_window().unwrap_or(self.cwnd);
+        cwnd.max(self.config.min_congestion_window)
+    }
+
+    fn initial_window(&self) -> u64 {
+        self.config.initial_congestion_window
+    }
+
+    fn minimal_window(&self) -> u64 {
+        self.config.min_congestion_window
+    }
+
+    fn stats(&self) -> &CongestionStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn new_packet(pkt_num: u64, time_sent: Instant) -> SentPacket {
+        SentPacket {
+            pkt_num,
+            ack_eliciting: true,
+            in_flight: true,
+            sent_size: 1200,
+            time_sent,
+            ..SentPacket::default()
+        }
+    }
+
+    fn new_prague() -> Prague {
+        let conf = RecoveryConfig {
+            initial_congestion_window: 10,
+            min_congestion_window: 2,
+            max_datagram_size: 1200,
+            ..RecoveryConfig::default()
+        };
+        Prague::new(PragueConfig::from(&conf))
+    }
+
+    #[test]
+    fn prague_init() {
+        let p = new_prague();
+        assert_eq!(p.name(), "PRAGUE");
+        assert_eq!(p.congestion_window(), 1200 * 10);
+        assert_eq!(p.initial_window(), 1200 * 10);
+        assert_eq!(p.minimal_window(), 1200 * 2);
+        assert_eq!(p.in_slow_start(), true);
+        assert_eq!(p.in_recovery(Instant::now()), false);
+        assert_eq!(p.is_l4s(), true);
+        assert_eq!(p.pacing_rate(), None);
+    }
+
+    #[test]
+    fn prague_slow_start_and_loss() {
+        let mut p = new_prague();
+        let rtt = RttEstimator::new(Duration::from_millis(100));
+        let now = Instant::now();
+
+        let mut pkt = new_packet(0, now);
+        p.on_sent(now, &mut pkt, 1200);
+        let now = now + Duration::from_millis(100);
+        p.begin_ack(now, 1200);
+        p.on_ack(&mut pkt, now, false, &rtt, 0);
+        p.end_ack();
+        assert_eq!(p.congestion_window(), 1200 * 11);
+
+        // The window is halved on loss, at most once per round trip.
+        let pkt = new_packet(1, now);
+        let now = now + Duration::from_millis(100);
+        p.on_congestion_event(now, &pkt, false, 1200, 0);
+        assert_eq!(p.congestion_window(), 1200 * 11 / 2);
+        assert_eq!(p.in_slow_start(), false);
+        p.on_congestion_event(now, &pkt, false, 1200, 0);
+        assert_eq!(p.congestion_window(), 1200 * 11 / 2);
+        assert_eq!(p.stats().bytes_lost_in_total, 2400);
+
+        // Persistent congestion
+        p.on_congestion_event(now, &pkt, true, 1200, 0);
+        assert_eq!(p.congestion_window(), p.minimal_window());
+    }
+
+    #[test]
+    fn prague_prr() {
+        let conf = RecoveryConfig {
+            initial_congestion_window: 10,
+            min_congestion_window: 2,
+            max_datagram_size: 1200,
+            prr_enabled: true,
+            ..RecoveryConfig::default()
+        };
+        let mut p = Prague::new(PragueConfig::from(&conf));
+        let rtt = RttEstimator::new(Duration::from_millis(100));
+        let now = Instant::now();
+        for n in 0..10 {
+            let mut pkt = new_packet(n, now);
+            p.on_sent(now, &mut pkt, n * 1200);
+        }
+
+        // The window is halved on loss, but the packets are sent in
+        // proportion to the delivered bytes during recovery.
+        let pkt = new_packet(0, now);
+        let now = now + Duration::from_millis(100);
+        p.begin_ack(now, 1200 * 10);
+        p.on_congestion_event(now, &pkt, false, 1200, 1200 * 9);
+        p.end_ack();
+        assert_eq!(p.ssthresh, 1200 * 10 / 2);
+        assert_eq!(p.congestion_window(), 1200 * 9);
+
+        let mut pkt = new_packet(1, now - Duration::from_millis(100));
+        p.begin_ack(now, 1200 * 9);
+        p.on_ack(&mut pkt, now, false, &rtt, 1200 * 8);
+        p.end_ack();
+        assert_eq!(p.congestion_window(), 1200 * 8 + 600);
+
+        // Persistent congestion
+        p.on_congestion_event(now, &pkt, true, 1200, 0);
+        assert_eq!(p.congestion_window(), p.minimal_window());
+    }
+
+    #[test]
+    fn prague_ecn_feedback() {
+        let mut p = new_prague();
+        let now = Instant::now();
+        for i in 0..10 {
+            p.on_sent(now, &mut new_packet(i, now), 0);
+        }
+
+        // The first round ends with 2 of 10 packets marked with ECN-CE.
+        let now = now + Duration::from_millis(100);
+        p.on_ecn_feedback(now, &new_packet(9, now), 10, 2, 0);
+        let alpha = (1.0 - ALPHA_GAIN) + ALPHA_GAIN * 0.2;
+        assert_eq!(p.alpha, alpha);
+        assert_eq!(
+            p.congestion_window(),
+            (1200.0 * 10.0 * (1.0 - alpha / 2.0)) as u64
+        );
+        assert_eq!(p.in_slow_start(), false);
+
+        // Marks within the same round trip are ignored.
+        let cwnd = p.congestion_window();
+        p.on_ecn_feedback(now, &new_packet(9, now), 1, 1, 0);
+        assert_eq!(p.congestion_window(), cwnd);
+
+        // Alpha decays with less ECN-CE marks.
+        let now = now + Duration::from_millis(1);
+        for i in 10..20 {
+            p.on_sent(now, &mut new_packet(i, now), 0);
+        }
+        let now = now + Duration::from_millis(100);
+        p.on_ecn_feedback(now, &new_packet(19, now), 10, 0, 0);
+        assert!(p.alpha < alpha);
+        assert_eq!(p.congestion_window(), cwnd);
+    }
+}
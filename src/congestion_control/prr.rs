@@ -0,0 +1,196 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Proportional Rate Reduction (PRR) for the loss-based congestion
+/// controllers. During recovery, the number of bytes allowed to be sent is
+/// derived from the bytes delivered to the receiver, so that the window is
+/// reduced to the slow start threshold gradually over about one round trip,
+/// instead of stopping transmission until enough data leaves the network.
+/// The slow start reduction bound (PRR-SSRB) is used once the bytes in
+/// flight drop below the slow start threshold.
+///
+/// See RFC 6937.
+#[derive(Debug, Default)]
+pub(super) struct Prr {
+    /// Whether PRR is enabled.
+    enabled: bool,
+
+    /// Whether the sender is in recovery.
+    in_recovery: bool,
+
+    /// The bytes in flight at the start of recovery, i.e. RecoverFS.
+    recover_fs: u64,
+
+    /// The total bytes delivered since the start of recovery.
+    prr_delivered: u64,
+
+    /// The total bytes sent since the start of recovery.
+    prr_out: u64,
+
+    /// The bytes delivered by the current ACK frame.
+    delivered: u64,
+
+    /// The bytes allowed to be sent before the next ACK frame.
+    snd_cnt: u64,
+
+    /// The current bytes in flight.
+    bytes_in_flight: u64,
+}
+
+impl Prr {
+    pub(super) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// Start recovery with the bytes in flight, including the lost ones.
+    pub(super) fn on_recovery_start(&mut self, bytes_in_flight: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        self.in_recovery = true;
+        self.recover_fs = bytes_in_flight.max(1);
+        self.prr_delivered = 0;
+        self.prr_out = 0;
+        self.snd_cnt = 0;
+    }
+
+    /// Stop recovery when a packet sent after the start of recovery is acked,
+    /// or the window is reset.
+    pub(super) fn on_recovery_end(&mut self) {
+        self.in_recovery = false;
+    }
+
+    pub(super) fn on_sent(&mut self, sent_bytes: u64, bytes_in_flight: u64) {
+        self.bytes_in_flight = bytes_in_flight.saturating_add(sent_bytes);
+        if !self.in_recovery {
+            return;
+        }
+
+        self.prr_out = self.prr_out.saturating_add(sent_bytes);
+        self.snd_cnt = self.snd_cnt.saturating_sub(sent_bytes);
+    }
+
+    pub(super) fn begin_ack(&mut self, bytes_in_flight: u64) {
+        self.bytes_in_flight = bytes_in_flight;
+        self.delivered = 0;
+    }
+
+    pub(super) fn on_ack(&mut self, acked_bytes: u64, bytes_in_flight: u64) {
+        self.bytes_in_flight = bytes_in_flight;
+        self.delivered = self.delivered.saturating_add(acked_bytes);
+    }
+
+    pub(super) fn on_lost(&mut self, bytes_in_flight: u64) {
+        self.bytes_in_flight = bytes_in_flight;
+    }
+
+    /// Calculate the bytes allowed to be sent upon the end of an ACK frame.
+    pub(super) fn end_ack(&mut self, ssthresh: u64, max_datagram_size: u64) {
+        if !self.in_recovery {
+            return;
+        }
+
+        self.prr_delivered = self.prr_delivered.saturating_add(self.delivered);
+        let pipe = self.bytes_in_flight;
+        self.snd_cnt = if pipe > ssthresh {
+            // Proportional rate reduction:
+            //  sndcnt = CEIL(prr_delivered * ssthresh / RecoverFS) - prr_out
+            let recover_fs = self.recover_fs as u128;
+            let target =
+                (self.prr_delivered as u128 * ssthresh as u128 + recover_fs - 1) / recover_fs;
+            let target = target as u64;
+            target.saturating_sub(self.prr_out)
+        } else {
+            // Slow start reduction bound:
+            //  limit = MAX(prr_delivered - prr_out, DeliveredData) + MSS
+            //  sndcnt = MIN(ssthresh - pipe, limit)
+            let limit = self
+                .prr_delivered
+                .saturating_sub(self.prr_out)
+                .max(self.delivered)
+                .saturating_add(max_datagram_size);
+            (ssthresh - pipe).min(limit)
+        };
+    }
+
+    /// Return the congestion window during recovery, which allows sending
+    /// the bytes calculated by PRR.
+    pub(super) fn congestion_window(&self) -> Option<u64> {
+        if !self.in_recovery {
+            return None;
+        }
+        Some(self.bytes_in_flight.saturating_add(self.snd_cnt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSS: u64 = 1000;
+
+    #[test]
+    fn prr_disabled() {
+        let mut prr = Prr::new(false);
+        prr.on_recovery_start(10 * MSS);
+        prr.begin_ack(10 * MSS);
+        prr.on_ack(MSS, 9 * MSS);
+        prr.end_ack(5 * MSS, MSS);
+        assert_eq!(prr.congestion_window(), None);
+    }
+
+    #[test]
+    fn prr_proportional_reduction() {
+        let mut prr = Prr::new(true);
+        assert_eq!(prr.congestion_window(), None);
+
+        // 10 packets are in flight. An ACK frame acknowledges 2 packets and
+        // declares 1 packet lost, and the slow start threshold is halved.
+        prr.begin_ack(10 * MSS);
+        prr.on_ack(MSS, 9 * MSS);
+        prr.on_ack(MSS, 8 * MSS);
+        prr.on_lost(7 * MSS);
+        prr.on_recovery_start(8 * MSS);
+        prr.end_ack(4 * MSS, MSS);
+        assert_eq!(prr.congestion_window(), Some(8 * MSS));
+        prr.on_sent(MSS, 7 * MSS);
+        assert_eq!(prr.congestion_window(), Some(8 * MSS));
+
+        // The sender sends one packet for every two packets delivered.
+        for i in 0..2 {
+            prr.begin_ack((8 - i) * MSS);
+            prr.on_ack(MSS, (7 - i) * MSS);
+            prr.on_ack(MSS, (6 - i) * MSS);
+            prr.end_ack(4 * MSS, MSS);
+            assert_eq!(prr.congestion_window(), Some((7 - i) * MSS));
+            prr.on_sent(MSS, (6 - i) * MSS);
+        }
+
+        // The slow start reduction bound applies once the bytes in flight
+        // drop below the slow start threshold.
+        prr.begin_ack(6 * MSS);
+        prr.on_ack(MSS, 5 * MSS);
+        prr.on_ack(MSS, 4 * MSS);
+        prr.on_lost(3 * MSS);
+        prr.end_ack(4 * MSS, MSS);
+        assert_eq!(prr.congestion_window(), Some(4 * MSS));
+
+        prr.on_recovery_end();
+        assert_eq!(prr.congestion_window(), None);
+    }
+}
@@ -0,0 +1,672 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A deterministic network simulator for validating congestion controllers.
+//!
+//! The simulator drives a congestion controller against a scripted trace of
+//! a bottleneck link with a mock clock, and produces the throughput and
+//! latency traces. A bulk sender always has data to send, and the receiver
+//! acknowledges each packet immediately. The packets are queued at the
+//! bottleneck link, which may drop them randomly or by its AQM. The lost
+//! packets are detected by the packet threshold or by the probe timeout.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::CongestionController;
+use super::DeliveryRateEstimator;
+use crate::connection::rtt::RttEstimator;
+use crate::connection::space::SentPacket;
+
+/// The time step of the mock clock.
+const TICK: Duration = Duration::from_millis(1);
+
+/// The interval of the throughput and latency samples.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The size of each packet sent.
+const PACKET_SIZE: u64 = crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE as u64;
+
+/// The packet reordering threshold for loss detection.
+/// See RFC 9002 Section 6.1.1
+const PACKET_THRESHOLD: u64 = 3;
+
+/// Active queue management of the bottleneck link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Aqm {
+    /// Drop the arriving packets once the queue is full.
+    DropTail,
+
+    /// Random Early Detection, which drops the arriving packets with a
+    /// probability growing linearly from 0 to `max_p` as the queue grows from
+    /// `min_thresh` to `max_thresh` bytes, and drops all of them beyond.
+    /// See RFC 2309
+    Red {
+        min_thresh: u64,
+        max_thresh: u64,
+        max_p: f64,
+    },
+}
+
+/// A segment of the network trace, during which the bottleneck link is
+/// unchanged.
+#[derive(Debug, Clone)]
+pub(crate) struct LinkSegment {
+    /// The duration of the segment.
+    pub duration: Duration,
+
+    /// The bandwidth of the bottleneck link in bytes per second.
+    pub bandwidth: u64,
+
+    /// The round-trip propagation delay.
+    pub rtt: Duration,
+
+    /// The rate of random loss, which is in the range [0, 1).
+    pub loss_rate: f64,
+
+    /// The buffer size of the bottleneck link in bytes.
+    pub queue_limit: u64,
+
+    /// The AQM of the bottleneck link.
+    pub aqm: Aqm,
+}
+
+impl LinkSegment {
+    /// Create a lossless drop-tail link with a buffer of one BDP.
+    pub(crate) fn new(duration: Duration, bandwidth: u64, rtt: Duration) -> Self {
+        Self {
+            duration,
+            bandwidth,
+            rtt,
+            loss_rate: 0.0,
+            queue_limit: (bandwidth as u128 * rtt.as_micros() / 1_000_000) as u64,
+            aqm: Aqm::DropTail,
+        }
+    }
+
+    /// Return the time to transmit `bytes` over the link.
+    fn transmission_time(&self, bytes: u64) -> Duration {
+        Duration::from_micros(bytes.saturating_mul(1_000_000) / self.bandwidth.max(1))
+    }
+}
+
+/// A scripted trace of the bottleneck link. The last segment lasts until the
+/// end of the simulation.
+#[derive(Debug, Clone)]
+pub(crate) struct NetworkTrace {
+    segments: Vec<LinkSegment>,
+}
+
+impl NetworkTrace {
+    pub(crate) fn new(segments: Vec<LinkSegment>) -> Self {
+        assert!(!segments.is_empty());
+        Self { segments }
+    }
+
+    /// Return the total duration of the segments.
+    pub(crate) fn duration(&self) -> Duration {
+        self.segments.iter().map(|s| s.duration).sum()
+    }
+
+    /// Return the link segment at the given time since the start.
+    fn segment_at(&self, elapsed: Duration) -> &LinkSegment {
+        let mut end = Duration::ZERO;
+        for segment in &self.segments {
+            end += segment.duration;
+            if elapsed < end {
+                return segment;
+            }
+        }
+        self.segments.last().unwrap()
+    }
+}
+
+/// A sample of the throughput and latency traces.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SimulationSample {
+    /// The time since the start at the end of the sample interval.
+    pub time: Duration,
+
+    /// The throughput of the acknowledged data in the sample interval, in
+    /// bytes per second.
+    pub throughput: u64,
+
+    /// The latest RTT sample.
+    pub rtt: Duration,
+
+    /// The queueing delay at the bottleneck link.
+    pub queue_delay: Duration,
+
+    /// The congestion window.
+    pub cwnd: u64,
+
+    /// The bytes in flight.
+    pub bytes_in_flight: u64,
+}
+
+/// The output of a simulation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct SimulationReport {
+    /// The throughput and latency traces.
+    pub samples: Vec<SimulationSample>,
+
+    /// The total bytes sent.
+    pub sent_bytes: u64,
+
+    /// The total bytes acknowledged.
+    pub acked_bytes: u64,
+
+    /// The number of packets dropped by the network.
+    pub dropped_packets: u64,
+
+    /// The number of packets detected lost by the sender.
+    pub lost_packets: u64,
+}
+
+impl SimulationReport {
+    /// Return the average throughput in bytes per second of the samples
+    /// within the given time range.
+    pub(crate) fn throughput(&self, from: Duration, to: Duration) -> u64 {
+        let samples: Vec<u64> = self
+            .samples
+            .iter()
+            .filter(|s| s.time > from && s.time <= to)
+            .map(|s| s.throughput)
+            .collect();
+        if samples.is_empty() {
+            return 0;
+        }
+        samples.iter().sum::<u64>() / samples.len() as u64
+    }
+
+    /// Return the maximum queueing delay of the samples.
+    pub(crate) fn max_queue_delay(&self) -> Duration {
+        self.samples
+            .iter()
+            .map(|s| s.queue_delay)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// A packet queued at or departed from the bottleneck link.
+#[derive(Debug)]
+struct LinkPacket {
+    pkt_num: u64,
+    size: u64,
+    time: Instant,
+}
+
+/// A pseudo-random number generator with a fixed seed, so that the random
+/// losses are reproducible.
+#[derive(Debug)]
+struct Xorshift(u64);
+
+impl Xorshift {
+    /// Return a number in the range [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        (x >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// Network simulator driving a congestion controller.
+pub(crate) struct Simulator {
+    /// The congestion controller under test.
+    congestion: Box<dyn CongestionController>,
+
+    /// The delivery rate estimator shared by the congestion controllers.
+    delivery_rate: DeliveryRateEstimator,
+
+    /// The RTT estimator of the sender.
+    rtt: RttEstimator,
+
+    /// The network trace.
+    trace: NetworkTrace,
+
+    /// The mock clock.
+    now: Instant,
+
+    /// The start time of the simulation.
+    start: Instant,
+
+    /// The next packet number.
+    next_pkt_num: u64,
+
+    /// The earliest time to send the next packet if pacing is used.
+    next_send_time: Instant,
+
+    /// The packets sent but neither acknowledged nor detected lost.
+    sent: BTreeMap<u64, SentPacket>,
+
+    /// The bytes in flight.
+    bytes_in_flight: u64,
+
+    /// The largest acknowledged packet number.
+    largest_acked: Option<u64>,
+
+    /// The packets dropped by the network but not detected lost yet.
+    dropped: BTreeSet<u64>,
+
+    /// The packets queued at the bottleneck link, with their departure time.
+    queue: VecDeque<LinkPacket>,
+
+    /// The bytes queued at the bottleneck link.
+    queue_bytes: u64,
+
+    /// The time when the bottleneck link becomes idle.
+    link_free_time: Instant,
+
+    /// The packets departed from the bottleneck link, with the arrival time
+    /// of their acknowledgements.
+    acks: Vec<LinkPacket>,
+
+    /// The generator of random losses.
+    rng: Xorshift,
+
+    /// The bytes acknowledged in the current sample interval.
+    sample_acked: u64,
+
+    /// The end of the current sample interval.
+    sample_end: Instant,
+
+    /// The output of the simulation.
+    report: SimulationReport,
+}
+
+impl Simulator {
+    pub(crate) fn new(congestion: Box<dyn CongestionController>, trace: NetworkTrace) -> Self {
+        let now = Instant::now();
+        Self {
+            congestion,
+            delivery_rate: DeliveryRateEstimator::default(),
+            rtt: RttEstimator::new(crate::INITIAL_RTT),
+            trace,
+            now,
+            start: now,
+            next_pkt_num: 0,
+            next_send_time: now,
+            sent: BTreeMap::new(),
+            bytes_in_flight: 0,
+            largest_acked: None,
+            dropped: BTreeSet::new(),
+            queue: VecDeque::new(),
+            queue_bytes: 0,
+            link_free_time: now,
+            acks: Vec::new(),
+            rng: Xorshift(0x2545_f491_4f6c_dd1d),
+            sample_acked: 0,
+            sample_end: now + SAMPLE_INTERVAL,
+            report: SimulationReport::default(),
+        }
+    }
+
+    /// Run the simulation for the whole network trace.
+    pub(crate) fn run(self) -> SimulationReport {
+        let duration = self.trace.duration();
+        self.run_for(duration)
+    }
+
+    /// Run the simulation for the given duration.
+    pub(crate) fn run_for(mut self, duration: Duration) -> SimulationReport {
+        let end = self.now + duration;
+        while self.now < end {
+            self.now += TICK;
+            self.on_tick();
+        }
+        self.report
+    }
+
+    fn on_tick(&mut self) {
+        self.forward_link();
+        if !self.process_acks() {
+            // Detect the lost packets by the probe timeout.
+            self.detect_lost_packets();
+        }
+        self.send_packets();
+
+        if self.now >= self.sample_end {
+            self.take_sample();
+        }
+    }
+
+    /// Send packets as allowed by the congestion window and the pacing rate.
+    fn send_packets(&mut self) {
+        // No credit is accumulated for the ticks in which the sender is
+        // blocked by the congestion window.
+        if self.next_send_time + TICK < self.now {
+            self.next_send_time = self.now - TICK;
+        }
+
+        while self.bytes_in_flight + PACKET_SIZE <= self.congestion.congestion_window() {
+            let pacing_rate = self.congestion.pacing_rate().filter(|r| *r > 0);
+            if pacing_rate.is_some() && self.next_send_time > self.now {
+                break;
+            }
+
+            let mut pkt = SentPacket {
+                pkt_num: self.next_pkt_num,
+                time_sent: self.now,
+                sent_size: PACKET_SIZE as usize,
+                ack_eliciting: true,
+                in_flight: true,
+                has_data: true,
+                ..SentPacket::default()
+            };
+            self.next_pkt_num += 1;
+
+            let lost = self.delivery_rate.lost();
+            self.delivery_rate
+                .on_packet_sent(&mut pkt, self.bytes_in_flight, lost);
+            self.congestion
+                .on_sent(self.now, &mut pkt, self.bytes_in_flight);
+            self.bytes_in_flight += PACKET_SIZE;
+            self.report.sent_bytes += PACKET_SIZE;
+
+            if let Some(rate) = pacing_rate {
+                self.next_send_time += Duration::from_micros(PACKET_SIZE * 1_000_000 / rate.max(1));
+            }
+
+            self.enqueue(pkt.pkt_num);
+            self.sent.insert(pkt.pkt_num, pkt);
+        }
+    }
+
+    /// A packet arrives at the bottleneck link.
+    fn enqueue(&mut self, pkt_num: u64) {
+        let segment = self.trace.segment_at(self.now - self.start).clone();
+        let random_loss = segment.loss_rate > 0.0 && self.rng.next_f64() < segment.loss_rate;
+        let overflow = self.queue_bytes + PACKET_SIZE > segment.queue_limit;
+        if random_loss || overflow || self.aqm_drop(segment.aqm) {
+            self.dropped.insert(pkt_num);
+            self.report.dropped_packets += 1;
+            return;
+        }
+
+        // The packets queued keep the departure time computed upon arrival,
+        // even if the bandwidth changes.
+        let departure = self.link_free_time.max(self.now) + segment.transmission_time(PACKET_SIZE);
+        self.link_free_time = departure;
+        self.queue_bytes += PACKET_SIZE;
+        self.queue.push_back(LinkPacket {
+            pkt_num,
+            size: PACKET_SIZE,
+            time: departure,
+        });
+    }
+
+    /// Check if the arriving packet is dropped by the AQM.
+    fn aqm_drop(&mut self, aqm: Aqm) -> bool {
+        match aqm {
+            Aqm::DropTail => false,
+            Aqm::Red {
+                min_thresh,
+                max_thresh,
+                max_p,
+            } => {
+                if self.queue_bytes >= max_thresh {
+                    return true;
+                }
+                if self.queue_bytes < min_thresh {
+                    return false;
+                }
+                let p = max_p * (self.queue_bytes - min_thresh) as f64
+                    / (max_thresh - min_thresh).max(1) as f64;
+                self.rng.next_f64() < p
+            }
+        }
+    }
+
+    /// Move the packets departed from the bottleneck link towards the
+    /// receiver, whose acknowledgements arrive after the propagation delay.
+    fn forward_link(&mut self) {
+        while let Some(pkt) = self.queue.front() {
+            if pkt.time > self.now {
+                break;
+            }
+            let mut pkt = self.queue.pop_front().unwrap();
+            self.queue_bytes -= pkt.size;
+            pkt.time += self.trace.segment_at(pkt.time - self.start).rtt;
+            self.acks.push(pkt);
+        }
+    }
+
+    /// Process the acknowledgements arrived as a single ACK frame.
+    ///
+    /// Return true if any packet is acknowledged.
+    fn process_acks(&mut self) -> bool {
+        let now = self.now;
+        let (mut arrived, pending): (Vec<_>, Vec<_>) =
+            self.acks.drain(..).partition(|p| p.time <= now);
+        self.acks = pending;
+        if arrived.is_empty() {
+            return false;
+        }
+        arrived.sort_by_key(|p| p.pkt_num);
+
+        self.congestion.begin_ack(now, self.bytes_in_flight);
+        let mut rtt_sample = None;
+        for ack in arrived {
+            let mut pkt = match self.sent.remove(&ack.pkt_num) {
+                Some(pkt) => pkt,
+                None => continue,
+            };
+            pkt.time_acked = Some(now);
+            self.bytes_in_flight = self.bytes_in_flight.saturating_sub(ack.size);
+            self.sample_acked += ack.size;
+            self.report.acked_bytes += ack.size;
+
+            self.delivery_rate.update_rate_sample(&mut pkt);
+            self.congestion
+                .on_ack(&mut pkt, now, false, &self.rtt, self.bytes_in_flight);
+
+            self.largest_acked = Some(pkt.pkt_num.max(self.largest_acked.unwrap_or(0)));
+            rtt_sample = Some(now.saturating_duration_since(pkt.time_sent));
+        }
+
+        self.delivery_rate.generate_rate_sample();
+        self.congestion.on_rate_sample(&self.delivery_rate);
+        if let Some(rtt) = rtt_sample {
+            self.rtt.update(Duration::ZERO, rtt);
+        }
+
+        self.detect_lost_packets();
+        self.congestion.end_ack();
+        if self.congestion.is_rate_limited() {
+            self.delivery_rate.set_app_limited(true);
+        }
+        true
+    }
+
+    /// Detect the dropped packets as lost by the packet threshold once a
+    /// later packet is acknowledged, or by the probe timeout.
+    fn detect_lost_packets(&mut self) {
+        let pto = self.rtt.pto_base();
+        let mut lost_bytes = 0;
+        let mut latest_lost = None;
+        for pkt_num in self.dropped.clone() {
+            let pkt = match self.sent.get(&pkt_num) {
+                Some(pkt) => pkt,
+                None => continue,
+            };
+            let lost = match self.largest_acked {
+                Some(largest) if largest >= pkt_num + PACKET_THRESHOLD => true,
+                _ => self.now >= pkt.time_sent + pto,
+            };
+            if !lost {
+                continue;
+            }
+
+            self.dropped.remove(&pkt_num);
+            let pkt = self.sent.remove(&pkt_num).unwrap();
+            self.bytes_in_flight = self.bytes_in_flight.saturating_sub(PACKET_SIZE);
+            self.report.lost_packets += 1;
+            lost_bytes += PACKET_SIZE;
+            latest_lost = Some(pkt);
+        }
+
+        if let Some(pkt) = latest_lost {
+            self.delivery_rate.on_packet_lost(lost_bytes);
+            self.congestion.on_congestion_event(
+                self.now,
+                &pkt,
+                false,
+                lost_bytes,
+                self.bytes_in_flight,
+            );
+        }
+    }
+
+    fn take_sample(&mut self) {
+        self.report.samples.push(SimulationSample {
+            time: self.now - self.start,
+            throughput: self.sample_acked * 1_000_000 / SAMPLE_INTERVAL.as_micros() as u64,
+            rtt: self.rtt.latest_rtt(),
+            queue_delay: self.link_free_time.saturating_duration_since(self.now),
+            cwnd: self.congestion.congestion_window(),
+            bytes_in_flight: self.bytes_in_flight,
+        });
+        self.sample_acked = 0;
+        self.sample_end += SAMPLE_INTERVAL;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::congestion_control::build_congestion_controller;
+    use crate::CongestionControlAlgorithm;
+    use crate::RecoveryConfig;
+
+    /// 10 Mbps
+    const BANDWIDTH: u64 = 1_250_000;
+
+    const RTT: Duration = Duration::from_millis(40);
+
+    fn new_simulator(algor: CongestionControlAlgorithm, trace: NetworkTrace) -> Simulator {
+        let conf = RecoveryConfig {
+            congestion_control_algorithm: algor,
+            ..RecoveryConfig::default()
+        };
+        Simulator::new(build_congestion_controller(&conf), trace)
+    }
+
+    #[test]
+    fn simulator_deterministic() {
+        let trace = NetworkTrace::new(vec![LinkSegment {
+            loss_rate: 0.01,
+            ..LinkSegment::new(Duration::from_secs(5), BANDWIDTH, RTT)
+        }]);
+
+        let r1 = new_simulator(CongestionControlAlgorithm::Cubic, trace.clone()).run();
+        let r2 = new_simulator(CongestionControlAlgorithm::Cubic, trace).run();
+        assert_eq!(r1.samples.len(), 50);
+        assert_eq!(r1, r2);
+    }
+
+    #[test]
+    fn simulator_link_capacity() {
+        let trace = NetworkTrace::new(vec![LinkSegment::new(
+            Duration::from_secs(10),
+            BANDWIDTH,
+            RTT,
+        )]);
+        let max_queue_delay = Duration::from_micros(
+            (trace.segments[0].queue_limit + PACKET_SIZE) * 1_000_000 / BANDWIDTH,
+        );
+
+        let cases = [
+            CongestionControlAlgorithm::Cubic,
+            CongestionControlAlgorithm::Bbr,
+            CongestionControlAlgorithm::Bbr3,
+            CongestionControlAlgorithm::Copa,
+            CongestionControlAlgorithm::Prague,
+            CongestionControlAlgorithm::Hybrid,
+            CongestionControlAlgorithm::Ledbat,
+            CongestionControlAlgorithm::Nada,
+            CongestionControlAlgorithm::Balia,
+        ];
+        for algor in cases {
+            let report = new_simulator(algor, trace.clone()).run();
+            assert!(report.acked_bytes > 0, "{:?}", algor);
+            assert!(report.acked_bytes <= report.sent_bytes, "{:?}", algor);
+
+            // The throughput is limited by the bandwidth, and the RTT is not
+            // less than the propagation delay.
+            let limit = BANDWIDTH * 10 + PACKET_SIZE;
+            assert!(report.acked_bytes <= limit, "{:?}", algor);
+            for s in &report.samples {
+                assert!(s.rtt >= RTT, "{:?} {:?}", algor, s);
+                assert!(s.queue_delay <= max_queue_delay, "{:?} {:?}", algor, s);
+            }
+        }
+    }
+
+    #[test]
+    fn simulator_random_loss() {
+        let trace = NetworkTrace::new(vec![LinkSegment {
+            loss_rate: 0.01,
+            queue_limit: u64::MAX,
+            ..LinkSegment::new(Duration::from_secs(10), BANDWIDTH, RTT)
+        }]);
+
+        let report = new_simulator(CongestionControlAlgorithm::Cubic, trace).run();
+        assert!(report.dropped_packets > 0);
+        assert!(report.lost_packets > 0);
+        assert!(report.lost_packets <= report.dropped_packets);
+    }
+
+    #[test]
+    fn simulator_bandwidth_change() {
+        let trace = NetworkTrace::new(vec![
+            LinkSegment::new(Duration::from_secs(5), BANDWIDTH, RTT),
+            LinkSegment::new(Duration::from_secs(5), BANDWIDTH / 5, RTT),
+        ]);
+
+        let report = new_simulator(CongestionControlAlgorithm::Cubic, trace).run();
+        let secs = Duration::from_secs;
+        assert!(report.throughput(secs(0), secs(5)) <= BANDWIDTH + PACKET_SIZE);
+        assert!(report.throughput(secs(6), secs(10)) <= BANDWIDTH / 5 + PACKET_SIZE);
+        assert!(report.throughput(secs(6), secs(10)) > 0);
+    }
+
+    #[test]
+    fn simulator_red() {
+        let (min_thresh, max_thresh) = (10 * PACKET_SIZE, 25 * PACKET_SIZE);
+        let trace = NetworkTrace::new(vec![LinkSegment {
+            queue_limit: 100 * PACKET_SIZE,
+            aqm: Aqm::Red {
+                min_thresh,
+                max_thresh,
+                max_p: 0.1,
+            },
+            ..LinkSegment::new(Duration::from_secs(10), BANDWIDTH, RTT)
+        }]);
+
+        let report = new_simulator(CongestionControlAlgorithm::Cubic, trace).run();
+        assert!(report.dropped_packets > 0);
+
+        // The queue is kept below the maximum threshold.
+        let max_queue_delay =
+            Duration::from_micros((max_thresh + PACKET_SIZE) * 1_000_000 / BANDWIDTH);
+        assert!(report.max_queue_delay() <= max_queue_delay);
+    }
+}
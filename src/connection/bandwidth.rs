@@ -0,0 +1,174 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+use std::time::Instant;
+
+/// The minimum interval of a delivery rate sample, which avoids the noise of
+/// ACK compression on paths with a very small RTT.
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The gain of the moving average of the delivery rate samples.
+const ESTIMATE_GAIN: f64 = 1.0 / 4.0;
+
+/// The number of samples needed for the estimate to reach the full confidence.
+const FULL_CONFIDENCE_SAMPLES: u64 = 8;
+
+/// Bandwidth estimator for a path, which is independent of the congestion
+/// controller. It measures the delivery rate of the acked data in each
+/// interval of about one smoothed RTT, and smooths the samples with a moving
+/// average. The samples in an application limited interval are used only if
+/// the rate is higher than the estimate, since they underestimate the
+/// bandwidth.
+#[derive(Debug, Default)]
+pub(super) struct BandwidthEstimator {
+    /// The start time of the current sample interval.
+    interval_start: Option<Instant>,
+
+    /// The bytes acked in the current sample interval.
+    interval_acked: u64,
+
+    /// Whether the current sample interval is application limited.
+    interval_app_limited: bool,
+
+    /// The latest delivery rate sample in bytes per second.
+    delivery_rate: u64,
+
+    /// The smoothed bandwidth estimate in bytes per second.
+    estimate: u64,
+
+    /// The moving average of the deviation of the samples from the estimate.
+    deviation: u64,
+
+    /// The number of samples used for the estimate.
+    samples: u64,
+}
+
+impl BandwidthEstimator {
+    /// Update the estimator with an acked packet.
+    pub(super) fn on_packet_acked(
+        &mut self,
+        acked_bytes: u64,
+        app_limited: bool,
+        srtt: Duration,
+        now: Instant,
+    ) {
+        let start = match self.interval_start {
+            Some(t) => t,
+            None => {
+                self.start_interval(now);
+                return;
+            }
+        };
+
+        self.interval_acked = self.interval_acked.saturating_add(acked_bytes);
+        self.interval_app_limited |= app_limited;
+
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed < srtt.max(MIN_SAMPLE_INTERVAL) {
+            return;
+        }
+
+        let rate = (self.interval_acked as u128 * 1_000_000 / elapsed.as_micros().max(1)) as u64;
+        self.delivery_rate = rate;
+        if !self.interval_app_limited || rate > self.estimate {
+            self.update_estimate(rate);
+        }
+        self.start_interval(now);
+    }
+
+    /// Return the latest delivery rate sample in bytes per second.
+    pub(super) fn delivery_rate(&self) -> u64 {
+        self.delivery_rate
+    }
+
+    /// Return the smoothed bandwidth estimate in bytes per second.
+    pub(super) fn estimate(&self) -> u64 {
+        self.estimate
+    }
+
+    /// Return the confidence of the estimate in percent. It grows with the
+    /// number of samples, and drops with the deviation of the samples.
+    pub(super) fn confidence(&self) -> u8 {
+        if self.samples == 0 || self.estimate == 0 {
+            return 0;
+        }
+
+        let samples = self.samples.min(FULL_CONFIDENCE_SAMPLES) as f64;
+        let stability = 1.0 - (self.deviation as f64 / self.estimate as f64).min(1.0);
+        (100.0 * samples / FULL_CONFIDENCE_SAMPLES as f64 * stability) as u8
+    }
+
+    fn start_interval(&mut self, now: Instant) {
+        self.interval_start = Some(now);
+        self.interval_acked = 0;
+        self.interval_app_limited = false;
+    }
+
+    fn update_estimate(&mut self, rate: u64) {
+        if self.samples == 0 {
+            self.estimate = rate;
+        } else {
+            let deviation = rate.abs_diff(self.estimate) as f64;
+            self.deviation =
+                ((1.0 - ESTIMATE_GAIN) * self.deviation as f64 + ESTIMATE_GAIN * deviation) as u64;
+            self.estimate =
+                ((1.0 - ESTIMATE_GAIN) * self.estimate as f64 + ESTIMATE_GAIN * rate as f64) as u64;
+        }
+        self.samples = self.samples.saturating_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bandwidth_estimate() {
+        let mut bw = BandwidthEstimator::default();
+        let srtt = Duration::from_millis(100);
+        let mut now = Instant::now();
+        assert_eq!(bw.estimate(), 0);
+        assert_eq!(bw.confidence(), 0);
+
+        // 10000 bytes are acked in each round trip.
+        bw.on_packet_acked(1000, false, srtt, now);
+        for _ in 0..FULL_CONFIDENCE_SAMPLES {
+            for _ in 0..10 {
+                now += Duration::from_millis(10);
+                bw.on_packet_acked(1000, false, srtt, now);
+            }
+            assert_eq!(bw.delivery_rate(), 100_000);
+        }
+        assert_eq!(bw.estimate(), 100_000);
+        assert_eq!(bw.confidence(), 100);
+
+        // The application limited samples lower than the estimate are ignored.
+        for _ in 0..10 {
+            now += Duration::from_millis(10);
+            bw.on_packet_acked(500, true, srtt, now);
+        }
+        assert_eq!(bw.delivery_rate(), 50_000);
+        assert_eq!(bw.estimate(), 100_000);
+
+        // The estimate follows the samples, but the confidence drops.
+        for _ in 0..10 {
+            now += Duration::from_millis(10);
+            bw.on_packet_acked(2000, false, srtt, now);
+        }
+        assert_eq!(bw.delivery_rate(), 200_000);
+        assert_eq!(bw.estimate(), 125_000);
+        assert!(bw.confidence() < 100);
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::congestion_control::CongestionController;
+
+/// The saved capacity is not used if the current RTT is less than the saved
+/// RTT divided by the factor, since the path may have changed.
+const MIN_RTT_DIVISOR: u32 = 2;
+
+/// The saved capacity is not used if the current RTT is larger than the saved
+/// RTT multiplied by the factor, since the path may have changed.
+const MAX_RTT_FACTOR: u32 = 10;
+
+/// The phases of Careful Resume.
+/// See draft-ietf-tsvwg-careful-resume Section 4
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum CarefulResumePhase {
+    /// The sender starts with the normal congestion control, and checks
+    /// whether the saved capacity could be used for the path.
+    Reconnaissance,
+
+    /// The congestion window is jump-started to a half of the saved capacity,
+    /// which is not validated yet.
+    Unvalidated,
+
+    /// The sender checks whether the data sent in the unvalidated phase is
+    /// acknowledged without congestion.
+    Validating,
+
+    /// Congestion is detected in the unvalidated or validating phase, and the
+    /// congestion window is reduced to a half of the validated capacity.
+    SafeRetreat,
+
+    /// Careful Resume is not used, or it has completed.
+    Normal,
+}
+
+/// Careful Resume for a path, which jump-starts the congestion window with the
+/// capacity saved from a previous connection.
+/// See draft-ietf-tsvwg-careful-resume
+pub(super) struct CarefulResume {
+    /// The current phase.
+    phase: CarefulResumePhase,
+
+    /// The saved capacity of the path in bytes.
+    saved_capacity: u64,
+
+    /// The saved RTT of the path.
+    saved_rtt: Duration,
+
+    /// The congestion window used in the unvalidated and validating phases.
+    cwnd: u64,
+
+    /// The volume of data acknowledged since the reconnaissance phase, which
+    /// is the capacity validated.
+    pipesize: u64,
+
+    /// The packet number of the first packet sent in the unvalidated phase.
+    first_unvalidated_pkt: Option<u64>,
+
+    /// The packet number of the last packet sent in the unvalidated phase.
+    last_unvalidated_pkt: Option<u64>,
+
+    /// The largest packet number sent.
+    largest_sent_pkt: Option<u64>,
+
+    /// The largest packet number acknowledged.
+    largest_acked_pkt: Option<u64>,
+}
+
+impl CarefulResume {
+    pub(super) fn new() -> Self {
+        Self {
+            phase: CarefulResumePhase::Normal,
+            saved_capacity: 0,
+            saved_rtt: Duration::ZERO,
+            cwnd: 0,
+            pipesize: 0,
+            first_unvalidated_pkt: None,
+            last_unvalidated_pkt: None,
+            largest_sent_pkt: None,
+            largest_acked_pkt: None,
+        }
+    }
+
+    /// Start Careful Resume with the saved capacity and RTT of the path.
+    pub(super) fn enable(&mut self, saved_capacity: u64, saved_rtt: Duration) {
+        if saved_capacity == 0
+            || saved_rtt.is_zero()
+            || self.largest_sent_pkt.is_some()
+            || self.phase != CarefulResumePhase::Normal
+        {
+            return;
+        }
+        self.phase = CarefulResumePhase::Reconnaissance;
+        self.saved_capacity = saved_capacity;
+        self.saved_rtt = saved_rtt;
+    }
+
+    /// Return the congestion window to use instead of the one of the
+    /// congestion controller if it is larger.
+    pub(super) fn congestion_window(&self) -> Option<u64> {
+        match self.phase {
+            CarefulResumePhase::Unvalidated | CarefulResumePhase::Validating => Some(self.cwnd),
+            _ => None,
+        }
+    }
+
+    /// Return the pacing rate in bytes per second. The data in the unvalidated
+    /// phase should be paced based on the current RTT.
+    pub(super) fn pacing_rate(&self, srtt: Duration) -> Option<u64> {
+        if self.phase != CarefulResumePhase::Unvalidated || srtt.is_zero() {
+            return None;
+        }
+        Some((self.cwnd as u128 * 1_000_000 / srtt.as_micros().max(1)) as u64)
+    }
+
+    /// Handle sent event of a packet in the Application Data space.
+    pub(super) fn on_packet_sent(&mut self, pkt_num: u64, bytes_in_flight: u64) {
+        self.largest_sent_pkt = Some(pkt_num);
+        if self.phase != CarefulResumePhase::Unvalidated {
+            return;
+        }
+
+        if self.first_unvalidated_pkt.is_none() {
+            self.first_unvalidated_pkt = Some(pkt_num);
+        }
+
+        // All of the jump window has been sent.
+        if bytes_in_flight >= self.cwnd {
+            self.enter_validating(bytes_in_flight);
+        }
+    }
+
+    /// Handle acknowledgement of a packet in the Application Data space.
+    pub(super) fn on_packet_acked(&mut self, pkt_num: u64, sent_size: u64) {
+        if self.phase == CarefulResumePhase::Normal {
+            return;
+        }
+        self.largest_acked_pkt = self.largest_acked_pkt.max(Some(pkt_num));
+        self.pipesize = self.pipesize.saturating_add(sent_size);
+        if self.phase == CarefulResumePhase::Validating {
+            self.cwnd = self.cwnd.max(self.pipesize);
+        }
+    }
+
+    /// Update the phase after all the acknowledged packets in an ACK frame are
+    /// processed.
+    pub(super) fn end_ack(
+        &mut self,
+        latest_rtt: Option<Duration>,
+        bytes_in_flight: u64,
+        congestion: &mut dyn CongestionController,
+        now: Instant,
+    ) {
+        match self.phase {
+            CarefulResumePhase::Reconnaissance => {
+                // The saved capacity is not used if the path has changed.
+                if let Some(rtt) = latest_rtt {
+                    if rtt < self.saved_rtt / MIN_RTT_DIVISOR
+                        || rtt > self.saved_rtt * MAX_RTT_FACTOR
+                    {
+                        self.phase = CarefulResumePhase::Normal;
+                        return;
+                    }
+                }
+
+                // Jump after the initial window is acknowledged.
+                if latest_rtt.is_none() || self.pipesize < congestion.initial_window() {
+                    return;
+                }
+                let cwnd = congestion.congestion_window();
+                let jump_cwnd = self.saved_capacity / 2;
+                if jump_cwnd <= cwnd {
+                    self.phase = CarefulResumePhase::Normal;
+                    return;
+                }
+                self.phase = CarefulResumePhase::Unvalidated;
+                self.cwnd = jump_cwnd;
+                self.pipesize = cwnd;
+            }
+            CarefulResumePhase::Unvalidated => {
+                if self.is_acked(self.first_unvalidated_pkt) {
+                    self.enter_validating(bytes_in_flight);
+                }
+            }
+            CarefulResumePhase::Validating => {
+                // The capacity is validated, and the congestion controller
+                // continues with it.
+                if self.is_acked(self.last_unvalidated_pkt) {
+                    self.phase = CarefulResumePhase::Normal;
+                    congestion.on_careful_resume(now, self.pipesize);
+                }
+            }
+            CarefulResumePhase::SafeRetreat => {
+                if self.is_acked(self.last_unvalidated_pkt) {
+                    self.phase = CarefulResumePhase::Normal;
+                }
+            }
+            CarefulResumePhase::Normal => (),
+        }
+    }
+
+    /// Handle the congestion event detected by packet loss or ECN-CE marks.
+    pub(super) fn on_congestion_event(
+        &mut self,
+        congestion: &mut dyn CongestionController,
+        now: Instant,
+    ) {
+        match self.phase {
+            CarefulResumePhase::Reconnaissance => {
+                self.phase = CarefulResumePhase::Normal;
+            }
+            CarefulResumePhase::Unvalidated | CarefulResumePhase::Validating => {
+                if self.last_unvalidated_pkt.is_none() {
+                    self.last_unvalidated_pkt = self.largest_sent_pkt;
+                }
+                self.phase = CarefulResumePhase::SafeRetreat;
+                congestion.on_careful_resume(now, self.pipesize / 2);
+            }
+            _ => (),
+        }
+    }
+
+    fn enter_validating(&mut self, bytes_in_flight: u64) {
+        self.phase = CarefulResumePhase::Validating;
+        self.last_unvalidated_pkt = self.largest_sent_pkt;
+        self.cwnd = bytes_in_flight.max(self.pipesize);
+    }
+
+    fn is_acked(&self, pkt_num: Option<u64>) -> bool {
+        match (pkt_num, self.largest_acked_pkt) {
+            (Some(pkt_num), Some(acked)) => acked >= pkt_num,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::congestion_control::build_congestion_controller;
+    use crate::CongestionControlAlgorithm;
+    use crate::RecoveryConfig;
+
+    const MSS: u64 = 1200;
+
+    fn new_test_congestion_controller() -> Box<dyn CongestionController> {
+        let conf = RecoveryConfig {
+            max_datagram_size: MSS as usize,
+            congestion_control_algorithm: CongestionControlAlgorithm::Cubic,
+            initial_congestion_window: 10,
+            min_congestion_window: 2,
+            ..RecoveryConfig::default()
+        };
+        build_congestion_controller(&conf)
+    }
+
+    /// Send the packets in the range, and return the bytes in flight.
+    fn send(cr: &mut CarefulResume, pkts: std::ops::Range<u64>, mut inflight: u64) -> u64 {
+        for pkt_num in pkts {
+            inflight += MSS;
+            cr.on_packet_sent(pkt_num, inflight);
+        }
+        inflight
+    }
+
+    /// Acknowledge the packets in the range.
+    fn ack(cr: &mut CarefulResume, pkts: std::ops::Range<u64>) {
+        for pkt_num in pkts {
+            cr.on_packet_acked(pkt_num, MSS);
+        }
+    }
+
+    #[test]
+    fn careful_resume_disabled() {
+        let mut cc = new_test_congestion_controller();
+        let mut cr = CarefulResume::new();
+        cr.enable(0, Duration::from_millis(100));
+        assert_eq!(cr.phase, CarefulResumePhase::Normal);
+
+        send(&mut cr, 0..10, 0);
+        ack(&mut cr, 0..10);
+        cr.end_ack(
+            Some(Duration::from_millis(100)),
+            0,
+            cc.as_mut(),
+            Instant::now(),
+        );
+        assert_eq!(cr.phase, CarefulResumePhase::Normal);
+        assert_eq!(cr.congestion_window(), None);
+
+        // It can not be enabled after packets are sent.
+        cr.enable(100 * MSS, Duration::from_millis(100));
+        assert_eq!(cr.phase, CarefulResumePhase::Normal);
+    }
+
+    #[test]
+    fn careful_resume_validated() {
+        let mut cc = new_test_congestion_controller();
+        let mut cr = CarefulResume::new();
+        let rtt = Duration::from_millis(100);
+        let now = Instant::now();
+        cr.enable(100 * MSS, rtt);
+        assert_eq!(cr.phase, CarefulResumePhase::Reconnaissance);
+
+        // Jump after the initial window is acknowledged.
+        send(&mut cr, 0..10, 0);
+        ack(&mut cr, 0..5);
+        cr.end_ack(Some(rtt), 5 * MSS, cc.as_mut(), now);
+        assert_eq!(cr.phase, CarefulResumePhase::Reconnaissance);
+        ack(&mut cr, 5..10);
+        cr.end_ack(Some(rtt), 0, cc.as_mut(), now);
+        assert_eq!(cr.phase, CarefulResumePhase::Unvalidated);
+        assert_eq!(cr.congestion_window(), Some(50 * MSS));
+        assert_eq!(cr.pacing_rate(rtt), Some(50 * MSS * 10));
+
+        // All of the jump window is sent.
+        let inflight = send(&mut cr, 10..59, 0);
+        assert_eq!(cr.phase, CarefulResumePhase::Unvalidated);
+        send(&mut cr, 59..60, inflight);
+        assert_eq!(cr.phase, CarefulResumePhase::Validating);
+        assert_eq!(cr.congestion_window(), Some(50 * MSS));
+        assert_eq!(cr.pacing_rate(rtt), None);
+
+        // The data sent in the unvalidated phase is acknowledged.
+        ack(&mut cr, 10..60);
+        cr.end_ack(Some(rtt), 0, cc.as_mut(), now);
+        assert_eq!(cr.phase, CarefulResumePhase::Normal);
+        assert_eq!(cr.congestion_window(), None);
+        assert_eq!(cc.congestion_window(), 60 * MSS);
+        assert_eq!(cc.in_slow_start(), false);
+    }
+
+    #[test]
+    fn careful_resume_safe_retreat() {
+        let mut cc = new_test_congestion_controller();
+        let mut cr = CarefulResume::new();
+        let rtt = Duration::from_millis(100);
+        let now = Instant::now();
+        cr.enable(100 * MSS, rtt);
+
+        send(&mut cr, 0..10, 0);
+        ack(&mut cr, 0..10);
+        cr.end_ack(Some(rtt), 0, cc.as_mut(), now);
+        assert_eq!(cr.phase, CarefulResumePhase::Unvalidated);
+
+        // The first unvalidated packet is acknowledged.
+        send(&mut cr, 10..30, 0);
+        ack(&mut cr, 10..12);
+        cr.end_ack(Some(rtt), 18 * MSS, cc.as_mut(), now);
+        assert_eq!(cr.phase, CarefulResumePhase::Validating);
+        assert_eq!(cr.congestion_window(), Some(18 * MSS));
+
+        // Congestion is detected.
+        cr.on_congestion_event(cc.as_mut(), now);
+        assert_eq!(cr.phase, CarefulResumePhase::SafeRetreat);
+        assert_eq!(cr.congestion_window(), None);
+        assert_eq!(cc.congestion_window(), 6 * MSS);
+
+        ack(&mut cr, 12..30);
+        cr.end_ack(Some(rtt), 0, cc.as_mut(), now);
+        assert_eq!(cr.phase, CarefulResumePhase::Normal);
+    }
+
+    #[test]
+    fn careful_resume_path_changed() {
+        let rtt = Duration::from_millis(100);
+        let now = Instant::now();
+
+        for latest_rtt in [Duration::from_millis(49), Duration::from_millis(1001)] {
+            let mut cc = new_test_congestion_controller();
+            let mut cr = CarefulResume::new();
+            cr.enable(100 * MSS, rtt);
+            send(&mut cr, 0..1, 0);
+            ack(&mut cr, 0..1);
+            cr.end_ack(Some(latest_rtt), 0, cc.as_mut(), now);
+            assert_eq!(cr.phase, CarefulResumePhase::Normal);
+        }
+
+        // Congestion in the reconnaissance phase
+        let mut cc = new_test_congestion_controller();
+        let mut cr = CarefulResume::new();
+        cr.enable(100 * MSS, rtt);
+        send(&mut cr, 0..1, 0);
+        cr.on_congestion_event(cc.as_mut(), now);
+        assert_eq!(cr.phase, CarefulResumePhase::Normal);
+    }
+}
@@ -23,21 +23,27 @@ use std::cmp;
 use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::time;
 
 use bytes::Bytes;
+use bytes::BytesMut;
 use enumflags2::bitflags;
 use enumflags2::BitFlags;
 use log::*;
 use strum::IntoEnumIterator;
 
 use self::cid::ConnectionIdItem;
+use self::keep_alive::KeepAlive;
+use self::recovery::SendLimited;
 use self::space::BufferFlags;
 use self::space::BufferType;
 use self::space::PacketNumSpace;
 use self::space::RateSamplePacketState;
 use self::space::SpaceId;
+use self::stream::SendBuf;
 use self::stream::Stream;
+use self::stream::StreamIdHashMap;
 use self::stream::StreamIter;
 use self::timer::Timer;
 use self::ConnectionFlags::*;
@@ -54,17 +60,26 @@ use crate::packet::PacketHeader;
 use crate::packet::PacketType;
 use crate::qlog;
 use crate::qlog::events;
+use crate::ranges::RangeSet;
 use crate::tls;
 use crate::tls::Keys;
 use crate::tls::Level;
 use crate::tls::Open;
 use crate::tls::TlsSession;
 use crate::token::AddressToken;
+use crate::token::AddressTokenKey;
 use crate::token::ResetToken;
 use crate::trans_param::TransportParams;
+use crate::trans_param::VersionInformation;
+use crate::BlockedEvent;
 use crate::Config;
+use crate::CongestionControlAlgorithm;
+use crate::CongestionControlSelector;
+use crate::CongestionStats;
 use crate::ConnectionId;
 use crate::ConnectionQueues;
+use crate::Ecn;
+use crate::EcnPolicy;
 use crate::Event;
 use crate::EventQueue;
 use crate::FourTuple;
@@ -73,15 +88,26 @@ use crate::MultipathConfig;
 use crate::PacketInfo;
 use crate::PathEvent;
 use crate::PathStats;
+use crate::PersistentCongestionEvent;
+use crate::PtoProbeStrategy;
 use crate::RecoveryConfig;
+use crate::RecvWindowBudget;
 use crate::Result;
 use crate::Shutdown;
+use crate::StreamLossPolicy;
+
+/// The duration in seconds for which the path capacity shared in BDP_FRAME is
+/// valid.
+const BDP_LIFETIME: u64 = 3600;
 
 /// A QUIC connection.
 pub struct Connection {
     /// QUIC version used for the connection.
     version: u32,
 
+    /// Acceptable QUIC versions in the order of preference.
+    versions: Vec<u32>,
+
     /// Whether this is a server connection.
     is_server: bool,
 
@@ -113,15 +139,27 @@ pub struct Connection {
     /// Raw packets that were received before decryption keys are available.
     undecryptable_packets: UndecryptablePackets,
 
-    /// Peer transport parameters.
-    peer_transport_params: TransportParams,
+    /// Packets written into the outgoing datagram whose protection is pending.
+    pending_packets: Vec<packet::PendingPacket>,
+
+    /// A datagram written for a GSO batch whose release time or codepoints
+    /// differ from the batch. It is sent first in the next batch.
+    held_datagram: Option<(usize, Vec<u8>, PacketInfo)>,
+
+    /// Peer transport parameters. They are boxed since they are rarely used
+    /// on the hot path, which keeps the connection struct small.
+    peer_transport_params: Box<TransportParams>,
 
     /// Local transport parameters.
-    local_transport_params: TransportParams,
+    local_transport_params: Box<TransportParams>,
 
     /// Recovery and congestion control configurations.
     recovery_conf: RecoveryConfig,
 
+    /// Selector of the congestion control algorithm, which is consulted once
+    /// by the server.
+    congestion_control_selector: Option<Arc<dyn CongestionControlSelector>>,
+
     /// Error to be sent to the peer in a CONNECTION_CLOSE frame.
     local_error: Option<ConnectionError>,
 
@@ -147,6 +185,55 @@ pub struct Connection {
     /// For server, it is the resume address token to issue to the client.
     token: Option<Vec<u8>>,
 
+    /// The key for issuing address tokens. Applicable to Server only.
+    address_token_key: Option<AddressTokenKey>,
+
+    /// The path capacity shared by the server in BDP_FRAME.
+    peer_bdp: Option<BdpInfo>,
+
+    /// The start time and the bytes read by the application of the current
+    /// receive rate sample.
+    rx_rate_sample: Option<(time::Instant, u64)>,
+
+    /// The maximum receive rate observed, in bytes per second.
+    max_rx_rate: u64,
+
+    /// The sequence number of the next OBSERVED_ADDRESS frame to send.
+    observed_addr_seq: u64,
+
+    /// The largest sequence number of the received OBSERVED_ADDRESS frames.
+    max_observed_addr_seq: Option<u64>,
+
+    /// The sequence number of the next ACK_FREQUENCY frame to send.
+    ack_frequency_seq: u64,
+
+    /// The acknowledgement behavior requested by the peer in the received
+    /// ACK_FREQUENCY frame with the largest sequence number.
+    peer_ack_frequency: Option<AckFrequency>,
+
+    /// Keep-alive of the connection.
+    keep_alive: KeepAlive,
+
+    /// Tags of the application probes to be sent.
+    ping_tags: VecDeque<u64>,
+
+    /// The connection-level flow control limit at which the send-side was
+    /// blocked, and the time when a DATA_BLOCKED frame was first sent.
+    data_blocked: Option<(u64, time::Instant)>,
+
+    /// The stream-level flow control limits at which the streams were
+    /// blocked, and the time when a STREAM_DATA_BLOCKED frame was first sent.
+    stream_data_blocked: StreamIdHashMap<(u64, time::Instant)>,
+
+    /// Whether the latency spin bit is used on the connection.
+    spin_enabled: bool,
+
+    /// The DSCP codepoint of the outgoing packets.
+    dscp: u8,
+
+    /// The average interval in packets between the skipped packet numbers.
+    pkt_num_skip_interval: u64,
+
     /// Internal Identifier of connection on the Endpoint.
     index: Option<u64>,
 
@@ -156,6 +243,12 @@ pub struct Connection {
     /// Status observed by the endpoint.
     queues: Option<Rc<RefCell<ConnectionQueues>>>,
 
+    /// Receive window budget shared by the endpoint and the connection.
+    recv_budget: Option<Rc<RefCell<RecvWindowBudget>>>,
+
+    /// Receive window of the connection reserved from the budget.
+    recv_budget_reserved: u64,
+
     /// User context for the connection.
     context: Option<Box<dyn Any + Send + Sync>>,
 
@@ -238,6 +331,13 @@ impl Connection {
             stream::StreamTransportParams::from(&conf.local_transport_params),
         );
         streams.set_trace_id(&trace_id);
+        streams.set_window_decay_rtts(conf.window_decay_rtts);
+        streams.set_window_decay_floor(conf.window_decay_floor);
+        streams.set_window_tuner(conf.window_tuner());
+        streams.set_scheduler(conf.stream_scheduler());
+        streams.set_window_sharing(conf.stream_window_sharing);
+        streams.set_max_concurrent_streams(true, conf.max_concurrent_streams_bidi);
+        streams.set_max_concurrent_streams(false, conf.max_concurrent_streams_uni);
 
         let mut tls_session = conf.new_tls_session(server_name, is_server)?;
         if let Some(tls_config_selector) = &conf.tls_config_selector {
@@ -246,7 +346,8 @@ impl Connection {
         tls_session.set_trace_id(&trace_id);
 
         let mut conn = Connection {
-            version: crate::QUIC_VERSION_V1,
+            version: conf.versions[0],
+            versions: conf.versions.clone(),
             is_server,
             cids,
             spaces: space::PacketNumSpaceMap::new(),
@@ -255,22 +356,53 @@ impl Connection {
             multipath_conf: conf.multipath.clone(),
             streams,
             tls_session,
-            crypto_streams: Rc::new(RefCell::new(CryptoStreams::new())),
+            crypto_streams: Rc::new(RefCell::new(CryptoStreams::new(
+                conf.max_crypto_buffer_size,
+            ))),
             undecryptable_packets: UndecryptablePackets::new(conf.max_undecryptable_packets),
-            peer_transport_params: TransportParams::default(),
-            local_transport_params: conf.local_transport_params.clone(),
+            pending_packets: Vec::new(),
+            held_datagram: None,
+            peer_transport_params: Box::default(),
+            local_transport_params: Box::new(conf.local_transport_params.clone()),
             recovery_conf: conf.recovery.clone(),
+            congestion_control_selector: if is_server {
+                conf.congestion_control_selector.clone()
+            } else {
+                None
+            },
             local_error: None,
             peer_error: None,
-            timers: timer::TimerTable::default(),
+            timers: timer::TimerTable::new(conf.timer_granularity),
             flags: BitFlags::default(),
             stats: ConnectionStats::default(),
             odcid: None,
             rscid: None,
             token: None,
+            address_token_key: None,
+            peer_bdp: None,
+            rx_rate_sample: None,
+            max_rx_rate: 0,
+            observed_addr_seq: 0,
+            max_observed_addr_seq: None,
+            ack_frequency_seq: 0,
+            peer_ack_frequency: None,
+            keep_alive: KeepAlive::new(
+                conf.keep_alive_interval,
+                conf.adaptive_keep_alive && !is_server,
+            ),
+            ping_tags: VecDeque::new(),
+            data_blocked: None,
+            stream_data_blocked: StreamIdHashMap::default(),
+            // The spin bit is disabled for a random selection of at least one
+            // in every 16 connections. See RFC 9000 Section 17.4
+            spin_enabled: conf.spin_bit && rand::random::<u8>() % 16 != 0,
+            dscp: conf.dscp,
+            pkt_num_skip_interval: conf.pkt_num_skip_interval,
             index: None,
             events: EventQueue::default(),
             queues: None,
+            recv_budget: None,
+            recv_budget_reserved: 0,
             context: None,
             qlog: None,
             trace_id,
@@ -319,23 +451,136 @@ impl Connection {
             if let Ok(token) = token.encode(&conf.address_token_key[0]) {
                 conn.token = Some(token);
             }
+            conn.address_token_key = Some(conf.address_token_key[0].clone());
+        }
+
+        // Jump-start the congestion window with the path capacity saved in
+        // the address token of a previous connection.
+        if let Some((capacity, rtt)) = addr_token.and_then(|t| t.capacity) {
+            if is_server && conf.recovery.careful_resume {
+                conn.paths
+                    .get_mut(active_pid)?
+                    .recovery
+                    .enable_careful_resume(capacity, rtt);
+            }
         }
 
         Ok(conn)
     }
 
-    /// Configure the given session data for resumption.
-    pub fn set_session(&mut self, mut buf: &[u8]) -> Result<()> {
+    /// Configure the given session data for resumption and 0-RTT.
+    ///
+    /// The session data should be the one returned by `session()` of a previous
+    /// connection to the same server. If it could not be used by the current
+    /// connection, for example it is malformed or was negotiated with another
+    /// QUIC version, it is ignored and a full handshake will be performed.
+    /// Applicable to Client only.
+    pub fn set_session(&mut self, buf: &[u8]) -> Result<()> {
+        if self.is_server {
+            return Err(Error::InvalidOperation("not a client".into()));
+        }
+
+        let (session_bytes, peer_params, bdp) = match self.decode_session(buf) {
+            Ok(v) => v,
+            Err(e) => {
+                debug!("{} ignore session data: {:?}", self.trace_id, e);
+                return Ok(());
+            }
+        };
+        if let Err(e) = self.tls_session.set_session(&session_bytes) {
+            debug!("{} ignore session data: {:?}", self.trace_id, e);
+            return Ok(());
+        }
+
+        // Avoid slow window ramp-up by using the BDP estimate of the previous
+        // connection to the same peer.
+        if let Some(bdp) = bdp {
+            debug!("{} seed receive windows with {:?}", self.trace_id, bdp);
+            self.streams.seed_window(bdp.seed_window());
+        }
+        self.set_peer_trans_params(peer_params)
+    }
+
+    /// Decode and validate the session data for resumption.
+    ///
+    /// The session data is encoded as follows:
+    ///
+    /// ```text
+    /// Session Data {
+    ///   Format Version (8),
+    ///   Version (32),
+    ///   Application Protocol Length (64),
+    ///   Application Protocol (..),
+    ///   TLS Session Length (64),
+    ///   TLS Session (..),
+    ///   Transport Parameters Length (64),
+    ///   Transport Parameters (..),
+    ///   [BDP Estimate (192)],
+    /// }
+    ///
+    /// BDP Estimate {
+    ///   Window (64),
+    ///   RTT in microseconds (64),
+    ///   Bandwidth in bytes per second (64),
+    /// }
+    /// ```
+    fn decode_session(
+        &self,
+        mut buf: &[u8],
+    ) -> Result<(Vec<u8>, TransportParams, Option<BdpEstimate>)> {
+        // The session data saved by a release with another encoding is ignored.
+        let format_version = buf.read_u8()?;
+        if format_version != tls::SESSION_FORMAT_VERSION {
+            return Err(Error::InvalidState("unsupported session format".into()));
+        }
+
+        // The session ticket and the 0-RTT keys are bound to the QUIC version.
+        let version = buf.read_u32()?;
+        if version != self.version {
+            return Err(Error::InvalidState("version mismatch".into()));
+        }
+
+        // The application protocol is checked by the TLS stack when deciding
+        // whether to accept early data, and an empty one is never negotiated.
+        let alpn_len = buf.read_u64()? as usize;
+        let alpn = buf.read(alpn_len)?;
+        if alpn.is_empty() {
+            return Err(Error::InvalidState("application protocol empty".into()));
+        }
+
         let session_len = buf.read_u64()? as usize;
         let session_bytes = buf.read(session_len)?;
-        self.tls_session.set_session(&session_bytes)?;
 
         let params_len = buf.read_u64()? as usize;
         let params_bytes = buf.read(params_len)?;
-        let (peer_params, _) = TransportParams::decode(&params_bytes, self.is_server)?;
-        self.set_peer_trans_params(peer_params)?;
+        let (mut peer_params, _) = TransportParams::decode(&params_bytes, self.is_server)?;
+
+        // The BDP estimate is optional, see `session_with_bdp()`.
+        let bdp = match buf.is_empty() {
+            true => None,
+            false => Some(BdpEstimate {
+                window: buf.read_u64()?,
+                rtt: time::Duration::from_micros(buf.read_u64()?),
+                bandwidth: buf.read_u64()?,
+            }),
+        };
+        if !buf.is_empty() {
+            return Err(Error::InvalidState("trailing session data".into()));
+        }
 
-        Ok(())
+        // The client MUST NOT use remembered values for the following
+        // parameters, and the values from the current handshake are used
+        // instead. See RFC 9000 Section 7.4.1
+        let default_params = TransportParams::default();
+        peer_params.ack_delay_exponent = default_params.ack_delay_exponent;
+        peer_params.max_ack_delay = default_params.max_ack_delay;
+        peer_params.initial_source_connection_id = None;
+        peer_params.original_destination_connection_id = None;
+        peer_params.preferred_address = None;
+        peer_params.retry_source_connection_id = None;
+        peer_params.stateless_reset_token = None;
+
+        Ok((session_bytes, peer_params, bdp))
     }
 
     /// Set address token used by the client connection.
@@ -388,6 +633,11 @@ impl Connection {
             self.tls_session.cipher(),
         );
 
+        // Write RecoveryParametersSet event to qlog
+        if let Ok(path) = self.paths.get_active() {
+            path.recovery.qlog_recovery_parameters_set(&mut writer);
+        }
+
         self.qlog = Some(writer);
     }
 
@@ -419,19 +669,25 @@ impl Connection {
             self.paths.inc_anti_ampl_limit(pid, len);
         }
 
-        // Process each QUIC packet in the UDP datagram
-        let mut left = len;
-        while left > 0 {
-            let read = match self.recv_packet(&mut buf[(len - left)..len], info, pid) {
+        // Process each QUIC packet in the UDP datagram. The datagram is copied
+        // into a shared buffer only once, and the packets are decrypted in
+        // place so that the frames could reference the plaintext directly.
+        let mut dgram = BytesMut::from(&buf[..]);
+        while !dgram.is_empty() {
+            let left = dgram.len();
+            let read = match self.recv_packet(&mut dgram, info, pid) {
                 Ok(s) => s,
-                Err(Error::Done) => left, // stop and skip the remaining data
+                Err(Error::Done) => break, // stop and skip the remaining data
                 Err(e) => {
                     self.close(false, e.to_wire(), b"").ok(); // close connection
                     info!("{} recv error and close {:?}", self.trace_id, e);
                     return Err(e);
                 }
             };
-            left -= read;
+
+            // Skip the packet if it was not split from the datagram.
+            let consumed = left - dgram.len();
+            let _ = dgram.split_to(read - consumed);
         }
 
         // Try to process undecryptable packets
@@ -439,13 +695,15 @@ impl Connection {
             self.try_process_undecryptable_packets();
         }
 
-        Ok(len - left)
+        Ok(len)
     }
 
-    /// Process an incoming QUIC packet from the peer.
+    /// Process an incoming QUIC packet at the beginning of `buf` and return
+    /// the length of the packet. The packet may be split from `buf` if its
+    /// payload is referenced by the frames.
     fn recv_packet(
         &mut self,
-        buf: &mut [u8],
+        buf: &mut BytesMut,
         info: &PacketInfo,
         pid: Option<usize>,
     ) -> Result<usize> {
@@ -462,6 +720,8 @@ impl Connection {
         // Parse header of the QUIC packet
         let (mut hdr, mut read) =
             PacketHeader::from_bytes(buf, self.scid()?.len()).map_err(|_| Error::Done)?;
+        // The spin bit is not protected by the header protection.
+        let spin_bit = buf[0] & packet::HEADER_SPIN_BIT != 0;
 
         // Process Version Negotiation packet
         if hdr.pkt_type == PacketType::VersionNegotiation {
@@ -475,11 +735,13 @@ impl Connection {
 
         // Check version of packet
         if self.is_server && !self.flags.contains(DidVersionNegotiation) {
-            if !crate::version_is_supported(hdr.version) {
+            if !self.versions.contains(&hdr.version) {
                 return Err(Error::UnknownVersion);
             }
             self.version = hdr.version;
             self.flags.insert(DidVersionNegotiation);
+            // Update the chosen version in the version_information.
+            self.set_transport_params()?;
         }
         if hdr.pkt_type != PacketType::OneRTT && hdr.version != self.version {
             return Err(Error::Done);
@@ -566,19 +828,26 @@ impl Connection {
             &hdr,
             space,
         )?;
-        let mut payload = if !is_encryption_disabled {
+        let plaintext_len = if !is_encryption_disabled {
             packet::decrypt_payload(buf, payload_offset, payload_len, cid_seq, pkt_num, key)
                 .map_err(|_| Error::Done)?
         } else {
-            bytes::Bytes::copy_from_slice(&buf[payload_offset..payload_offset + payload_len])
+            payload_len
         };
-        if payload.is_empty() {
+        if plaintext_len == 0 {
             // An endpoint MUST treat receipt of a packet containing no frames as a connection error
             // of type PROTOCOL_VIOLATION.
             return Err(Error::ProtocolViolation);
         }
         read += length;
 
+        // Split the decrypted packet from the datagram without copying, and
+        // the data of STREAM and CRYPTO frames are slices of the payload.
+        let mut payload = buf
+            .split_to(read)
+            .freeze()
+            .slice(payload_offset..payload_offset + plaintext_len);
+
         debug!(
             "{} recv packet {:?} pn={} {:?}",
             self.trace_id,
@@ -633,12 +902,25 @@ impl Connection {
         }
 
         // Process acknowledged frames.
-        self.try_process_acked_frames();
+        self.try_process_acked_frames(info.time);
 
         // The peer may issue new connection ids. If there is any path waiting
         // for a dcid, try to allocate one for it.
         self.try_allocate_cids_from_peer();
 
+        // The server sets the spin value of the path to the spin bit of the
+        // 1-RTT packet with the largest packet number received from the
+        // client, and the client sets it to the inverse of that received from
+        // the server.
+        // See RFC 9000 Section 17.4
+        if hdr.pkt_type == PacketType::OneRTT {
+            let path = self.paths.get_mut(pid)?;
+            if path.spin_pkt_num.map_or(true, |v| pkt_num > v) {
+                path.spin_pkt_num = Some(pkt_num);
+                path.spin_value = spin_bit == self.is_server;
+            }
+        }
+
         // Update packet number space
         let space = self.spaces.get_mut(space_id).ok_or(Error::InternalError)?;
         if space.recv_pkt_num_need_ack.max() < Some(pkt_num) {
@@ -656,8 +938,9 @@ impl Connection {
             space.largest_rx_ack_eliciting_pkt_num =
                 cmp::max(space.largest_rx_ack_eliciting_pkt_num, pkt_num);
         }
+        space.recv_ecn_counts.on_packet_received(info.ecn);
 
-        self.try_schedule_ack_frame(space_id, pkt_num, ack_eliciting_pkt)?;
+        self.try_schedule_ack_frame(space_id, pkt_num, ack_eliciting_pkt, info.ecn)?;
 
         // An endpoint restarts its idle timer when a packet from its peer is
         // received and processed successfully.
@@ -665,6 +948,9 @@ impl Connection {
         if let Some(idle_timeout) = self.idle_timeout() {
             self.timers.set(Timer::Idle, now + idle_timeout);
         }
+        if self.is_established() {
+            self.keep_alive.on_activity(now);
+        }
 
         // Update statistic metrics
         self.stats.recv_count += 1;
@@ -714,7 +1000,7 @@ impl Connection {
             Frame::Ack {
                 ack_delay,
                 ack_ranges,
-                ..
+                ecn_counts,
             } => {
                 // ACK Delay is decoded by multiplying the value in the field
                 // by 2 to the power of the ack_delay_exponent transport
@@ -727,6 +1013,28 @@ impl Connection {
                 if space_id == SpaceId::Handshake {
                     self.flags.insert(PeerVerifiedInitialAddress);
                 }
+
+                // An endpoint skips packet numbers on purpose to detect the
+                // peer acknowledging packets it has not received.
+                // See RFC 9000 Section 21.4
+                let space = self.spaces.get_mut(space_id).ok_or(Error::InternalError)?;
+                space.latest_ack_delay = time::Duration::from_micros(ack_delay);
+                if let Some(pkt_num) = space.skipped_pkt_num {
+                    if ack_ranges.contains(pkt_num) {
+                        warn!(
+                            "{} optimistic ack detected, pkt_num {} was skipped",
+                            self.trace_id, pkt_num
+                        );
+                        if let Some(qlog) = &mut self.qlog {
+                            let ev_data = events::EventData::SecurityOptimisticAckDetected {
+                                packet_number: pkt_num,
+                            };
+                            qlog.add_event_data(now, ev_data).ok();
+                        }
+                        return Err(Error::ProtocolViolation);
+                    }
+                }
+
                 if space_id == SpaceId::Data && self.is_established() {
                     self.flags.insert(PeerVerifiedInitialAddress);
                     // A client MAY consider the handshake to be confirmed when
@@ -747,12 +1055,20 @@ impl Connection {
                 let (lost_pkts, lost_bytes) = path.recovery.on_ack_received(
                     &ack_ranges,
                     ack_delay,
+                    ecn_counts.as_ref(),
                     space_id,
                     &mut self.spaces,
                     handshake_status,
                     self.qlog.as_mut(),
                     now,
                 )?;
+                if let Some(bitrate) = path.recovery.target_bitrate_updated() {
+                    self.events.add(Event::TargetBitrateUpdated(bitrate));
+                }
+                if let Some(event) = Self::persistent_congestion_event(path) {
+                    self.events.add(event);
+                }
+                self.paths.update_coupled_congestion();
                 self.stats.lost_count += lost_pkts;
                 self.stats.lost_bytes += lost_bytes;
 
@@ -773,8 +1089,7 @@ impl Connection {
                     // self.tls_session.read(). Do NOT mutably borrrow it again at the
                     // same scope.
                     let mut crypto_streams = self.crypto_streams.borrow_mut();
-                    let crypto_stream = crypto_streams.get_mut(level)?;
-                    crypto_stream.recv.write(offset, data, false)?;
+                    crypto_streams.recv(level, offset, data)?;
                 }
 
                 // Read crypto data in order and feed it to the TLS session
@@ -794,6 +1109,77 @@ impl Connection {
                 }
             }
 
+            Frame::Bdp {
+                lifetime,
+                saved_capacity,
+                saved_rtt,
+            } => {
+                if self.is_server || !self.flags.contains(EnableBdp) {
+                    return Err(Error::ProtocolViolation);
+                }
+                self.peer_bdp = Some(BdpInfo {
+                    lifetime: time::Duration::from_secs(lifetime),
+                    saved_capacity,
+                    saved_rtt: time::Duration::from_micros(saved_rtt),
+                });
+            }
+
+            Frame::ObservedAddress { seq_num, addr } => {
+                if !self.flags.contains(RecvObservedAddress) {
+                    return Err(Error::ProtocolViolation);
+                }
+
+                // Frames with a sequence number lower than the largest one
+                // received are stale and ignored.
+                if self.max_observed_addr_seq.map_or(true, |v| seq_num > v) {
+                    self.max_observed_addr_seq = Some(seq_num);
+                    let path = self.paths.get_mut(path_id)?;
+                    // A changed address indicates that the NAT binding of the
+                    // path has been lost.
+                    if path.observed_addr.is_some_and(|v| v != addr) {
+                        self.keep_alive.on_rebinding_detected();
+                    }
+                    path.observed_addr = Some(addr);
+                }
+            }
+
+            Frame::AckFrequency {
+                seq_num,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            } => {
+                // The requested max_ack_delay MUST NOT be less than the
+                // min_ack_delay advertised by the endpoint.
+                match self.local_transport_params.min_ack_delay {
+                    Some(v) if request_max_ack_delay >= v => (),
+                    _ => return Err(Error::ProtocolViolation),
+                }
+
+                // Frames with a sequence number not larger than the largest
+                // one received are stale and ignored.
+                if self
+                    .peer_ack_frequency
+                    .map_or(true, |v| seq_num > v.seq_num)
+                {
+                    self.peer_ack_frequency = Some(AckFrequency {
+                        seq_num,
+                        ack_eliciting_threshold,
+                        max_ack_delay: time::Duration::from_micros(request_max_ack_delay),
+                        reordering_threshold,
+                    });
+                }
+            }
+
+            Frame::ImmediateAck => {
+                if self.local_transport_params.min_ack_delay.is_none() {
+                    return Err(Error::ProtocolViolation);
+                }
+                let space = self.spaces.get_mut(space_id).ok_or(Error::InternalError)?;
+                space.need_send_ack = true;
+                space.ack_timer = None;
+            }
+
             Frame::HandshakeDone => {
                 if self.is_server {
                     return Err(Error::ProtocolViolation);
@@ -869,6 +1255,9 @@ impl Connection {
             }
 
             Frame::PathResponse { data } => {
+                if self.keep_alive.on_path_resp_received(data) {
+                    return Ok(());
+                }
                 if self.paths.on_path_resp_received(path_id, data) {
                     // Notify the path event to the multipath scheduler
                     if let Some(ref mut scheduler) = self.multipath_scheduler {
@@ -951,11 +1340,31 @@ impl Connection {
 
             Frame::MaxData { max } => {
                 self.streams.on_max_data_frame_received(max);
+                self.stats.max_data_recv_count += 1;
+
+                // The send-side is unblocked by the new limit.
+                if let Some((limit, since)) = self.data_blocked {
+                    if max > limit {
+                        let duration = now.saturating_duration_since(since);
+                        self.stats.data_blocked_duration += duration.as_micros() as u64;
+                        self.data_blocked = None;
+                    }
+                }
             }
 
             Frame::MaxStreamData { stream_id, max } => {
                 self.streams
                     .on_max_stream_data_frame_received(stream_id, max)?;
+                self.stats.max_stream_data_recv_count += 1;
+
+                // The stream is unblocked by the new limit.
+                if let Some(&(limit, since)) = self.stream_data_blocked.get(&stream_id) {
+                    if max > limit {
+                        let duration = now.saturating_duration_since(since);
+                        self.stats.stream_data_blocked_duration += duration.as_micros() as u64;
+                        self.stream_data_blocked.remove(&stream_id);
+                    }
+                }
             }
 
             Frame::MaxStreams { bidi, max } => {
@@ -964,11 +1373,18 @@ impl Connection {
 
             Frame::DataBlocked { max } => {
                 self.streams.on_data_blocked_frame_received(max);
+                let event = BlockedEvent::PeerDataBlocked { limit: max };
+                self.events.add(Event::FlowControlBlocked(event));
             }
 
             Frame::StreamDataBlocked { stream_id, max } => {
                 self.streams
                     .on_stream_data_blocked_frame_received(stream_id, max)?;
+                let event = BlockedEvent::PeerStreamDataBlocked {
+                    stream_id,
+                    limit: max,
+                };
+                self.events.add(Event::FlowControlBlocked(event));
             }
 
             Frame::StreamsBlocked { bidi, max } => {
@@ -1014,24 +1430,24 @@ impl Connection {
             return Err(Error::Done);
         }
 
-        let mut found_version = 0;
+        let mut server_versions = Vec::new();
         while !payload.is_empty() {
             let version = payload.read_u32().map_err(|_| Error::Done)?;
-            if crate::version_is_supported(version) {
-                found_version = cmp::max(found_version, version);
-            }
-        }
-
-        if found_version == 0 {
-            return Err(Error::UnknownVersion);
+            server_versions.push(version);
         }
 
         // A client MUST discard a Version Negotiation packet that lists the
         // QUIC version selected by the client.
-        if found_version == self.version {
+        if server_versions.contains(&self.version) {
             return Err(Error::Done);
         }
 
+        // Select the most preferred version which is supported by the server.
+        let found_version = match self.choose_version(&server_versions) {
+            Some(version) => version,
+            None => return Err(Error::UnknownVersion),
+        };
+
         self.version = found_version;
         self.flags.insert(DidVersionNegotiation);
         self.flags.remove(GotPeerCid);
@@ -1170,8 +1586,44 @@ impl Connection {
         Ok(())
     }
 
+    /// Choose the most preferred version which is also supported by the peer.
+    fn choose_version(&self, peer_versions: &[u32]) -> Option<u32> {
+        self.versions
+            .iter()
+            .find(|v| peer_versions.contains(v))
+            .copied()
+    }
+
+    /// Validate the version_information transport parameter advertised by the
+    /// peer. See RFC 9368 Section 4
+    fn validate_version_information(&self, peer_params: &TransportParams) -> Result<()> {
+        // The client MUST validate the version information if it has reacted
+        // to a Version Negotiation packet, otherwise the version may have been
+        // downgraded by an attacker.
+        let reacted_to_vn = !self.is_server && self.flags.contains(DidVersionNegotiation);
+        let info = match peer_params.version_information {
+            Some(ref info) => info,
+            None if reacted_to_vn => return Err(Error::VersionNegotiationError),
+            None => return Ok(()),
+        };
+
+        // The Chosen Version must match the version in use.
+        if info.chosen_version != self.version {
+            return Err(Error::VersionNegotiationError);
+        }
+
+        // The version in use must be the one the client would have chosen
+        // given the Available Versions of the server.
+        if reacted_to_vn && self.choose_version(&info.available_versions) != Some(self.version) {
+            return Err(Error::VersionNegotiationError);
+        }
+        Ok(())
+    }
+
     /// Validate and apply transport parameters advertised by the peer.
     fn process_peer_trans_params(&mut self, peer_params: TransportParams) -> Result<()> {
+        self.validate_version_information(&peer_params)?;
+
         // Validate cid related transport parameters
         if peer_params.initial_source_connection_id != Some(self.dcid()?) {
             return Err(Error::TransportParameterError);
@@ -1201,9 +1653,53 @@ impl Connection {
             );
         }
 
+        // The peer accepts packets with the QUIC Bit set to 0
+        if peer_params.grease_quic_bit {
+            self.flags.insert(GreaseQuicBit);
+        }
+
+        // The BDP_FRAME extension is successfully negotiated
+        if peer_params.enable_bdp && self.local_transport_params.enable_bdp {
+            self.flags.insert(EnableBdp);
+        }
+
+        // The peer supports the ACK frequency extension, and could be requested
+        // to acknowledge less frequently.
+        if peer_params.min_ack_delay.is_some() {
+            self.flags.insert(EnableAckFrequency);
+            if self.recovery_conf.ack_frequency.is_some() {
+                self.flags.insert(NeedSendAckFrequency);
+            }
+        }
+
+        // The address discovery extension is negotiated. The value 0 means
+        // providing observations only, 1 means receiving observations only.
+        if let (Some(local), Some(peer)) = (
+            self.local_transport_params.address_discovery,
+            peer_params.address_discovery,
+        ) {
+            if local != 1 && peer != 0 {
+                self.flags.insert(SendObservedAddress);
+            }
+            if local != 0 && peer != 1 {
+                self.flags.insert(RecvObservedAddress);
+            }
+        }
+
         self.set_peer_trans_params(peer_params)?;
         self.flags.insert(AppliedPeerTransportParams);
 
+        // The keep-alive interval should be well below the idle timeout.
+        if let Some(idle_timeout) = self.idle_timeout() {
+            self.keep_alive.limit(idle_timeout / 2);
+        }
+
+        // The expiry of the NAT binding is detected from the addresses
+        // observed by the peer.
+        if !self.flags.contains(RecvObservedAddress) {
+            self.keep_alive.disable_search();
+        }
+
         // Write TransportParametersSet event to qlog.
         if let Some(qlog) = &mut self.qlog {
             Self::qlog_quic_params_set(
@@ -1214,6 +1710,36 @@ impl Connection {
             );
         }
 
+        // The server name and application protocol are known as soon as the
+        // ClientHello is processed.
+        if self.is_server {
+            self.select_congestion_control()?;
+        }
+
+        Ok(())
+    }
+
+    /// Select the congestion control algorithm for the server connection
+    /// using the selector.
+    fn select_congestion_control(&mut self) -> Result<()> {
+        let selector = match self.congestion_control_selector.take() {
+            Some(selector) => selector,
+            None => return Ok(()),
+        };
+
+        let peer_addr = self.paths.get_active()?.remote_addr();
+        let mut conf = self.recovery_conf.clone();
+        selector.select(
+            peer_addr,
+            self.tls_session.server_name(),
+            self.tls_session.alpn_protocol(),
+            &mut conf,
+        );
+
+        for (_, path) in self.paths.iter_mut() {
+            path.recovery.set_congestion_controller(&conf);
+        }
+        self.recovery_conf = conf;
         Ok(())
     }
 
@@ -1239,7 +1765,7 @@ impl Connection {
 
         self.cids.set_scid_limit(peer_params.active_conn_id_limit);
 
-        self.peer_transport_params = peer_params;
+        self.peer_transport_params = Box::new(peer_params);
         Ok(())
     }
 
@@ -1332,6 +1858,7 @@ impl Connection {
         space_id: SpaceId,
         pkt_num: u64,
         ack_eliciting: bool,
+        ecn: Ecn,
     ) -> Result<()> {
         if !ack_eliciting {
             return Ok(());
@@ -1349,10 +1876,23 @@ impl Connection {
             return Ok(());
         }
 
+        // An endpoint SHOULD immediately acknowledge all ack-eliciting packets
+        // marked with ECN-CE, so that the peer can react to the congestion
+        // promptly. See RFC 9000 Section 13.2.1
+        if ecn == Ecn::Ce {
+            space.need_send_ack = true;
+            space.ack_timer = None;
+            return Ok(());
+        }
+
         // A receiver SHOULD send an ACK frame after receiving at least two
-        // ack-eliciting packets.
+        // ack-eliciting packets, unless another threshold is requested by the
+        // peer in an ACK_FREQUENCY frame.
         space.ack_eliciting_pkts_since_last_sent_ack += 1;
-        let ack_eliciting_threshold = self.recovery_conf.ack_eliciting_threshold;
+        let ack_eliciting_threshold = match self.peer_ack_frequency {
+            Some(v) => v.ack_eliciting_threshold.saturating_add(1),
+            None => self.recovery_conf.ack_eliciting_threshold,
+        };
         if space.ack_eliciting_pkts_since_last_sent_ack >= ack_eliciting_threshold {
             space.need_send_ack = true;
             space.ack_timer = None;
@@ -1367,8 +1907,18 @@ impl Connection {
         // - when the packet has a packet number larger than the highest-numbered
         // ack-eliciting packet that has been received and there are missing
         // packets between that packet and this packet.
-        if pkt_num < space.largest_rx_ack_eliciting_pkt_num
-            || pkt_num > space.largest_rx_ack_eliciting_pkt_num + 1
+        // The peer may request to tolerate more reordering, or not to send
+        // ACK frames immediately for out-of-order packets with a reordering
+        // threshold of 0 in an ACK_FREQUENCY frame.
+        let reordering_threshold = self
+            .peer_ack_frequency
+            .map_or(1, |v| v.reordering_threshold);
+        if reordering_threshold > 0
+            && (pkt_num < space.largest_rx_ack_eliciting_pkt_num
+                || pkt_num
+                    > space
+                        .largest_rx_ack_eliciting_pkt_num
+                        .saturating_add(reordering_threshold))
         {
             space.need_send_ack = true;
             space.ack_timer = None;
@@ -1378,7 +1928,10 @@ impl Connection {
         // All ack-eliciting 0-RTT and 1-RTT packets within its advertised
         // max_ack_delay.
         if space.ack_timer.is_none() {
-            let ack_delay = time::Duration::from_millis(self.peer_transport_params.max_ack_delay);
+            let ack_delay = match self.peer_ack_frequency {
+                Some(v) => v.max_ack_delay,
+                None => time::Duration::from_millis(self.peer_transport_params.max_ack_delay),
+            };
             space.ack_timer = Some(time::Instant::now() + ack_delay);
             debug!(
                 "{} set ack timer for space {:?}, timeout {:?} ",
@@ -1388,8 +1941,40 @@ impl Connection {
         Ok(())
     }
 
+    /// Continue processing the ACK ranges carried over from large ACK frames.
+    fn process_pending_acks(&mut self, now: time::Instant) -> Result<()> {
+        let handshake_status = self.handshake_status();
+        let mut processed = false;
+        for (_, path) in self.paths.iter_mut() {
+            if !path.recovery.has_pending_acks() {
+                continue;
+            }
+            let (lost_pkts, lost_bytes) = path.recovery.on_pending_acks(
+                &mut self.spaces,
+                handshake_status,
+                self.qlog.as_mut(),
+                now,
+            )?;
+            self.stats.lost_count += lost_pkts;
+            self.stats.lost_bytes += lost_bytes;
+            processed = true;
+        }
+
+        if processed {
+            self.try_process_acked_frames(now);
+        }
+        Ok(())
+    }
+
+    /// Return whether there are ACK ranges carried over to be processed.
+    fn has_pending_acks(&self) -> bool {
+        self.paths
+            .iter()
+            .any(|(_, p)| p.recovery.has_pending_acks())
+    }
+
     /// Process acknowledged frames in each packet number space
-    fn try_process_acked_frames(&mut self) {
+    fn try_process_acked_frames(&mut self, now: time::Instant) {
         for (_, space) in self.spaces.iter_mut() {
             for acked_frame in space.acked.drain(..) {
                 match acked_frame {
@@ -1438,6 +2023,7 @@ impl Connection {
 
                     Frame::Ping {
                         pmtu_probe: Some((path_id, probe_size)),
+                        ..
                     } => {
                         if let Ok(path) = self.paths.get_mut(path_id) {
                             let peer_mds = self.peer_transport_params.max_udp_payload_size as usize;
@@ -1448,6 +2034,18 @@ impl Connection {
                         }
                     }
 
+                    // The application probe has been acknowledged, and the
+                    // RTT is measured from the last time it was sent, less
+                    // the ACK delay reported by the peer.
+                    Frame::Ping {
+                        probe: Some((tag, sent_time)),
+                        ..
+                    } => {
+                        let rtt = now.saturating_duration_since(sent_time);
+                        let rtt = rtt.checked_sub(space.latest_ack_delay).unwrap_or(rtt);
+                        self.events.add(Event::PingAcked(tag, rtt));
+                    }
+
                     _ => (),
                 }
             }
@@ -1499,6 +2097,34 @@ impl Connection {
     ///
     /// Return Error::Done if no packet can be sent.
     pub(crate) fn send(&mut self, out: &mut [u8]) -> Result<(usize, PacketInfo)> {
+        let (len, _, _, info) = self.send_segments(out, 1)?;
+        Ok((len, info))
+    }
+
+    /// Write up to `max_segments` UDP datagrams of the same size to the given
+    /// buffer, so that they can be sent to the peer in a single write with
+    /// UDP generic segmentation offload (i.e. UDP_SEGMENT).
+    ///
+    /// The segment size is the size of the first datagram, and all the
+    /// datagrams are written back to back with the segment size except the
+    /// last one, which may be smaller. All the datagrams are sent on the same
+    /// path. If more than one datagram is written, the segment size is also
+    /// set in the returned packet info.
+    ///
+    /// A datagram is only added to the batch if it has the same release time,
+    /// ECN codepoint and DSCP as the first one, since they are shared by all
+    /// the segments on the wire. Otherwise it starts the next batch.
+    ///
+    /// Return a tuple consisting of the total size written, the segment size,
+    /// the number of datagrams and the packet info upon success. If an error
+    /// occurs after the first datagram, the datagrams already written are
+    /// returned, since they have been recorded as sent.
+    /// Return Error::Done if no packet can be sent.
+    pub(crate) fn send_segments(
+        &mut self,
+        out: &mut [u8],
+        max_segments: usize,
+    ) -> Result<(usize, usize, usize, PacketInfo)> {
         if out.len() < crate::MIN_CLIENT_INITIAL_LEN {
             return Err(Error::BufferTooShort);
         }
@@ -1523,23 +2149,92 @@ impl Connection {
             self.flags.insert(InitiatedClientHandshake);
         }
 
+        // Continue processing the carried over ACK ranges, which may free
+        // the congestion window for sending.
+        self.process_pending_acks(time::Instant::now())?;
+
         // Process all lost frames and prepare for retransmitting
         self.process_all_lost_frames();
 
-        // Select a path for sending a packet
-        let pid = self.select_send_path()?;
+        // Send the datagram held from the previous batch first, or select a
+        // path for sending a packet.
+        let (pid, mut done, mut info) = match self.held_datagram.take() {
+            Some((pid, buf, info)) if self.paths.get(pid).is_ok() => {
+                if out.len() < buf.len() {
+                    self.held_datagram = Some((pid, buf, info));
+                    return Err(Error::BufferTooShort);
+                }
+                out[..buf.len()].copy_from_slice(&buf);
+                (pid, buf.len(), info)
+            }
+            _ => {
+                let pid = self.select_send_path()?;
+                let (done, info) = self.send_datagram(out, pid)?;
+                (pid, done, info)
+            }
+        };
+
+        // Write the following datagrams with the segment size on the path.
+        let segment_size = done;
+        let mut segments = 1;
+        while segments < max_segments && out.len() - done >= segment_size {
+            let buf = &mut out[done..done + segment_size];
+            let (len, seg_info) = match self.send_datagram(buf, pid) {
+                Ok(v) => v,
+                Err(Error::Done) => break,
+                Err(e) => {
+                    warn!(
+                        "{} send segment {} failed: {:?}",
+                        self.trace_id, segments, e
+                    );
+                    break;
+                }
+            };
+
+            // A datagram without a release time gets the current time, which
+            // is not later than the release time of the batch.
+            if seg_info.time > cmp::max(info.time, time::Instant::now())
+                || seg_info.ecn != info.ecn
+                || seg_info.dscp != info.dscp
+            {
+                let buf = out[done..done + len].to_vec();
+                self.held_datagram = Some((pid, buf, seg_info));
+                break;
+            }
+            done += len;
+            segments += 1;
+
+            // A smaller datagram can only be the last segment.
+            if len < segment_size {
+                break;
+            }
+        }
+
+        if segments > 1 {
+            info.segment_size = segment_size;
+        }
+        Ok((done, segment_size, segments, info))
+    }
 
+    /// Write coalesced multiple QUIC packets of a UDP datagram to the given
+    /// buffer, which will be sent on the given path.
+    ///
+    /// Return Error::Done if no packet can be sent.
+    fn send_datagram(&mut self, out: &mut [u8], pid: usize) -> Result<(usize, PacketInfo)> {
         // Limit bytes sent by path MTU limit and server send limit before address validation
         let mut left = cmp::min(out.len(), self.max_datagram_size(pid));
         left = self.paths.cmp_anti_ampl_limit(pid, left);
 
         let mut done = 0;
 
-        // Write QUIC packets to the buffer
+        // Write QUIC packets to the buffer, and the packets are protected
+        // together after all of them are written.
         let mut has_initial = false;
+        let mut has_ack_eliciting = false;
+        self.pending_packets.clear();
         while left > 0 {
-            let (pkt_type, is_pmtu_probe, written) =
-                match self.send_packet(&mut out[done..], left, pid, done == 0, has_initial) {
+            let (pkt_type, is_pmtu_probe, ack_eliciting, written) =
+                match self.send_packet(out, done, left, pid, has_initial) {
                     Ok(v) => v,
                     Err(Error::BufferTooShort) | Err(Error::Done) => break,
                     Err(e) => return Err(e),
@@ -1547,9 +2242,26 @@ impl Connection {
 
             left = left.saturating_sub(written);
             done = done.saturating_add(written);
+            has_ack_eliciting |= ack_eliciting;
+
+            // The successful use of Handshake packets indicates that no more
+            // Initial packets need to be exchanged, as these keys can only be
+            // produced after receiving all CRYPTO frames from Initial packets.
+            // Thus, a client MUST discard Initial keys when it first sends a
+            // Handshake packet
+            if !self.is_server && pkt_type == PacketType::Handshake {
+                self.protect_pending_packets(&mut out[..done])?;
+                self.drop_space_state(SpaceId::Initial, time::Instant::now());
+            }
 
             match pkt_type {
-                PacketType::Initial => has_initial = true,
+                // A client MUST expand the payload of all UDP datagrams carrying
+                // Initial packets, while a server only needs to expand those
+                // carrying ack-eliciting Initial packets. Datagrams with only
+                // ACK frames are not padded to save anti-amplification budget
+                // for the CRYPTO data.
+                // See RFC 9000 Section 14.1
+                PacketType::Initial if !self.is_server || ack_eliciting => has_initial = true,
 
                 // A packet with a short header does not include a length, so it
                 // can only be the last packet included in a UDP datagram.
@@ -1567,8 +2279,17 @@ impl Connection {
         }
 
         if done == 0 {
+            self.on_send_stalled(pid)?;
             return Err(Error::Done);
         }
+        self.protect_pending_packets(&mut out[..done])?;
+
+        // The application limited or flow control limited period ends once
+        // data is sent again.
+        let limited = self.paths.get(pid)?.recovery.send_limited();
+        if has_ack_eliciting && matches!(limited, SendLimited::App | SendLimited::FlowControl) {
+            self.update_send_limited(pid, SendLimited::None)?;
+        }
 
         // Sending UDP datagrams carrying Initial packets of this size ensures
         // that the network path supports a reasonable Path Maximum Transmission
@@ -1581,28 +2302,101 @@ impl Connection {
             done += pad_len;
         }
 
-        let path = self.paths.get(pid)?;
+        // All packets in the datagram are marked with the same ECN codepoint.
+        let path = self.paths.get_mut(pid)?;
+
+        // Write AntiAmplificationBudgetUpdated event to qlog.
+        if self.is_server && !path.verified_peer_address {
+            if let Some(qlog) = &mut self.qlog {
+                let ev_data = events::EventData::ConnectivityAntiAmplificationBudgetUpdated {
+                    sent: done as u64,
+                    budget: path.anti_ampl_limit as u64,
+                };
+                qlog.add_event_data(time::Instant::now(), ev_data).ok();
+            }
+        }
+
+        let now = time::Instant::now();
         let info = PacketInfo {
             src: path.local_addr(),
             dst: path.remote_addr(),
-            time: time::Instant::now(),
+            time: path.recovery.take_release_time().unwrap_or(now).max(now),
+            ecn: path.recovery.ecn.ecn_mark(),
+            dscp: path.dscp.unwrap_or(self.dscp),
+            segment_size: 0,
         };
+        path.recovery.ecn.on_datagram_sent();
         Ok((done, info))
     }
 
-    /// Write a QUIC packet to the given buffer.
+    /// Track the reason why no packet can be sent on the path. The sender is
+    /// limited by the flow control if it is blocked, or by the application if
+    /// there is no data to send. The periods limited by the congestion window
+    /// are tracked when writing frames, and the pacer is not regarded as a
+    /// limit.
+    fn on_send_stalled(&mut self, pid: usize) -> Result<()> {
+        let r = &self.paths.get(pid)?.recovery;
+        if !self.is_established() || r.is_cwnd_limited() || r.pacer_timer.is_some() {
+            return Ok(());
+        }
+
+        let limited = if self.streams.is_send_blocked() {
+            SendLimited::FlowControl
+        } else {
+            self.paths.get_mut(pid)?.recovery.on_app_limited();
+            SendLimited::App
+        };
+        self.update_send_limited(pid, limited)
+    }
+
+    /// Update the send limited state of the path, and write a qlog event if
+    /// the state is changed.
+    fn update_send_limited(&mut self, pid: usize, limited: SendLimited) -> Result<()> {
+        let now = time::Instant::now();
+        let recovery = &mut self.paths.get_mut(pid)?.recovery;
+        let old = recovery.send_limited();
+        if !recovery.stat_send_limited(limited, now) {
+            return Ok(());
+        }
+
+        if let Some(qlog) = &mut self.qlog {
+            let ev_data = events::EventData::RecoverySendLimitedUpdated {
+                old: Some(old.to_qlog()),
+                new: limited.to_qlog(),
+            };
+            qlog.add_event_data(now, ev_data).ok();
+        }
+        Ok(())
+    }
+
+    /// Apply packet protection to the pending packets written into `out`.
+    fn protect_pending_packets(&mut self, out: &mut [u8]) -> Result<()> {
+        if self.pending_packets.is_empty() {
+            return Ok(());
+        }
+
+        let tls_session = &self.tls_session;
+        packet::encrypt_packets(out, &self.pending_packets, |level| {
+            tls_session.get_keys(level).seal.as_ref()
+        })?;
+        self.pending_packets.clear();
+        Ok(())
+    }
+
+    /// Write a QUIC packet to the given buffer. The packet is recorded as
+    /// pending and it should be protected by `protect_pending_packets()`
+    /// before the datagram is sent.
     ///
-    /// The `out` is the write buffer with a size that must be no less than `left`.
+    /// The `out` is the datagram buffer, and the packet is written at `offset`.
+    /// The size of the remaining buffer must be no less than `left`.
     /// The `left` is the upper limit for the write size when sending a non-PMTU
     /// probe packet.
     /// The `path_id` is the selected path for sending out packets.
-    /// The `first` indicates that it is the first packet being written to the UDP
-    /// datagram.
-    /// The `has_initial` indicates that a previous Initial packet has been written
-    /// the UDP datagram.
+    /// The `has_initial` indicates that a previous Initial packet which requires
+    /// the UDP datagram to be expanded has been written.
     ///
-    /// Return a tuple consisting of the packet type, PMUT probe flag, and the
-    /// packet size upon success.
+    /// Return a tuple consisting of the packet type, PMUT probe flag, ack-eliciting
+    /// flag and the packet size upon success.
     /// Return `Error::BufferTooShort` if the input buffer is too small to
     /// write a single QUIC packet.
     /// Return `Error::Done` if no packet can be sent.
@@ -1610,12 +2404,14 @@ impl Connection {
     fn send_packet(
         &mut self,
         out: &mut [u8],
+        offset: usize,
         mut left: usize,
         path_id: usize,
-        first: bool,
         has_initial: bool,
-    ) -> Result<(PacketType, bool, usize)> {
+    ) -> Result<(PacketType, bool, bool, usize)> {
         let now = time::Instant::now();
+        let first = offset == 0;
+        let out = &mut out[offset..];
 
         if out.len() < left {
             return Err(Error::InvalidState("buffer too short".into()));
@@ -1629,6 +2425,14 @@ impl Connection {
         let pkt_type = self.select_send_packet_type(path_id)?;
         let level = pkt_type.to_level()?;
 
+        // Before the peer address is validated, the server keeps part of its
+        // anti-amplification budget for Initial and Handshake packets, which
+        // carry the ACK and CRYPTO frames needed for the handshake to make
+        // progress under loss.
+        if pkt_type == PacketType::OneRTT {
+            left = self.paths.cmp_reserved_anti_ampl_limit(path_id, left);
+        }
+
         // Prepare and encode packet header (except for the Length and Packet Number field)
         let space_id = self.get_space_id(pkt_type, path_id)?;
         let (pkt_num, pkt_num_len) = {
@@ -1671,6 +2475,27 @@ impl Connection {
         };
         let hdr_offset = hdr.to_bytes(&mut out[..left])?;
 
+        // Set the spin bit of 1-RTT packets to the spin value of the path, or
+        // to a random value if it is disabled.
+        // See RFC 9000 Section 17.4
+        if pkt_type == PacketType::OneRTT {
+            let spin_bit = if self.spin_enabled {
+                self.paths.get(path_id)?.spin_value
+            } else {
+                rand::random::<bool>()
+            };
+            if spin_bit {
+                out[0] |= packet::HEADER_SPIN_BIT;
+            }
+        }
+
+        // Set the QUIC Bit to an unpredictable value if the peer has advertised
+        // the grease_quic_bit transport parameter.
+        // See RFC 9287 Section 3.1
+        if self.flags.contains(GreaseQuicBit) && rand::random::<bool>() {
+            out[0] &= !packet::HEADER_FIXED_BIT;
+        }
+
         // Check the size of remaining space of the buffer
         let mut pkt_num_offset = hdr_offset;
         if pkt_type != PacketType::OneRTT {
@@ -1701,11 +2526,16 @@ impl Connection {
         let payload_offset = pkt_num_offset + len;
 
         // Write frames into the packet payload
-        let (ack_elicit_required, is_probe) = {
+        let (ack_elicit_required, is_probe, frames) = {
             let space = self.spaces.get_mut(space_id).ok_or(Error::InternalError)?;
-            (space.need_elicit_ack(), space.loss_probes > 0)
+            (
+                space.need_elicit_ack(),
+                space.loss_probes > 0,
+                space.sent.alloc_frames(),
+            )
         };
         let mut write_status = FrameWriteStatus {
+            frames,
             ack_elicit_required,
             is_probe,
             overhead: total_overhead,
@@ -1738,28 +2568,23 @@ impl Connection {
             out.write_varint_with_len(len as u64, crate::LENGTH_FIELD_LEN)?;
         }
 
-        // Encrypt the packet header fields and payload
-        let key = self.tls_session.get_keys(pkt_type.to_level()?);
-        let key = match &key.seal {
-            Some(seal) => seal,
-            None => return Err(Error::InternalError),
-        };
+        // The packet header fields and payload are encrypted later
         let mut cid_seq = None;
         if self.flags.contains(EnableMultipath) {
             cid_seq = Some(dcid_seq as u32);
         }
 
         let written = if !self.is_encryption_disabled(hdr.pkt_type) {
-            packet::encrypt_packet(
-                out,
-                cid_seq,
-                pkt_num,
-                pkt_num_len,
-                payload_len,
+            self.pending_packets.push(packet::PendingPacket {
+                offset,
                 payload_offset,
-                None,
-                key,
-            )?
+                payload_len,
+                pkt_num,
+                pkt_num_len,
+                cid_seq,
+                level,
+            });
+            payload_offset + payload_len + crypto_overhead
         } else {
             payload_offset + payload_len
         };
@@ -1779,6 +2604,7 @@ impl Connection {
             frames: write_status.frames,
             rate_sample_state: Default::default(),
             buffer_flags: write_status.buffer_flags,
+            ecn_marked: self.paths.get(path_id)?.recovery.ecn.ecn_mark() != Ecn::NotEct,
         };
         debug!(
             "{} sent packet {:?} {:?} {:?}",
@@ -1832,6 +2658,13 @@ impl Connection {
             self.paths.on_path_chal_sent(path_id, data, written, now)?;
         }
 
+        if let Some(data) = write_status.keep_alive_probe {
+            // Wait for the PATH_RESPONSE and the OBSERVED_ADDRESS frame for a
+            // few PTOs.
+            let timeout = 3 * self.paths.get(path_id)?.recovery.rtt.pto_base();
+            self.keep_alive.on_probe_sent(data, now, timeout);
+        }
+
         if write_status.is_pmtu_probe {
             self.paths
                 .get_mut(path_id)?
@@ -1856,18 +2689,10 @@ impl Connection {
                 if space.first_pkt_num_sent.is_none() {
                     space.first_pkt_num_sent = Some(pkt_num);
                 }
+                space.try_skip_pkt_num(self.pkt_num_skip_interval);
             }
         }
 
-        // The successful use of Handshake packets indicates that no more
-        // Initial packets need to be exchanged, as these keys can only be
-        // produced after receiving all CRYPTO frames from Initial packets.
-        // Thus, a client MUST discard Initial keys when it first sends a
-        // Handshake packet
-        if !self.is_server && pkt_type == PacketType::Handshake {
-            self.drop_space_state(SpaceId::Initial, now);
-        }
-
         // An endpoint also restarts its idle timer when sending an ack-eliciting
         // packet if no other ack-eliciting packets have been sent since last
         // receiving and processing a packet.
@@ -1876,11 +2701,19 @@ impl Connection {
                 self.timers.set(Timer::Idle, now + idle_timeout);
             }
         }
+        if write_status.ack_eliciting && self.is_established() {
+            self.keep_alive.on_activity(now);
+        }
         if write_status.ack_eliciting {
             self.flags.insert(SentAckElicitingSinceRecvPkt);
         }
 
-        Ok((pkt_type, write_status.is_pmtu_probe, written))
+        Ok((
+            pkt_type,
+            write_status.is_pmtu_probe,
+            write_status.ack_eliciting,
+            written,
+        ))
     }
 
     /// Write QUIC frames to the payload of a QUIC packet.
@@ -1905,8 +2738,14 @@ impl Connection {
         // Write a CONNECTION_CLOSE frame
         self.try_write_close_frame(&mut buf[..left], st, pkt_type, path_id)?;
 
-        let path = self.paths.get_mut(path_id)?;
-        path.recovery.stat_cwnd_limited();
+        // Track the period during which sending is limited by the congestion
+        // window.
+        let r = &self.paths.get(path_id)?.recovery;
+        if r.is_cwnd_limited() {
+            self.update_send_limited(path_id, SendLimited::Cwnd)?;
+        } else if r.send_limited() == SendLimited::Cwnd {
+            self.update_send_limited(path_id, SendLimited::None)?;
+        }
 
         let now = time::Instant::now();
         let r = &mut self.paths.get_mut(path_id)?.recovery;
@@ -1950,30 +2789,71 @@ impl Connection {
         }
 
         // Write stream control frames
-        self.try_write_stream_control_frames(out, st, pkt_type, path_id)?;
+        self.try_write_stream_control_frames(out, st, pkt_type, path_id, now)?;
 
         // Write a CRYPTO frame
+        // Note: The CRYPTO frame is written ahead of the buffered frames and
+        // STREAM frames, so that new or lost crypto data is never preempted by
+        // application data.
         self.try_write_crypto_frame(out, st, pkt_type, path_id)?;
 
         // Write buffered frames
         self.try_write_buffered_frames(out, st, pkt_type, path_id)?;
 
         // Write STREAM frames
-        self.try_write_stream_frames(out, st, pkt_type, path_id)?;
+        // Note: The probe packets carry no stream data if only PING frames are
+        // expected.
+        let ping_probe = st.is_probe
+            && pkt_type == PacketType::OneRTT
+            && self.paths.get(path_id)?.recovery.pto_probe_strategy() == PtoProbeStrategy::Ping;
+        if !ping_probe {
+            self.try_write_stream_frames(out, st, pkt_type, path_id)?;
+        }
 
         // Write a NEW_TOKEN frame
         self.try_write_new_token_frame(out, st, pkt_type, path_id)?;
 
+        // Write a BDP_FRAME frame
+        self.try_write_bdp_frame(out, st, pkt_type, path_id)?;
+
+        // Write an OBSERVED_ADDRESS frame
+        self.try_write_observed_address_frame(out, st, pkt_type, path_id)?;
+
+        // Write ACK_FREQUENCY/IMMEDIATE_ACK frames
+        self.try_write_ack_frequency_frames(out, st, pkt_type, path_id)?;
+
         // Write a PING frame
         if ((st.ack_elicit_required && !st.ack_eliciting)
             || self.paths.get_mut(path_id)?.need_send_ping)
             && !self.is_closing()
         {
-            let frame = Frame::Ping { pmtu_probe: None };
-            Connection::write_frame_to_packet(frame, out, st)?;
+            // The application probes are sent on the active path.
+            let active = self.paths.get(path_id)?.active();
+            let mut probe_written = false;
+            if active && pkt_type == PacketType::OneRTT {
+                while let Some(tag) = self.ping_tags.pop_front() {
+                    let frame = Frame::Ping {
+                        pmtu_probe: None,
+                        probe: Some((tag, time::Instant::now())),
+                    };
+                    if Connection::write_frame_to_packet(frame, out, st).is_err() {
+                        self.ping_tags.push_front(tag);
+                        break;
+                    }
+                    probe_written = true;
+                }
+            }
+
+            if !probe_written {
+                let frame = Frame::Ping {
+                    pmtu_probe: None,
+                    probe: None,
+                };
+                Connection::write_frame_to_packet(frame, out, st)?;
+            }
             st.ack_eliciting = true;
             st.in_flight = true;
-            self.paths.get_mut(path_id)?.need_send_ping = false;
+            self.paths.get_mut(path_id)?.need_send_ping = active && !self.ping_tags.is_empty();
         }
 
         // No frames to be sent
@@ -2051,6 +2931,16 @@ impl Connection {
             st.challenge = Some(data);
         }
 
+        // Create PATH_CHALLENGE frame for keep-alive probing if needed.
+        if self.keep_alive.need_send_probe() && self.paths.get(path_id)?.active() {
+            let data = rand::random::<u64>().to_be_bytes();
+            let frame = Frame::PathChallenge { data };
+            Connection::write_frame_to_packet(frame, out, st)?;
+            st.ack_eliciting = true;
+            st.in_flight = true;
+            st.keep_alive_probe = Some(data);
+        }
+
         Ok(())
     }
 
@@ -2087,6 +2977,7 @@ impl Connection {
         // The content of the PMTU probe is limited to PING and PADDING frames.
         let frame = frame::Frame::Ping {
             pmtu_probe: Some((path_id, probe_size)),
+            probe: None,
         };
         Connection::write_frame_to_packet(frame, buf, st)?;
 
@@ -2112,6 +3003,7 @@ impl Connection {
         path_id: usize,
     ) -> Result<()> {
         let is_closing = self.is_closing();
+        let qlog_enabled = self.qlog.is_some();
         let space_id = self.get_space_id(pkt_type, path_id)?;
         let space = self.spaces.get_mut(space_id).ok_or(Error::InternalError)?;
 
@@ -2127,12 +3019,43 @@ impl Connection {
         let ack_delay_exp = self.local_transport_params.ack_delay_exponent as u32;
         let ack_delay = space.largest_rx_pkt_time.elapsed();
         let ack_delay = ack_delay.as_micros() as u64 / 2_u64.pow(ack_delay_exp);
-        let frame = Frame::Ack {
+
+        // Encode the ACK frame from the ranges to be acknowledged directly,
+        // instead of writing a `Frame::Ack` with a copy of the ranges.
+        // The ECN counts are included if any packet with an ECN codepoint has
+        // been received in the packet number space.
+        let ack_ranges = &space.recv_pkt_num_need_ack;
+        let ecn_counts = if space.recv_ecn_counts.is_empty() {
+            None
+        } else {
+            Some(space.recv_ecn_counts)
+        };
+        st.written += match frame::encode_ack_frame(
             ack_delay,
-            ack_ranges: space.recv_pkt_num_need_ack.clone(),
-            ecn_counts: None, // ECN not supported
+            ack_ranges,
+            ecn_counts.as_ref(),
+            &mut out[st.written..],
+        ) {
+            Ok(len) => len,
+            Err(_) => return Err(Error::Done),
         };
-        Connection::write_frame_to_packet(frame, out, st)?;
+
+        // Only the largest acknowledged range is required when the ACK frame
+        // is acknowledged, and the full ranges are retained only for qlog.
+        let ack_ranges = if qlog_enabled {
+            ack_ranges.clone()
+        } else {
+            let mut ranges = RangeSet::new(1);
+            if let Some(largest) = ack_ranges.iter().next_back() {
+                ranges.insert(largest);
+            }
+            ranges
+        };
+        st.frames.push(Frame::Ack {
+            ack_delay,
+            ack_ranges,
+            ecn_counts,
+        });
         space.need_send_ack = false;
         space.ack_eliciting_pkts_since_last_sent_ack = 0;
 
@@ -2203,6 +3126,7 @@ impl Connection {
         st: &mut FrameWriteStatus,
         pkt_type: PacketType,
         path_id: usize,
+        now: time::Instant,
     ) -> Result<()> {
         // STREAM control frames can only be sent in 1-RTT packet.
         if pkt_type != PacketType::OneRTT || self.is_closing() {
@@ -2214,11 +3138,13 @@ impl Connection {
             return Ok(());
         }
 
-        let now = time::Instant::now();
-
         // Create MAX_STREAMS frame if needed.
         for bidi in &[true, false] {
             if self.streams.should_update_local_max_streams(*bidi) {
+                // Adjust the max streams limit automatically.
+                self.streams
+                    .autotune_max_streams(*bidi, now, path.recovery.rtt.smoothed_rtt());
+
                 let frame = frame::Frame::MaxStreams {
                     bidi: *bidi,
                     max: self.streams.max_streams_next(*bidi),
@@ -2243,6 +3169,14 @@ impl Connection {
 
             // Clear the data_blocked state.
             self.streams.update_data_blocked_at(None);
+
+            // A retransmitted DATA_BLOCKED frame does not start a new period.
+            if self.data_blocked.map(|(limit, _)| limit) != Some(blocked_at) {
+                self.data_blocked = Some((blocked_at, now));
+                self.stats.data_blocked_count += 1;
+                let event = BlockedEvent::DataBlocked { limit: blocked_at };
+                self.events.add(Event::FlowControlBlocked(event));
+            }
         }
 
         // Create MAX_STREAM_DATA frames if needed.
@@ -2257,23 +3191,40 @@ impl Connection {
                 }
             };
 
+            // The application withholds the stream-level credit, and the
+            // stream will be marked almost full again once resumed.
+            if stream.recv.is_flow_control_paused() {
+                self.streams.mark_almost_full(stream_id, false);
+                continue;
+            }
+
             // Adjust the stream window size automatically.
             stream
                 .recv
                 .autotune_window(now, path.recovery.rtt.smoothed_rtt());
+            let recv_win = stream.recv.window();
 
-            let frame = frame::Frame::MaxStreamData {
-                stream_id,
-                max: stream.recv.max_data_next(),
+            let max = match self.streams.stream_max_data_next(stream_id) {
+                Some(v) => v,
+
+                None => {
+                    // No credit can be allocated to the stream from the shared
+                    // connection window at present, and the stream will be
+                    // marked almost full again after more data is read.
+                    self.streams.mark_almost_full(stream_id, false);
+                    continue;
+                }
             };
+            let frame = frame::Frame::MaxStreamData { stream_id, max };
 
             Connection::write_frame_to_packet(frame, buf, st)?;
             st.ack_eliciting = true;
             st.in_flight = true;
 
-            let recv_win = stream.recv.window();
+            self.stats.max_stream_data_sent_count += 1;
+
             // Apply the new flow control limit.
-            stream.recv.update_max_data(now);
+            self.streams.update_stream_max_data(stream_id, max, now);
             self.streams.mark_almost_full(stream_id, false);
 
             // Ensure that the connection window always has some room
@@ -2290,8 +3241,8 @@ impl Connection {
         // Create MAX_DATA frame if needed.
         if self.streams.need_send_max_data() {
             // Adjust the connection window size automatically.
-            self.streams
-                .autotune_window(now, path.recovery.rtt.smoothed_rtt());
+            let srtt = path.recovery.rtt.smoothed_rtt();
+            self.tune_recv_window(now, srtt);
 
             let frame = frame::Frame::MaxData {
                 max: self.streams.max_rx_data_next(),
@@ -2301,6 +3252,8 @@ impl Connection {
             st.ack_eliciting = true;
             st.in_flight = true;
 
+            self.stats.max_data_sent_count += 1;
+
             self.streams.rx_almost_full = false;
             // Apply the new flow control limit.
             self.streams.update_max_rx_data(now);
@@ -2345,6 +3298,11 @@ impl Connection {
             self.streams.mark_reset(stream_id, false, 0, 0);
         }
 
+        // The blocked period of a closed stream never ends, so forget it.
+        let streams = &self.streams;
+        self.stream_data_blocked
+            .retain(|&stream_id, _| !streams.is_closed(stream_id));
+
         // Create STREAM_DATA_BLOCKED frames if needed.
         for (stream_id, limit) in self
             .streams
@@ -2366,6 +3324,16 @@ impl Connection {
             st.in_flight = true;
 
             self.streams.mark_blocked(stream_id, false, 0);
+
+            // A retransmitted STREAM_DATA_BLOCKED frame does not start a new
+            // period.
+            let blocked = self.stream_data_blocked.get(&stream_id);
+            if blocked.map(|(limit, _)| *limit) != Some(limit) {
+                self.stream_data_blocked.insert(stream_id, (limit, now));
+                self.stats.stream_data_blocked_count += 1;
+                let event = BlockedEvent::StreamDataBlocked { stream_id, limit };
+                self.events.add(Event::FlowControlBlocked(event));
+            }
         }
 
         // Create STREAMS_BLOCKED frames if needed.
@@ -2501,19 +3469,24 @@ impl Connection {
 
         let mut len = 0;
         let mut cap: usize = out.len();
+        let mut retrans_bytes = 0;
+        if self.try_hold_stream_data(cap) {
+            return Ok(());
+        }
 
         while let Some(stream_id) = self.streams.peek_sendable() {
             let stream = match self.streams.get_mut(stream_id) {
                 // We should not send frames for streams that were already stopped.
                 Some(s) if !s.send.is_stopped() => s,
                 _ => {
-                    self.streams.remove_sendable();
+                    self.streams.remove_sendable(stream_id);
                     continue;
                 }
             };
 
             // Get the lowest offset of data to be sent.
             let stream_off = stream.send.send_off();
+            let retransmitting = stream.send.is_retransmitting();
 
             // Encode stream frame, instead of create a `frame::Frame::Stream`,
             // encode the data into the packet buffer directly.
@@ -2525,18 +3498,13 @@ impl Connection {
             let frame_hdr_len = frame::stream_header_wire_len(stream_id, stream_off);
 
             // Read stream data and write into the packet buffer directly.
+            //
+            // Note that the stream data is not retained in the sent frame,
+            // which only refers to the range of data in the stream's SendBuf.
+            // The data is read from the SendBuf again if it needs to be
+            // retransmitted or reinjected.
             let (frame_data_len, fin) = stream.send.read(&mut out[len + frame_hdr_len..])?;
 
-            // Retain stream data if needed.
-            let data = if self.flags.contains(EnableMultipath)
-                && buffer_required(self.multipath_conf.multipath_algorithm)
-            {
-                let start = len + frame_hdr_len;
-                Bytes::copy_from_slice(&out[start..start + frame_data_len])
-            } else {
-                Bytes::new()
-            };
-
             frame::encode_stream_header(
                 stream_id,
                 stream_off,
@@ -2549,6 +3517,9 @@ impl Connection {
             st.written += frame_len;
             len += frame_len;
             cap -= frame_len;
+            if retransmitting {
+                retrans_bytes += frame_data_len as u64;
+            }
 
             st.ack_eliciting = true;
             st.in_flight = true;
@@ -2558,12 +3529,15 @@ impl Connection {
                 offset: stream_off,
                 length: frame_data_len,
                 fin,
-                data,
+                data: Bytes::new(),
             });
 
+            let sendable = stream.is_sendable();
+            self.streams.on_sendable_sent(stream_id, frame_data_len);
+
             // If the stream is no longer sendable, remove it from the queue
-            if !stream.is_sendable() {
-                self.streams.remove_sendable();
+            if !sendable {
+                self.streams.remove_sendable(stream_id);
             }
 
             // If the buffer is too short, we won't attempt to write any more stream frames into it.
@@ -2572,9 +3546,62 @@ impl Connection {
             }
         }
 
+        if retrans_bytes > 0 {
+            self.paths
+                .get_mut(path_id)?
+                .recovery
+                .stat_retrans_stream_event(retrans_bytes);
+        }
+
+        // All the stream data held for coalescing has been sent.
+        if self.flags.contains(FlushStreamData) && self.streams.sendable_len(1) == 0 {
+            self.flags.remove(FlushStreamData);
+        }
+
         Ok(())
     }
 
+    /// Check whether the stream data should be held for coalescing, so that
+    /// small writes of the application can be packed into fewer packets.
+    ///
+    /// The data is held until it is enough to fill a packet with the given
+    /// capacity, or the coalescing delay has elapsed since it was held. Lost
+    /// data and the end of a stream are never held, which would delay loss
+    /// recovery and the completion of the stream.
+    fn try_hold_stream_data(&mut self, cap: usize) -> bool {
+        let delay = self.recovery_conf.send_coalescing_delay;
+        if delay.is_zero() || self.flags.contains(FlushStreamData) {
+            return false;
+        }
+        if self.streams.has_urgent_sendable() {
+            self.timers.stop(Timer::Coalescing);
+            return false;
+        }
+
+        let len = self.streams.sendable_len(cap);
+        if len == 0 {
+            return false;
+        }
+        if len >= cap {
+            self.timers.stop(Timer::Coalescing);
+            return false;
+        }
+
+        let now = time::Instant::now();
+        match self.timers.get(Timer::Coalescing) {
+            Some(time) if time > now => true,
+            Some(_) => {
+                self.timers.stop(Timer::Coalescing);
+                self.flags.insert(FlushStreamData);
+                false
+            }
+            None => {
+                self.timers.set(Timer::Coalescing, now + delay);
+                true
+            }
+        }
+    }
+
     /// Populate NewToken frame to packet payload buffer.
     fn try_write_new_token_frame(
         &mut self,
@@ -2605,6 +3632,123 @@ impl Connection {
         Ok(())
     }
 
+    /// Populate BDP_FRAME frame to packet payload buffer.
+    fn try_write_bdp_frame(
+        &mut self,
+        out: &mut [u8],
+        st: &mut FrameWriteStatus,
+        pkt_type: PacketType,
+        path_id: usize,
+    ) -> Result<()> {
+        if !(pkt_type == PacketType::OneRTT
+            && !self.is_closing()
+            && self.paths.get(path_id)?.active()
+            && self.need_send_bdp_frame())
+        {
+            return Ok(());
+        }
+
+        let recovery = &self.paths.get(path_id)?.recovery;
+        let frame = Frame::Bdp {
+            lifetime: BDP_LIFETIME,
+            saved_capacity: recovery.congestion.congestion_window(),
+            saved_rtt: recovery.rtt.min_rtt().as_micros() as u64,
+        };
+
+        Connection::write_frame_to_packet(frame, out, st)?;
+        st.ack_eliciting = true;
+        st.in_flight = true;
+        self.flags.remove(NeedSendBdp);
+
+        Ok(())
+    }
+
+    /// Populate OBSERVED_ADDRESS frame to packet payload buffer.
+    fn try_write_observed_address_frame(
+        &mut self,
+        out: &mut [u8],
+        st: &mut FrameWriteStatus,
+        pkt_type: PacketType,
+        path_id: usize,
+    ) -> Result<()> {
+        let path = self.paths.get(path_id)?;
+        if !(pkt_type == PacketType::OneRTT
+            && !self.is_closing()
+            && path.active()
+            && self.need_send_observed_address_frame(path))
+        {
+            return Ok(());
+        }
+
+        let frame = Frame::ObservedAddress {
+            seq_num: self.observed_addr_seq,
+            addr: path.remote_addr(),
+        };
+
+        Connection::write_frame_to_packet(frame, out, st)?;
+        st.ack_eliciting = true;
+        st.in_flight = true;
+        self.observed_addr_seq += 1;
+        self.paths.get_mut(path_id)?.need_send_observed_addr = false;
+
+        Ok(())
+    }
+
+    /// Populate ACK_FREQUENCY and IMMEDIATE_ACK frames to packet payload buffer.
+    fn try_write_ack_frequency_frames(
+        &mut self,
+        out: &mut [u8],
+        st: &mut FrameWriteStatus,
+        pkt_type: PacketType,
+        path_id: usize,
+    ) -> Result<()> {
+        if !(pkt_type == PacketType::OneRTT
+            && !self.is_closing()
+            && self.is_established()
+            && self.paths.get(path_id)?.active()
+            && self.flags.contains(EnableAckFrequency))
+        {
+            return Ok(());
+        }
+
+        if self.flags.contains(NeedSendAckFrequency) {
+            if let Some((threshold, max_ack_delay)) = self.recovery_conf.ack_frequency {
+                // The requested max_ack_delay MUST NOT be less than the
+                // min_ack_delay of the peer.
+                let min_ack_delay = self.peer_transport_params.min_ack_delay.unwrap_or(0);
+                let max_ack_delay = cmp::max(max_ack_delay.as_micros() as u64, min_ack_delay);
+                let frame = Frame::AckFrequency {
+                    seq_num: self.ack_frequency_seq,
+                    ack_eliciting_threshold: threshold - 1,
+                    request_max_ack_delay: max_ack_delay,
+                    reordering_threshold: 1,
+                };
+                Connection::write_frame_to_packet(frame, out, st)?;
+                st.ack_eliciting = true;
+                st.in_flight = true;
+                self.ack_frequency_seq += 1;
+
+                // The PTO should account for the larger acknowledgement delay
+                // that the peer may use.
+                let max_ack_delay = time::Duration::from_micros(max_ack_delay);
+                for (_, path) in self.paths.iter_mut() {
+                    let r = &mut path.recovery;
+                    r.max_ack_delay = cmp::max(r.max_ack_delay, max_ack_delay);
+                }
+            }
+            self.flags.remove(NeedSendAckFrequency);
+        }
+
+        if self.flags.contains(NeedSendImmediateAck) {
+            Connection::write_frame_to_packet(Frame::ImmediateAck, out, st)?;
+            st.ack_eliciting = true;
+            st.in_flight = true;
+            self.flags.remove(NeedSendImmediateAck);
+        }
+
+        Ok(())
+    }
+
     /// Populate buffered frame to packet payload buffer.
     fn try_write_buffered_frames(
         &mut self,
@@ -2648,7 +3792,7 @@ impl Connection {
                     offset,
                     length,
                     fin,
-                    data,
+                    ..
                 } => {
                     let stream = match self.streams.get_mut(stream_id) {
                         Some(v) => v,
@@ -2660,9 +3804,9 @@ impl Connection {
                     if let Some(r) = stream.send.filter_acked(range) {
                         let data_len = Self::write_buffered_stream_frame_to_packet(
                             stream_id,
-                            r.start,
+                            r.clone(),
                             fin && r.end == offset + length as u64,
-                            data.slice((r.start - offset) as usize..(r.end - offset) as usize),
+                            &stream.send,
                             out,
                             buffer_type,
                             st,
@@ -2677,7 +3821,7 @@ impl Connection {
                                 offset: r.start + data_len as u64,
                                 length: tail_len,
                                 fin,
-                                data: data.slice(length - tail_len..),
+                                data: Bytes::new(),
                             };
                             space.buffered.push_front(frame, buffer_type);
                         }
@@ -2696,11 +3840,13 @@ impl Connection {
         Ok(())
     }
 
+    /// Write a stream frame carrying the data of the given range, which is
+    /// read from the stream's SendBuf.
     fn write_buffered_stream_frame_to_packet(
         stream_id: u64,
-        offset: u64,
+        range: Range<u64>,
         mut fin: bool,
-        mut data: Bytes,
+        send: &SendBuf,
         out: &mut [u8],
         buffer_type: BufferType,
         st: &mut FrameWriteStatus,
@@ -2710,15 +3856,22 @@ impl Connection {
             return Ok(0);
         }
 
+        let offset = range.start;
+        let range_len = (range.end - range.start) as usize;
         let hdr_len = frame::stream_header_wire_len(stream_id, offset);
-        let data_len = cmp::min(data.len(), out.len() - hdr_len);
-        if data_len < data.len() {
-            data.truncate(data_len);
+        let cap = cmp::min(range_len, out.len() - hdr_len);
+        let data_len = match send.read_at(offset, &mut out[hdr_len..hdr_len + cap]) {
+            Ok(v) => v,
+            // A frame carrying only the FIN flag has no data to read.
+            Err(Error::Done) if range_len == 0 && fin => 0,
+            Err(Error::Done) => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if data_len < range_len {
             fin = false;
         }
 
         frame::encode_stream_header(stream_id, offset, data_len as u64, fin, out)?;
-        out[hdr_len..hdr_len + data.len()].copy_from_slice(&data);
 
         st.written += hdr_len + data_len;
         st.ack_eliciting = true;
@@ -2728,9 +3881,9 @@ impl Connection {
         st.frames.push(Frame::Stream {
             stream_id,
             offset,
-            length: data.len(),
+            length: data_len,
             fin,
-            data,
+            data: Bytes::new(),
         });
         Ok(data_len)
     }
@@ -2761,6 +3914,18 @@ impl Connection {
         self.is_server && self.is_established() && self.flags.contains(NeedSendNewToken)
     }
 
+    /// Check whether a BDP_FRAME frame should be sent.
+    fn need_send_bdp_frame(&self) -> bool {
+        self.is_server && self.is_established() && self.flags.contains(NeedSendBdp)
+    }
+
+    /// Check whether an OBSERVED_ADDRESS frame should be sent on the path.
+    fn need_send_observed_address_frame(&self, path: &path::Path) -> bool {
+        self.is_established()
+            && self.flags.contains(SendObservedAddress)
+            && path.need_send_observed_addr
+    }
+
     /// Process lost frames in all packet number spaces and prepare for retransmitting
     ///
     /// QUIC packets that are determined to be lost are not retransmitted whole.
@@ -2803,6 +3968,30 @@ impl Connection {
                         self.flags.insert(NeedSendNewToken);
                     }
 
+                    // BDP_FRAME frames are retransmitted if the packet
+                    // containing them is lost.
+                    Frame::Bdp { .. } => {
+                        self.flags.insert(NeedSendBdp);
+                    }
+
+                    // ACK_FREQUENCY frames are retransmitted with a new
+                    // sequence number if the packet containing them is lost.
+                    Frame::AckFrequency { seq_num, .. }
+                        if seq_num + 1 == self.ack_frequency_seq =>
+                    {
+                        self.flags.insert(NeedSendAckFrequency);
+                    }
+
+                    // OBSERVED_ADDRESS frames are retransmitted with a new
+                    // sequence number if the packet containing them is lost.
+                    Frame::ObservedAddress { addr, .. } => {
+                        for (_, path) in self.paths.iter_mut() {
+                            if path.remote_addr() == addr {
+                                path.need_send_observed_addr = true;
+                            }
+                        }
+                    }
+
                     // Data sent in CRYPTO frames is retransmitted according to
                     // the rules in [QUIC-RECOVERY], until all data has been
                     // acknowledged.
@@ -2897,6 +4086,7 @@ impl Connection {
                     // of a PMTU probe, we will try to schedule a new probe.
                     Frame::Ping {
                         pmtu_probe: Some((path_id, probe_size)),
+                        ..
                     } => {
                         if let Ok(path) = self.paths.get_mut(path_id) {
                             let peer_mds = self.peer_transport_params.max_udp_payload_size as usize;
@@ -2908,6 +4098,17 @@ impl Connection {
                         }
                     }
 
+                    // The lost application probe is sent again.
+                    Frame::Ping {
+                        probe: Some((tag, _)),
+                        ..
+                    } => {
+                        self.ping_tags.push_back(tag);
+                        if let Ok(path) = self.paths.get_active_mut() {
+                            path.need_send_ping = true;
+                        }
+                    }
+
                     _ => (),
                 }
             }
@@ -2948,6 +4149,8 @@ impl Connection {
             }
 
             // Select a validated path with ACK/PTO/Buffered packets to send.
+            let send_observed_addr =
+                self.is_established() && self.flags.contains(SendObservedAddress);
             for (pid, path) in self.paths.iter_mut() {
                 if !path.active() {
                     continue;
@@ -2966,6 +4169,9 @@ impl Connection {
                         if path.need_send_ping {
                             return Ok(pid);
                         }
+                        if send_observed_addr && path.need_send_observed_addr {
+                            return Ok(pid);
+                        }
                         continue;
                     }
                     None => continue,
@@ -3061,8 +4267,11 @@ impl Connection {
             || self.tls_session.is_in_early_data())
             && (self.need_send_handshake_done_frame()
                 || self.need_send_new_token_frame()
+                || self.need_send_bdp_frame()
+                || self.need_send_observed_address_frame(path)
                 || self.local_error.as_ref().map_or(false, |e| e.is_app)
                 || path.need_send_validation_frames(self.is_server)
+                || (path.active() && self.keep_alive.need_send_probe())
                 || path.dplpmtud.should_probe()
                 || path.need_send_ping
                 || self.cids.need_send_cid_control_frames()
@@ -3082,6 +4291,7 @@ impl Connection {
     fn need_send_path_unaware_frames(&self) -> bool {
         self.need_send_handshake_done_frame()
             || self.need_send_new_token_frame()
+            || self.need_send_bdp_frame()
             || self.local_error.as_ref().map_or(false, |e| e.is_app)
             || self.cids.need_send_cid_control_frames()
             || self.streams.need_send_stream_frames()
@@ -3199,6 +4409,10 @@ impl Connection {
                 Some(time) => self.timers.set(Timer::Ack, time),
                 None => self.timers.stop(Timer::Ack),
             }
+            match self.keep_alive.timeout() {
+                Some(time) => self.timers.set(Timer::KeepAlive, time),
+                None => self.timers.stop(Timer::KeepAlive),
+            }
 
             self.timers.next_timeout()
         };
@@ -3216,6 +4430,67 @@ impl Connection {
         d
     }
 
+    /// Build the event about the persistent congestion declared on the path,
+    /// if any.
+    fn persistent_congestion_event(path: &mut path::Path) -> Option<Event> {
+        let (duration, lost_packets, congestion_window) =
+            path.recovery.persistent_congestion_declared()?;
+        Some(Event::PersistentCongestion(PersistentCongestionEvent {
+            path: FourTuple {
+                local: path.local_addr(),
+                remote: path.remote_addr(),
+            },
+            duration,
+            lost_packets,
+            congestion_window,
+        }))
+    }
+
+    /// Move one of the two probe packets of a path to another active path
+    /// without pending probes, so that a probe is delivered even if the path
+    /// suffers from a blackout.
+    fn spread_pto_probes(&mut self) {
+        let paths: Vec<(usize, SpaceId)> = self
+            .paths
+            .iter()
+            .filter(|(_, p)| p.active() && p.validated())
+            .map(|(pid, p)| (pid, p.space_id))
+            .collect();
+
+        for (pid, space_id) in paths.iter() {
+            let path = match self.paths.get(*pid) {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            if !path.recovery.pto_probes_across_paths() {
+                continue;
+            }
+            let loss_probes = |spaces: &space::PacketNumSpaceMap, sid: SpaceId| {
+                spaces.get(sid).map_or(0, |s| s.loss_probes)
+            };
+            if loss_probes(&self.spaces, *space_id) < 2 {
+                continue;
+            }
+            let target = paths
+                .iter()
+                .find(|(p, s)| p != pid && s != space_id && loss_probes(&self.spaces, *s) == 0);
+            let Some((target_pid, target_space_id)) = target else {
+                continue;
+            };
+
+            if let Some(space) = self.spaces.get_mut(*space_id) {
+                space.loss_probes -= 1;
+            }
+            if let Some(space) = self.spaces.get_mut(*target_space_id) {
+                space.loss_probes += 1;
+            }
+            debug!(
+                "{} move a probe packet from path {} to path {}",
+                self.trace_id, pid, target_pid
+            );
+        }
+    }
+
     /// Process timeout event on the connection.
     pub(crate) fn on_timeout(&mut self, now: time::Instant) {
         for timer in Timer::iter() {
@@ -3242,6 +4517,9 @@ impl Connection {
                             );
                             self.stats.lost_count += lost_pkts;
                             self.stats.lost_bytes += lost_bytes;
+                            if let Some(event) = Self::persistent_congestion_event(path) {
+                                self.events.add(event);
+                            }
 
                             // Write RecoveryMetricsUpdate event to qlog.
                             if let Some(qlog) = &mut self.qlog {
@@ -3249,6 +4527,9 @@ impl Connection {
                             }
                         }
                     }
+                    if self.flags.contains(EnableMultipath) {
+                        self.spread_pto_probes();
+                    }
                 }
 
                 Timer::Ack => {
@@ -3286,10 +4567,19 @@ impl Connection {
 
                 Timer::KeyDiscard => self.tls_session.discard_prev_key(),
 
-                Timer::KeepAlive => (), // TODO: schedule an outgoing Ping
+                Timer::KeepAlive => {
+                    if self.keep_alive.on_timeout(now) {
+                        let _ = self.paths.mark_ping(None);
+                    }
+                }
 
                 Timer::PathChallenge => self.paths.on_path_chal_timeout(now),
 
+                Timer::Coalescing => {
+                    self.flags.insert(FlushStreamData);
+                    self.mark_tickable(true);
+                }
+
                 Timer::Handshake => {
                     info!("{} handshake timeout", self.trace_id);
                     self.flags.insert(Closed);
@@ -3297,6 +4587,10 @@ impl Connection {
                 }
             }
         }
+
+        // The receive windows of an idle connection may decay without any
+        // window update.
+        self.decay_recv_windows(None, now);
     }
 
     /// Return the idle timeout of the connection.
@@ -3371,20 +4665,182 @@ impl Connection {
         self.flags.contains(EnableMultipath)
     }
 
-    /// Return the negotiated application level protocol.
-    pub fn application_proto(&self) -> &[u8] {
-        self.tls_session.alpn_protocol()
-    }
+    /// Share the path capacity measured on the connection with the client in
+    /// a BDP_FRAME frame, which could be used to speed up the ramp-up of a
+    /// future connection. It is typically called at the end of a session.
+    /// Applicable to Server only.
+    pub fn send_bdp_frame(&mut self) -> Result<()> {
+        if !self.is_server || !self.is_established() {
+            return Err(Error::InvalidOperation("disallowed".into()));
+        }
+        if !self.flags.contains(EnableBdp) {
+            return Err(Error::InvalidOperation("disallowed".into()));
+        }
 
-    /// Return the server name in the TLS SNI extension.
-    pub fn server_name(&self) -> Option<&str> {
-        self.tls_session.server_name()
+        self.flags.insert(NeedSendBdp);
+        self.mark_tickable(true);
+        Ok(())
     }
 
-    /// Return the session data used by resumption.
-    pub fn session(&self) -> Option<&[u8]> {
-        self.tls_session.session()
-    }
+    /// Request the peer to send an ACK frame immediately with an IMMEDIATE_ACK
+    /// frame, for example to get an RTT sample promptly when ACKs are delayed
+    /// as requested with ACK_FREQUENCY frames. The ACK frequency extension
+    /// should be supported by the peer.
+    pub fn send_immediate_ack(&mut self) -> Result<()> {
+        if !self.is_established() || !self.flags.contains(EnableAckFrequency) {
+            return Err(Error::InvalidOperation("disallowed".into()));
+        }
+
+        self.flags.insert(NeedSendImmediateAck);
+        self.mark_tickable(true);
+        Ok(())
+    }
+
+    /// Issue an address token to the client in a NEW_TOKEN frame, which
+    /// carries the capacity of the active path measured on the connection.
+    /// The token sent by the client in a future connection could be used to
+    /// jump-start the congestion window if Careful Resume is enabled. It is
+    /// typically called at the end of a session. Applicable to Server only.
+    pub fn send_capacity_token(&mut self) -> Result<()> {
+        if !self.is_server || !self.is_established() {
+            return Err(Error::InvalidOperation("disallowed".into()));
+        }
+        let key = match self.address_token_key {
+            Some(ref key) => key,
+            None => return Err(Error::InvalidOperation("disallowed".into())),
+        };
+
+        let path = self.paths.get_active()?;
+        let token = AddressToken::new_capacity_token(
+            path.remote_addr(),
+            path.recovery.congestion.congestion_window(),
+            path.recovery.rtt.min_rtt(),
+        );
+        self.token = Some(token.encode(key)?);
+        self.flags.insert(NeedSendNewToken);
+        self.mark_tickable(true);
+        Ok(())
+    }
+
+    /// Return the smoothed RTT, the minimum RTT and the congestion window of
+    /// the active path, which could be shared with a future connection to the
+    /// same peer. Return None if no RTT sample has been taken on the path.
+    pub(crate) fn path_estimates(&self) -> Option<(time::Duration, time::Duration, u64)> {
+        let recovery = &self.paths.get_active().ok()?.recovery;
+        if !recovery.rtt.has_sample() {
+            return None;
+        }
+        Some((
+            recovery.rtt.smoothed_rtt(),
+            recovery.rtt.min_rtt(),
+            recovery.congestion.congestion_window(),
+        ))
+    }
+
+    /// Initialize the active path with the estimates saved from a previous
+    /// connection to the same peer. The smoothed RTT is used as the initial
+    /// RTT, and the congestion window is jump-started with the saved capacity
+    /// if Careful Resume is enabled. It should be called before any packet is
+    /// sent.
+    pub(crate) fn set_path_estimates(
+        &mut self,
+        srtt: time::Duration,
+        min_rtt: time::Duration,
+        cwnd: u64,
+    ) {
+        let careful_resume = self.recovery_conf.careful_resume;
+        if let Ok(path) = self.paths.get_active_mut() {
+            path.recovery.rtt.try_set_init_rtt(srtt);
+            if careful_resume {
+                path.recovery.enable_careful_resume(cwnd, min_rtt);
+            }
+        }
+    }
+
+    /// Return the path capacity shared by the server in a BDP_FRAME frame, if
+    /// any. The application could save it along with the session state and
+    /// use it to configure a future connection to the same server.
+    /// Applicable to Client only.
+    pub fn peer_bdp(&self) -> Option<&BdpInfo> {
+        self.peer_bdp.as_ref()
+    }
+
+    /// Return the negotiated application level protocol.
+    pub fn application_proto(&self) -> &[u8] {
+        self.tls_session.alpn_protocol()
+    }
+
+    /// Return the server name in the TLS SNI extension.
+    pub fn server_name(&self) -> Option<&str> {
+        self.tls_session.server_name()
+    }
+
+    /// Return the session data used by resumption and 0-RTT.
+    ///
+    /// It is an opaque blob which bundles the session ticket, the transport
+    /// parameters of the server, and the negotiated version and application
+    /// protocol. The client could save it and use it to configure a future
+    /// connection to the same server by `set_session()`.
+    pub fn session(&self) -> Option<&[u8]> {
+        self.tls_session.session()
+    }
+
+    /// Return the session data bundled with the current BDP estimate of the
+    /// connection. It is usually called when the connection is about to be
+    /// closed, and passed to `set_session()` of the next connection to the
+    /// same peer, so that the receive windows are sized by the BDP estimate
+    /// without slow ramp-up. Note that the transport parameters of the next
+    /// connection are still sent as configured, so the seeded windows take
+    /// effect from the first MAX_DATA and MAX_STREAM_DATA frames.
+    pub fn session_with_bdp(&self) -> Option<Vec<u8>> {
+        let session = self.session()?;
+        let bdp = match self.bdp_estimate() {
+            Some(v) => v,
+            None => return Some(session.to_vec()),
+        };
+
+        let mut buf = Vec::with_capacity(session.len() + 24);
+        buf.extend_from_slice(session);
+        buf.extend_from_slice(&bdp.window.to_be_bytes());
+        buf.extend_from_slice(&(bdp.rtt.as_micros() as u64).to_be_bytes());
+        buf.extend_from_slice(&bdp.bandwidth.to_be_bytes());
+        Some(buf)
+    }
+
+    /// Return the current BDP estimate of the connection on the active path.
+    /// The bandwidth is the maximum rate at which the application read data,
+    /// as the receive windows are sized by the receive rate rather than the
+    /// local sending rate.
+    pub fn bdp_estimate(&self) -> Option<BdpEstimate> {
+        let path = self.paths.get_active().ok()?;
+        if path.recovery.rtt.smoothed_rtt().is_zero() {
+            return None;
+        }
+
+        Some(BdpEstimate {
+            window: self.streams.window(),
+            rtt: path.recovery.rtt.min_rtt(),
+            bandwidth: self.max_rx_rate,
+        })
+    }
+
+    /// Update the receive rate with the bytes read by the application. Each
+    /// rate sample lasts for at least one smoothed RTT.
+    fn update_rx_rate(&mut self, read: usize, now: time::Instant) {
+        let srtt = match self.paths.get_active() {
+            Ok(path) => path.recovery.rtt.smoothed_rtt(),
+            Err(_) => return,
+        };
+        let (start, bytes) = self.rx_rate_sample.get_or_insert((now, 0));
+        *bytes += read as u64;
+        let elapsed = now.saturating_duration_since(*start);
+        if srtt.is_zero() || elapsed < srtt {
+            return;
+        }
+        let rate = (*bytes as f64 / elapsed.as_secs_f64()) as u64;
+        self.max_rx_rate = cmp::max(self.max_rx_rate, rate);
+        self.rx_rate_sample = Some((now, 0));
+    }
 
     /// Return details why 0-RTT was accepted or rejected.
     pub fn early_data_reason(&self) -> Result<Option<&str>> {
@@ -3477,6 +4933,38 @@ impl Connection {
         &self.stats
     }
 
+    /// Return the target bitrate in bits per second for the media encoder on
+    /// the active path, if the congestion controller is designed for
+    /// real-time media, such as NADA.
+    pub fn target_bitrate(&self) -> Option<u64> {
+        self.paths.get_active().ok()?.recovery.target_bitrate()
+    }
+
+    /// Return the estimated memory in bytes used by the connection.
+    ///
+    /// It includes the stream data buffered to be sent or read, and the
+    /// metadata of sent packets. Note that the QPACK implementation does not
+    /// use dynamic tables, so there is no memory used by QPACK tables.
+    pub fn memory_usage(&self) -> usize {
+        let mut usage = std::mem::size_of::<Connection>() + self.streams.memory_usage();
+        for (_, s) in self.spaces.iter() {
+            usage += s.sent.len() * std::mem::size_of::<space::SentPacket>();
+            usage += s
+                .sent
+                .iter()
+                .map(|p| p.frames.capacity() * std::mem::size_of::<Frame>())
+                .sum::<usize>();
+        }
+        usage
+    }
+
+    /// Update the memory usage in the statistics of the connection, and
+    /// return the previous value.
+    pub(crate) fn update_memory_usage(&mut self) -> u64 {
+        let usage = self.memory_usage() as u64;
+        std::mem::replace(&mut self.stats.memory_usage, usage)
+    }
+
     /// Discard packet number space and related secrets.
     ///
     /// After QUIC has completed a move to a new encryption level, packet
@@ -3641,7 +5129,12 @@ impl Connection {
     /// quic_transport_parameters extension in either the ClientHello or
     /// EncryptedExtensions handshake message.
     fn set_transport_params(&mut self) -> Result<()> {
-        let mut raw_params = [0; 128];
+        self.local_transport_params.version_information = Some(VersionInformation {
+            chosen_version: self.version,
+            available_versions: self.versions.clone(),
+        });
+
+        let mut raw_params = [0; 256];
         let len = TransportParams::encode(
             &self.local_transport_params,
             self.is_server,
@@ -3670,6 +5163,22 @@ impl Connection {
         self.paths.mark_ping(path_addr)
     }
 
+    /// Send a Ping frame carrying the given tag on the active path.
+    ///
+    /// When the Ping frame is acknowledged by the peer, the RTT measured for
+    /// the probe is reported along with the tag by `on_ping_acked()` of the
+    /// transport handler. If the Ping frame is lost, it is sent again.
+    pub fn ping_with_tag(&mut self, tag: u64) -> Result<()> {
+        if !self.is_established() || self.is_closing() {
+            return Err(Error::InvalidOperation("disallowed".into()));
+        }
+
+        self.ping_tags.push_back(tag);
+        self.paths.get_active_mut()?.need_send_ping = true;
+        self.mark_tickable(true);
+        Ok(())
+    }
+
     /// Client add a new path on the connection.
     pub fn add_path(&mut self, local_addr: SocketAddr, remote_addr: SocketAddr) -> Result<u64> {
         if self.is_server {
@@ -3769,6 +5278,98 @@ impl Connection {
         Ok(self.paths.get_mut(pid)?.stats())
     }
 
+    /// Return the latest statistics about the congestion controller of the
+    /// specified path, including the internal state of the algorithm.
+    pub fn get_congestion_stats(
+        &self,
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+    ) -> Result<CongestionStats> {
+        let pid = self
+            .paths
+            .get_path_id(&(local_addr, remote_addr))
+            .ok_or(Error::InvalidOperation("not found".into()))?;
+        Ok(self.paths.get(pid)?.congestion_stats())
+    }
+
+    /// Set the upper limit of the pacing rate in bytes per second for all
+    /// paths on the live connection, and for the paths created later. It caps
+    /// the sending rate of each path regardless of the congestion controller,
+    /// and pacing is always applied if the limit is set. The value `0` means
+    /// no limit.
+    pub fn set_max_pacing_rate(&mut self, v: u64) {
+        self.recovery_conf.max_pacing_rate = if v > 0 { Some(v) } else { None };
+        for (_, path) in self.paths.iter_mut() {
+            path.recovery.set_max_pacing_rate(&self.recovery_conf);
+        }
+        self.mark_tickable(true);
+    }
+
+    /// Switch the congestion control algorithm of all paths on the live
+    /// connection, and of the paths created later. The RTT samples of each
+    /// path are kept, and the congestion window is migrated to the new
+    /// congestion controller if the old one has left slow start. The
+    /// congestion controller statistics restart with the new controller.
+    ///
+    /// Note that it takes precedence over the congestion controller factory.
+    /// The ECN codepoint of the outgoing packets follows the new congestion
+    /// controller, i.e. ECT(1) for the L4S congestion controllers and ECT(0)
+    /// otherwise.
+    pub fn set_congestion_control_algorithm(&mut self, algor: CongestionControlAlgorithm) {
+        self.recovery_conf.congestion_control_algorithm = algor;
+        self.recovery_conf.congestion_controller_factory = None;
+
+        let now = Instant::now();
+        for (_, path) in self.paths.iter_mut() {
+            path.recovery
+                .switch_congestion_controller(&self.recovery_conf, now);
+        }
+    }
+
+    /// Set the policy of using ECN on the specified path, and restart the ECN
+    /// validation on the path.
+    pub fn set_path_ecn_policy(
+        &mut self,
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+        policy: EcnPolicy,
+    ) -> Result<()> {
+        let pid = self
+            .paths
+            .get_path_id(&(local_addr, remote_addr))
+            .ok_or(Error::InvalidOperation("not found".into()))?;
+        self.paths.get_mut(pid)?.recovery.ecn.set_policy(policy);
+        Ok(())
+    }
+
+    /// Set the DSCP codepoint of the outgoing packets of the connection. Only
+    /// the lower 6 bits are used.
+    pub fn set_dscp(&mut self, dscp: u8) {
+        self.dscp = dscp & crate::MAX_DSCP;
+    }
+
+    /// Return the DSCP codepoint of the outgoing packets of the connection.
+    pub fn dscp(&self) -> u8 {
+        self.dscp
+    }
+
+    /// Set the DSCP codepoint of the outgoing packets on the specified path.
+    /// It overrides the value of the connection for the path. Only the lower
+    /// 6 bits are used.
+    pub fn set_path_dscp(
+        &mut self,
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+        dscp: u8,
+    ) -> Result<()> {
+        let pid = self
+            .paths
+            .get_path_id(&(local_addr, remote_addr))
+            .ok_or(Error::InvalidOperation("not found".into()))?;
+        self.paths.get_mut(pid)?.dscp = Some(dscp & crate::MAX_DSCP);
+        Ok(())
+    }
+
     /// Migrates the connection to the specified path.
     #[doc(hidden)]
     pub fn migrate_path(&mut self, local_addr: SocketAddr, remote_addr: SocketAddr) -> Result<()> {
@@ -3810,7 +5411,24 @@ impl Connection {
     /// Return true if the stream has enough flow control capacity to send data
     /// and application wants to send more data.
     pub(crate) fn stream_check_writable(&self, stream_id: u64) -> bool {
-        self.streams.check_writable(stream_id)
+        if !self.streams.check_writable(stream_id) {
+            return false;
+        }
+
+        // The congestion window is also taken into account if the application
+        // has set a writable threshold for the stream.
+        let thresh = match self.streams.write_threshold(stream_id) {
+            Some(v) => v,
+            None => return true,
+        };
+        match self.paths.get_active() {
+            Ok(path) => {
+                let recovery = &path.recovery;
+                let cwnd = recovery.congestion.congestion_window() as usize;
+                cwnd.saturating_sub(recovery.bytes_in_flight) >= thresh
+            }
+            Err(_) => false,
+        }
     }
 
     /// Return true if application wants to read more data from the stream.
@@ -3824,12 +5442,76 @@ impl Connection {
         self.streams.want_write(stream_id, want)
     }
 
+    /// Set want write flag for a stream with a writable threshold.
+    ///
+    /// The application is notified that the stream is writable only when at
+    /// least `bytes` bytes of send credit, limited by both flow control and the
+    /// congestion window, is available. It avoids frequent wakeups for the
+    /// application writing data in large chunks. Setting `bytes` to `0` removes
+    /// the threshold.
+    pub fn stream_want_write_threshold(&mut self, stream_id: u64, bytes: usize) -> Result<()> {
+        self.mark_tickable(true);
+        self.streams.want_write_threshold(stream_id, bytes)
+    }
+
+    /// Pause or resume the advertisement of the stream-level flow control limit.
+    ///
+    /// While paused, no MAX_STREAM_DATA frame is sent for the stream regardless
+    /// of how much data has been read, so that the application can explicitly
+    /// withhold credit from the peer, e.g. to propagate backpressure in a proxy.
+    pub fn stream_set_flow_control_paused(&mut self, stream_id: u64, paused: bool) -> Result<()> {
+        self.mark_tickable(true);
+        self.streams
+            .stream_set_flow_control_paused(stream_id, paused)
+    }
+
+    /// Pause or resume the advertisement of the connection-level flow control
+    /// limit.
+    ///
+    /// While paused, no MAX_DATA frame is sent regardless of how much data has
+    /// been read.
+    pub fn set_flow_control_paused(&mut self, paused: bool) {
+        self.mark_tickable(true);
+        self.streams.set_flow_control_paused(paused);
+    }
+
     /// Set want read flag for a stream.
     pub fn stream_want_read(&mut self, stream_id: u64, want: bool) -> Result<()> {
         self.mark_tickable(true);
         self.streams.want_read(stream_id, want)
     }
 
+    /// Subscribe to or unsubscribe from the reset event of a stream.
+    ///
+    /// The application is notified by `TransportHandler::on_stream_event()`
+    /// once a RESET_STREAM frame is received, regardless of whether it wants
+    /// to read from the stream.
+    pub fn stream_want_reset(&mut self, stream_id: u64, want: bool) -> Result<()> {
+        self.mark_tickable(true);
+        self.streams.want_reset(stream_id, want)
+    }
+
+    /// Subscribe to or unsubscribe from the stop sending event of a stream.
+    ///
+    /// The application is notified by `TransportHandler::on_stream_event()`
+    /// once a STOP_SENDING frame is received, regardless of whether it wants
+    /// to write to the stream.
+    pub fn stream_want_stop_sending(&mut self, stream_id: u64, want: bool) -> Result<()> {
+        self.mark_tickable(true);
+        self.streams.want_stop_sending(stream_id, want)
+    }
+
+    /// Subscribe to the acknowledgement of the data sent on a stream.
+    ///
+    /// The application is notified by `TransportHandler::on_stream_event()`
+    /// once the data is acknowledged contiguously up to `offset`, e.g. to
+    /// release the resources held for the data. Setting `offset` to `0`
+    /// removes the subscription.
+    pub fn stream_want_acked(&mut self, stream_id: u64, offset: u64) -> Result<()> {
+        self.mark_tickable(true);
+        self.streams.want_acked(stream_id, offset)
+    }
+
     /// Read data from a stream
     pub fn stream_read(&mut self, stream_id: u64, out: &mut [u8]) -> Result<(usize, bool)> {
         self.mark_tickable(true);
@@ -3841,6 +5523,9 @@ impl Connection {
                 if let Some(qlog) = &mut self.qlog {
                     Self::qlog_transport_data_read(qlog, stream_id, read_off.unwrap_or(0), read);
                 }
+                let now = time::Instant::now();
+                self.update_rx_rate(read, now);
+                self.decay_recv_windows(Some(stream_id), now);
 
                 Ok((read, fin))
             }
@@ -3849,6 +5534,13 @@ impl Connection {
     }
 
     /// Write data to a stream.
+    ///
+    /// The ownership of the buffer is taken, and the buffer is kept in the
+    /// send buffer of the stream without being copied until the data is
+    /// acknowledged by the peer. A buffer shared with other `Bytes` handles
+    /// (e.g. slices of a larger buffer received from another connection) is
+    /// only released once all of them are dropped. If the buffer is partially
+    /// written, the remaining data can be written later by slicing the buffer.
     pub fn stream_write(&mut self, stream_id: u64, buf: Bytes, fin: bool) -> Result<usize> {
         self.mark_tickable(true);
         let write_off = self.streams.stream_write_offset(stream_id);
@@ -3910,6 +5602,22 @@ impl Connection {
             .stream_set_priority(stream_id, urgency, incremental)
     }
 
+    /// Set the receive-side flow control window and maximum window for a stream.
+    ///
+    /// This allows applications to give a large window to bulk transfer
+    /// streams and a small one to control streams on the same connection.
+    /// Credit that has already been issued to the peer is never withdrawn.
+    pub fn stream_set_recv_window(
+        &mut self,
+        stream_id: u64,
+        window: u64,
+        max_window: u64,
+    ) -> Result<()> {
+        self.mark_tickable(true);
+        self.streams
+            .stream_set_recv_window(stream_id, window, max_window)
+    }
+
     /// Return the stream's send capacity in bytes.
     pub fn stream_capacity(&self, stream_id: u64) -> Result<usize> {
         self.streams.stream_capacity(stream_id)
@@ -3945,6 +5653,17 @@ impl Connection {
         self.streams.stream_context(stream_id)
     }
 
+    /// Set the hook which decides whether the lost data of a stream is
+    /// retransmitted. If the hook skips the retransmission, the send-side of
+    /// the stream is reset. The default is to always retransmit.
+    pub fn stream_set_loss_policy(
+        &mut self,
+        stream_id: u64,
+        policy: Option<Arc<dyn StreamLossPolicy>>,
+    ) -> Result<()> {
+        self.streams.stream_set_loss_policy(stream_id, policy)
+    }
+
     /// Return immutable reference to streams
     pub(crate) fn get_streams(&self) -> &stream::StreamMap {
         &self.streams
@@ -3975,6 +5694,76 @@ impl Connection {
         self.queues = Some(queues);
     }
 
+    /// Set the receive window budget shared by the endpoint and the
+    /// connection, and reserve the current receive window from it.
+    pub(crate) fn set_recv_budget(&mut self, budget: Rc<RefCell<RecvWindowBudget>>) {
+        self.release_recv_budget();
+        self.recv_budget_reserved = self.streams.window();
+        budget.borrow_mut().reserve(self.recv_budget_reserved);
+        self.recv_budget = Some(budget);
+    }
+
+    /// Release the receive window reserved from the shared budget.
+    pub(crate) fn release_recv_budget(&mut self) {
+        if let Some(budget) = self.recv_budget.take() {
+            budget.borrow_mut().release(self.recv_budget_reserved);
+        }
+        self.recv_budget_reserved = 0;
+    }
+
+    /// Adjust the connection receive window before sending a MAX_DATA frame.
+    ///
+    /// If a shared budget is set, autotuning is suppressed when the budget is
+    /// tight, and the window growth is limited to the remaining budget.
+    fn tune_recv_window(&mut self, now: time::Instant, srtt: time::Duration) {
+        let budget = match &self.recv_budget {
+            Some(v) => v,
+            None => {
+                self.streams.autotune_window(now, srtt);
+                return;
+            }
+        };
+
+        let mut budget = budget.borrow_mut();
+        if !budget.is_tight() {
+            self.streams.autotune_window(now, srtt);
+        }
+        let window = self.streams.window();
+        let granted = budget.resize(self.recv_budget_reserved, window);
+        if granted < window {
+            self.streams.limit_window(granted);
+        }
+
+        // The window already advertised to the peer is always reserved.
+        let window = self.streams.window();
+        if window > granted {
+            budget.reserve(window - granted);
+        }
+        self.recv_budget_reserved = window;
+    }
+
+    /// Let the receive windows of the connection and the given stream decay
+    /// if they are underutilized, and give the released window back to the
+    /// shared budget.
+    fn decay_recv_windows(&mut self, stream_id: Option<u64>, now: time::Instant) {
+        let srtt = match self.paths.get_active() {
+            Ok(path) => path.recovery.rtt.smoothed_rtt(),
+            Err(_) => return,
+        };
+        self.streams.decay_window(stream_id, now, srtt);
+
+        // The credit advertised to the peer is kept reserved.
+        let window = cmp::max(self.streams.window(), self.streams.advertised_window());
+        if let Some(budget) = &self.recv_budget {
+            if window < self.recv_budget_reserved {
+                budget
+                    .borrow_mut()
+                    .resize(self.recv_budget_reserved, window);
+                self.recv_budget_reserved = window;
+            }
+        }
+    }
+
     /// Client start handshake.
     pub(crate) fn start_handshake(&mut self) -> Result<()> {
         if self.is_server {
@@ -4005,6 +5794,7 @@ impl Connection {
             || !self.streams.events.is_empty()
             || self.streams.has_readable()
             || self.streams.has_writable()
+            || self.has_pending_acks()
             || self.is_closed()
     }
 
@@ -4219,18 +6009,38 @@ impl Connection {
 /// A set of crypto streams for Initial/Handshake/1RTT level.
 struct CryptoStreams {
     streams: [Stream; 3],
+
+    /// Maximum size of buffered out-of-order crypto data for each level.
+    max_buffer_size: u64,
 }
 
 impl CryptoStreams {
     /// Create crypto streams for Initial/Handshake/1RTT level.
-    pub fn new() -> Self {
+    pub fn new(max_buffer_size: u64) -> Self {
         CryptoStreams {
             streams: [
                 CryptoStreams::new_stream(),
                 CryptoStreams::new_stream(),
                 CryptoStreams::new_stream(),
             ],
+            max_buffer_size,
+        }
+    }
+
+    /// Write crypto data received from the peer to the crypto stream for the
+    /// given encryption level.
+    ///
+    /// Return `Error::CryptoBufferExceeded` if the data is beyond the limit of
+    /// out-of-order crypto data that can be buffered.
+    /// See RFC 9000 Section 7.5
+    pub fn recv(&mut self, level: Level, offset: u64, data: Bytes) -> Result<()> {
+        let max_buffer_size = self.max_buffer_size;
+        let stream = self.get_mut(level)?;
+        let max_off = offset.saturating_add(data.len() as u64);
+        if max_off > stream.recv.read_off().saturating_add(max_buffer_size) {
+            return Err(Error::CryptoBufferExceeded);
         }
+        stream.recv.write(offset, data, false)
     }
 
     /// Get crypto stream for the given encryption level.
@@ -4250,7 +6060,7 @@ impl CryptoStreams {
                 self.streams[0] = CryptoStreams::new_stream();
             }
             Level::Handshake => {
-                self.streams[0] = CryptoStreams::new_stream();
+                self.streams[1] = CryptoStreams::new_stream();
             }
             _ => (),
         }
@@ -4276,10 +6086,11 @@ struct UndecryptablePackets {
 
 impl UndecryptablePackets {
     fn new(capacity: usize) -> Self {
+        // Undecryptable packets are rare, so the queues are allocated lazily.
         Self {
-            zerortt_pkts: VecDeque::with_capacity(capacity),
-            handshake_pkts: VecDeque::with_capacity(capacity),
-            onertt_pkts: VecDeque::with_capacity(capacity),
+            zerortt_pkts: VecDeque::new(),
+            handshake_pkts: VecDeque::new(),
+            onertt_pkts: VecDeque::new(),
             capacity,
         }
     }
@@ -4410,6 +6221,88 @@ enum ConnectionFlags {
 
     /// The disable_1rtt_encryption is successfully negotiated.
     DisableEncryption = 1 << 21,
+
+    /// The stream data held for coalescing should be sent.
+    FlushStreamData = 1 << 22,
+
+    /// The BDP_FRAME extension is successfully negotiated.
+    EnableBdp = 1 << 23,
+
+    /// A BDP_FRAME frame should be sent.
+    NeedSendBdp = 1 << 24,
+
+    /// The OBSERVED_ADDRESS frames should be sent to the peer.
+    SendObservedAddress = 1 << 25,
+
+    /// The OBSERVED_ADDRESS frames are expected from the peer.
+    RecvObservedAddress = 1 << 26,
+
+    /// The QUIC Bit of outgoing packets could be greased.
+    GreaseQuicBit = 1 << 27,
+
+    /// The ACK frequency extension is supported by the peer.
+    EnableAckFrequency = 1 << 28,
+
+    /// An ACK_FREQUENCY frame should be sent.
+    NeedSendAckFrequency = 1 << 29,
+
+    /// An IMMEDIATE_ACK frame should be sent.
+    NeedSendImmediateAck = 1 << 30,
+}
+
+/// The acknowledgement behavior requested by the peer in an ACK_FREQUENCY
+/// frame. See draft-ietf-quic-ack-frequency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct AckFrequency {
+    /// The sequence number of the ACK_FREQUENCY frame.
+    seq_num: u64,
+
+    /// The maximum number of ack-eliciting packets that can go unacknowledged.
+    ack_eliciting_threshold: u64,
+
+    /// The maximum amount of time to delay an acknowledgement.
+    max_ack_delay: time::Duration,
+
+    /// The number of out-of-order packets that triggers an acknowledgement.
+    reordering_threshold: u64,
+}
+
+/// The path capacity saved by the server and shared in a BDP_FRAME frame.
+/// See draft-kuhn-quic-bdpframe-extension.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BdpInfo {
+    /// The duration for which the saved values are valid.
+    pub lifetime: time::Duration,
+
+    /// The saved bytes-in-flight capacity of the path.
+    pub saved_capacity: u64,
+
+    /// The saved minimum RTT of the path.
+    pub saved_rtt: time::Duration,
+}
+
+/// An estimate of the bandwidth-delay product (BDP) of a connection, which can
+/// be persisted with the session data to seed the receive windows of the next
+/// connection to the same peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BdpEstimate {
+    /// The receive window of the connection, in bytes.
+    pub window: u64,
+
+    /// The minimum RTT of the connection.
+    pub rtt: time::Duration,
+
+    /// The maximum receive rate of the connection, in bytes per second.
+    pub bandwidth: u64,
+}
+
+impl BdpEstimate {
+    /// Return the receive window seeded by the estimate. It is large enough
+    /// to avoid window updates occurring within 2 RTTs.
+    fn seed_window(&self) -> u64 {
+        let bdp = (self.bandwidth as f64 * self.rtt.as_secs_f64()) as u64;
+        cmp::max(self.window, bdp.saturating_mul(2))
+    }
 }
 
 /// Statistics about a QUIC connection.
@@ -4433,6 +6326,38 @@ pub struct ConnectionStats {
 
     /// Total number of bytes lost on the connection.
     pub lost_bytes: u64,
+
+    /// Estimated memory in bytes used by the connection, which is updated
+    /// each time the connection is processed by the endpoint.
+    pub memory_usage: u64,
+
+    /// Number of times the send-side was blocked by the connection-level
+    /// flow control.
+    pub data_blocked_count: u64,
+
+    /// Total duration in microseconds the send-side was blocked by the
+    /// connection-level flow control.
+    pub data_blocked_duration: u64,
+
+    /// Number of times the streams were blocked by the stream-level flow
+    /// control.
+    pub stream_data_blocked_count: u64,
+
+    /// Total duration in microseconds the streams were blocked by the
+    /// stream-level flow control.
+    pub stream_data_blocked_duration: u64,
+
+    /// Total number of MAX_DATA frames sent.
+    pub max_data_sent_count: u64,
+
+    /// Total number of MAX_DATA frames received.
+    pub max_data_recv_count: u64,
+
+    /// Total number of MAX_STREAM_DATA frames sent.
+    pub max_stream_data_sent_count: u64,
+
+    /// Total number of MAX_STREAM_DATA frames received.
+    pub max_stream_data_recv_count: u64,
 }
 
 /// FrameWriteStatus is used to collect various states during writing frames
@@ -4458,6 +6383,9 @@ struct FrameWriteStatus {
     /// Whether it contains a PATH_CHALLENGE frame
     challenge: Option<[u8; 8]>,
 
+    /// Whether it contains a PATH_CHALLENGE frame for keep-alive probing
+    keep_alive_probe: Option<[u8; 8]>,
+
     /// Whether a PING frame should be added to elicit an ACK from the peer.
     ack_elicit_required: bool,
 
@@ -4504,6 +6432,7 @@ pub(crate) mod tests {
     use crate::CongestionControlAlgorithm;
     use crate::ConnectionIdGenerator;
     use crate::RandomConnectionIdGenerator;
+    use crate::StreamEvent;
     use bytes::BytesMut;
     use rand::prelude::SliceRandom;
     use rand::thread_rng;
@@ -4817,6 +6746,9 @@ pub(crate) mod tests {
                 src: if is_server { server_addr } else { client_addr },
                 dst: if is_server { client_addr } else { server_addr },
                 time: time::Instant::now(),
+                ecn: Ecn::NotEct,
+                dscp: 0,
+                segment_size: 0,
             }
         }
 
@@ -5042,6 +6974,40 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    struct TestCongestionControlSelector;
+
+    impl CongestionControlSelector for TestCongestionControlSelector {
+        fn select(
+            &self,
+            _peer_addr: SocketAddr,
+            server_name: Option<&str>,
+            alpn: &[u8],
+            conf: &mut RecoveryConfig,
+        ) {
+            if server_name == Some("example.org") && alpn == b"h3" {
+                conf.congestion_control_algorithm = CongestionControlAlgorithm::Bbr;
+            }
+        }
+    }
+
+    #[test]
+    fn handshake_with_congestion_control_selector() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_congestion_control_selector(Arc::new(TestCongestionControlSelector));
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_congestion_control_selector(Arc::new(TestCongestionControlSelector));
+
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        // The selector is only consulted by the server.
+        let path = test_pair.server.paths.get_active()?;
+        assert_eq!(path.recovery.congestion.name(), "BBR");
+        let path = test_pair.client.paths.get_active()?;
+        assert_eq!(path.recovery.congestion.name(), "CUBIC");
+        Ok(())
+    }
+
     #[test]
     fn handshake_resume() -> Result<()> {
         let mut client_config = TestPair::new_test_config(false)?;
@@ -5073,20 +7039,178 @@ pub(crate) mod tests {
     }
 
     #[test]
-    fn handshake_confirm() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-
-        // Client send Initial
-        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+    fn handshake_resume_remembered_params() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
 
-        // Server send Initial and Handshake
-        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
-        assert_eq!(test_pair.client.is_established(), false);
-        assert_eq!(test_pair.client.is_confirmed(), false);
-        assert_eq!(test_pair.server.is_established(), false);
-        assert_eq!(test_pair.server.is_confirmed(), false);
-        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let session = test_pair.client.session().unwrap();
+        let peer_params = test_pair.client.peer_transport_params.clone();
+        assert!(peer_params.stateless_reset_token.is_some());
+
+        // Client only applies the remembered parameters allowed for 0-RTT
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        test_pair.client.set_session(&session)?;
+        let params = &test_pair.client.peer_transport_params;
+        assert_eq!(params.initial_max_data, peer_params.initial_max_data);
+        assert_eq!(
+            params.initial_max_streams_bidi,
+            peer_params.initial_max_streams_bidi
+        );
+        assert_eq!(
+            params.max_ack_delay,
+            TransportParams::default().max_ack_delay
+        );
+        assert_eq!(params.initial_source_connection_id, None);
+        assert_eq!(params.original_destination_connection_id, None);
+        assert_eq!(params.stateless_reset_token, None);
+
+        // The parameters from the current handshake are used
+        assert_eq!(test_pair.handshake(), Ok(()));
+        assert_eq!(test_pair.client.is_resumed(), true);
+        assert!(test_pair
+            .client
+            .peer_transport_params
+            .stateless_reset_token
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_resume_with_bdp() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let session = test_pair.client.session().unwrap().to_vec();
+        assert!(test_pair.client.bdp_estimate().is_some());
+        let session_with_bdp = test_pair.client.session_with_bdp().unwrap();
+        assert_eq!(session_with_bdp.len(), session.len() + 24);
+        assert_eq!(session_with_bdp[..session.len()], session[..]);
+
+        // Client seeds the receive windows with the BDP estimate
+        let bdp = BdpEstimate {
+            window: 1000,
+            rtt: time::Duration::from_millis(100),
+            bandwidth: 100000,
+        };
+        let mut buf = session.clone();
+        buf.extend_from_slice(&bdp.window.to_be_bytes());
+        buf.extend_from_slice(&(bdp.rtt.as_micros() as u64).to_be_bytes());
+        buf.extend_from_slice(&bdp.bandwidth.to_be_bytes());
+
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        test_pair.client.set_session(&buf)?;
+        assert_eq!(bdp.seed_window(), 20000);
+        assert_eq!(test_pair.client.streams.window(), 20000);
+        assert_eq!(test_pair.handshake(), Ok(()));
+        assert_eq!(test_pair.client.is_resumed(), true);
+
+        // Client ignores a truncated BDP estimate
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        test_pair.client.set_session(&buf[..buf.len() - 1])?;
+        assert_eq!(test_pair.client.streams.window(), 90);
+        assert_eq!(test_pair.handshake(), Ok(()));
+        assert_eq!(test_pair.client.is_resumed(), false);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bdp_estimate_with_rx_rate() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let conn = &mut test_pair.client;
+        assert_eq!(conn.bdp_estimate().unwrap().bandwidth, 0);
+
+        // The rate sample lasts for at least one smoothed RTT
+        let srtt = conn.paths.get_active()?.recovery.rtt.smoothed_rtt();
+        let now = time::Instant::now();
+        conn.update_rx_rate(1000, now);
+        conn.update_rx_rate(1000, now + srtt / 2);
+        assert_eq!(conn.max_rx_rate, 0);
+        conn.update_rx_rate(1000, now + srtt);
+        let rate = (3000.0 / srtt.as_secs_f64()) as u64;
+        assert_eq!(conn.bdp_estimate().unwrap().bandwidth, rate);
+
+        // The maximum receive rate is kept
+        conn.update_rx_rate(0, now + srtt * 2);
+        assert_eq!(conn.bdp_estimate().unwrap().bandwidth, rate);
+
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_resume_with_invalid_session() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let session = test_pair.client.session().unwrap().to_vec();
+
+        // Client fall back to a full handshake for the malformed session data,
+        // or the session data of an unsupported format
+        assert_eq!(session[0], tls::SESSION_FORMAT_VERSION);
+        let sessions: [&[u8]; 4] = [&session[..session.len() - 1], &session[1..], &[0; 16], &[]];
+        for session in sessions {
+            let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+            test_pair.client.set_session(session)?;
+            assert!(!test_pair.client.is_in_early_data());
+            assert_eq!(test_pair.handshake(), Ok(()));
+            assert_eq!(test_pair.client.is_resumed(), false);
+        }
+
+        // Server could not set session data
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert!(test_pair.server.set_session(&session).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_resume_with_mismatched_version() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_versions(&[crate::QUIC_VERSION_V1, crate::QUIC_VERSION_V2])?;
+
+        // Client perform the first handshake with version 1
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let session = test_pair.client.session().unwrap().to_vec();
+
+        // Client fall back to a full handshake with version 2
+        client_config.set_versions(&[crate::QUIC_VERSION_V2])?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        test_pair.client.set_session(&session)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!test_pair.client.is_in_early_data());
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        assert_eq!(test_pair.client.version, crate::QUIC_VERSION_V2);
+        assert_eq!(test_pair.client.is_resumed(), false);
+
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_confirm() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+
+        // Client send Initial
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+
+        // Server send Initial and Handshake
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        assert_eq!(test_pair.client.is_established(), false);
+        assert_eq!(test_pair.client.is_confirmed(), false);
+        assert_eq!(test_pair.server.is_established(), false);
+        assert_eq!(test_pair.server.is_confirmed(), false);
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
 
         // Client send Handshake and completes handshake.
         let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
@@ -5125,12 +7249,16 @@ pub(crate) mod tests {
         let (initial_pkt, initial_info) = packets.pop().unwrap();
         let hdr = PacketHeader::from_bytes(&initial_pkt, 20)?.0;
         let mut buf = vec![0; 256];
-        let len = packet::version_negotiation(&hdr.dcid, &hdr.scid, &mut buf)?;
+        let versions = [crate::QUIC_VERSION_V1];
+        let len = packet::version_negotiation(&hdr.dcid, &hdr.scid, &versions, &mut buf)?;
         buf.truncate(len);
         let info = PacketInfo {
             src: initial_info.dst,
             dst: initial_info.src,
             time: initial_info.time,
+            ecn: initial_info.ecn,
+            dscp: initial_info.dscp,
+            segment_size: 0,
         };
 
         // Client drop the Version Negotiation packet with the same version.
@@ -5145,6 +7273,136 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    /// Inject a Version Negotiation packet to the client in response to its
+    /// first Initial packet, which is dropped.
+    fn inject_version_negotiation(test_pair: &mut TestPair, versions: &[u32]) -> Result<()> {
+        let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        let (initial_pkt, initial_info) = packets.pop().unwrap();
+        let hdr = PacketHeader::from_bytes(&initial_pkt, 20)?.0;
+        let mut buf = vec![0; 256];
+        let len = packet::version_negotiation(&hdr.dcid, &hdr.scid, versions, &mut buf)?;
+        buf.truncate(len);
+        let info = PacketInfo {
+            src: initial_info.dst,
+            dst: initial_info.src,
+            time: initial_info.time,
+            ecn: initial_info.ecn,
+            dscp: initial_info.dscp,
+            segment_size: 0,
+        };
+        TestPair::conn_packets_in(&mut test_pair.client, vec![(buf, info)])
+    }
+
+    #[test]
+    fn handshake_with_version_v2() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_versions(&[crate::QUIC_VERSION_V2])?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_versions(&[crate::QUIC_VERSION_V2])?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        let hdr = PacketHeader::from_bytes(&packets[0].0, 20)?.0;
+        assert_eq!(hdr.version, crate::QUIC_VERSION_V2);
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+
+        assert_eq!(test_pair.handshake(), Ok(()));
+        assert_eq!(test_pair.client.version, crate::QUIC_VERSION_V2);
+        assert_eq!(test_pair.server.version, crate::QUIC_VERSION_V2);
+
+        // Data could be exchanged after the key update
+        let space = test_pair
+            .client
+            .spaces
+            .get_mut(SpaceId::Data)
+            .ok_or(Error::InternalError)?;
+        test_pair
+            .client
+            .tls_session
+            .initiate_key_update(space, false)?;
+        let data = TestPair::new_test_data(1024);
+        test_pair.client.stream_write(0, data, true)?;
+        test_pair.move_forward()?;
+        assert_eq!(test_pair.server.stream_readable(0), true);
+        assert!(test_pair.server.tls_session.current_key_phase());
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_with_pinned_version() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_versions(&[crate::QUIC_VERSION_V1])?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_versions(&[crate::QUIC_VERSION_V2])?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+
+        // Server reject the Initial packet with QUIC version 1
+        assert_eq!(test_pair.handshake(), Err(Error::UnknownVersion));
+
+        // Client doesn't support versions in the Version Negotiation packet
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(
+            inject_version_negotiation(&mut test_pair, &[crate::QUIC_VERSION_V2]),
+            Err(Error::UnknownVersion)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_with_incompatible_version_negotiation() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_versions(&[crate::QUIC_VERSION_V2, crate::QUIC_VERSION_V1])?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_versions(&[crate::QUIC_VERSION_V1])?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+
+        // Client switch to QUIC version 1 which is supported by the server
+        inject_version_negotiation(&mut test_pair, &[crate::QUIC_VERSION_V1])?;
+        assert_eq!(test_pair.client.version, crate::QUIC_VERSION_V1);
+        assert!(test_pair.client.flags.contains(DidVersionNegotiation));
+
+        assert_eq!(test_pair.handshake(), Ok(()));
+        assert_eq!(test_pair.server.version, crate::QUIC_VERSION_V1);
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_with_version_downgrade() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_versions(&[crate::QUIC_VERSION_V2, crate::QUIC_VERSION_V1])?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_versions(&[crate::QUIC_VERSION_V1, crate::QUIC_VERSION_V2])?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+
+        // An attacker forges a Version Negotiation packet to downgrade the
+        // version, and the client detects it based on the version_information
+        // of the server.
+        inject_version_negotiation(&mut test_pair, &[crate::QUIC_VERSION_V1])?;
+        assert_eq!(test_pair.client.version, crate::QUIC_VERSION_V1);
+        assert_eq!(test_pair.handshake(), Err(Error::VersionNegotiationError));
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_with_mismatched_chosen_version() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+
+        // Client advertises a chosen version different from the long header
+        let mut params = test_pair.client.local_transport_params.clone();
+        params.version_information = Some(VersionInformation {
+            chosen_version: crate::QUIC_VERSION_V2,
+            available_versions: vec![crate::QUIC_VERSION_V2, crate::QUIC_VERSION_V1],
+        });
+        let mut raw_params = [0; 256];
+        let len = TransportParams::encode(&params, false, &mut raw_params)?;
+        test_pair
+            .client
+            .tls_session
+            .set_transport_params(&raw_params[..len])?;
+        assert_eq!(test_pair.handshake(), Err(Error::VersionNegotiationError));
+        Ok(())
+    }
+
     #[test]
     fn handshake_with_retry() -> Result<()> {
         let mut test_pair = TestPair::new_with_test_config()?;
@@ -5179,6 +7437,9 @@ pub(crate) mod tests {
             src: info.dst,
             dst: info.src,
             time: info.time,
+            ecn: info.ecn,
+            dscp: info.dscp,
+            segment_size: 0,
         };
 
         // Client recv Retry
@@ -5743,585 +8004,1793 @@ pub(crate) mod tests {
     }
 
     #[test]
-    fn max_datagram_size() -> Result<()> {
+    fn grease_quic_bit() -> Result<()> {
         let mut client_config = TestPair::new_test_config(false)?;
-        client_config.set_send_udp_payload_size(1200);
+        client_config.enable_grease_quic_bit(true);
         let mut server_config = TestPair::new_test_config(true)?;
-        server_config.set_recv_udp_payload_size(1550);
-        server_config.set_initial_max_data(10000);
-        server_config.set_initial_max_stream_data_bidi_remote(10000);
-        server_config.set_ack_eliciting_threshold(1);
         let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
-        assert_eq!(
-            test_pair.client.paths.get(0)?.recovery.max_datagram_size,
-            1200,
-        );
-
-        // Handshake and discovery path MTU
         assert_eq!(test_pair.handshake(), Ok(()));
         test_pair.move_forward()?;
 
-        // Check path MTU
-        let mds_ipv4 = 1472;
-        assert_eq!(
-            test_pair.client.paths.get(0)?.recovery.max_datagram_size,
-            mds_ipv4
-        );
+        // Only the server greases the QUIC bit
+        assert!(test_pair.server.flags.contains(GreaseQuicBit));
+        assert!(!test_pair.client.flags.contains(GreaseQuicBit));
 
-        // Check outgoing packet size
-        let mut buf = vec![0; 2000];
-        assert!(test_pair
-            .client
-            .stream_write(0, Bytes::from(vec![0; 2000]), true)
-            .is_ok());
-        let r = test_pair.client.send(&mut buf);
-        assert!(r.is_ok());
-        assert_eq!(r.unwrap().0, mds_ipv4);
+        // The QUIC bit of packets sent by the server is unpredictable, and the
+        // client accepts them.
+        let mut fixed_bits = [false; 2];
+        for _ in 0..32 {
+            test_pair.server.ping(None)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+            for (pkt, _) in &packets {
+                fixed_bits[(pkt[0] & packet::HEADER_FIXED_BIT != 0) as usize] = true;
+            }
+            TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        }
+        assert_eq!(fixed_bits, [true, true]);
+
+        // The QUIC bit of packets sent by the client is always set
+        test_pair.client.ping(None)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        for (pkt, _) in &packets {
+            assert_ne!(pkt[0] & packet::HEADER_FIXED_BIT, 0);
+        }
 
         Ok(())
     }
 
     #[test]
-    fn transport_params() -> Result<()> {
-        let server_trans_params = TransportParams {
-            max_idle_timeout: 15000,
-            initial_max_data: 1024000,
-            ..TransportParams::default()
-        };
-
-        // Client perform the first handshake
+    fn spin_bit() -> Result<()> {
         let mut client_config = TestPair::new_test_config(false)?;
+        client_config.enable_spin_bit(true);
         let mut server_config = TestPair::new_test_config(true)?;
-        server_config.local_transport_params = server_trans_params.clone();
-
+        server_config.enable_spin_bit(true);
         let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
         assert_eq!(test_pair.handshake(), Ok(()));
-        assert_eq!(
-            test_pair.client.peer_transport_params.max_idle_timeout,
-            server_trans_params.max_idle_timeout
-        );
-        assert_eq!(
-            test_pair.client.peer_transport_params.initial_max_data,
-            server_trans_params.initial_max_data
-        );
+        test_pair.move_forward()?;
 
-        // Client perform the second handshake
-        let session = test_pair.client.session().unwrap();
-        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
-        test_pair.client.set_session(&session)?;
-        assert_eq!(
-            test_pair.client.peer_transport_params.max_idle_timeout,
-            server_trans_params.max_idle_timeout
-        );
-        assert_eq!(
-            test_pair.client.peer_transport_params.initial_max_data,
-            server_trans_params.initial_max_data
-        );
-        assert_eq!(test_pair.handshake(), Ok(()));
+        // The spin bit may be disabled randomly
+        test_pair.client.spin_enabled = true;
+        test_pair.server.spin_enabled = true;
+
+        // The spin value is inverted by the client once per round trip
+        let spin_bit =
+            |packets: &Vec<(Vec<u8>, PacketInfo)>| packets[0].0[0] & packet::HEADER_SPIN_BIT != 0;
+        let mut spin = test_pair.client.paths.get_active()?.spin_value;
+        for _ in 0..4 {
+            test_pair.client.ping(None)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+            assert_eq!(spin_bit(&packets), spin);
+            TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+            assert_eq!(test_pair.server.paths.get_active()?.spin_value, spin);
+
+            test_pair.server.ping(None)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+            assert_eq!(spin_bit(&packets), spin);
+            TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+            assert_eq!(test_pair.client.paths.get_active()?.spin_value, !spin);
+            spin = !spin;
+        }
+
+        // The spin bit is set to random values if disabled
+        test_pair.client.spin_enabled = false;
+        let mut spin_bits = [false; 2];
+        for _ in 0..32 {
+            test_pair.client.ping(None)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+            spin_bits[spin_bit(&packets) as usize] = true;
+            TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        }
+        assert_eq!(spin_bits, [true, true]);
 
         Ok(())
     }
 
     #[test]
-    fn cid_advertise_and_retire() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-        test_pair.client.set_index(0);
-        test_pair.server.set_index(0);
-        test_pair.handshake()?;
-
-        // Client add a new cid
-        let (scid, reset_token) = (ConnectionId::random(), 1);
-        test_pair.client.add_scid(scid, reset_token, true)?;
-        assert_eq!(test_pair.client.cids.unused_scids(), 1);
-        assert_eq!(test_pair.server.cids.unused_dcids(), 0);
+    fn keep_alive() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_keep_alive_interval(60000);
+        client_config.enable_adaptive_keep_alive(true);
+        client_config.enable_address_discovery(false, true);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_keep_alive_interval(20000);
+        server_config.enable_adaptive_keep_alive(true);
+        server_config.enable_address_discovery(true, false);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
 
-        // Client send NEW_CONNECTION_ID
+        // The server sends a PING frame after the fixed interval
+        assert!(!test_pair.server.keep_alive.is_searching());
+        test_pair.server.timeout();
+        let now = test_pair.server.timers.get(Timer::KeepAlive).unwrap();
+        test_pair.server.on_timeout(now);
+        assert!(test_pair.server.paths.get_active()?.need_send_ping);
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        test_pair.move_forward()?;
+
+        // The client probes the NAT binding after a quiet interval
+        assert!(test_pair.client.keep_alive.is_searching());
+        let interval = test_pair.client.keep_alive.interval();
+        test_pair.client.timeout();
+        let now = test_pair.client.timers.get(Timer::KeepAlive).unwrap();
+        test_pair.client.on_timeout(now);
+        assert!(test_pair.client.keep_alive.need_send_probe());
+        assert!(!test_pair.client.paths.get_active()?.need_send_ping);
         let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!test_pair.client.keep_alive.need_send_probe());
         TestPair::conn_packets_in(&mut test_pair.server, packets)?;
-        assert_eq!(test_pair.client.cids.unused_scids(), 1);
-        assert_eq!(test_pair.server.cids.unused_dcids(), 1);
 
-        // Client add another cid
-        let (scid, reset_token) = (ConnectionId::random(), 2);
-        test_pair.client.add_scid(scid, reset_token, true)?;
-        assert_eq!(test_pair.client.cids.unused_scids(), 2);
+        // The client receives the PATH_RESPONSE, and the interval is
+        // increased if no NAT rebinding is reported before the probe is settled
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        assert_eq!(test_pair.client.keep_alive.interval(), interval);
+        test_pair.client.timeout();
+        let now = test_pair.client.timers.get(Timer::KeepAlive).unwrap();
+        test_pair.client.on_timeout(now);
+        let learned = test_pair.client.keep_alive.interval();
+        assert!(learned > interval);
+        test_pair.move_forward()?;
 
-        // Client send NEW_CONNECTION_ID
+        // The probe after a longer quiet interval is answered, but the server
+        // reports a changed client address
+        test_pair.client.timeout();
+        let now = test_pair.client.timers.get(Timer::KeepAlive).unwrap();
+        test_pair.client.on_timeout(now);
         let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
         TestPair::conn_packets_in(&mut test_pair.server, packets)?;
-        assert_eq!(test_pair.client.cids.unused_scids(), 2);
-        assert_eq!(test_pair.server.cids.unused_dcids(), 1); // exceed cid limit
-
-        // Server send RETIRE_CONNECTION_ID
         let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
         TestPair::conn_packets_in(&mut test_pair.client, packets)?;
-        assert_eq!(test_pair.client.cids.unused_scids(), 1);
-        assert_eq!(test_pair.server.cids.unused_dcids(), 1);
-
-        Ok(())
-    }
-
-    #[test]
-    fn cid_add_exceed_limit() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-        test_pair.handshake()?;
-
-        // Client add a new cid
-        test_pair.client.add_scid(ConnectionId::random(), 1, true)?;
-        assert_eq!(test_pair.client.cids.unused_scids(), 1);
 
-        // Client add more cid
-        assert_eq!(
-            test_pair.client.add_scid(ConnectionId::random(), 2, false),
-            Err(Error::ConnectionIdLimitError)
-        );
+        let mut addr = test_pair.client.get_active_path()?.local_addr();
+        addr.set_port(addr.port() + 1);
+        let frame = frame::Frame::ObservedAddress { seq_num: 100, addr };
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.server, PacketType::OneRTT, &[frame])?;
+        let info = TestPair::new_test_packet_info(true);
+        test_pair.client.recv(&mut packet, &info)?;
+        test_pair.client.timeout();
+        let now = test_pair.client.timers.get(Timer::KeepAlive).unwrap();
+        test_pair.client.on_timeout(now);
+        assert_eq!(test_pair.client.keep_alive.interval(), learned);
+        assert!(test_pair.client.keep_alive.is_searching());
+        assert!(test_pair.client.keep_alive.need_send_probe());
 
         Ok(())
     }
 
     #[test]
-    fn cid_advertise_on_zero_cid_conn() -> Result<()> {
-        let mut test_pair = TestPair::new_with_zero_cid()?;
+    fn keep_alive_without_address_discovery() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.enable_adaptive_keep_alive(true);
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
         assert_eq!(test_pair.handshake(), Ok(()));
 
-        let frame = frame::Frame::NewConnectionId {
-            seq_num: 1,
-            retire_prior_to: 0,
-            conn_id: ConnectionId::random(),
-            reset_token: ResetToken(1_u128.to_be_bytes()),
-        };
-        let mut packet =
-            TestPair::conn_build_packet(&mut test_pair.client, PacketType::OneRTT, &[frame])?;
+        // The NAT rebinding can not be detected, so no search is performed
+        assert!(test_pair.client.keep_alive.enabled());
+        assert!(!test_pair.client.keep_alive.is_searching());
 
-        let info = TestPair::new_test_packet_info(false);
-        assert_eq!(
-            test_pair.server.recv(&mut packet, &info),
-            Err(Error::ProtocolViolation)
-        );
         Ok(())
     }
 
     #[test]
-    fn cid_retire_on_zero_cid_conn() -> Result<()> {
-        let mut test_pair = TestPair::new_with_zero_cid()?;
-        assert_eq!(test_pair.handshake(), Ok(()));
+    fn address_discovery() -> Result<()> {
+        let cases = [
+            // The items in each case are as following:
+            // - client (provide, receive) observed addresses
+            // - server (provide, receive) observed addresses
+            // - whether client/server learned the observed address
+            ((false, false), (true, true), false, false),
+            ((false, true), (true, false), true, false),
+            ((true, false), (false, true), false, true),
+            ((true, true), (true, true), true, true),
+            ((false, true), (false, true), false, false),
+        ];
+        for (client, server, client_learned, server_learned) in cases {
+            let mut client_config = TestPair::new_test_config(false)?;
+            client_config.enable_address_discovery(client.0, client.1);
+            let mut server_config = TestPair::new_test_config(true)?;
+            server_config.enable_address_discovery(server.0, server.1);
 
-        let frame = frame::Frame::RetireConnectionId { seq_num: 1 };
-        let mut packet =
-            TestPair::conn_build_packet(&mut test_pair.client, PacketType::OneRTT, &[frame])?;
+            let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+            assert_eq!(test_pair.handshake(), Ok(()));
+            test_pair.move_forward()?;
+
+            let client_path = test_pair.client.get_active_path()?;
+            let server_path = test_pair.server.get_active_path()?;
+            assert_eq!(
+                client_path.observed_addr(),
+                client_learned.then_some(client_path.local_addr())
+            );
+            assert_eq!(
+                server_path.observed_addr(),
+                server_learned.then_some(server_path.local_addr())
+            );
+        }
 
-        let info = TestPair::new_test_packet_info(false);
-        assert_eq!(
-            test_pair.server.recv(&mut packet, &info),
-            Err(Error::ProtocolViolation)
-        );
         Ok(())
     }
 
     #[test]
-    fn path_new_by_client() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-        test_pair.handshake()?;
-        assert_eq!(test_pair.client.paths_iter().len(), 1);
-        assert_eq!(test_pair.server.paths_iter().len(), 1);
-
-        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
-        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+    fn ack_frequency() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.enable_ack_frequency(true);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.enable_ack_frequency(true);
+        server_config.set_ack_frequency(10, 50);
 
-        // Client and server advertise new cids
-        test_pair.advertise_new_cids()?;
-        assert_eq!(test_pair.client.cids.unused_scids(), 1);
-        assert_eq!(test_pair.client.cids.unused_dcids(), 1);
-        assert_eq!(test_pair.server.cids.unused_scids(), 1);
-        assert_eq!(test_pair.server.cids.unused_dcids(), 1);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
 
-        // Client try to add path again
-        test_pair.client.add_path(client_addr, server_addr)?;
-        assert_eq!(test_pair.client.paths_iter().len(), 2);
-        assert_eq!(test_pair.server.paths_iter().len(), 1);
+        // Client honors the ACK_FREQUENCY frame sent by the server
         assert_eq!(
-            test_pair.client.get_path(client_addr, server_addr)?.state(),
-            PathState::Unknown
+            test_pair.client.peer_ack_frequency,
+            Some(AckFrequency {
+                seq_num: 0,
+                ack_eliciting_threshold: 9,
+                max_ack_delay: time::Duration::from_millis(50),
+                reordering_threshold: 1,
+            })
         );
+        assert_eq!(test_pair.server.peer_ack_frequency, None);
+        let r = &test_pair.server.get_active_path()?.recovery;
+        assert_eq!(r.max_ack_delay, time::Duration::from_millis(50));
 
-        // Client send PATH_CHALLENGE
-        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        // Stale ACK_FREQUENCY frames are ignored
+        let info = TestPair::new_test_packet_info(false);
+        let frame = frame::Frame::AckFrequency {
+            seq_num: 0,
+            ack_eliciting_threshold: 1,
+            request_max_ack_delay: 1000,
+            reordering_threshold: 0,
+        };
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.server, PacketType::OneRTT, &[frame])?;
+        test_pair.client.recv(&mut packet, &info)?;
         assert_eq!(
-            test_pair.client.get_path(client_addr, server_addr)?.state(),
-            PathState::Validating
+            test_pair.client.peer_ack_frequency.map(|v| v.seq_num),
+            Some(0)
         );
-
-        // Server send PATH_RESPONSE/PATH_CHALLENGE
-        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
-        assert_eq!(test_pair.server.paths_iter().len(), 2);
         assert_eq!(
-            test_pair.server.get_path(server_addr, client_addr)?.state(),
-            PathState::Validating
+            test_pair
+                .client
+                .peer_ack_frequency
+                .map(|v| v.ack_eliciting_threshold),
+            Some(9)
         );
 
-        // Client recv PATH_RESPONSE/PATH_CHALLENGE
+        // Client sends an ACK frame immediately upon IMMEDIATE_ACK
+        assert!(test_pair.server.send_immediate_ack().is_ok());
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
         TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        let space = test_pair.client.spaces.get(SpaceId::Data).unwrap();
+        assert!(space.need_send_ack);
+        assert!(space.ack_timer.is_none());
+
+        // The requested max_ack_delay is less than min_ack_delay
+        let frame = frame::Frame::AckFrequency {
+            seq_num: 1,
+            ack_eliciting_threshold: 1,
+            request_max_ack_delay: 999,
+            reordering_threshold: 1,
+        };
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.server, PacketType::OneRTT, &[frame])?;
         assert_eq!(
-            test_pair.client.get_path(client_addr, server_addr)?.state(),
-            PathState::Validated
+            test_pair.client.recv(&mut packet, &info),
+            Err(Error::ProtocolViolation)
         );
 
-        // Client send PATH_RESPONSE
-        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        // The extension is not supported by the client
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
+        assert_eq!(test_pair.client.peer_ack_frequency, None);
+        assert!(test_pair.server.send_immediate_ack().is_err());
+
+        let frame = frame::Frame::ImmediateAck;
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.server, PacketType::OneRTT, &[frame])?;
         assert_eq!(
-            test_pair.server.get_path(server_addr, client_addr)?.state(),
-            PathState::Validated
+            test_pair.client.recv(&mut packet, &info),
+            Err(Error::ProtocolViolation)
         );
 
         Ok(())
     }
 
     #[test]
-    fn path_new_duplicated() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-        test_pair.handshake()?;
-
-        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9443);
-        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+    fn crypto_buffer_exceeded() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_max_crypto_buffer_size(4096);
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let info = TestPair::new_test_packet_info(true);
+        let read_off = test_pair
+            .client
+            .crypto_streams
+            .borrow_mut()
+            .get_mut(Level::OneRTT)?
+            .recv
+            .read_off();
+
+        // Client recv out-of-order crypto data within the limit
+        let frame = frame::Frame::Crypto {
+            offset: read_off + 4000,
+            length: 96,
+            data: Bytes::from_static(&[0; 96]),
+        };
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.server, PacketType::OneRTT, &[frame])?;
+        assert!(test_pair.client.recv(&mut packet, &info).is_ok());
+
+        // Client recv out-of-order crypto data beyond the limit
+        let frame = frame::Frame::Crypto {
+            offset: read_off + 4000,
+            length: 97,
+            data: Bytes::from_static(&[0; 97]),
+        };
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.server, PacketType::OneRTT, &[frame])?;
         assert_eq!(
-            test_pair.client.add_path(client_addr, server_addr),
-            Err(Error::Done)
+            test_pair.client.recv(&mut packet, &info),
+            Err(Error::CryptoBufferExceeded)
         );
+
         Ok(())
     }
 
     #[test]
-    fn path_new_with_zero_cid() -> Result<()> {
-        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
-        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
-
-        // Client try to add path the connection with non-zero cid
-        let mut test_pair = TestPair::new_with_test_config()?;
-        test_pair.handshake()?;
-        assert_eq!(test_pair.client.add_path(client_addr, server_addr), Ok(1));
-        let path = test_pair.client.get_path(client_addr, server_addr)?;
-        assert_eq!(path.dcid_seq, None);
-
-        // Client try to add path on the connection with zero cid
-        let mut test_pair = TestPair::new_with_zero_cid()?;
-        test_pair.handshake()?;
-        assert_eq!(test_pair.client.add_path(client_addr, server_addr), Ok(1));
+    fn crypto_streams_clear() -> Result<()> {
+        let mut streams = CryptoStreams::new(4096);
+        let mut buf = [0; 32];
+
+        // The Initial space is dropped
+        streams.recv(Level::Initial, 0, Bytes::from_static(b"initial"))?;
+        streams.clear(Level::Initial);
+        let stream = streams.get_mut(Level::Initial)?;
+        assert_eq!(stream.recv.read(&mut buf), Err(Error::Done));
+
+        // Handshake crypto data received after the Initial space is dropped
+        // reaches the Handshake stream
+        streams.recv(Level::Handshake, 0, Bytes::from_static(b"handshake"))?;
+        let stream = streams.get_mut(Level::Handshake)?;
+        assert_eq!(stream.recv.read(&mut buf)?, (9, false));
+        assert_eq!(&buf[..9], b"handshake");
+        assert_eq!(stream.recv.read_off(), 9);
+        let stream = streams.get_mut(Level::Initial)?;
+        assert_eq!(stream.recv.read(&mut buf), Err(Error::Done));
+
+        // The Handshake space is dropped, and only the Handshake stream is
+        // cleared
+        streams.recv(Level::OneRTT, 0, Bytes::from_static(b"onertt"))?;
+        streams.clear(Level::Handshake);
+        let stream = streams.get_mut(Level::Handshake)?;
+        assert_eq!(stream.recv.read_off(), 0);
+        let stream = streams.get_mut(Level::OneRTT)?;
+        assert_eq!(stream.recv.read(&mut buf)?, (6, false));
+        assert_eq!(&buf[..6], b"onertt");
 
         Ok(())
     }
 
     #[test]
-    fn path_new_by_server() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-        test_pair.handshake()?;
+    fn observed_address_unexpected() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
 
-        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9443);
-        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 444);
+        // Client recv an OBSERVED_ADDRESS frame which is not negotiated
+        let frame = frame::Frame::ObservedAddress {
+            seq_num: 0,
+            addr: test_pair.client.get_active_path()?.local_addr(),
+        };
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.server, PacketType::OneRTT, &[frame])?;
 
-        // Server try to add path
+        let info = TestPair::new_test_packet_info(true);
         assert_eq!(
-            test_pair.server.add_path(server_addr, client_addr),
-            Err(Error::InvalidOperation("disallowed".into()))
+            test_pair.client.recv(&mut packet, &info),
+            Err(Error::ProtocolViolation)
         );
+
         Ok(())
     }
 
     #[test]
-    fn path_chal_timer_operations() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-        test_pair.handshake()?;
+    fn send_capacity_token() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.enable_careful_resume(true);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
 
-        // Client and server advertise new cids.
-        test_pair.advertise_new_cids()?;
+        // Server can not issue the token before the handshake completes
+        assert!(test_pair.server.send_capacity_token().is_err());
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
+        while test_pair.client.poll().is_some() {}
+
+        // Client can not issue the token
+        assert!(test_pair.client.send_capacity_token().is_err());
+
+        // Server issue the token carrying the path capacity
+        test_pair.server.send_capacity_token()?;
+        let path = test_pair.server.paths.get(0)?;
+        let client_addr = path.remote_addr();
+        let server_addr = path.local_addr();
+        let cwnd = path.recovery.congestion.congestion_window();
+        let min_rtt = path.recovery.rtt.min_rtt().as_micros();
+        test_pair.move_forward()?;
 
-        // Client try to add path.
-        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
-        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
-        test_pair.client.add_path(client_addr, server_addr)?;
-        assert_eq!(
-            test_pair.client.get_path(client_addr, server_addr)?.state(),
-            PathState::Unknown
-        );
-        assert!(test_pair.client.timers.get(Timer::PathChallenge).is_none());
+        let mut token = None;
+        while let Some(event) = test_pair.client.poll() {
+            if let Event::NewToken(t) = event {
+                token = Some(t);
+            }
+        }
+        let token = AddressToken::decode(
+            &server_config.address_token_key,
+            &token.unwrap(),
+            &client_addr,
+            &ConnectionId::random(),
+            server_config.address_token_lifetime,
+        )?;
+        let (capacity, rtt) = token.capacity.unwrap();
+        assert_eq!(capacity, cwnd);
+        assert_eq!(rtt.as_micros(), min_rtt);
 
-        // Client send PATH_CHALLENGE and start PathChallenge timer.
-        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
-        assert_eq!(
-            test_pair.client.get_path(client_addr, server_addr)?.state(),
-            PathState::Validating
-        );
-        assert!(test_pair.client.timeout().is_some());
-        assert!(test_pair.client.timers.get(Timer::PathChallenge).is_some());
+        // Server create a new connection with the token
+        let conn = Connection::new_server(
+            &ConnectionId::random(),
+            server_addr,
+            client_addr,
+            Some(&token),
+            &mut server_config,
+        )?;
+        assert!(conn.paths.get(0)?.verified_peer_address);
 
-        // Client recv PATH_RESPONSE and stop PathChallenge timer.
-        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
-        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        Ok(())
+    }
+
+    #[test]
+    fn bdp_frame() -> Result<()> {
+        let cases = [
+            // The items in each case are as following:
+            // - client enable_bdp_frame
+            // - server enable_bdp_frame
+            // - bdp frame negotiation result
+            (true, false, false),
+            (false, true, false),
+            (true, true, true),
+        ];
+        for case in cases {
+            let mut client_config = TestPair::new_test_config(false)?;
+            client_config.enable_bdp_frame(case.0);
+            let mut server_config = TestPair::new_test_config(true)?;
+            server_config.enable_bdp_frame(case.1);
+
+            let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+            assert_eq!(test_pair.handshake(), Ok(()));
+            test_pair.move_forward()?;
+            assert_eq!(test_pair.client.flags.contains(EnableBdp), case.2);
+            assert_eq!(test_pair.server.flags.contains(EnableBdp), case.2);
+
+            // Client can not send BDP_FRAME
+            assert!(test_pair.client.send_bdp_frame().is_err());
+            if !case.2 {
+                assert!(test_pair.server.send_bdp_frame().is_err());
+                continue;
+            }
+
+            // Server share the path capacity with the client
+            assert_eq!(test_pair.client.peer_bdp(), None);
+            test_pair.server.send_bdp_frame()?;
+            let recovery = &test_pair.server.paths.get(0)?.recovery;
+            let cwnd = recovery.congestion.congestion_window();
+            let min_rtt = recovery.rtt.min_rtt().as_micros();
+            test_pair.move_forward()?;
+
+            let bdp = test_pair.client.peer_bdp().unwrap();
+            assert_eq!(bdp.lifetime, Duration::from_secs(BDP_LIFETIME));
+            assert_eq!(bdp.saved_capacity, cwnd);
+            assert_eq!(bdp.saved_rtt.as_micros(), min_rtt);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn max_datagram_size() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_send_udp_payload_size(1200);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_recv_udp_payload_size(1550);
+        server_config.set_initial_max_data(10000);
+        server_config.set_initial_max_stream_data_bidi_remote(10000);
+        server_config.set_ack_eliciting_threshold(1);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(
+            test_pair.client.paths.get(0)?.recovery.max_datagram_size,
+            1200,
+        );
+
+        // Handshake and discovery path MTU
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
+
+        // Check path MTU
+        let mds_ipv4 = 1472;
+        assert_eq!(
+            test_pair.client.paths.get(0)?.recovery.max_datagram_size,
+            mds_ipv4
+        );
+
+        // Check outgoing packet size
+        let mut buf = vec![0; 2000];
+        assert!(test_pair
+            .client
+            .stream_write(0, Bytes::from(vec![0; 2000]), true)
+            .is_ok());
+        let r = test_pair.client.send(&mut buf);
+        assert!(r.is_ok());
+        assert_eq!(r.unwrap().0, mds_ipv4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transport_params() -> Result<()> {
+        let server_trans_params = TransportParams {
+            max_idle_timeout: 15000,
+            initial_max_data: 1024000,
+            ..TransportParams::default()
+        };
+
+        // Client perform the first handshake
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.local_transport_params = server_trans_params.clone();
+
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        assert_eq!(
+            test_pair.client.peer_transport_params.max_idle_timeout,
+            server_trans_params.max_idle_timeout
+        );
+        assert_eq!(
+            test_pair.client.peer_transport_params.initial_max_data,
+            server_trans_params.initial_max_data
+        );
+
+        // Client perform the second handshake
+        let session = test_pair.client.session().unwrap();
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        test_pair.client.set_session(&session)?;
+        assert_eq!(
+            test_pair.client.peer_transport_params.max_idle_timeout,
+            server_trans_params.max_idle_timeout
+        );
+        assert_eq!(
+            test_pair.client.peer_transport_params.initial_max_data,
+            server_trans_params.initial_max_data
+        );
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cid_advertise_and_retire() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        test_pair.client.set_index(0);
+        test_pair.server.set_index(0);
+        test_pair.handshake()?;
+
+        // Client add a new cid
+        let (scid, reset_token) = (ConnectionId::random(), 1);
+        test_pair.client.add_scid(scid, reset_token, true)?;
+        assert_eq!(test_pair.client.cids.unused_scids(), 1);
+        assert_eq!(test_pair.server.cids.unused_dcids(), 0);
+
+        // Client send NEW_CONNECTION_ID
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(test_pair.client.cids.unused_scids(), 1);
+        assert_eq!(test_pair.server.cids.unused_dcids(), 1);
+
+        // Client add another cid
+        let (scid, reset_token) = (ConnectionId::random(), 2);
+        test_pair.client.add_scid(scid, reset_token, true)?;
+        assert_eq!(test_pair.client.cids.unused_scids(), 2);
+
+        // Client send NEW_CONNECTION_ID
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(test_pair.client.cids.unused_scids(), 2);
+        assert_eq!(test_pair.server.cids.unused_dcids(), 1); // exceed cid limit
+
+        // Server send RETIRE_CONNECTION_ID
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        assert_eq!(test_pair.client.cids.unused_scids(), 1);
+        assert_eq!(test_pair.server.cids.unused_dcids(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cid_add_exceed_limit() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        test_pair.handshake()?;
+
+        // Client add a new cid
+        test_pair.client.add_scid(ConnectionId::random(), 1, true)?;
+        assert_eq!(test_pair.client.cids.unused_scids(), 1);
+
+        // Client add more cid
+        assert_eq!(
+            test_pair.client.add_scid(ConnectionId::random(), 2, false),
+            Err(Error::ConnectionIdLimitError)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn cid_advertise_on_zero_cid_conn() -> Result<()> {
+        let mut test_pair = TestPair::new_with_zero_cid()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        let frame = frame::Frame::NewConnectionId {
+            seq_num: 1,
+            retire_prior_to: 0,
+            conn_id: ConnectionId::random(),
+            reset_token: ResetToken(1_u128.to_be_bytes()),
+        };
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.client, PacketType::OneRTT, &[frame])?;
+
+        let info = TestPair::new_test_packet_info(false);
+        assert_eq!(
+            test_pair.server.recv(&mut packet, &info),
+            Err(Error::ProtocolViolation)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cid_retire_on_zero_cid_conn() -> Result<()> {
+        let mut test_pair = TestPair::new_with_zero_cid()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        let frame = frame::Frame::RetireConnectionId { seq_num: 1 };
+        let mut packet =
+            TestPair::conn_build_packet(&mut test_pair.client, PacketType::OneRTT, &[frame])?;
+
+        let info = TestPair::new_test_packet_info(false);
+        assert_eq!(
+            test_pair.server.recv(&mut packet, &info),
+            Err(Error::ProtocolViolation)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn path_new_by_client() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        test_pair.handshake()?;
+        assert_eq!(test_pair.client.paths_iter().len(), 1);
+        assert_eq!(test_pair.server.paths_iter().len(), 1);
+
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+
+        // Client and server advertise new cids
+        test_pair.advertise_new_cids()?;
+        assert_eq!(test_pair.client.cids.unused_scids(), 1);
+        assert_eq!(test_pair.client.cids.unused_dcids(), 1);
+        assert_eq!(test_pair.server.cids.unused_scids(), 1);
+        assert_eq!(test_pair.server.cids.unused_dcids(), 1);
+
+        // Client try to add path again
+        test_pair.client.add_path(client_addr, server_addr)?;
+        assert_eq!(test_pair.client.paths_iter().len(), 2);
+        assert_eq!(test_pair.server.paths_iter().len(), 1);
+        assert_eq!(
+            test_pair.client.get_path(client_addr, server_addr)?.state(),
+            PathState::Unknown
+        );
+
+        // Client send PATH_CHALLENGE
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(
+            test_pair.client.get_path(client_addr, server_addr)?.state(),
+            PathState::Validating
+        );
+
+        // Server send PATH_RESPONSE/PATH_CHALLENGE
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        assert_eq!(test_pair.server.paths_iter().len(), 2);
+        assert_eq!(
+            test_pair.server.get_path(server_addr, client_addr)?.state(),
+            PathState::Validating
+        );
+
+        // Client recv PATH_RESPONSE/PATH_CHALLENGE
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
         assert_eq!(
             test_pair.client.get_path(client_addr, server_addr)?.state(),
             PathState::Validated
         );
-        assert!(test_pair.client.timeout().is_some());
-        assert!(test_pair.client.timers.get(Timer::PathChallenge).is_none());
 
+        // Client send PATH_RESPONSE
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(
+            test_pair.server.get_path(server_addr, client_addr)?.state(),
+            PathState::Validated
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_new_duplicated() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        test_pair.handshake()?;
+
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9443);
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+        assert_eq!(
+            test_pair.client.add_path(client_addr, server_addr),
+            Err(Error::Done)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn path_new_with_zero_cid() -> Result<()> {
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+
+        // Client try to add path the connection with non-zero cid
+        let mut test_pair = TestPair::new_with_test_config()?;
+        test_pair.handshake()?;
+        assert_eq!(test_pair.client.add_path(client_addr, server_addr), Ok(1));
+        let path = test_pair.client.get_path(client_addr, server_addr)?;
+        assert_eq!(path.dcid_seq, None);
+
+        // Client try to add path on the connection with zero cid
+        let mut test_pair = TestPair::new_with_zero_cid()?;
+        test_pair.handshake()?;
+        assert_eq!(test_pair.client.add_path(client_addr, server_addr), Ok(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_new_by_server() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        test_pair.handshake()?;
+
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9443);
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 444);
+
+        // Server try to add path
+        assert_eq!(
+            test_pair.server.add_path(server_addr, client_addr),
+            Err(Error::InvalidOperation("disallowed".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn path_chal_timer_operations() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        test_pair.handshake()?;
+
+        // Client and server advertise new cids.
+        test_pair.advertise_new_cids()?;
+
+        // Client try to add path.
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+        test_pair.client.add_path(client_addr, server_addr)?;
+        assert_eq!(
+            test_pair.client.get_path(client_addr, server_addr)?.state(),
+            PathState::Unknown
+        );
+        assert!(test_pair.client.timers.get(Timer::PathChallenge).is_none());
+
+        // Client send PATH_CHALLENGE and start PathChallenge timer.
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(
+            test_pair.client.get_path(client_addr, server_addr)?.state(),
+            PathState::Validating
+        );
+        assert!(test_pair.client.timeout().is_some());
+        assert!(test_pair.client.timers.get(Timer::PathChallenge).is_some());
+
+        // Client recv PATH_RESPONSE and stop PathChallenge timer.
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        assert_eq!(
+            test_pair.client.get_path(client_addr, server_addr)?.state(),
+            PathState::Validated
+        );
+        assert!(test_pair.client.timeout().is_some());
+        assert!(test_pair.client.timers.get(Timer::PathChallenge).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_chal_with_packet_loss() -> Result<()> {
+        let mut test_pair = TestPair::new_with_zero_cid()?;
+        test_pair.handshake()?;
+
+        // Client send and fake lost of PATH_CHALLENGE.
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+        let pid = test_pair.client.add_path(client_addr, server_addr)? as usize;
+        TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert_eq!(
+            test_pair.client.paths.get(pid)?.state(),
+            PathState::Validating
+        );
+
+        // Advance ticks until PATH_CHALLENGE timeout.
+        assert!(test_pair.client.timeout().is_some());
+        let now = time::Instant::now() + time::Duration::from_millis(path::INITIAL_CHAL_TIMEOUT);
+        test_pair.client.on_timeout(now);
+
+        // Client send PATH_CHALLENGE again.
+        assert!(test_pair
+            .client
+            .paths
+            .get(pid)?
+            .need_send_validation_frames(false));
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+
+        // Client recv PATH_RESPONSE.
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        assert_eq!(
+            test_pair.client.paths.get(pid)?.state(),
+            PathState::Validated
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_chal_loss_and_failed() -> Result<()> {
+        let mut test_pair = TestPair::new_with_zero_cid()?;
+        test_pair.handshake()?;
+
+        // Client send and fake lost of PATH_CHALLENGE.
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+        let pid = test_pair.client.add_path(client_addr, server_addr)? as usize;
+        TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert_eq!(
+            test_pair.client.paths.get(pid)?.state(),
+            PathState::Validating
+        );
+
+        for i in 0..path::MAX_PROBING_TIMEOUTS {
+            // Advance ticks until PATH_CHALLENGE timeout.
+            assert!(test_pair.client.timeout().is_some());
+            let now = test_pair.client.timers.get(Timer::PathChallenge).unwrap();
+            test_pair.client.on_timeout(now);
+
+            // Try to send PATH_CHALLENGE again.
+            TestPair::conn_packets_out(&mut test_pair.client)?;
+        }
+
+        // Path validation finally failed.
+        assert_eq!(test_pair.client.paths.get(pid)?.state(), PathState::Failed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_active_all_failed() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+
+        // Fake failing of all active path
+        let path = test_pair.client.paths.get_mut(0)?;
+        path.set_active(false);
+
+        assert!(test_pair.client.scid().is_err());
+        assert!(test_pair.client.dcid().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_anti_ampl_limit() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        {
+            let path = test_pair.server.paths.get_active().unwrap();
+            assert_eq!(path.anti_ampl_limit, 0);
+        }
+
+        // Client send Initial.
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        let len_in: usize = packets.iter().map(|p| p.0.len()).sum();
+
+        // Server recv Initial.
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        {
+            let path = test_pair.server.paths.get_active().unwrap();
+            assert_eq!(
+                path.anti_ampl_limit,
+                len_in * test_pair.server.paths.anti_ampl_factor
+            );
+        }
+
+        // Server send Initial and Handshake.
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        let len_out: usize = packets.iter().map(|p| p.0.len()).sum();
+        {
+            let path = test_pair.server.paths.get_active().unwrap();
+            assert_eq!(
+                path.anti_ampl_limit,
+                len_in * test_pair.server.paths.anti_ampl_factor - len_out
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_anti_ampl_budget_qlog() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        let slog = NamedTempFile::new().unwrap();
+        let mut sfile = slog.reopen().unwrap();
+        test_pair
+            .server
+            .set_qlog(Box::new(slog), "title".into(), "desc".into());
+
+        // Server sends Initial and Handshake before address validation
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        let _ = TestPair::conn_packets_out(&mut test_pair.server)?;
+
+        let mut slog_content = String::new();
+        sfile.read_to_string(&mut slog_content).unwrap();
+        assert!(slog_content.contains("connectivity:anti_amplification_budget_updated"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_mtu_discovery_max() -> Result<()> {
+        let cases = [
+            // (cli_enable_dplpmtud, srv_enable_dplpmtud, cli_mtu , srv_mtu)
+            (false, false, 1200, 1200),
+            (false, true, 1200, 1472),
+            (true, false, 1472, 1200),
+            (true, true, 1472, 1472),
+        ];
+
+        for case in cases {
+            let mut client_config = TestPair::new_test_config(false)?;
+            client_config.enable_dplpmtud(case.0);
+            client_config.set_ack_eliciting_threshold(1);
+            let mut server_config = TestPair::new_test_config(true)?;
+            server_config.enable_dplpmtud(case.1);
+            server_config.set_ack_eliciting_threshold(1);
+            let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+            assert_eq!(test_pair.handshake(), Ok(()));
+
+            test_pair.move_forward()?;
+            assert_eq!(
+                test_pair.client.paths.get(0)?.recovery.max_datagram_size,
+                case.2
+            );
+            assert_eq!(
+                test_pair.server.paths.get(0)?.recovery.max_datagram_size,
+                case.3
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_mtu_discovery_lost() -> Result<()> {
+        let cases = [
+            // (router_mtu, searched_mtu)
+            (1472, 1463),
+            (1452, 1446),
+            (1432, 1429),
+            (1412, 1404),
+            (1392, 1387),
+            (1372, 1370),
+        ];
+
+        for case in cases {
+            let mut client_config = TestPair::new_test_config(false)?;
+            client_config.enable_dplpmtud(true);
+            client_config.set_ack_eliciting_threshold(1);
+            let mut server_config = TestPair::new_test_config(true)?;
+            server_config.enable_dplpmtud(false);
+            server_config.set_initial_max_data(10240);
+            server_config.set_initial_max_stream_data_bidi_remote(10240);
+            server_config.set_ack_eliciting_threshold(1);
+            let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+            let router_mtu: usize = case.0;
+
+            // Handshake
+            while !test_pair.client.is_established() || !test_pair.server.is_established() {
+                let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+                packets.retain(|p| p.0.len() < router_mtu); // fake dropping packets
+                TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+
+                let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+                TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+            }
+
+            // Path MTU searching
+            let data = Bytes::from_static(b"data");
+            for i in 0..30 {
+                let _ = test_pair.client.stream_write(0, data.clone(), false);
+                let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+                packets.retain(|p| p.0.len() < router_mtu); // fake dropping packets
+
+                TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+                let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+                TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+
+                if test_pair.client.timeout().is_some() {
+                    let timeout = test_pair.client.timers.get(Timer::LossDetection);
+                    test_pair.client.on_timeout(timeout.unwrap());
+                }
+            }
+
+            // Check final MTU
+            assert_eq!(
+                test_pair.client.paths.get(0)?.recovery.max_datagram_size,
+                case.1
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_loss_stats() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_ack_eliciting_threshold(1);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_ack_eliciting_threshold(1);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        test_pair.handshake()?;
+        test_pair.move_forward()?;
+
+        // Fake dropping the packet carrying the data of stream 0
+        let data = Bytes::from_static(b"data");
+        test_pair.client.stream_write(0, data.clone(), false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+
+        // The packet is declared lost after the later packets are
+        // acknowledged, and the data is retransmitted
+        for stream_id in [4, 8, 12] {
+            test_pair
+                .client
+                .stream_write(stream_id, data.clone(), false)?;
+            test_pair.move_forward()?;
+        }
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert!(stats.lost_count >= 1);
+        assert_eq!(stats.retrans_stream_bytes, data.len() as u64);
+        assert_eq!(stats.pto_count, 0);
+
+        // Fake dropping the packet and the probe timeout expires
+        test_pair.client.stream_write(16, data.clone(), false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+        let timeout = test_pair.client.timers.get(Timer::LossDetection);
+        test_pair.client.on_timeout(timeout.unwrap());
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert_eq!(stats.pto_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn pto_probe_strategy() -> Result<()> {
+        for strategy in [
+            PtoProbeStrategy::OldestUnacked,
+            PtoProbeStrategy::NewData,
+            PtoProbeStrategy::Ping,
+        ] {
+            let mut client_config = TestPair::new_test_config(false)?;
+            client_config.set_pto_probe_strategy(strategy);
+            let mut server_config = TestPair::new_test_config(true)?;
+            let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+            test_pair.handshake()?;
+            test_pair.move_forward()?;
+
+            // Fake dropping the packet carrying the data of stream 0
+            let data = Bytes::from_static(b"data");
+            test_pair.client.stream_write(0, data.clone(), false)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+            assert!(!packets.is_empty());
+            let space = test_pair.client.spaces.get(SpaceId::Data).unwrap();
+            let next_pkt_num = space.next_pkt_num;
+
+            // The probe timeout expires, and new data of stream 4 is available
+            let timeout = test_pair.client.timers.get(Timer::LossDetection);
+            test_pair.client.on_timeout(timeout.unwrap());
+            test_pair.client.stream_write(4, data.clone(), false)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+            assert!(!packets.is_empty());
+
+            // Check the frames of the probe packet
+            let space = test_pair.client.spaces.get(SpaceId::Data).unwrap();
+            let probe = space.sent.get(next_pkt_num).unwrap();
+            let has_stream = |id| {
+                probe
+                    .frames
+                    .iter()
+                    .any(|f| matches!(f, Frame::Stream { stream_id, .. } if *stream_id == id))
+            };
+            let has_ping = probe.frames.iter().any(|f| matches!(f, Frame::Ping { .. }));
+            match strategy {
+                PtoProbeStrategy::OldestUnacked => assert!(has_stream(0)),
+                PtoProbeStrategy::NewData => assert!(!has_stream(0) && has_stream(4)),
+                PtoProbeStrategy::Ping => assert!(!has_stream(0) && !has_stream(4) && has_ping),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn ping() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.enable_dplpmtud(false);
+        client_config.local_transport_params = TransportParams {
+            max_idle_timeout: 15000,
+            ..TransportParams::default()
+        };
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.enable_dplpmtud(false);
+        server_config.local_transport_params = TransportParams {
+            max_idle_timeout: 15000,
+            ..TransportParams::default()
+        };
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        test_pair.handshake()?;
+
+        // Move both connections to idle state
+        test_pair.move_forward()?;
+
+        // Enable qlog for Server
+        let slog = NamedTempFile::new().unwrap();
+        let mut sfile = slog.reopen().unwrap();
+        test_pair
+            .server
+            .set_qlog(Box::new(slog), "title".into(), "desc".into());
+
+        // Client send a Ping frame
+        test_pair.client.ping(None)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets.clone())?;
+
+        let mut slog_content = String::new();
+        sfile.read_to_string(&mut slog_content).unwrap();
+        assert_eq!(slog_content.contains("quic:packet_received"), true);
+        assert_eq!(slog_content.contains("frame_type\":\"ping"), true);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ping_with_tag() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        test_pair.client.events.enable();
+        assert!(test_pair.client.ping_with_tag(1).is_err());
+
+        test_pair.handshake()?;
+        test_pair.move_forward()?;
+        while test_pair.client.poll().is_some() {}
+
+        // Client send Ping frames carrying the tags
+        test_pair.client.ping_with_tag(1)?;
+        test_pair.client.ping_with_tag(2)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert_eq!(packets.len(), 1);
+        assert!(test_pair.client.ping_tags.is_empty());
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+
+        // Server send an ACK frame after the ack delay
+        let dur = test_pair.server.timeout().unwrap();
+        test_pair.server.on_timeout(time::Instant::now() + dur);
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+
+        // Client report the RTT of each probe
+        let mut tags = Vec::new();
+        while let Some(event) = test_pair.client.poll() {
+            if let Event::PingAcked(tag, rtt) = event {
+                assert!(rtt < time::Duration::from_secs(1));
+                tags.push(tag);
+            }
+        }
+        assert_eq!(tags, vec![1, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn conn_memory_usage() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        assert_eq!(test_pair.client.stats().memory_usage, 0);
+        let usage = test_pair.client.memory_usage();
+        assert!(usage > 0);
+        assert_eq!(test_pair.client.update_memory_usage(), 0);
+        assert_eq!(test_pair.client.stats().memory_usage, usage as u64);
+
+        // Data buffered to be sent is counted
+        let base = test_pair.client.streams.memory_usage();
+        let data = TestPair::new_test_data(40);
+        test_pair.client.stream_write(0, data, false)?;
+        let stream_usage = test_pair.client.streams.memory_usage();
+        assert!(stream_usage >= base + 40);
+        assert!(test_pair.client.memory_usage() >= usage + 40);
+
+        // Data buffered to be read is counted
+        test_pair.move_forward()?;
+        let base = std::mem::size_of::<Stream>();
+        assert_eq!(test_pair.server.streams.memory_usage(), base + 40);
+        Ok(())
+    }
+
+    #[test]
+    fn conn_send_segments() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_initial_max_data(20000);
+        server_config.set_initial_max_stream_data_bidi_remote(20000);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        let data = TestPair::new_test_data(10000);
+        test_pair.client.stream_write(0, data, true)?;
+
+        // Write multiple datagrams of the same size
+        let mut out = vec![0u8; 65535];
+        let (len, seg, count, info) = test_pair.client.send_segments(&mut out, 4)?;
+        assert!(seg > 0);
+        assert_eq!(count, 4);
+        assert!(len > seg * 3);
+        assert!(len <= seg * 4);
+        assert_eq!(info.segment_size, seg);
+
+        // Each segment can be processed by the peer
+        for pkt in out[..len].chunks_mut(seg) {
+            assert!(test_pair.server.recv(pkt, &info)? > 0);
+        }
+        test_pair.move_forward()?;
+
+        let mut buf = vec![0; 10000];
+        assert_eq!(test_pair.server.stream_read(0, &mut buf)?, (10000, true));
+
+        // The buffer is too short
+        let mut out = vec![0u8; 1000];
+        assert!(matches!(
+            test_pair.client.send_segments(&mut out, 4),
+            Err(Error::BufferTooShort)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn conn_send_segments_with_pacing_offload() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_initial_congestion_window(50);
+        client_config.set_pacing_min_burst(2);
+        client_config.set_pacing_max_burst(2);
+        client_config.set_max_pacing_rate(100_000);
+        client_config.enable_pacing_offload(true);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_initial_max_data(50000);
+        server_config.set_initial_max_stream_data_bidi_remote(50000);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        let data = TestPair::new_test_data(20000);
+        test_pair.client.stream_write(0, data, true)?;
+
+        // A datagram released later is not sent in the batch of the previous
+        // datagrams, so each segment leaves at its own release time.
+        let mut out = vec![0u8; 65535];
+        let mut last_time = None;
+        let (mut paced, mut sent) = (0, 0);
+        loop {
+            let (_, _, count, info) = match test_pair.client.send_segments(&mut out, 8) {
+                Ok(v) => v,
+                Err(Error::Done) => break,
+                Err(e) => return Err(e),
+            };
+            if info.time > time::Instant::now() {
+                assert_eq!(count, 1);
+                paced += 1;
+            }
+            assert!(last_time.map_or(true, |t| info.time >= t));
+            last_time = Some(info.time);
+            sent += count;
+        }
+        assert!(paced > 0);
+        assert!(sent > 10);
+        Ok(())
+    }
+
+    #[test]
+    fn conn_send_segments_with_ecn_testing() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_initial_congestion_window(50);
+        client_config.enable_pacing(false);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_initial_max_data(50000);
+        server_config.set_initial_max_stream_data_bidi_remote(50000);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
+
+        // Start testing ECN on the path
+        let path = test_pair.client.paths.get(0)?;
+        let (local, remote) = (path.local_addr(), path.remote_addr());
+        test_pair
+            .client
+            .set_path_ecn_policy(local, remote, EcnPolicy::TestThenUse)?;
+        let space = test_pair.server.spaces.get(SpaceId::Data).unwrap();
+        let ect0_count = space.recv_ecn_counts.ect0_count;
+
+        let data = TestPair::new_test_data(20000);
+        test_pair.client.stream_write(0, data, true)?;
+
+        // The testing datagrams are marked with ECT(0), and the following ones
+        // are not marked, so they are sent in separate batches.
+        let mut out = vec![0u8; 65535];
+        let (len, seg, count, info) = test_pair.client.send_segments(&mut out, 16)?;
+        assert_eq!(count, 10);
+        assert_eq!(info.ecn, Ecn::Ect0);
+        for pkt in out[..len].chunks_mut(seg) {
+            assert!(test_pair.server.recv(pkt, &info)? > 0);
+        }
+
+        let (len, seg, count, info) = test_pair.client.send_segments(&mut out, 16)?;
+        assert!(count > 1);
+        assert_eq!(info.ecn, Ecn::NotEct);
+        for pkt in out[..len].chunks_mut(seg) {
+            assert!(test_pair.server.recv(pkt, &info)? > 0);
+        }
+
+        // The server received exactly the testing datagrams with ECT(0)
+        let space = test_pair.server.spaces.get(SpaceId::Data).unwrap();
+        assert_eq!(space.recv_ecn_counts.ect0_count, ect0_count + 10);
+        Ok(())
+    }
+
+    #[test]
+    fn conn_send_coalescing_delay() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_send_coalescing_delay(500);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_initial_max_data(20000);
+        server_config.set_initial_max_stream_data_bidi_remote(20000);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
+
+        // Small writes are held for coalescing
+        let data = TestPair::new_test_data(10);
+        test_pair.client.stream_write(0, data.clone(), false)?;
+        assert!(TestPair::conn_packets_out(&mut test_pair.client)?.is_empty());
+        let timeout = test_pair.client.timers.get(Timer::Coalescing);
+        assert!(timeout.is_some());
+
+        test_pair.client.stream_write(0, data, false)?;
+        assert!(TestPair::conn_packets_out(&mut test_pair.client)?.is_empty());
+        assert_eq!(test_pair.client.timers.get(Timer::Coalescing), timeout);
+
+        // The held data is sent in one packet after the delay
+        test_pair.client.on_timeout(timeout.unwrap());
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert_eq!(packets.len(), 1);
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        let mut buf = vec![0; 100];
+        assert_eq!(test_pair.server.stream_read(0, &mut buf)?, (20, false));
+        assert!(!test_pair.client.flags.contains(FlushStreamData));
+
+        // Data which fill a packet is sent immediately
+        let data = TestPair::new_test_data(2000);
+        test_pair.client.stream_write(0, data, false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+        assert!(test_pair.client.timers.get(Timer::Coalescing).is_some());
         Ok(())
     }
 
     #[test]
-    fn path_chal_with_packet_loss() -> Result<()> {
-        let mut test_pair = TestPair::new_with_zero_cid()?;
-        test_pair.handshake()?;
+    fn conn_send_coalescing_delay_with_loss() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_send_coalescing_delay(500);
+        client_config.set_ack_eliciting_threshold(1);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_ack_eliciting_threshold(1);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
 
-        // Client send and fake lost of PATH_CHALLENGE.
-        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
-        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
-        let pid = test_pair.client.add_path(client_addr, server_addr)? as usize;
-        TestPair::conn_packets_out(&mut test_pair.client)?;
-        assert_eq!(
-            test_pair.client.paths.get(pid)?.state(),
-            PathState::Validating
-        );
+        // Fake dropping the packet carrying the held data of stream 0
+        let data = TestPair::new_test_data(10);
+        test_pair.client.stream_write(0, data.clone(), false)?;
+        assert!(TestPair::conn_packets_out(&mut test_pair.client)?.is_empty());
+        let timeout = test_pair.client.timers.get(Timer::Coalescing);
+        test_pair.client.on_timeout(timeout.unwrap());
+        assert_eq!(TestPair::conn_packets_out(&mut test_pair.client)?.len(), 1);
+
+        // Small writes with fin are sent immediately. The dropped packet is
+        // declared lost after the later packets are acknowledged, and the lost
+        // data is retransmitted without delay.
+        for stream_id in [4, 8, 12] {
+            test_pair
+                .client
+                .stream_write(stream_id, data.clone(), true)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+            assert!(!packets.is_empty());
+            assert!(test_pair.client.timers.get(Timer::Coalescing).is_none());
+            TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+            test_pair.move_forward()?;
+        }
+        let mut buf = vec![0; 100];
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert!(stats.lost_count >= 1);
+        assert_eq!(stats.retrans_stream_bytes, data.len() as u64);
+        assert_eq!(test_pair.server.stream_read(0, &mut buf)?, (10, false));
+        assert!(test_pair.client.timers.get(Timer::Coalescing).is_none());
+        Ok(())
+    }
 
-        // Advance ticks until PATH_CHALLENGE timeout.
-        assert!(test_pair.client.timeout().is_some());
-        let now = time::Instant::now() + time::Duration::from_millis(path::INITIAL_CHAL_TIMEOUT);
-        test_pair.client.on_timeout(now);
+    #[test]
+    fn conn_send_limited_stats() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
 
-        // Client send PATH_CHALLENGE again.
-        assert!(test_pair
-            .client
-            .paths
-            .get(pid)?
-            .need_send_validation_frames(false));
+        // Client has no data to send
+        let recovery = &test_pair.client.paths.get(0)?.recovery;
+        assert_eq!(recovery.send_limited(), SendLimited::App);
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert!(stats.app_limited_count > 0);
+        assert_eq!(stats.flow_control_limited_count, 0);
+
+        // Client is blocked by the stream-level flow control
+        let data = TestPair::new_test_data(100);
+        assert_eq!(test_pair.client.stream_write(0, data, false)?, 40);
         let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert!(!packets.is_empty());
+        let recovery = &test_pair.client.paths.get(0)?.recovery;
+        assert_eq!(recovery.send_limited(), SendLimited::FlowControl);
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert_eq!(stats.flow_control_limited_count, 1);
+        Ok(())
+    }
 
-        // Client recv PATH_RESPONSE.
-        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
-        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+    #[test]
+    fn conn_congestion_stats() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_congestion_control_algorithm(CongestionControlAlgorithm::Bbr);
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
+
+        let path = test_pair.client.paths.get(0)?;
+        let (local, remote) = (path.local_addr(), path.remote_addr());
+        let stats = test_pair.client.get_congestion_stats(local, remote)?;
+        assert_eq!(stats.algorithm, "BBR");
+        assert_eq!(stats.state, "Startup");
+        assert_eq!(stats.cwnd, path.recovery.congestion.congestion_window());
+        assert!(stats.pacing_rate > 0);
+
+        let path = test_pair.server.paths.get(0)?;
+        let (local, remote) = (path.local_addr(), path.remote_addr());
+        let stats = test_pair.server.get_congestion_stats(local, remote)?;
+        assert_eq!(stats.algorithm, "CUBIC");
+        assert_eq!(stats.state, "SlowStart");
+        assert_eq!(stats.bandwidth, 0);
         assert_eq!(
-            test_pair.client.paths.get(pid)?.state(),
-            PathState::Validated
+            stats.min_rtt,
+            path.recovery.rtt.min_rtt().as_micros() as u64
         );
 
+        // Unknown path
+        assert!(test_pair
+            .server
+            .get_congestion_stats(remote, remote)
+            .is_err());
         Ok(())
     }
 
     #[test]
-    fn path_chal_loss_and_failed() -> Result<()> {
-        let mut test_pair = TestPair::new_with_zero_cid()?;
-        test_pair.handshake()?;
-
-        // Client send and fake lost of PATH_CHALLENGE.
-        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
-        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
-        let pid = test_pair.client.add_path(client_addr, server_addr)? as usize;
-        TestPair::conn_packets_out(&mut test_pair.client)?;
+    fn conn_set_max_pacing_rate() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
         assert_eq!(
-            test_pair.client.paths.get(pid)?.state(),
-            PathState::Validating
+            test_pair.client.paths.get(0)?.recovery.pacer.enabled(),
+            false
         );
 
-        for i in 0..path::MAX_PROBING_TIMEOUTS {
-            // Advance ticks until PATH_CHALLENGE timeout.
-            assert!(test_pair.client.timeout().is_some());
-            let now = test_pair.client.timers.get(Timer::PathChallenge).unwrap();
-            test_pair.client.on_timeout(now);
-
-            // Try to send PATH_CHALLENGE again.
-            TestPair::conn_packets_out(&mut test_pair.client)?;
-        }
-
-        // Path validation finally failed.
-        assert_eq!(test_pair.client.paths.get(pid)?.state(), PathState::Failed);
+        // Cap the pacing rate on the live connection.
+        test_pair.client.set_max_pacing_rate(10_000);
+        assert_eq!(test_pair.client.recovery_conf.max_pacing_rate, Some(10_000));
+        let path = test_pair.client.paths.get(0)?;
+        assert_eq!(path.recovery.pacer.enabled(), true);
 
+        // Remove the cap.
+        test_pair.client.set_max_pacing_rate(0);
+        assert_eq!(test_pair.client.recovery_conf.max_pacing_rate, None);
+        assert_eq!(
+            test_pair.client.paths.get(0)?.recovery.pacer.enabled(),
+            false
+        );
         Ok(())
     }
 
     #[test]
-    fn path_active_all_failed() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-
-        // Fake failing of all active path
-        let path = test_pair.client.paths.get_mut(0)?;
-        path.set_active(false);
+    fn conn_set_congestion_control_algorithm() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let min_rtt = test_pair.client.paths.get(0)?.recovery.rtt.min_rtt();
 
-        assert!(test_pair.client.scid().is_err());
-        assert!(test_pair.client.dcid().is_err());
+        // Switch the algorithm on the live connection.
+        test_pair
+            .client
+            .set_congestion_control_algorithm(CongestionControlAlgorithm::Bbr);
+        let path = test_pair.client.paths.get(0)?;
+        assert_eq!(path.recovery.congestion.name(), "BBR");
+        assert_eq!(path.recovery.rtt.min_rtt(), min_rtt);
+        assert_eq!(
+            test_pair.client.recovery_conf.congestion_control_algorithm,
+            CongestionControlAlgorithm::Bbr
+        );
 
+        // Data is transferred with the new congestion controller.
+        let data = TestPair::new_test_data(30);
+        test_pair.client.stream_write(0, data.clone(), true)?;
+        test_pair.move_forward()?;
+        let mut buf = vec![0; 64];
+        assert_eq!(test_pair.server.stream_read(0, &mut buf)?, (30, true));
+        assert_eq!(&buf[..30], &data[..]);
         Ok(())
     }
 
     #[test]
-    fn path_anti_ampl_limit() -> Result<()> {
-        let mut test_pair = TestPair::new_with_test_config()?;
-        {
-            let path = test_pair.server.paths.get_active().unwrap();
-            assert_eq!(path.anti_ampl_limit, 0);
-        }
-
-        // Client send Initial.
-        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-        let len_in: usize = packets.iter().map(|p| p.0.len()).sum();
+    fn conn_ecn() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.enable_ecn(true);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.enable_ecn(true);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
 
-        // Server recv Initial.
-        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
-        {
-            let path = test_pair.server.paths.get_active().unwrap();
-            assert_eq!(
-                path.anti_ampl_limit,
-                len_in * test_pair.server.paths.anti_ampl_factor
-            );
-        }
+        // ECN validation succeeded
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert_eq!(stats.ecn_capable, true);
+        assert_eq!(stats.ecn_ce_count, 0);
+        let stats = test_pair.server.paths.get_mut(0)?.stats();
+        assert_eq!(stats.ecn_capable, true);
 
-        // Server send Initial and Handshake.
-        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
-        let len_out: usize = packets.iter().map(|p| p.0.len()).sum();
-        {
-            let path = test_pair.server.paths.get_active().unwrap();
-            assert_eq!(
-                path.anti_ampl_limit,
-                len_in * test_pair.server.paths.anti_ampl_factor - len_out
-            );
+        // Client send packets which are marked with ECN-CE by the network
+        let data = TestPair::new_test_data(10);
+        test_pair.client.stream_write(0, data, false)?;
+        let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+        for (_, info) in packets.iter_mut() {
+            assert_eq!(info.ecn, Ecn::Ect0);
+            info.ecn = Ecn::Ce;
         }
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        test_pair.move_forward()?;
 
+        let space = test_pair.server.spaces.get(SpaceId::Data).unwrap();
+        assert!(space.recv_ecn_counts.ecn_ce_count > 0);
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert_eq!(stats.ecn_capable, true);
+        assert!(stats.ecn_ce_count > 0);
         Ok(())
     }
 
     #[test]
-    fn path_mtu_discovery_max() -> Result<()> {
-        let cases = [
-            // (cli_enable_dplpmtud, srv_enable_dplpmtud, cli_mtu , srv_mtu)
-            (false, false, 1200, 1200),
-            (false, true, 1200, 1472),
-            (true, false, 1472, 1200),
-            (true, true, 1472, 1472),
-        ];
+    fn conn_ecn_l4s() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.enable_ecn(true);
+        client_config.set_congestion_control_algorithm(CongestionControlAlgorithm::Prague);
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.enable_ecn(true);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.move_forward()?;
 
-        for case in cases {
-            let mut client_config = TestPair::new_test_config(false)?;
-            client_config.enable_dplpmtud(case.0);
-            client_config.set_ack_eliciting_threshold(1);
-            let mut server_config = TestPair::new_test_config(true)?;
-            server_config.enable_dplpmtud(case.1);
-            server_config.set_ack_eliciting_threshold(1);
-            let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
-            assert_eq!(test_pair.handshake(), Ok(()));
+        // ECN validation succeeded with ECT(1)
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert_eq!(stats.ecn_capable, true);
+        let space = test_pair.server.spaces.get(SpaceId::Data).unwrap();
+        assert!(space.recv_ecn_counts.ect1_count > 0);
+        assert_eq!(space.recv_ecn_counts.ect0_count, 0);
 
-            test_pair.move_forward()?;
-            assert_eq!(
-                test_pair.client.paths.get(0)?.recovery.max_datagram_size,
-                case.2
-            );
-            assert_eq!(
-                test_pair.server.paths.get(0)?.recovery.max_datagram_size,
-                case.3
-            );
+        // Client send packets which are marked with ECN-CE by the network
+        let cwnd = test_pair
+            .client
+            .paths
+            .get(0)?
+            .recovery
+            .congestion
+            .congestion_window();
+        let data = TestPair::new_test_data(10);
+        test_pair.client.stream_write(0, data, false)?;
+        let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+        for (_, info) in packets.iter_mut() {
+            assert_eq!(info.ecn, Ecn::Ect1);
+            info.ecn = Ecn::Ce;
         }
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        test_pair.move_forward()?;
 
+        // The congestion window is reduced in proportion to the ECN-CE marks
+        let path = test_pair.client.paths.get(0)?;
+        assert!(path.recovery.ecn.ce_count() > 0);
+        assert!(path.recovery.congestion.congestion_window() < cwnd);
         Ok(())
     }
 
     #[test]
-    fn path_mtu_discovery_lost() -> Result<()> {
-        let cases = [
-            // (router_mtu, searched_mtu)
-            (1472, 1463),
-            (1452, 1446),
-            (1432, 1429),
-            (1412, 1404),
-            (1392, 1387),
-            (1372, 1370),
-        ];
-
-        for case in cases {
-            let mut client_config = TestPair::new_test_config(false)?;
-            client_config.enable_dplpmtud(true);
-            client_config.set_ack_eliciting_threshold(1);
-            let mut server_config = TestPair::new_test_config(true)?;
-            server_config.enable_dplpmtud(false);
-            server_config.set_initial_max_data(10240);
-            server_config.set_initial_max_stream_data_bidi_remote(10240);
-            server_config.set_ack_eliciting_threshold(1);
-            let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
-            let router_mtu: usize = case.0;
-
-            // Handshake
-            while !test_pair.client.is_established() || !test_pair.server.is_established() {
-                let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-                packets.retain(|p| p.0.len() < router_mtu); // fake dropping packets
-                TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+    fn conn_ecn_not_supported() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.enable_ecn(true);
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
 
-                let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
-                TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        // The ECN codepoints are cleared by the network
+        while !test_pair.client.is_established() || !test_pair.server.is_established() {
+            let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+            for (_, info) in packets.iter_mut() {
+                info.ecn = Ecn::NotEct;
             }
+            TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+            TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        }
 
-            // Path MTU searching
-            let data = Bytes::from_static(b"data");
-            for i in 0..30 {
-                let _ = test_pair.client.stream_write(0, data.clone(), false);
-                let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-                packets.retain(|p| p.0.len() < router_mtu); // fake dropping packets
+        // ECN validation failed
+        let stats = test_pair.client.paths.get_mut(0)?.stats();
+        assert_eq!(stats.ecn_capable, false);
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        for (_, info) in packets.iter() {
+            assert_eq!(info.ecn, Ecn::NotEct);
+        }
+        Ok(())
+    }
 
-                TestPair::conn_packets_in(&mut test_pair.server, packets)?;
-                let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
-                TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+    #[test]
+    fn conn_ecn_policy_always() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_ecn_policy(EcnPolicy::Always);
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
 
-                if test_pair.client.timeout().is_some() {
-                    let timeout = test_pair.client.timers.get(Timer::LossDetection);
-                    test_pair.client.on_timeout(timeout.unwrap());
-                }
+        // The ECN codepoints are cleared by the network
+        while !test_pair.client.is_established() || !test_pair.server.is_established() {
+            let mut packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+            for (_, info) in packets.iter_mut() {
+                info.ecn = Ecn::NotEct;
             }
+            TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+            let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+            TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        }
 
-            // Check final MTU
-            assert_eq!(
-                test_pair.client.paths.get(0)?.recovery.max_datagram_size,
-                case.1
-            );
+        // The packets are still marked although the ECN validation failed
+        let data = TestPair::new_test_data(10);
+        test_pair.client.stream_write(0, data, false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+        for (_, info) in packets.iter() {
+            assert_eq!(info.ecn, Ecn::Ect0);
+        }
+
+        // Disable ECN on the path
+        let path = test_pair.client.paths.get(0)?;
+        let (local, remote) = (path.local_addr(), path.remote_addr());
+        test_pair
+            .client
+            .set_path_ecn_policy(local, remote, EcnPolicy::Disabled)?;
+        let data = TestPair::new_test_data(10);
+        test_pair.client.stream_write(0, data, false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+        for (_, info) in packets.iter() {
+            assert_eq!(info.ecn, Ecn::NotEct);
         }
 
+        // Unknown path
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        assert!(test_pair
+            .client
+            .set_path_ecn_policy(addr, addr, EcnPolicy::Always)
+            .is_err());
         Ok(())
     }
 
     #[test]
-    fn ping() -> Result<()> {
+    fn conn_dscp() -> Result<()> {
         let mut client_config = TestPair::new_test_config(false)?;
-        client_config.enable_dplpmtud(false);
-        client_config.local_transport_params = TransportParams {
-            max_idle_timeout: 15000,
-            ..TransportParams::default()
-        };
+        client_config.set_dscp(46);
         let mut server_config = TestPair::new_test_config(true)?;
-        server_config.enable_dplpmtud(false);
-        server_config.local_transport_params = TransportParams {
-            max_idle_timeout: 15000,
-            ..TransportParams::default()
-        };
         let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
         test_pair.handshake()?;
 
-        // Move both connections to idle state
-        test_pair.move_forward()?;
-
-        // Enable qlog for Server
-        let slog = NamedTempFile::new().unwrap();
-        let mut sfile = slog.reopen().unwrap();
-        test_pair
-            .server
-            .set_qlog(Box::new(slog), "title".into(), "desc".into());
+        // The packets are marked with the DSCP of the config
+        assert_eq!(test_pair.client.dscp(), 46);
+        let data = TestPair::new_test_data(10);
+        test_pair.client.stream_write(0, data.clone(), false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+        for (_, info) in packets.iter() {
+            assert_eq!(info.dscp, 46);
+            assert_eq!(info.tos(), (46 << 2) | info.ecn as u8);
+        }
 
-        // Client send a Ping frame
-        test_pair.client.ping(None)?;
+        // The packets are marked with the DSCP of the connection
+        test_pair.client.set_dscp(0xff);
+        assert_eq!(test_pair.client.dscp(), 0x3f);
+        test_pair.client.set_dscp(10);
+        test_pair.client.stream_write(0, data.clone(), false)?;
         let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
-        TestPair::conn_packets_in(&mut test_pair.server, packets.clone())?;
+        assert!(!packets.is_empty());
+        for (_, info) in packets.iter() {
+            assert_eq!(info.dscp, 10);
+        }
 
-        let mut slog_content = String::new();
-        sfile.read_to_string(&mut slog_content).unwrap();
-        assert_eq!(slog_content.contains("quic:packet_received"), true);
-        assert_eq!(slog_content.contains("frame_type\":\"ping"), true);
+        // The packets are marked with the DSCP of the path
+        let path = test_pair.client.paths.get(0)?;
+        let (local, remote) = (path.local_addr(), path.remote_addr());
+        test_pair.client.set_path_dscp(local, remote, 34)?;
+        test_pair.client.stream_write(0, data, false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(!packets.is_empty());
+        for (_, info) in packets.iter() {
+            assert_eq!(info.dscp, 34);
+        }
 
+        // Unknown path
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        assert!(test_pair.client.set_path_dscp(addr, addr, 34).is_err());
         Ok(())
     }
 
@@ -6365,7 +9834,9 @@ pub(crate) mod tests {
         // Ignore the empty packet
         assert_eq!(test_pair.server.recv(&mut [], &info), Err(Error::NoError));
         assert_eq!(
-            test_pair.server.recv_packet(&mut [], &info, None),
+            test_pair
+                .server
+                .recv_packet(&mut BytesMut::new(), &info, None),
             Err(Error::Done)
         );
         Ok(())
@@ -6561,7 +10032,9 @@ pub(crate) mod tests {
 
         // Server drop the truncated packet
         assert_eq!(
-            test_pair.server.recv_packet(&mut initial_pkt, &info, None),
+            test_pair
+                .server
+                .recv_packet(&mut BytesMut::from(&initial_pkt[..]), &info, None),
             Err(Error::Done)
         );
         assert_eq!(
@@ -6639,6 +10112,46 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn optimistic_ack_detection() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_packet_number_skip_interval(1);
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        // The skipped packet numbers are not acknowledged by an honest peer.
+        let data = Bytes::from_static(b"data");
+        for stream_id in [0, 4, 8, 12] {
+            test_pair
+                .client
+                .stream_write(stream_id, data.clone(), true)?;
+            test_pair.move_forward()?;
+        }
+        let space = test_pair.client.spaces.get(SpaceId::Data).unwrap();
+        let skipped = space.skipped_pkt_num.unwrap();
+
+        // Fake an ACK frame acknowledging the skipped packet number.
+        let mut ack_ranges = RangeSet::default();
+        ack_ranges.insert(0..skipped + 1);
+        let mut packet = TestPair::conn_build_packet(
+            &mut test_pair.server,
+            PacketType::OneRTT,
+            &[frame::Frame::Ack {
+                ack_delay: 0,
+                ack_ranges,
+                ecn_counts: None,
+            }],
+        )?;
+        let info = TestPair::new_test_packet_info(true);
+        assert_eq!(
+            test_pair.client.recv(&mut packet, &info),
+            Err(Error::ProtocolViolation)
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn recv_packet_skipped_packet_number() -> Result<()> {
         let mut client_config = TestPair::new_test_config(false)?;
@@ -6656,7 +10169,10 @@ pub(crate) mod tests {
             let packet = TestPair::conn_build_packet(
                 &mut test_pair.client,
                 PacketType::OneRTT,
-                &[frame::Frame::Ping { pmtu_probe: None }],
+                &[frame::Frame::Ping {
+                    pmtu_probe: None,
+                    probe: None,
+                }],
             )?;
 
             // Server recv OneRTT packet and send ack
@@ -6690,7 +10206,10 @@ pub(crate) mod tests {
                 TestPair::conn_build_packet(
                     &mut test_pair.client,
                     PacketType::OneRTT,
-                    &[frame::Frame::Ping { pmtu_probe: None }],
+                    &[frame::Frame::Ping {
+                        pmtu_probe: None,
+                        probe: None,
+                    }],
                 )?,
                 info,
             ));
@@ -6995,6 +10514,148 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn flow_control_paused() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        // Client send data on a stream
+        let (sid, data) = (0, TestPair::new_test_data(30));
+        test_pair.client.stream_write(sid, data.clone(), false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(test_pair.client.stream_capacity(sid)?, 10);
+
+        // Server read data while the flow control is paused
+        test_pair.server.set_flow_control_paused(true);
+        assert_eq!(
+            test_pair.server.stream_set_flow_control_paused(sid, true),
+            Ok(())
+        );
+        let mut buf = vec![0; 30];
+        assert_eq!(test_pair.server.stream_read(sid, &mut buf)?, (30, false));
+        assert!(!test_pair.server.streams.need_send_max_data());
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        assert_eq!(test_pair.client.stream_capacity(sid)?, 10);
+
+        // Server resume the stream-level flow control
+        test_pair
+            .server
+            .stream_set_flow_control_paused(sid, false)?;
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        assert_eq!(test_pair.client.stream_capacity(sid)?, 40);
+
+        // Server resume the connection-level flow control
+        test_pair.server.set_flow_control_paused(false);
+        assert!(test_pair.server.streams.need_send_max_data());
+
+        // The stream does not exist
+        assert_eq!(
+            test_pair.server.stream_set_flow_control_paused(8, true),
+            Err(Error::StreamStateError)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn stream_want_write_threshold() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let sid = 0;
+        test_pair.client.stream_new(sid, 0, false)?;
+
+        // The stream is writable when the flow control credit reaches the threshold
+        test_pair.client.stream_want_write_threshold(sid, 40)?;
+        assert!(test_pair.client.stream_check_writable(sid));
+        test_pair.client.stream_want_write_threshold(sid, 41)?;
+        assert!(!test_pair.client.stream_check_writable(sid));
+
+        // The congestion window is taken into account as well
+        test_pair.client.stream_want_write_threshold(sid, 20)?;
+        let path = test_pair.client.paths.get_active_mut()?;
+        let cwnd = path.recovery.congestion.congestion_window() as usize;
+        path.recovery.bytes_in_flight = cwnd - 10;
+        assert!(!test_pair.client.stream_check_writable(sid));
+
+        // The threshold is removed
+        test_pair.client.stream_want_write_threshold(sid, 0)?;
+        assert!(test_pair.client.stream_check_writable(sid));
+
+        // The stream does not exist
+        assert_eq!(
+            test_pair.client.stream_want_write_threshold(8, 20),
+            Err(Error::Done)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn stream_event_subscription() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.client.streams.events.enable();
+        test_pair.server.streams.events.enable();
+
+        fn stream_events(conn: &mut Connection) -> Vec<(u64, StreamEvent)> {
+            let mut events = vec![];
+            while let Some(event) = conn.poll() {
+                if let Event::StreamNotified(stream_id, event) = event {
+                    events.push((stream_id, event));
+                }
+            }
+            events
+        }
+
+        // Client subscribes to the acknowledgement of the stream data
+        let data = TestPair::new_test_data(10);
+        for sid in [0, 4] {
+            test_pair.client.stream_write(sid, data.clone(), false)?;
+        }
+        test_pair.client.stream_want_acked(0, 10)?;
+        test_pair.client.stream_want_acked(4, 20)?;
+        test_pair.client.stream_want_stop_sending(0, true)?;
+        assert_eq!(test_pair.client.stream_want_acked(8, 10), Err(Error::Done));
+
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+
+        // The STREAM frames are acknowledged
+        for sid in [0, 4] {
+            test_pair.client.streams.on_stream_frame_acked(sid, 0, 10);
+        }
+        assert_eq!(
+            stream_events(&mut test_pair.client),
+            vec![(0, StreamEvent::Acked { offset: 10 })]
+        );
+
+        // The data is already acknowledged
+        test_pair.client.stream_want_acked(4, 10)?;
+        assert_eq!(
+            stream_events(&mut test_pair.client),
+            vec![(4, StreamEvent::Acked { offset: 10 })]
+        );
+
+        // Server subscribes to the reset event and stops the stream
+        test_pair.server.stream_want_reset(4, true)?;
+        test_pair.server.stream_shutdown(0, Shutdown::Read, 7)?;
+        test_pair.client.stream_shutdown(4, Shutdown::Write, 5)?;
+        test_pair.move_forward()?;
+        assert_eq!(
+            stream_events(&mut test_pair.client),
+            vec![(0, StreamEvent::StopSending { error_code: 7 })]
+        );
+        assert_eq!(
+            stream_events(&mut test_pair.server),
+            vec![(4, StreamEvent::Reset { error_code: 5 })]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn stream_operations() -> Result<()> {
         let mut test_pair = TestPair::new_with_test_config()?;
@@ -7377,6 +11038,105 @@ pub(crate) mod tests {
         // client send STREAM_DATA_BLOCKED
         let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
         TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(test_pair.client.stats().stream_data_blocked_count, 1);
+        assert!(test_pair.client.stream_data_blocked.contains_key(&sid));
+
+        // Server read data and send MAX_STREAM_DATA
+        let mut buf = [0; 64];
+        assert_eq!(
+            test_pair.server.stream_read(sid, &mut buf)?,
+            (data.len(), false)
+        );
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        assert_eq!(test_pair.server.stats().max_stream_data_sent_count, 1);
+
+        // Client is unblocked
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        assert_eq!(test_pair.client.stats().max_stream_data_recv_count, 1);
+        assert!(test_pair.client.stream_data_blocked.is_empty());
+        assert_eq!(test_pair.client.stats().stream_data_blocked_count, 1);
+
+        // The blocked state is removed once the stream is closed
+        let now = time::Instant::now();
+        test_pair.client.stream_data_blocked.insert(sid, (100, now));
+        test_pair.client.streams.get_mut(sid).unwrap().mark_closed();
+        test_pair.client.stream_write(4, data.clone(), false)?;
+        let _ = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert!(test_pair.client.stream_data_blocked.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn flow_control_blocked_events() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        test_pair.client.events.enable();
+        test_pair.server.events.enable();
+
+        // Client is blocked by the stream-level and connection-level limits
+        let data = TestPair::new_test_data(40);
+        for i in 0..3 {
+            test_pair.client.stream_write(i * 4, data.clone(), false)?;
+        }
+        test_pair.move_forward()?;
+
+        let mut client_events = vec![];
+        while let Some(event) = test_pair.client.poll() {
+            if let Event::FlowControlBlocked(event) = event {
+                client_events.push(event);
+            }
+        }
+        assert!(client_events.contains(&BlockedEvent::StreamDataBlocked {
+            stream_id: 0,
+            limit: 40
+        }));
+        assert!(client_events.contains(&BlockedEvent::DataBlocked { limit: 90 }));
+
+        // Server is informed that the client is blocked
+        let mut server_events = vec![];
+        while let Some(event) = test_pair.server.poll() {
+            if let Event::FlowControlBlocked(event) = event {
+                server_events.push(event);
+            }
+        }
+        assert!(
+            server_events.contains(&BlockedEvent::PeerStreamDataBlocked {
+                stream_id: 0,
+                limit: 40
+            })
+        );
+        assert!(server_events.contains(&BlockedEvent::PeerDataBlocked { limit: 90 }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn target_bitrate_updated_events() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        client_config.set_congestion_control_algorithm(CongestionControlAlgorithm::Nada);
+        client_config.set_nada_min_bitrate(300_000);
+        let mut server_config = TestPair::new_test_config(true)?;
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        test_pair.client.events.enable();
+        test_pair.server.events.enable();
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        // The initial target bitrate is reported once
+        let mut client_events = vec![];
+        while let Some(event) = test_pair.client.poll() {
+            if let Event::TargetBitrateUpdated(bitrate) = event {
+                client_events.push(bitrate);
+            }
+        }
+        assert_eq!(client_events, vec![300_000]);
+        assert_eq!(test_pair.client.target_bitrate(), Some(300_000));
+
+        // The congestion controller of server is not for real-time media
+        while let Some(event) = test_pair.server.poll() {
+            assert!(!matches!(event, Event::TargetBitrateUpdated(_)));
+        }
+        assert_eq!(test_pair.server.target_bitrate(), None);
 
         Ok(())
     }
@@ -7410,6 +11170,119 @@ pub(crate) mod tests {
         // client send DATA_BLOCKED
         let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
         TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        assert_eq!(test_pair.client.stats().data_blocked_count, 1);
+        assert!(test_pair.client.data_blocked.is_some());
+
+        // The lost DATA_BLOCKED frame does not start a new blocked period
+        test_pair.client.streams.on_data_blocked_frame_lost(90);
+        let _ = TestPair::conn_packets_out(&mut test_pair.client)?;
+        assert_eq!(test_pair.client.stats().data_blocked_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn conn_recv_window_budget() -> Result<()> {
+        let mut test_pair = TestPair::new_with_test_config()?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+
+        // The initial window of the server is reserved from the budget
+        let budget = Rc::new(RefCell::new(RecvWindowBudget::new(100)));
+        test_pair.server.set_recv_budget(budget.clone());
+        assert_eq!(test_pair.server.recv_budget_reserved, 90);
+        assert_eq!(budget.borrow().used, 90);
+
+        // Client send data on the streams and server read them
+        let data = TestPair::new_test_data(30);
+        for i in 0..3 {
+            test_pair.client.stream_write(i * 4, data.clone(), false)?;
+        }
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        let mut buf = vec![0; 30];
+        for i in 0..3 {
+            assert_eq!(test_pair.server.stream_read(i * 4, &mut buf)?, (30, false));
+        }
+
+        // Server advertise a window limited by the remaining budget
+        test_pair.server.streams.ensure_window_lower_bound(200);
+        let _ = TestPair::conn_packets_out(&mut test_pair.server)?;
+        assert_eq!(test_pair.server.streams.window(), 100);
+        assert_eq!(test_pair.server.streams.max_rx_data(), 190);
+        assert_eq!(test_pair.server.recv_budget_reserved, 100);
+        assert_eq!(budget.borrow().used, 100);
+
+        // The window is returned to the budget
+        test_pair.server.release_recv_budget();
+        assert_eq!(budget.borrow().used, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn conn_recv_window_decay() -> Result<()> {
+        let mut client_config = TestPair::new_test_config(false)?;
+        let mut server_config = TestPair::new_test_config(true)?;
+        server_config.set_window_decay_rtts(100);
+        server_config.set_window_decay_floor(20);
+        let mut test_pair = TestPair::new(&mut client_config, &mut server_config)?;
+        assert_eq!(test_pair.handshake(), Ok(()));
+        let budget = Rc::new(RefCell::new(RecvWindowBudget::new(300)));
+        test_pair.server.set_recv_budget(budget.clone());
+
+        // Client send data on the streams and server read them
+        let data = TestPair::new_test_data(30);
+        for i in 0..3 {
+            test_pair.client.stream_write(i * 4, data.clone(), false)?;
+        }
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        let mut buf = vec![0; 30];
+        for i in 0..3 {
+            assert_eq!(test_pair.server.stream_read(i * 4, &mut buf)?, (30, false));
+        }
+
+        // Server send MAX_DATA/MAX_STREAM_DATA
+        let packets = TestPair::conn_packets_out(&mut test_pair.server)?;
+        TestPair::conn_packets_in(&mut test_pair.client, packets)?;
+        assert_eq!(test_pair.server.streams.window(), 90);
+        assert_eq!(test_pair.server.streams.max_rx_data(), 180);
+
+        // The window of the idle connection decays on timeout, at most once
+        // within the decay period, but the advertised credit is kept
+        let now = time::Instant::now();
+        let srtt = test_pair
+            .server
+            .paths
+            .get_active()?
+            .recovery
+            .rtt
+            .smoothed_rtt();
+        for _ in 0..2 {
+            test_pair.server.on_timeout(now + srtt * 101);
+            assert_eq!(test_pair.server.streams.window(), 45);
+            assert_eq!(test_pair.server.streams.max_rx_data(), 180);
+            assert_eq!(test_pair.server.recv_budget_reserved, 90);
+        }
+
+        // The consumed credit is returned to the budget when the window decays
+        let data = TestPair::new_test_data(20);
+        for i in 0..3 {
+            test_pair.client.stream_write(i * 4, data.clone(), false)?;
+        }
+        let packets = TestPair::conn_packets_out(&mut test_pair.client)?;
+        TestPair::conn_packets_in(&mut test_pair.server, packets)?;
+        for i in 0..3 {
+            assert_eq!(test_pair.server.stream_read(i * 4, &mut buf)?, (20, false));
+        }
+        test_pair.server.on_timeout(now + srtt * 202);
+        assert_eq!(test_pair.server.streams.window(), 22);
+        assert_eq!(test_pair.server.recv_budget_reserved, 30);
+        assert_eq!(budget.borrow().used, 30);
+
+        // The window is not shrunk below the floor
+        test_pair.server.on_timeout(now + srtt * 303);
+        assert_eq!(test_pair.server.streams.window(), 20);
 
         Ok(())
     }
@@ -7695,7 +11568,12 @@ pub(crate) mod tests {
         assert_eq!(clog_content.contains("quic:parameters_set"), true);
         assert_eq!(clog_content.contains("quic:stream_data_moved"), true);
         assert_eq!(clog_content.contains("quic:packet_sent"), true);
+        assert_eq!(clog_content.contains("recovery:parameters_set"), true);
         assert_eq!(clog_content.contains("recovery:metrics_updated"), true);
+        assert_eq!(
+            clog_content.contains("recovery:congestion_state_updated"),
+            true
+        );
         assert_eq!(clog_content.contains("recovery:packet_lost"), true);
 
         // Check server qlog
@@ -7706,6 +11584,10 @@ pub(crate) mod tests {
         assert_eq!(slog_content.contains("quic:stream_data_moved"), true);
         assert_eq!(slog_content.contains("quic:packet_received"), true);
         assert_eq!(slog_content.contains("recovery:metrics_updated"), true);
+        assert_eq!(
+            slog_content.contains("recovery:congestion_state_updated"),
+            true
+        );
 
         Ok(())
     }
@@ -7888,12 +11770,17 @@ pub(crate) mod tests {
     }
 }
 
+mod bandwidth;
+mod careful_resume;
 mod cid;
-mod flowcontrol;
+mod ecn;
+pub(crate) mod flowcontrol;
+mod keep_alive;
 pub mod path;
 mod pmtu;
 mod recovery;
 pub(crate) mod rtt;
 pub(crate) mod space;
 pub(crate) mod stream;
+pub(crate) mod stream_scheduler;
 pub(crate) mod timer;
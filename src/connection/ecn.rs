@@ -0,0 +1,530 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::frame::EcnCounts;
+use crate::Ecn;
+use crate::EcnPolicy;
+
+/// The number of packets marked with ECT(0) sent during the testing period.
+/// See RFC 9000 Section A.4
+const ECN_TESTING_COUNT: u64 = 10;
+
+/// The state of ECN validation on a path.
+/// See RFC 9000 Section A.4
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum EcnState {
+    /// The endpoint is sending packets with an ECT(0) codepoint to test the
+    /// path.
+    Testing,
+
+    /// The testing packets have been sent, and the endpoint stops marking
+    /// packets until the validation succeeds or fails.
+    Unknown,
+
+    /// The validation succeeded, and the packets are marked with ECT(0).
+    Capable,
+
+    /// The validation failed or ECN is disabled, and the packets are not
+    /// marked.
+    Failed,
+}
+
+/// ECN validation for a path.
+/// See RFC 9000 Section 13.4.2
+pub(super) struct EcnValidator {
+    /// The policy of using ECN on the path.
+    policy: EcnPolicy,
+
+    /// The current state of ECN validation.
+    state: EcnState,
+
+    /// The number of validation failures.
+    failures: u32,
+
+    /// The number of validation failures before falling back to not using
+    /// ECN on the path.
+    max_failures: u32,
+
+    /// Whether to test a new path even if the validation failed on another
+    /// path of the connection.
+    retest_on_migration: bool,
+
+    /// The number of packets marked with ECT(0) sent during validation.
+    testing_sent: u64,
+
+    /// The number of packets marked with ECT(0) lost during validation.
+    testing_lost: u64,
+
+    /// The total increase in the ECN-CE counts reported by the peer.
+    ce_count: u64,
+
+    /// Whether to mark the packets with ECT(1) instead of ECT(0) for the L4S
+    /// congestion controllers. See RFC 9331 Section 4.1
+    l4s: bool,
+
+    /// Whether any packet marked with ECT has been sent.
+    ect_sent: bool,
+
+    /// Whether the ECT codepoint has been changed after packets were sent.
+    /// Since the packets in flight may carry either ECT codepoint, both of
+    /// them are counted in the validation afterwards.
+    ect_changed: bool,
+}
+
+impl EcnValidator {
+    pub(super) fn new(
+        policy: EcnPolicy,
+        max_failures: u32,
+        retest_on_migration: bool,
+        l4s: bool,
+    ) -> Self {
+        Self {
+            policy,
+            state: Self::initial_state(policy),
+            failures: 0,
+            max_failures,
+            retest_on_migration,
+            testing_sent: 0,
+            testing_lost: 0,
+            ce_count: 0,
+            l4s,
+            ect_sent: false,
+            ect_changed: false,
+        }
+    }
+
+    fn initial_state(policy: EcnPolicy) -> EcnState {
+        match policy {
+            EcnPolicy::Disabled => EcnState::Failed,
+            EcnPolicy::TestThenUse => EcnState::Testing,
+            EcnPolicy::Always => EcnState::Capable,
+        }
+    }
+
+    /// Change the policy of using ECN, and restart the validation.
+    pub(super) fn set_policy(&mut self, policy: EcnPolicy) {
+        self.policy = policy;
+        self.state = Self::initial_state(policy);
+        self.failures = 0;
+        self.testing_sent = 0;
+        self.testing_lost = 0;
+    }
+
+    /// Mark the packets with ECT(1) for the L4S congestion controllers, or
+    /// with ECT(0) otherwise.
+    pub(super) fn set_l4s(&mut self, l4s: bool) {
+        if self.l4s != l4s {
+            self.l4s = l4s;
+            self.ect_changed = self.ect_sent;
+        }
+    }
+
+    /// Return whether the validation has failed on the path.
+    pub(super) fn is_failed(&self) -> bool {
+        self.policy == EcnPolicy::TestThenUse && self.state == EcnState::Failed
+    }
+
+    /// Return whether a new path should be tested if the validation failed on
+    /// another path.
+    pub(super) fn retest_on_migration(&self) -> bool {
+        self.retest_on_migration
+    }
+
+    /// Stop using ECN on the path without testing.
+    pub(super) fn fall_back(&mut self) {
+        if self.policy == EcnPolicy::TestThenUse {
+            self.state = EcnState::Failed;
+        }
+    }
+
+    /// Handle a validation failure. The path is tested again until the number
+    /// of failures reaches the limit. The packets are always marked with the
+    /// `Always` policy.
+    fn on_validation_failed(&mut self) {
+        if self.policy != EcnPolicy::TestThenUse {
+            return;
+        }
+
+        self.failures += 1;
+        if self.failures < self.max_failures {
+            self.state = EcnState::Testing;
+            self.testing_sent = 0;
+            self.testing_lost = 0;
+        } else {
+            self.state = EcnState::Failed;
+        }
+    }
+
+    /// Return whether the path has been validated as ECN capable.
+    pub(super) fn is_capable(&self) -> bool {
+        self.state == EcnState::Capable
+    }
+
+    /// Return the ECN codepoint for the packets to be sent.
+    pub(super) fn ecn_mark(&self) -> Ecn {
+        match self.state {
+            EcnState::Testing | EcnState::Capable if self.l4s => Ecn::Ect1,
+            EcnState::Testing | EcnState::Capable => Ecn::Ect0,
+            EcnState::Unknown | EcnState::Failed => Ecn::NotEct,
+        }
+    }
+
+    /// Return the total number of packets with the ECN-CE codepoint reported
+    /// by the peer.
+    pub(super) fn ce_count(&self) -> u64 {
+        self.ce_count
+    }
+
+    /// Handle sent event of a packet marked with ECT(0).
+    pub(super) fn on_packet_sent(&mut self) {
+        self.ect_sent = true;
+        if self.state == EcnState::Testing {
+            self.testing_sent += 1;
+        }
+    }
+
+    /// Handle sent event of a UDP datagram. The state is only changed between
+    /// datagrams, so that all packets in a datagram are marked the same.
+    pub(super) fn on_datagram_sent(&mut self) {
+        if self.state == EcnState::Testing && self.testing_sent >= ECN_TESTING_COUNT {
+            self.state = EcnState::Unknown;
+        }
+    }
+
+    /// Handle loss of packets marked with ECT(0).
+    pub(super) fn on_packets_lost(&mut self, lost: u64) {
+        if self.state != EcnState::Testing && self.state != EcnState::Unknown {
+            return;
+        }
+
+        // If all packets marked with ECT(0) are lost, the path may drop the
+        // packets marked with ECN codepoints.
+        self.testing_lost += lost;
+        if self.testing_lost >= self.testing_sent {
+            self.on_validation_failed();
+        }
+    }
+
+    /// Validate the ECN counts in an ACK frame, which newly acknowledges
+    /// `newly_acked` packets marked with ECT(0). The `peer_counts` is the
+    /// largest ECN counts reported by the peer in the packet number space,
+    /// and it is updated if the validation succeeds.
+    ///
+    /// Validating ECN counts from reordered ACK frames can result in failure,
+    /// so it should only be called for ACK frames that increase the largest
+    /// acknowledged packet number. See RFC 9000 Section 13.4.2.1
+    ///
+    /// Return the increase in the ECN-CE count, which should be treated as a
+    /// congestion signal.
+    pub(super) fn on_ack_received(
+        &mut self,
+        newly_acked: u64,
+        counts: Option<&EcnCounts>,
+        peer_counts: &mut EcnCounts,
+    ) -> u64 {
+        if self.state == EcnState::Failed || newly_acked == 0 {
+            return 0;
+        }
+
+        // An ACK frame that newly acknowledges a packet marked with ECT(0)
+        // should contain ECN counts.
+        let counts = match counts {
+            Some(v) => v,
+            None => {
+                self.on_validation_failed();
+                return 0;
+            }
+        };
+
+        // The ECN counts should never decrease. The increase in the ECT and
+        // ECN-CE counts should be no less than the number of newly
+        // acknowledged packets marked with ECT. The count of the other ECT
+        // codepoint should not increase since it is never used.
+        let peer = peer_counts;
+        let (ect, peer_ect, other, peer_other) = if self.ect_changed {
+            (
+                counts.ect0_count + counts.ect1_count,
+                peer.ect0_count + peer.ect1_count,
+                0,
+                0,
+            )
+        } else if self.l4s {
+            (
+                counts.ect1_count,
+                peer.ect1_count,
+                counts.ect0_count,
+                peer.ect0_count,
+            )
+        } else {
+            (
+                counts.ect0_count,
+                peer.ect0_count,
+                counts.ect1_count,
+                peer.ect1_count,
+            )
+        };
+        if ect < peer_ect
+            || counts.ecn_ce_count < peer.ecn_ce_count
+            || other != peer_other
+            || (ect - peer_ect) + (counts.ecn_ce_count - peer.ecn_ce_count) < newly_acked
+        {
+            self.on_validation_failed();
+            return 0;
+        }
+
+        let ce_increase = counts.ecn_ce_count - peer.ecn_ce_count;
+        *peer = *counts;
+        self.ce_count += ce_increase;
+        self.state = EcnState::Capable;
+        ce_increase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_counts(ect0_count: u64, ect1_count: u64, ecn_ce_count: u64) -> EcnCounts {
+        EcnCounts {
+            ect0_count,
+            ect1_count,
+            ecn_ce_count,
+        }
+    }
+
+    #[test]
+    fn ecn_disabled() {
+        let mut peer = EcnCounts::default();
+        let mut v = EcnValidator::new(EcnPolicy::Disabled, 1, true, false);
+        assert_eq!(v.state, EcnState::Failed);
+        assert_eq!(v.ecn_mark(), Ecn::NotEct);
+
+        assert_eq!(
+            v.on_ack_received(1, Some(&new_counts(1, 0, 0)), &mut peer),
+            0
+        );
+        assert_eq!(v.state, EcnState::Failed);
+    }
+
+    #[test]
+    fn ecn_testing() {
+        let mut peer = EcnCounts::default();
+        let mut v = EcnValidator::new(EcnPolicy::TestThenUse, 1, true, false);
+        assert_eq!(v.state, EcnState::Testing);
+
+        for _ in 0..ECN_TESTING_COUNT {
+            assert_eq!(v.ecn_mark(), Ecn::Ect0);
+            v.on_packet_sent();
+            v.on_datagram_sent();
+        }
+        assert_eq!(v.state, EcnState::Unknown);
+        assert_eq!(v.ecn_mark(), Ecn::NotEct);
+
+        // Some testing packets are lost
+        v.on_packets_lost(2);
+        assert_eq!(v.state, EcnState::Unknown);
+
+        // Validation succeeded
+        assert_eq!(
+            v.on_ack_received(8, Some(&new_counts(7, 0, 1)), &mut peer),
+            1
+        );
+        assert_eq!(v.state, EcnState::Capable);
+        assert_eq!(v.ecn_mark(), Ecn::Ect0);
+        assert_eq!(v.ce_count(), 1);
+
+        // Packets marked with ECN-CE
+        assert_eq!(
+            v.on_ack_received(3, Some(&new_counts(8, 0, 3)), &mut peer),
+            2
+        );
+        assert_eq!(v.ce_count(), 3);
+
+        // Loss is ignored after validation
+        v.on_packets_lost(100);
+        assert_eq!(v.state, EcnState::Capable);
+    }
+
+    #[test]
+    fn ecn_testing_lost() {
+        let mut v = EcnValidator::new(EcnPolicy::TestThenUse, 1, true, false);
+        for _ in 0..ECN_TESTING_COUNT {
+            v.on_packet_sent();
+        }
+        v.on_datagram_sent();
+        assert_eq!(v.state, EcnState::Unknown);
+
+        // All testing packets are lost
+        v.on_packets_lost(ECN_TESTING_COUNT);
+        assert_eq!(v.state, EcnState::Failed);
+        assert_eq!(v.ecn_mark(), Ecn::NotEct);
+    }
+
+    #[test]
+    fn ecn_validation_failed() {
+        let cases = [
+            // ACK frame without ECN counts
+            None,
+            // Counts are less than the number of newly acked packets
+            Some(new_counts(1, 0, 0)),
+            // ECT(1) count increased
+            Some(new_counts(2, 1, 0)),
+        ];
+
+        for counts in cases {
+            let mut peer = EcnCounts::default();
+            let mut v = EcnValidator::new(EcnPolicy::TestThenUse, 1, true, false);
+            v.on_packet_sent();
+            v.on_packet_sent();
+            assert_eq!(v.on_ack_received(2, counts.as_ref(), &mut peer), 0);
+            assert_eq!(v.state, EcnState::Failed);
+        }
+
+        // Counts decreased
+        let mut peer = EcnCounts::default();
+        let mut v = EcnValidator::new(EcnPolicy::TestThenUse, 1, true, false);
+        v.on_packet_sent();
+        v.on_packet_sent();
+        assert_eq!(
+            v.on_ack_received(1, Some(&new_counts(1, 0, 0)), &mut peer),
+            0
+        );
+        assert_eq!(v.state, EcnState::Capable);
+        assert_eq!(
+            v.on_ack_received(1, Some(&new_counts(0, 0, 1)), &mut peer),
+            0
+        );
+        assert_eq!(v.state, EcnState::Failed);
+    }
+
+    #[test]
+    fn ecn_no_newly_acked() {
+        let mut peer = EcnCounts::default();
+        let mut v = EcnValidator::new(EcnPolicy::TestThenUse, 1, true, false);
+        assert_eq!(v.on_ack_received(0, None, &mut peer), 0);
+        assert_eq!(v.state, EcnState::Testing);
+    }
+
+    #[test]
+    fn ecn_always() {
+        let mut peer = EcnCounts::default();
+        let mut v = EcnValidator::new(EcnPolicy::Always, 1, true, false);
+        assert_eq!(v.state, EcnState::Capable);
+        assert_eq!(v.ecn_mark(), Ecn::Ect0);
+
+        // Validation failures are ignored
+        v.on_packet_sent();
+        v.on_packets_lost(1);
+        assert_eq!(v.on_ack_received(1, None, &mut peer), 0);
+        assert_eq!(v.ecn_mark(), Ecn::Ect0);
+        assert!(!v.is_failed());
+
+        // Packets marked with ECN-CE
+        assert_eq!(
+            v.on_ack_received(1, Some(&new_counts(0, 0, 1)), &mut peer),
+            1
+        );
+        assert_eq!(v.ce_count(), 1);
+    }
+
+    #[test]
+    fn ecn_retest() {
+        let mut v = EcnValidator::new(EcnPolicy::TestThenUse, 2, true, false);
+        for _ in 0..ECN_TESTING_COUNT {
+            v.on_packet_sent();
+        }
+        v.on_datagram_sent();
+        assert_eq!(v.state, EcnState::Unknown);
+
+        // The path is tested again after the first failure
+        v.on_packets_lost(ECN_TESTING_COUNT);
+        assert_eq!(v.state, EcnState::Testing);
+        assert_eq!(v.ecn_mark(), Ecn::Ect0);
+        assert!(!v.is_failed());
+
+        // Fall back after the second failure
+        v.on_packet_sent();
+        v.on_packets_lost(1);
+        assert_eq!(v.state, EcnState::Failed);
+        assert_eq!(v.ecn_mark(), Ecn::NotEct);
+        assert!(v.is_failed());
+
+        // Restart the validation with a new policy
+        v.set_policy(EcnPolicy::TestThenUse);
+        assert_eq!(v.state, EcnState::Testing);
+        v.set_policy(EcnPolicy::Disabled);
+        assert_eq!(v.ecn_mark(), Ecn::NotEct);
+        assert!(!v.is_failed());
+    }
+
+    #[test]
+    fn ecn_l4s() {
+        let mut peer = EcnCounts::default();
+        let mut v = EcnValidator::new(EcnPolicy::TestThenUse, 1, true, true);
+        assert_eq!(v.ecn_mark(), Ecn::Ect1);
+        v.on_packet_sent();
+        v.on_packet_sent();
+
+        // Validation succeeded with ECT(1) counts
+        assert_eq!(
+            v.on_ack_received(2, Some(&new_counts(0, 1, 1)), &mut peer),
+            1
+        );
+        assert_eq!(v.state, EcnState::Capable);
+        assert_eq!(v.ecn_mark(), Ecn::Ect1);
+
+        // ECT(0) count increased
+        v.on_packet_sent();
+        assert_eq!(
+            v.on_ack_received(1, Some(&new_counts(1, 1, 1)), &mut peer),
+            0
+        );
+        assert_eq!(v.state, EcnState::Failed);
+    }
+
+    #[test]
+    fn ecn_set_l4s() {
+        let mut peer = EcnCounts::default();
+        let mut v = EcnValidator::new(EcnPolicy::TestThenUse, 1, true, false);
+
+        // The codepoint is changed before any packet is sent
+        v.set_l4s(true);
+        assert_eq!(v.ecn_mark(), Ecn::Ect1);
+        assert!(!v.ect_changed);
+        v.set_l4s(false);
+        assert_eq!(v.ecn_mark(), Ecn::Ect0);
+        v.on_packet_sent();
+
+        // The codepoint is changed with packets in flight, and both ECT
+        // counts are accepted
+        v.set_l4s(true);
+        assert_eq!(v.ecn_mark(), Ecn::Ect1);
+        assert!(v.ect_changed);
+        v.on_packet_sent();
+        assert_eq!(
+            v.on_ack_received(2, Some(&new_counts(1, 1, 0)), &mut peer),
+            0
+        );
+        assert_eq!(v.state, EcnState::Capable);
+
+        v.set_l4s(false);
+        assert_eq!(v.ecn_mark(), Ecn::Ect0);
+        v.on_packet_sent();
+        assert_eq!(
+            v.on_ack_received(1, Some(&new_counts(2, 1, 0)), &mut peer),
+            0
+        );
+        assert_eq!(v.state, EcnState::Capable);
+    }
+}
@@ -12,15 +12,111 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 
+/// Default target interval between window updates, in units of srtt.
+pub const DEFAULT_AUTOTUNE_INTERVAL_RTTS: f64 = 2.0;
+
+/// Maximum target interval between window updates, in units of srtt.
+pub const MAX_AUTOTUNE_INTERVAL_RTTS: f64 = 100.0;
+
+/// Default growth factor of the window.
+pub const DEFAULT_AUTOTUNE_FACTOR: f64 = 2.0;
+
+/// Policy of auto-tuning the receive window.
+///
+/// The tuner is consulted each time before a new flow control limit is sent
+/// to the peer in a MAX_DATA/MAX_STREAM_DATA frame. It is shared by the
+/// connection and all its streams, so the per-window state needed by the
+/// policy is passed in.
+pub trait WindowTuner: std::fmt::Debug + Send + Sync {
+    /// Name of the window tuning policy.
+    fn name(&self) -> &str;
+
+    /// Return the new receive window size.
+    ///
+    /// The `since_last_update` is the interval since the last update of the
+    /// flow control limit, if any. The returned value will be capped by the
+    /// `max_window`, and a receive window is never decreased by the tuner.
+    fn tune(
+        &self,
+        window: u64,
+        max_window: u64,
+        since_last_update: Option<Duration>,
+        srtt: Duration,
+    ) -> u64;
+}
+
+/// The default window tuning policy.
+///
+/// If the last update of the flow control limit is within `interval_rtts` *
+/// srtt, the window is too small to keep up with the BDP, so the window size
+/// is multiplied by `factor`. By default, the window is doubled if updates
+/// occur within 2 RTTs.
+#[derive(Debug)]
+pub struct DefaultWindowTuner {
+    /// Target interval between window updates, in units of srtt.
+    interval_rtts: f64,
+
+    /// Growth factor of the window.
+    factor: f64,
+}
+
+impl DefaultWindowTuner {
+    /// Create a window tuner with the given target interval (in units of
+    /// srtt) and growth factor. The interval is capped at
+    /// `MAX_AUTOTUNE_INTERVAL_RTTS`, and the default interval is used if it
+    /// is NaN.
+    pub fn new(interval_rtts: f64, factor: f64) -> Self {
+        let interval_rtts = if interval_rtts.is_nan() {
+            DEFAULT_AUTOTUNE_INTERVAL_RTTS
+        } else {
+            interval_rtts.clamp(0.0, MAX_AUTOTUNE_INTERVAL_RTTS)
+        };
+        Self {
+            interval_rtts,
+            factor: factor.max(1.0),
+        }
+    }
+}
+
+impl Default for DefaultWindowTuner {
+    fn default() -> Self {
+        Self::new(DEFAULT_AUTOTUNE_INTERVAL_RTTS, DEFAULT_AUTOTUNE_FACTOR)
+    }
+}
+
+impl WindowTuner for DefaultWindowTuner {
+    fn name(&self) -> &str {
+        "default"
+    }
+
+    fn tune(
+        &self,
+        window: u64,
+        _max_window: u64,
+        since_last_update: Option<Duration>,
+        srtt: Duration,
+    ) -> u64 {
+        match since_last_update {
+            Some(interval) if interval < srtt.mul_f64(self.interval_rtts) => {
+                (window as f64 * self.factor) as u64
+            }
+            _ => window,
+        }
+    }
+}
+
 /// A flow control implementation that allows the size of the receive buffer to
 /// be auto-tuned.
 ///
 /// The basic idea is to start with relatively small initial window size, and
-/// then grow the window as necessary. For simplicity, auto-tuning may increase
+/// then grow the window as necessary. By default, auto-tuning may increase
 /// the window size, but never decreases (contrast with congestion control).
+/// Optionally, the window may decay back toward its initial size once it has
+/// been underutilized for a configured number of RTTs.
 ///
 /// The ideal size of the window is one that is large enough that it can
 /// encompass the bandwidth delay product (BDP) to the peer.
@@ -32,7 +128,9 @@ use std::time::Instant;
 /// expected to occur with frequency corresponding to more than the 1 RTT
 /// indicative of blocking, but not too much more. The default target chosen for
 /// auto-tuning corresponds to 2 RTTs.
-#[derive(Default, Debug)]
+///
+/// The policy of auto-tuning is pluggable, see [`WindowTuner`].
+#[derive(Debug)]
 pub struct FlowControl {
     /// Number of bytes consumed (cumulative).
     ///
@@ -59,8 +157,48 @@ pub struct FlowControl {
     /// due to window autotuning.
     max_window: u64,
 
+    /// Minimum receive window, used as the floor when the window decays.
+    min_window: u64,
+
+    /// Number of RTTs without a window update after which the window is
+    /// halved. Zero means the window never decays.
+    decay_rtts: u32,
+
+    /// Configured floor when the window decays. Zero means `min_window` is
+    /// used as the floor.
+    decay_floor: u64,
+
     /// Timestamp of the last update moment of max_data due to window autotuning.
     last_updated: Option<Instant>,
+
+    /// Timestamp of the last decay of the window.
+    last_decayed: Option<Instant>,
+
+    /// Policy of window autotuning.
+    tuner: Arc<dyn WindowTuner>,
+
+    /// Whether the advertisement of new max_data limits is paused by the
+    /// application.
+    paused: bool,
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        FlowControl {
+            read_off: 0,
+            recv_off: 0,
+            max_data: 0,
+            window: 0,
+            max_window: 0,
+            min_window: 0,
+            decay_rtts: 0,
+            decay_floor: 0,
+            last_updated: None,
+            last_decayed: None,
+            tuner: Arc::new(DefaultWindowTuner::default()),
+            paused: false,
+        }
+    }
 }
 
 impl FlowControl {
@@ -69,15 +207,26 @@ impl FlowControl {
             max_data: window,
             window,
             max_window,
+            min_window: window,
             ..FlowControl::default()
         }
     }
 
+    /// Set the policy of window autotuning.
+    pub fn set_tuner(&mut self, tuner: Arc<dyn WindowTuner>) {
+        self.tuner = tuner;
+    }
+
     /// Get the current receive window size.
     pub fn window(&self) -> u64 {
         self.window
     }
 
+    /// Get the maximum receive window size.
+    pub fn max_window(&self) -> u64 {
+        self.max_window
+    }
+
     /// Get the current flow control limit.
     pub fn max_data(&self) -> u64 {
         self.max_data
@@ -98,11 +247,24 @@ impl FlowControl {
         self.read_off += delta;
     }
 
+    /// Pause or resume the advertisement of new max_data limits.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Return true if the advertisement of new max_data limits is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
     /// Check if we should send a MAX_DATA/MAX_STREAM_DATA frame to the peer.
     ///
     /// Return true if the available window is smaller than the half
-    /// of the current window.
+    /// of the current window, and the advertisement is not paused.
     pub fn should_send_max_data(&self) -> bool {
+        if self.paused {
+            return false;
+        }
         let v = (self.max_data - self.read_off) * 2 < self.window;
         log::debug!(
             "~~~ should_send_max_data {}: max_data {} - read_off {} < window {} / 2",
@@ -120,23 +282,94 @@ impl FlowControl {
         self.read_off + self.window
     }
 
-    /// Apply the new max_data limit.
+    /// Apply the new max_data limit, which is never decreased even if the
+    /// window has decayed.
     pub fn update_max_data(&mut self, now: Instant) {
-        self.max_data = self.max_data_next();
+        self.max_data = std::cmp::max(self.max_data, self.max_data_next());
+        self.last_updated = Some(now);
+    }
+
+    /// Apply the given max_data limit, which is never decreased.
+    pub fn update_max_data_to(&mut self, max_data: u64, now: Instant) {
+        self.max_data = std::cmp::max(self.max_data, max_data);
         self.last_updated = Some(now);
     }
 
-    /// Adjust the window size automatically. If the last update
-    /// is within 2 * srtt, increase the window size by 2, but
-    /// not exceeding the max_window.
+    /// Reset the receive window and the maximum receive window. The window
+    /// is capped by max_window, and is used as the new floor for decay.
+    pub fn set_window(&mut self, window: u64, max_window: u64) {
+        self.max_window = max_window;
+        self.window = std::cmp::min(window, max_window);
+        self.min_window = self.window;
+    }
+
+    /// Set the number of RTTs without a window update after which the
+    /// window is halved. Zero disables window decay.
+    pub fn set_decay_rtts(&mut self, v: u32) {
+        self.decay_rtts = v;
+    }
+
+    /// Set the floor when the window decays. Zero means the initial window is
+    /// used as the floor.
+    pub fn set_decay_floor(&mut self, v: u64) {
+        self.decay_floor = v;
+    }
+
+    /// Adjust the window size automatically by the window tuner, but not
+    /// exceeding the max_window. If the window is not grown, it may decay,
+    /// see `decay_window()`.
     pub fn autotune_window(&mut self, now: Instant, srtt: Duration) {
-        if let Some(last_updated) = self.last_updated {
-            if now - last_updated < srtt * 2 {
-                self.window = std::cmp::min(self.window * 2, self.max_window);
-            }
+        let since_last_update = self
+            .last_updated
+            .map(|last_updated| now.saturating_duration_since(last_updated));
+        let window = self
+            .tuner
+            .tune(self.window, self.max_window, since_last_update, srtt);
+        if window > self.window {
+            self.window = std::cmp::min(window, self.max_window);
+        } else {
+            self.decay_window(now, srtt);
         }
     }
 
+    /// Halve the window size if window decay is enabled and there has been
+    /// neither a window update nor a decay within decay_rtts * srtt, but not
+    /// below the floor. The credit which has been advertised to the peer is
+    /// not taken back, see `update_max_data()`.
+    pub fn decay_window(&mut self, now: Instant, srtt: Duration) {
+        if self.decay_rtts == 0 {
+            return;
+        }
+        let last = match std::cmp::max(self.last_updated, self.last_decayed) {
+            Some(v) => v,
+            None => return,
+        };
+        if now.saturating_duration_since(last) <= srtt * self.decay_rtts {
+            return;
+        }
+
+        let floor = match self.decay_floor {
+            0 => self.min_window,
+            v => v,
+        };
+        let floor = std::cmp::min(floor, self.window);
+        self.window = std::cmp::max(self.window / 2, floor);
+        self.last_decayed = Some(now);
+    }
+
+    /// Limit the window size to the given max_window, but not less than the
+    /// window which has been advertised to the peer.
+    pub fn limit_window(&mut self, max_window: u64) {
+        let advertised = self.advertised_window();
+        self.window = std::cmp::min(self.window, std::cmp::max(max_window, advertised));
+    }
+
+    /// Get the credit which has been advertised to the peer but not consumed
+    /// yet.
+    pub fn advertised_window(&self) -> u64 {
+        self.max_data.saturating_sub(self.read_off)
+    }
+
     /// Ensure that the lower bound of the window is equal to
     /// the given min_window.
     pub fn ensure_window_lower_bound(&mut self, min_window: u64) {
@@ -242,6 +475,159 @@ mod tests {
         assert_eq!(fc.window, max_window);
     }
 
+    #[derive(Debug)]
+    struct StepWindowTuner(u64);
+
+    impl WindowTuner for StepWindowTuner {
+        fn name(&self) -> &str {
+            "step"
+        }
+
+        fn tune(&self, window: u64, _: u64, _: Option<Duration>, _: Duration) -> u64 {
+            window + self.0
+        }
+    }
+
+    #[test]
+    fn fc_autotune_window_with_tuner() {
+        let now = Instant::now();
+        let srtt = Duration::from_millis(100);
+        let mut fc = FlowControl::new(10, 30);
+        fc.set_tuner(Arc::new(StepWindowTuner(8)));
+
+        // The window is grown by the tuner even before any update
+        fc.autotune_window(now, srtt);
+        assert_eq!(fc.window(), 18);
+
+        // The window is capped by the max_window
+        for _ in 0..3 {
+            fc.autotune_window(now, srtt);
+        }
+        assert_eq!(fc.window(), 30);
+
+        // The window is never decreased
+        fc.set_tuner(Arc::new(StepWindowTuner(0)));
+        fc.ensure_window_lower_bound(40);
+        fc.autotune_window(now, srtt);
+        assert_eq!(fc.window(), 40);
+    }
+
+    #[test]
+    fn default_window_tuner() {
+        let tuner = DefaultWindowTuner::default();
+        let srtt = Duration::from_millis(100);
+        assert_eq!(tuner.name(), "default");
+        assert_eq!(tuner.tune(10, 30, None, srtt), 10);
+        assert_eq!(tuner.tune(10, 30, Some(srtt), srtt), 20);
+        assert_eq!(tuner.tune(10, 30, Some(srtt * 2), srtt), 10);
+
+        // Custom interval and growth factor
+        let tuner = DefaultWindowTuner::new(1.5, 1.5);
+        assert_eq!(tuner.tune(10, 30, Some(srtt), srtt), 15);
+        assert_eq!(tuner.tune(10, 30, Some(srtt * 3 / 2), srtt), 10);
+
+        // The window is never shrunk by the factor
+        let tuner = DefaultWindowTuner::new(2.0, 0.5);
+        assert_eq!(tuner.tune(10, 30, Some(srtt), srtt), 10);
+
+        // The interval is capped, and NaN falls back to the default
+        let tuner = DefaultWindowTuner::new(f64::INFINITY, 2.0);
+        assert_eq!(tuner.interval_rtts, MAX_AUTOTUNE_INTERVAL_RTTS);
+        assert_eq!(tuner.tune(10, 30, Some(srtt * 99), srtt), 20);
+        let tuner = DefaultWindowTuner::new(f64::NAN, 2.0);
+        assert_eq!(tuner.interval_rtts, DEFAULT_AUTOTUNE_INTERVAL_RTTS);
+    }
+
+    #[test]
+    fn fc_autotune_window_decay() {
+        let now = Instant::now();
+        let srtt = Duration::from_millis(100);
+        let mut fc = FlowControl::new(10, 80);
+        fc.update_max_data(now);
+        fc.autotune_window(now, srtt);
+        fc.autotune_window(now, srtt);
+        fc.autotune_window(now, srtt);
+        assert_eq!(fc.window, 80);
+
+        // Decay is disabled by default.
+        fc.autotune_window(now + srtt * 100, srtt);
+        assert_eq!(fc.window, 80);
+
+        // Window is not halved within decay_rtts * srtt.
+        fc.set_decay_rtts(10);
+        fc.autotune_window(now + srtt * 5, srtt);
+        assert_eq!(fc.window, 80);
+
+        // Window is halved after decay_rtts * srtt, at most once within
+        // decay_rtts * srtt, but not below the initial window.
+        for (i, window) in [40, 20, 10, 10].into_iter().enumerate() {
+            let now = now + srtt * 11 * (i as u32 + 1);
+            fc.autotune_window(now, srtt);
+            assert_eq!(fc.window, window);
+            fc.autotune_window(now, srtt);
+            assert_eq!(fc.window, window);
+        }
+    }
+
+    #[test]
+    fn fc_decay_window() {
+        let now = Instant::now();
+        let srtt = Duration::from_millis(100);
+        let mut fc = FlowControl::new(10, 80);
+        fc.ensure_window_lower_bound(80);
+        fc.set_decay_rtts(10);
+
+        // Window never decays before the first update.
+        fc.decay_window(now + srtt * 100, srtt);
+        assert_eq!(fc.window, 80);
+
+        // The advertised credit is not taken back.
+        fc.update_max_data(now);
+        assert_eq!(fc.max_data(), 80);
+        fc.decay_window(now + srtt * 11, srtt);
+        assert_eq!(fc.window, 40);
+        fc.decay_window(now + srtt * 12, srtt);
+        assert_eq!(fc.window, 40);
+        fc.increase_read_off(10);
+        fc.update_max_data(now + srtt * 12);
+        assert_eq!(fc.max_data(), 80);
+        assert_eq!(fc.advertised_window(), 70);
+
+        // Window is not shrunk below the configured floor.
+        fc.set_decay_floor(30);
+        fc.decay_window(now + srtt * 33, srtt);
+        assert_eq!(fc.window, 30);
+        fc.decay_window(now + srtt * 44, srtt);
+        assert_eq!(fc.window, 30);
+
+        // Window is never grown by the floor.
+        fc.set_decay_floor(60);
+        fc.decay_window(now + srtt * 55, srtt);
+        assert_eq!(fc.window, 30);
+    }
+
+    #[test]
+    fn fc_set_window() {
+        let mut fc = FlowControl::new(100, 200);
+
+        // Enlarge the window, MAX_DATA should be sent immediately.
+        fc.set_window(1000, 2000);
+        assert_eq!(fc.window(), 1000);
+        assert_eq!(fc.max_window, 2000);
+        assert_eq!(fc.should_send_max_data(), true);
+        assert_eq!(fc.max_data_next(), 1000);
+
+        // The window is capped by max_window.
+        fc.set_window(300, 200);
+        assert_eq!(fc.window(), 200);
+        assert_eq!(fc.min_window, 200);
+
+        // Shrink the window, the credit already granted is kept.
+        fc.set_window(10, 10);
+        assert_eq!(fc.should_send_max_data(), false);
+        assert_eq!(fc.max_data(), 100);
+    }
+
     #[test]
     fn fc_ensure_window_lower_bound() {
         let mut fc = FlowControl::new(10, 200);
@@ -258,4 +644,46 @@ mod tests {
             assert_eq!(fc.window(), window);
         }
     }
+
+    #[test]
+    fn fc_pause() {
+        let mut fc = FlowControl::new(100, 200);
+        fc.increase_recv_off(60);
+        fc.increase_read_off(60);
+        assert!(fc.should_send_max_data());
+
+        // No new limit is advertised while paused
+        fc.set_paused(true);
+        assert!(fc.is_paused());
+        assert!(!fc.should_send_max_data());
+
+        // The advertisement is resumed
+        fc.set_paused(false);
+        assert!(fc.should_send_max_data());
+    }
+
+    #[test]
+    fn fc_limit_window() {
+        let mut fc = FlowControl::new(100, 200);
+        fc.increase_recv_off(30);
+        fc.increase_read_off(30);
+        fc.update_max_data(Instant::now());
+        assert_eq!(fc.max_data(), 130);
+        fc.increase_recv_off(40);
+        fc.increase_read_off(40);
+
+        // max_window > window, unchanged
+        fc.limit_window(150);
+        assert_eq!(fc.window(), 100);
+
+        // max_window < window, updated
+        fc.limit_window(80);
+        assert_eq!(fc.window(), 80);
+        assert_eq!(fc.max_data_next(), 150);
+
+        // Not less than the window advertised to the peer
+        fc.limit_window(0);
+        assert_eq!(fc.window(), 60);
+        assert_eq!(fc.max_data_next(), fc.max_data());
+    }
 }
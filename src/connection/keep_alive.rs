@@ -0,0 +1,366 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The lower bound of the keep-alive interval learned in adaptive mode.
+/// NAT bindings of UDP flows are rarely shorter than this, and keep-alives
+/// are sent at this rate while no longer interval has been verified.
+const MIN_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The upper bound of the keep-alive interval learned in adaptive mode if the
+/// interval is not configured.
+const MAX_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// The adaptive search is converged when the gap between the longest quiet
+/// interval that kept the NAT binding and the shortest one that lost it is
+/// no more than this value.
+const KEEP_ALIVE_PRECISION: Duration = Duration::from_secs(5);
+
+/// An outstanding probe sent after a quiet interval.
+#[derive(Clone, Copy, Debug)]
+struct Probe {
+    /// The data of the PATH_CHALLENGE frame.
+    data: [u8; 8],
+
+    /// The quiet interval verified by the probe.
+    interval: Duration,
+
+    /// The time when the probe is settled.
+    deadline: Instant,
+
+    /// Whether the PATH_RESPONSE of the probe has been received.
+    responded: bool,
+}
+
+/// Keep-alive of a connection.
+///
+/// In the fixed mode, a PING frame is sent if the connection has been quiet
+/// for the configured interval. In the adaptive mode, the longest quiet
+/// interval that keeps the NAT binding is learned by binary search: a
+/// PATH_CHALLENGE frame is sent after each quiet interval under test.
+///
+/// A packet sent after the binding has expired creates a new binding, so the
+/// PATH_RESPONSE arrives either way. The expiry is instead detected by the
+/// peer as a change of the client address, which is reported back in an
+/// OBSERVED_ADDRESS frame. The interval is considered safe if the
+/// PATH_RESPONSE arrives and no address change is reported before the probe
+/// is settled. Once the search is converged, PING frames are sent at the
+/// learned interval.
+pub(super) struct KeepAlive {
+    /// Whether the keep-alive interval is learned.
+    adaptive: bool,
+
+    /// The longest quiet interval known to keep the NAT binding. It is the
+    /// configured interval in the fixed mode. Zero means disabled.
+    safe: Duration,
+
+    /// The shortest quiet interval known to lose the NAT binding, or the
+    /// upper bound of the search.
+    upper: Duration,
+
+    /// The time of the latest activity on the connection.
+    last_activity: Option<Instant>,
+
+    /// Whether a probe should be sent.
+    need_send_probe: bool,
+
+    /// The outstanding probe.
+    probe: Option<Probe>,
+}
+
+impl KeepAlive {
+    pub(super) fn new(interval: Duration, adaptive: bool) -> Self {
+        if !adaptive {
+            return Self {
+                adaptive,
+                safe: interval,
+                upper: interval,
+                last_activity: None,
+                need_send_probe: false,
+                probe: None,
+            };
+        }
+
+        let upper = if interval.is_zero() {
+            MAX_KEEP_ALIVE_INTERVAL
+        } else {
+            cmp::max(interval, MIN_KEEP_ALIVE_INTERVAL)
+        };
+        Self {
+            adaptive,
+            safe: MIN_KEEP_ALIVE_INTERVAL,
+            upper,
+            last_activity: None,
+            need_send_probe: false,
+            probe: None,
+        }
+    }
+
+    /// Stop the adaptive search if the NAT rebinding can not be detected,
+    /// i.e. the peer does not report the observed addresses. Keep-alives are
+    /// sent at the lower bound of the search.
+    pub(super) fn disable_search(&mut self) {
+        if self.adaptive {
+            self.upper = self.safe;
+            self.need_send_probe = false;
+            self.probe = None;
+        }
+    }
+
+    /// Return whether keep-alive is enabled.
+    pub(super) fn enabled(&self) -> bool {
+        !self.safe.is_zero()
+    }
+
+    /// Return whether the adaptive search is in progress.
+    pub(super) fn is_searching(&self) -> bool {
+        self.adaptive && self.upper.saturating_sub(self.safe) > KEEP_ALIVE_PRECISION
+    }
+
+    /// Return the current keep-alive interval.
+    pub(super) fn interval(&self) -> Duration {
+        self.safe
+    }
+
+    /// Limit the upper bound of the search. The connection is closed if it is
+    /// quiet for the idle timeout, so longer intervals are never probed.
+    pub(super) fn limit(&mut self, max: Duration) {
+        if !self.enabled() {
+            return;
+        }
+        if !self.adaptive {
+            self.safe = cmp::min(self.safe, max);
+            self.upper = self.safe;
+            return;
+        }
+        self.upper = cmp::max(cmp::min(self.upper, max), self.safe);
+    }
+
+    /// Return the quiet interval to wait before the next keep-alive or probe.
+    fn next_interval(&self) -> Duration {
+        if self.is_searching() {
+            self.safe + (self.upper - self.safe) / 2
+        } else {
+            self.safe
+        }
+    }
+
+    /// Return the time of the next keep-alive event.
+    pub(super) fn timeout(&self) -> Option<Instant> {
+        if let Some(probe) = self.probe {
+            return Some(probe.deadline);
+        }
+        if !self.enabled() || self.need_send_probe {
+            return None;
+        }
+        self.last_activity.map(|t| t + self.next_interval())
+    }
+
+    /// Handle sending or receiving of packets.
+    pub(super) fn on_activity(&mut self, now: Instant) {
+        if self.enabled() {
+            self.last_activity = Some(now);
+        }
+    }
+
+    /// Handle the keep-alive timeout. Return whether a PING frame should be
+    /// sent.
+    pub(super) fn on_timeout(&mut self, now: Instant) -> bool {
+        if let Some(probe) = self.probe {
+            if probe.deadline <= now {
+                // The NAT binding was kept during the quiet interval. If the
+                // PATH_RESPONSE is lost, the interval is probed again.
+                if probe.responded {
+                    self.safe = cmp::min(probe.interval, self.upper);
+                }
+                self.probe = None;
+            }
+            return false;
+        }
+
+        if self.is_searching() {
+            self.need_send_probe = true;
+            return false;
+        }
+        true
+    }
+
+    /// Return whether a PATH_CHALLENGE frame should be sent for probing.
+    pub(super) fn need_send_probe(&self) -> bool {
+        self.need_send_probe
+    }
+
+    /// Handle sent event of a probe. The `timeout` is the time to wait for
+    /// the PATH_RESPONSE frame.
+    pub(super) fn on_probe_sent(&mut self, data: [u8; 8], now: Instant, timeout: Duration) {
+        self.need_send_probe = false;
+        self.probe = Some(Probe {
+            data,
+            interval: self.next_interval(),
+            deadline: now + timeout,
+            responded: false,
+        });
+    }
+
+    /// Handle incoming PATH_RESPONSE data. Return true if it is the response
+    /// of the outstanding probe.
+    pub(super) fn on_path_resp_received(&mut self, data: [u8; 8]) -> bool {
+        match &mut self.probe {
+            Some(probe) if probe.data == data => {
+                probe.responded = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Handle the change of the local address observed by the peer.
+    pub(super) fn on_rebinding_detected(&mut self) {
+        if let Some(probe) = self.probe.take() {
+            // The NAT binding was lost during the quiet interval.
+            self.upper = cmp::max(probe.interval, self.safe);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_alive_disabled() {
+        let now = Instant::now();
+        let mut k = KeepAlive::new(Duration::ZERO, false);
+        assert!(!k.enabled());
+        k.on_activity(now);
+        assert_eq!(k.timeout(), None);
+    }
+
+    #[test]
+    fn keep_alive_fixed() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(20);
+        let mut k = KeepAlive::new(interval, false);
+        assert!(k.enabled());
+        assert!(!k.is_searching());
+        assert_eq!(k.timeout(), None);
+
+        k.on_activity(now);
+        assert_eq!(k.timeout(), Some(now + interval));
+        assert!(k.on_timeout(now + interval));
+        assert!(!k.need_send_probe());
+
+        // The interval is limited by the idle timeout
+        k.limit(Duration::from_secs(10));
+        assert_eq!(k.interval(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn keep_alive_adaptive() {
+        let mut now = Instant::now();
+        let timeout = Duration::from_secs(1);
+        // The NAT binding timeout of the simulated network
+        let binding_timeout = Duration::from_secs(100);
+
+        let mut k = KeepAlive::new(Duration::from_secs(300), true);
+        assert!(k.is_searching());
+        assert_eq!(k.interval(), MIN_KEEP_ALIVE_INTERVAL);
+
+        k.on_activity(now);
+        let mut probes = 0;
+        while k.is_searching() {
+            now = k.timeout().unwrap();
+            let interval = now - k.last_activity.unwrap();
+            assert!(!k.on_timeout(now));
+            assert!(k.need_send_probe());
+            assert_eq!(k.timeout(), None);
+
+            let data = [probes as u8; 8];
+            k.on_probe_sent(data, now, timeout);
+            k.on_activity(now);
+            assert_eq!(k.timeout(), Some(now + timeout));
+
+            // The PATH_RESPONSE arrives even if the binding is lost
+            assert!(!k.on_path_resp_received([0xff; 8]));
+            assert!(k.on_path_resp_received(data));
+            if interval >= binding_timeout {
+                k.on_rebinding_detected();
+                assert_eq!(k.timeout(), None);
+            } else {
+                assert_eq!(k.timeout(), Some(now + timeout));
+                now += timeout;
+                assert!(!k.on_timeout(now));
+            }
+            k.on_activity(now);
+            probes += 1;
+        }
+
+        assert!(probes <= 7);
+        assert!(k.interval() < binding_timeout);
+        assert!(k.interval() + KEEP_ALIVE_PRECISION >= binding_timeout);
+
+        // Send PING frames at the learned interval
+        assert_eq!(k.timeout(), Some(now + k.interval()));
+        assert!(k.on_timeout(now + k.interval()));
+    }
+
+    #[test]
+    fn keep_alive_adaptive_probe_lost() {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(1);
+        let mut k = KeepAlive::new(Duration::ZERO, true);
+        k.on_activity(now);
+
+        // The same interval is probed again if the PATH_RESPONSE is lost
+        let now = k.timeout().unwrap();
+        assert!(!k.on_timeout(now));
+        k.on_probe_sent([0; 8], now, timeout);
+        k.on_activity(now);
+        assert!(!k.on_timeout(now + timeout));
+        assert_eq!(k.interval(), MIN_KEEP_ALIVE_INTERVAL);
+        assert!(k.is_searching());
+        assert_eq!(k.timeout(), Some(now + k.next_interval()));
+    }
+
+    #[test]
+    fn keep_alive_adaptive_disabled_search() {
+        let now = Instant::now();
+        let mut k = KeepAlive::new(Duration::ZERO, true);
+        k.disable_search();
+        assert!(k.enabled());
+        assert!(!k.is_searching());
+
+        // Send PING frames at the lower bound of the search
+        k.on_activity(now);
+        assert_eq!(k.timeout(), Some(now + MIN_KEEP_ALIVE_INTERVAL));
+        assert!(k.on_timeout(now + MIN_KEEP_ALIVE_INTERVAL));
+    }
+
+    #[test]
+    fn keep_alive_adaptive_limit() {
+        let mut k = KeepAlive::new(Duration::ZERO, true);
+        assert_eq!(k.upper, MAX_KEEP_ALIVE_INTERVAL);
+
+        k.limit(Duration::from_secs(30));
+        assert_eq!(k.upper, Duration::from_secs(30));
+
+        // The upper bound is never below the lower bound
+        k.limit(Duration::from_secs(5));
+        assert_eq!(k.upper, MIN_KEEP_ALIVE_INTERVAL);
+        assert!(!k.is_searching());
+    }
+}
@@ -29,6 +29,8 @@ use super::timer;
 use crate::connection::SpaceId;
 use crate::error::Error;
 use crate::multipath_scheduler::MultipathScheduler;
+use crate::CongestionStats;
+use crate::CoupledPaths;
 use crate::FourTuple;
 use crate::PathStats;
 use crate::RecoveryConfig;
@@ -43,6 +45,10 @@ pub(crate) const MAX_PATH_CHALS_RECV: usize = 8;
 
 pub(crate) const MIN_PATH_PROBE_SIZE: usize = 64;
 
+/// The anti-amplification budget reserved by the server for Initial and
+/// Handshake packets, which is enough for a PTO probe carrying CRYPTO data.
+pub(crate) const RESERVED_ANTI_AMPL_LIMIT: usize = crate::MIN_CLIENT_INITIAL_LEN;
+
 /// A network path on which QUIC packets can be sent.
 pub struct Path {
     /// The local address.
@@ -97,6 +103,19 @@ pub struct Path {
     /// Whether a Ping frame should be sent on the path.
     pub(super) need_send_ping: bool,
 
+    /// Whether an OBSERVED_ADDRESS frame should be sent on the path.
+    pub(super) need_send_observed_addr: bool,
+
+    /// The local address observed by the peer on the path.
+    pub(super) observed_addr: Option<SocketAddr>,
+
+    /// The spin value to send in 1-RTT packets on the path.
+    pub(super) spin_value: bool,
+
+    /// The largest packet number of the 1-RTT packets that updated the spin
+    /// value of the path.
+    pub(super) spin_pkt_num: Option<u64>,
+
     /// Trace id.
     trace_id: String,
 
@@ -105,6 +124,10 @@ pub struct Path {
 
     /// Whether the path has been abandoned in MPQUIC mode.
     pub(super) is_abandon: bool,
+
+    /// The DSCP codepoint of the outgoing packets on the path. If not set, the
+    /// value of the connection is used.
+    pub(super) dscp: Option<u8>,
 }
 
 impl Path {
@@ -146,9 +169,14 @@ impl Path {
             anti_ampl_limit: 0,
             dplpmtud,
             need_send_ping: false,
+            need_send_observed_addr: true,
+            observed_addr: None,
+            spin_value: false,
+            spin_pkt_num: None,
             trace_id: trace_id.to_string(),
             space_id: SpaceId::Data,
             is_abandon: false,
+            dscp: None,
         }
     }
 
@@ -169,6 +197,13 @@ impl Path {
         self.remote_addr
     }
 
+    /// Return the local address observed by the peer on the path, which is
+    /// reported with the address discovery extension. It is the public address
+    /// of the local endpoint if there is a NAT on the path.
+    pub fn observed_addr(&self) -> Option<SocketAddr> {
+        self.observed_addr
+    }
+
     /// Handle incoming PATH_CHALLENGE data.
     pub(super) fn on_path_chal_received(&mut self, data: [u8; 8]) {
         if self.recv_chals.len() >= MAX_PATH_CHALS_RECV {
@@ -335,6 +370,12 @@ impl Path {
         &self.recovery.stats
     }
 
+    /// Return the latest statistics about the congestion controller of the
+    /// path
+    pub fn congestion_stats(&self) -> CongestionStats {
+        self.recovery.congestion_stats()
+    }
+
     /// Return the validation state of the path
     pub fn state(&self) -> PathState {
         self.state
@@ -477,7 +518,7 @@ impl PathMap {
     }
 
     /// Insert a new path
-    pub fn insert_path(&mut self, path: Path) -> Result<usize> {
+    pub fn insert_path(&mut self, mut path: Path) -> Result<usize> {
         // eliminate an unused path if the maximum paths limit is reached
         if self.paths.len() >= self.max_paths {
             let (pid_to_remove, _) = self
@@ -489,6 +530,14 @@ impl PathMap {
             self.addrs.remove(&(path.local_addr, path.remote_addr));
         }
 
+        // Don't test the new path for ECN capability if it has been found
+        // not capable on another path, unless retesting is allowed.
+        let ecn = &mut path.recovery.ecn;
+        if !ecn.retest_on_migration() && self.paths.iter().any(|(_, p)| p.recovery.ecn.is_failed())
+        {
+            ecn.fall_back();
+        }
+
         // insert new path
         let local_addr = path.local_addr;
         let remote_addr = path.remote_addr;
@@ -619,6 +668,23 @@ impl PathMap {
         left
     }
 
+    /// Return the min value between the given `left` and `anti_ampl_limit`
+    /// excluding the budget reserved for Initial and Handshake packets.
+    pub fn cmp_reserved_anti_ampl_limit(&self, pid: usize, left: usize) -> usize {
+        if !self.is_server {
+            return left;
+        }
+        if let Some(path) = self.paths.get(pid) {
+            if !path.verified_peer_address {
+                let limit = path
+                    .anti_ampl_limit
+                    .saturating_sub(RESERVED_ANTI_AMPL_LIMIT);
+                return cmp::min(left, limit);
+            }
+        }
+        left
+    }
+
     /// Schedule a Ping frame on the specified path or all active paths.
     pub fn mark_ping(&mut self, path_addr: Option<FourTuple>) -> Result<()> {
         // If multipath is not enabled, schedule a Ping frame on the current
@@ -650,12 +716,38 @@ impl PathMap {
     pub fn enable_multipath(&mut self) {
         self.is_multipath = true;
     }
+
+    /// Update the coupled congestion controllers of the active paths with
+    /// the sending rates of the other active paths.
+    pub fn update_coupled_congestion(&mut self) {
+        if !self.is_multipath {
+            return;
+        }
+
+        let rates: Vec<(usize, f64)> = self
+            .paths
+            .iter()
+            .filter(|(_, p)| p.active())
+            .map(|(pid, p)| (pid, p.recovery.sending_rate()))
+            .collect();
+        for &(pid, rate) in &rates {
+            let mut others = CoupledPaths::default();
+            for &(_, r) in rates.iter().filter(|(id, _)| *id != pid) {
+                others.sum_rate += r;
+                others.max_rate = others.max_rate.max(r);
+            }
+            if let Some(path) = self.paths.get_mut(pid) {
+                path.recovery.congestion.on_coupled_update(&others);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::congestion_control::CongestionControlAlgorithm;
+    use crate::connection::rtt::RttEstimator;
     use std::net::IpAddr;
     use std::net::Ipv4Addr;
     use std::time::Duration;
@@ -954,4 +1046,79 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn reserved_anti_ampl_limit() -> Result<()> {
+        let client_addrs = vec![SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            9443,
+        )];
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+        let mut path_mgr = new_path_mgr(&client_addrs, server_addr, 8, true)?;
+
+        // Server receives an Initial datagram of 1200 bytes
+        path_mgr.inc_anti_ampl_limit(0, 1200);
+        assert_eq!(path_mgr.cmp_anti_ampl_limit(0, 1500), 1500);
+        assert_eq!(path_mgr.cmp_reserved_anti_ampl_limit(0, 1500), 1500);
+
+        // Part of the budget is reserved for Initial and Handshake packets
+        path_mgr.dec_anti_ampl_limit(0, 1800);
+        assert_eq!(path_mgr.cmp_anti_ampl_limit(0, 1500), 1500);
+        assert_eq!(
+            path_mgr.cmp_reserved_anti_ampl_limit(0, 1500),
+            1800 - RESERVED_ANTI_AMPL_LIMIT
+        );
+        path_mgr.dec_anti_ampl_limit(0, 1200);
+        assert_eq!(path_mgr.cmp_anti_ampl_limit(0, 1500), 600);
+        assert_eq!(path_mgr.cmp_reserved_anti_ampl_limit(0, 1500), 0);
+
+        // No limit after the peer address is validated
+        path_mgr.get_mut(0)?.verified_peer_address = true;
+        assert_eq!(path_mgr.cmp_reserved_anti_ampl_limit(0, 1500), 1500);
+
+        Ok(())
+    }
+
+    #[test]
+    fn coupled_congestion() -> Result<()> {
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9443);
+        let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 443);
+        let conf = RecoveryConfig {
+            congestion_control_algorithm: CongestionControlAlgorithm::Balia,
+            ..new_test_recovery_config()
+        };
+        let initial_path = Path::new(client_addr, server_addr, true, &conf, "");
+        let mut path_mgr = PathMap::new(initial_path, 8, crate::ANTI_AMPLIFICATION_FACTOR, false);
+        path_mgr.enable_multipath();
+
+        // Add an active path with four times the sending rate
+        let client_addr1 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9444);
+        let mut new_path = Path::new(client_addr1, server_addr, false, &conf, "");
+        new_path.dcid_seq = Some(1);
+        new_path.set_active(true);
+        let now = time::Instant::now();
+        new_path
+            .recovery
+            .congestion
+            .on_careful_resume(now, 1200 * 40);
+        let pid = path_mgr.insert_path(new_path)?;
+        let rate = path_mgr.get(0)?.recovery.sending_rate();
+        assert_eq!(path_mgr.get(pid)?.recovery.sending_rate(), rate * 4.0);
+
+        // The path with the lower rate is reduced more on loss
+        path_mgr.update_coupled_congestion();
+        let mut pkt = crate::connection::space::SentPacket {
+            time_sent: now,
+            ..Default::default()
+        };
+        let rtt = RttEstimator::new(crate::INITIAL_RTT);
+        for (id, cwnd) in [(0, 1200 * 10 / 4), (pid, 1200 * 40 / 2)] {
+            let cc = &mut path_mgr.get_mut(id)?.recovery.congestion;
+            cc.on_ack(&mut pkt, now, true, &rtt, 0);
+            cc.on_congestion_event(now, &pkt, false, 1200, 0);
+            assert_eq!(cc.congestion_window(), cwnd);
+        }
+
+        Ok(())
+    }
 }
@@ -13,7 +13,6 @@
 // limitations under the License.
 
 use std::cmp;
-use std::collections::VecDeque;
 use std::ops::Range;
 use std::time;
 use std::time::Duration;
@@ -21,6 +20,9 @@ use std::time::Instant;
 
 use log::*;
 
+use super::bandwidth::BandwidthEstimator;
+use super::careful_resume::CarefulResume;
+use super::ecn::EcnValidator;
 use super::rtt::RttEstimator;
 use super::space::AckedPacket;
 use super::space::PacketNumSpace;
@@ -32,24 +34,75 @@ use super::Connection;
 use super::HandshakeStatus;
 use crate::congestion_control;
 use crate::congestion_control::CongestionController;
+use crate::congestion_control::CongestionStats;
+use crate::congestion_control::DeliveryRateEstimator;
 use crate::congestion_control::Pacer;
 use crate::connection::Timer;
 use crate::frame;
+use crate::frame::EcnCounts;
 use crate::qlog;
 use crate::qlog::events::EventData;
+use crate::qlog::events::PacketLostTrigger;
 use crate::ranges::RangeSet;
 use crate::Error;
 use crate::PathStats;
+use crate::PtoProbeStrategy;
 use crate::RecoveryConfig;
 use crate::Result;
 use crate::TIMER_GRANULARITY;
 
-const INITIAL_PACKET_THRESHOLD: u64 = 3;
+/// The upper bound of the adaptive packet reordering threshold.
+const MAX_PACKET_THRESHOLD: u64 = 20;
 
-const INITIAL_TIME_THRESHOLD: f64 = 9.0 / 8.0;
+/// The upper bound of the adaptive time reordering threshold, specified as an
+/// RTT multiplier.
+const MAX_TIME_THRESHOLD: f64 = 2.0;
 
 const MAX_PTO_PROBES_COUNT: usize = 2;
 
+/// The period of persistent congestion, as multiples of the probe timeout.
+/// See RFC 9002 Section 7.6.1
+const PERSISTENT_CONGESTION_THRESHOLD: u32 = 3;
+
+/// The maximum number of ACK ranges processed each time an ACK frame is
+/// received or the connection is polled. The remaining ranges of a large ACK
+/// frame are carried over, so that a single ACK frame could not stall the
+/// event loop.
+const MAX_ACK_RANGES_PER_POLL: usize = 256;
+
+/// The maximum number of ACK ranges carried over in a packet number space.
+/// The smallest ranges are dropped if it is exceeded, and the packets in them
+/// may be declared lost and retransmitted.
+const MAX_PENDING_ACK_RANGES: usize = 4096;
+
+/// The reason why the sender of a path is not sending more data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SendLimited {
+    /// The sender is not limited.
+    None,
+
+    /// The application has no more data to send.
+    App,
+
+    /// Sending is limited by the congestion window.
+    Cwnd,
+
+    /// Sending is limited by the flow control of the peer.
+    FlowControl,
+}
+
+impl SendLimited {
+    /// Get the send limited state for Qlog.
+    pub(crate) fn to_qlog(self) -> qlog::events::SendLimitedState {
+        match self {
+            SendLimited::None => qlog::events::SendLimitedState::NotLimited,
+            SendLimited::App => qlog::events::SendLimitedState::ApplicationLimited,
+            SendLimited::Cwnd => qlog::events::SendLimitedState::CongestionLimited,
+            SendLimited::FlowControl => qlog::events::SendLimitedState::FlowControlLimited,
+        }
+    }
+}
+
 /// An implementation of the loss detection mechanisms described in
 /// RFC 9002 Section 6 and Appendix A.
 pub struct Recovery {
@@ -67,6 +120,23 @@ pub struct Recovery {
     /// Upper limit of probe timeout.
     max_pto: Duration,
 
+    /// The probe timeout before the first RTT sample, as an initial RTT
+    /// multiplier.
+    initial_pto_factor: Option<f64>,
+
+    /// Multiplier of probe timeout upon each backoff.
+    pto_multiplier: u32,
+
+    /// Upper limit of the consecutive probe timeout backoffs.
+    max_pto_backoff: u32,
+
+    /// The content of the probe packets in the Application Data packet number
+    /// space.
+    pto_probe_strategy: PtoProbeStrategy,
+
+    /// Whether to send the two probe packets on different paths.
+    pto_probes_across_paths: bool,
+
     /// The number of times a PTO has been sent without receiving an
     /// acknowledgment. It is used for PTO calculation.
     pto_count: usize,
@@ -83,6 +153,23 @@ pub struct Recovery {
     /// considers a packet lost. Specified as an RTT multiplier.
     pub time_thresh: f64,
 
+    /// Whether to increase the reordering thresholds upon spurious losses.
+    adaptive_reordering: bool,
+
+    /// The time of the last congestion event caused by packet loss, and the
+    /// number of packets declared lost since then which are not acknowledged.
+    /// The event is spurious if all of them are acknowledged later.
+    undo_epoch: Option<(Instant, u64)>,
+
+    /// The time of the first RTT sample. The persistent congestion period
+    /// does not start until there is at least one RTT sample.
+    first_rtt_sample: Option<Instant>,
+
+    /// The persistent congestion declared but not reported to the application
+    /// yet, i.e. the duration of the congestion period, the number of packets
+    /// lost in the period, and the resulting congestion window.
+    persistent_congestion: Option<(Duration, u64, u64)>,
+
     /// The sum of the size in bytes of all sent packets that contain at least
     /// one ack-eliciting or PADDING frame and have not been acknowledged or
     /// declared lost. The size does not include IP or UDP overhead.
@@ -103,11 +190,42 @@ pub struct Recovery {
     /// Next pacer tick
     pub pacer_timer: Option<Instant>,
 
+    /// Upper limit of the pacing rate in bytes per second.
+    max_pacing_rate: Option<u64>,
+
+    /// Whether pacing is offloaded to the kernel or hardware. The packets are
+    /// not delayed by the pacer, and their release time is exported.
+    pacing_offload: bool,
+
+    /// The release time of the UDP datagram being written, if it should be
+    /// delayed.
+    release_time: Option<Instant>,
+
     /// Cache pkt size
     pub cache_pkt_size: usize,
 
-    /// The time for last congestion window event
-    last_cwnd_limited_time: Option<Instant>,
+    /// The reason why the sender is limited currently.
+    send_limited: SendLimited,
+
+    /// The start time of the current send limited period, or the time the
+    /// duration of the period was updated last.
+    send_limited_time: Option<Instant>,
+
+    /// ECN validation for the corresponding path.
+    pub(super) ecn: EcnValidator,
+
+    /// Careful Resume for the corresponding path.
+    careful_resume: CarefulResume,
+
+    /// Delivery rate estimator shared by the congestion controllers.
+    delivery_rate: DeliveryRateEstimator,
+
+    /// Bandwidth estimator independent of the congestion controller.
+    bandwidth: BandwidthEstimator,
+
+    /// ACK ranges carried over to be processed later for each packet number
+    /// space.
+    pending_ack_ranges: Vec<(SpaceId, RangeSet)>,
 
     /// Path level Statistics.
     pub stats: PathStats,
@@ -116,35 +234,112 @@ pub struct Recovery {
     /// event.
     last_metrics: RecoveryMetrics,
 
+    /// The last target bitrate reported to the application.
+    last_target_bitrate: Option<u64>,
+
     /// Trace id.
     trace_id: String,
 }
 
 impl Recovery {
     pub(super) fn new(conf: &RecoveryConfig) -> Self {
+        let congestion = congestion_control::build_congestion_controller(conf);
+        let l4s = congestion.is_l4s();
         Recovery {
             max_ack_delay: conf.max_ack_delay,
             max_datagram_size: crate::DEFAULT_SEND_UDP_PAYLOAD_SIZE,
             pto_linear_factor: conf.pto_linear_factor,
             max_pto: conf.max_pto,
+            initial_pto_factor: conf.initial_pto_factor,
+            pto_multiplier: conf.pto_multiplier,
+            max_pto_backoff: conf.max_pto_backoff,
+            pto_probe_strategy: conf.pto_probe_strategy,
+            pto_probes_across_paths: conf.pto_probes_across_paths,
             pto_count: 0,
             loss_detection_timer: None,
-            pkt_thresh: INITIAL_PACKET_THRESHOLD,
-            time_thresh: INITIAL_TIME_THRESHOLD,
+            pkt_thresh: conf.packet_reordering_threshold,
+            time_thresh: conf.time_reordering_threshold,
+            adaptive_reordering: conf.adaptive_reordering,
+            undo_epoch: None,
+            first_rtt_sample: None,
+            persistent_congestion: None,
             bytes_in_flight: 0,
             ack_eliciting_in_flight: 0,
             rtt: RttEstimator::new(conf.initial_rtt),
-            congestion: congestion_control::build_congestion_controller(conf),
+            congestion,
             pacer: Pacer::build_pacer_controller(conf),
             pacer_timer: None,
+            max_pacing_rate: conf.max_pacing_rate,
+            pacing_offload: conf.pacing_offload,
+            release_time: None,
             cache_pkt_size: conf.max_datagram_size,
-            last_cwnd_limited_time: None,
+            send_limited: SendLimited::None,
+            send_limited_time: None,
+            ecn: EcnValidator::new(
+                conf.ecn_policy,
+                conf.ecn_max_failures,
+                conf.ecn_retest_on_migration,
+                l4s,
+            ),
+            careful_resume: CarefulResume::new(),
+            delivery_rate: DeliveryRateEstimator::default(),
+            bandwidth: BandwidthEstimator::default(),
+            pending_ack_ranges: Vec::new(),
             stats: PathStats::default(),
             last_metrics: RecoveryMetrics::default(),
+            last_target_bitrate: None,
             trace_id: String::from(""),
         }
     }
 
+    /// Replace the congestion controller and the pacer with the ones built
+    /// from the given configuration. It should only be called before any
+    /// packet is sent.
+    pub(super) fn set_congestion_controller(&mut self, conf: &RecoveryConfig) {
+        self.congestion = congestion_control::build_congestion_controller(conf);
+        self.ecn.set_l4s(self.congestion.is_l4s());
+        self.pacer = Pacer::build_pacer_controller(conf);
+        self.max_pacing_rate = conf.max_pacing_rate;
+        self.pacing_offload = conf.pacing_offload;
+    }
+
+    /// Update the upper limit of the pacing rate with the given
+    /// configuration. Pacing is enabled if the limit is set.
+    pub(super) fn set_max_pacing_rate(&mut self, conf: &RecoveryConfig) {
+        self.max_pacing_rate = conf.max_pacing_rate;
+        self.pacer
+            .set_enabled(conf.enable_pacing || conf.max_pacing_rate.is_some());
+    }
+
+    /// Switch to a new congestion controller built from the given
+    /// configuration on a live path. The RTT estimator is kept, and the
+    /// congestion window is handed over to the new controller through
+    /// `set_congestion_window()` if the old one has left slow start.
+    /// Otherwise the new controller starts in slow start. The ECN codepoint
+    /// of the outgoing packets follows the new controller.
+    pub(super) fn switch_congestion_controller(&mut self, conf: &RecoveryConfig, now: Instant) {
+        let mut congestion = congestion_control::build_congestion_controller(conf);
+        if !self.congestion.in_slow_start() {
+            congestion.set_congestion_window(now, self.congestion.congestion_window());
+        }
+        self.ecn.set_l4s(congestion.is_l4s());
+        trace!(
+            "{} switch congestion controller from {} to {}, cwnd={}",
+            self.trace_id,
+            self.congestion.name(),
+            congestion.name(),
+            congestion.congestion_window()
+        );
+        self.congestion = congestion;
+    }
+
+    /// Jump-start the congestion window with the capacity and RTT saved from
+    /// a previous connection. It should only be called before any packet is
+    /// sent.
+    pub(super) fn enable_careful_resume(&mut self, saved_capacity: u64, saved_rtt: Duration) {
+        self.careful_resume.enable(saved_capacity, saved_rtt);
+    }
+
     /// Set trace id.
     pub fn set_trace_id(&mut self, trace_id: &str) {
         self.trace_id = trace_id.to_string();
@@ -161,10 +356,14 @@ impl Recovery {
         handshake_status: HandshakeStatus,
         now: Instant,
     ) {
+        let pkt_num = pkt.pkt_num;
         let in_flight = pkt.in_flight;
         let ack_eliciting = pkt.ack_eliciting;
         let pacing = pkt.pacing;
         let sent_size = pkt.sent_size;
+        if pkt.ecn_marked {
+            self.ecn.on_packet_sent();
+        }
 
         pkt.time_sent = now;
         let space = match spaces.get_mut(space_id) {
@@ -174,6 +373,9 @@ impl Recovery {
         if in_flight {
             // notify congestion controller of the sent event
             if space_id != SpaceId::Initial && space_id != SpaceId::Handshake {
+                let lost = self.delivery_rate.lost();
+                self.delivery_rate
+                    .on_packet_sent(&mut pkt, self.bytes_in_flight as u64, lost);
                 self.congestion
                     .on_sent(now, &mut pkt, self.bytes_in_flight as u64);
                 trace!(
@@ -210,12 +412,20 @@ impl Recovery {
             space.bytes_in_flight += sent_size;
             self.bytes_in_flight += sent_size;
             self.cache_pkt_size = sent_size;
+            if space_id != SpaceId::Initial && space_id != SpaceId::Handshake {
+                self.careful_resume
+                    .on_packet_sent(pkt_num, self.bytes_in_flight as u64);
+            }
 
             self.set_loss_detection_timer(space_id, spaces, handshake_status, now);
         }
 
         // Update pacing tokens number.
         if pacing {
+            if self.pacing_offload {
+                let release_time = self.pacing_release_time(sent_size as u64, now);
+                self.release_time = self.release_time.max(release_time);
+            }
             self.pacer.on_sent(sent_size as u64);
         }
     }
@@ -228,21 +438,38 @@ impl Recovery {
         &mut self,
         ranges: &RangeSet,
         ack_delay: u64,
+        ecn_counts: Option<&EcnCounts>,
         space_id: SpaceId,
         spaces: &mut PacketNumSpaceMap,
         handshake_status: HandshakeStatus,
-        qlog: Option<&mut qlog::QlogWriter>,
+        mut qlog: Option<&mut qlog::QlogWriter>,
         now: Instant,
     ) -> Result<(u64, u64)> {
         let space = spaces.get_mut(space_id).ok_or(Error::InternalError)?;
 
-        // Update the largest packet number acknowledged in the space
-        let largest_acked_pkt = ranges.max().unwrap();
-        if space.largest_acked_pkt == u64::MAX {
-            space.largest_acked_pkt = largest_acked_pkt;
+        // Update the largest packet number acknowledged in the space. The
+        // ranges are empty if only the carried over ranges are processed.
+        let largest_acked_increased = match ranges.max() {
+            Some(largest_acked_pkt) => {
+                let increased = space.largest_acked_pkt == u64::MAX
+                    || largest_acked_pkt > space.largest_acked_pkt;
+                if increased {
+                    space.largest_acked_pkt = largest_acked_pkt;
+                }
+                increased
+            }
+            None => false,
+        };
+
+        // Process the ranges of a large ACK frame incrementally, starting
+        // from the largest ones.
+        let batch;
+        let ranges = if ranges.len() > MAX_ACK_RANGES_PER_POLL || self.has_pending_acks() {
+            batch = self.take_ack_ranges(space_id, ranges);
+            &batch
         } else {
-            space.largest_acked_pkt = cmp::max(space.largest_acked_pkt, largest_acked_pkt);
-        }
+            ranges
+        };
 
         if space_id != SpaceId::Initial && space_id != SpaceId::Handshake {
             self.congestion.begin_ack(now, self.bytes_in_flight as u64);
@@ -263,6 +490,41 @@ impl Recovery {
             return Ok((0, 0));
         }
 
+        // Detect spurious congestion event and undo the window reduction.
+        for pkt in newly_acked_pkts.iter().filter(|p| p.time_lost.is_some()) {
+            if let Some(qlog) = qlog.as_deref_mut() {
+                self.qlog_recovery_spurious_loss_detected(qlog, pkt, now);
+            }
+            if let Some((start, lost)) = self.undo_epoch.as_mut() {
+                if pkt.time_lost.is_some_and(|t| t >= *start) {
+                    *lost = lost.saturating_sub(1);
+                }
+            }
+        }
+        if let Some((_, 0)) = self.undo_epoch {
+            self.undo_epoch = None;
+            self.congestion.on_spurious_congestion_event(now);
+            self.stats.spurious_congestion_count =
+                self.stats.spurious_congestion_count.saturating_add(1);
+            trace!(
+                "now={:?} {} {} ON_SPURIOUS_CONGESTION_EVENT inflight={} cwnd={}",
+                now,
+                self.trace_id,
+                self.congestion.name(),
+                self.bytes_in_flight,
+                self.congestion.congestion_window()
+            );
+        }
+
+        // Generate the delivery rate sample of the ACK frame.
+        if space_id != SpaceId::Initial && space_id != SpaceId::Handshake {
+            self.delivery_rate.generate_rate_sample();
+            self.congestion.on_rate_sample(&self.delivery_rate);
+            if let Some(qlog) = qlog.as_deref_mut() {
+                self.qlog_recovery_delivery_rate_sampled(qlog);
+            }
+        }
+
         // Update RTT estimation
         // TODO: check ack_delay against amx_ack_delay
         if let Some(rtt) = rtt_sample {
@@ -278,18 +540,50 @@ impl Recovery {
             let ack_delay = Duration::from_micros(ack_delay);
             if !rtt.is_zero() {
                 self.rtt.update(ack_delay, rtt);
+                self.first_rtt_sample.get_or_insert(now);
+            }
+        }
+
+        // Validate ECN counts and react to the increase in ECN-CE count.
+        // See RFC 9002 Section B.7
+        if largest_acked_increased {
+            let ecn_acked = newly_acked_pkts.iter().filter(|p| p.ecn_marked).count() as u64;
+            let ce_increase =
+                self.ecn
+                    .on_ack_received(ecn_acked, ecn_counts, &mut space.peer_ecn_counts);
+            if self.ecn.is_capable()
+                && space_id != SpaceId::Initial
+                && space_id != SpaceId::Handshake
+            {
+                self.on_ecn_feedback(space, space.largest_acked_pkt, ecn_acked, ce_increase, now);
             }
         }
 
-        // Detect lost packets
-        let (lost_packets, lost_bytes) = self.detect_lost_packets(space, qlog, now);
+        // Detect lost packets. It is deferred until all the carried over
+        // ranges of the space are processed, otherwise the packets in them
+        // would be declared lost spuriously.
+        let deferred = self.pending_ack_ranges.iter().any(|p| p.0 == space_id);
+        let (lost_packets, lost_bytes) = if deferred {
+            (0, 0)
+        } else {
+            self.detect_lost_packets(space, qlog, now)
+        };
 
         // Remove acked or lost packets from sent queue in batch.
         self.drain_sent_packets(space, now, self.rtt.smoothed_rtt());
 
         // Notify the congestion controller of acked event
         if space_id != SpaceId::Initial && space_id != SpaceId::Handshake {
+            self.careful_resume.end_ack(
+                rtt_sample,
+                self.bytes_in_flight as u64,
+                self.congestion.as_mut(),
+                now,
+            );
             self.congestion.end_ack();
+            if self.congestion.is_rate_limited() {
+                self.delivery_rate.set_app_limited(true);
+            }
         }
 
         self.pto_count = 0;
@@ -297,6 +591,120 @@ impl Recovery {
         Ok((lost_packets, lost_bytes))
     }
 
+    /// Continue processing the ACK ranges carried over from previous ACK
+    /// frames.
+    pub(super) fn on_pending_acks(
+        &mut self,
+        spaces: &mut PacketNumSpaceMap,
+        handshake_status: HandshakeStatus,
+        mut qlog: Option<&mut qlog::QlogWriter>,
+        now: Instant,
+    ) -> Result<(u64, u64)> {
+        let mut lost = (0, 0);
+        let space_ids: Vec<SpaceId> = self.pending_ack_ranges.iter().map(|p| p.0).collect();
+        for space_id in space_ids {
+            let (lost_pkts, lost_bytes) = self.on_ack_received(
+                &RangeSet::default(),
+                0,
+                None,
+                space_id,
+                spaces,
+                handshake_status,
+                qlog.as_deref_mut(),
+                now,
+            )?;
+            lost.0 += lost_pkts;
+            lost.1 += lost_bytes;
+        }
+        Ok(lost)
+    }
+
+    /// Return whether there are ACK ranges carried over to be processed.
+    pub(super) fn has_pending_acks(&self) -> bool {
+        !self.pending_ack_ranges.is_empty()
+    }
+
+    /// Merge the received ACK ranges into the carried over ranges of the
+    /// space, and take at most `MAX_ACK_RANGES_PER_POLL` largest ranges out
+    /// for processing.
+    fn take_ack_ranges(&mut self, space_id: SpaceId, ranges: &RangeSet) -> RangeSet {
+        let idx = match self.pending_ack_ranges.iter().position(|p| p.0 == space_id) {
+            Some(idx) => idx,
+            None => {
+                self.pending_ack_ranges
+                    .push((space_id, RangeSet::default()));
+                self.pending_ack_ranges.len() - 1
+            }
+        };
+        let pending = &mut self.pending_ack_ranges[idx].1;
+        for r in ranges.iter() {
+            pending.insert(r);
+        }
+        while pending.len() > MAX_PENDING_ACK_RANGES {
+            pending.pop_min();
+            self.stats.ack_ranges_dropped += 1;
+        }
+
+        let mut batch = RangeSet::default();
+        while batch.len() < MAX_ACK_RANGES_PER_POLL {
+            match pending.pop_max() {
+                Some(r) => batch.insert(r),
+                None => break,
+            }
+        }
+
+        if pending.is_empty() {
+            self.pending_ack_ranges.swap_remove(idx);
+        } else {
+            self.stats.ack_deferred_count += 1;
+            trace!(
+                "{} ack ranges carried over space={:?} remaining={}",
+                self.trace_id,
+                space_id,
+                pending.len()
+            );
+        }
+        batch
+    }
+
+    /// Handle the acknowledgement of a packet which has been declared lost.
+    ///
+    /// If adaptive reordering is enabled, the packet threshold is increased to
+    /// the observed reordering distance and the time threshold to the observed
+    /// reordering time, so that the same reordering would not be declared
+    /// lost again. See RFC 9002 Section 6.1 and RFC 8985 Section 6.2.
+    fn on_spurious_loss(
+        &mut self,
+        pkt_num: u64,
+        time_sent: Instant,
+        largest_acked: u64,
+        now: Instant,
+    ) {
+        self.stats.spurious_lost_count = self.stats.spurious_lost_count.saturating_add(1);
+        if !self.adaptive_reordering {
+            return;
+        }
+
+        let reordering = largest_acked.saturating_sub(pkt_num).saturating_add(1);
+        self.pkt_thresh = cmp::min(cmp::max(self.pkt_thresh, reordering), MAX_PACKET_THRESHOLD);
+
+        let rtt = cmp::max(self.rtt.latest_rtt(), self.rtt.smoothed_rtt());
+        if !rtt.is_zero() {
+            let reordering =
+                now.saturating_duration_since(time_sent).as_secs_f64() / rtt.as_secs_f64();
+            self.time_thresh = reordering.max(self.time_thresh).min(MAX_TIME_THRESHOLD);
+        }
+
+        trace!(
+            "now={:?} {} SPURIOUS_LOST pkt_num={} pkt_thresh={} time_thresh={}",
+            now,
+            self.trace_id,
+            pkt_num,
+            self.pkt_thresh,
+            self.time_thresh
+        );
+    }
+
     /// Detect acknowledged packets.
     ///
     /// It return the latest RTT sample, if any.
@@ -311,23 +719,22 @@ impl Recovery {
         let mut largest_newly_acked_sent_time = now;
         let mut newly_ack_eliciting_pkt_acked = false;
 
-        let mut idx = 0;
-        'ranges_loop: for r in ranges.iter() {
-            'sent_pkt_loop: while idx < space.sent.len() {
-                let sent_pkt = space.sent.get_mut(idx).unwrap();
-                // Find an unacked sent packet which matches the current ACK range.
-                // Note: The packet numbers in ranges and space.sent are in ascending order.
-                if sent_pkt.pkt_num < r.start || sent_pkt.time_acked.is_some() {
-                    idx += 1;
-                    continue 'sent_pkt_loop;
-                }
-                if sent_pkt.pkt_num >= r.end {
-                    continue 'ranges_loop;
+        for r in ranges.iter() {
+            // Find unacked sent packets which match the current ACK range.
+            for sent_pkt in space.sent.range_mut(r.start..r.end) {
+                if sent_pkt.time_acked.is_some() {
+                    continue;
                 }
 
                 sent_pkt.time_acked = Some(now);
-                // TODO: detect spurious retransmissions and increase the
-                // packet or time reordering threshold
+                if sent_pkt.time_lost.is_some() {
+                    self.on_spurious_loss(
+                        sent_pkt.pkt_num,
+                        sent_pkt.time_sent,
+                        space.largest_acked_pkt,
+                        now,
+                    );
+                }
 
                 // TODO: update rtt.
 
@@ -337,7 +744,9 @@ impl Recovery {
                     newly_ack_eliciting_pkt_acked = true
                 }
 
-                if sent_pkt.in_flight {
+                // The bytes of a packet declared lost have already been
+                // removed from the bytes in flight.
+                if sent_pkt.in_flight && sent_pkt.time_lost.is_none() {
                     space.bytes_in_flight =
                         space.bytes_in_flight.saturating_sub(sent_pkt.sent_size);
                     self.bytes_in_flight = self.bytes_in_flight.saturating_sub(sent_pkt.sent_size);
@@ -353,6 +762,7 @@ impl Recovery {
                 // Process each acked packet in congestion controller and update delivery
                 // rate sample.
                 if space.id != SpaceId::Initial && space.id != SpaceId::Handshake {
+                    self.delivery_rate.update_rate_sample(sent_pkt);
                     self.congestion.on_ack(
                         sent_pkt,
                         now,
@@ -360,6 +770,14 @@ impl Recovery {
                         &self.rtt,
                         self.bytes_in_flight as u64,
                     );
+                    self.careful_resume
+                        .on_packet_acked(sent_pkt.pkt_num, sent_pkt.sent_size as u64);
+                    self.bandwidth.on_packet_acked(
+                        sent_pkt.sent_size as u64,
+                        self.send_limited == SendLimited::App,
+                        self.rtt.smoothed_rtt(),
+                        now,
+                    );
                 }
 
                 trace!(
@@ -375,16 +793,13 @@ impl Recovery {
 
                 space.acked.append(&mut sent_pkt.frames);
                 newly_acked.push(AckedPacket {
+                    pkt_type: sent_pkt.pkt_type,
                     pkt_num: sent_pkt.pkt_num,
                     time_sent: sent_pkt.time_sent,
                     rtt: now.saturating_duration_since(sent_pkt.time_sent),
+                    ecn_marked: sent_pkt.ecn_marked,
+                    time_lost: sent_pkt.time_lost,
                 });
-
-                // Process next sent packet.
-                idx += 1;
-                if idx == space.sent.len() {
-                    break 'ranges_loop;
-                }
             }
         }
 
@@ -400,14 +815,84 @@ impl Recovery {
         }
     }
 
-    /// Check whether in persistent congestion.
+    /// Notify the congestion controller of the ECN feedback, which is
+    /// attributed to the largest acked packet. The L4S congestion controllers
+    /// receive all the feedback, and the others treat the increase in ECN-CE
+    /// count as a congestion event.
+    fn on_ecn_feedback(
+        &mut self,
+        space: &PacketNumSpace,
+        largest_acked_pkt: u64,
+        ecn_acked: u64,
+        ce_increase: u64,
+        now: Instant,
+    ) {
+        let pkt = match space.sent.get(largest_acked_pkt) {
+            Some(pkt) => pkt,
+            None => return,
+        };
+        let bytes_in_flight = self.bytes_in_flight as u64;
+        if self.congestion.is_l4s() {
+            self.congestion
+                .on_ecn_feedback(now, pkt, ecn_acked, ce_increase, bytes_in_flight);
+        } else if ce_increase > 0 {
+            self.congestion
+                .on_congestion_event(now, pkt, false, 0, bytes_in_flight);
+        } else {
+            return;
+        }
+        if ce_increase > 0 {
+            self.undo_epoch = None;
+            self.careful_resume
+                .on_congestion_event(self.congestion.as_mut(), now);
+        }
+        trace!(
+            "now={:?} {} {} ON_ECN_FEEDBACK acked={} ce={} inflight={} cwnd={}",
+            now,
+            self.trace_id,
+            self.congestion.name(),
+            ecn_acked,
+            ce_increase,
+            self.bytes_in_flight,
+            self.congestion.congestion_window()
+        );
+    }
+
+    /// Check whether in persistent congestion, given the packet number and the
+    /// send time of the earliest and the latest ack-eliciting packets that are
+    /// declared lost. Return the duration of the congestion period and the
+    /// number of packets sent in the period.
     ///
     /// When a sender establishes loss of all packets sent over a long enough duration,
     /// the network is considered to be experiencing persistent congestion.
     /// See https://www.rfc-editor.org/rfc/rfc9002.html#name-persistent-congestion
-    fn in_persistent_congestion(&self) -> bool {
-        // todo: Check whether in persistent congestion.
-        false
+    fn in_persistent_congestion(
+        &self,
+        space: &PacketNumSpace,
+        lost: Option<((u64, Instant), (u64, Instant))>,
+    ) -> Option<(Duration, u64)> {
+        let ((first_pkt_num, first_sent), (last_pkt_num, last_sent)) = lost?;
+        let duration = last_sent.saturating_duration_since(first_sent);
+        let threshold =
+            (self.rtt.pto_base() + self.max_ack_delay) * PERSISTENT_CONGESTION_THRESHOLD;
+        if duration < threshold {
+            return None;
+        }
+
+        // None of the packets sent between the two packets are acknowledged.
+        let mut packets = 0;
+        for pkt in space
+            .sent
+            .iter()
+            .skip_while(|p| p.pkt_num < first_pkt_num)
+            .take_while(|p| p.pkt_num <= last_pkt_num)
+        {
+            if pkt.time_acked.is_some() {
+                return None;
+            }
+            packets += 1;
+        }
+        Some((duration, packets))
     }
 
     /// Detect lost packets from the sent packets.
@@ -425,7 +910,9 @@ impl Recovery {
 
         let mut lost_packets = 0;
         let mut lost_bytes = 0;
+        let mut ecn_lost_packets = 0;
         let mut latest_lost_packet = None;
+        let mut persistent_lost: Option<((u64, Instant), (u64, Instant))> = None;
 
         let loss_delay =
             cmp::max(self.rtt.latest_rtt(), self.rtt.smoothed_rtt()).mul_f64(self.time_thresh);
@@ -445,13 +932,15 @@ impl Recovery {
             // * The packet was sent kPacketThreshold packets before an
             //   acknowledged packet, or it was sent long enough in the past.
             // See RFC 9002 Section 6.1
-            if unacked.time_sent <= lost_send_time
-                || unacked.pkt_num + self.pkt_thresh <= space.largest_acked_pkt
-            {
+            let reordered = unacked.pkt_num + self.pkt_thresh <= space.largest_acked_pkt;
+            if unacked.time_sent <= lost_send_time || reordered {
                 space.lost.append(&mut unacked.frames);
                 unacked.time_lost = Some(now);
 
                 lost_packets += 1;
+                if unacked.ecn_marked {
+                    ecn_lost_packets += 1;
+                }
                 if unacked.in_flight {
                     lost_bytes += unacked.sent_size as u64;
                     space.bytes_in_flight = space.bytes_in_flight.saturating_sub(unacked.sent_size);
@@ -470,8 +959,22 @@ impl Recovery {
                 if !unacked.pmtu_probe {
                     latest_lost_packet = Some(unacked.clone());
                 }
+                if unacked.ack_eliciting
+                    && self.first_rtt_sample.is_some_and(|t| unacked.time_sent > t)
+                {
+                    let lost = (unacked.pkt_num, unacked.time_sent);
+                    persistent_lost = match persistent_lost {
+                        Some((first, _)) => Some((first, lost)),
+                        None => Some((lost, lost)),
+                    };
+                }
                 if let Some(qlog) = qlog.as_mut() {
-                    self.qlog_recovery_packet_lost(qlog, unacked);
+                    let trigger = if reordered {
+                        PacketLostTrigger::ReorderingThreshold
+                    } else {
+                        PacketLostTrigger::TimeThreshold
+                    };
+                    self.qlog_recovery_packet_lost(qlog, unacked, trigger);
                 }
                 trace!(
                     "now={:?} {} {} ON_LOST {:?} inflight={} cwnd={}",
@@ -494,13 +997,27 @@ impl Recovery {
         // Notify congestion controller of the lost event
         if let Some(lost_packet) = latest_lost_packet {
             if space.id != SpaceId::Initial && space.id != SpaceId::Handshake {
+                self.delivery_rate.on_packet_lost(lost_bytes);
+                let persistent_congestion = self.in_persistent_congestion(space, persistent_lost);
+                let in_recovery = self.congestion.in_recovery(lost_packet.time_sent);
                 self.congestion.on_congestion_event(
                     now,
                     &lost_packet,
-                    self.in_persistent_congestion(),
+                    persistent_congestion.is_some(),
                     lost_bytes,
                     self.bytes_in_flight as u64,
                 );
+                if let Some((duration, packets)) = persistent_congestion {
+                    self.on_persistent_congestion(duration, packets, qlog.as_deref_mut());
+                }
+                self.undo_epoch = match self.undo_epoch {
+                    _ if persistent_congestion.is_some() => None,
+                    Some((start, lost)) if in_recovery => Some((start, lost + lost_packets)),
+                    _ if in_recovery => None,
+                    _ => Some((now, lost_packets)),
+                };
+                self.careful_resume
+                    .on_congestion_event(self.congestion.as_mut(), now);
                 trace!(
                     "now={:?} {} {} ON_CONGESTION_EVENT lost_size={} inflight={} cwnd={}",
                     now,
@@ -513,30 +1030,25 @@ impl Recovery {
             }
         }
 
-        self.stat_lost_event(lost_packets, lost_bytes);
+        if ecn_lost_packets > 0 {
+            self.ecn.on_packets_lost(ecn_lost_packets);
+        }
+
+        self.stat_lost_event(space.id, lost_packets, lost_bytes);
         (lost_packets, lost_bytes)
     }
 
     // Remove acked or lost packet from the packet sent queue in batch.
     //
-    // Removing packets from the middle would require copying to compact the vec.
-    // So it only remove a contiguous range of packets from the start of the vec.
+    // The slots of the sent queue are indexed by packet number, so it only
+    // remove a contiguous range of packets from the start of the queue.
     fn drain_sent_packets(&mut self, space: &mut PacketNumSpace, now: Instant, rtt: Duration) {
-        let mut lowest_non_expired_pkt_index = space.sent.len();
-        for (i, pkt) in space.sent.iter().enumerate() {
-            // find the first element that is neither acked nor lost.
-            if pkt.time_acked.is_none() && pkt.time_lost.is_none() {
-                lowest_non_expired_pkt_index = i;
-                break;
-            }
-            if let Some(time_lost) = pkt.time_lost {
-                if time_lost + rtt > now {
-                    lowest_non_expired_pkt_index = i;
-                    break;
-                }
-            }
-        }
-        space.sent.drain(..lowest_non_expired_pkt_index);
+        // Stop at the first packet that is neither acked nor lost, or the
+        // packet that was lost recently.
+        space.sent.remove_front_while(|pkt| match pkt.time_lost {
+            Some(time_lost) => time_lost + rtt <= now,
+            None => pkt.time_acked.is_some(),
+        });
     }
 
     // Set timer for loss detection.
@@ -577,6 +1089,17 @@ impl Recovery {
         self.loss_detection_timer
     }
 
+    /// Return the content of the probe packets in the Application Data packet
+    /// number space.
+    pub(super) fn pto_probe_strategy(&self) -> PtoProbeStrategy {
+        self.pto_probe_strategy
+    }
+
+    /// Return whether to send the two probe packets on different paths.
+    pub(super) fn pto_probes_across_paths(&self) -> bool {
+        self.pto_probes_across_paths
+    }
+
     /// Handles timeout event.
     ///
     /// When the loss detection timer expires, the timer's mode determines the
@@ -626,6 +1149,7 @@ impl Recovery {
             None => return (0, 0),
         };
         self.pto_count += 1;
+        self.stats.pto_count = self.stats.pto_count.saturating_add(1);
 
         space.loss_probes = match sid {
             Initial | Handshake => 1,
@@ -634,12 +1158,17 @@ impl Recovery {
 
         // An endpoint SHOULD include new data in packets that are sent on PTO
         // expiration. Previously sent data MAY be sent if no new data can be
-        // sent. However, we only try to retransmit the oldest unacked data.
+        // sent. By default, we try to retransmit the oldest unacked data, which
+        // repairs a tail loss without waiting for the loss detection.
+        let retransmit = match sid {
+            Initial | Handshake => true,
+            _ => self.pto_probe_strategy == PtoProbeStrategy::OldestUnacked,
+        };
         let unacked_iter = space
             .sent
             .iter_mut()
             .filter(|p| p.has_data && p.time_acked.is_none() && p.time_lost.is_none())
-            .take(space.loss_probes);
+            .take(if retransmit { space.loss_probes } else { 0 });
 
         for unacked in unacked_iter {
             // A PTO timer expiration event does not indicate packet loss and
@@ -680,26 +1209,38 @@ impl Recovery {
         (time, sid)
     }
 
-    /// Calculate the probe timeout.
-    fn calculate_pto(&self) -> Duration {
+    /// Calculate the multiplier of the probe timeout for the consecutive
+    /// probe timeouts.
+    fn pto_backoff(&self) -> u32 {
         let backoff_factor = self
             .pto_count
             .saturating_sub(self.pto_linear_factor as usize);
+        let backoff_factor = cmp::min(backoff_factor as u64, self.max_pto_backoff as u64);
 
-        cmp::min(
-            self.rtt.pto_base() * 2_u32.saturating_pow(backoff_factor as u32),
-            self.max_pto,
-        )
+        self.pto_multiplier.saturating_pow(backoff_factor as u32)
+    }
+
+    /// Calculate the probe timeout.
+    ///
+    /// Before the first RTT sample is taken, the probe timeout is derived from
+    /// the initial RTT with a configurable multiplier, which allows the
+    /// Initial and Handshake data to be retransmitted earlier.
+    fn calculate_pto(&self) -> Duration {
+        let pto_base = match self.initial_pto_factor {
+            Some(factor) if !self.rtt.has_sample() => {
+                let pto_base = self.rtt.latest_rtt().as_secs_f64() * factor;
+                let pto_base = Duration::try_from_secs_f64(pto_base).unwrap_or(self.max_pto);
+                cmp::max(pto_base, TIMER_GRANULARITY)
+            }
+            _ => self.rtt.pto_base(),
+        };
+        cmp::min(pto_base.saturating_mul(self.pto_backoff()), self.max_pto)
     }
 
     /// Calculate the probe timeout include `max_ack_delay`.
     fn pto_with_ack_delay(&self, duration: Duration) -> Duration {
-        let backoff_factor = self
-            .pto_count
-            .saturating_sub(self.pto_linear_factor as usize);
-
         cmp::min(
-            duration + self.max_ack_delay * 2_u32.saturating_pow(backoff_factor as u32),
+            duration.saturating_add(self.max_ack_delay.saturating_mul(self.pto_backoff())),
             self.max_pto,
         )
     }
@@ -782,10 +1323,10 @@ impl Recovery {
         // Removing discarded packets from bytes in flight
         self.remove_from_bytes_in_flight(space);
 
-        // Clear packet queue
-        space.sent.clear();
-        space.lost.clear();
-        space.acked.clear();
+        // Clear packet queue and free the memory since the space will never
+        // be used again
+        space.release();
+        self.pending_ack_ranges.retain(|p| p.0 != space_id);
 
         // Reset loss dection timer
         space.time_of_last_sent_ack_eliciting_pkt = None;
@@ -801,7 +1342,7 @@ impl Recovery {
     /// When Initial or Handshake keys are discarded, packets sent in that
     /// space no longer count toward bytes in flight.
     fn remove_from_bytes_in_flight(&mut self, space: &PacketNumSpace) {
-        for pkt in &space.sent {
+        for pkt in space.sent.iter() {
             if !pkt.in_flight || pkt.time_acked.is_some() || pkt.time_lost.is_some() {
                 continue;
             }
@@ -833,16 +1374,16 @@ impl Recovery {
 
     /// Check whether this path can still send packets.
     pub(crate) fn can_send(&mut self) -> bool {
-        if self.bytes_in_flight >= self.congestion.congestion_window() as usize {
+        if self.bytes_in_flight >= self.congestion_window() as usize {
             debug!(
                 "{} sending is limited by congestion controller, inflight {}, window {}",
                 self.trace_id,
                 self.bytes_in_flight,
-                self.congestion.congestion_window()
+                self.congestion_window()
             );
             return false;
         }
-        if self.pacer.enabled() && !self.can_pacing() {
+        if self.pacer.enabled() && !self.pacing_offload && !self.can_pacing() {
             debug!(
                 "{} sending is limited by pacer, pacing timer {:?}",
                 self.trace_id, self.pacer_timer
@@ -852,12 +1393,35 @@ impl Recovery {
         true
     }
 
+    /// Return the congestion window, which may be jump-started by Careful
+    /// Resume.
+    fn congestion_window(&self) -> u64 {
+        let cwnd = self.congestion.congestion_window();
+        match self.careful_resume.congestion_window() {
+            Some(jump_cwnd) => cwnd.max(jump_cwnd),
+            None => cwnd,
+        }
+    }
+
+    /// Return the pacing rate capped by the upper limit, if any.
+    fn pacing_rate(&self) -> Option<u64> {
+        let rate = self
+            .careful_resume
+            .pacing_rate(self.rtt.smoothed_rtt())
+            .or_else(|| self.congestion.pacing_rate());
+        match (rate, self.max_pacing_rate) {
+            (Some(rate), Some(limit)) if rate > 0 => Some(rate.min(limit)),
+            (rate, None) => rate,
+            (_, limit) => limit,
+        }
+    }
+
     fn can_pacing(&mut self) -> bool {
         let now = time::Instant::now();
-        let cwnd = self.congestion.congestion_window();
+        let cwnd = self.congestion_window();
         let srtt = self.rtt.smoothed_rtt() as Duration;
 
-        if let Some(pr) = self.congestion.pacing_rate() {
+        if let Some(pr) = self.pacing_rate() {
             self.pacer_timer = self.pacer.schedule(
                 self.cache_pkt_size as u64,
                 pr,
@@ -871,6 +1435,28 @@ impl Recovery {
         self.pacer_timer.is_none()
     }
 
+    /// Return the time to release a packet of the given size if pacing is
+    /// offloaded, or None if it can be sent immediately.
+    fn pacing_release_time(&mut self, bytes: u64, now: Instant) -> Option<Instant> {
+        if !self.pacer.enabled() {
+            return None;
+        }
+        let pacing_rate = self.pacing_rate()?;
+        self.pacer.release_time(
+            bytes,
+            pacing_rate,
+            self.rtt.smoothed_rtt(),
+            self.congestion_window(),
+            self.max_datagram_size as u64,
+            now,
+        )
+    }
+
+    /// Take the release time of the UDP datagram that has been written.
+    pub(crate) fn take_release_time(&mut self) -> Option<Instant> {
+        self.release_time.take()
+    }
+
     /// Update statistics for the packet sent event
     pub(crate) fn stat_sent_event(&mut self, sent_pkts: u64, sent_bytes: u64) {
         self.stats.sent_count = self.stats.sent_count.saturating_add(sent_pkts);
@@ -891,9 +1477,21 @@ impl Recovery {
     }
 
     /// Update statistics for the packet loss event
-    pub(crate) fn stat_lost_event(&mut self, lost_pkts: u64, lost_bytes: u64) {
+    pub(crate) fn stat_lost_event(&mut self, space_id: SpaceId, lost_pkts: u64, lost_bytes: u64) {
         self.stats.lost_count = self.stats.lost_count.saturating_add(lost_pkts);
         self.stats.lost_bytes = self.stats.lost_bytes.saturating_add(lost_bytes);
+
+        let space_lost_count = match space_id {
+            Initial => &mut self.stats.initial_lost_count,
+            Handshake => &mut self.stats.handshake_lost_count,
+            _ => &mut self.stats.app_data_lost_count,
+        };
+        *space_lost_count = space_lost_count.saturating_add(lost_pkts);
+    }
+
+    /// Update statistics for the retransmitted stream data
+    pub(crate) fn stat_retrans_stream_event(&mut self, bytes: u64) {
+        self.stats.retrans_stream_bytes = self.stats.retrans_stream_bytes.saturating_add(bytes);
     }
 
     /// Update statistics for the congestion_window
@@ -917,25 +1515,129 @@ impl Recovery {
         }
     }
 
-    /// Update statistics for the congestion window limited event
-    pub(crate) fn stat_cwnd_limited(&mut self) {
-        let is_cwnd_limited = self.bytes_in_flight >= self.congestion.congestion_window() as usize;
-        let now = Instant::now();
-        if let Some(last_cwnd_limited_time) = self.last_cwnd_limited_time {
-            // Update duration timely, in case it stays in cwnd limited all the time.
-            let duration = now.saturating_duration_since(last_cwnd_limited_time);
-            let duration = duration.as_millis() as u64;
-            self.stats.cwnd_limited_duration =
-                self.stats.cwnd_limited_duration.saturating_add(duration);
-            if is_cwnd_limited {
-                self.last_cwnd_limited_time = Some(now);
-            } else {
-                self.last_cwnd_limited_time = None;
+    /// Check whether sending is limited by the congestion window.
+    pub(crate) fn is_cwnd_limited(&self) -> bool {
+        self.bytes_in_flight >= self.congestion_window() as usize
+    }
+
+    /// Return the reason why the sender is limited currently.
+    pub(crate) fn send_limited(&self) -> SendLimited {
+        self.send_limited
+    }
+
+    /// Return the sending rate of the path in bytes per second, which is the
+    /// congestion window divided by the smoothed RTT.
+    pub(super) fn sending_rate(&self) -> f64 {
+        let srtt = self.rtt.smoothed_rtt();
+        if srtt.is_zero() {
+            return 0.0;
+        }
+        self.congestion_window() as f64 / srtt.as_secs_f64()
+    }
+
+    /// Return the target bitrate of the media encoder in bits per second, if
+    /// the congestion controller is designed for real-time media.
+    pub(crate) fn target_bitrate(&self) -> Option<u64> {
+        self.congestion.target_bitrate()
+    }
+
+    /// Record the persistent congestion declared, which collapses the
+    /// congestion window to the minimum.
+    fn on_persistent_congestion(
+        &mut self,
+        duration: Duration,
+        packets: u64,
+        qlog: Option<&mut qlog::QlogWriter>,
+    ) {
+        let cwnd = self.congestion.congestion_window();
+        self.stats.persistent_congestion_count =
+            self.stats.persistent_congestion_count.saturating_add(1);
+        self.persistent_congestion = Some((duration, packets, cwnd));
+        warn!(
+            "{} persistent congestion declared, duration={:?} lost_packets={} cwnd={}",
+            self.trace_id, duration, packets, cwnd
+        );
+
+        if let Some(qlog) = qlog {
+            let ev_data = EventData::RecoveryPersistentCongestion {
+                duration: duration.as_secs_f32() * 1000.0,
+                lost_packets: packets,
+                congestion_window: cwnd,
+            };
+            qlog.add_event_data(Instant::now(), ev_data).ok();
+        }
+    }
+
+    /// Return the persistent congestion declared since the last call, i.e.
+    /// the duration of the congestion period, the number of packets lost in
+    /// the period, and the resulting congestion window.
+    pub(super) fn persistent_congestion_declared(&mut self) -> Option<(Duration, u64, u64)> {
+        self.persistent_congestion.take()
+    }
+
+    /// Return the target bitrate if it is changed since the last call.
+    pub(super) fn target_bitrate_updated(&mut self) -> Option<u64> {
+        let bitrate = self.congestion.target_bitrate()?;
+        if self.last_target_bitrate == Some(bitrate) {
+            return None;
+        }
+        self.last_target_bitrate = Some(bitrate);
+        Some(bitrate)
+    }
+
+    /// Mark the path as application limited for the delivery rate estimation,
+    /// since there is no data to send and the sender is not limited by the
+    /// congestion window.
+    pub(super) fn on_app_limited(&mut self) {
+        self.delivery_rate.set_app_limited(true);
+    }
+
+    /// Update statistics for the send limited events.
+    ///
+    /// Return true if the send limited state is changed.
+    pub(crate) fn stat_send_limited(&mut self, limited: SendLimited, now: Instant) -> bool {
+        // Update duration timely, in case it stays in the same state all the time.
+        if let Some(last_time) = self.send_limited_time {
+            let duration = now.saturating_duration_since(last_time).as_micros() as u64;
+            if let Some((_, total)) = self.send_limited_stats(self.send_limited) {
+                *total = total.saturating_add(duration);
             }
-        } else if is_cwnd_limited {
-            // A new cwnd limited event
-            self.stats.cwnd_limited_count = self.stats.cwnd_limited_count.saturating_add(1);
-            self.last_cwnd_limited_time = Some(now);
+            self.send_limited_time = Some(now);
+        }
+        if limited == self.send_limited {
+            return false;
+        }
+
+        // A new send limited event
+        if let Some((count, _)) = self.send_limited_stats(limited) {
+            *count = count.saturating_add(1);
+        }
+        self.send_limited = limited;
+        self.send_limited_time = match limited {
+            SendLimited::None => None,
+            _ => Some(now),
+        };
+        true
+    }
+
+    /// Return the count and the duration statistics for the given send
+    /// limited state.
+    fn send_limited_stats(&mut self, limited: SendLimited) -> Option<(&mut u64, &mut u64)> {
+        let stats = &mut self.stats;
+        match limited {
+            SendLimited::None => None,
+            SendLimited::App => Some((
+                &mut stats.app_limited_count,
+                &mut stats.app_limited_duration,
+            )),
+            SendLimited::Cwnd => Some((
+                &mut stats.cwnd_limited_count,
+                &mut stats.cwnd_limited_duration,
+            )),
+            SendLimited::FlowControl => Some((
+                &mut stats.flow_control_limited_count,
+                &mut stats.flow_control_limited_duration,
+            )),
         }
     }
 
@@ -947,12 +1649,89 @@ impl Recovery {
         self.stats.rttvar = self.rtt.rttvar().as_micros() as u64;
         self.stats.in_slow_start = self.congestion.in_slow_start();
         self.stats.pacing_rate = self.congestion.pacing_rate().unwrap_or_default();
+        self.stats.bandwidth = self.bandwidth.estimate();
+        self.stats.bandwidth_confidence = self.bandwidth.confidence();
+        self.stats.delivery_rate = self.bandwidth.delivery_rate();
+        self.stats.ecn_capable = self.ecn.is_capable();
+        self.stats.ecn_ce_count = self.ecn.ce_count();
+        self.stats.packet_threshold = self.pkt_thresh;
+        self.stat_send_limited(self.send_limited, Instant::now());
+    }
+
+    /// Return the statistics of the congestion controller with the latest
+    /// values of its internal state.
+    pub(crate) fn congestion_stats(&self) -> CongestionStats {
+        let min_rtt = self.congestion.min_rtt().unwrap_or(self.rtt.min_rtt());
+        CongestionStats {
+            algorithm: self.congestion.name().to_string(),
+            state: self.congestion.state().to_string(),
+            cwnd: self.congestion_window(),
+            pacing_rate: self.pacing_rate().unwrap_or_default(),
+            bandwidth: self.congestion.bandwidth().unwrap_or_default(),
+            min_rtt: min_rtt.as_micros() as u64,
+            ..self.congestion.stats().clone()
+        }
+    }
+
+    /// Write a qlog RecoveryParametersSet event.
+    pub(crate) fn qlog_recovery_parameters_set(&self, qlog: &mut qlog::QlogWriter) {
+        let ev_data = EventData::RecoveryParametersSet {
+            reordering_threshold: Some(self.pkt_thresh as u16),
+            time_threshold: Some(self.time_thresh as f32),
+            timer_granularity: Some(TIMER_GRANULARITY.as_millis() as u16),
+            initial_rtt: None,
+            max_datagram_size: Some(self.max_datagram_size as u32),
+            initial_congestion_window: Some(self.congestion.initial_window()),
+            minimum_congestion_window: Some(self.congestion.minimal_window() as u32),
+            loss_reduction_factor: None,
+            persistent_congestion_threshold: None,
+        };
+        qlog.add_event_data(Instant::now(), ev_data).ok();
     }
 
-    /// Write a qlog RecoveryMetricsUpdated event if any recovery metric is updated.
+    /// Write a qlog RecoveryCongestionStateUpdated event if the state of the
+    /// congestion controller is changed.
+    fn qlog_recovery_congestion_state_updated(&mut self, qlog: &mut qlog::QlogWriter) {
+        let state = self.congestion.state();
+        if self.last_metrics.congestion_state == state {
+            return;
+        }
+
+        let old = std::mem::replace(&mut self.last_metrics.congestion_state, state.to_string());
+        let ev_data = EventData::RecoveryCongestionStateUpdated {
+            old: if old.is_empty() { None } else { Some(old) },
+            new: self.last_metrics.congestion_state.clone(),
+            trigger: None,
+        };
+        qlog.add_event_data(Instant::now(), ev_data).ok();
+    }
+
+    /// Write a qlog RecoveryDeliveryRateSampled event.
+    fn qlog_recovery_delivery_rate_sampled(&self, qlog: &mut qlog::QlogWriter) {
+        let ev_data = EventData::RecoveryDeliveryRateSampled {
+            delivery_rate: self.delivery_rate.delivery_rate(),
+            delivered: self.delivery_rate.sample_delivered(),
+            interval: Some(self.delivery_rate.sample_interval().as_secs_f32() * 1000.0),
+            rtt: Some(self.delivery_rate.sample_rtt().as_secs_f32() * 1000.0),
+            is_app_limited: self.delivery_rate.is_sample_app_limited(),
+        };
+        qlog.add_event_data(Instant::now(), ev_data).ok();
+    }
+
+    /// Write a qlog RecoveryMetricsUpdated event if any recovery metric is
+    /// updated, and a RecoveryCongestionStateUpdated event if the state of the
+    /// congestion controller is changed.
     pub(crate) fn qlog_recovery_metrics_updated(&mut self, qlog: &mut qlog::QlogWriter) {
+        self.qlog_recovery_congestion_state_updated(qlog);
         let mut updated = false;
 
+        let mut pto_count = None;
+        if self.last_metrics.pto_count != self.pto_count {
+            self.last_metrics.pto_count = self.pto_count;
+            pto_count = Some(self.pto_count as u16);
+            updated = true;
+        }
+
         let mut min_rtt = None;
         if self.last_metrics.min_rtt != self.rtt.min_rtt() {
             self.last_metrics.min_rtt = self.rtt.min_rtt();
@@ -982,8 +1761,8 @@ impl Recovery {
         }
 
         let mut congestion_window = None;
-        if self.last_metrics.cwnd != self.congestion.congestion_window() {
-            self.last_metrics.cwnd = self.congestion.congestion_window();
+        if self.last_metrics.cwnd != self.congestion_window() {
+            self.last_metrics.cwnd = self.congestion_window();
             congestion_window = Some(self.last_metrics.cwnd);
             updated = true;
         }
@@ -996,8 +1775,8 @@ impl Recovery {
         }
 
         let mut pacing_rate = None;
-        if self.last_metrics.pacing_rate != self.congestion.pacing_rate() {
-            self.last_metrics.pacing_rate = self.congestion.pacing_rate();
+        if self.last_metrics.pacing_rate != self.pacing_rate() {
+            self.last_metrics.pacing_rate = self.pacing_rate();
             pacing_rate = self.last_metrics.pacing_rate.map(|v| v * 8); // bps
             updated = true;
         }
@@ -1011,7 +1790,7 @@ impl Recovery {
             smoothed_rtt,
             latest_rtt,
             rtt_variance,
-            pto_count: None,
+            pto_count,
             congestion_window,
             bytes_in_flight,
             ssthresh: None,
@@ -1026,6 +1805,7 @@ impl Recovery {
         &mut self,
         qlog: &mut qlog::QlogWriter,
         pkt: &SentPacket,
+        trigger: PacketLostTrigger,
     ) {
         let ev_data = EventData::RecoveryPacketLost {
             header: Some(qlog::events::PacketHeader {
@@ -1034,11 +1814,30 @@ impl Recovery {
                 ..qlog::events::PacketHeader::default()
             }),
             frames: None,
-            is_mtu_probe_packet: None,
-            trigger: None,
+            is_mtu_probe_packet: Some(pkt.pmtu_probe),
+            trigger: Some(trigger),
         };
         qlog.add_event_data(Instant::now(), ev_data).ok();
     }
+
+    /// Write a qlog RecoverySpuriousLossDetected event.
+    fn qlog_recovery_spurious_loss_detected(
+        &self,
+        qlog: &mut qlog::QlogWriter,
+        pkt: &AckedPacket,
+        now: Instant,
+    ) {
+        let ev_data = EventData::RecoverySpuriousLossDetected {
+            header: Some(qlog::events::PacketHeader {
+                packet_type: pkt.pkt_type.to_qlog(),
+                packet_number: pkt.pkt_num,
+                ..qlog::events::PacketHeader::default()
+            }),
+            packet_threshold: Some(self.pkt_thresh),
+            time_threshold: Some(self.time_thresh as f32),
+        };
+        qlog.add_event_data(now, ev_data).ok();
+    }
 }
 
 /// Metrics used for emitting qlog RecoveryMetricsUpdated event.
@@ -1066,6 +1865,12 @@ struct RecoveryMetrics {
 
     /// Pacing rate in Bps
     pacing_rate: Option<u64>,
+
+    /// The number of times a PTO has been sent without receiving an ack.
+    pto_count: usize,
+
+    /// The state of the congestion controller.
+    congestion_state: String,
 }
 
 #[cfg(test)]
@@ -1073,6 +1878,7 @@ mod tests {
     use super::*;
     use crate::congestion_control::CongestionControlAlgorithm;
     use crate::connection::space::RateSamplePacketState;
+    use crate::connection::space::SentPacketQueue;
     use crate::ranges::RangeSet;
     use std::time::Duration;
     use std::time::Instant;
@@ -1151,6 +1957,7 @@ mod tests {
         recovery.on_ack_received(
             &acked,
             0,
+            None,
             SpaceId::Handshake,
             &mut spaces,
             status,
@@ -1223,6 +2030,7 @@ mod tests {
         let (lost_pkts, lost_bytes) = recovery.on_ack_received(
             &acked,
             0,
+            None,
             SpaceId::Handshake,
             &mut spaces,
             status,
@@ -1232,12 +2040,16 @@ mod tests {
         assert_eq!(spaces.get(space_id).unwrap().sent.len(), 4);
         assert_eq!(lost_pkts, 1);
         assert_eq!(lost_bytes, 1000);
+        assert_eq!(recovery.stats.initial_lost_count, 0);
+        assert_eq!(recovery.stats.handshake_lost_count, 1);
+        assert_eq!(recovery.stats.app_data_lost_count, 0);
 
         // Advance ticks and fake receiving of duplicated ack
         now += recovery.rtt.smoothed_rtt();
         let (lost_pkts, lost_bytes) = recovery.on_ack_received(
             &acked,
             0,
+            None,
             SpaceId::Handshake,
             &mut spaces,
             status,
@@ -1257,6 +2069,171 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn adaptive_reordering() -> Result<()> {
+        let mut conf = new_test_recovery_config();
+        conf.adaptive_reordering = true;
+        let mut recovery = Recovery::new(&conf);
+        let mut spaces = PacketNumSpaceMap::new();
+        let space_id = SpaceId::Handshake;
+        let status = HandshakeStatus {
+            derived_handshake_keys: true,
+            peer_verified_address: true,
+            completed: false,
+        };
+        let mut now = Instant::now();
+
+        // Fake sending of packet 0-3
+        for pkt_num in 0..4 {
+            let sent_pkt = new_test_sent_packet(pkt_num, 1000, now);
+            recovery.on_packet_sent(sent_pkt, space_id, &mut spaces, status, now);
+        }
+        assert_eq!(recovery.bytes_in_flight, 4000);
+
+        // Advance ticks and fake receiving of ack, and packet 0 is declared
+        // lost base on reordering threshold
+        now += Duration::from_millis(100);
+        let mut acked = RangeSet::default();
+        acked.insert(1..4);
+        let (lost_pkts, _) = recovery.on_ack_received(
+            &acked,
+            0,
+            None,
+            SpaceId::Handshake,
+            &mut spaces,
+            status,
+            None,
+            now,
+        )?;
+        assert_eq!(lost_pkts, 1);
+        assert_eq!(recovery.bytes_in_flight, 0);
+        assert_eq!(recovery.pkt_thresh, conf.packet_reordering_threshold);
+
+        // Advance ticks and fake receiving of ack for the lost packet
+        now += Duration::from_millis(50);
+        let mut acked = RangeSet::default();
+        acked.insert(0..4);
+        let (lost_pkts, _) = recovery.on_ack_received(
+            &acked,
+            0,
+            None,
+            SpaceId::Handshake,
+            &mut spaces,
+            status,
+            None,
+            now,
+        )?;
+        assert_eq!(lost_pkts, 0);
+        assert_eq!(recovery.bytes_in_flight, 0);
+        assert_eq!(recovery.stats.spurious_lost_count, 1);
+
+        // The thresholds are increased to cover the observed reordering.
+        assert_eq!(recovery.pkt_thresh, 4);
+        assert!((recovery.time_thresh - 1.5).abs() < 1e-6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn spurious_congestion_event() -> Result<()> {
+        let mut conf = new_test_recovery_config();
+        conf.congestion_control_algorithm = CongestionControlAlgorithm::Cubic;
+        let mut recovery = Recovery::new(&conf);
+        let mut spaces = PacketNumSpaceMap::new();
+        let space_id = SpaceId::Data;
+        let status = HandshakeStatus {
+            derived_handshake_keys: true,
+            peer_verified_address: true,
+            completed: true,
+        };
+        let now = Instant::now();
+
+        // Fake sending of packet 0-4
+        for pkt_num in 0..5 {
+            let sent_pkt = new_test_sent_packet(pkt_num, 1000, now);
+            recovery.on_packet_sent(sent_pkt, space_id, &mut spaces, status, now);
+        }
+
+        // Advance ticks and fake receiving of ack, and packet 0 and 1 are
+        // declared lost base on reordering threshold
+        let now = now + Duration::from_millis(100);
+        let mut acked = RangeSet::default();
+        acked.insert(2..5);
+        let (lost_pkts, _) =
+            recovery.on_ack_received(&acked, 0, None, space_id, &mut spaces, status, None, now)?;
+        assert_eq!(lost_pkts, 2);
+        let cwnd = recovery.congestion.congestion_window();
+
+        // Only one of the lost packets is acknowledged later.
+        let mut acked = RangeSet::default();
+        acked.insert(0..1);
+        recovery.on_ack_received(&acked, 0, None, space_id, &mut spaces, status, None, now)?;
+        assert_eq!(recovery.stats.spurious_lost_count, 1);
+        assert_eq!(recovery.stats.spurious_congestion_count, 0);
+        assert_eq!(recovery.congestion.congestion_window(), cwnd);
+
+        // All the lost packets are acknowledged later, and the window
+        // reduction is undone.
+        let mut acked = RangeSet::default();
+        acked.insert(1..2);
+        recovery.on_ack_received(&acked, 0, None, space_id, &mut spaces, status, None, now)?;
+        assert_eq!(recovery.stats.spurious_lost_count, 2);
+        assert_eq!(recovery.stats.spurious_congestion_count, 1);
+        assert!(recovery.congestion.congestion_window() > cwnd);
+
+        Ok(())
+    }
+
+    #[test]
+    fn persistent_congestion() -> Result<()> {
+        let mut conf = new_test_recovery_config();
+        conf.congestion_control_algorithm = CongestionControlAlgorithm::Cubic;
+        let mut recovery = Recovery::new(&conf);
+        let mut spaces = PacketNumSpaceMap::new();
+        let space_id = SpaceId::Data;
+        let status = HandshakeStatus {
+            derived_handshake_keys: true,
+            peer_verified_address: true,
+            completed: true,
+        };
+        let now = Instant::now();
+
+        // Fake sending of packet 0 and receiving of ack, which takes the
+        // first RTT sample
+        let sent_pkt = new_test_sent_packet(0, 1000, now);
+        recovery.on_packet_sent(sent_pkt, space_id, &mut spaces, status, now);
+        let now = now + Duration::from_millis(50);
+        let mut acked = RangeSet::default();
+        acked.insert(0..1);
+        recovery.on_ack_received(&acked, 0, None, space_id, &mut spaces, status, None, now)?;
+        assert_eq!(recovery.persistent_congestion_declared(), None);
+
+        // Fake sending of packet 1-5 over a long period
+        let mut sent_time = now;
+        for pkt_num in 1..6 {
+            sent_time += Duration::from_millis(200);
+            let sent_pkt = new_test_sent_packet(pkt_num, 1000, sent_time);
+            recovery.on_packet_sent(sent_pkt, space_id, &mut spaces, status, sent_time);
+        }
+
+        // Packet 1-4 are declared lost, and the period between them is longer
+        // than the persistent congestion duration
+        let now = sent_time + Duration::from_millis(50);
+        let mut acked = RangeSet::default();
+        acked.insert(5..6);
+        let (lost_pkts, _) =
+            recovery.on_ack_received(&acked, 0, None, space_id, &mut spaces, status, None, now)?;
+        assert_eq!(lost_pkts, 4);
+        assert_eq!(recovery.stats.persistent_congestion_count, 1);
+        assert_eq!(
+            recovery.persistent_congestion_declared(),
+            Some((Duration::from_millis(600), 4, conf.min_cwnd()))
+        );
+        assert_eq!(recovery.persistent_congestion_declared(), None);
+
+        Ok(())
+    }
+
     #[test]
     fn pto() -> Result<()> {
         let conf = new_test_recovery_config();
@@ -1291,6 +2268,7 @@ mod tests {
         let (lost_pkts, lost_bytes) = recovery.on_ack_received(
             &acked,
             0,
+            None,
             SpaceId::Handshake,
             &mut spaces,
             status,
@@ -1361,6 +2339,7 @@ mod tests {
         recovery.on_ack_received(
             &acked,
             0,
+            None,
             SpaceId::Handshake,
             &mut spaces,
             status,
@@ -1385,7 +2364,7 @@ mod tests {
         Ok(())
     }
 
-    fn check_acked_packets(sent: &VecDeque<SentPacket>, acked_ranges: Vec<Range<u64>>) -> bool {
+    fn check_acked_packets(sent: &SentPacketQueue, acked_ranges: Vec<Range<u64>>) -> bool {
         let ranges_contain = |pkt_num: u64, ranges: Vec<Range<u64>>| {
             for range in &ranges {
                 if range.contains(&pkt_num) {
@@ -1395,7 +2374,7 @@ mod tests {
             return false;
         };
 
-        for sent_pkt in sent {
+        for sent_pkt in sent.iter() {
             match sent_pkt.pkt_num {
                 pkt_num if ranges_contain(pkt_num, acked_ranges.clone()) => {
                     if sent_pkt.time_acked.is_none() {
@@ -1467,7 +2446,7 @@ mod tests {
             vec![500..950],
         ));
         // Fake receiving duplicated ACK.
-        recovery.on_ack_received(&ack, 0, SpaceId::Data, &mut spaces, status, None, now)?;
+        recovery.on_ack_received(&ack, 0, None, SpaceId::Data, &mut spaces, status, None, now)?;
         assert!(check_acked_packets(
             &spaces.get(SpaceId::Data).unwrap().sent,
             vec![500..950],
@@ -1515,6 +2494,104 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn delivery_rate_sample() -> Result<()> {
+        let conf = new_test_recovery_config();
+        let mut recovery = Recovery::new(&conf);
+        let mut spaces = PacketNumSpaceMap::new();
+        let status = HandshakeStatus {
+            derived_handshake_keys: true,
+            peer_verified_address: true,
+            completed: false,
+        };
+        let mut now = Instant::now();
+
+        // Fake sending of packets on Data space.
+        for pkt_num in 0..10 {
+            let sent_pkt = new_test_sent_packet(pkt_num, 1000, now);
+            recovery.on_packet_sent(sent_pkt, SpaceId::Data, &mut spaces, status, now);
+        }
+        now += Duration::from_millis(100);
+
+        // The rate sample is generated upon the ACK frame.
+        let ack = generate_ack(vec![0..5]);
+        recovery.on_ack_received(&ack, 0, None, SpaceId::Data, &mut spaces, status, None, now)?;
+        assert_eq!(recovery.delivery_rate.delivered(), 5000);
+        assert_eq!(recovery.delivery_rate.sample_delivered(), 5000);
+        assert_eq!(
+            recovery.delivery_rate.sample_rtt(),
+            Duration::from_millis(100)
+        );
+        assert_eq!(recovery.delivery_rate.delivery_rate(), 50_000);
+        assert_eq!(recovery.delivery_rate.is_app_limited(), false);
+
+        // The path becomes application limited until the packets sent are
+        // acked.
+        recovery.on_app_limited();
+        assert_eq!(recovery.delivery_rate.is_app_limited(), true);
+        let sent_pkt = new_test_sent_packet(10, 1000, now);
+        recovery.on_packet_sent(sent_pkt, SpaceId::Data, &mut spaces, status, now);
+        now += Duration::from_millis(100);
+
+        let ack = generate_ack(vec![5..11]);
+        recovery.on_ack_received(&ack, 0, None, SpaceId::Data, &mut spaces, status, None, now)?;
+        assert_eq!(recovery.delivery_rate.delivered(), 11000);
+        assert_eq!(recovery.delivery_rate.is_sample_app_limited(), true);
+        assert_eq!(recovery.delivery_rate.is_app_limited(), false);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ack_ranges_carried_over() -> Result<()> {
+        let conf = new_test_recovery_config();
+        let mut recovery = Recovery::new(&conf);
+        let mut spaces = PacketNumSpaceMap::new();
+        let status = HandshakeStatus {
+            derived_handshake_keys: true,
+            peer_verified_address: true,
+            completed: false,
+        };
+        let mut now = Instant::now();
+
+        // Fake sending of packets on Data space.
+        for pkt_num in 0..10000 as u64 {
+            let sent_pkt = new_test_sent_packet(pkt_num, 1000, now);
+            recovery.on_packet_sent(sent_pkt, SpaceId::Data, &mut spaces, status, now);
+        }
+        now += Duration::from_millis(100);
+
+        // Fake receiving an ACK frame which acknowledges every other packet.
+        let ranges = (0..5000).map(|i| i * 2..i * 2 + 1).collect();
+        let ack = generate_ack(ranges);
+        recovery.on_ack_received(&ack, 0, None, SpaceId::Data, &mut spaces, status, None, now)?;
+        assert_eq!(recovery.stats.acked_count, MAX_ACK_RANGES_PER_POLL as u64);
+        assert_eq!(
+            recovery.stats.ack_ranges_dropped,
+            (5000 - MAX_PENDING_ACK_RANGES) as u64
+        );
+        assert_eq!(recovery.stats.ack_deferred_count, 1);
+        assert!(recovery.has_pending_acks());
+
+        // Process the carried over ranges.
+        let mut polls = 1;
+        while recovery.has_pending_acks() {
+            recovery.on_pending_acks(&mut spaces, status, None, now)?;
+            polls += 1;
+        }
+        assert_eq!(polls, MAX_PENDING_ACK_RANGES / MAX_ACK_RANGES_PER_POLL);
+        assert_eq!(recovery.stats.acked_count, MAX_PENDING_ACK_RANGES as u64);
+        assert_eq!(recovery.stats.ack_deferred_count, polls as u64 - 1);
+
+        // Small ACK frames are processed at once.
+        let ack = generate_ack(vec![9999..10000]);
+        recovery.on_ack_received(&ack, 0, None, SpaceId::Data, &mut spaces, status, None, now)?;
+        assert!(!recovery.has_pending_acks());
+        assert_eq!(recovery.stats.ack_deferred_count, polls as u64 - 1);
+
+        Ok(())
+    }
+
     #[test]
     fn check_cwnd_for_non_app_data_ack() -> Result<()> {
         let conf = new_test_recovery_config();
@@ -1546,6 +2623,7 @@ mod tests {
         let (lost_pkts, lost_bytes) = recovery.on_ack_received(
             &acked,
             0,
+            None,
             SpaceId::Handshake,
             &mut spaces,
             status,
@@ -1570,6 +2648,184 @@ mod tests {
         (duration, recovery.pto_with_ack_delay(duration))
     }
 
+    #[test]
+    fn pacing_rate_limit() -> Result<()> {
+        let mut spaces = PacketNumSpaceMap::new();
+        let status = HandshakeStatus {
+            derived_handshake_keys: true,
+            peer_verified_address: true,
+            completed: true,
+        };
+
+        for (limit, min_wait, max_wait) in [
+            (None, 0, 50),
+            (Some(12_000), 90, 110), // 1200 bytes at 12000 bytes per second
+        ] {
+            let mut conf = new_test_recovery_config();
+            conf.initial_congestion_window = 100;
+            conf.max_pacing_rate = limit;
+            let mut recovery = Recovery::new(&conf);
+            let now = Instant::now();
+
+            // Exhaust the initial pacing tokens.
+            let mut pkt_num = 0;
+            while recovery.can_send() && pkt_num < 100 {
+                let mut pkt = new_test_sent_packet(pkt_num, 1200, now);
+                pkt.pacing = true;
+                recovery.on_packet_sent(pkt, SpaceId::Data, &mut spaces, status, now);
+                pkt_num += 1;
+            }
+            assert_eq!(pkt_num, 10);
+
+            let wait = recovery.pacer_timer.unwrap().duration_since(now);
+            assert!(wait >= Duration::from_millis(min_wait));
+            assert!(wait <= Duration::from_millis(max_wait));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn send_limited_stats() -> Result<()> {
+        let mut recovery = Recovery::new(&new_test_recovery_config());
+        let now = Instant::now();
+        assert_eq!(recovery.send_limited(), SendLimited::None);
+
+        // Application limited period
+        assert_eq!(recovery.stat_send_limited(SendLimited::App, now), true);
+        let now = now + Duration::from_millis(10);
+        assert_eq!(recovery.stat_send_limited(SendLimited::App, now), false);
+        assert_eq!(recovery.stats.app_limited_count, 1);
+        assert_eq!(recovery.stats.app_limited_duration, 10_000);
+
+        // Congestion window limited period
+        let now = now + Duration::from_millis(10);
+        assert_eq!(recovery.stat_send_limited(SendLimited::Cwnd, now), true);
+        assert_eq!(recovery.stats.app_limited_duration, 20_000);
+        let now = now + Duration::from_millis(5);
+        assert_eq!(recovery.stat_send_limited(SendLimited::None, now), true);
+        assert_eq!(recovery.stats.cwnd_limited_count, 1);
+        assert_eq!(recovery.stats.cwnd_limited_duration, 5_000);
+
+        // Flow control limited period
+        let now = now + Duration::from_millis(5);
+        assert_eq!(
+            recovery.stat_send_limited(SendLimited::FlowControl, now),
+            true
+        );
+        let now = now + Duration::from_millis(30);
+        assert_eq!(recovery.stat_send_limited(SendLimited::App, now), true);
+        assert_eq!(recovery.stats.flow_control_limited_count, 1);
+        assert_eq!(recovery.stats.flow_control_limited_duration, 30_000);
+        assert_eq!(recovery.stats.app_limited_count, 2);
+        assert_eq!(recovery.stats.cwnd_limited_duration, 5_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_max_pacing_rate() -> Result<()> {
+        let mut conf = new_test_recovery_config();
+        conf.enable_pacing = false;
+        let mut recovery = Recovery::new(&conf);
+        assert_eq!(recovery.pacer.enabled(), false);
+
+        // The limit is applied on the live path.
+        conf.max_pacing_rate = Some(1_000);
+        recovery.set_max_pacing_rate(&conf);
+        assert_eq!(recovery.pacer.enabled(), true);
+        assert_eq!(recovery.pacing_rate(), Some(1_000));
+
+        // The limit is removed.
+        conf.max_pacing_rate = None;
+        recovery.set_max_pacing_rate(&conf);
+        assert_eq!(recovery.pacer.enabled(), false);
+        assert_eq!(recovery.pacing_rate(), recovery.congestion.pacing_rate());
+
+        Ok(())
+    }
+
+    #[test]
+    fn switch_congestion_controller() -> Result<()> {
+        let mut conf = new_test_recovery_config();
+        conf.ecn_policy = crate::EcnPolicy::TestThenUse;
+        let mut recovery = Recovery::new(&conf);
+        assert_eq!(recovery.ecn.ecn_mark(), crate::Ecn::Ect0);
+        let now = Instant::now();
+        recovery
+            .rtt
+            .update(Duration::ZERO, Duration::from_millis(50));
+
+        // The new controller starts in slow start.
+        conf.congestion_control_algorithm = CongestionControlAlgorithm::Cubic;
+        recovery.switch_congestion_controller(&conf, now);
+        assert_eq!(recovery.congestion.name(), "CUBIC");
+        assert_eq!(recovery.congestion.in_slow_start(), true);
+        assert_eq!(recovery.congestion.congestion_window(), 1200 * 10);
+        assert_eq!(recovery.rtt.min_rtt(), Duration::from_millis(50));
+
+        // The congestion window is migrated after leaving slow start.
+        recovery.congestion.on_careful_resume(now, 1200 * 50);
+        conf.congestion_control_algorithm = CongestionControlAlgorithm::Prague;
+        recovery.switch_congestion_controller(&conf, now);
+        assert_eq!(recovery.congestion.name(), "PRAGUE");
+        assert_eq!(recovery.congestion.in_slow_start(), false);
+        assert_eq!(recovery.congestion.congestion_window(), 1200 * 50);
+        assert_eq!(recovery.rtt.min_rtt(), Duration::from_millis(50));
+
+        // The ECN codepoint follows the new controller.
+        assert_eq!(recovery.ecn.ecn_mark(), crate::Ecn::Ect1);
+        conf.congestion_control_algorithm = CongestionControlAlgorithm::Cubic;
+        recovery.switch_congestion_controller(&conf, now);
+        assert_eq!(recovery.congestion.congestion_window(), 1200 * 50);
+        assert_eq!(recovery.ecn.ecn_mark(), crate::Ecn::Ect0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn pacing_offload() -> Result<()> {
+        let mut spaces = PacketNumSpaceMap::new();
+        let status = HandshakeStatus {
+            derived_handshake_keys: true,
+            peer_verified_address: true,
+            completed: true,
+        };
+
+        let mut conf = new_test_recovery_config();
+        conf.initial_congestion_window = 100;
+        conf.max_pacing_rate = Some(12_000);
+        conf.pacing_offload = true;
+        let mut recovery = Recovery::new(&conf);
+        let now = Instant::now();
+
+        // Packets are not blocked by the pacer, and the release time of each
+        // packet after the initial tokens is exported.
+        let mut last_release_time = None;
+        for pkt_num in 0..20 {
+            assert!(recovery.can_send());
+            let mut pkt = new_test_sent_packet(pkt_num, 1200, now);
+            pkt.pacing = true;
+            recovery.on_packet_sent(pkt, SpaceId::Data, &mut spaces, status, now);
+
+            let release_time = recovery.take_release_time();
+            if pkt_num < 10 {
+                assert_eq!(release_time, None);
+            } else {
+                assert!(release_time > last_release_time);
+                last_release_time = release_time;
+            }
+        }
+        assert_eq!(recovery.pacer_timer, None);
+
+        // 1200 bytes at 12000 bytes per second
+        let wait = last_release_time.unwrap().duration_since(now);
+        assert!(wait >= Duration::from_millis(900));
+        assert!(wait <= Duration::from_millis(1100));
+
+        Ok(())
+    }
+
     #[test]
     fn calculate_pto() -> Result<()> {
         assert_eq!(
@@ -1601,4 +2857,61 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn calculate_pto_with_backoff_limits() -> Result<()> {
+        let mut conf = new_test_recovery_config();
+        conf.pto_multiplier = 3;
+        conf.max_pto_backoff = 2;
+        let mut recovery = Recovery::new(&conf);
+
+        recovery.pto_count = 1;
+        assert_eq!(recovery.calculate_pto(), Duration::from_millis(2997)); // 999 * 3 ^ 1
+
+        // The backoff stops growing after the limit.
+        for count in [2, 3, 100] {
+            recovery.pto_count = count;
+            assert_eq!(recovery.calculate_pto(), Duration::from_millis(8991)); // 999 * 3 ^ 2
+        }
+
+        // The exponential backoff is disabled.
+        conf.pto_multiplier = 1;
+        let mut recovery = Recovery::new(&conf);
+        recovery.pto_count = 10;
+        assert_eq!(recovery.calculate_pto(), Duration::from_millis(999));
+
+        Ok(())
+    }
+
+    #[test]
+    fn calculate_pto_before_rtt_sample() -> Result<()> {
+        let mut conf = new_test_recovery_config();
+        conf.initial_rtt = Duration::from_millis(100);
+        conf.initial_pto_factor = Some(2.0);
+        let mut recovery = Recovery::new(&conf);
+
+        // The probe timeout is derived from the initial RTT with the factor.
+        assert_eq!(recovery.calculate_pto(), Duration::from_millis(200)); // 100 * 2
+        recovery.pto_count = 1;
+        assert_eq!(recovery.calculate_pto(), Duration::from_millis(400)); // 100 * 2 * 2 ^ 1
+
+        // The factor no longer applies after the first RTT sample is taken.
+        recovery.pto_count = 0;
+        recovery
+            .rtt
+            .update(Duration::ZERO, Duration::from_millis(50));
+        assert_eq!(recovery.calculate_pto(), Duration::from_millis(150)); // 50 + 4 * 25
+
+        // The probe timeout is derived from the initial RTT by default.
+        conf.initial_pto_factor = None;
+        let recovery = Recovery::new(&conf);
+        assert_eq!(recovery.calculate_pto(), Duration::from_millis(300)); // 100 + 4 * 50
+
+        // The probe timeout is capped if the factor overflows.
+        conf.initial_pto_factor = Some(f64::INFINITY);
+        let recovery = Recovery::new(&conf);
+        assert_eq!(recovery.calculate_pto(), conf.max_pto);
+
+        Ok(())
+    }
 }
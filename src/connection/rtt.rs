@@ -76,6 +76,11 @@ impl RttEstimator {
         self.max_rtt
     }
 
+    /// Return true if any RTT sample has been taken.
+    pub fn has_sample(&self) -> bool {
+        self.smoothed_rtt.is_some()
+    }
+
     /// Return the PTO computed as described in RFC 9002 Section 6.2.1
     pub fn pto_base(&self) -> Duration {
         self.smoothed_rtt() + cmp::max(4 * self.rttvar, TIMER_GRANULARITY)
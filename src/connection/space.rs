@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cmp;
+use std::collections::hash_map::Iter;
 use std::collections::hash_map::IterMut;
 use std::collections::VecDeque;
+use std::ops::Range;
 use std::time::Duration;
 use std::time::Instant;
 
@@ -27,6 +30,9 @@ use crate::window::SeqNumWindow;
 
 pub const MAX_NON_ACK_ELICITING: usize = 24;
 
+/// Maximum number of recycled frame vectors kept by a sent packet queue.
+const MAX_RECYCLED_FRAMES: usize = 256;
+
 /// Packet numbers are divided into three spaces in QUIC
 pub const SPACE_COUNT: usize = 3;
 
@@ -86,6 +92,9 @@ pub struct PacketNumSpace {
     /// The time at which the packet of highest sequence number arrived.
     pub largest_rx_pkt_time: Instant,
 
+    /// The ACK delay carried by the latest ACK frame received.
+    pub latest_ack_delay: Duration,
+
     /// Highest received non-probing packet number.
     pub largest_rx_non_probing_pkt_num: u64,
 
@@ -95,6 +104,12 @@ pub struct PacketNumSpace {
     /// The packet numbers to acknowledge.
     pub recv_pkt_num_need_ack: RangeSet,
 
+    /// The counts of received packets with ECN codepoints.
+    pub recv_ecn_counts: frame::EcnCounts,
+
+    /// The largest ECN counts reported by the peer.
+    pub peer_ecn_counts: frame::EcnCounts,
+
     /// The packet number window for deduplicate detection.
     pub recv_pkt_num_win: SeqNumWindow,
 
@@ -109,7 +124,7 @@ pub struct PacketNumSpace {
 
     /// Sent packets metadata for loss recovery and congestion control.
     /// See RFC 9002 Section 9.1
-    pub sent: VecDeque<SentPacket>,
+    pub sent: SentPacketQueue,
 
     /// Lost frames.
     pub lost: Vec<frame::Frame>,
@@ -142,6 +157,12 @@ pub struct PacketNumSpace {
 
     /// Packet number space for application data
     pub is_data: bool,
+
+    /// The latest packet number skipped on purpose.
+    pub skipped_pkt_num: Option<u64>,
+
+    /// The packet number to be skipped next.
+    next_skip_pkt_num: Option<u64>,
 }
 
 impl PacketNumSpace {
@@ -155,14 +176,17 @@ impl PacketNumSpace {
             first_pkt_num_recv: None,
             first_pkt_num_sent: None,
             largest_rx_pkt_time: Instant::now(),
+            latest_ack_delay: Duration::ZERO,
             largest_rx_non_probing_pkt_num: 0,
             largest_rx_ack_eliciting_pkt_num: 0,
             recv_pkt_num_need_ack: RangeSet::new(crate::MAX_ACK_RANGES),
+            recv_ecn_counts: frame::EcnCounts::default(),
+            peer_ecn_counts: frame::EcnCounts::default(),
             recv_pkt_num_win: SeqNumWindow::default(),
             need_send_ack: false,
             ack_eliciting_pkts_since_last_sent_ack: 0,
             ack_timer: None,
-            sent: VecDeque::new(),
+            sent: SentPacketQueue::default(),
             lost: Vec::new(),
             acked: Vec::new(),
             buffered: BufferQueue::default(),
@@ -173,9 +197,32 @@ impl PacketNumSpace {
             bytes_in_flight: 0,
             ack_eliciting_in_flight: 0,
             is_data: id != SpaceId::Initial && id != SpaceId::Handshake,
+            skipped_pkt_num: None,
+            next_skip_pkt_num: None,
         }
     }
 
+    /// Skip the next packet number if it is chosen to be skipped, and choose
+    /// the next one randomly, `interval` packets later on average. An ACK
+    /// frame for a skipped packet number indicates an optimistic ACK attack.
+    /// See RFC 9000 Section 21.4
+    pub fn try_skip_pkt_num(&mut self, interval: u64) {
+        if interval == 0 {
+            return;
+        }
+        match self.next_skip_pkt_num {
+            Some(pkt_num) if pkt_num > self.next_pkt_num => return,
+            Some(pkt_num) if pkt_num == self.next_pkt_num => {
+                self.skipped_pkt_num = Some(pkt_num);
+                self.next_pkt_num += 1;
+            }
+            _ => (),
+        }
+
+        let gap = 1 + rand::random::<u64>() % interval.saturating_mul(2);
+        self.next_skip_pkt_num = Some(self.next_pkt_num.saturating_add(gap));
+    }
+
     /// Return whether the `pkt_num` is duplicated.
     pub fn detect_duplicated_pkt_num(&mut self, pkt_num: u64) -> bool {
         self.recv_pkt_num_win.contains(pkt_num)
@@ -220,6 +267,20 @@ impl PacketNumSpace {
             None
         }
     }
+
+    /// Free the memory held by the packet number space after it is discarded.
+    /// The Initial and Handshake spaces are never used again once their keys
+    /// are discarded, so the sent packets, pending frames and ack ranges could
+    /// be dropped early instead of living as long as the connection.
+    pub fn release(&mut self) {
+        self.sent.release();
+        self.lost = Vec::new();
+        self.acked = Vec::new();
+        self.buffered = BufferQueue::default();
+        self.recv_pkt_num_need_ack = RangeSet::new(crate::MAX_ACK_RANGES);
+        self.need_send_ack = false;
+        self.ack_timer = None;
+    }
 }
 
 /// All packet number spaces on a QUIC connection
@@ -263,6 +324,11 @@ impl PacketNumSpaceMap {
         }
     }
 
+    /// Return an iterator over all spaces.
+    pub fn iter(&self) -> Iter<'_, u64, PacketNumSpace> {
+        self.spaces.iter()
+    }
+
     /// Return a mutable iterator over all spaces.
     pub fn iter_mut(&mut self) -> IterMut<'_, u64, PacketNumSpace> {
         self.spaces.iter_mut()
@@ -381,6 +447,9 @@ pub struct SentPacket {
 
     /// Status about buffered frames written into the packet.
     pub buffer_flags: BufferFlags,
+
+    /// Whether the packet is sent with the ECT(0) codepoint.
+    pub ecn_marked: bool,
 }
 
 impl Default for SentPacket {
@@ -400,6 +469,7 @@ impl Default for SentPacket {
             sent_size: 0,
             rate_sample_state: RateSamplePacketState::default(),
             buffer_flags: BufferFlags::default(),
+            ecn_marked: false,
         }
     }
 }
@@ -414,8 +484,163 @@ impl std::fmt::Debug for SentPacket {
     }
 }
 
+/// Sent packets metadata of a packet number space.
+///
+/// The metadata is stored in a ring of slots indexed by packet number, so a
+/// sent packet can be located by its packet number in constant time and the
+/// slots of retired packets are reused by later packets. The frame vectors of
+/// retired packets are recycled as well, which cuts allocations at high packet
+/// rates.
+#[derive(Default)]
+pub struct SentPacketQueue {
+    /// Slots of sent packets. The packet number of `slots[i]` is `base + i`,
+    /// and the slot is empty if the packet number was skipped or not stored.
+    slots: VecDeque<Option<SentPacket>>,
+
+    /// The packet number of the first slot.
+    base: u64,
+
+    /// Number of sent packets in the queue.
+    len: usize,
+
+    /// Frame vectors of retired packets for reuse.
+    recycled: Vec<Vec<frame::Frame>>,
+}
+
+impl SentPacketQueue {
+    /// Append a sent packet to the queue.
+    ///
+    /// The packet number should be greater than that of any packet in the
+    /// queue. Otherwise, the packet is stored only if its slot is vacant.
+    pub fn push_back(&mut self, pkt: SentPacket) {
+        if self.slots.is_empty() {
+            self.base = pkt.pkt_num;
+        }
+        let idx = match pkt.pkt_num.checked_sub(self.base) {
+            Some(idx) => idx as usize,
+            None => return,
+        };
+
+        if idx >= self.slots.len() {
+            self.slots.resize_with(idx, || None);
+            self.slots.push_back(Some(pkt));
+            self.len += 1;
+        } else if self.slots[idx].is_none() {
+            self.slots[idx] = Some(pkt);
+            self.len += 1;
+        }
+    }
+
+    /// Return a reference to the sent packet with the given packet number.
+    pub fn get(&self, pkt_num: u64) -> Option<&SentPacket> {
+        let idx = pkt_num.checked_sub(self.base)?;
+        self.slots.get(idx as usize)?.as_ref()
+    }
+
+    /// Return a mutable reference to the sent packet with the given packet
+    /// number.
+    pub fn get_mut(&mut self, pkt_num: u64) -> Option<&mut SentPacket> {
+        let idx = pkt_num.checked_sub(self.base)?;
+        self.slots.get_mut(idx as usize)?.as_mut()
+    }
+
+    /// Return the sent packet with the lowest packet number.
+    pub fn front(&self) -> Option<&SentPacket> {
+        self.iter().next()
+    }
+
+    /// Return an iterator over the sent packets in ascending order of packet
+    /// numbers.
+    pub fn iter(&self) -> impl Iterator<Item = &SentPacket> {
+        self.slots.iter().filter_map(|s| s.as_ref())
+    }
+
+    /// Return a mutable iterator over the sent packets in ascending order of
+    /// packet numbers.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut SentPacket> {
+        self.slots.iter_mut().filter_map(|s| s.as_mut())
+    }
+
+    /// Return a mutable iterator over the sent packets whose packet numbers
+    /// are in the given range.
+    pub fn range_mut(&mut self, range: Range<u64>) -> impl Iterator<Item = &mut SentPacket> {
+        let end = self.base + self.slots.len() as u64;
+        let start = range.start.clamp(self.base, end) - self.base;
+        let stop = range.end.clamp(self.base, end) - self.base;
+        let stop = cmp::max(start, stop);
+        self.slots
+            .range_mut(start as usize..stop as usize)
+            .filter_map(|s| s.as_mut())
+    }
+
+    /// Remove sent packets from the front of the queue while the predicate
+    /// returns true.
+    pub fn remove_front_while<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&SentPacket) -> bool,
+    {
+        while let Some(slot) = self.slots.front() {
+            if let Some(pkt) = slot {
+                if !f(pkt) {
+                    break;
+                }
+            }
+            if let Some(Some(pkt)) = self.slots.pop_front() {
+                self.len -= 1;
+                self.recycle_frames(pkt.frames);
+            }
+            self.base += 1;
+        }
+    }
+
+    /// Remove all the sent packets.
+    pub fn clear(&mut self) {
+        while let Some(slot) = self.slots.pop_front() {
+            if let Some(pkt) = slot {
+                self.recycle_frames(pkt.frames);
+            }
+        }
+        self.len = 0;
+    }
+
+    /// Remove all the sent packets and free the memory held by the queue.
+    pub fn release(&mut self) {
+        self.clear();
+        self.slots = VecDeque::new();
+        self.recycled = Vec::new();
+    }
+
+    /// Return an empty frame vector for a new packet, recycled from retired
+    /// packets if possible.
+    pub fn alloc_frames(&mut self) -> Vec<frame::Frame> {
+        self.recycled.pop().unwrap_or_default()
+    }
+
+    /// Keep the frame vector of a retired packet for reuse.
+    fn recycle_frames(&mut self, mut frames: Vec<frame::Frame>) {
+        if frames.capacity() == 0 || self.recycled.len() >= MAX_RECYCLED_FRAMES {
+            return;
+        }
+        frames.clear();
+        self.recycled.push(frames);
+    }
+
+    /// Return the number of sent packets in the queue.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return true if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
 /// Metadata of acknowledged packet
 pub struct AckedPacket {
+    /// The packet type of the sent packet.
+    pub pkt_type: packet::PacketType,
+
     /// The packet number of the sent packet.
     pub pkt_num: u64,
 
@@ -424,6 +649,12 @@ pub struct AckedPacket {
 
     /// The Duration between the time the packet is sent and acknowledged
     pub rtt: Duration,
+
+    /// Whether the packet was sent with the ECT(0) codepoint.
+    pub ecn_marked: bool,
+
+    /// The time the packet was declared lost, if it was spuriously lost.
+    pub time_lost: Option<Instant>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -549,6 +780,36 @@ mod tests {
         assert_eq!(space.detect_duplicated_pkt_num(5), false);
     }
 
+    #[test]
+    fn skip_pkt_num() {
+        let mut space = PacketNumSpace::new(SpaceId::Data);
+
+        // Skipping is disabled.
+        for _ in 0..100 {
+            space.next_pkt_num += 1;
+            space.try_skip_pkt_num(0);
+        }
+        assert_eq!(space.next_pkt_num, 100);
+        assert_eq!(space.skipped_pkt_num, None);
+
+        // About one of every ten packet numbers is skipped.
+        let mut skipped = 0;
+        let mut last_skipped = None;
+        for _ in 0..1000 {
+            space.next_pkt_num += 1;
+            space.try_skip_pkt_num(10);
+            if space.skipped_pkt_num != last_skipped {
+                let pkt_num = space.skipped_pkt_num.unwrap();
+                assert_eq!(space.next_pkt_num, pkt_num + 1);
+                assert!(last_skipped < Some(pkt_num));
+                last_skipped = space.skipped_pkt_num;
+                skipped += 1;
+            }
+        }
+        assert!(skipped > 0);
+        assert_eq!(space.next_pkt_num, 1100 + skipped);
+    }
+
     #[test]
     fn extra_spaces() {
         let mut spaces = PacketNumSpaceMap::default();
@@ -579,7 +840,10 @@ mod tests {
         let sent_pkt = SentPacket {
             pkt_num: 9,
             frames: vec![
-                frame::Frame::Ping { pmtu_probe: None },
+                frame::Frame::Ping {
+                    pmtu_probe: None,
+                    probe: None,
+                },
                 frame::Frame::Paddings { len: 200 },
             ],
             time_sent: Instant::now(),
@@ -598,6 +862,110 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sent_packet_queue() {
+        let mut queue = SentPacketQueue::default();
+        assert_eq!(queue.is_empty(), true);
+        assert!(queue.front().is_none());
+
+        // Packet numbers 3 and 6 are skipped
+        for pkt_num in [1, 2, 4, 5, 7] {
+            queue.push_back(SentPacket {
+                pkt_num,
+                frames: vec![Frame::Ping {
+                    pmtu_probe: None,
+                    probe: None,
+                }],
+                ..SentPacket::default()
+            });
+        }
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.front().unwrap().pkt_num, 1);
+        assert_eq!(queue.get(4).unwrap().pkt_num, 4);
+        assert!(queue.get(0).is_none());
+        assert!(queue.get(3).is_none());
+        assert!(queue.get(8).is_none());
+
+        // Packet numbers should be in ascending order
+        let pkt_nums: Vec<u64> = queue.iter().map(|p| p.pkt_num).collect();
+        assert_eq!(pkt_nums, vec![1, 2, 4, 5, 7]);
+
+        // Look up packets by range
+        for pkt in queue.range_mut(2..6) {
+            pkt.time_acked = Some(Instant::now());
+        }
+        let pkt_nums: Vec<u64> = queue.range_mut(0..100).map(|p| p.pkt_num).collect();
+        assert_eq!(pkt_nums, vec![1, 2, 4, 5, 7]);
+        assert_eq!(queue.range_mut(8..100).count(), 0);
+        assert_eq!(queue.range_mut(6..2).count(), 0);
+        queue.get_mut(1).unwrap().time_lost = Some(Instant::now());
+
+        // Remove packets from the front of the queue
+        queue.remove_front_while(|p| p.time_acked.is_some());
+        assert_eq!(queue.len(), 5);
+        queue.remove_front_while(|p| p.time_acked.is_some() || p.time_lost.is_some());
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.front().unwrap().pkt_num, 7);
+        assert!(queue.get(5).is_none());
+
+        // Frame vectors of the removed packets are recycled
+        let frames = queue.alloc_frames();
+        assert!(frames.is_empty());
+        assert!(frames.capacity() > 0);
+
+        // Push packet after the queue is cleared
+        queue.clear();
+        assert_eq!(queue.is_empty(), true);
+        queue.push_back(SentPacket {
+            pkt_num: 10,
+            ..SentPacket::default()
+        });
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.get(10).unwrap().pkt_num, 10);
+    }
+
+    #[test]
+    fn space_release() {
+        let mut space = PacketNumSpace::new(SpaceId::Handshake);
+        for pkt_num in 0..10 {
+            space.sent.push_back(SentPacket {
+                pkt_num,
+                frames: vec![Frame::Ping {
+                    pmtu_probe: None,
+                    probe: None,
+                }],
+                ..SentPacket::default()
+            });
+        }
+        space.lost.push(Frame::Ping {
+            pmtu_probe: None,
+            probe: None,
+        });
+        space.acked.push(Frame::Ping {
+            pmtu_probe: None,
+            probe: None,
+        });
+        space.buffered.push_back(
+            Frame::Ping {
+                pmtu_probe: None,
+                probe: None,
+            },
+            BufferType::High,
+        );
+        space.recv_pkt_num_need_ack.insert(0..5);
+        space.need_send_ack = true;
+
+        space.release();
+        assert!(space.sent.is_empty());
+        assert_eq!(space.sent.slots.capacity(), 0);
+        assert_eq!(space.sent.recycled.capacity(), 0);
+        assert_eq!(space.lost.capacity(), 0);
+        assert_eq!(space.acked.capacity(), 0);
+        assert!(space.buffered.is_empty());
+        assert!(space.recv_pkt_num_need_ack.is_empty());
+        assert_eq!(space.need_send_ack, false);
+    }
+
     #[test]
     fn buffer_queue() {
         // initial queue
@@ -622,7 +990,10 @@ mod tests {
         assert_eq!(queue.len(), 2);
         assert_eq!(queue.is_empty(), false);
 
-        let f3 = Frame::Ping { pmtu_probe: None };
+        let f3 = Frame::Ping {
+            pmtu_probe: None,
+            probe: None,
+        };
         queue.push_back(f3.clone(), BufferType::Low);
 
         assert_eq!(queue.pop_front(), Some((f2.clone(), BufferType::High)));
@@ -16,12 +16,11 @@
 
 use std::any::Any;
 use std::cmp;
-use std::collections::btree_map;
 use std::collections::hash_map;
 use std::collections::BTreeMap;
-use std::collections::BinaryHeap;
 use std::collections::VecDeque;
 use std::ops::Range;
+use std::sync::Arc;
 use std::time;
 use std::time::Instant;
 
@@ -37,13 +36,19 @@ use rustc_hash::FxHashSet;
 use smallvec::SmallVec;
 
 use self::StreamFlags::*;
+use crate::codec::VINT_MAX;
 use crate::connection::flowcontrol;
+use crate::connection::flowcontrol::WindowTuner;
+use crate::connection::stream_scheduler::StreamScheduler;
 use crate::ranges;
 use crate::Error;
 use crate::Event;
 use crate::EventQueue;
+use crate::LostDataAction;
 use crate::Result;
 use crate::Shutdown;
+use crate::StreamEvent;
+use crate::StreamLossPolicy;
 use crate::TransportParams;
 use crate::MAX_STREAMS_PER_TYPE;
 
@@ -80,8 +85,8 @@ pub struct StreamMap {
     streams: StreamIdHashMap<Stream>,
 
     /// Streams that have outstanding data ready to be sent to the peer,
-    /// and categorized by their urgency, lower value means higher priority.
-    sendable: BTreeMap<u8, StreamPriorityQueue>,
+    /// which are ordered by the stream scheduler.
+    sendable: Box<dyn StreamScheduler>,
 
     /// Streams that have outstanding data can be read by the application.
     readable: StreamIdHashSet,
@@ -128,6 +133,32 @@ pub struct StreamMap {
     /// from the connection configuration, and applies to all streams.
     max_stream_window: u64,
 
+    /// The number of RTTs without a window update after which the receive-side
+    /// flow control windows are halved. Zero means windows never decay.
+    window_decay_rtts: u32,
+
+    /// The floor of the receive-side flow control windows when they decay.
+    /// Zero means the initial windows are used as the floor.
+    window_decay_floor: u64,
+
+    /// The policy of window autotuning, it is inherited from the connection
+    /// configuration, and applies to the connection and all streams.
+    window_tuner: Option<Arc<dyn WindowTuner>>,
+
+    /// Whether the stream receive windows are allocated dynamically from the
+    /// connection receive window.
+    window_sharing: bool,
+
+    /// Total unused credit of the streams which may still receive data from
+    /// the peer.
+    rx_allocated: u64,
+
+    /// Number of the streams which may still receive data from the peer.
+    rx_active_streams: u64,
+
+    /// Initial receive window of new streams seeded by a previous connection.
+    stream_window_seed: u64,
+
     /// Connection received-side flow control capacity almost full,
     /// local endpoint should issue more credit by sending a MAX_DATA
     /// frame to the peer.
@@ -193,6 +224,27 @@ impl StreamMap {
         self.trace_id = trace_id.to_string();
     }
 
+    /// Set whether the stream receive windows are allocated from the
+    /// connection receive window.
+    pub fn set_window_sharing(&mut self, v: bool) {
+        self.window_sharing = v;
+    }
+
+    /// Seed the receive windows of the connection and new streams, e.g. with
+    /// the window estimated by a previous connection to the same peer. The
+    /// windows are never decreased or grown beyond their maximum sizes.
+    pub fn seed_window(&mut self, window: u64) {
+        let conn_window = cmp::min(window, self.flow_control.max_window());
+        self.flow_control.ensure_window_lower_bound(conn_window);
+        self.stream_window_seed = cmp::min(window, self.max_stream_window);
+    }
+
+    /// Set the policy of window autotuning for the connection and streams.
+    pub fn set_window_tuner(&mut self, tuner: Arc<dyn WindowTuner>) {
+        self.flow_control.set_tuner(tuner.clone());
+        self.window_tuner = Some(tuner);
+    }
+
     /// Return a reference to the stream with the given ID if it exists, or `None`.
     fn get(&self, id: u64) -> Option<&Stream> {
         self.streams.get(&id)
@@ -313,7 +365,7 @@ impl StreamMap {
         }
     }
 
-    /// Write data to the stream's send buffer.
+    /// Write data to the stream's send buffer without copying it.
     pub fn stream_write(&mut self, stream_id: u64, mut buf: Bytes, fin: bool) -> Result<usize> {
         // Peer initiated unidirectional streams are receive-only, so we can't write to them.
         if !is_bidi(stream_id) && !is_local(stream_id, self.is_server) {
@@ -515,6 +567,49 @@ impl StreamMap {
         stream.urgency = urgency;
         stream.incremental = incremental;
 
+        // Move the stream to the queue of the new priority if it is already
+        // waiting to be scheduled, so that the change takes effect immediately.
+        if self.unqueue_sendable(stream_id) {
+            self.push_sendable(stream_id, urgency, incremental);
+        }
+
+        Ok(())
+    }
+
+    /// Set the receive-side flow control window and maximum window of a stream.
+    ///
+    /// The window is used to compute the credit issued in MAX_STREAM_DATA
+    /// frames, and it may grow up to `max_window` due to window autotuning.
+    /// Credit that has already been issued to the peer is never withdrawn.
+    pub fn stream_set_recv_window(
+        &mut self,
+        stream_id: u64,
+        window: u64,
+        max_window: u64,
+    ) -> Result<()> {
+        // Local initiated unidirectional streams are send-only.
+        if !is_bidi(stream_id) && is_local(stream_id, self.is_server) {
+            return Err(Error::StreamStateError);
+        }
+
+        // Get or create the stream if it was not created before.
+        let stream = match self.get_or_create(stream_id, true) {
+            Ok(v) => v,
+            // Stream has been closed, just ignore the setting.
+            Err(Error::Done) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        stream
+            .recv
+            .flow_control
+            .set_window(window, cmp::min(max_window, VINT_MAX));
+
+        // Issue more credit to the peer if the window was enlarged.
+        if stream.recv.should_send_max_data() {
+            self.mark_almost_full(stream_id, true);
+        }
+
         Ok(())
     }
 
@@ -612,6 +707,24 @@ impl StreamMap {
         Ok(())
     }
 
+    /// Set the hook which decides whether the lost data of a stream is
+    /// retransmitted.
+    pub fn stream_set_loss_policy(
+        &mut self,
+        stream_id: u64,
+        policy: Option<Arc<dyn StreamLossPolicy>>,
+    ) -> Result<()> {
+        // Get or create the stream if it was not created before.
+        let stream = match self.get_or_create(stream_id, true) {
+            Ok(v) => v,
+            Err(Error::Done) => return Ok(()), // stream closed
+            Err(e) => return Err(e),
+        };
+
+        stream.loss_policy = policy;
+        Ok(())
+    }
+
     /// Return the stream's user context.
     pub fn stream_context(&mut self, stream_id: u64) -> Option<&mut dyn Any> {
         if let Some(s) = self.get_mut(stream_id) {
@@ -701,14 +814,36 @@ impl StreamMap {
                     max_rx_data,
                     self.max_stream_window,
                 );
+                new_stream
+                    .recv
+                    .flow_control
+                    .set_decay_rtts(self.window_decay_rtts);
+                new_stream
+                    .recv
+                    .flow_control
+                    .set_decay_floor(self.window_decay_floor);
                 let trace_id = format!("{}-{}", &self.trace_id, id);
                 new_stream.set_trace_id(&trace_id);
+                if let Some(tuner) = &self.window_tuner {
+                    new_stream.recv.flow_control.set_tuner(tuner.clone());
+                }
+                if self.stream_window_seed > 0 {
+                    new_stream
+                        .recv
+                        .flow_control
+                        .ensure_window_lower_bound(self.stream_window_seed);
+                }
 
                 // Stream might already be writable due to initial flow control credit.
                 if new_stream.is_writable() {
                     self.writable.insert(id);
                 }
 
+                if let Some(credit) = Self::rx_unused_credit(&new_stream) {
+                    self.rx_allocated += credit;
+                    self.rx_active_streams += 1;
+                }
+
                 // Update stream id for next bidirectional/unidirectional stream.
                 if bidi {
                     self.next_stream_id_bidi = cmp::max(self.next_stream_id_bidi, id);
@@ -731,7 +866,33 @@ impl StreamMap {
     /// Return true if we should send `MAX_DATA` frame to peer to update
     /// the connection level flow control limit.
     pub fn need_send_max_data(&self) -> bool {
-        self.rx_almost_full && self.max_rx_data() < self.max_rx_data_next()
+        self.rx_almost_full
+            && !self.flow_control.is_paused()
+            && self.max_rx_data() < self.max_rx_data_next()
+    }
+
+    /// Pause or resume the advertisement of the connection-level flow control
+    /// limit.
+    pub fn set_flow_control_paused(&mut self, paused: bool) {
+        self.flow_control.set_paused(paused);
+        if self.flow_control.should_send_max_data() {
+            self.rx_almost_full = true;
+        }
+    }
+
+    /// Pause or resume the advertisement of the stream-level flow control
+    /// limit.
+    pub fn stream_set_flow_control_paused(&mut self, stream_id: u64, paused: bool) -> Result<()> {
+        let stream = match self.get_mut(stream_id) {
+            Some(v) => v,
+            None => return Err(Error::StreamStateError),
+        };
+
+        stream.recv.set_flow_control_paused(paused);
+        if stream.recv.should_send_max_data() {
+            self.mark_almost_full(stream_id, true);
+        }
+        Ok(())
     }
 
     /// Return true if need to send stream frames.
@@ -754,74 +915,39 @@ impl StreamMap {
     /// not be called to ensure the fairness of the scheduling and avoid the
     /// spurious cycles through the queue.
     fn push_sendable(&mut self, stream_id: u64, urgency: u8, incremental: bool) {
-        // 1.Get priority queue with the given urgency, if it does not exist, create a new one.
-        let queue = match self.sendable.entry(urgency) {
-            btree_map::Entry::Vacant(v) => v.insert(StreamPriorityQueue::default()),
-            btree_map::Entry::Occupied(v) => v.into_mut(),
-        };
-
-        // 2.Push the element to the queue corresponding to the given incremental flag.
-        if !incremental {
-            // Non-incremental streams are scheduled in order of their stream ID.
-            queue.non_incremental.push(cmp::Reverse(stream_id))
-        } else {
-            // Incremental streams are scheduled in a round-robin fashion.
-            queue.incremental.push_back(stream_id)
-        };
+        self.sendable.push(stream_id, urgency, incremental);
     }
 
-    /// Return the first stream ID from the sendable queue with the highest priority.
+    /// Return the stream ID selected by the stream scheduler from the sendable
+    /// queue.
     ///
     /// Note that the caller should call `remove_sendable` to remove the stream from the
     /// queue if it is no longer sendable after sending some of its outstanding data.
     pub fn peek_sendable(&mut self) -> Option<u64> {
-        let queue = match self.sendable.iter_mut().next() {
-            Some((_, queue)) => queue,
-            None => return None,
-        };
+        self.sendable.peek()
+    }
 
-        // 1.Try to get the non-incremental stream with the lowest stream ID.
-        match queue.non_incremental.peek().map(|x| x.0) {
-            Some(stream_id) => Some(stream_id),
-            None => {
-                // 2.Try to get the incremental stream from the front of the queue.
-                // Incremental streams are scheduled in a round-robin fashion, So
-                // we should move the current peeked incremental stream to the end
-                // of the queue.
-                match queue.incremental.pop_front() {
-                    Some(stream_id) => {
-                        queue.incremental.push_back(stream_id);
-                        Some(stream_id)
-                    }
-                    // Should never happen.
-                    None => None,
-                }
-            }
-        }
+    /// Notify the stream scheduler that a STREAM frame carrying `len` bytes
+    /// of the stream data was written.
+    pub fn on_sendable_sent(&mut self, stream_id: u64, len: usize) {
+        self.sendable.on_sent(stream_id, len);
     }
 
-    /// Remove the last peeked stream from the sendable streams queue.
-    pub fn remove_sendable(&mut self) {
-        // Get the first entry which is the queue with the highest priority.
-        let mut entry = match self.sendable.first_entry() {
-            Some(entry) => entry,
-            // Should never happen, as `peek_sendable()` must be called priorly.
-            None => return,
-        };
+    /// Remove the stream from the sendable streams queue.
+    pub fn remove_sendable(&mut self, stream_id: u64) {
+        self.sendable.remove(stream_id);
+    }
 
-        let queue = entry.get_mut();
-        queue
-            .non_incremental
-            .pop()
-            .map(|x| x.0)
-            .or_else(|| queue.incremental.pop_back());
-
-        // Remove the queue from the queues list if it is empty at present time,
-        // so that the next time `peek_sendable()` is invoked, the next non-empty
-        // queue is selected.
-        if queue.non_incremental.is_empty() && queue.incremental.is_empty() {
-            entry.remove();
-        }
+    /// Remove the given stream ID from the sendable queue, wherever it is
+    /// queued. Return true if the stream was queued.
+    fn unqueue_sendable(&mut self, stream_id: u64) -> bool {
+        self.sendable.remove(stream_id)
+    }
+
+    /// Set the stream scheduler. It should be called before any stream
+    /// becomes sendable.
+    pub fn set_scheduler(&mut self, scheduler: Box<dyn StreamScheduler>) {
+        self.sendable = scheduler;
     }
 
     /// Add or remove the stream ID to/from the `readable` streams set.
@@ -929,7 +1055,40 @@ impl StreamMap {
 
     /// Destroy the closed stream.
     pub(crate) fn stream_destroy(&mut self, stream_id: u64) {
-        self.streams.remove(&stream_id);
+        if let Some(stream) = self.streams.remove(&stream_id) {
+            if let Some(credit) = Self::rx_unused_credit(&stream) {
+                self.rx_allocated = self.rx_allocated.saturating_sub(credit);
+                self.rx_active_streams = self.rx_active_streams.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Return the unused credit of the stream, or `None` if the stream will
+    /// not receive any more data from the peer.
+    fn rx_unused_credit(stream: &Stream) -> Option<u64> {
+        if (stream.local && !stream.bidi) || stream.recv.fin_off.is_some() {
+            return None;
+        }
+        let fc = &stream.recv.flow_control;
+        Some(fc.max_data().saturating_sub(fc.recv_off()))
+    }
+
+    /// Update the total unused credit of the streams after the receive-side
+    /// state of the stream is changed, given its unused credit before the
+    /// change.
+    fn update_rx_allocated(&mut self, stream_id: u64, before: Option<u64>) {
+        if let Some(credit) = before {
+            self.rx_allocated = self.rx_allocated.saturating_sub(credit);
+            self.rx_active_streams = self.rx_active_streams.saturating_sub(1);
+        }
+        if let Some(credit) = self
+            .streams
+            .get(&stream_id)
+            .and_then(Self::rx_unused_credit)
+        {
+            self.rx_allocated += credit;
+            self.rx_active_streams += 1;
+        }
     }
 
     /// Get the maximum streams that the peer allows the local endpoint to open.
@@ -942,6 +1101,19 @@ impl StreamMap {
         self.concurrency_control.update_local_max_streams(bidi);
     }
 
+    /// Set the ceiling of the number of concurrent streams that the peer is
+    /// allowed to open when auto-tuning the max streams limit.
+    pub fn set_max_concurrent_streams(&mut self, bidi: bool, v: u64) {
+        self.concurrency_control.set_max_concurrency(bidi, v);
+    }
+
+    /// Autotune the max streams limit before sending a MAX_STREAMS(type:
+    /// 0x12..0x13) frame to the peer.
+    pub fn autotune_max_streams(&mut self, bidi: bool, now: Instant, srtt: time::Duration) {
+        self.concurrency_control
+            .autotune_max_streams(bidi, now, srtt);
+    }
+
     /// Get the maximum streams that the local endpoint allow the peer to open.
     pub fn max_streams(&self, bidi: bool) -> u64 {
         match bidi {
@@ -1052,6 +1224,33 @@ impl StreamMap {
         }
     }
 
+    /// Set want write flag for a stream with a writable threshold. The stream
+    /// is reported writable only when at least `bytes` bytes of send credit is
+    /// available. `0` removes the threshold.
+    ///
+    /// Return `Error::Done` if the stream is not found.
+    pub fn want_write_threshold(&mut self, stream_id: u64, bytes: usize) -> Result<()> {
+        match self.get_mut(stream_id) {
+            Some(stream) => {
+                stream.mark_wantwrite(true)?;
+                stream.want_write_thresh = bytes;
+                Ok(())
+            }
+            None => Err(Error::Done),
+        }
+    }
+
+    /// Return the writable threshold set by the application for a stream, or
+    /// `None` if it is not set or the stream was stopped by peer.
+    pub fn write_threshold(&self, stream_id: u64) -> Option<usize> {
+        match self.get(stream_id) {
+            Some(stream) if stream.want_write_thresh > 0 && stream.send.capacity().is_ok() => {
+                Some(stream.want_write_thresh)
+            }
+            _ => None,
+        }
+    }
+
     /// Set want read flag for a stream.
     ///
     /// Return `Error::Done` if the stream is not found.
@@ -1062,6 +1261,63 @@ impl StreamMap {
         }
     }
 
+    /// Set want reset flag for a stream, so that application is notified when
+    /// the stream is reset by peer.
+    ///
+    /// Return `Error::Done` if the stream is not found.
+    pub fn want_reset(&mut self, stream_id: u64, want: bool) -> Result<()> {
+        match self.get_mut(stream_id) {
+            Some(stream) => stream.mark_wantreset(want),
+            None => Err(Error::Done),
+        }
+    }
+
+    /// Set want stop sending flag for a stream, so that application is
+    /// notified when the peer asks to stop sending on the stream.
+    ///
+    /// Return `Error::Done` if the stream is not found.
+    pub fn want_stop_sending(&mut self, stream_id: u64, want: bool) -> Result<()> {
+        match self.get_mut(stream_id) {
+            Some(stream) => stream.mark_wantstopsending(want),
+            None => Err(Error::Done),
+        }
+    }
+
+    /// Set the offset up to which the sent data should be acknowledged before
+    /// application is notified. `0` removes the subscription. If the data is
+    /// already acknowledged, application is notified immediately.
+    ///
+    /// Return `Error::Done` if the stream is not found.
+    pub fn want_acked(&mut self, stream_id: u64, offset: u64) -> Result<()> {
+        let stream = match self.streams.get_mut(&stream_id) {
+            Some(v) => v,
+            None => return Err(Error::Done),
+        };
+
+        if !stream.bidi && !stream.local {
+            return Err(Error::InternalError);
+        }
+
+        stream.want_acked_off = offset;
+        Self::check_acked(&mut self.events, stream_id, stream);
+        Ok(())
+    }
+
+    /// Notify application if the sent data of the stream is acknowledged up
+    /// to the subscribed offset.
+    fn check_acked(events: &mut EventQueue, stream_id: u64, stream: &mut Stream) {
+        let ack_off = stream.send.ack_off();
+        if stream.want_acked_off == 0 || ack_off < stream.want_acked_off {
+            return;
+        }
+
+        stream.want_acked_off = 0;
+        events.add(Event::StreamNotified(
+            stream_id,
+            StreamEvent::Acked { offset: ack_off },
+        ));
+    }
+
     /// Return true if application wants to write more data to the stream
     /// and it has enough flow control capacity to do so.
     ///
@@ -1081,7 +1337,8 @@ impl StreamMap {
                 Err(_) => return true,
             };
 
-            if cmp::min(self.send_capacity.capacity, capacity) >= stream.write_thresh {
+            let thresh = cmp::max(stream.write_thresh, stream.want_write_thresh);
+            if cmp::min(self.send_capacity.capacity, capacity) >= thresh {
                 return true;
             }
         }
@@ -1141,6 +1398,17 @@ impl StreamMap {
         true
     }
 
+    /// Return true if sending is blocked by the connection-level flow control,
+    /// or the stream-level flow control of any stream.
+    pub fn is_send_blocked(&self) -> bool {
+        if self.send_capacity.capacity == 0 {
+            return true;
+        }
+        self.streams
+            .values()
+            .any(|s| s.send.blocked_at() == Some(s.send.max_data()))
+    }
+
     /// Return true if there are any streams that have buffered data to send.
     fn has_sendable_streams(&self) -> bool {
         !self.sendable.is_empty()
@@ -1356,6 +1624,7 @@ impl StreamMap {
         // the received data as consumed, which might trigger a connection-level
         // flow control update.
         let max_fc_off_delta = final_size.saturating_sub(stream.recv.read_off());
+        let rx_credit = Self::rx_unused_credit(stream);
 
         let max_rx_off_delta = stream.recv.reset(error_code, final_size)? as u64;
 
@@ -1366,11 +1635,23 @@ impl StreamMap {
         let is_readable = stream.is_readable();
         let is_complete = stream.is_complete();
         let local = stream.local;
+        let want_reset = stream.is_wantreset();
+        if want_reset {
+            stream.flags.remove(WantReset);
+        }
+        self.update_rx_allocated(stream_id, rx_credit);
 
         if !was_readable && is_readable {
             self.mark_readable(stream_id, true);
         }
 
+        if want_reset {
+            self.events.add(Event::StreamNotified(
+                stream_id,
+                StreamEvent::Reset { error_code },
+            ));
+        }
+
         // Mark closed if the stream is complete and not readable.
         if is_complete && !is_readable {
             self.mark_closed(stream_id, local);
@@ -1427,6 +1708,10 @@ impl StreamMap {
         }
 
         let was_writable = stream.is_writable();
+        let want_stop_sending = stream.is_wantstopsending();
+        if want_stop_sending {
+            stream.flags.remove(WantStopSending);
+        }
 
         if let Ok((final_size, unsent)) = stream.send.stop(error_code) {
             // Claw back some flow control allowance from data that was
@@ -1452,6 +1737,13 @@ impl StreamMap {
                 self.mark_writable(stream_id, true);
             }
         }
+
+        if want_stop_sending {
+            self.events.add(Event::StreamNotified(
+                stream_id,
+                StreamEvent::StopSending { error_code },
+            ));
+        }
         Ok(())
     }
 
@@ -1460,6 +1752,37 @@ impl StreamMap {
         self.flow_control.autotune_window(now, srtt);
     }
 
+    /// Set the number of RTTs without a window update after which the
+    /// receive-side flow control windows of the connection and its streams
+    /// are halved. Zero disables window decay.
+    pub fn set_window_decay_rtts(&mut self, v: u32) {
+        self.window_decay_rtts = v;
+        self.flow_control.set_decay_rtts(v);
+        for stream in self.streams.values_mut() {
+            stream.recv.flow_control.set_decay_rtts(v);
+        }
+    }
+
+    /// Set the floor of the receive-side flow control windows of the
+    /// connection and its streams when they decay. Zero means the initial
+    /// windows are used as the floor.
+    pub fn set_window_decay_floor(&mut self, v: u64) {
+        self.window_decay_floor = v;
+        self.flow_control.set_decay_floor(v);
+        for stream in self.streams.values_mut() {
+            stream.recv.flow_control.set_decay_floor(v);
+        }
+    }
+
+    /// Let the receive-side flow control windows of the connection and the
+    /// given stream decay if they have not been updated for a while.
+    pub fn decay_window(&mut self, stream_id: Option<u64>, now: Instant, srtt: time::Duration) {
+        self.flow_control.decay_window(now, srtt);
+        if let Some(stream) = stream_id.and_then(|id| self.streams.get_mut(&id)) {
+            stream.recv.flow_control.decay_window(now, srtt);
+        }
+    }
+
     /// Get the connection's receive-side flow control limit.
     pub fn max_rx_data(&self) -> u64 {
         self.flow_control.max_data()
@@ -1476,6 +1799,63 @@ impl StreamMap {
         self.flow_control.update_max_data(now);
     }
 
+    /// Get the next max_data limit of the stream, which will be sent to the
+    /// peer in a MAX_STREAM_DATA frame. Return `None` if the stream does not
+    /// exist, or if the windows are shared and no new credit can be allocated
+    /// to it.
+    ///
+    /// If the windows are shared, the new credit of the stream is allocated
+    /// from the connection credit that has not been allocated to other streams,
+    /// and a fair share of the connection window is always guaranteed to avoid
+    /// starvation. Otherwise, each stream advertises its full window.
+    pub fn stream_max_data_next(&self, stream_id: u64) -> Option<u64> {
+        let stream = self.get(stream_id)?;
+        let fc = &stream.recv.flow_control;
+        if !self.window_sharing {
+            return Some(fc.max_data_next());
+        }
+
+        // The unused credits of the other streams which may still receive data
+        // from the peer.
+        let own_credit = Self::rx_unused_credit(stream).unwrap_or(0);
+        let allocated = self.rx_allocated.saturating_sub(own_credit);
+        let active = self.rx_active_streams;
+
+        let conn_credit = self
+            .flow_control
+            .max_data_next()
+            .saturating_sub(self.flow_control.recv_off());
+        let fair_share = self.flow_control.window() / cmp::max(active, 1);
+        let credit = cmp::max(conn_credit.saturating_sub(allocated), fair_share);
+        let max_data = cmp::min(fc.max_data_next(), fc.recv_off().saturating_add(credit));
+        Some(max_data).filter(|&v| v > fc.max_data())
+    }
+
+    /// Apply the new flow control limit of the stream.
+    pub fn update_stream_max_data(&mut self, stream_id: u64, max_data: u64, now: Instant) {
+        if let Some(stream) = self.get_mut(stream_id) {
+            let rx_credit = Self::rx_unused_credit(stream);
+            stream.recv.update_max_data_to(max_data, now);
+            self.update_rx_allocated(stream_id, rx_credit);
+        }
+    }
+
+    /// Get the connection's receive-side flow control window.
+    pub fn window(&self) -> u64 {
+        self.flow_control.window()
+    }
+
+    /// Get the connection's receive-side credit which has been advertised to
+    /// the peer but not consumed yet.
+    pub fn advertised_window(&self) -> u64 {
+        self.flow_control.advertised_window()
+    }
+
+    /// Limit the connection's receive-side flow control window size.
+    pub fn limit_window(&mut self, max_window: u64) {
+        self.flow_control.limit_window(max_window);
+    }
+
     /// Ensure that the connection flow control window always has some room
     /// compared to the stream flow control window.
     pub fn ensure_window_lower_bound(&mut self, min_window: u64) {
@@ -1565,11 +1945,14 @@ impl StreamMap {
 
         let was_readable = stream.is_readable();
         let was_draining = stream.is_draining();
+        let rx_credit = Self::rx_unused_credit(stream);
 
         // Insert the new data into the stream's receive buffer.
         stream.recv.write(offset, data, fin)?;
 
-        if !was_readable && stream.is_readable() {
+        let is_readable = stream.is_readable();
+        self.update_rx_allocated(stream_id, rx_credit);
+        if !was_readable && is_readable {
             self.mark_readable(stream_id, true);
         }
 
@@ -1597,6 +1980,7 @@ impl StreamMap {
         };
 
         stream.send.ack_and_drop(offset, length);
+        Self::check_acked(&mut self.events, stream_id, stream);
 
         // Mark closed if the stream is complete and not readable.
         if stream.is_complete() && !stream.is_readable() {
@@ -1633,6 +2017,22 @@ impl StreamMap {
         let was_sendable = stream.is_sendable();
         let empty_fin = length == 0 && fin;
 
+        // The application may decide to abandon the lost data.
+        if let Some(policy) = stream.loss_policy.clone() {
+            if let LostDataAction::Reset(err) = policy.on_data_lost(stream_id, offset, length) {
+                trace!(
+                    "{} stream {} lost data {}+{} is not retransmitted",
+                    self.trace_id,
+                    stream_id,
+                    offset,
+                    length
+                );
+                // The send-side may have been shutdown already.
+                let _ = self.stream_shutdown(stream_id, Shutdown::Write, err);
+                return;
+            }
+        }
+
         // Mark data block should be retransmitted.
         stream.send.retransmit(offset, length);
 
@@ -1723,6 +2123,40 @@ impl StreamMap {
         }
     }
 
+    /// Return the estimated memory in bytes used by the streams, including the
+    /// buffered data to be sent or read.
+    pub fn memory_usage(&self) -> usize {
+        self.streams
+            .values()
+            .map(|s| std::mem::size_of::<Stream>() + s.send.unacked_len + s.recv.buffered_len())
+            .sum()
+    }
+
+    /// Return the total length of the stream data ready to be sent. It stops
+    /// counting once the length reaches the given limit.
+    pub fn sendable_len(&self, limit: usize) -> usize {
+        let mut len = 0;
+        for id in self.sendable.iter() {
+            if let Some(stream) = self.streams.get(&id) {
+                len += stream.send.sendable_len();
+            }
+            if len >= limit {
+                return len;
+            }
+        }
+        len
+    }
+
+    /// Return true if any sendable stream has lost data to retransmit or has
+    /// its final size known, so that its data should not be held back.
+    pub fn has_urgent_sendable(&self) -> bool {
+        self.sendable.iter().any(|id| {
+            self.streams
+                .get(&id)
+                .is_some_and(|s| s.send.is_retransmitting() || s.send.fin_off.is_some())
+        })
+    }
+
     /// Get the number of active streams in the map.
     #[cfg(test)]
     pub fn len(&self) -> usize {
@@ -1759,6 +2193,12 @@ enum StreamFlags {
 
     /// The stream has been closed and is waiting to release its resources.
     Closed = 1 << 2,
+
+    /// Upper layer want to be notified when the stream is reset by peer.
+    WantReset = 1 << 3,
+
+    /// Upper layer want to be notified when the peer asks to stop sending.
+    WantStopSending = 1 << 4,
 }
 
 #[derive(Default)]
@@ -1806,12 +2246,24 @@ pub struct Stream {
     //  has enough capacity before sending headers.
     pub write_thresh: usize,
 
+    /// Application is notified that the stream is writable only when the send
+    /// credit, including the flow control credit and the congestion window, is
+    /// not less than this value. `0` means no such threshold.
+    pub want_write_thresh: usize,
+
+    /// Application is notified once the sent data is acknowledged contiguously
+    /// up to this offset. `0` means no such subscription.
+    pub want_acked_off: u64,
+
     /// Various stream states.
     flags: BitFlags<StreamFlags>,
 
     /// For holding Application context.
     pub context: Option<Box<dyn Any + Send + Sync>>,
 
+    /// Application hook for the lost data of the stream.
+    pub loss_policy: Option<Arc<dyn StreamLossPolicy>>,
+
     /// Unique trace id for debug logging.
     trace_id: String,
 }
@@ -1863,8 +2315,11 @@ impl Stream {
             recv: RecvBuf::new(max_rx_data, max_window),
             send: SendBuf::new(max_tx_data),
             write_thresh: 1,
+            want_write_thresh: 0,
+            want_acked_off: 0,
             flags,
             context: None,
+            loss_policy: None,
             trace_id: String::new(),
         }
     }
@@ -1958,6 +2413,48 @@ impl Stream {
         Ok(())
     }
 
+    /// Check whether the stream is WantReset
+    pub fn is_wantreset(&self) -> bool {
+        self.flags.contains(WantReset)
+    }
+
+    /// Mark the stream as WantReset or not.
+    ///
+    /// Return error if the stream is not bidi and not remote uni stream.
+    pub fn mark_wantreset(&mut self, flag: bool) -> Result<()> {
+        if !self.bidi && self.local {
+            return Err(Error::InternalError);
+        }
+
+        match flag {
+            true => self.flags.insert(WantReset),
+            false => self.flags.remove(WantReset),
+        };
+
+        Ok(())
+    }
+
+    /// Check whether the stream is WantStopSending
+    pub fn is_wantstopsending(&self) -> bool {
+        self.flags.contains(WantStopSending)
+    }
+
+    /// Mark the stream as WantStopSending or not.
+    ///
+    /// Return error if the stream is not bidi and not local uni stream.
+    pub fn mark_wantstopsending(&mut self, flag: bool) -> Result<()> {
+        if !self.bidi && !self.local {
+            return Err(Error::InternalError);
+        }
+
+        match flag {
+            true => self.flags.insert(WantStopSending),
+            false => self.flags.remove(WantStopSending),
+        };
+
+        Ok(())
+    }
+
     /// Check whether the stream is closed.
     pub fn is_closed(&self) -> bool {
         self.flags.contains(Closed)
@@ -2122,6 +2619,16 @@ impl RecvBuf {
             }
         }
 
+        // The newly received data is beyond all the buffered segments, which
+        // is the common case, store it directly as a new segment.
+        if buf.off() >= self.recv_off && !buf.is_empty() {
+            self.recv_off = buf.max_off();
+            if !self.shutdown {
+                self.data.insert(buf.max_off(), buf);
+            }
+            return Ok(());
+        }
+
         // The newly received data may overlap with existing data blocks, and
         // it may be split into multiple segments before being stored.
         let mut tmp_bufs = VecDeque::with_capacity(2);
@@ -2241,6 +2748,11 @@ impl RecvBuf {
         Ok((len, self.is_fin()))
     }
 
+    /// Return the number of bytes buffered but not yet read by the application.
+    fn buffered_len(&self) -> usize {
+        self.data.values().map(|b| b.len()).sum()
+    }
+
     /// Return true if the stream has buffered data to be read or an error to
     /// be collected.
     fn ready(&self) -> bool {
@@ -2329,6 +2841,11 @@ impl RecvBuf {
         self.flow_control.max_data_next()
     }
 
+    /// Apply the given local flow control limit, which is never decreased.
+    pub fn update_max_data_to(&mut self, max_data: u64, now: time::Instant) {
+        self.flow_control.update_max_data_to(max_data, now);
+    }
+
     /// Get the local current flow control limit.
     fn max_data(&self) -> u64 {
         self.flow_control.max_data()
@@ -2344,8 +2861,18 @@ impl RecvBuf {
         self.flow_control.autotune_window(now, srtt);
     }
 
+    /// Pause or resume the advertisement of the local flow control limit.
+    pub fn set_flow_control_paused(&mut self, paused: bool) {
+        self.flow_control.set_paused(paused);
+    }
+
+    /// Return true if the advertisement of the local flow control limit is paused.
+    pub fn is_flow_control_paused(&self) -> bool {
+        self.flow_control.is_paused()
+    }
+
     /// Get the lowest data offset that has yet to be read by the application.
-    fn read_off(&self) -> u64 {
+    pub fn read_off(&self) -> u64 {
         self.read_off
     }
 
@@ -2443,6 +2970,9 @@ impl SendBuf {
 
     /// Insert data at the end of the buffer.
     /// Return the number of bytes that actually got written.
+    ///
+    /// The data is not copied, the buffer is split into chunks referring to
+    /// it, which are dropped once they are acknowledged.
     pub fn write(&mut self, mut data: Bytes, mut fin: bool) -> Result<usize> {
         let max_off = self.write_off + data.len() as u64;
 
@@ -2606,6 +3136,13 @@ impl SendBuf {
         Ok((out.len() - cap, fin))
     }
 
+    /// Return the length of data that is ready to be sent and allowed by
+    /// flow control.
+    fn sendable_len(&self) -> usize {
+        let end = cmp::min(self.write_off, self.max_data);
+        end.saturating_sub(self.send_off()) as usize
+    }
+
     /// Return true if there is data to be sent.
     ///
     /// There may be some data inflight that has been sent but not yet acknowledged,
@@ -2805,6 +3342,43 @@ impl SendBuf {
         self.acked.filter(range)
     }
 
+    /// Copy the buffered data starting at the given offset into the output
+    /// buffer, without changing the sending state.
+    ///
+    /// It is used for rebuilding a stream frame from the data that has been
+    /// sent but not yet acknowledged, so the data of inflight stream frames
+    /// need not be retained elsewhere.
+    ///
+    /// Return the number of bytes copied, which is less than the length of
+    /// `out` if the data is not available in the buffer.
+    /// Return `Done` if `out` is empty or no data is available at `off`.
+    pub fn read_at(&self, mut off: u64, out: &mut [u8]) -> Result<usize> {
+        if out.is_empty() {
+            return Err(Error::Done);
+        }
+
+        let mut len = 0;
+        let mut i = self.data.partition_point(|b| b.max_off() <= off);
+        while len < out.len() {
+            let segment = match self.data.get(i) {
+                Some(v) if v.off() <= off => v,
+                _ => break,
+            };
+
+            let start = (off - segment.off()) as usize;
+            let n = cmp::min(segment.len() - start, out.len() - len);
+            out[len..len + n].copy_from_slice(&segment[start..start + n]);
+            len += n;
+            off += n as u64;
+            i += 1;
+        }
+
+        if len == 0 {
+            return Err(Error::Done);
+        }
+        Ok(len)
+    }
+
     /// Reset the stream at the current offset and clean up the cached data.
     ///
     /// Upon receiving a STOP_SENDING frame from peer, or actively shutting down
@@ -2882,6 +3456,11 @@ impl SendBuf {
         self.error.is_some()
     }
 
+    /// Return true if the data to be sent next is a retransmission.
+    pub fn is_retransmitting(&self) -> bool {
+        !self.retransmits.is_empty()
+    }
+
     /// Get the lowest offset of data to be sent.
     pub fn send_off(&self) -> u64 {
         // retransmits.min little than unsent_off, always.
@@ -3095,6 +3674,28 @@ struct ConcurrencyControl {
 
     /// Available stream ids for local initiated unidirectional streams.
     local_uni_avail_ids: ranges::RangeSet,
+
+    /// The number of bidirectional streams that the peer is allowed to open
+    /// concurrently, which may be auto-tuned up to `max_concurrency_bidi`.
+    concurrency_bidi: u64,
+
+    /// The number of unidirectional streams that the peer is allowed to open
+    /// concurrently, which may be auto-tuned up to `max_concurrency_uni`.
+    concurrency_uni: u64,
+
+    /// Ceiling of the auto-tuned concurrency for bidirectional streams.
+    max_concurrency_bidi: u64,
+
+    /// Ceiling of the auto-tuned concurrency for unidirectional streams.
+    max_concurrency_uni: u64,
+
+    /// Timestamp of the last update of the max_streams limit for bidirectional
+    /// streams.
+    max_streams_bidi_updated: Option<Instant>,
+
+    /// Timestamp of the last update of the max_streams limit for unidirectional
+    /// streams.
+    max_streams_uni_updated: Option<Instant>,
 }
 
 impl ConcurrencyControl {
@@ -3111,6 +3712,10 @@ impl ConcurrencyControl {
             local_max_streams_uni_next: local_max_streams_uni,
             peer_bidi_avail_ids,
             peer_uni_avail_ids,
+            concurrency_bidi: local_max_streams_bidi,
+            concurrency_uni: local_max_streams_uni,
+            max_concurrency_bidi: local_max_streams_bidi,
+            max_concurrency_uni: local_max_streams_uni,
             ..ConcurrencyControl::default()
         }
     }
@@ -3216,6 +3821,56 @@ impl ConcurrencyControl {
         }
     }
 
+    /// Set the ceiling of the auto-tuned concurrency. It is not less than the
+    /// current concurrency.
+    fn set_max_concurrency(&mut self, bidi: bool, v: u64) {
+        let v = cmp::min(v, MAX_STREAMS_PER_TYPE);
+        match bidi {
+            true => self.max_concurrency_bidi = cmp::max(v, self.concurrency_bidi),
+            false => self.max_concurrency_uni = cmp::max(v, self.concurrency_uni),
+        }
+    }
+
+    /// Adjust the concurrency automatically before sending a MAX_STREAMS(type:
+    /// 0x12..0x13) frame to the peer.
+    ///
+    /// Similar to the flow control window autotuning, if the last update of the
+    /// max_streams limit is within 2 * srtt, the peer is consuming the stream
+    /// credits too quickly, so the concurrency is doubled but not exceeding the
+    /// ceiling.
+    fn autotune_max_streams(&mut self, bidi: bool, now: Instant, srtt: time::Duration) {
+        let (concurrency, max_concurrency, last_updated, next) = match bidi {
+            true => (
+                &mut self.concurrency_bidi,
+                self.max_concurrency_bidi,
+                &mut self.max_streams_bidi_updated,
+                &mut self.local_max_streams_bidi_next,
+            ),
+            false => (
+                &mut self.concurrency_uni,
+                self.max_concurrency_uni,
+                &mut self.max_streams_uni_updated,
+                &mut self.local_max_streams_uni_next,
+            ),
+        };
+
+        if let Some(last_updated) = *last_updated {
+            if now.saturating_duration_since(last_updated) < srtt * 2
+                && *concurrency < max_concurrency
+            {
+                // The max streams limit must not exceed 2^60.
+                // See RFC 9000 Section 4.6
+                let new_concurrency = cmp::min(concurrency.saturating_mul(2), max_concurrency);
+                *next = cmp::min(
+                    next.saturating_add(new_concurrency - *concurrency),
+                    MAX_STREAMS_PER_TYPE,
+                );
+                *concurrency = new_concurrency;
+            }
+        }
+        *last_updated = Some(now);
+    }
+
     /// Update connection concurrency control blocked state.
     fn update_streams_blocked_at(&mut self, bidi: bool, blocket_at: Option<u64>) {
         match bidi {
@@ -3383,30 +4038,13 @@ impl SendCapacity {
     }
 }
 
-/// Stream priority queue
-///
-/// Streams are categorized based on their urgency, where each urgency level
-/// has two queues, including non-incremental and incremental streams.
-///
-/// Streams with lower urgency level are scheduled first, and within the
-/// same urgency level non-incremental streams are scheduled before incremental
-/// streams.
-///
-/// Non-incremental streams are scheduled in the order of their stream IDs.
-/// Incremental streams are scheduled in a round-robin fashion.
-#[derive(Debug, Default)]
-struct StreamPriorityQueue {
-    /// Non-incremental streams.
-    non_incremental: BinaryHeap<std::cmp::Reverse<u64>>,
-    /// Incremental streams.
-    incremental: VecDeque<u64>,
-}
-
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
     use super::*;
+    use crate::connection::stream_scheduler::build_stream_scheduler;
+    use crate::StreamSchedulingPolicy;
 
     // StreamMap unit tests
     #[test]
@@ -3717,6 +4355,85 @@ mod tests {
         assert!(map.stream_set_priority(0, 1, true).is_ok());
     }
 
+    // Test StreamMap::stream_set_priority on queued streams
+    #[test]
+    fn stream_set_priority_requeue() {
+        let peer_tp = StreamTransportParams {
+            initial_max_streams_bidi: 2,
+            ..StreamTransportParams::default()
+        };
+
+        // Create a client StreamMap.
+        let mut map = StreamMap::new(false, 50, 50, StreamTransportParams::default());
+        map.update_peer_stream_transport_params(peer_tp);
+        assert!(map.stream_set_priority(0, 3, false).is_ok());
+        assert!(map.stream_set_priority(4, 3, false).is_ok());
+        map.push_sendable(0, 3, false);
+        map.push_sendable(4, 3, false);
+        assert_eq!(map.peek_sendable(), Some(0));
+
+        // 1. Raise the priority of a queued stream.
+        assert!(map.stream_set_priority(4, 1, true).is_ok());
+        assert_eq!(map.peek_sendable(), Some(4));
+
+        // 2. Lower the priority of a queued stream.
+        assert!(map.stream_set_priority(4, 5, false).is_ok());
+        assert_eq!(map.peek_sendable(), Some(0));
+        map.remove_sendable(0);
+        assert_eq!(map.peek_sendable(), Some(4));
+        map.remove_sendable(4);
+        assert!(!map.has_sendable_streams());
+
+        // 3. Change the priority of a stream which is not queued.
+        assert!(map.stream_set_priority(0, 2, true).is_ok());
+        assert!(!map.has_sendable_streams());
+    }
+
+    // Test StreamMap::stream_set_recv_window
+    #[test]
+    fn stream_set_recv_window() {
+        let local_tp = StreamTransportParams {
+            initial_max_data: 100,
+            initial_max_stream_data_bidi_local: 10,
+            ..StreamTransportParams::default()
+        };
+        let peer_tp = StreamTransportParams {
+            initial_max_streams_bidi: 2,
+            initial_max_streams_uni: 1,
+            ..StreamTransportParams::default()
+        };
+
+        // Create a client StreamMap.
+        let mut map = StreamMap::new(false, 1000, 1000, local_tp);
+        map.update_peer_stream_transport_params(peer_tp);
+
+        // 1. Set window on an invalid or a send-only stream.
+        assert_eq!(
+            map.stream_set_recv_window(1, 100, 100),
+            Err(Error::StreamStateError)
+        );
+        assert_eq!(
+            map.stream_set_recv_window(2, 100, 100),
+            Err(Error::StreamStateError)
+        );
+
+        // 2. Enlarge the window of a not created stream.
+        assert!(map.stream_set_recv_window(0, 100, 200).is_ok());
+        let stream = map.get(0).unwrap();
+        assert_eq!(stream.recv.window(), 100);
+        assert_eq!(stream.recv.max_data(), 10);
+        assert!(map.almost_full.contains(&0));
+
+        // 3. Shrink the window of another stream, no credit is issued.
+        assert!(map.stream_set_recv_window(4, 5, 5).is_ok());
+        assert_eq!(map.get(4).unwrap().recv.window(), 5);
+        assert!(!map.almost_full.contains(&4));
+
+        // 4. Set window on a closed(0, simulation, not true) stream.
+        map.mark_closed(0, true);
+        assert!(map.stream_set_recv_window(0, 100, 100).is_ok());
+    }
+
     // Test StreamMap::stream_shutdown
     #[test]
     fn stream_shutdown_invalid_direction() {
@@ -4181,38 +4898,38 @@ mod tests {
         assert!(map.has_sendable_streams());
         assert_eq!(map.peek_sendable(), Some(4));
         assert_eq!(map.peek_sendable(), Some(4));
-        map.remove_sendable();
+        map.remove_sendable(4);
 
         // 2.Streams with lower urgency level are scheduled first.
         map.push_sendable(4, 2, false);
         map.push_sendable(8, 3, false);
         map.push_sendable(12, 1, false);
         assert_eq!(map.peek_sendable(), Some(12));
-        map.remove_sendable();
+        map.remove_sendable(12);
         assert_eq!(map.peek_sendable(), Some(4));
-        map.remove_sendable();
+        map.remove_sendable(4);
         assert_eq!(map.peek_sendable(), Some(8));
-        map.remove_sendable();
+        map.remove_sendable(8);
 
         // 3.Within the same urgency level non-incremental streams are scheduled
         // before incremental streams.
         map.push_sendable(4, 7, true);
         map.push_sendable(8, 7, false);
         assert_eq!(map.peek_sendable(), Some(8));
-        map.remove_sendable();
+        map.remove_sendable(8);
         assert_eq!(map.peek_sendable(), Some(4));
-        map.remove_sendable();
+        map.remove_sendable(4);
 
         // 4.Non-incremental streams are scheduled in the order of their stream IDs.
         map.push_sendable(12, 7, false);
         map.push_sendable(4, 7, false);
         map.push_sendable(8, 7, false);
         assert_eq!(map.peek_sendable(), Some(4));
-        map.remove_sendable();
+        map.remove_sendable(4);
         assert_eq!(map.peek_sendable(), Some(8));
-        map.remove_sendable();
+        map.remove_sendable(8);
         assert_eq!(map.peek_sendable(), Some(12));
-        map.remove_sendable();
+        map.remove_sendable(12);
 
         // 5.Incremental streams are scheduled in a round-robin fashion.
         map.push_sendable(12, 7, true);
@@ -4224,9 +4941,9 @@ mod tests {
         assert_eq!(map.peek_sendable(), Some(12));
         assert_eq!(map.peek_sendable(), Some(8));
         assert_eq!(map.peek_sendable(), Some(4));
-        map.remove_sendable();
-        map.remove_sendable();
-        map.remove_sendable();
+        map.remove_sendable(4);
+        map.remove_sendable(8);
+        map.remove_sendable(12);
 
         assert!(
             !map.has_sendable_streams(),
@@ -4234,6 +4951,24 @@ mod tests {
         );
     }
 
+    // Test StreamMap::set_scheduler
+    #[test]
+    fn stream_map_scheduler() {
+        let mut map = StreamMap::new(true, 50, 50, StreamTransportParams::default());
+        map.set_scheduler(build_stream_scheduler(StreamSchedulingPolicy::RoundRobin));
+
+        // Streams are scheduled in turn regardless of their priority.
+        map.push_sendable(4, 7, false);
+        map.push_sendable(8, 0, false);
+        assert_eq!(map.peek_sendable(), Some(4));
+        assert_eq!(map.peek_sendable(), Some(8));
+        assert_eq!(map.peek_sendable(), Some(4));
+        map.remove_sendable(4);
+        assert_eq!(map.peek_sendable(), Some(8));
+        map.remove_sendable(8);
+        assert!(!map.has_sendable_streams());
+    }
+
     // Test StreamMap::mark_readable
     #[test]
     fn stream_map_readable() {
@@ -4327,6 +5062,92 @@ mod tests {
         assert!(map.almost_full.is_empty());
     }
 
+    // Test StreamMap::stream_max_data_next
+    #[test]
+    fn stream_map_window_sharing() {
+        let local_tp = StreamTransportParams {
+            initial_max_data: 100,
+            initial_max_stream_data_bidi_local: 50,
+            initial_max_stream_data_bidi_remote: 50,
+            initial_max_stream_data_uni: 50,
+            initial_max_streams_bidi: 10,
+            initial_max_streams_uni: 5,
+        };
+        let mut map = StreamMap::new(true, 1000, 1000, local_tp);
+        for stream_id in [0, 4, 8] {
+            assert!(map.get_or_create(stream_id, false).is_ok());
+        }
+
+        assert_eq!(map.rx_allocated, 150);
+        assert_eq!(map.rx_active_streams, 3);
+
+        // Stream 0 received and consumed 30 bytes
+        let data = Bytes::from_static(&[0; 30]);
+        assert!(map.on_stream_frame_received(0, 0, 30, false, data).is_ok());
+        let stream = map.get_mut(0).unwrap();
+        stream.recv.flow_control.increase_read_off(30);
+        map.flow_control.increase_read_off(30);
+        assert_eq!(map.rx_allocated, 120);
+
+        // Each stream advertises its full window
+        assert_eq!(map.stream_max_data_next(0), Some(80));
+        assert_eq!(map.stream_max_data_next(4), Some(50));
+        assert_eq!(map.stream_max_data_next(12), None);
+
+        // The connection credit has been allocated to other streams, so only a
+        // fair share of the connection window is allocated to stream 0
+        map.set_window_sharing(true);
+        assert_eq!(map.stream_max_data_next(0), Some(30 + 100 / 3));
+        assert_eq!(map.stream_max_data_next(4), None);
+
+        // The credit of a stream whose final size known is not counted
+        assert!(map
+            .on_stream_frame_received(8, 0, 0, true, Bytes::new())
+            .is_ok());
+        assert_eq!(map.rx_allocated, 70);
+        assert_eq!(map.rx_active_streams, 2);
+        assert_eq!(map.stream_max_data_next(0), Some(80));
+
+        map.update_stream_max_data(0, 80, Instant::now());
+        assert_eq!(map.get_mut(0).unwrap().recv.max_data(), 80);
+        assert_eq!(map.rx_allocated, 100);
+        assert_eq!(map.stream_max_data_next(0), None);
+
+        // The credit of a destroyed stream is not counted
+        map.stream_destroy(4);
+        assert_eq!(map.rx_allocated, 50);
+        assert_eq!(map.rx_active_streams, 1);
+    }
+
+    // Test StreamMap::seed_window
+    #[test]
+    fn stream_map_seed_window() {
+        let local_tp = StreamTransportParams {
+            initial_max_data: 50,
+            initial_max_stream_data_bidi_local: 20,
+            initial_max_stream_data_bidi_remote: 20,
+            initial_max_stream_data_uni: 20,
+            initial_max_streams_bidi: 10,
+            initial_max_streams_uni: 5,
+        };
+        let mut map = StreamMap::new(true, 100, 60, local_tp);
+
+        // The windows are capped by the maximum window sizes
+        map.seed_window(80);
+        assert_eq!(map.window(), 80);
+        assert_eq!(map.stream_window_seed, 60);
+        map.seed_window(200);
+        assert_eq!(map.window(), 100);
+
+        // New streams use the seeded window
+        assert!(map.get_or_create(0, false).is_ok());
+        assert_eq!(map.get_mut(0).unwrap().recv.window(), 60);
+
+        // The windows are never decreased
+        map.seed_window(10);
+        assert_eq!(map.window(), 100);
+    }
+
     // Test StreamMap::mark_closed
     #[test]
     fn stream_map_closed() {
@@ -5569,7 +6390,7 @@ mod tests {
         // Retransmit the third block of data of stream 0
         assert_eq!(stream.send.read(&mut out_buf[14..]), Ok((4, true)));
         assert!(!stream.is_sendable());
-        map.remove_sendable();
+        map.remove_sendable(0);
 
         // Lost empty data with fin
         assert!(map.peek_sendable().is_none());
@@ -5598,6 +6419,65 @@ mod tests {
         map.on_stream_frame_lost(0, 18, 0, true);
     }
 
+    struct SkipStaleData {
+        stale_off: u64,
+    }
+
+    impl StreamLossPolicy for SkipStaleData {
+        fn on_data_lost(&self, _stream_id: u64, offset: u64, _length: usize) -> LostDataAction {
+            if offset < self.stale_off {
+                LostDataAction::Reset(9)
+            } else {
+                LostDataAction::Retransmit
+            }
+        }
+    }
+
+    #[test]
+    fn stream_map_on_stream_frame_lost_with_loss_policy() {
+        let tp = StreamTransportParams {
+            initial_max_data: 100,
+            initial_max_stream_data_bidi_local: 50,
+            initial_max_stream_data_bidi_remote: 50,
+            initial_max_streams_bidi: 10,
+            ..StreamTransportParams::default()
+        };
+        let mut map = StreamMap::new(false, 50, 50, tp);
+        map.update_peer_stream_transport_params(tp);
+        let policy = Arc::new(SkipStaleData { stale_off: 10 });
+        assert_eq!(map.stream_set_loss_policy(0, Some(policy)), Ok(()));
+
+        // Send all data of stream 0
+        let stream = map.get_mut(0).unwrap();
+        assert_eq!(
+            stream.send.write(Bytes::from_static(b"Everything"), false),
+            Ok(10)
+        );
+        assert_eq!(stream.send.write(Bytes::from_static(b"Over"), false), Ok(4));
+        let mut out_buf = [0; 14];
+        assert_eq!(stream.send.read(&mut out_buf), Ok((14, false)));
+
+        // The lost data that is not stale is retransmitted
+        map.on_stream_frame_lost(0, 10, 4, false);
+        assert_eq!(map.peek_sendable(), Some(0));
+        assert_eq!(map.reset().count(), 0);
+        let stream = map.get_mut(0).unwrap();
+        assert_eq!(stream.send.read(&mut out_buf), Ok((4, false)));
+        map.remove_sendable(0);
+
+        // The lost stale data is abandoned, and the stream is reset with the
+        // final size of all the data written
+        map.on_stream_frame_lost(0, 0, 10, false);
+        assert!(map.peek_sendable().is_none());
+        let v = map.reset().map(|(&k, &v)| (k, v)).collect::<Vec<_>>();
+        assert_eq!(v, [(0, (9, 14))]);
+
+        // The stream has been reset.
+        map.on_stream_frame_lost(0, 0, 10, false);
+        let v = map.reset().map(|(&k, &v)| (k, v)).collect::<Vec<_>>();
+        assert_eq!(v, [(0, (9, 14))]);
+    }
+
     // Test StreamMap::on_reset_stream_frame_lost
     #[test]
     fn stream_map_on_reset_stream_frame_lost() {
@@ -6210,6 +7090,10 @@ mod tests {
                 streams_blocked_at_uni: None,
                 peer_bidi_avail_ids,
                 peer_uni_avail_ids,
+                concurrency_bidi: 10,
+                concurrency_uni: 3,
+                max_concurrency_bidi: 10,
+                max_concurrency_uni: 3,
                 ..ConcurrencyControl::default()
             }
         );
@@ -6428,6 +7312,52 @@ mod tests {
         assert_eq!(cc.peer_streams_left(false), 4);
     }
 
+    // Test ConcurrencyControl::autotune_max_streams
+    #[test]
+    fn concurrency_control_autotune_max_streams() {
+        let mut cc = ConcurrencyControl::new(10, 3);
+        let now = Instant::now();
+        let srtt = time::Duration::from_millis(100);
+
+        // Autotuning is disabled without a ceiling above the initial limit
+        cc.autotune_max_streams(true, now, srtt);
+        cc.autotune_max_streams(true, now, srtt);
+        assert_eq!(cc.concurrency_bidi, 10);
+        assert_eq!(cc.local_max_streams_bidi_next, 10);
+
+        cc.set_max_concurrency(true, 30);
+        cc.set_max_concurrency(false, 1);
+        assert_eq!(cc.max_concurrency_uni, 3);
+
+        // Last update is over 2 * srtt, unchanged
+        cc.autotune_max_streams(true, now + srtt * 2, srtt);
+        assert_eq!(cc.concurrency_bidi, 10);
+
+        // Last update is within 2 * srtt, the concurrency is doubled
+        cc.autotune_max_streams(true, now + srtt * 3, srtt);
+        assert_eq!(cc.concurrency_bidi, 20);
+        assert_eq!(cc.local_max_streams_bidi_next, 20);
+
+        // The concurrency is capped by the ceiling
+        cc.autotune_max_streams(true, now + srtt * 4, srtt);
+        assert_eq!(cc.concurrency_bidi, 30);
+        assert_eq!(cc.local_max_streams_bidi_next, 30);
+        assert!(cc.should_update_local_max_streams(true));
+
+        // Unidirectional streams are not affected
+        assert_eq!(cc.concurrency_uni, 3);
+        assert_eq!(cc.local_max_streams_uni_next, 3);
+
+        // The limit never exceeds 2^60
+        cc.set_max_concurrency(true, u64::MAX);
+        assert_eq!(cc.max_concurrency_bidi, MAX_STREAMS_PER_TYPE);
+        cc.concurrency_bidi = MAX_STREAMS_PER_TYPE - 1;
+        cc.local_max_streams_bidi_next = MAX_STREAMS_PER_TYPE - 1;
+        cc.autotune_max_streams(true, now + srtt * 5, srtt);
+        assert_eq!(cc.concurrency_bidi, MAX_STREAMS_PER_TYPE);
+        assert_eq!(cc.local_max_streams_bidi_next, MAX_STREAMS_PER_TYPE);
+    }
+
     // RecvBuf unit tests
     // Test RecvBuf::new
     #[test]
@@ -6544,6 +7474,31 @@ mod tests {
         assert_eq!(out_buf[..data_len], data[..data_len]);
     }
 
+    #[test]
+    fn recv_buf_write_with_early_gap() {
+        let mut recv = RecvBuf::new(1 << 20, 1 << 20);
+        let chunk = Bytes::from(vec![1; 1000]);
+
+        // The first chunk is missing, and the following chunks are stored as
+        // separate segments without copying.
+        for i in 1..100 {
+            assert_eq!(recv.write(i * 1000, chunk.clone(), false), Ok(()));
+            let (_, buf) = recv.data.last_key_value().unwrap();
+            assert_eq!(buf.as_ptr(), chunk.as_ptr());
+        }
+        assert_eq!(recv.data.len(), 99);
+        assert_eq!(recv.recv_off, 100 * 1000);
+
+        let mut out_buf = vec![0; 200 * 1000];
+        assert_eq!(recv.read(&mut out_buf), Err(Error::Done));
+
+        // The gap is filled, and all the data can be read.
+        assert_eq!(recv.write(0, chunk.clone(), false), Ok(()));
+        assert_eq!(recv.data.len(), 100);
+        assert_eq!(recv.read(&mut out_buf), Ok((100 * 1000, false)));
+        assert_eq!(recv.data.len(), 0);
+    }
+
     #[test]
     fn recv_buf_write_overlapping_data() {
         let mut recv = RecvBuf::new(20, 10);
@@ -6785,6 +7740,29 @@ mod tests {
         assert_eq!(send.write(Bytes::new(), false), Err(Error::FinalSizeError));
     }
 
+    // Test that SendBuf::write retains the buffer without copying it
+    #[test]
+    fn send_buf_write_zero_copy() {
+        let mut send = SendBuf::new(100);
+        let data = Bytes::from(b"a".repeat(12));
+        assert_eq!(send.write(data.clone(), false), Ok(12));
+
+        // The chunks of SEND_BUFFER_SIZE(5) refer to the written buffer.
+        assert_eq!(send.data.len(), 3);
+        for (i, buf) in send.data.iter().enumerate() {
+            assert_eq!(buf.data.as_ptr(), data[i * 5..].as_ptr());
+        }
+
+        // The chunks are dropped once they are acknowledged.
+        let mut out = [0; 12];
+        assert_eq!(send.read(&mut out), Ok((12, false)));
+        send.ack_and_drop(0, 6);
+        assert_eq!(send.data.len(), 2);
+        assert_eq!(send.data[0].data.as_ptr(), data[6..].as_ptr());
+        send.ack_and_drop(6, 6);
+        assert_eq!(send.data.len(), 0);
+    }
+
     // Test for SendBuf::{write, read}
     #[test]
     fn send_buf_multi_write() {
@@ -6821,6 +7799,37 @@ mod tests {
         assert_eq!(out_buf[..13], data[..13]);
     }
 
+    #[test]
+    fn send_buf_read_at() {
+        let mut send = SendBuf::new(100);
+        assert_eq!(send.write(Bytes::from("Hell"), false), Ok(4));
+        assert_eq!(send.write(Bytes::from("o, T"), false), Ok(4));
+        assert_eq!(send.write(Bytes::from("QUIC!"), true), Ok(5));
+
+        let mut out_buf = [0; 128];
+        assert_eq!(send.read(&mut out_buf[..128]), Ok((13, true)));
+
+        // Read data across segments
+        let mut out_buf = [0; 128];
+        assert_eq!(send.read_at(2, &mut out_buf[..8]), Ok(8));
+        assert_eq!(&out_buf[..8], b"llo, TQU");
+        assert_eq!(send.read_at(10, &mut out_buf), Ok(3));
+        assert_eq!(&out_buf[..3], b"IC!");
+
+        // The sending state is not changed
+        assert_eq!(send.unsent_off, 13);
+        assert_eq!(send.unacked_len, 13);
+
+        // Acknowledged data is not available
+        send.ack_and_drop(0, 8);
+        assert_eq!(send.read_at(4, &mut out_buf), Err(Error::Done));
+        assert_eq!(send.read_at(8, &mut out_buf), Ok(5));
+        assert_eq!(send.read_at(13, &mut out_buf), Err(Error::Done));
+
+        // Zero-length read
+        assert_eq!(send.read_at(8, &mut out_buf[..0]), Err(Error::Done));
+    }
+
     #[test]
     fn send_buf_ack_in_order() {
         let mut send = SendBuf::new(100);
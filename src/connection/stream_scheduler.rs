@@ -0,0 +1,514 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(unused_variables)]
+
+use std::cmp;
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::BinaryHeap;
+use std::collections::VecDeque;
+
+use rustc_hash::FxHashMap;
+
+/// Scheduler of the streams that have data ready to be sent.
+///
+/// The scheduler decides the order in which the data of sendable streams is
+/// packetized. Before each STREAM frame is written, `peek` is called to
+/// select the stream, and `on_sent` is called once the frame is written.
+/// A stream is removed from the scheduler once it has no more data to send,
+/// it was stopped, or its priority was changed.
+pub trait StreamScheduler: Send {
+    /// Name of the stream scheduler.
+    fn name(&self) -> &str;
+
+    /// Add a stream which becomes sendable, with its urgency and incremental
+    /// flag. It is never called for a stream which is already scheduled.
+    fn push(&mut self, stream_id: u64, urgency: u8, incremental: bool);
+
+    /// Return the stream from which the next STREAM frame will be built.
+    fn peek(&mut self) -> Option<u64>;
+
+    /// Process a STREAM frame carrying `len` bytes of the stream data, which
+    /// was written after the stream was selected by `peek`.
+    fn on_sent(&mut self, stream_id: u64, len: usize) {}
+
+    /// Remove the given stream. Return true if the stream was scheduled.
+    fn remove(&mut self, stream_id: u64) -> bool;
+
+    /// Return true if there are no scheduled streams.
+    fn is_empty(&self) -> bool;
+
+    /// Return an iterator over the scheduled streams.
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_>;
+}
+
+/// Factory of user-provided stream schedulers.
+///
+/// A new stream scheduler is built for each connection. It takes precedence
+/// over the `stream_scheduling_policy` setting.
+pub trait StreamSchedulerFactory: Send + Sync {
+    /// Build a stream scheduler for a new connection.
+    fn build(&self) -> Box<dyn StreamScheduler>;
+}
+
+/// Available stream scheduling policies.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StreamSchedulingPolicy {
+    /// Streams with lower urgency are scheduled first. Within the same
+    /// urgency, non-incremental streams are sent one by one in the order of
+    /// their stream IDs before the incremental streams, which share the
+    /// bandwidth in a round-robin fashion. See RFC 9218.
+    #[default]
+    Priority,
+
+    /// Streams are sent one by one in the order of their urgency and stream
+    /// IDs, regardless of the incremental flag.
+    Sequential,
+
+    /// All streams share the bandwidth in a round-robin fashion, regardless
+    /// of their urgency and incremental flag.
+    RoundRobin,
+
+    /// Streams share the bandwidth in proportion to the weights derived from
+    /// their urgency, regardless of the incremental flag. A stream with the
+    /// urgency `u` is given the weight of `8 - min(u, 7)`.
+    WeightedFair,
+}
+
+/// Build a stream scheduler.
+pub(crate) fn build_stream_scheduler(policy: StreamSchedulingPolicy) -> Box<dyn StreamScheduler> {
+    match policy {
+        StreamSchedulingPolicy::Priority => Box::<PriorityScheduler>::default(),
+        StreamSchedulingPolicy::Sequential => Box::<SequentialScheduler>::default(),
+        StreamSchedulingPolicy::RoundRobin => Box::<RoundRobinScheduler>::default(),
+        StreamSchedulingPolicy::WeightedFair => Box::<WeightedFairScheduler>::default(),
+    }
+}
+
+impl Default for Box<dyn StreamScheduler> {
+    fn default() -> Self {
+        build_stream_scheduler(StreamSchedulingPolicy::default())
+    }
+}
+
+/// Stream priority queue
+///
+/// Streams are categorized based on their urgency, where each urgency level
+/// has two queues, including non-incremental and incremental streams.
+#[derive(Debug, Default)]
+struct StreamPriorityQueue {
+    /// Non-incremental streams.
+    non_incremental: BinaryHeap<cmp::Reverse<u64>>,
+    /// Incremental streams.
+    incremental: VecDeque<u64>,
+}
+
+impl StreamPriorityQueue {
+    fn is_empty(&self) -> bool {
+        self.non_incremental.is_empty() && self.incremental.is_empty()
+    }
+
+    fn remove(&mut self, stream_id: u64) -> bool {
+        // The last peeked stream is at the top of the heap or at the end of
+        // the incremental queue.
+        if self.non_incremental.peek().map(|x| x.0) == Some(stream_id) {
+            self.non_incremental.pop();
+            return true;
+        }
+        if self.incremental.back() == Some(&stream_id) {
+            self.incremental.pop_back();
+            return true;
+        }
+
+        let len = self.non_incremental.len() + self.incremental.len();
+        self.non_incremental.retain(|x| x.0 != stream_id);
+        self.incremental.retain(|&x| x != stream_id);
+        self.non_incremental.len() + self.incremental.len() != len
+    }
+}
+
+/// The stream scheduler of the Extensible Prioritization Scheme.
+///
+/// Streams with lower urgency level are scheduled first, and within the
+/// same urgency level non-incremental streams are scheduled before incremental
+/// streams.
+///
+/// Non-incremental streams are scheduled in the order of their stream IDs.
+/// Incremental streams are scheduled in a round-robin fashion.
+#[derive(Debug, Default)]
+struct PriorityScheduler {
+    /// Streams categorized by their urgency, lower value means higher priority.
+    queues: BTreeMap<u8, StreamPriorityQueue>,
+}
+
+impl StreamScheduler for PriorityScheduler {
+    fn name(&self) -> &str {
+        "priority"
+    }
+
+    fn push(&mut self, stream_id: u64, urgency: u8, incremental: bool) {
+        // 1.Get priority queue with the given urgency, if it does not exist, create a new one.
+        let queue = match self.queues.entry(urgency) {
+            btree_map::Entry::Vacant(v) => v.insert(StreamPriorityQueue::default()),
+            btree_map::Entry::Occupied(v) => v.into_mut(),
+        };
+
+        // 2.Push the element to the queue corresponding to the given incremental flag.
+        if !incremental {
+            // Non-incremental streams are scheduled in order of their stream ID.
+            queue.non_incremental.push(cmp::Reverse(stream_id))
+        } else {
+            // Incremental streams are scheduled in a round-robin fashion.
+            queue.incremental.push_back(stream_id)
+        };
+    }
+
+    fn peek(&mut self) -> Option<u64> {
+        let queue = self.queues.values_mut().next()?;
+
+        // 1.Try to get the non-incremental stream with the lowest stream ID.
+        match queue.non_incremental.peek().map(|x| x.0) {
+            Some(stream_id) => Some(stream_id),
+            None => {
+                // 2.Try to get the incremental stream from the front of the queue.
+                // Incremental streams are scheduled in a round-robin fashion, So
+                // we should move the current peeked incremental stream to the end
+                // of the queue.
+                let stream_id = queue.incremental.pop_front()?;
+                queue.incremental.push_back(stream_id);
+                Some(stream_id)
+            }
+        }
+    }
+
+    fn remove(&mut self, stream_id: u64) -> bool {
+        let mut found = None;
+        for (&urgency, queue) in self.queues.iter_mut() {
+            if queue.remove(stream_id) {
+                found = Some((urgency, queue.is_empty()));
+                break;
+            }
+        }
+
+        match found {
+            Some((urgency, empty)) => {
+                // Remove the queue from the queues list if it is empty at
+                // present time, so that the next time `peek()` is invoked,
+                // the next non-empty queue is selected.
+                if empty {
+                    self.queues.remove(&urgency);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.queues.values().flat_map(|queue| {
+            let ids = queue.non_incremental.iter().map(|x| x.0);
+            ids.chain(queue.incremental.iter().copied())
+        }))
+    }
+}
+
+/// The stream scheduler which sends streams one by one in the order of their
+/// urgency and stream IDs.
+#[derive(Debug, Default)]
+struct SequentialScheduler {
+    /// Streams ordered by their urgency and stream IDs.
+    queue: BTreeSet<(u8, u64)>,
+
+    /// Urgency of the scheduled streams.
+    urgencies: FxHashMap<u64, u8>,
+}
+
+impl StreamScheduler for SequentialScheduler {
+    fn name(&self) -> &str {
+        "sequential"
+    }
+
+    fn push(&mut self, stream_id: u64, urgency: u8, incremental: bool) {
+        self.queue.insert((urgency, stream_id));
+        self.urgencies.insert(stream_id, urgency);
+    }
+
+    fn peek(&mut self) -> Option<u64> {
+        self.queue.first().map(|x| x.1)
+    }
+
+    fn remove(&mut self, stream_id: u64) -> bool {
+        match self.urgencies.remove(&stream_id) {
+            Some(urgency) => self.queue.remove(&(urgency, stream_id)),
+            None => false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.queue.iter().map(|x| x.1))
+    }
+}
+
+/// The stream scheduler which sends streams in a round-robin fashion.
+#[derive(Debug, Default)]
+struct RoundRobinScheduler {
+    /// Scheduled streams, the last peeked stream is at the end of the queue.
+    queue: VecDeque<u64>,
+}
+
+impl StreamScheduler for RoundRobinScheduler {
+    fn name(&self) -> &str {
+        "roundrobin"
+    }
+
+    fn push(&mut self, stream_id: u64, urgency: u8, incremental: bool) {
+        self.queue.push_back(stream_id);
+    }
+
+    fn peek(&mut self) -> Option<u64> {
+        let stream_id = self.queue.pop_front()?;
+        self.queue.push_back(stream_id);
+        Some(stream_id)
+    }
+
+    fn remove(&mut self, stream_id: u64) -> bool {
+        if self.queue.back() == Some(&stream_id) {
+            self.queue.pop_back();
+            return true;
+        }
+
+        let len = self.queue.len();
+        self.queue.retain(|&x| x != stream_id);
+        self.queue.len() != len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.queue.iter().copied())
+    }
+}
+
+/// The scale of the virtual time of the weighted fair queuing.
+const WFQ_VIRTUAL_TIME_SCALE: u64 = 840;
+
+/// The stream scheduler of weighted fair queuing.
+///
+/// Each stream is assigned a virtual finish time, which is advanced by the
+/// bytes sent divided by the weight of the stream. The stream with the
+/// smallest virtual finish time is scheduled first. A stream which becomes
+/// sendable starts from the current virtual time, so that it can not claim
+/// the bandwidth it did not use while it was idle.
+#[derive(Debug, Default)]
+struct WeightedFairScheduler {
+    /// Streams ordered by their virtual finish time and stream IDs.
+    queue: BTreeSet<(u64, u64)>,
+
+    /// Virtual finish time and weight of the scheduled streams.
+    streams: FxHashMap<u64, (u64, u64)>,
+
+    /// The virtual finish time of the last peeked stream.
+    virtual_time: u64,
+}
+
+impl WeightedFairScheduler {
+    /// Return the weight of the stream with the given urgency.
+    fn weight(urgency: u8) -> u64 {
+        8 - cmp::min(urgency, 7) as u64
+    }
+}
+
+impl StreamScheduler for WeightedFairScheduler {
+    fn name(&self) -> &str {
+        "weightedfair"
+    }
+
+    fn push(&mut self, stream_id: u64, urgency: u8, incremental: bool) {
+        let finish = self.virtual_time;
+        self.queue.insert((finish, stream_id));
+        self.streams
+            .insert(stream_id, (finish, Self::weight(urgency)));
+    }
+
+    fn peek(&mut self) -> Option<u64> {
+        let (finish, stream_id) = *self.queue.first()?;
+        self.virtual_time = finish;
+        Some(stream_id)
+    }
+
+    fn on_sent(&mut self, stream_id: u64, len: usize) {
+        let (finish, weight) = match self.streams.get_mut(&stream_id) {
+            Some(v) => v,
+            None => return,
+        };
+
+        self.queue.remove(&(*finish, stream_id));
+        // The scale is divisible by all the weights, so that a frame of a few
+        // bytes still advances the virtual time.
+        *finish = finish
+            .saturating_add((len as u64).max(1).saturating_mul(WFQ_VIRTUAL_TIME_SCALE) / *weight);
+        self.queue.insert((*finish, stream_id));
+    }
+
+    fn remove(&mut self, stream_id: u64) -> bool {
+        match self.streams.remove(&stream_id) {
+            Some((finish, _)) => self.queue.remove(&(finish, stream_id)),
+            None => false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.queue.iter().map(|x| x.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_scheduler() {
+        let mut s = build_stream_scheduler(StreamSchedulingPolicy::Priority);
+        assert_eq!(s.name(), "priority");
+        assert!(s.is_empty());
+
+        s.push(8, 1, true);
+        s.push(4, 1, true);
+        s.push(12, 1, false);
+        s.push(0, 3, false);
+        assert_eq!(s.iter().collect::<Vec<u64>>(), vec![12, 8, 4, 0]);
+
+        // Non-incremental streams are scheduled before incremental streams.
+        assert_eq!(s.peek(), Some(12));
+        assert_eq!(s.peek(), Some(12));
+        assert!(s.remove(12));
+
+        // Incremental streams are scheduled in a round-robin fashion.
+        assert_eq!(s.peek(), Some(8));
+        assert_eq!(s.peek(), Some(4));
+        assert_eq!(s.peek(), Some(8));
+        assert!(s.remove(8));
+        assert!(!s.remove(8));
+        assert!(s.remove(4));
+
+        // Streams with higher urgency values are scheduled last.
+        assert_eq!(s.peek(), Some(0));
+        assert!(s.remove(0));
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn sequential_scheduler() {
+        let mut s = build_stream_scheduler(StreamSchedulingPolicy::Sequential);
+        assert_eq!(s.name(), "sequential");
+
+        s.push(8, 1, true);
+        s.push(4, 1, true);
+        s.push(0, 3, false);
+        assert_eq!(s.iter().collect::<Vec<u64>>(), vec![4, 8, 0]);
+
+        // Streams are scheduled one by one, regardless of the incremental flag.
+        assert_eq!(s.peek(), Some(4));
+        s.on_sent(4, 1000);
+        assert_eq!(s.peek(), Some(4));
+        assert!(s.remove(4));
+        assert_eq!(s.peek(), Some(8));
+        assert!(s.remove(0));
+        assert!(!s.remove(0));
+        assert!(s.remove(8));
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn round_robin_scheduler() {
+        let mut s = build_stream_scheduler(StreamSchedulingPolicy::RoundRobin);
+        assert_eq!(s.name(), "roundrobin");
+
+        s.push(0, 7, false);
+        s.push(4, 0, false);
+        s.push(8, 3, true);
+
+        // Streams are scheduled in turn, regardless of their urgency.
+        for _ in 0..2 {
+            assert_eq!(s.peek(), Some(0));
+            assert_eq!(s.peek(), Some(4));
+            assert_eq!(s.peek(), Some(8));
+        }
+        assert!(s.remove(4));
+        assert_eq!(s.peek(), Some(0));
+        assert_eq!(s.peek(), Some(8));
+        assert!(s.remove(8));
+        assert!(s.remove(0));
+        assert!(!s.remove(0));
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn weighted_fair_scheduler() {
+        let mut s = build_stream_scheduler(StreamSchedulingPolicy::WeightedFair);
+        assert_eq!(s.name(), "weightedfair");
+
+        // The weight of stream 0 is 4 times the weight of stream 4.
+        s.push(0, 4, false);
+        s.push(4, 7, false);
+        s.push(8, 7, true);
+
+        let mut sent = FxHashMap::default();
+        for _ in 0..600 {
+            let stream_id = s.peek().unwrap();
+            s.on_sent(stream_id, 1000);
+            *sent.entry(stream_id).or_insert(0) += 1;
+        }
+        assert_eq!(sent[&0], 400);
+        assert_eq!(sent[&4], 100);
+        assert_eq!(sent[&8], 100);
+
+        // A stream which becomes sendable does not claim the bandwidth it
+        // did not use while it was idle.
+        assert!(s.remove(4));
+        s.push(4, 7, false);
+        let mut sent = FxHashMap::default();
+        for _ in 0..12 {
+            let stream_id = s.peek().unwrap();
+            s.on_sent(stream_id, 1000);
+            *sent.entry(stream_id).or_insert(0) += 1;
+        }
+        assert_eq!(sent[&0], 8);
+        assert_eq!(sent[&4], 2);
+        assert_eq!(sent[&8], 2);
+
+        assert!(s.remove(0));
+        assert!(s.remove(4));
+        assert!(s.remove(8));
+        assert!(!s.remove(8));
+        assert!(s.is_empty());
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
 use std::time::Instant;
 
 use strum::EnumCount;
@@ -46,18 +47,40 @@ pub(crate) enum Timer {
 
     /// When to declare PATH_CHALLENGE probing packet lost
     PathChallenge,
+
+    /// When to send the small stream data held for coalescing
+    Coalescing,
 }
 
 /// Associated timeout values with each `Timer`
-#[derive(Debug, Copy, Clone, Default)]
+///
+/// The expiration time of each timer is rounded up to a multiple of the
+/// granularity since the creation of the table, so that the timers expiring
+/// close to each other are coalesced into a single timeout event.
+#[derive(Debug, Copy, Clone)]
 pub(crate) struct TimerTable {
     expires: [Option<Instant>; Timer::COUNT],
+
+    /// The granularity for coalescing timers. Zero means disabled.
+    granularity: Duration,
+
+    /// The start time of the granularity slots.
+    base: Instant,
 }
 
 impl TimerTable {
+    /// Create a timer table with the given granularity for coalescing timers
+    pub fn new(granularity: Duration) -> Self {
+        Self {
+            expires: [None; Timer::COUNT],
+            granularity,
+            base: Instant::now(),
+        }
+    }
+
     /// Set expiration time for the giver timer
     pub fn set(&mut self, timer: Timer, time: Instant) {
-        self.expires[timer as usize] = Some(time);
+        self.expires[timer as usize] = Some(self.coalesce(time));
     }
 
     /// Get expiration time for the giver timer
@@ -79,6 +102,24 @@ impl TimerTable {
     pub fn is_expired(&self, timer: Timer, after: Instant) -> bool {
         self.expires[timer as usize].map_or(false, |x| x <= after)
     }
+
+    /// Round up the given time to the end of the granularity slot. The timers
+    /// never expire earlier than the given time.
+    fn coalesce(&self, time: Instant) -> Instant {
+        if self.granularity.is_zero() {
+            return time;
+        }
+        let granularity = self.granularity.as_nanos();
+        let elapsed = time.saturating_duration_since(self.base).as_nanos();
+        let slots = (elapsed + granularity - 1) / granularity;
+        self.base + Duration::from_nanos((slots * granularity) as u64)
+    }
+}
+
+impl Default for TimerTable {
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
 }
 
 #[cfg(test)]
@@ -135,4 +176,36 @@ mod tests {
         assert_eq!(timers.is_expired(Timer::LossDetection, now), true);
         assert_eq!(timers.is_expired(Timer::Idle, now), true);
     }
+
+    #[test]
+    fn timer_coalescing() {
+        let granularity = Duration::from_millis(10);
+        let mut timers = TimerTable::new(granularity);
+        let base = timers.base;
+
+        // Timers in the same slot are coalesced
+        let ack_time = base.add(Duration::from_millis(21));
+        let pacer_time = base.add(Duration::from_millis(29));
+        timers.set(Timer::Ack, ack_time);
+        timers.set(Timer::Pacer, pacer_time);
+        let slot_end = base.add(Duration::from_millis(30));
+        assert_eq!(timers.get(Timer::Ack), Some(slot_end));
+        assert_eq!(timers.get(Timer::Pacer), Some(slot_end));
+        assert_eq!(timers.next_timeout(), Some(slot_end));
+
+        // Timers never expire earlier than the given time
+        assert_eq!(timers.is_expired(Timer::Ack, ack_time), false);
+        assert_eq!(timers.is_expired(Timer::Ack, slot_end), true);
+        assert_eq!(timers.is_expired(Timer::Pacer, slot_end), true);
+
+        // Timers at the slot boundary are not delayed
+        let loss_time = base.add(Duration::from_millis(40));
+        timers.set(Timer::LossDetection, loss_time);
+        assert_eq!(timers.get(Timer::LossDetection), Some(loss_time));
+
+        // Coalescing is disabled
+        let mut timers = TimerTable::new(Duration::ZERO);
+        timers.set(Timer::Ack, ack_time);
+        assert_eq!(timers.get(Timer::Ack), Some(ack_time));
+    }
 }
@@ -20,6 +20,7 @@ use std::cell::RefCell;
 use std::cmp;
 use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::rc::Rc;
 use std::time::Duration;
@@ -46,10 +47,12 @@ use crate::token::ResetToken;
 use crate::ConnectionId;
 use crate::ConnectionIdGenerator;
 use crate::ConnectionQueues;
+use crate::Ecn;
 use crate::Event;
 use crate::FourTuple;
 use crate::PacketInfo;
 use crate::PacketSendHandler;
+use crate::RecvWindowBudget;
 use crate::Result;
 use crate::TransportHandler;
 
@@ -91,9 +94,24 @@ pub struct Endpoint {
     /// potential misordering or loss of Initial packets.
     buffer: PacketBuffer,
 
+    /// Rate limiter for stateless packets sent to unknown source addresses.
+    limiter: StatelessLimiter,
+
+    /// Path properties shared by the connections to the same peer host.
+    path_cache: PathCache,
+
     /// Packets generated by the endpoint.
     packets: PacketQueue,
 
+    /// Estimated memory in bytes used by all the connections.
+    memory_usage: u64,
+
+    /// Receive window budget shared by all the connections.
+    recv_budget: Rc<RefCell<RecvWindowBudget>>,
+
+    /// The connection being closed for exceeding the memory limit, if any.
+    evicting: Option<u64>,
+
     /// The endpoint is shutdown.
     closed: bool,
 
@@ -114,7 +132,13 @@ impl Endpoint {
         });
         let trace_id = if is_server { "SERVER" } else { "CLIENT" };
         let buffer = PacketBuffer::new(config.zerortt_buffer_size);
-        let packets = PacketQueue::new(config.send_batch_size);
+        let limiter = StatelessLimiter::new(config.stateless_response_rate);
+        let path_cache = PathCache::new(config.path_cache_size, config.path_cache_lifetime);
+        let packets = PacketQueue::new(
+            config.send_batch_size,
+            MAX_BUFFER_SIZE * config.max_send_segments,
+        );
+        let recv_budget = RecvWindowBudget::new(config.max_recv_window_budget);
 
         Self {
             is_server,
@@ -127,7 +151,12 @@ impl Endpoint {
             handler,
             sender,
             buffer,
+            limiter,
+            path_cache,
             packets,
+            memory_usage: 0,
+            recv_budget: Rc::new(RefCell::new(recv_budget)),
+            evicting: None,
             closed: false,
             trace_id: trace_id.to_string(),
         }
@@ -165,6 +194,10 @@ impl Endpoint {
         if let Some(conn) = self.conns.get_mut(idx) {
             conn.set_index(idx);
             conn.set_queues(self.queues.clone());
+            conn.set_recv_budget(self.recv_budget.clone());
+            if let Some(p) = self.path_cache.get(remote.ip(), Instant::now()) {
+                conn.set_path_estimates(p.srtt, p.min_rtt, p.cwnd);
+            }
             if let Some(session) = session {
                 conn.set_session(session)?;
             }
@@ -242,8 +275,13 @@ impl Endpoint {
                 return Ok(());
             }
 
+            // Check memory usage limit
+            if self.memory_exceeded() {
+                return Ok(());
+            }
+
             // Validate version of the packet
-            if !crate::version_is_supported(hdr.version) {
+            if !self.config.versions.contains(&hdr.version) {
                 return self.send_version_negotiation(&hdr, local, remote);
             }
 
@@ -287,6 +325,10 @@ impl Endpoint {
             if let Some(conn) = self.conns.get_mut(idx) {
                 conn.set_index(idx);
                 conn.set_queues(self.queues.clone());
+                conn.set_recv_budget(self.recv_budget.clone());
+                if let Some(p) = self.path_cache.get(remote.ip(), Instant::now()) {
+                    conn.set_path_estimates(p.srtt, p.min_rtt, p.cwnd);
+                }
                 trace!(
                     "{} create a server connection {:?}",
                     &self.trace_id,
@@ -332,20 +374,13 @@ impl Endpoint {
     /// Decode and validate the address token.
     fn validate_address_token(
         &mut self,
-        addr_token: &mut [u8],
+        addr_token: &[u8],
         cli_addr: &SocketAddr,
         cli_pkt_dcid: &ConnectionId,
     ) -> Result<AddressToken> {
         let lifetime = self.config.address_token_lifetime;
-
-        for key in &self.config.address_token_key {
-            match AddressToken::decode(key, addr_token, cli_addr, cli_pkt_dcid, lifetime) {
-                Ok(token) => return Ok(token),
-                Err(Error::ExpiredToken) => return Err(Error::ExpiredToken),
-                _ => continue, // try the next key
-            }
-        }
-        Err(Error::InvalidToken)
+        let keys = &self.config.address_token_key;
+        AddressToken::decode(keys, addr_token, cli_addr, cli_pkt_dcid, lifetime)
     }
 
     /// Write an Version Negoiation packet which will be sent later.
@@ -355,15 +390,31 @@ impl Endpoint {
         local: SocketAddr,
         remote: SocketAddr,
     ) -> Result<()> {
+        if !self.limiter.allow(remote.ip(), Instant::now()) {
+            trace!(
+                "{} version negotiation rate limited: remote {:?}",
+                &self.trace_id,
+                remote
+            );
+            return Ok(());
+        }
+
         let mut pkt_out = self.packets.get_buffer();
-        let len =
-            packet::version_negotiation(&cli_pkt_hdr.dcid, &cli_pkt_hdr.scid, &mut pkt_out[..])?;
+        let len = packet::version_negotiation(
+            &cli_pkt_hdr.dcid,
+            &cli_pkt_hdr.scid,
+            &self.config.versions,
+            &mut pkt_out[..],
+        )?;
         pkt_out.truncate(len);
 
         let pkt_info = PacketInfo {
             src: local,
             dst: remote,
             time: Instant::now(),
+            ecn: Ecn::NotEct,
+            dscp: self.config.dscp,
+            segment_size: 0,
         };
 
         trace!(
@@ -383,6 +434,11 @@ impl Endpoint {
         local: SocketAddr,
         remote: SocketAddr,
     ) -> Result<()> {
+        if !self.limiter.allow(remote.ip(), Instant::now()) {
+            trace!("{} retry rate limited: remote {:?}", &self.trace_id, remote);
+            return Ok(());
+        }
+
         let mut pkt_out = self.packets.get_buffer();
 
         // Generate a retry token
@@ -396,7 +452,7 @@ impl Endpoint {
             &initial_pkt_hdr.scid, // client cid
             &initial_pkt_hdr.dcid, // original dcid
             &token,
-            crate::QUIC_VERSION_V1,
+            initial_pkt_hdr.version,
             &mut pkt_out[..],
         )?;
         pkt_out.truncate(len);
@@ -405,6 +461,9 @@ impl Endpoint {
             src: local,
             dst: remote,
             time: Instant::now(),
+            ecn: Ecn::NotEct,
+            dscp: self.config.dscp,
+            segment_size: 0,
         };
 
         trace!(
@@ -418,7 +477,6 @@ impl Endpoint {
     }
 
     /// Write an Stateless Reset packet which will be sent later.
-    /// TODO: rate limit based on address
     fn send_stateless_reset(
         &mut self,
         pkt_in_len: usize,
@@ -441,6 +499,15 @@ impl Endpoint {
         }
         let pkt_out_len = cmp::min(pkt_out_len, crate::MAX_RESET_PACKET_LEN);
 
+        if !self.limiter.allow(remote.ip(), Instant::now()) {
+            trace!(
+                "{} stateless reset rate limited: remote {:?}",
+                &self.trace_id,
+                remote
+            );
+            return Ok(());
+        }
+
         // Generate stateless reset token based on the dcid.
         let key = &self.config.reset_token_key;
         let reset_token = ResetToken::generate(key, dcid);
@@ -454,6 +521,9 @@ impl Endpoint {
             src: local,
             dst: remote,
             time: Instant::now(),
+            ecn: Ecn::NotEct,
+            dscp: self.config.dscp,
+            segment_size: 0,
         };
 
         trace!(
@@ -503,6 +573,9 @@ impl Endpoint {
         );
         let mut ready = Vec::<u64>::new();
 
+        // Close the connection using the most memory if needed.
+        self.evict_connection();
+
         // Process all tickable connections
         while let Some(idx) = self.conn_tickable_next() {
             if self.process_connection(idx, &mut ready) {
@@ -521,8 +594,18 @@ impl Endpoint {
             }
 
             self.handler.on_conn_closed(conn);
+            if let Ok(path) = conn.get_active_path() {
+                let addr = path.remote_addr().ip();
+                self.path_cache
+                    .update(addr, conn.path_estimates(), Instant::now());
+            }
             conn.mark_tickable(false);
             conn.mark_sendable(false);
+            self.memory_usage = self.memory_usage.saturating_sub(conn.stats().memory_usage);
+            conn.release_recv_budget();
+            if self.evicting == Some(idx) {
+                self.evicting = None;
+            }
             self.timers.del(&idx);
             self.routes.remove(conn);
             self.conns.remove(idx);
@@ -561,6 +644,24 @@ impl Endpoint {
 
                 Event::NewToken(token) => self.handler.on_new_token(conn, token),
 
+                Event::PingAcked(tag, rtt) => self.handler.on_ping_acked(conn, tag, rtt),
+
+                Event::FlowControlBlocked(event) => {
+                    self.handler.on_flow_control_blocked(conn, event)
+                }
+
+                Event::TargetBitrateUpdated(bitrate) => {
+                    self.handler.on_target_bitrate_updated(conn, bitrate)
+                }
+
+                Event::PersistentCongestion(event) => {
+                    self.handler.on_persistent_congestion(conn, event)
+                }
+
+                Event::StreamNotified(stream_id, event) => {
+                    self.handler.on_stream_event(conn, stream_id, event)
+                }
+
                 Event::ScidToAdvertise(num) => {
                     let key = &self.config.reset_token_key;
                     Self::conn_add_scids(conn, num, &mut self.cid_gen, key, &mut self.routes);
@@ -605,6 +706,10 @@ impl Endpoint {
         // Add the connection to the sendable queue
         conn.mark_sendable(true);
 
+        // Update the memory usage of the endpoint
+        let prev = conn.update_memory_usage();
+        self.memory_usage = (self.memory_usage + conn.stats().memory_usage).saturating_sub(prev);
+
         // Try to update the timer of the connection
         if let Some(t) = conn.timeout() {
             self.timers.add(idx, t, Instant::now());
@@ -620,6 +725,45 @@ impl Endpoint {
         true
     }
 
+    /// Return true if the memory used by the connections exceeds the limit.
+    fn memory_exceeded(&self) -> bool {
+        self.config.max_memory_usage > 0 && self.memory_usage > self.config.max_memory_usage
+    }
+
+    /// Close the connection using the most memory if the memory limit of the
+    /// endpoint is exceeded.
+    ///
+    /// It waits for the previously evicted connection to be cleaned up before
+    /// closing another one, since the memory of a closing connection is not
+    /// released immediately.
+    fn evict_connection(&mut self) {
+        if !self.memory_exceeded() || self.evicting.is_some() {
+            return;
+        }
+
+        let conn = self
+            .conns
+            .conns
+            .iter_mut()
+            .max_by_key(|(_, c)| c.stats().memory_usage);
+        if let Some((&idx, conn)) = conn {
+            warn!(
+                "{} memory usage {} exceeds the limit, close connection {:?} using {} bytes",
+                &self.trace_id,
+                self.memory_usage,
+                conn.trace_id(),
+                conn.stats().memory_usage
+            );
+            let _ = conn.close(
+                false,
+                Error::InternalError.to_wire(),
+                b"memory limit exceeded",
+            );
+            conn.mark_tickable(true);
+            self.evicting = Some(idx);
+        }
+    }
+
     /// Add scids for the given connection
     fn conn_add_scids(
         conn: &mut Connection,
@@ -700,8 +844,8 @@ impl Endpoint {
                     }
 
                     let mut buf = self.packets.get_buffer();
-                    match conn.send(&mut buf) {
-                        Ok((len, info)) => {
+                    match conn.send_segments(&mut buf, self.config.max_send_segments) {
+                        Ok((len, _, _, info)) => {
                             buf.truncate(len);
                             self.packets.add_packet(buf, info);
                             sent.insert(idx);
@@ -794,10 +938,13 @@ impl Endpoint {
                 conn.stream_destroy(stream_id);
             }
             self.handler.on_conn_closed(conn);
+            conn.release_recv_budget();
         }
         self.timers.clear();
         self.routes.clear();
         self.conns.clear();
+        self.memory_usage = 0;
+        self.evicting = None;
     }
 
     /// Set the connection id generator
@@ -1031,6 +1178,128 @@ impl PacketBuffer {
     }
 }
 
+/// The maximum number of source addresses tracked by the StatelessLimiter.
+const MAX_STATELESS_SOURCES: usize = 4096;
+
+/// StatelessLimiter limits the rate of stateless packets (Version Negotiation,
+/// Retry and Stateless Reset) sent to each source address, so that spoofed
+/// packets can not make the endpoint flood a victim address.
+struct StatelessLimiter {
+    /// The maximum number of stateless packets sent to an address per second.
+    /// A value of 0 means no limit.
+    rate: u32,
+
+    /// The remaining tokens and the start of the current window for each
+    /// source address.
+    sources: lru::LruCache<IpAddr, (u32, Instant)>,
+}
+
+impl StatelessLimiter {
+    fn new(rate: u32) -> Self {
+        let size = std::num::NonZeroUsize::new(MAX_STATELESS_SOURCES).unwrap();
+        Self {
+            rate,
+            sources: lru::LruCache::new(size),
+        }
+    }
+
+    /// Return whether a stateless packet is allowed to be sent to the address.
+    fn allow(&mut self, addr: IpAddr, now: Instant) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+
+        if let Some((tokens, start)) = self.sources.get_mut(&addr) {
+            if now.saturating_duration_since(*start) >= Duration::from_secs(1) {
+                *tokens = self.rate;
+                *start = now;
+            }
+            if *tokens == 0 {
+                return false;
+            }
+            *tokens -= 1;
+            return true;
+        }
+
+        self.sources.put(addr, (self.rate - 1, now));
+        true
+    }
+}
+
+/// The RTT and capacity estimates of the path to a peer host, which are
+/// saved when a connection to the host is closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PathProperties {
+    /// The smoothed RTT of the path.
+    srtt: Duration,
+
+    /// The minimum RTT of the path.
+    min_rtt: Duration,
+
+    /// The congestion window of the path in bytes.
+    cwnd: u64,
+
+    /// The time when the estimates were saved.
+    time: Instant,
+}
+
+/// PathCache shares the path properties measured by the closed connections
+/// with the new connections to the same peer host, like the TCP metrics
+/// cache. See RFC 9040.
+struct PathCache {
+    /// The lifetime of the cached path properties.
+    lifetime: Duration,
+
+    /// The cached path properties indexed by the peer host. None if the cache
+    /// is disabled.
+    entries: Option<lru::LruCache<IpAddr, PathProperties>>,
+}
+
+impl PathCache {
+    fn new(cache_size: usize, lifetime: Duration) -> Self {
+        Self {
+            lifetime,
+            entries: std::num::NonZeroUsize::new(cache_size).map(lru::LruCache::new),
+        }
+    }
+
+    /// Return the unexpired path properties of the peer host.
+    fn get(&mut self, addr: IpAddr, now: Instant) -> Option<PathProperties> {
+        let entries = self.entries.as_mut()?;
+        let props = *entries.get(&addr)?;
+        if now.saturating_duration_since(props.time) > self.lifetime {
+            entries.pop(&addr);
+            return None;
+        }
+        Some(props)
+    }
+
+    /// Save the smoothed RTT, the minimum RTT and the congestion window
+    /// measured by a closed connection to the peer host. The entry is
+    /// invalidated if no RTT sample was taken by the connection, for example
+    /// the handshake timed out, since the path may have changed.
+    fn update(&mut self, addr: IpAddr, estimates: Option<(Duration, Duration, u64)>, now: Instant) {
+        let entries = match self.entries.as_mut() {
+            Some(v) => v,
+            None => return,
+        };
+        match estimates {
+            Some((srtt, min_rtt, cwnd)) => {
+                let props = PathProperties {
+                    srtt,
+                    min_rtt,
+                    cwnd,
+                    time: now,
+                };
+                entries.put(addr, props);
+            }
+            None => {
+                entries.pop(&addr);
+            }
+        }
+    }
+}
+
 const MAX_BUFFER_SIZE: usize = 2048;
 
 /// PacketQueue is used for sending out packets in batches.
@@ -1043,14 +1312,18 @@ struct PacketQueue {
 
     /// Send buffer pool.
     buffers: VecDeque<Vec<u8>>,
+
+    /// The size of each send buffer.
+    buffer_size: usize,
 }
 
 impl PacketQueue {
-    fn new(batch_size: usize) -> Self {
+    fn new(batch_size: usize, buffer_size: usize) -> Self {
         Self {
             packets: VecDeque::new(),
             batch_size,
             buffers: VecDeque::new(),
+            buffer_size,
         }
     }
 
@@ -1081,7 +1354,7 @@ impl PacketQueue {
     fn drain_front(&mut self, n: usize) {
         let len = cmp::min(n, self.packets.len());
         for mut p in self.packets.drain(..len) {
-            p.0.resize(MAX_BUFFER_SIZE, 0);
+            p.0.resize(self.buffer_size, 0);
             self.buffers.push_back(p.0);
         }
     }
@@ -1090,13 +1363,13 @@ impl PacketQueue {
     fn get_buffer(&mut self) -> Vec<u8> {
         match self.buffers.pop_front() {
             Some(v) => v,
-            None => vec![0; MAX_BUFFER_SIZE],
+            None => vec![0; self.buffer_size],
         }
     }
 
     /// Get a packet buffer from the buffer pool.
     fn put_buffer(&mut self, mut buf: Vec<u8>) {
-        buf.resize(MAX_BUFFER_SIZE, 0);
+        buf.resize(self.buffer_size, 0);
         self.buffers.push_back(buf);
     }
 }
@@ -1105,9 +1378,13 @@ impl PacketQueue {
 mod tests {
     use super::*;
     use crate::connection;
+    use crate::token::AddressTokenKey;
+    use crate::BlockedEvent;
     use crate::Config;
     use crate::CongestionControlAlgorithm;
     use crate::Error;
+    use crate::PersistentCongestionEvent;
+    use crate::StreamEvent;
     use crate::TlsConfig;
     use bytes::Buf;
     use connection::tests::TestPair as TestTool;
@@ -1115,9 +1392,6 @@ mod tests {
     use rand::prelude::SliceRandom;
     use rand::rngs::mock::StepRng;
     use rand::RngCore;
-    use ring::aead;
-    use ring::aead::LessSafeKey;
-    use ring::aead::UnboundKey;
     use std::cmp;
     use std::net::IpAddr;
     use std::net::Ipv4Addr;
@@ -1315,6 +1589,9 @@ mod tests {
                     src: remote,
                     dst: s.socket.local_addr().unwrap(),
                     time: Instant::now(),
+                    ecn: Ecn::NotEct,
+                    dscp: 0,
+                    segment_size: 0,
                 };
                 match e.recv(pkt_buf, &pkt_info) {
                     Ok(_) => {}
@@ -1332,8 +1609,7 @@ mod tests {
             let client_addr = SocketAddr::new(client_ip, 0);
             let token = AddressToken::new_resume_token(client_addr);
 
-            let token_key = LessSafeKey::new(UnboundKey::new(&aead::AES_128_GCM, &key).unwrap());
-            token.encode(&token_key).unwrap()
+            token.encode(&AddressTokenKey::new(key)).unwrap()
         }
 
         /// Create test config for endpoint
@@ -1603,14 +1879,21 @@ mod tests {
                 // Simulate event of packet corruption
                 self.try_mangle_packet(&mut pkt, &info);
 
-                // Send the packet out.
-                if let Err(e) = self.socket.send_to(&pkt, info.dst) {
-                    if e.kind() == std::io::ErrorKind::WouldBlock {
-                        return Ok(count);
+                // Send the packet out, and emulate UDP generic segmentation
+                // offload if the buffer contains multiple datagrams.
+                let segment_size = match info.segment_size {
+                    0 => pkt.len(),
+                    v => v,
+                };
+                for segment in pkt.chunks(segment_size) {
+                    if let Err(e) = self.socket.send_to(segment, info.dst) {
+                        if e.kind() == std::io::ErrorKind::WouldBlock {
+                            return Ok(count);
+                        }
+                        return Err(crate::Error::InvalidOperation(
+                            format!("send_to(): {:?}", e).into(),
+                        ));
                     }
-                    return Err(crate::Error::InvalidOperation(
-                        format!("send_to(): {:?}", e).into(),
-                    ));
                 }
                 trace!("{} write {} bytes {:?}", &self.trace_id, pkt.len(), info);
                 count += 1;
@@ -1834,6 +2117,21 @@ mod tests {
         fn on_new_token(&mut self, conn: &mut Connection, token: Vec<u8>) {
             self.token = Some(token);
         }
+
+        fn on_ping_acked(&mut self, conn: &mut Connection, tag: u64, rtt: Duration) {}
+
+        fn on_flow_control_blocked(&mut self, conn: &mut Connection, event: BlockedEvent) {}
+
+        fn on_target_bitrate_updated(&mut self, conn: &mut Connection, bitrate: u64) {}
+
+        fn on_persistent_congestion(
+            &mut self,
+            conn: &mut Connection,
+            event: PersistentCongestionEvent,
+        ) {
+        }
+
+        fn on_stream_event(&mut self, conn: &mut Connection, stream_id: u64, event: StreamEvent) {}
     }
 
     struct ServerStreamContext {
@@ -1915,6 +2213,21 @@ mod tests {
         }
 
         fn on_new_token(&mut self, conn: &mut Connection, token: Vec<u8>) {}
+
+        fn on_ping_acked(&mut self, conn: &mut Connection, tag: u64, rtt: Duration) {}
+
+        fn on_flow_control_blocked(&mut self, conn: &mut Connection, event: BlockedEvent) {}
+
+        fn on_target_bitrate_updated(&mut self, conn: &mut Connection, bitrate: u64) {}
+
+        fn on_persistent_congestion(
+            &mut self,
+            conn: &mut Connection,
+            event: PersistentCongestionEvent,
+        ) {
+        }
+
+        fn on_stream_event(&mut self, conn: &mut Connection, stream_id: u64, event: StreamEvent) {}
     }
 
     // Test Initial packet
@@ -2319,6 +2632,41 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn endpoint_version_negtiation_with_pinned_version() -> Result<()> {
+        let mut initial = TEST_INITIAL.clone();
+
+        // Server only accepts QUIC version 2
+        let mut conf = TestPair::new_test_config(true)?;
+        conf.set_versions(&[crate::QUIC_VERSION_V2])?;
+        let sock = Rc::new(MockSocket::new());
+        let mut e = Endpoint::new(
+            Box::new(conf),
+            true,
+            Box::new(ServerHandler::new(
+                CaseConf::default(),
+                Arc::new(AtomicBool::new(false)),
+            )),
+            sock.clone(),
+        );
+        let info = TestTool::new_test_packet_info(false);
+
+        // Server recv an Initial with QUIC version 1
+        e.recv(&mut initial, &info)?;
+        e.process_connections()?;
+        assert_eq!(e.conns.len(), 0);
+
+        // Server send Version Negoiation which only lists QUIC version 2
+        let packets = sock.packets.borrow();
+        assert!(packets.len() > 0);
+
+        let (packet, _) = &packets[0];
+        let (hdr, hdr_len) = PacketHeader::from_bytes(&packet, 8)?;
+        assert_eq!(hdr.pkt_type, PacketType::VersionNegotiation);
+        assert_eq!(&packet[hdr_len..], &crate::QUIC_VERSION_V2.to_be_bytes());
+        Ok(())
+    }
+
     #[test]
     fn endpoint_stateless_reset_for_restart() -> Result<()> {
         let new_endpoint = |is_server, conf, sock: Rc<MockSocket>| -> Endpoint {
@@ -2438,6 +2786,167 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn endpoint_stateless_reset_rate_limited() -> Result<()> {
+        let mut conf = TestPair::new_test_config(true)?;
+        conf.set_stateless_response_rate(2);
+        let sock = Rc::new(MockSocket::new());
+        let mut e = Endpoint::new(
+            Box::new(conf),
+            true,
+            Box::new(ServerHandler::new(
+                CaseConf::default(),
+                Arc::new(AtomicBool::new(false)),
+            )),
+            sock.clone(),
+        );
+
+        // Endpoint recv unknown packets from the same source.
+        let info = TestTool::new_test_packet_info(true);
+        for _ in 0..5 {
+            let mut pkt_unknown = Vec::from(TEST_STATELESS_RESET);
+            e.recv(&mut pkt_unknown, &info)?;
+        }
+
+        // Endpoint send limited stateless resets
+        e.process_connections()?;
+        assert_eq!(sock.packets.borrow().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn endpoint_retry_rate_limited() -> Result<()> {
+        for (rate, expected) in [(0, 5), (2, 2)] {
+            let mut conf = TestPair::new_test_config(true)?;
+            conf.enable_retry(true);
+            conf.set_stateless_response_rate(rate);
+            let sock = Rc::new(MockSocket::new());
+            let mut e = Endpoint::new(
+                Box::new(conf),
+                true,
+                Box::new(ServerHandler::new(
+                    CaseConf::default(),
+                    Arc::new(AtomicBool::new(false)),
+                )),
+                sock.clone(),
+            );
+
+            // Server recv Initials without token from the same source
+            let info = TestTool::new_test_packet_info(false);
+            for _ in 0..5 {
+                let mut initial = TEST_INITIAL.clone();
+                e.recv(&mut initial, &info)?;
+            }
+            e.process_connections()?;
+            assert_eq!(e.conns.len(), 0);
+
+            // Server send Retry packets up to the limit
+            let packets = sock.packets.borrow();
+            assert_eq!(packets.len(), expected);
+            for (packet, _) in packets.iter() {
+                let (hdr, _) = PacketHeader::from_bytes(&packet, 8)?;
+                assert_eq!(hdr.pkt_type, PacketType::Retry);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn endpoint_version_negotiation_rate_limited() -> Result<()> {
+        for (rate, expected) in [(0, 5), (2, 2)] {
+            let mut conf = TestPair::new_test_config(true)?;
+            conf.set_stateless_response_rate(rate);
+            let sock = Rc::new(MockSocket::new());
+            let mut e = Endpoint::new(
+                Box::new(conf),
+                true,
+                Box::new(ServerHandler::new(
+                    CaseConf::default(),
+                    Arc::new(AtomicBool::new(false)),
+                )),
+                sock.clone(),
+            );
+
+            // Server recv Initials with unknown version from the same source
+            let info = TestTool::new_test_packet_info(false);
+            for _ in 0..5 {
+                let mut initial_unknown_ver = TEST_INITIAL.clone();
+                initial_unknown_ver[1] = 0x73;
+                e.recv(&mut initial_unknown_ver, &info)?;
+            }
+            e.process_connections()?;
+
+            // Server send Version Negotiation packets up to the limit
+            let packets = sock.packets.borrow();
+            assert_eq!(packets.len(), expected);
+            for (packet, _) in packets.iter() {
+                let (hdr, _) = PacketHeader::from_bytes(&packet, 8)?;
+                assert_eq!(hdr.pkt_type, PacketType::VersionNegotiation);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn stateless_limiter() {
+        let addr: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        // Rate limited per source address
+        let mut l = StatelessLimiter::new(2);
+        assert!(l.allow(addr, now));
+        assert!(l.allow(addr, now));
+        assert!(!l.allow(addr, now));
+        assert!(l.allow(other, now));
+
+        // Tokens are refilled in the next window
+        let now = now + Duration::from_secs(1);
+        assert!(l.allow(addr, now));
+        assert!(l.allow(addr, now));
+        assert!(!l.allow(addr, now));
+
+        // No limit
+        let mut l = StatelessLimiter::new(0);
+        for _ in 0..100 {
+            assert!(l.allow(addr, now));
+        }
+    }
+
+    #[test]
+    fn path_cache() {
+        let addr: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+        let now = Instant::now();
+        let srtt = Duration::from_millis(50);
+        let min_rtt = Duration::from_millis(40);
+
+        // Path properties are saved per peer host
+        let mut c = PathCache::new(2, Duration::from_secs(10));
+        assert_eq!(c.get(addr, now), None);
+        c.update(addr, Some((srtt, min_rtt, 100_000)), now);
+        let p = c.get(addr, now).unwrap();
+        assert_eq!((p.srtt, p.min_rtt, p.cwnd), (srtt, min_rtt, 100_000));
+        assert_eq!(c.get(other, now), None);
+
+        // Invalidated by a connection without RTT samples
+        c.update(addr, None, now);
+        assert_eq!(c.get(addr, now), None);
+
+        // Expired entries are not used
+        c.update(other, Some((srtt, min_rtt, 100_000)), now);
+        assert!(c.get(other, now + Duration::from_secs(10)).is_some());
+        assert_eq!(c.get(other, now + Duration::from_secs(11)), None);
+
+        // Disabled
+        let mut c = PathCache::new(0, Duration::from_secs(10));
+        c.update(addr, Some((srtt, min_rtt, 100_000)), now);
+        assert_eq!(c.get(addr, now), None);
+    }
+
     #[test]
     fn endpoint_client_recv_invalid_initial() -> Result<()> {
         let sock = Rc::new(MockSocket::new());
@@ -2513,6 +3022,23 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn transfer_single_stream_with_send_segments() -> Result<()> {
+        let mut t = TestPair::new();
+
+        let mut cli_conf = TestPair::new_test_config(false)?;
+        cli_conf.set_max_send_segments(8);
+        let mut srv_conf = TestPair::new_test_config(true)?;
+        srv_conf.set_max_send_segments(8);
+
+        let mut case_conf = CaseConf::default();
+        case_conf.request_num = 1;
+        case_conf.request_size = 1024 * 64;
+
+        t.run(cli_conf, srv_conf, case_conf)?;
+        Ok(())
+    }
+
     #[test]
     fn transfer_single_stream_0rtt_and_1rtt() -> Result<()> {
         let mut t = TestPair::new();
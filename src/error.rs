@@ -105,6 +105,10 @@ pub enum Error {
     /// was not covered by more specific error codes.
     MultipathProtocolViolation,
 
+    /// An endpoint detected an error in the version negotiation, such as a
+    /// possible downgrade attack. See RFC 9368 Section 10.2
+    VersionNegotiationError,
+
     /* Note: Private error codes are as follows */
     /// There is no more work to do.
     Done,
@@ -177,6 +181,7 @@ impl Error {
             Error::NoViablePath => 0x10,
             Error::CryptoError(v) => v as u64,
             Error::MultipathProtocolViolation => 0x1001d76d3ded42f3,
+            Error::VersionNegotiationError => 0x11,
             _ => 0x0,
         }
     }
@@ -203,6 +208,7 @@ impl Error {
             Error::NoViablePath => -16,
             Error::CryptoError(_) => -17,
             Error::MultipathProtocolViolation => -18,
+            Error::VersionNegotiationError => -19,
             Error::Done => -100,
             Error::BufferTooShort => -101,
             Error::UnknownVersion => -102,
@@ -28,6 +28,7 @@ use std::slice;
 use std::str::FromStr;
 use std::sync::atomic;
 use std::sync::Arc;
+use std::time::Duration;
 use std::time::Instant;
 
 #[cfg(unix)]
@@ -192,12 +193,52 @@ pub extern "C" fn quic_config_set_max_idle_timeout(config: &mut Config, v: u64)
     config.set_max_idle_timeout(v);
 }
 
+/// Set the acceptable QUIC versions in the order of preference.
+/// The default value is `[QUIC_VERSION_V1]`.
+#[no_mangle]
+pub extern "C" fn quic_config_set_versions(
+    config: &mut Config,
+    versions: *const u32,
+    versions_len: size_t,
+) -> c_int {
+    let versions = unsafe { slice::from_raw_parts(versions, versions_len) };
+    match config.set_versions(versions) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
 /// Set handshake timeout in milliseconds. Zero turns the timeout off.
 #[no_mangle]
 pub extern "C" fn quic_config_set_max_handshake_timeout(config: &mut Config, v: u64) {
     config.set_max_handshake_timeout(v);
 }
 
+/// Set the keep-alive interval in milliseconds. A PING frame is sent if
+/// the connection has been quiet for the interval, which is capped to
+/// half of the idle timeout. Zero turns keep-alive off.
+/// The default value is 0.
+#[no_mangle]
+pub extern "C" fn quic_config_set_keep_alive_interval(config: &mut Config, v: u64) {
+    config.set_keep_alive_interval(v);
+}
+
+/// Enable adaptive keep-alive. (Experimental)
+/// The client learns the NAT binding timeout by binary search over quiet
+/// intervals, and sends keep-alives at the longest interval found to keep
+/// the binding. A PATH_CHALLENGE frame is sent after each quiet interval,
+/// and the binding is considered lost if the server then reports a changed
+/// client address, so the search requires the server to provide observed
+/// addresses, see `quic_config_enable_address_discovery()`. Otherwise,
+/// keep-alives are sent at a conservative interval of 15 seconds. The
+/// keep-alive interval, if set, is used as the upper bound of the search.
+/// It does not take effect on the server.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_adaptive_keep_alive(config: &mut Config, v: bool) {
+    config.enable_adaptive_keep_alive(v);
+}
+
 /// Set the `max_udp_payload_size` transport parameter in bytes. It limits
 /// the size of UDP payloads that the endpoint is willing to receive.
 #[no_mangle]
@@ -212,6 +253,39 @@ pub extern "C" fn enable_dplpmtud(config: &mut Config, v: bool) {
     config.enable_dplpmtud(v);
 }
 
+/// Enable Explicit Congestion Notification. The outgoing packets are
+/// marked with ECT(0) if the path is validated as ECN capable, and the
+/// application should set the ECN codepoint of the outgoing packets and
+/// report the ECN codepoint of the incoming packets via `PacketInfo`.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_ecn(config: &mut Config, v: bool) {
+    config.enable_ecn(v);
+}
+
+/// Set the policy of using Explicit Congestion Notification on the paths.
+/// The default value is `QUIC_ECN_POLICY_DISABLED`.
+#[no_mangle]
+pub extern "C" fn quic_config_set_ecn_policy(config: &mut Config, v: EcnPolicy) {
+    config.set_ecn_policy(v);
+}
+
+/// Set the number of ECN validation failures on a path before falling back
+/// to not marking the packets.
+/// The default value is 1.
+#[no_mangle]
+pub extern "C" fn quic_config_set_ecn_max_failures(config: &mut Config, v: u32) {
+    config.set_ecn_max_failures(v);
+}
+
+/// Set whether to test new paths of a connection for ECN capability if
+/// the ECN validation has failed on another path.
+/// The default value is true.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_ecn_retest_on_migration(config: &mut Config, v: bool) {
+    config.enable_ecn_retest_on_migration(v);
+}
+
 /// Set the maximum outgoing UDP payload size in bytes.
 /// It corresponds to the maximum datagram size that DPLPMTUD tries to discovery.
 /// The default value is `1200` which means let DPLPMTUD choose a value.
@@ -265,6 +339,24 @@ pub extern "C" fn quic_config_set_initial_max_streams_uni(config: &mut Config, v
     config.set_initial_max_streams_uni(v);
 }
 
+/// Set the ceiling of the number of concurrent bidirectional streams that the
+/// peer is allowed to open. If the peer consumes the stream credits quickly, the
+/// limit is doubled each time up to the ceiling.
+/// The default value is `0`, which means the limit is not auto-tuned.
+#[no_mangle]
+pub extern "C" fn quic_config_set_max_concurrent_streams_bidi(config: &mut Config, v: u64) {
+    config.set_max_concurrent_streams_bidi(v);
+}
+
+/// Set the ceiling of the number of concurrent unidirectional streams that the
+/// peer is allowed to open. If the peer consumes the stream credits quickly, the
+/// limit is doubled each time up to the ceiling.
+/// The default value is `0`, which means the limit is not auto-tuned.
+#[no_mangle]
+pub extern "C" fn quic_config_set_max_concurrent_streams_uni(config: &mut Config, v: u64) {
+    config.set_max_concurrent_streams_uni(v);
+}
+
 /// Set the `ack_delay_exponent` transport parameter.
 #[no_mangle]
 pub extern "C" fn quic_config_set_ack_delay_exponent(config: &mut Config, v: u64) {
@@ -277,6 +369,27 @@ pub extern "C" fn quic_config_set_max_ack_delay(config: &mut Config, v: u64) {
     config.set_max_ack_delay(v);
 }
 
+/// Enable the ACK frequency extension, which allows the peer to control the
+/// acknowledgement behavior of the endpoint. (Experimental)
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_ack_frequency(config: &mut Config, v: bool) {
+    config.enable_ack_frequency(v);
+}
+
+/// Request the peer to send an acknowledgement after receiving at most
+/// `threshold` ack-eliciting packets, and to delay an acknowledgement by at
+/// most `max_ack_delay` milliseconds, with an ACK_FREQUENCY frame once the
+/// ACK frequency extension is negotiated. (Experimental)
+#[no_mangle]
+pub extern "C" fn quic_config_set_ack_frequency(
+    config: &mut Config,
+    threshold: u64,
+    max_ack_delay: u64,
+) {
+    config.set_ack_frequency(threshold, max_ack_delay);
+}
+
 /// Set congestion control algorithm that the connection would use.
 #[no_mangle]
 pub extern "C" fn quic_config_set_congestion_control_algorithm(
@@ -300,6 +413,24 @@ pub extern "C" fn quic_config_set_min_congestion_window(config: &mut Config, v:
     config.set_min_congestion_window(v);
 }
 
+/// Set the initial congestion window in bytes, which takes precedence over
+/// the initial congestion window in packets. The value 0 means the initial
+/// congestion window is set in packets.
+/// The default value is 0.
+#[no_mangle]
+pub extern "C" fn quic_config_set_initial_congestion_window_bytes(config: &mut Config, v: u64) {
+    config.set_initial_congestion_window_bytes(v);
+}
+
+/// Set the minimal congestion window in bytes, which takes precedence over
+/// the minimal congestion window in packets. The value 0 means the minimal
+/// congestion window is set in packets.
+/// The default value is 0.
+#[no_mangle]
+pub extern "C" fn quic_config_set_min_congestion_window_bytes(config: &mut Config, v: u64) {
+    config.set_min_congestion_window_bytes(v);
+}
+
 /// Set the threshold for slow start in packets.
 /// The default value is the maximum value of u64.
 #[no_mangle]
@@ -307,6 +438,21 @@ pub extern "C" fn quic_config_set_slow_start_thresh(config: &mut Config, v: u64)
     config.set_slow_start_thresh(v);
 }
 
+/// Enable HyStart++ in CUBIC slow start.
+/// The default value is true.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_cubic_hystart(config: &mut Config, v: bool) {
+    config.enable_cubic_hystart(v);
+}
+
+/// Enable Proportional Rate Reduction (RFC 6937) in the loss-based
+/// congestion controllers, i.e. CUBIC and Prague.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_prr(config: &mut Config, v: bool) {
+    config.enable_prr(v);
+}
+
 /// Set the minimum duration for BBR ProbeRTT state in milliseconds.
 /// The default value is 200 milliseconds.
 #[no_mangle]
@@ -360,6 +506,27 @@ pub extern "C" fn quic_config_enable_copa_use_standing_rtt(config: &mut Config,
     config.enable_copa_use_standing_rtt(v);
 }
 
+/// Set the target queueing delay in milliseconds for LEDBAT.
+/// The default value is 60 milliseconds.
+#[no_mangle]
+pub extern "C" fn quic_config_set_ledbat_target_delay(config: &mut Config, millis: u64) {
+    config.set_ledbat_target_delay(millis);
+}
+
+/// Set the minimum target bitrate in bits per second for NADA.
+/// The default value is 150000 bits per second.
+#[no_mangle]
+pub extern "C" fn quic_config_set_nada_min_bitrate(config: &mut Config, bps: u64) {
+    config.set_nada_min_bitrate(bps);
+}
+
+/// Set the maximum target bitrate in bits per second for NADA.
+/// The default value is 1500000 bits per second.
+#[no_mangle]
+pub extern "C" fn quic_config_set_nada_max_bitrate(config: &mut Config, bps: u64) {
+    config.set_nada_max_bitrate(bps);
+}
+
 /// Set the initial RTT in milliseconds. The default value is 333ms.
 /// The configuration should be changed with caution. Setting a value less than the default
 /// will cause retransmission of handshake packets to be more aggressive.
@@ -368,6 +535,17 @@ pub extern "C" fn quic_config_set_initial_rtt(config: &mut Config, v: u64) {
     config.set_initial_rtt(v);
 }
 
+/// Set the probe timeout before the first RTT sample is taken, as a multiple
+/// of the initial RTT. A smaller value retransmits the Initial and Handshake
+/// data earlier. Values less than 1.0 will be ignored, and values greater than
+/// 100.0 will be capped.
+/// By default, the timeout is derived from the initial RTT, which is equivalent
+/// to 3.0.
+#[no_mangle]
+pub extern "C" fn quic_config_set_initial_pto_factor(config: &mut Config, v: f64) {
+    config.set_initial_pto_factor(v);
+}
+
 /// Enable pacing to smooth the flow of packets sent onto the network.
 /// The default value is true.
 #[no_mangle]
@@ -376,12 +554,66 @@ pub extern "C" fn quic_config_enable_pacing(config: &mut Config, v: bool) {
 }
 
 /// Set clock granularity used by the pacer.
-/// The default value is 10 milliseconds.
+/// The default value is 1 milliseconds.
 #[no_mangle]
 pub extern "C" fn quic_config_set_pacing_granularity(config: &mut Config, v: u64) {
     config.set_pacing_granularity(v);
 }
 
+/// Set the minimum number of packets that the pacer may release back to back.
+/// The default value is 10.
+#[no_mangle]
+pub extern "C" fn quic_config_set_pacing_min_burst(config: &mut Config, packets: u64) {
+    config.set_pacing_min_burst(packets);
+}
+
+/// Set the maximum number of packets that the pacer may release back to back.
+/// The default value is 128.
+#[no_mangle]
+pub extern "C" fn quic_config_set_pacing_max_burst(config: &mut Config, packets: u64) {
+    config.set_pacing_max_burst(packets);
+}
+
+/// Set the upper limit of the pacing rate in bytes per second, which caps
+/// the sending rate of each path regardless of the congestion controller.
+/// Pacing is always applied if the limit is set. The value `0` means no
+/// limit. The default value is `0`.
+#[no_mangle]
+pub extern "C" fn quic_config_set_max_pacing_rate(config: &mut Config, v: u64) {
+    config.set_max_pacing_rate(v);
+}
+
+/// Enable offloading pacing to the kernel or hardware, e.g. via SO_TXTIME
+/// with the ETF qdisc. The outgoing packets are not delayed by the pacer,
+/// and the delay to release each packet is exported in `release_delay` of
+/// `quic_packet_out_spec_t`. It takes effect only if pacing is enabled.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_pacing_offload(config: &mut Config, v: bool) {
+    config.enable_pacing_offload(v);
+}
+
+/// Enable Careful Resume for the server. The congestion window of a
+/// connection is jump-started with the path capacity saved in the address
+/// token, which is sent by `quic_conn_send_capacity_token()` in a previous
+/// connection. The saved capacity is used only if the RTT of the path is
+/// similar, and it is validated before use.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_careful_resume(config: &mut Config, v: bool) {
+    config.enable_careful_resume(v);
+}
+
+/// Set the delay in microseconds for coalescing small stream data. The
+/// stream data that is not enough to fill a packet is held for at most
+/// the delay before being sent, so that data written by multiple small
+/// writes can be packed into fewer packets. The value `0` means the data
+/// is sent immediately. The default value is `0`.
+#[no_mangle]
+pub extern "C" fn quic_config_set_send_coalescing_delay(config: &mut Config, v: u64) {
+    config.set_send_coalescing_delay(v);
+}
+
 /// Set the linear factor for calculating the probe timeout.
 /// The endpoint do not backoff the first `v` consecutive probe timeouts.
 /// The default value is `0`.
@@ -401,6 +633,62 @@ pub extern "C" fn quic_config_set_max_pto(config: &mut Config, v: u64) {
     config.set_max_pto(v);
 }
 
+/// Enable adapting the reordering thresholds of loss detection. When a packet
+/// is acknowledged after being declared lost, the packet and time thresholds
+/// are increased to the observed reordering.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_adaptive_reordering(config: &mut Config, v: bool) {
+    config.enable_adaptive_reordering(v);
+}
+
+/// Set the initial time threshold of loss detection, as a multiplier of the RTT.
+/// Values less than 1.0 will be ignored.
+/// The default value is 1.125.
+#[no_mangle]
+pub extern "C" fn quic_config_set_time_reordering_threshold(config: &mut Config, v: f64) {
+    config.set_time_reordering_threshold(v);
+}
+
+/// Set the multiplier of the probe timeout upon each backoff.
+/// The value 1 disables the exponential backoff. Values less than 1 will be ignored.
+/// The default value is 2.
+#[no_mangle]
+pub extern "C" fn quic_config_set_pto_multiplier(config: &mut Config, v: u32) {
+    config.set_pto_multiplier(v);
+}
+
+/// Set the upper limit of the consecutive probe timeout backoffs.
+/// The default value is unlimited.
+#[no_mangle]
+pub extern "C" fn quic_config_set_max_pto_backoff(config: &mut Config, v: u32) {
+    config.set_max_pto_backoff(v);
+}
+
+/// Set the packet threshold of loss detection.
+/// Values less than 1 will be ignored.
+/// The default value is 3.
+#[no_mangle]
+pub extern "C" fn quic_config_set_packet_reordering_threshold(config: &mut Config, v: u64) {
+    config.set_packet_reordering_threshold(v);
+}
+
+/// Set the content of the probe packets sent upon a probe timeout in the
+/// Application Data packet number space.
+/// The default value is QUIC_PTO_PROBE_STRATEGY_OLDEST_UNACKED.
+#[no_mangle]
+pub extern "C" fn quic_config_set_pto_probe_strategy(config: &mut Config, v: PtoProbeStrategy) {
+    config.set_pto_probe_strategy(v);
+}
+
+/// Enable sending the two probe packets of a path on different paths if
+/// multipath is enabled.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_pto_probes_across_paths(config: &mut Config, v: bool) {
+    config.enable_pto_probes_across_paths(v);
+}
+
 /// Set the `active_connection_id_limit` transport parameter.
 #[no_mangle]
 pub extern "C" fn quic_config_set_active_connection_id_limit(config: &mut Config, v: u64) {
@@ -421,6 +709,22 @@ pub extern "C" fn quic_config_set_multipath_algorithm(config: &mut Config, v: Mu
     config.set_multipath_algorithm(v);
 }
 
+/// Set the weights of paths indexed by path id, which are used by the
+/// RoundRobin scheduler. A path with weight N is selected N times in a row
+/// before moving to the next path. Paths without a weight are given 1.
+#[no_mangle]
+pub extern "C" fn quic_config_set_multipath_path_weights(
+    config: &mut Config,
+    weights: *const u32,
+    weights_len: size_t,
+) {
+    let weights = match weights_len {
+        0 => Vec::new(),
+        _ => unsafe { slice::from_raw_parts(weights, weights_len) }.to_vec(),
+    };
+    config.set_multipath_path_weights(weights);
+}
+
 /// Set the maximum size of the connection flow control window.
 /// The default value is MAX_CONNECTION_WINDOW (15 MB).
 #[no_mangle]
@@ -436,12 +740,96 @@ pub extern "C" fn quic_config_set_max_stream_window(config: &mut Config, v: u64)
     config.set_max_stream_window(v);
 }
 
+/// Set the number of RTTs without a flow control window update after which
+/// an auto-tuned receive window is halved. By default, the window is never
+/// shrunk below its initial size. `0` disables window decay.
+/// The default value is `0`.
+#[no_mangle]
+pub extern "C" fn quic_config_set_window_decay_rtts(config: &mut Config, v: u32) {
+    config.set_window_decay_rtts(v);
+}
+
+/// Set the floor of the receive windows when they decay.
+/// The default value is `0`, which means the initial window sizes are used as
+/// the floors.
+#[no_mangle]
+pub extern "C" fn quic_config_set_window_decay_floor(config: &mut Config, v: u64) {
+    config.set_window_decay_floor(v);
+}
+
+/// Set the target interval between flow control window updates, in units of
+/// srtt. If window updates occur within the interval, the window is grown by
+/// `flow_control_autotune_factor`. The value is capped at `100.0`, and NaN
+/// will be ignored.
+/// The default value is `2.0`.
+#[no_mangle]
+pub extern "C" fn quic_config_set_flow_control_autotune_interval_rtts(config: &mut Config, v: f64) {
+    config.set_flow_control_autotune_interval_rtts(v);
+}
+
+/// Set the growth factor of the flow control window when auto-tuning.
+/// The value should not be less than `1.0`.
+/// The default value is `2.0`.
+#[no_mangle]
+pub extern "C" fn quic_config_set_flow_control_autotune_factor(config: &mut Config, v: f64) {
+    config.set_flow_control_autotune_factor(v);
+}
+
+/// Enable allocating the stream receive windows dynamically from the connection
+/// receive window. If enabled, the credit advertised in a MAX_STREAM_DATA frame is
+/// limited to the connection credit that has not been allocated to other streams,
+/// but no less than a fair share of the connection window.
+/// The default value is `false`.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_stream_window_sharing(config: &mut Config, v: bool) {
+    config.enable_stream_window_sharing(v);
+}
+
+/// Set the policy of scheduling the data of sendable streams into packets.
+/// The default value is StreamSchedulingPolicy::Priority
+#[no_mangle]
+pub extern "C" fn quic_config_set_stream_scheduling_policy(
+    config: &mut Config,
+    v: StreamSchedulingPolicy,
+) {
+    config.set_stream_scheduling_policy(v);
+}
+
+/// Set the maximum size of data received in out-of-order CRYPTO frames
+/// that can be buffered for each encryption level. The connection is
+/// closed with a CRYPTO_BUFFER_EXCEEDED error if the peer sends more.
+/// The value should not be less than 4096.
+/// The default value is 65536.
+#[no_mangle]
+pub extern "C" fn quic_config_set_max_crypto_buffer_size(config: &mut Config, v: u64) {
+    config.set_max_crypto_buffer_size(v);
+}
+
 /// Set the Maximum number of concurrent connections.
 #[no_mangle]
 pub extern "C" fn quic_config_set_max_concurrent_conns(config: &mut Config, v: u32) {
     config.set_max_concurrent_conns(v);
 }
 
+/// Set the maximum memory in bytes used by all the connections of an endpoint.
+/// If it is exceeded, the endpoint refuses new connections and closes the
+/// connection using the most memory.
+/// The default value is `0`, which means no limit.
+#[no_mangle]
+pub extern "C" fn quic_config_set_max_memory_usage(config: &mut Config, v: u64) {
+    config.set_max_memory_usage(v);
+}
+
+/// Set the maximum sum in bytes of the receiver connection flow control windows
+/// of all the connections of an endpoint. When the budget is tight, window
+/// autotuning is suppressed, and the growth of the windows advertised in
+/// MAX_DATA frames is limited to the remaining budget.
+/// The default value is `0`, which means no limit.
+#[no_mangle]
+pub extern "C" fn quic_config_set_max_recv_window_budget(config: &mut Config, v: u64) {
+    config.set_max_recv_window_budget(v);
+}
+
 /// Set the key for reset token generation. The token_key_len should be not less
 /// than 64.
 /// Applicable to Server only.
@@ -472,7 +860,8 @@ pub extern "C" fn quic_config_set_address_token_lifetime(config: &mut Config, se
 }
 
 /// Set the key for address token generation. It also enables retry.
-/// The token_key_len should be a multiple of 16.
+/// The token_key_len should be a multiple of 16. The first key is used for
+/// generating new tokens, and all of the keys are used for validating tokens.
 /// Applicable to Server only.
 #[no_mangle]
 pub extern "C" fn quic_config_set_address_token_key(
@@ -515,6 +904,35 @@ pub extern "C" fn quic_config_enable_stateless_reset(config: &mut Config, enable
     config.enable_stateless_reset(enabled);
 }
 
+/// Set the maximum number of stateless packets (Version Negotiation, Retry
+/// and Stateless Reset) sent to a source address per second.
+/// The value 0 means no limit. Setting a limit also drops Retry packets
+/// beyond it, which may delay the clients behind a shared NAT address.
+/// The default value is `0`.
+/// Applicable to Endpoint only.
+#[no_mangle]
+pub extern "C" fn quic_config_set_stateless_response_rate(config: &mut Config, v: u32) {
+    config.set_stateless_response_rate(v);
+}
+
+/// Set the maximum number of peer hosts whose path properties are cached
+/// by the endpoint. A new connection to a cached host inherits the RTT and
+/// congestion window estimates of the previous connections.
+/// The default value is `0`, which means the cache is disabled.
+/// Applicable to Endpoint only.
+#[no_mangle]
+pub extern "C" fn quic_config_set_path_cache_size(config: &mut Config, v: usize) {
+    config.set_path_cache_size(v);
+}
+
+/// Set the lifetime in seconds of the cached path properties.
+/// The default value is `600`.
+/// Applicable to Endpoint only.
+#[no_mangle]
+pub extern "C" fn quic_config_set_path_cache_lifetime(config: &mut Config, seconds: u64) {
+    config.set_path_cache_lifetime(seconds);
+}
+
 /// Set the length of source cid. The length should not be greater than 20.
 /// Applicable to Endpoint only.
 #[no_mangle]
@@ -538,6 +956,17 @@ pub extern "C" fn quic_config_set_send_batch_size(config: &mut Config, v: u16) {
     config.set_send_batch_size(v as usize);
 }
 
+/// Set the maximum number of UDP datagrams of the same size written to a
+/// single packet buffer. If it is greater than 1, the buffer should be sent
+/// with UDP_SEGMENT according to `segment_size` of `quic_packet_out_spec_t`.
+/// The value is capped to 64.
+/// Applicable to Endpoint only.
+/// The default value is 1.
+#[no_mangle]
+pub extern "C" fn quic_config_set_max_send_segments(config: &mut Config, v: u16) {
+    config.set_max_send_segments(v as usize);
+}
+
 /// Set the buffer size for disordered zerortt packets on the server.
 /// The default value is `1000`. A value of 0 will be treated as default value.
 /// Applicable to Server only.
@@ -562,6 +991,70 @@ pub extern "C" fn quic_config_enable_encryption(config: &mut Config, v: bool) {
     config.enable_encryption(v);
 }
 
+/// Set whether to advertise the grease_quic_bit transport parameter, which
+/// allows the peer to set the QUIC Bit of packets to an unpredictable value.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_grease_quic_bit(config: &mut Config, v: bool) {
+    config.enable_grease_quic_bit(v);
+}
+
+/// Set whether to use the latency spin bit, which allows on-path
+/// observers to measure the RTT of the connection. Even if enabled, the
+/// spin bit is disabled on a random selection of one in every 16
+/// connections.
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_spin_bit(config: &mut Config, v: bool) {
+    config.enable_spin_bit(v);
+}
+
+/// Set the DSCP codepoint of the outgoing packets, which allows network
+/// level prioritization of the traffic. Only the lower 6 bits are used.
+/// The default value is `0` (Default Forwarding).
+#[no_mangle]
+pub extern "C" fn quic_config_set_dscp(config: &mut Config, v: u8) {
+    config.set_dscp(v);
+}
+
+/// Set the average interval in packets between the packet numbers which are
+/// skipped on purpose in the application data space, for detecting the
+/// optimistic ACK attack. The value 0 disables skipping packet numbers.
+/// The default value is 0.
+#[no_mangle]
+pub extern "C" fn quic_config_set_packet_number_skip_interval(config: &mut Config, v: u64) {
+    config.set_packet_number_skip_interval(v);
+}
+
+/// Set the granularity in milliseconds for coalescing the timers of a
+/// connection. The value 0 disables coalescing.
+/// The default value is 0.
+#[no_mangle]
+pub extern "C" fn quic_config_set_timer_granularity(config: &mut Config, v: u64) {
+    config.set_timer_granularity(v);
+}
+
+/// Enable the BDP_FRAME extension, which allows the server to share the
+/// measured path capacity with the client. (Experimental)
+/// The default value is false.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_bdp_frame(config: &mut Config, v: bool) {
+    config.enable_bdp_frame(v);
+}
+
+/// Set whether to report the observed addresses to the peer, and whether to
+/// receive the observed addresses from the peer, with the address discovery
+/// extension. (Experimental)
+/// The default value is false for both.
+#[no_mangle]
+pub extern "C" fn quic_config_enable_address_discovery(
+    config: &mut Config,
+    provide: bool,
+    receive: bool,
+) {
+    config.enable_address_discovery(provide, receive);
+}
+
 /// Create a new TlsConfig.
 /// The caller is responsible for the memory of the TlsConfig and should properly
 /// destroy it by calling `quic_tls_config_free`.
@@ -1058,7 +1551,9 @@ pub extern "C" fn quic_conn_server_name(
     }
 }
 
-/// Return the session data used by resumption.
+/// Return the session data used by resumption and 0-RTT. It is an opaque blob
+/// which bundles the session ticket, the transport parameters of the server,
+/// and the negotiated version and application protocol.
 #[no_mangle]
 pub extern "C" fn quic_conn_session(
     conn: &mut Connection,
@@ -1074,6 +1569,30 @@ pub extern "C" fn quic_conn_session(
     }
 }
 
+/// Copy the session data bundled with the current BDP estimate of the
+/// connection into the given buffer. Passing it to `quic_endpoint_connect()` for
+/// the next connection to the same peer avoids slow window ramp-up.
+/// Return the number of bytes copied, `0` if no session data is available, or
+/// a negative error code if the buffer is too short.
+#[no_mangle]
+pub extern "C" fn quic_conn_session_with_bdp(
+    conn: &mut Connection,
+    out: *mut u8,
+    out_len: size_t,
+) -> ssize_t {
+    let session = match conn.session_with_bdp() {
+        Some(v) => v,
+        None => return 0,
+    };
+    if session.len() > out_len {
+        return Error::BufferTooShort.to_errno() as ssize_t;
+    }
+
+    let out = unsafe { slice::from_raw_parts_mut(out, out_len) };
+    out[..session.len()].copy_from_slice(&session);
+    session.len() as ssize_t
+}
+
 /// Return details why 0-RTT was accepted or rejected.
 #[no_mangle]
 pub extern "C" fn quic_conn_early_data_reason(
@@ -1096,6 +1615,77 @@ pub extern "C" fn quic_conn_early_data_reason(
     }
 }
 
+/// Share the path capacity measured on the connection with the client in a
+/// BDP_FRAME frame. Applicable to Server only.
+#[no_mangle]
+pub extern "C" fn quic_conn_send_bdp_frame(conn: &mut Connection) -> c_int {
+    match conn.send_bdp_frame() {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Request the peer to send an ACK frame immediately with an IMMEDIATE_ACK
+/// frame. The ACK frequency extension should be supported by the peer.
+#[no_mangle]
+pub extern "C" fn quic_conn_send_immediate_ack(conn: &mut Connection) -> c_int {
+    match conn.send_immediate_ack() {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Issue an address token carrying the capacity of the active path to the
+/// client in a NEW_TOKEN frame, which could be used for Careful Resume in a
+/// future connection. Applicable to Server only.
+#[no_mangle]
+pub extern "C" fn quic_conn_send_capacity_token(conn: &mut Connection) -> c_int {
+    match conn.send_capacity_token() {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Set the upper limit of the pacing rate in bytes per second for the
+/// connection, which caps the sending rate of each path regardless of the
+/// congestion controller. The value `0` means no limit.
+#[no_mangle]
+pub extern "C" fn quic_conn_set_max_pacing_rate(conn: &mut Connection, v: u64) {
+    conn.set_max_pacing_rate(v);
+}
+
+/// Switch the congestion control algorithm of the connection. The RTT
+/// samples of each path are kept, and the congestion window is migrated to
+/// the new congestion controller if the old one has left slow start.
+#[no_mangle]
+pub extern "C" fn quic_conn_set_congestion_control_algorithm(
+    conn: &mut Connection,
+    v: CongestionControlAlgorithm,
+) {
+    conn.set_congestion_control_algorithm(v);
+}
+
+/// Get the path capacity shared by the server in a BDP_FRAME frame. The
+/// `lifetime` is in seconds and the `saved_rtt` is in microseconds.
+/// Return false if the server has not shared it. Applicable to Client only.
+#[no_mangle]
+pub extern "C" fn quic_conn_peer_bdp(
+    conn: &mut Connection,
+    lifetime: &mut u64,
+    saved_capacity: &mut u64,
+    saved_rtt: &mut u64,
+) -> bool {
+    match conn.peer_bdp() {
+        Some(bdp) => {
+            *lifetime = bdp.lifetime.as_secs();
+            *saved_capacity = bdp.saved_capacity;
+            *saved_rtt = bdp.saved_rtt.as_micros() as u64;
+            true
+        }
+        None => false,
+    }
+}
+
 /// Send a Ping frame on the active path(s) for keep-alive.
 #[no_mangle]
 pub extern "C" fn quic_conn_ping(conn: &mut Connection) -> c_int {
@@ -1125,6 +1715,61 @@ pub extern "C" fn quic_conn_ping_path(
     }
 }
 
+/// Send a Ping frame carrying the given tag on the active path. The RTT
+/// measured for the probe is reported by the `on_ping_acked` callback.
+#[no_mangle]
+pub extern "C" fn quic_conn_ping_with_tag(conn: &mut Connection, tag: u64) -> c_int {
+    match conn.ping_with_tag(tag) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Set the policy of using ECN on the specified path, and restart the ECN
+/// validation on the path.
+#[no_mangle]
+pub extern "C" fn quic_conn_set_path_ecn_policy(
+    conn: &mut Connection,
+    local: &sockaddr,
+    local_len: socklen_t,
+    remote: &sockaddr,
+    remote_len: socklen_t,
+    policy: EcnPolicy,
+) -> c_int {
+    let local = sock_addr_from_c(local, local_len);
+    let remote = sock_addr_from_c(remote, remote_len);
+    match conn.set_path_ecn_policy(local, remote, policy) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Set the DSCP codepoint of the outgoing packets of the connection.
+/// Only the lower 6 bits are used.
+#[no_mangle]
+pub extern "C" fn quic_conn_set_dscp(conn: &mut Connection, dscp: u8) {
+    conn.set_dscp(dscp);
+}
+
+/// Set the DSCP codepoint of the outgoing packets on the specified path.
+/// Only the lower 6 bits are used.
+#[no_mangle]
+pub extern "C" fn quic_conn_set_path_dscp(
+    conn: &mut Connection,
+    local: &sockaddr,
+    local_len: socklen_t,
+    remote: &sockaddr,
+    remote_len: socklen_t,
+    dscp: u8,
+) -> c_int {
+    let local = sock_addr_from_c(local, local_len);
+    let remote = sock_addr_from_c(remote, remote_len);
+    match conn.set_path_dscp(local, remote, dscp) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
 /// Add a new path on the client connection.
 #[no_mangle]
 pub extern "C" fn quic_conn_add_path(
@@ -1233,6 +1878,21 @@ pub extern "C" fn quic_conn_active_path(conn: &Connection, a: &mut PathAddress)
     false
 }
 
+/// Return the local address observed by the peer on the active path, which is
+/// reported with the address discovery extension.
+#[no_mangle]
+pub extern "C" fn quic_conn_observed_addr(
+    conn: &Connection,
+    addr: &mut sockaddr_storage,
+    addr_len: &mut socklen_t,
+) -> bool {
+    if let Some(v) = conn.get_active_path().ok().and_then(|p| p.observed_addr()) {
+        *addr_len = sock_addr_to_c(&v, addr);
+        return true;
+    }
+    false
+}
+
 /// Return the latest statistics about the specified path.
 #[no_mangle]
 pub extern "C" fn quic_conn_path_stats<'a>(
@@ -1460,6 +2120,45 @@ pub extern "C" fn quic_stream_wantwrite(
     }
 }
 
+/// Set want write flag for a stream with a writable threshold. The stream is
+/// reported writable only when at least `bytes` bytes of send credit, limited by
+/// both flow control and the congestion window, is available. Setting `bytes`
+/// to `0` removes the threshold.
+#[no_mangle]
+pub extern "C" fn quic_stream_wantwrite_threshold(
+    conn: &mut Connection,
+    stream_id: u64,
+    bytes: size_t,
+) -> c_int {
+    match conn.stream_want_write_threshold(stream_id, bytes) {
+        Ok(_) | Err(Error::Done) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Pause or resume the advertisement of the stream-level flow control limit.
+/// While paused, no MAX_STREAM_DATA frame is sent for the stream regardless of
+/// how much data has been read.
+#[no_mangle]
+pub extern "C" fn quic_stream_set_flow_control_paused(
+    conn: &mut Connection,
+    stream_id: u64,
+    paused: bool,
+) -> c_int {
+    match conn.stream_set_flow_control_paused(stream_id, paused) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Pause or resume the advertisement of the connection-level flow control
+/// limit. While paused, no MAX_DATA frame is sent regardless of how much data
+/// has been read.
+#[no_mangle]
+pub extern "C" fn quic_conn_set_flow_control_paused(conn: &mut Connection, paused: bool) {
+    conn.set_flow_control_paused(paused);
+}
+
 /// Set want read flag for a stream.
 #[no_mangle]
 pub extern "C" fn quic_stream_wantread(conn: &mut Connection, stream_id: u64, want: bool) -> c_int {
@@ -1469,6 +2168,49 @@ pub extern "C" fn quic_stream_wantread(conn: &mut Connection, stream_id: u64, wa
     }
 }
 
+/// Subscribe to or unsubscribe from the reset event of a stream. The
+/// application is notified once a RESET_STREAM frame is received.
+#[no_mangle]
+pub extern "C" fn quic_stream_wantreset(
+    conn: &mut Connection,
+    stream_id: u64,
+    want: bool,
+) -> c_int {
+    match conn.stream_want_reset(stream_id, want) {
+        Ok(_) | Err(Error::Done) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Subscribe to or unsubscribe from the stop sending event of a stream. The
+/// application is notified once a STOP_SENDING frame is received.
+#[no_mangle]
+pub extern "C" fn quic_stream_wantstopsending(
+    conn: &mut Connection,
+    stream_id: u64,
+    want: bool,
+) -> c_int {
+    match conn.stream_want_stop_sending(stream_id, want) {
+        Ok(_) | Err(Error::Done) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
+/// Subscribe to the acknowledgement of the data sent on a stream. The
+/// application is notified once the data is acknowledged contiguously up to
+/// `offset`. Setting `offset` to `0` removes the subscription.
+#[no_mangle]
+pub extern "C" fn quic_stream_wantacked(
+    conn: &mut Connection,
+    stream_id: u64,
+    offset: u64,
+) -> c_int {
+    match conn.stream_want_acked(stream_id, offset) {
+        Ok(_) | Err(Error::Done) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
 /// Read data from a stream.
 #[no_mangle]
 pub extern "C" fn quic_stream_read(
@@ -1585,6 +2327,21 @@ pub extern "C" fn quic_stream_set_priority(
     }
 }
 
+/// Set the receive-side flow control window and maximum window for a stream.
+/// Credit that has already been issued to the peer is never withdrawn.
+#[no_mangle]
+pub extern "C" fn quic_stream_set_recv_window(
+    conn: &mut Connection,
+    stream_id: u64,
+    window: u64,
+    max_window: u64,
+) -> c_int {
+    match conn.stream_set_recv_window(stream_id, window, max_window) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
 /// Return the stream’s send capacity in bytes.
 #[no_mangle]
 pub extern "C" fn quic_stream_capacity(conn: &mut Connection, stream_id: u64) -> ssize_t {
@@ -1628,6 +2385,49 @@ pub extern "C" fn quic_stream_context(conn: &mut Connection, stream_id: u64) ->
     }
 }
 
+/// The callback which decides whether the lost data of a stream is retransmitted.
+pub type StreamLossCallback =
+    fn(ctx: *mut c_void, stream_id: u64, offset: u64, length: size_t, err: *mut u64) -> bool;
+
+struct StreamLossHook {
+    callback: StreamLossCallback,
+    context: Context,
+}
+
+impl StreamLossPolicy for StreamLossHook {
+    fn on_data_lost(&self, stream_id: u64, offset: u64, length: usize) -> LostDataAction {
+        let mut err = 0;
+        if (self.callback)(self.context.0, stream_id, offset, length, &mut err) {
+            LostDataAction::Retransmit
+        } else {
+            LostDataAction::Reset(err)
+        }
+    }
+}
+
+/// Set the callback which decides whether the lost data of a stream is
+/// retransmitted. The callback returns true to retransmit the data, or false
+/// to reset the send-side of the stream with the error code written to `err`.
+/// A null callback restores the default, which always retransmits.
+#[no_mangle]
+pub extern "C" fn quic_stream_set_loss_callback(
+    conn: &mut Connection,
+    stream_id: u64,
+    callback: Option<StreamLossCallback>,
+    ctx: *mut c_void,
+) -> c_int {
+    let policy = callback.map(|callback| {
+        Arc::new(StreamLossHook {
+            callback,
+            context: Context(ctx),
+        }) as Arc<dyn StreamLossPolicy>
+    });
+    match conn.stream_set_loss_policy(stream_id, policy) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno() as c_int,
+    }
+}
+
 #[repr(transparent)]
 pub struct TlsConfigSelectorContext(*mut c_void);
 
@@ -1712,6 +2512,91 @@ pub struct TransportMethods {
     /// is optional.
     pub on_new_token:
         Option<fn(tctx: *mut c_void, conn: &mut Connection, token: *const u8, token_len: size_t)>,
+
+    /// Called when the Ping frame sent by `quic_conn_ping_with_tag()` is
+    /// acknowledged by the peer, with the RTT in microseconds measured for
+    /// the probe. This callback is optional.
+    pub on_ping_acked: Option<fn(tctx: *mut c_void, conn: &mut Connection, tag: u64, rtt: u64)>,
+
+    /// Called when the connection is blocked by the flow control limits of
+    /// the peer, or the peer reports that it is blocked by the local limits.
+    /// The `stream_id` is only meaningful for stream-level blocking. This
+    /// callback is optional.
+    pub on_flow_control_blocked: Option<
+        fn(
+            tctx: *mut c_void,
+            conn: &mut Connection,
+            blocked_type: BlockedType,
+            stream_id: u64,
+            limit: u64,
+        ),
+    >,
+
+    /// Called when the target bitrate in bits per second for the media
+    /// encoder is updated by a congestion controller designed for real-time
+    /// media, such as NADA. This callback is optional.
+    pub on_target_bitrate_updated:
+        Option<fn(tctx: *mut c_void, conn: &mut Connection, bitrate: u64)>,
+
+    /// Called when a persistent congestion is declared on a path, with the
+    /// duration in microseconds of the congestion period, the number of
+    /// packets lost in the period, and the resulting congestion window in
+    /// bytes. This callback is optional.
+    pub on_persistent_congestion: Option<
+        fn(
+            tctx: *mut c_void,
+            conn: &mut Connection,
+            duration: u64,
+            lost_packets: u64,
+            congestion_window: u64,
+        ),
+    >,
+
+    /// Called when a stream event subscribed by `quic_stream_wantreset()`,
+    /// `quic_stream_wantstopsending()` or `quic_stream_wantacked()` occurs.
+    /// The `value` is the error code for the reset and stop sending events,
+    /// or the acknowledged offset for the acked event. This callback is
+    /// optional.
+    pub on_stream_event: Option<
+        fn(
+            tctx: *mut c_void,
+            conn: &mut Connection,
+            stream_id: u64,
+            event_type: StreamEventType,
+            value: u64,
+        ),
+    >,
+}
+
+/// Type of flow control blocking.
+#[repr(C)]
+pub enum BlockedType {
+    /// The connection is blocked by the connection-level flow control limit
+    /// of the peer.
+    DataBlocked,
+
+    /// The stream is blocked by the stream-level flow control limit of the
+    /// peer.
+    StreamDataBlocked,
+
+    /// The peer is blocked by the local connection-level flow control limit.
+    PeerDataBlocked,
+
+    /// The peer is blocked by the local stream-level flow control limit.
+    PeerStreamDataBlocked,
+}
+
+/// Type of stream events subscribed by the application.
+#[repr(C)]
+pub enum StreamEventType {
+    /// The stream is reset by the peer.
+    Reset,
+
+    /// The peer asks to stop sending on the stream.
+    StopSending,
+
+    /// The sent data is acknowledged up to the subscribed offset.
+    Acked,
 }
 
 #[repr(transparent)]
@@ -1790,6 +2675,73 @@ impl crate::TransportHandler for TransportHandler {
             }
         }
     }
+
+    fn on_ping_acked(&mut self, conn: &mut Connection, tag: u64, rtt: Duration) {
+        unsafe {
+            if let Some(f) = (*self.methods).on_ping_acked {
+                f(self.context.0, conn, tag, rtt.as_micros() as u64);
+            }
+        }
+    }
+
+    fn on_flow_control_blocked(&mut self, conn: &mut Connection, event: crate::BlockedEvent) {
+        let (blocked_type, stream_id, limit) = match event {
+            crate::BlockedEvent::DataBlocked { limit } => (BlockedType::DataBlocked, 0, limit),
+            crate::BlockedEvent::StreamDataBlocked { stream_id, limit } => {
+                (BlockedType::StreamDataBlocked, stream_id, limit)
+            }
+            crate::BlockedEvent::PeerDataBlocked { limit } => {
+                (BlockedType::PeerDataBlocked, 0, limit)
+            }
+            crate::BlockedEvent::PeerStreamDataBlocked { stream_id, limit } => {
+                (BlockedType::PeerStreamDataBlocked, stream_id, limit)
+            }
+        };
+        unsafe {
+            if let Some(f) = (*self.methods).on_flow_control_blocked {
+                f(self.context.0, conn, blocked_type, stream_id, limit);
+            }
+        }
+    }
+
+    fn on_target_bitrate_updated(&mut self, conn: &mut Connection, bitrate: u64) {
+        unsafe {
+            if let Some(f) = (*self.methods).on_target_bitrate_updated {
+                f(self.context.0, conn, bitrate);
+            }
+        }
+    }
+
+    fn on_persistent_congestion(
+        &mut self,
+        conn: &mut Connection,
+        event: crate::PersistentCongestionEvent,
+    ) {
+        unsafe {
+            if let Some(f) = (*self.methods).on_persistent_congestion {
+                f(
+                    self.context.0,
+                    conn,
+                    event.duration.as_micros() as u64,
+                    event.lost_packets,
+                    event.congestion_window,
+                );
+            }
+        }
+    }
+
+    fn on_stream_event(&mut self, conn: &mut Connection, stream_id: u64, event: StreamEvent) {
+        let (event_type, value) = match event {
+            StreamEvent::Reset { error_code } => (StreamEventType::Reset, error_code),
+            StreamEvent::StopSending { error_code } => (StreamEventType::StopSending, error_code),
+            StreamEvent::Acked { offset } => (StreamEventType::Acked, offset),
+        };
+        unsafe {
+            if let Some(f) = (*self.methods).on_stream_event {
+                f(self.context.0, conn, stream_id, event_type, value);
+            }
+        }
+    }
 }
 
 #[repr(C)]
@@ -1843,6 +2795,13 @@ impl crate::PacketSendHandler for PacketSendHandler {
                 src_addr_len,
                 dst_addr: &dst_addrs[i] as *const _ as *const c_void,
                 dst_addr_len,
+                ecn: info.ecn as u8,
+                dscp: info.dscp,
+                release_delay: info
+                    .time
+                    .saturating_duration_since(Instant::now())
+                    .as_nanos() as u64,
+                segment_size: info.segment_size,
             };
 
             pkt_specs.push(pkt_spec);
@@ -1981,6 +2940,7 @@ pub struct PacketInfo<'a> {
     src_len: socklen_t,
     dst: &'a sockaddr,
     dst_len: socklen_t,
+    ecn: u8,
 }
 
 impl<'a> From<&PacketInfo<'a>> for crate::PacketInfo {
@@ -1989,6 +2949,9 @@ impl<'a> From<&PacketInfo<'a>> for crate::PacketInfo {
             src: sock_addr_from_c(info.src, info.src_len),
             dst: sock_addr_from_c(info.dst, info.dst_len),
             time: Instant::now(),
+            ecn: crate::Ecn::from(info.ecn),
+            dscp: 0,
+            segment_size: 0,
         }
     }
 }
@@ -2002,6 +2965,14 @@ pub struct PacketOutSpec {
     src_addr_len: socklen_t,
     dst_addr: *const c_void,
     dst_addr_len: socklen_t,
+    ecn: u8,
+    dscp: u8,
+    /// The delay in nanoseconds before the packet should be released, which
+    /// is non-zero only if pacing offload is enabled.
+    release_delay: u64,
+    /// The size of each UDP datagram if the buffer contains multiple
+    /// datagrams to be sent with UDP_SEGMENT, otherwise zero.
+    segment_size: size_t,
 }
 
 #[repr(C)]
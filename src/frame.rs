@@ -12,6 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::net::SocketAddr;
+use std::time::Instant;
+
 use bytes::Bytes;
 
 use crate::codec;
@@ -27,6 +30,7 @@ use crate::qlog::events::TokenType;
 use crate::ranges::RangeSet;
 use crate::token::ResetToken;
 use crate::ConnectionId;
+use crate::Ecn;
 use crate::Result;
 
 /// The largest offset delivered on a stream cannot exceed 2^62-1, as it is not
@@ -49,9 +53,13 @@ pub enum Frame {
 
     /// PING frame (type=0x01) is used to verify that peers are still alive
     /// or to check reachability to the peer.
-    /// The extra metadata `pmtu_probe` is solely for internal use and is not
-    /// transmitted over the network.
-    Ping { pmtu_probe: Option<(usize, usize)> },
+    /// The extra metadata `pmtu_probe` and `probe` are solely for internal use
+    /// and are not transmitted over the network. The `probe` carries the tag
+    /// of an application probe and the time it was sent.
+    Ping {
+        pmtu_probe: Option<(usize, usize)>,
+        probe: Option<(u64, Instant)>,
+    },
 
     /// ACK frame (types 0x02 and 0x03) is used to inform senders of packets
     /// they have received and processed. The ACK frame contains one or more
@@ -158,6 +166,49 @@ pub enum Frame {
     /// confirmation of the handshake to the client.
     HandshakeDone,
 
+    /// BDP_FRAME frame (type=0xbdf0fa, provisional) sent by the server shares
+    /// the path capacity measured on the current connection, which could be
+    /// used by the client to speed up the ramp-up of a future connection.
+    /// Note: The draft does not assign a type yet, and the type 0x1f is taken
+    /// by IMMEDIATE_ACK in draft-ietf-quic-ack-frequency.
+    /// See draft-kuhn-quic-bdpframe-extension.
+    Bdp {
+        /// The duration in seconds for which the saved values are valid.
+        lifetime: u64,
+        /// The saved bytes-in-flight capacity of the path.
+        saved_capacity: u64,
+        /// The saved minimum RTT of the path in microseconds.
+        saved_rtt: u64,
+    },
+
+    /// OBSERVED_ADDRESS frame (type=0x9f81a6..0x9f81a7) is used to report the
+    /// source address of the packets received from the peer on the path.
+    /// See draft-ietf-quic-address-discovery-00.
+    ObservedAddress { seq_num: u64, addr: SocketAddr },
+
+    /// ACK_FREQUENCY frame (type=0xaf) is used to request the peer to change
+    /// its acknowledgement behavior.
+    /// See draft-ietf-quic-ack-frequency.
+    AckFrequency {
+        /// The sequence number of the frame, which is used to ignore stale
+        /// frames received out of order.
+        seq_num: u64,
+        /// The maximum number of ack-eliciting packets received that can go
+        /// unacknowledged before an ACK frame is sent immediately.
+        ack_eliciting_threshold: u64,
+        /// The requested max_ack_delay in microseconds.
+        request_max_ack_delay: u64,
+        /// The number of out-of-order packets received before an ACK frame
+        /// is sent immediately. The value 0 means not sending an ACK frame
+        /// immediately for out-of-order packets.
+        reordering_threshold: u64,
+    },
+
+    /// IMMEDIATE_ACK frame (type=0x1f) is used to request the peer to send an
+    /// ACK frame immediately.
+    /// See draft-ietf-quic-ack-frequency.
+    ImmediateAck,
+
     /// PATH_ABANDON frame informs the peer to abandon a path.
     /// See draft-ietf-quic-multipath-05.
     PathAbandon {
@@ -194,7 +245,10 @@ impl Frame {
                 Frame::Paddings { len }
             }
 
-            0x01 => Frame::Ping { pmtu_probe: None },
+            0x01 => Frame::Ping {
+                pmtu_probe: None,
+                probe: None,
+            },
 
             0x02..=0x03 => {
                 let (frame, len) = parse_ack_frame(frame_type, b)?;
@@ -344,6 +398,41 @@ impl Frame {
 
             0x1e => Frame::HandshakeDone,
 
+            0x1f => Frame::ImmediateAck,
+
+            0xbdf0fa => Frame::Bdp {
+                lifetime: b.read_varint()?,
+                saved_capacity: b.read_varint()?,
+                saved_rtt: b.read_varint()?,
+            },
+
+            0x9f81a6 => {
+                let seq_num = b.read_varint()?;
+                let ip = b.read_ipv4_addr()?;
+                let port = b.read_u16()?;
+                Frame::ObservedAddress {
+                    seq_num,
+                    addr: SocketAddr::new(ip.into(), port),
+                }
+            }
+
+            0x9f81a7 => {
+                let seq_num = b.read_varint()?;
+                let ip = b.read_ipv6_addr()?;
+                let port = b.read_u16()?;
+                Frame::ObservedAddress {
+                    seq_num,
+                    addr: SocketAddr::new(ip.into(), port),
+                }
+            }
+
+            0xaf => Frame::AckFrequency {
+                seq_num: b.read_varint()?,
+                ack_eliciting_threshold: b.read_varint()?,
+                request_max_ack_delay: b.read_varint()?,
+                reordering_threshold: b.read_varint()?,
+            },
+
             0x15228c05 => Frame::PathAbandon {
                 dcid_seq_num: b.read_varint()?,
                 error_code: b.read_varint()?,
@@ -376,6 +465,8 @@ impl Frame {
             (PacketType::ZeroRTT, Frame::Ack { .. }) => false,
             (PacketType::ZeroRTT, Frame::Crypto { .. }) => false,
             (PacketType::ZeroRTT, Frame::HandshakeDone) => false,
+            (PacketType::ZeroRTT, Frame::Bdp { .. }) => false,
+            (PacketType::ZeroRTT, Frame::ObservedAddress { .. }) => false,
             (PacketType::ZeroRTT, Frame::NewToken { .. }) => false,
             (PacketType::ZeroRTT, Frame::PathResponse { .. }) => false,
             (PacketType::ZeroRTT, Frame::RetireConnectionId { .. }) => false,
@@ -418,36 +509,7 @@ impl Frame {
                 ack_ranges,
                 ecn_counts,
             } => {
-                if ecn_counts.is_none() {
-                    b.write_varint(0x02)?;
-                } else {
-                    b.write_varint(0x03)?;
-                }
-
-                let mut it = ack_ranges.iter().rev();
-
-                let first = it.next().unwrap();
-                let ack_range_len = (first.end - 1) - first.start;
-
-                b.write_varint(first.end - 1)?;
-                b.write_varint(*ack_delay)?;
-                b.write_varint(it.len() as u64)?;
-                b.write_varint(ack_range_len)?;
-
-                let mut smallest_ack = first.start;
-                for ack_range in it {
-                    let gap = smallest_ack - ack_range.end - 1;
-                    let ack_range_len = (ack_range.end - 1) - ack_range.start;
-                    b.write_varint(gap)?;
-                    b.write_varint(ack_range_len)?;
-                    smallest_ack = ack_range.start;
-                }
-
-                if let Some(ecn) = ecn_counts {
-                    b.write_varint(ecn.ect0_count)?;
-                    b.write_varint(ecn.ect1_count)?;
-                    b.write_varint(ecn.ecn_ce_count)?;
-                }
+                return encode_ack_frame(*ack_delay, ack_ranges, ecn_counts.as_ref(), b);
             }
 
             Frame::ResetStream {
@@ -584,6 +646,50 @@ impl Frame {
                 b.write_varint(0x1e)?;
             }
 
+            Frame::Bdp {
+                lifetime,
+                saved_capacity,
+                saved_rtt,
+            } => {
+                b.write_varint(0xbdf0fa)?;
+                b.write_varint(*lifetime)?;
+                b.write_varint(*saved_capacity)?;
+                b.write_varint(*saved_rtt)?;
+            }
+
+            Frame::ObservedAddress { seq_num, addr } => {
+                match addr {
+                    SocketAddr::V4(v) => {
+                        b.write_varint(0x9f81a6)?;
+                        b.write_varint(*seq_num)?;
+                        b.write_ipv4_addr(v.ip())?;
+                    }
+                    SocketAddr::V6(v) => {
+                        b.write_varint(0x9f81a7)?;
+                        b.write_varint(*seq_num)?;
+                        b.write_ipv6_addr(v.ip())?;
+                    }
+                }
+                b.write_u16(addr.port())?;
+            }
+
+            Frame::AckFrequency {
+                seq_num,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            } => {
+                b.write_varint(0xaf)?;
+                b.write_varint(*seq_num)?;
+                b.write_varint(*ack_eliciting_threshold)?;
+                b.write_varint(*request_max_ack_delay)?;
+                b.write_varint(*reordering_threshold)?;
+            }
+
+            Frame::ImmediateAck => {
+                b.write_varint(0x1f)?;
+            }
+
             Frame::PathAbandon {
                 dcid_seq_num,
                 error_code,
@@ -621,34 +727,7 @@ impl Frame {
                 ack_delay,
                 ack_ranges,
                 ecn_counts,
-            } => {
-                let mut it = ack_ranges.iter().rev();
-
-                let first = it.next().unwrap();
-                let ack_block = (first.end - 1) - first.start;
-
-                let mut len = 1
-                    + codec::encode_varint_len(first.end - 1)
-                    + codec::encode_varint_len(*ack_delay)
-                    + codec::encode_varint_len(it.len() as u64)
-                    + codec::encode_varint_len(ack_block);
-
-                let mut smallest_ack = first.start;
-                for block in it {
-                    let gap = smallest_ack - block.end - 1;
-                    let ack_block = (block.end - 1) - block.start;
-                    len += codec::encode_varint_len(gap) + codec::encode_varint_len(ack_block);
-                    smallest_ack = block.start;
-                }
-
-                if let Some(ecn) = ecn_counts {
-                    len += codec::encode_varint_len(ecn.ect0_count)
-                        + codec::encode_varint_len(ecn.ect1_count)
-                        + codec::encode_varint_len(ecn.ecn_ce_count);
-                }
-
-                len
-            }
+            } => ack_frame_wire_len(*ack_delay, ack_ranges, ecn_counts.as_ref()),
 
             Frame::ResetStream {
                 stream_id,
@@ -740,6 +819,38 @@ impl Frame {
 
             Frame::HandshakeDone => 1,
 
+            Frame::Bdp {
+                lifetime,
+                saved_capacity,
+                saved_rtt,
+            } => {
+                // length of frame type (0xbdf0fa) is 4
+                4 + codec::encode_varint_len(*lifetime)
+                    + codec::encode_varint_len(*saved_capacity)
+                    + codec::encode_varint_len(*saved_rtt)
+            }
+
+            Frame::ObservedAddress { seq_num, addr } => {
+                // length of frame type (0x9f81a6/0x9f81a7) is 4
+                let ip_len = if addr.is_ipv4() { 4 } else { 16 };
+                4 + codec::encode_varint_len(*seq_num) + ip_len + 2
+            }
+
+            Frame::AckFrequency {
+                seq_num,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            } => {
+                // length of frame type (0xaf) is 2
+                2 + codec::encode_varint_len(*seq_num)
+                    + codec::encode_varint_len(*ack_eliciting_threshold)
+                    + codec::encode_varint_len(*request_max_ack_delay)
+                    + codec::encode_varint_len(*reordering_threshold)
+            }
+
+            Frame::ImmediateAck => 1,
+
             Frame::PathAbandon {
                 dcid_seq_num,
                 error_code,
@@ -918,6 +1029,30 @@ impl Frame {
 
             Frame::HandshakeDone => QuicFrame::HandshakeDone,
 
+            Frame::Bdp { .. } => QuicFrame::Unknown {
+                raw_frame_type: 0xbdf0fa,
+                frame_type_value: None,
+                raw: None,
+            },
+
+            Frame::ObservedAddress { addr, .. } => QuicFrame::Unknown {
+                raw_frame_type: if addr.is_ipv4() { 0x9f81a6 } else { 0x9f81a7 },
+                frame_type_value: None,
+                raw: None,
+            },
+
+            Frame::AckFrequency { .. } => QuicFrame::Unknown {
+                raw_frame_type: 0xaf,
+                frame_type_value: None,
+                raw: None,
+            },
+
+            Frame::ImmediateAck => QuicFrame::Unknown {
+                raw_frame_type: 0x1f,
+                frame_type_value: None,
+                raw: None,
+            },
+
             Frame::PathAbandon { .. } => QuicFrame::Unknown {
                 raw_frame_type: 0x15228c05,
                 frame_type_value: None,
@@ -1087,6 +1222,39 @@ impl std::fmt::Debug for Frame {
                 write!(f, "HANDSHAKE_DONE")?;
             }
 
+            Frame::Bdp {
+                lifetime,
+                saved_capacity,
+                saved_rtt,
+            } => {
+                write!(
+                    f,
+                    "BDP lifetime={lifetime} saved_capacity={saved_capacity} saved_rtt={saved_rtt}"
+                )?;
+            }
+
+            Frame::ObservedAddress { seq_num, addr } => {
+                write!(f, "OBSERVED_ADDRESS seq_num={seq_num} addr={addr}")?;
+            }
+
+            Frame::AckFrequency {
+                seq_num,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            } => {
+                write!(
+                    f,
+                    "ACK_FREQUENCY seq_num={seq_num} ack_eliciting_threshold={ack_eliciting_threshold} \
+                    request_max_ack_delay={request_max_ack_delay} \
+                    reordering_threshold={reordering_threshold}",
+                )?;
+            }
+
+            Frame::ImmediateAck => {
+                write!(f, "IMMEDIATE_ACK")?;
+            }
+
             Frame::PathAbandon {
                 dcid_seq_num,
                 error_code,
@@ -1114,6 +1282,86 @@ impl std::fmt::Debug for Frame {
     }
 }
 
+/// Return the encoded length of ACK frame.
+pub fn ack_frame_wire_len(
+    ack_delay: u64,
+    ack_ranges: &RangeSet,
+    ecn_counts: Option<&EcnCounts>,
+) -> usize {
+    let mut it = ack_ranges.iter().rev();
+
+    let first = it.next().unwrap();
+    let ack_block = (first.end - 1) - first.start;
+
+    let mut len = 1
+        + codec::encode_varint_len(first.end - 1)
+        + codec::encode_varint_len(ack_delay)
+        + codec::encode_varint_len(it.len() as u64)
+        + codec::encode_varint_len(ack_block);
+
+    let mut smallest_ack = first.start;
+    for block in it {
+        let gap = smallest_ack - block.end - 1;
+        let ack_block = (block.end - 1) - block.start;
+        len += codec::encode_varint_len(gap) + codec::encode_varint_len(ack_block);
+        smallest_ack = block.start;
+    }
+
+    if let Some(ecn) = ecn_counts {
+        len += codec::encode_varint_len(ecn.ect0_count)
+            + codec::encode_varint_len(ecn.ect1_count)
+            + codec::encode_varint_len(ecn.ecn_ce_count);
+    }
+
+    len
+}
+
+/// Encode ACK frame to the given buffer.
+///
+/// The ACK ranges are encoded from the given range set directly, so there is
+/// no need to create a `Frame::Ack` with a copy of the range set.
+pub fn encode_ack_frame(
+    ack_delay: u64,
+    ack_ranges: &RangeSet,
+    ecn_counts: Option<&EcnCounts>,
+    mut b: &mut [u8],
+) -> Result<usize> {
+    let len = b.len();
+
+    if ecn_counts.is_none() {
+        b.write_varint(0x02)?;
+    } else {
+        b.write_varint(0x03)?;
+    }
+
+    let mut it = ack_ranges.iter().rev();
+
+    let first = it.next().unwrap();
+    let ack_range_len = (first.end - 1) - first.start;
+
+    b.write_varint(first.end - 1)?;
+    b.write_varint(ack_delay)?;
+    b.write_varint(it.len() as u64)?;
+    b.write_varint(ack_range_len)?;
+
+    let mut smallest_ack = first.start;
+    for ack_range in it {
+        let gap = smallest_ack - ack_range.end - 1;
+        let ack_range_len = (ack_range.end - 1) - ack_range.start;
+        b.write_varint(gap)?;
+        b.write_varint(ack_range_len)?;
+        smallest_ack = ack_range.start;
+    }
+
+    if let Some(ecn) = ecn_counts {
+        b.write_varint(ecn.ect0_count)?;
+        b.write_varint(ecn.ect1_count)?;
+        b.write_varint(ecn.ecn_ce_count)?;
+    }
+
+    Ok(len - b.len())
+}
+
 /// Return the encoded length of CRYPTO frame header.
 pub fn crypto_header_wire_len(offset: u64) -> usize {
     // Note: `encode_crypto_header()` encode length field in 2 bytes.
@@ -1164,19 +1412,36 @@ pub fn encode_stream_header(
 /// The ACK frame uses the least significant bit of the type value (type 0x03)
 /// to indicate ECN feedback and report receipt of QUIC packets with associated
 /// ECN codepoints of ECT(0), ECT(1), or ECN-CE in the packet's IP header.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct EcnCounts {
     /// The total number of packets received with the ECT(0) codepoint in the
     /// packet number space of the ACK frame.
-    ect0_count: u64,
+    pub ect0_count: u64,
 
     /// The total number of packets received with the ECT(1) codepoint in the
     /// packet number space of the ACK frame.
-    ect1_count: u64,
+    pub ect1_count: u64,
 
     /// The total number of packets received with the ECN-CE codepoint in the
     /// packet number space of the ACK frame.
-    ecn_ce_count: u64,
+    pub ecn_ce_count: u64,
+}
+
+impl EcnCounts {
+    /// Count a received packet with the given ECN codepoint.
+    pub fn on_packet_received(&mut self, ecn: Ecn) {
+        match ecn {
+            Ecn::Ect0 => self.ect0_count += 1,
+            Ecn::Ect1 => self.ect1_count += 1,
+            Ecn::Ce => self.ecn_ce_count += 1,
+            Ecn::NotEct => (),
+        }
+    }
+
+    /// Return true if no packet with an ECN codepoint has been received.
+    pub fn is_empty(&self) -> bool {
+        self.ect0_count == 0 && self.ect1_count == 0 && self.ecn_ce_count == 0
+    }
 }
 
 fn parse_ack_frame(frame_type: u64, mut b: &[u8]) -> Result<(Frame, usize)> {
@@ -1190,8 +1455,13 @@ fn parse_ack_frame(frame_type: u64, mut b: &[u8]) -> Result<(Frame, usize)> {
     // The acknowledgment delay in microseconds
     let ack_delay = b.read_varint()?;
 
-    // The number of ACK Range fields in the frame.
+    // The number of ACK Range fields in the frame. Each ACK Range field takes
+    // at least two bytes, so a larger count is bogus and is rejected before
+    // decoding the ranges.
     let range_count = b.read_varint()?;
+    if range_count > (b.len() / 2) as u64 {
+        return Err(Error::FrameEncodingError);
+    }
 
     // The number of contiguous packets preceding the Largest Acknowledged that
     // are being acknowledged.
@@ -1275,7 +1545,10 @@ mod tests {
 
     #[test]
     fn ping() -> Result<()> {
-        let frame = Frame::Ping { pmtu_probe: None };
+        let frame = Frame::Ping {
+            pmtu_probe: None,
+            probe: None,
+        };
         assert_eq!(format!("{:?}", &frame), "PING");
 
         let mut buf = [0; 128];
@@ -1331,6 +1604,46 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn ack_with_bogus_range_count() -> Result<()> {
+        // The ACK Range Count is larger than the remaining bytes could hold.
+        let mut buf = Bytes::from_static(&[0x02, 10, 0, 63, 0, 0, 0]);
+        assert_eq!(
+            Frame::from_bytes(&mut buf, PacketType::OneRTT),
+            Err(Error::FrameEncodingError)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ack_encoded_directly() -> Result<()> {
+        let mut ranges = RangeSet::default();
+        ranges.insert(0..8);
+        ranges.insert(10..15);
+        ranges.insert(21..30);
+
+        let mut buf = [0; 128];
+        let len = encode_ack_frame(200000, &ranges, None, &mut buf[..])?;
+        assert_eq!(len, ack_frame_wire_len(200000, &ranges, None));
+
+        // Same as the encoded Frame::Ack
+        let frame = Frame::Ack {
+            ack_delay: 200000,
+            ack_ranges: ranges.clone(),
+            ecn_counts: None,
+        };
+        let mut frame_buf = [0; 128];
+        assert_eq!(frame.to_bytes(&mut frame_buf[..])?, len);
+        assert_eq!(&buf[..len], &frame_buf[..len]);
+
+        // Buffer is too short
+        assert_eq!(
+            encode_ack_frame(200000, &ranges, None, &mut buf[..len - 1]),
+            Err(Error::BufferTooShort)
+        );
+        Ok(())
+    }
+
     #[test]
     fn reset_stream() -> Result<()> {
         let frame = Frame::ResetStream {
@@ -1788,6 +2101,116 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn bdp() -> Result<()> {
+        let frame = Frame::Bdp {
+            lifetime: 3600,
+            saved_capacity: 100000,
+            saved_rtt: 20000,
+        };
+        assert_eq!(
+            format!("{:?}", &frame),
+            "BDP lifetime=3600 saved_capacity=100000 saved_rtt=20000"
+        );
+
+        let mut buf = [0; 128];
+        let len = frame.to_bytes(&mut buf[..])?;
+        assert_eq!(len, frame.wire_len());
+        assert_eq!(len, 14);
+
+        let mut buf = Bytes::copy_from_slice(&buf);
+        assert_eq!(
+            (frame, 14),
+            Frame::from_bytes(&mut buf, PacketType::OneRTT)?
+        );
+        assert!(Frame::from_bytes(&mut buf, PacketType::ZeroRTT).is_err());
+        assert!(Frame::from_bytes(&mut buf, PacketType::Initial).is_err());
+        assert!(Frame::from_bytes(&mut buf, PacketType::Handshake).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ack_frequency() -> Result<()> {
+        let frame = Frame::AckFrequency {
+            seq_num: 1,
+            ack_eliciting_threshold: 9,
+            request_max_ack_delay: 25000,
+            reordering_threshold: 1,
+        };
+        assert_eq!(
+            format!("{:?}", &frame),
+            "ACK_FREQUENCY seq_num=1 ack_eliciting_threshold=9 \
+            request_max_ack_delay=25000 reordering_threshold=1"
+        );
+
+        let mut buf = [0; 128];
+        let len = frame.to_bytes(&mut buf[..])?;
+        assert_eq!(len, frame.wire_len());
+        assert_eq!(len, 9);
+
+        let mut buf = Bytes::copy_from_slice(&buf);
+        assert_eq!((frame, 9), Frame::from_bytes(&mut buf, PacketType::OneRTT)?);
+        assert!(Frame::from_bytes(&mut buf, PacketType::Initial).is_err());
+        assert!(Frame::from_bytes(&mut buf, PacketType::Handshake).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn immediate_ack() -> Result<()> {
+        let frame = Frame::ImmediateAck;
+        assert_eq!(format!("{:?}", &frame), "IMMEDIATE_ACK");
+
+        let mut buf = [0; 128];
+        let len = frame.to_bytes(&mut buf[..])?;
+        assert_eq!(len, frame.wire_len());
+        assert_eq!(len, 1);
+
+        let mut buf = Bytes::copy_from_slice(&buf);
+        assert_eq!((frame, 1), Frame::from_bytes(&mut buf, PacketType::OneRTT)?);
+        assert!(Frame::from_bytes(&mut buf, PacketType::Initial).is_err());
+        assert!(Frame::from_bytes(&mut buf, PacketType::Handshake).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn observed_address() -> Result<()> {
+        let cases = [
+            (
+                "192.168.1.1:443",
+                "OBSERVED_ADDRESS seq_num=1 addr=192.168.1.1:443",
+                11,
+            ),
+            (
+                "[2001:db8::1]:443",
+                "OBSERVED_ADDRESS seq_num=1 addr=[2001:db8::1]:443",
+                23,
+            ),
+        ];
+
+        for (addr, debug, wire_len) in cases {
+            let frame = Frame::ObservedAddress {
+                seq_num: 1,
+                addr: addr.parse().unwrap(),
+            };
+            assert_eq!(format!("{:?}", &frame), debug);
+
+            let mut buf = [0; 128];
+            let len = frame.to_bytes(&mut buf[..])?;
+            assert_eq!(len, frame.wire_len());
+            assert_eq!(len, wire_len);
+
+            let mut buf = Bytes::copy_from_slice(&buf);
+            assert_eq!(
+                (frame, wire_len),
+                Frame::from_bytes(&mut buf, PacketType::OneRTT)?
+            );
+            assert!(Frame::from_bytes(&mut buf, PacketType::ZeroRTT).is_err());
+            assert!(Frame::from_bytes(&mut buf, PacketType::Initial).is_err());
+            assert!(Frame::from_bytes(&mut buf, PacketType::Handshake).is_err());
+        }
+        Ok(())
+    }
+
     #[test]
     fn path_abandon() -> Result<()> {
         let frame = Frame::PathAbandon {
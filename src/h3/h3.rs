@@ -232,10 +232,12 @@ mod tests {
 
 pub use error::Http3Error;
 
+#[doc(hidden)]
 #[path = "qpack/qpack.rs"]
-mod qpack;
+pub mod qpack;
 
 pub mod connection;
 mod error;
-mod frame;
+#[doc(hidden)]
+pub mod frame;
 mod stream;
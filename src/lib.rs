@@ -65,15 +65,17 @@ use std::time::Instant;
 use bytes::Buf;
 use bytes::BufMut;
 use rand::RngCore;
-use ring::aead;
-use ring::aead::LessSafeKey;
-use ring::aead::UnboundKey;
 use ring::hmac;
 use rustc_hash::FxHashSet;
 
 use crate::codec::VINT_MAX;
+use crate::connection::flowcontrol::DEFAULT_AUTOTUNE_FACTOR;
+use crate::connection::flowcontrol::DEFAULT_AUTOTUNE_INTERVAL_RTTS;
+use crate::connection::flowcontrol::MAX_AUTOTUNE_INTERVAL_RTTS;
 use crate::connection::stream;
+use crate::connection::stream_scheduler::build_stream_scheduler;
 use crate::tls::TlsSession;
+use crate::token::AddressTokenKey;
 use crate::token::ResetToken;
 use crate::trans_param::TransportParams;
 
@@ -83,6 +85,10 @@ pub const QUIC_VERSION: u32 = QUIC_VERSION_V1;
 /// The QUIC Version 1
 pub const QUIC_VERSION_V1: u32 = 0x0000_0001;
 
+/// The QUIC Version 2
+/// See RFC 9369
+pub const QUIC_VERSION_V2: u32 = 0x6b33_43cf;
+
 /// The Connection ID MUST NOT exceed 20 bytes in QUIC version 1.
 /// See RFC 9000 Section 17.2
 pub const MAX_CID_LEN: usize = 20;
@@ -121,6 +127,23 @@ const DEFAULT_SEND_UDP_PAYLOAD_SIZE: usize = 1200;
 /// address to three times the amount of data received from that address.
 const ANTI_AMPLIFICATION_FACTOR: usize = 3;
 
+/// Implementations MUST support buffering at least 4096 bytes of data
+/// received in out-of-order CRYPTO frames.
+/// See RFC 9000 Section 7.5
+const MIN_CRYPTO_BUFFER_SIZE: u64 = 4096;
+
+/// The default maximum size of data received in out-of-order CRYPTO frames
+/// that can be buffered for each encryption level.
+const DEFAULT_MAX_CRYPTO_BUFFER_SIZE: u64 = 65536;
+
+/// The maximum number of UDP datagrams in a packet buffer for GSO, which is
+/// the limit of the Linux kernel (UDP_MAX_SEGMENTS).
+const MAX_SEND_SEGMENTS: usize = 64;
+
+/// The maximum value of the 6-bit DSCP codepoint.
+/// See RFC 2474 Section 3
+const MAX_DSCP: u8 = 0x3f;
+
 /// The RECOMMENDED value of the timer granularity is 1 millisecond.
 /// See RFC 9002 Section 6.1
 pub const TIMER_GRANULARITY: Duration = Duration::from_millis(1);
@@ -148,6 +171,24 @@ const DEFAULT_PTO_LINEAR_FACTOR: u64 = 0;
 /// Default upper limit of probe timeout.
 const MAX_PTO: Duration = Duration::MAX;
 
+/// Upper limit of the initial probe timeout, as a multiple of the initial RTT.
+const MAX_INITIAL_PTO_FACTOR: f64 = 100.0;
+
+/// Default packet threshold of loss detection.
+/// See RFC 9002 Section 6.1.1
+const DEFAULT_PACKET_THRESHOLD: u64 = 3;
+
+/// Default multiplier of the probe timeout upon each backoff.
+/// See RFC 9002 Section 6.2.1
+const DEFAULT_PTO_MULTIPLIER: u32 = 2;
+
+/// Default upper limit of the consecutive probe timeout backoffs.
+const DEFAULT_MAX_PTO_BACKOFF: u32 = u32::MAX;
+
+/// Default time threshold of loss detection, as an RTT multiplier.
+/// See RFC 9002 Section 6.1.2
+const DEFAULT_TIME_THRESHOLD: f64 = 9.0 / 8.0;
+
 /// Result type for quic operations.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -256,8 +297,105 @@ pub struct PacketInfo {
     /// The destination address of the packet
     pub dst: SocketAddr,
 
-    /// The time when the packet arrived or the time to send the packet
+    /// The time when the packet arrived or the time to send the packet. The
+    /// time to send may be in the future if pacing offload is enabled.
     pub time: time::Instant,
+
+    /// The ECN codepoint in the IP header of the packet that arrived, or the
+    /// ECN codepoint that should be set for the packet to send.
+    pub ecn: Ecn,
+
+    /// The DSCP codepoint that should be set for the packet to send. It is
+    /// ignored for the packet that arrived.
+    /// See RFC 2474 Section 3
+    pub dscp: u8,
+
+    /// The size of each UDP datagram if the buffer of the packet to send
+    /// contains multiple datagrams of the same size, which should be sent
+    /// with UDP generic segmentation offload (i.e. UDP_SEGMENT). The last
+    /// datagram may be smaller. Zero means the buffer contains a single
+    /// datagram. It is ignored for the packet that arrived.
+    pub segment_size: usize,
+}
+
+impl PacketInfo {
+    /// Return the value of the IPv4 TOS or IPv6 Traffic Class field, which
+    /// combines the DSCP and ECN codepoints. The I/O layer may set it with
+    /// `IP_TOS` or `IPV6_TCLASS` for the packet to send.
+    pub fn tos(&self) -> u8 {
+        (self.dscp << 2) | self.ecn as u8
+    }
+}
+
+/// The ECN codepoint in the IP header.
+/// See RFC 3168 Section 5
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Ecn {
+    /// Not ECN-Capable Transport
+    #[default]
+    NotEct = 0,
+
+    /// ECN Capable Transport (1)
+    Ect1 = 1,
+
+    /// ECN Capable Transport (0)
+    Ect0 = 2,
+
+    /// Congestion Experienced
+    Ce = 3,
+}
+
+/// The policy of using ECN on a path.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EcnPolicy {
+    /// The packets are not marked with ECN codepoints.
+    #[default]
+    Disabled,
+
+    /// The path is tested before the packets are marked with ECT(0), and the
+    /// endpoint stops marking packets if the validation fails.
+    /// See RFC 9000 Section 13.4.2
+    TestThenUse,
+
+    /// The packets are always marked with ECT(0) regardless of the result of
+    /// the validation.
+    Always,
+}
+
+/// The content of the probe packets sent upon a probe timeout (PTO) in the
+/// Application Data packet number space. The probes in the Initial and
+/// Handshake packet number spaces always retransmit the unacknowledged data.
+/// See RFC 9002 Section 6.2.4.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PtoProbeStrategy {
+    /// The oldest unacknowledged data is retransmitted in the probes, followed
+    /// by new data if there is any room left.
+    #[default]
+    OldestUnacked,
+
+    /// New data is sent in the probes if available, else a PING frame is sent.
+    /// The unacknowledged data is retransmitted after it is declared lost.
+    NewData,
+
+    /// Only a PING frame is sent in the probes, which elicits an
+    /// acknowledgment quickly at the cost of the smallest probe size.
+    Ping,
+}
+
+impl From<u8> for Ecn {
+    /// Convert the ECN field (i.e. the low-order two bits of the Traffic Class
+    /// or TOS byte) to the ECN codepoint.
+    fn from(v: u8) -> Self {
+        match v & 0x03 {
+            1 => Ecn::Ect1,
+            2 => Ecn::Ect0,
+            3 => Ecn::Ce,
+            _ => Ecn::NotEct,
+        }
+    }
 }
 
 /// Address tuple.
@@ -294,7 +432,7 @@ impl ExactSizeIterator for FourTupleIter {
 
 /// Check whether the protocol version is supported.
 fn version_is_supported(version: u32) -> bool {
-    matches!(version, QUIC_VERSION_V1)
+    matches!(version, QUIC_VERSION_V1 | QUIC_VERSION_V2)
 }
 
 /// Configurations about QUIC endpoint.
@@ -303,18 +441,71 @@ pub struct Config {
     /// QUIC transport configuration.
     local_transport_params: TransportParams,
 
+    /// Acceptable QUIC versions in the order of preference.
+    versions: Vec<u32>,
+
     /// Handshake timeout in microseconds.
     max_handshake_timeout: time::Duration,
 
     /// Maximum number of concurrent connections.
     max_concurrent_conns: u32,
 
+    /// Maximum memory in bytes used by all the connections of an endpoint.
+    max_memory_usage: u64,
+
+    /// Ceiling of the auto-tuned number of concurrent bidirectional streams
+    /// that the peer is allowed to open.
+    max_concurrent_streams_bidi: u64,
+
+    /// Ceiling of the auto-tuned number of concurrent unidirectional streams
+    /// that the peer is allowed to open.
+    max_concurrent_streams_uni: u64,
+
+    /// Maximum sum of the receiver connection flow control windows of all
+    /// the connections of an endpoint.
+    max_recv_window_budget: u64,
+
     /// Maximum size of the receiver connection flow control window.
     max_connection_window: u64,
 
     /// Maximum size of the receiver stream flow control window.
     max_stream_window: u64,
 
+    /// Number of RTTs without a window update after which the receiver flow
+    /// control windows are halved.
+    window_decay_rtts: u32,
+
+    /// Floor of the receiver flow control windows when they decay.
+    window_decay_floor: u64,
+
+    /// Whether the stream receive windows are allocated dynamically from the
+    /// connection receive window.
+    stream_window_sharing: bool,
+
+    /// Strategy for auto-tuning the receiver flow control windows.
+    window_tuner: Option<Arc<dyn WindowTuner>>,
+
+    /// Policy of scheduling the stream data to be sent.
+    stream_scheduling_policy: StreamSchedulingPolicy,
+
+    /// Factory of user-provided stream schedulers.
+    stream_scheduler_factory: Option<Arc<dyn StreamSchedulerFactory>>,
+
+    /// Selector of the congestion control algorithm for server connections.
+    congestion_control_selector: Option<Arc<dyn CongestionControlSelector>>,
+
+    /// Target interval between flow control window updates, in units of
+    /// srtt, used by the default window tuner.
+    flow_control_autotune_interval_rtts: f64,
+
+    /// Growth factor of the flow control window, used by the default window
+    /// tuner.
+    flow_control_autotune_factor: f64,
+
+    /// Maximum size of buffered out-of-order crypto data for each encryption
+    /// level.
+    max_crypto_buffer_size: u64,
+
     /// Uses Retry packets to reduce load on servers by forcing the client to
     /// prove ownership of its address
     retry: bool,
@@ -322,11 +513,33 @@ pub struct Config {
     /// Enable stateless reset or not.
     stateless_reset: bool,
 
+    /// The maximum number of stateless packets sent to a source address per
+    /// second.
+    stateless_response_rate: u32,
+
+    /// The interval of keep-alive on a quiet connection. Zero means disabled.
+    keep_alive_interval: Duration,
+
+    /// Whether to learn the keep-alive interval from the NAT binding timeout.
+    adaptive_keep_alive: bool,
+
+    /// Whether to use the latency spin bit.
+    spin_bit: bool,
+
+    /// The DSCP codepoint of the outgoing packets.
+    dscp: u8,
+
+    /// The average interval in packets between the skipped packet numbers.
+    pkt_num_skip_interval: u64,
+
+    /// The granularity for coalescing the timers of a connection.
+    timer_granularity: Duration,
+
     /// Duration after a retry token was issued for which it's considered valid.
     address_token_lifetime: Duration,
 
-    /// Key for address token generation.
-    address_token_key: Vec<LessSafeKey>,
+    /// Keys for address token generation and validation.
+    address_token_key: Vec<AddressTokenKey>,
 
     /// Key for stateless reset token generation.
     reset_token_key: hmac::Key,
@@ -340,12 +553,21 @@ pub struct Config {
     /// Maximum numbers of packets sent in a batch.
     send_batch_size: usize,
 
+    /// Maximum number of datagrams written to a packet buffer for GSO.
+    max_send_segments: usize,
+
     /// Buffer size for early incoming zero rtt packets, in packets.
     zerortt_buffer_size: usize,
 
     /// The maximum number of undecryptable packets that can be stored by one connection, in packets.
     max_undecryptable_packets: usize,
 
+    /// The maximum number of peer hosts whose path properties are cached.
+    path_cache_size: usize,
+
+    /// The lifetime of the cached path properties.
+    path_cache_lifetime: Duration,
+
     /// Configurations about loss recovery, congestion control, and pmtu discovery.
     recovery: RecoveryConfig,
 
@@ -387,20 +609,45 @@ impl Config {
 
         Ok(Self {
             local_transport_params,
+            versions: vec![QUIC_VERSION_V1],
             max_handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
             max_concurrent_conns: 1000000,
+            max_memory_usage: 0,
+            max_recv_window_budget: 0,
+            max_concurrent_streams_bidi: 0,
+            max_concurrent_streams_uni: 0,
             max_connection_window: stream::MAX_CONNECTION_WINDOW,
             max_stream_window: stream::MAX_STREAM_WINDOW,
+            window_decay_rtts: 0,
+            window_decay_floor: 0,
+            stream_window_sharing: false,
+            window_tuner: None,
+            stream_scheduling_policy: StreamSchedulingPolicy::Priority,
+            stream_scheduler_factory: None,
+            congestion_control_selector: None,
+            flow_control_autotune_interval_rtts: DEFAULT_AUTOTUNE_INTERVAL_RTTS,
+            flow_control_autotune_factor: DEFAULT_AUTOTUNE_FACTOR,
+            max_crypto_buffer_size: DEFAULT_MAX_CRYPTO_BUFFER_SIZE,
             retry: false,
             stateless_reset: true,
+            stateless_response_rate: 0,
+            keep_alive_interval: Duration::ZERO,
+            adaptive_keep_alive: false,
+            spin_bit: false,
+            dscp: 0,
+            pkt_num_skip_interval: 0,
+            timer_granularity: Duration::ZERO,
             address_token_lifetime: Duration::from_secs(86400),
             address_token_key: Self::rand_address_token_key()?,
             reset_token_key,
             cid_len: 8,
             anti_amplification_factor: ANTI_AMPLIFICATION_FACTOR,
             send_batch_size: 64,
+            max_send_segments: 1,
             zerortt_buffer_size: 1000,
             max_undecryptable_packets: 10,
+            path_cache_size: 0,
+            path_cache_lifetime: Duration::from_secs(600),
             recovery: RecoveryConfig::default(),
             multipath: MultipathConfig::default(),
             tls_config_selector: None,
@@ -413,11 +660,61 @@ impl Config {
         self.local_transport_params.max_idle_timeout = cmp::min(v, VINT_MAX);
     }
 
+    /// Set the acceptable QUIC versions in the order of preference.
+    ///
+    /// The client uses the first version for its first Initial packet, and
+    /// chooses the most preferred one among the versions supported by the
+    /// server upon receiving a Version Negotiation packet. The server only
+    /// accepts connections using these versions, and lists them in the
+    /// Version Negotiation packets. It may be used for deploying QUIC version
+    /// 2 only, or pinning to QUIC version 1 for compatibility.
+    /// The default value is `[QUIC_VERSION_V1]`.
+    pub fn set_versions(&mut self, versions: &[u32]) -> Result<()> {
+        if versions.is_empty() {
+            return Err(Error::InvalidConfig("versions empty".into()));
+        }
+        if let Some(v) = versions.iter().find(|v| !version_is_supported(**v)) {
+            return Err(Error::InvalidConfig(format!("unsupported version {:x}", v)));
+        }
+
+        let mut acceptable_versions = Vec::with_capacity(versions.len());
+        for v in versions {
+            if !acceptable_versions.contains(v) {
+                acceptable_versions.push(*v);
+            }
+        }
+        self.versions = acceptable_versions;
+        Ok(())
+    }
+
     /// Set handshake timeout in milliseconds. Zero turns the timeout off.
     pub fn set_max_handshake_timeout(&mut self, v: u64) {
         self.max_handshake_timeout = time::Duration::from_millis(v);
     }
 
+    /// Set the keep-alive interval in milliseconds. A PING frame is sent if
+    /// the connection has been quiet for the interval, which is capped to
+    /// half of the idle timeout. Zero turns keep-alive off.
+    /// The default value is 0.
+    pub fn set_keep_alive_interval(&mut self, v: u64) {
+        self.keep_alive_interval = time::Duration::from_millis(v);
+    }
+
+    /// Enable adaptive keep-alive. (Experimental)
+    /// The client learns the NAT binding timeout by binary search over quiet
+    /// intervals, and sends keep-alives at the longest interval found to keep
+    /// the binding. A PATH_CHALLENGE frame is sent after each quiet interval,
+    /// and the binding is considered lost if the server then reports a changed
+    /// client address, so the search requires the server to provide observed
+    /// addresses, see `enable_address_discovery()`. Otherwise, keep-alives are
+    /// sent at a conservative interval of 15 seconds. The keep-alive interval,
+    /// if set, is used as the upper bound of the search. It does not take effect
+    /// on the server.
+    /// The default value is false.
+    pub fn enable_adaptive_keep_alive(&mut self, v: bool) {
+        self.adaptive_keep_alive = v;
+    }
+
     /// Set the `max_udp_payload_size` transport parameter in bytes. It limits
     /// the size of UDP payloads that the endpoint is willing to receive. The
     /// default value is `65527`.
@@ -431,6 +728,42 @@ impl Config {
         self.recovery.enable_dplpmtud = v;
     }
 
+    /// Enable Explicit Congestion Notification. The outgoing packets are
+    /// marked with ECT(0) if the path is validated as ECN capable, and the
+    /// application should set the ECN codepoint of the outgoing packets and
+    /// report the ECN codepoint of the incoming packets via `PacketInfo`.
+    /// The default value is false.
+    pub fn enable_ecn(&mut self, v: bool) {
+        self.recovery.ecn_policy = if v {
+            EcnPolicy::TestThenUse
+        } else {
+            EcnPolicy::Disabled
+        };
+    }
+
+    /// Set the policy of using Explicit Congestion Notification on the paths.
+    /// It may be changed for a specific path by `Connection::set_path_ecn_policy`.
+    /// The default value is `EcnPolicy::Disabled`.
+    pub fn set_ecn_policy(&mut self, v: EcnPolicy) {
+        self.recovery.ecn_policy = v;
+    }
+
+    /// Set the number of ECN validation failures on a path before falling back
+    /// to not marking the packets. The path is tested again after each failure
+    /// until the limit is reached.
+    /// The default value is 1.
+    pub fn set_ecn_max_failures(&mut self, v: u32) {
+        self.recovery.ecn_max_failures = cmp::max(v, 1);
+    }
+
+    /// Set whether to test new paths of a connection for ECN capability if
+    /// the ECN validation has failed on another path. Otherwise, packets on
+    /// new paths are not marked in that case.
+    /// The default value is true.
+    pub fn enable_ecn_retest_on_migration(&mut self, v: bool) {
+        self.recovery.ecn_retest_on_migration = v;
+    }
+
     /// Set the maximum outgoing UDP payload size in bytes.
     /// It corresponds to the maximum datagram size that DPLPMTUD tries to discovery.
     /// The default value is `1200` which means let DPLPMTUD choose a value.
@@ -482,6 +815,26 @@ impl Config {
         self.local_transport_params.initial_max_streams_uni = cmp::min(v, VINT_MAX);
     }
 
+    /// Set the ceiling of the number of concurrent bidirectional streams that
+    /// the peer is allowed to open. If the peer consumes the stream credits
+    /// quickly, i.e. MAX_STREAMS frames are sent within 2 RTTs, the limit is
+    /// doubled each time up to the ceiling.
+    /// The default value is `0`, which means the limit is not auto-tuned and
+    /// stays at `initial_max_streams_bidi`.
+    pub fn set_max_concurrent_streams_bidi(&mut self, v: u64) {
+        self.max_concurrent_streams_bidi = cmp::min(v, MAX_STREAMS_PER_TYPE);
+    }
+
+    /// Set the ceiling of the number of concurrent unidirectional streams that
+    /// the peer is allowed to open. If the peer consumes the stream credits
+    /// quickly, i.e. MAX_STREAMS frames are sent within 2 RTTs, the limit is
+    /// doubled each time up to the ceiling.
+    /// The default value is `0`, which means the limit is not auto-tuned and
+    /// stays at `initial_max_streams_uni`.
+    pub fn set_max_concurrent_streams_uni(&mut self, v: u64) {
+        self.max_concurrent_streams_uni = cmp::min(v, MAX_STREAMS_PER_TYPE);
+    }
+
     /// Set the `ack_delay_exponent` transport parameter.
     /// The default value is `3`.
     pub fn set_ack_delay_exponent(&mut self, v: u64) {
@@ -501,12 +854,62 @@ impl Config {
         self.recovery.ack_eliciting_threshold = v;
     }
 
+    /// Enable the ACK frequency extension, which allows the peer to control
+    /// the acknowledgement behavior of the endpoint with ACK_FREQUENCY and
+    /// IMMEDIATE_ACK frames. (Experimental)
+    /// The default value is false.
+    /// See draft-ietf-quic-ack-frequency.
+    pub fn enable_ack_frequency(&mut self, v: bool) {
+        self.local_transport_params.min_ack_delay = if v {
+            Some(TIMER_GRANULARITY.as_micros() as u64)
+        } else {
+            None
+        };
+    }
+
+    /// Request the peer to send an acknowledgement after receiving at most
+    /// `threshold` ack-eliciting packets, and to delay an acknowledgement by
+    /// at most `max_ack_delay` milliseconds, with an ACK_FREQUENCY frame once
+    /// the ACK frequency extension is negotiated. Less frequent ACKs save CPU
+    /// and reverse-path bandwidth on high-bandwidth paths. (Experimental)
+    /// By default, no ACK_FREQUENCY frame is sent.
+    /// See draft-ietf-quic-ack-frequency.
+    pub fn set_ack_frequency(&mut self, threshold: u64, max_ack_delay: u64) {
+        self.recovery.ack_frequency = Some((
+            cmp::max(threshold, 1),
+            Duration::from_millis(cmp::min(max_ack_delay, VINT_MAX / 1000)),
+        ));
+    }
+
     /// Set congestion control algorithm that the connection would use.
     /// The default value is Bbr.
     pub fn set_congestion_control_algorithm(&mut self, cca: CongestionControlAlgorithm) {
         self.recovery.congestion_control_algorithm = cca;
     }
 
+    /// Set a factory of user-provided congestion controllers. A congestion
+    /// controller is built by the factory for each new path, and the
+    /// `congestion_control_algorithm` setting is ignored.
+    /// The default value is `None`.
+    pub fn set_congestion_controller_factory(
+        &mut self,
+        factory: Arc<dyn CongestionControllerFactory>,
+    ) {
+        self.recovery.congestion_controller_factory = Some(factory);
+    }
+
+    /// Set a selector of the congestion control algorithm and its parameters
+    /// for each server connection, according to the address of the client,
+    /// the server name and the negotiated application protocol. It is only
+    /// applicable to the server.
+    /// The default value is `None`.
+    pub fn set_congestion_control_selector(
+        &mut self,
+        selector: Arc<dyn CongestionControlSelector>,
+    ) {
+        self.congestion_control_selector = Some(selector);
+    }
+
     /// Set the initial congestion window in packets.
     /// The default value is 10.
     pub fn set_initial_congestion_window(&mut self, packets: u64) {
@@ -519,12 +922,44 @@ impl Config {
         self.recovery.min_congestion_window = packets
     }
 
+    /// Set the initial congestion window in bytes, which takes precedence
+    /// over `set_initial_congestion_window()`. The value 0 means the initial
+    /// congestion window is set in packets.
+    /// The default value is 0.
+    pub fn set_initial_congestion_window_bytes(&mut self, bytes: u64) {
+        self.recovery.initial_congestion_window_bytes = if bytes > 0 { Some(bytes) } else { None };
+    }
+
+    /// Set the minimal congestion window in bytes, which takes precedence
+    /// over `set_min_congestion_window()`. The value 0 means the minimal
+    /// congestion window is set in packets.
+    /// The default value is 0.
+    pub fn set_min_congestion_window_bytes(&mut self, bytes: u64) {
+        self.recovery.min_congestion_window_bytes = if bytes > 0 { Some(bytes) } else { None };
+    }
+
     /// Set the threshold for slow start in packets.
     /// The default value is the maximum value of u64.
     pub fn set_slow_start_thresh(&mut self, packets: u64) {
         self.recovery.slow_start_thresh = packets
     }
 
+    /// Enable HyStart++ (RFC 9406) in CUBIC slow start, which exits slow start
+    /// before overshooting the path by detecting the increase of RTT.
+    /// The default value is true.
+    pub fn enable_cubic_hystart(&mut self, v: bool) {
+        self.recovery.cubic_hystart_enabled = v;
+    }
+
+    /// Enable Proportional Rate Reduction (RFC 6937) in the loss-based
+    /// congestion controllers, i.e. CUBIC and Prague, which paces out the
+    /// packets during recovery instead of stopping transmission until the
+    /// bytes in flight drop below the reduced congestion window.
+    /// The default value is false.
+    pub fn enable_prr(&mut self, v: bool) {
+        self.recovery.prr_enabled = v;
+    }
+
     /// Set the minimum duration for BBR ProbeRTT state in milliseconds.
     /// The default value is 200 milliseconds.
     pub fn set_bbr_probe_rtt_duration(&mut self, millis: u64) {
@@ -574,6 +1009,25 @@ impl Config {
         self.recovery.copa_use_standing_rtt = v;
     }
 
+    /// Set the target queueing delay in milliseconds for LEDBAT.
+    /// The default value is 60 milliseconds.
+    pub fn set_ledbat_target_delay(&mut self, millis: u64) {
+        self.recovery.ledbat_target_delay =
+            cmp::max(Duration::from_millis(millis), TIMER_GRANULARITY);
+    }
+
+    /// Set the minimum target bitrate in bits per second for NADA.
+    /// The default value is 150000 bits per second.
+    pub fn set_nada_min_bitrate(&mut self, bps: u64) {
+        self.recovery.nada_min_bitrate = bps;
+    }
+
+    /// Set the maximum target bitrate in bits per second for NADA.
+    /// The default value is 1500000 bits per second.
+    pub fn set_nada_max_bitrate(&mut self, bps: u64) {
+        self.recovery.nada_max_bitrate = bps;
+    }
+
     /// Set the initial RTT in milliseconds. The default value is 333ms.
     ///
     /// The configuration should be changed with caution. Setting a value less than the default
@@ -582,6 +1036,23 @@ impl Config {
         self.recovery.initial_rtt = cmp::max(Duration::from_millis(millis), TIMER_GRANULARITY);
     }
 
+    /// Set the probe timeout before the first RTT sample is taken, as a
+    /// multiple of the initial RTT. A smaller value retransmits the Initial
+    /// and Handshake data earlier, which improves the handshake completion
+    /// time on lossy networks, without changing the initial RTT used by the
+    /// congestion controller and the pacer. The timeout still backs off upon
+    /// consecutive probe timeouts. Values less than `1.0` will be ignored, and
+    /// values greater than `100.0` will be capped.
+    /// By default, the timeout is derived from the initial RTT as recommended,
+    /// which is equivalent to `3.0`. It is not recommended to use a value less
+    /// than `1.5`, which may cause spurious retransmissions.
+    /// See RFC 9002 Section 6.2.2.
+    pub fn set_initial_pto_factor(&mut self, v: f64) {
+        if v >= 1.0 {
+            self.recovery.initial_pto_factor = Some(v.min(MAX_INITIAL_PTO_FACTOR));
+        }
+    }
+
     /// Enable pacing to smooth the flow of packets sent onto the network.
     /// The default value is true.
     pub fn enable_pacing(&mut self, v: bool) {
@@ -595,6 +1066,60 @@ impl Config {
             cmp::max(Duration::from_millis(millis), TIMER_GRANULARITY);
     }
 
+    /// Set the minimum number of packets that the pacer may release back to
+    /// back. The burst size of the pacer is the congestion window sent in a
+    /// pacing granularity, bounded by the minimum and the maximum. A larger
+    /// burst helps the senders with GSO on high speed NICs, while a smaller
+    /// one reduces the queueing on the slow links of mobile clients.
+    /// The default value is 10.
+    pub fn set_pacing_min_burst(&mut self, packets: u64) {
+        self.recovery.pacing_min_burst = cmp::max(packets, 1);
+    }
+
+    /// Set the maximum number of packets that the pacer may release back to
+    /// back. It should not be less than the minimum.
+    /// The default value is 128.
+    pub fn set_pacing_max_burst(&mut self, packets: u64) {
+        self.recovery.pacing_max_burst = cmp::max(packets, 1);
+    }
+
+    /// Set the upper limit of the pacing rate in bytes per second, which caps
+    /// the sending rate of each path regardless of the congestion controller.
+    /// Pacing is always applied if the limit is set. The value `0` means no
+    /// limit. The default value is `0`.
+    pub fn set_max_pacing_rate(&mut self, v: u64) {
+        self.recovery.max_pacing_rate = if v > 0 { Some(v) } else { None };
+    }
+
+    /// Enable offloading pacing to the kernel or hardware, e.g. via SO_TXTIME
+    /// with the ETF qdisc. The outgoing packets are not delayed by the pacer,
+    /// and the time to release each packet is exported in `PacketInfo::time`.
+    /// It takes effect only if pacing is enabled.
+    /// The default value is false.
+    pub fn enable_pacing_offload(&mut self, v: bool) {
+        self.recovery.pacing_offload = v;
+    }
+
+    /// Enable Careful Resume for the server. The congestion window of a
+    /// connection is jump-started with the path capacity saved in the address
+    /// token, which is sent by `Connection::send_capacity_token()` in a
+    /// previous connection. The saved capacity is used only if the RTT of the
+    /// path is similar, and it is validated before use.
+    /// See draft-ietf-tsvwg-careful-resume
+    /// The default value is false.
+    pub fn enable_careful_resume(&mut self, v: bool) {
+        self.recovery.careful_resume = v;
+    }
+
+    /// Set the delay in microseconds for coalescing small stream data. The
+    /// stream data that is not enough to fill a packet is held for at most
+    /// the delay before being sent, so that data written by multiple small
+    /// writes can be packed into fewer packets. The value `0` means the data
+    /// is sent immediately. The default value is `0`.
+    pub fn set_send_coalescing_delay(&mut self, v: u64) {
+        self.recovery.send_coalescing_delay = Duration::from_micros(v);
+    }
+
     /// Set the linear factor for calculating the probe timeout.
     /// The endpoint do not backoff the first `v` consecutive probe timeouts.
     /// The default value is `0`.
@@ -612,6 +1137,75 @@ impl Config {
         self.recovery.max_pto = cmp::max(Duration::from_millis(millis), TIMER_GRANULARITY);
     }
 
+    /// Set the multiplier of the probe timeout upon each backoff. The value
+    /// `1` disables the exponential backoff. Values less than `1` will be
+    /// ignored.
+    /// The default value is `2`.
+    /// See RFC 9002 Section 6.2.1.
+    pub fn set_pto_multiplier(&mut self, v: u32) {
+        if v >= 1 {
+            self.recovery.pto_multiplier = v;
+        }
+    }
+
+    /// Set the upper limit of the consecutive probe timeout backoffs. The
+    /// probe timeout stops growing after `v` backoffs.
+    /// The default value is unlimited.
+    pub fn set_max_pto_backoff(&mut self, v: u32) {
+        self.recovery.max_pto_backoff = v;
+    }
+
+    /// Set the packet threshold of loss detection. A packet is declared lost
+    /// if a packet sent at least `v` packets later has been acknowledged.
+    /// Values less than `1` will be ignored.
+    /// The default value is `3`. It is not recommended to use a value less
+    /// than the default.
+    /// See RFC 9002 Section 6.1.1.
+    pub fn set_packet_reordering_threshold(&mut self, v: u64) {
+        if v >= 1 {
+            self.recovery.packet_reordering_threshold = v;
+        }
+    }
+
+    /// Enable adapting the reordering thresholds of loss detection. When a
+    /// packet is acknowledged after being declared lost, the packet threshold
+    /// is increased to the observed reordering distance, and the time
+    /// threshold is increased to the observed reordering delay, which reduces
+    /// spurious losses on paths with heavy reordering, such as the paths over
+    /// link aggregation.
+    /// The default value is false.
+    /// See RFC 9002 Section 6.1 and RFC 8985 Section 6.2.
+    pub fn enable_adaptive_reordering(&mut self, v: bool) {
+        self.recovery.adaptive_reordering = v;
+    }
+
+    /// Set the initial time threshold of loss detection, as a multiplier of
+    /// the RTT. A packet sent longer than the threshold before an acknowledged
+    /// packet is declared lost. Values less than `1.0` will be ignored.
+    /// The default value is `1.125`.
+    /// See RFC 9002 Section 6.1.2.
+    pub fn set_time_reordering_threshold(&mut self, v: f64) {
+        if v >= 1.0 {
+            self.recovery.time_reordering_threshold = v;
+        }
+    }
+
+    /// Set the content of the probe packets sent upon a probe timeout in the
+    /// Application Data packet number space.
+    /// The default value is `PtoProbeStrategy::OldestUnacked`.
+    /// See RFC 9002 Section 6.2.4.
+    pub fn set_pto_probe_strategy(&mut self, v: PtoProbeStrategy) {
+        self.recovery.pto_probe_strategy = v;
+    }
+
+    /// Enable sending the two probe packets of a path on different paths if
+    /// multipath is enabled. One probe is moved to another active path, so
+    /// that it is delivered even if the path suffers from a blackout.
+    /// The default value is false.
+    pub fn enable_pto_probes_across_paths(&mut self, v: bool) {
+        self.recovery.pto_probes_across_paths = v;
+    }
+
     /// Set the `active_connection_id_limit` transport parameter.
     /// The default value is `2`. Lower values will be ignored.
     pub fn set_active_connection_id_limit(&mut self, v: u64) {
@@ -632,6 +1226,14 @@ impl Config {
         self.multipath.multipath_algorithm = v;
     }
 
+    /// Set the weights of paths indexed by path id, which are used by the
+    /// RoundRobin scheduler. A path with weight N is selected N times in a row
+    /// before moving to the next path. Paths without a weight are given 1.
+    /// The default value is empty.
+    pub fn set_multipath_path_weights(&mut self, v: Vec<u32>) {
+        self.multipath.path_weights = v;
+    }
+
     /// Set the maximum size of the connection flow control window.
     /// The default value is MAX_CONNECTION_WINDOW (15 MB).
     pub fn set_max_connection_window(&mut self, v: u64) {
@@ -645,12 +1247,134 @@ impl Config {
         self.max_stream_window = cmp::min(v, VINT_MAX);
     }
 
+    /// Set the number of RTTs without a flow control window update after which
+    /// an auto-tuned receive window is halved. The windows are evaluated when
+    /// the application reads data and when a timer of the connection fires,
+    /// so the windows of idle connections decay as well. By default, a window
+    /// is never shrunk below its initial size, i.e. `initial_max_data` for the
+    /// connection and `initial_max_stream_data_*` for streams. This allows
+    /// endpoints with many idle connections to reclaim memory budget. `0`
+    /// disables window decay.
+    /// The default value is `0`.
+    pub fn set_window_decay_rtts(&mut self, v: u32) {
+        self.window_decay_rtts = v;
+    }
+
+    /// Set the floor of the receive windows when they decay, which applies to
+    /// the connection and all streams. A window is never grown by the floor,
+    /// or shrunk below the credit which has been advertised to the peer.
+    /// The default value is `0`, which means the initial window sizes are
+    /// used as the floors.
+    pub fn set_window_decay_floor(&mut self, v: u64) {
+        self.window_decay_floor = v;
+    }
+
+    /// Enable allocating the stream receive windows dynamically from the
+    /// connection receive window. If enabled, the credit advertised in a
+    /// MAX_STREAM_DATA frame is limited to the connection credit that has not
+    /// been allocated to other streams, but no less than a fair share of the
+    /// connection window. It prevents the connection-level flow control from
+    /// being exhausted by many half-used stream windows.
+    /// The default value is `false`.
+    pub fn enable_stream_window_sharing(&mut self, v: bool) {
+        self.stream_window_sharing = v;
+    }
+
+    /// Set the strategy for auto-tuning the receiver flow control windows.
+    /// The tuner is consulted by the connection and each of its streams when
+    /// a window update is about to be sent, and the resulting window is
+    /// capped by `max_connection_window` or `max_stream_window`.
+    /// The default value is `DefaultWindowTuner`, which doubles the window if
+    /// window updates occur within 2 RTTs.
+    pub fn set_window_tuner(&mut self, tuner: Arc<dyn WindowTuner>) {
+        self.window_tuner = Some(tuner);
+    }
+
+    /// Set the target interval between flow control window updates, in units
+    /// of srtt. If window updates occur within the interval, the window is
+    /// grown by `flow_control_autotune_factor`. It is only applicable to the
+    /// default window tuner. The value is capped at `100.0`, and NaN will be
+    /// ignored.
+    /// The default value is `2.0`.
+    pub fn set_flow_control_autotune_interval_rtts(&mut self, v: f64) {
+        if !v.is_nan() {
+            self.flow_control_autotune_interval_rtts = v.clamp(0.0, MAX_AUTOTUNE_INTERVAL_RTTS);
+        }
+    }
+
+    /// Set the growth factor of the flow control window when auto-tuning.
+    /// The value should not be less than `1.0`. It is only applicable to the
+    /// default window tuner.
+    /// The default value is `2.0`.
+    pub fn set_flow_control_autotune_factor(&mut self, v: f64) {
+        self.flow_control_autotune_factor = v.max(1.0);
+    }
+
+    /// Set the policy of scheduling the data of sendable streams into packets.
+    /// The default value is StreamSchedulingPolicy::Priority
+    pub fn set_stream_scheduling_policy(&mut self, v: StreamSchedulingPolicy) {
+        self.stream_scheduling_policy = v;
+    }
+
+    /// Set a factory of user-provided stream schedulers. A stream scheduler
+    /// is built by the factory for each new connection, and the
+    /// `stream_scheduling_policy` setting is ignored.
+    /// The default value is `None`.
+    pub fn set_stream_scheduler_factory(&mut self, factory: Arc<dyn StreamSchedulerFactory>) {
+        self.stream_scheduler_factory = Some(factory);
+    }
+
+    /// Return a new stream scheduler for a connection.
+    fn stream_scheduler(&self) -> Box<dyn StreamScheduler> {
+        match &self.stream_scheduler_factory {
+            Some(factory) => factory.build(),
+            None => build_stream_scheduler(self.stream_scheduling_policy),
+        }
+    }
+
+    /// Return the window tuner used by the connections.
+    fn window_tuner(&self) -> Arc<dyn WindowTuner> {
+        match &self.window_tuner {
+            Some(tuner) => tuner.clone(),
+            None => Arc::new(DefaultWindowTuner::new(
+                self.flow_control_autotune_interval_rtts,
+                self.flow_control_autotune_factor,
+            )),
+        }
+    }
+
+    /// Set the maximum size of data received in out-of-order CRYPTO frames
+    /// that can be buffered for each encryption level. The connection is
+    /// closed with a CRYPTO_BUFFER_EXCEEDED error if the peer sends more.
+    /// The value should not be less than 4096.
+    /// The default value is 65536.
+    pub fn set_max_crypto_buffer_size(&mut self, v: u64) {
+        self.max_crypto_buffer_size = cmp::max(v, MIN_CRYPTO_BUFFER_SIZE);
+    }
+
     /// Set the maximum number of concurrent connections.
     /// The default value is `1000000`
     pub fn set_max_concurrent_conns(&mut self, v: u32) {
         self.max_concurrent_conns = v;
     }
 
+    /// Set the maximum memory in bytes used by all the connections of an
+    /// endpoint. If it is exceeded, the endpoint refuses new connections and
+    /// closes the connection using the most memory.
+    /// The default value is `0`, which means no limit.
+    pub fn set_max_memory_usage(&mut self, v: u64) {
+        self.max_memory_usage = v;
+    }
+
+    /// Set the maximum sum in bytes of the receiver connection flow control
+    /// windows of all the connections of an endpoint. When the budget is
+    /// tight, window autotuning is suppressed, and the growth of the windows
+    /// advertised in MAX_DATA frames is limited to the remaining budget.
+    /// The default value is `0`, which means no limit.
+    pub fn set_max_recv_window_budget(&mut self, v: u64) {
+        self.max_recv_window_budget = v;
+    }
+
     /// Set the key for reset token generation.
     /// Applicable to Server only.
     pub fn set_reset_token_key(&mut self, v: [u8; 64]) {
@@ -664,22 +1388,21 @@ impl Config {
         self.address_token_lifetime = Duration::from_secs(seconds);
     }
 
-    /// Set the key for address token generation.
+    /// Set the keys for address token generation and validation.
+    ///
+    /// The first key is used for generating new tokens, and all of the keys
+    /// are used for validating tokens. Each token carries a key ID derived
+    /// from the key material, so the servers sharing the same keys behind a
+    /// load balancer could validate tokens issued by each other. For key
+    /// rotation, add the new key in front of the old ones, and remove the old
+    /// ones after the lifetime of address tokens.
     /// Applicable to Server only.
     pub fn set_address_token_key(&mut self, keys: Vec<[u8; 16]>) -> Result<()> {
         if keys.is_empty() {
             return Err(Error::InvalidConfig("address token key empty".into()));
         }
 
-        let mut address_token_key = vec![];
-        for key in keys {
-            // AES-128 uses a 128-bit key length
-            let key = UnboundKey::new(&aead::AES_128_GCM, &key).map_err(|_| Error::CryptoFail)?;
-            let key = LessSafeKey::new(key);
-            address_token_key.push(key);
-        }
-        self.address_token_key = address_token_key;
-
+        self.address_token_key = keys.iter().map(|k| AddressTokenKey::new(k)).collect();
         Ok(())
     }
 
@@ -695,6 +1418,16 @@ impl Config {
         self.stateless_reset = enable_stateless_reset;
     }
 
+    /// Set the maximum number of stateless packets (Version Negotiation, Retry
+    /// and Stateless Reset) sent to a source address per second.
+    /// The value 0 means no limit. Setting a limit also drops Retry packets
+    /// beyond it, which may delay the clients behind a shared NAT address.
+    /// The default value is `0`.
+    /// Applicable to Endpoint only.
+    pub fn set_stateless_response_rate(&mut self, v: u32) {
+        self.stateless_response_rate = v;
+    }
+
     /// Set the length of source cid.
     /// Applicable to Endpoint only.
     pub fn set_cid_len(&mut self, v: usize) {
@@ -715,6 +1448,36 @@ impl Config {
         self.send_batch_size = cmp::max(v, 1);
     }
 
+    /// Set the maximum number of UDP datagrams of the same size written to a
+    /// single packet buffer. If it is greater than 1, the `PacketSendHandler`
+    /// should send the buffer with UDP generic segmentation offload according
+    /// to `PacketInfo::segment_size`. The value is capped to 64.
+    /// Applicable to Endpoint only.
+    /// The default value is 1.
+    pub fn set_max_send_segments(&mut self, v: usize) {
+        self.max_send_segments = v.clamp(1, MAX_SEND_SEGMENTS);
+    }
+
+    /// Set the maximum number of peer hosts whose path properties are cached
+    /// by the endpoint. When a connection is closed, the RTT and congestion
+    /// window measured on its active path are saved, and a new connection to
+    /// the same host uses the saved smoothed RTT as its initial RTT. The
+    /// congestion window is jump-started with the saved capacity only if
+    /// Careful Resume is enabled. See RFC 9040.
+    /// The default value is `0`, which means the cache is disabled.
+    /// Applicable to Endpoint only.
+    pub fn set_path_cache_size(&mut self, v: usize) {
+        self.path_cache_size = v;
+    }
+
+    /// Set the lifetime in seconds of the cached path properties. Expired
+    /// entries are not used by new connections.
+    /// The default value is `600`.
+    /// Applicable to Endpoint only.
+    pub fn set_path_cache_lifetime(&mut self, seconds: u64) {
+        self.path_cache_lifetime = Duration::from_secs(seconds);
+    }
+
     /// Set the buffer size for disordered zerortt packets on the server.
     /// The default value is `1000`. A value of 0 will be treated as default value.
     /// Applicable to Server only.
@@ -744,6 +1507,80 @@ impl Config {
         self.local_transport_params.disable_encryption = !v;
     }
 
+    /// Set whether to advertise the grease_quic_bit transport parameter, which
+    /// allows the peer to set the QUIC Bit of packets to an unpredictable value.
+    /// The default value is false.
+    /// See RFC 9287.
+    pub fn enable_grease_quic_bit(&mut self, v: bool) {
+        self.local_transport_params.grease_quic_bit = v;
+    }
+
+    /// Set whether to use the latency spin bit, which allows on-path
+    /// observers to measure the RTT of the connection. Even if enabled, the
+    /// spin bit is disabled on a random selection of one in every 16
+    /// connections.
+    /// The default value is false.
+    /// See RFC 9000 Section 17.4.
+    pub fn enable_spin_bit(&mut self, v: bool) {
+        self.spin_bit = v;
+    }
+
+    /// Set the DSCP codepoint of the outgoing packets, which allows network
+    /// level prioritization of the traffic. Only the lower 6 bits are used.
+    /// It is carried in `PacketInfo` and the application should set it in
+    /// the IP header of the outgoing packets. It may be changed for a
+    /// connection by `Connection::set_dscp` or a specific path by
+    /// `Connection::set_path_dscp`.
+    /// The default value is `0` (Default Forwarding).
+    pub fn set_dscp(&mut self, v: u8) {
+        self.dscp = v & MAX_DSCP;
+    }
+
+    /// Set the average interval in packets between the packet numbers which
+    /// are skipped on purpose in the application data space. An endpoint
+    /// that acknowledges a skipped packet number is performing an optimistic
+    /// ACK attack, and the connection is closed with PROTOCOL_VIOLATION. The
+    /// skipped packet numbers are chosen randomly. The value `0` disables
+    /// skipping packet numbers.
+    /// The default value is `0`.
+    /// See RFC 9000 Section 21.4.
+    pub fn set_packet_number_skip_interval(&mut self, v: u64) {
+        self.pkt_num_skip_interval = v;
+    }
+
+    /// Set the granularity in milliseconds for coalescing the timers of a
+    /// connection, such as the loss detection, idle, pacing and ACK delay
+    /// timers. The expiration time of each timer is rounded up to a multiple
+    /// of the granularity, so that the timers expiring close to each other
+    /// are processed in a single timeout event, and the connection timeout
+    /// changes less frequently. It reduces the timer churn for the endpoints
+    /// hosting a large number of connections, at the cost of delaying the
+    /// timers by up to the granularity. The value `0` disables coalescing.
+    /// The default value is `0`.
+    pub fn set_timer_granularity(&mut self, millis: u64) {
+        self.timer_granularity = Duration::from_millis(millis);
+    }
+
+    /// Enable the BDP_FRAME extension, which allows the server to share the
+    /// measured path capacity with the client. (Experimental)
+    /// The default value is false.
+    pub fn enable_bdp_frame(&mut self, v: bool) {
+        self.local_transport_params.enable_bdp = v;
+    }
+
+    /// Set whether to report the observed addresses to the peer, and whether
+    /// to receive the observed addresses from the peer, with the address
+    /// discovery extension. (Experimental)
+    /// The default value is false for both.
+    pub fn enable_address_discovery(&mut self, provide: bool, receive: bool) {
+        self.local_transport_params.address_discovery = match (provide, receive) {
+            (true, false) => Some(0),
+            (false, true) => Some(1),
+            (true, true) => Some(2),
+            (false, false) => None,
+        };
+    }
+
     /// Set TLS config.
     pub fn set_tls_config(&mut self, tls_config: tls::TlsConfig) {
         self.set_tls_config_selector(Arc::new(tls::DefaultTlsConfigSelector {
@@ -760,12 +1597,10 @@ impl Config {
     }
 
     /// Generate random address token key.
-    fn rand_address_token_key() -> Result<Vec<LessSafeKey>> {
+    fn rand_address_token_key() -> Result<Vec<AddressTokenKey>> {
         let mut key = [0_u8; 16];
         rand::thread_rng().fill_bytes(&mut key);
-        Ok(vec![LessSafeKey::new(
-            UnboundKey::new(&aead::AES_128_GCM, &key).map_err(|_| Error::CryptoFail)?,
-        )])
+        Ok(vec![AddressTokenKey::new(&key)])
     }
 
     /// Create new tls session.
@@ -787,6 +1622,15 @@ pub struct RecoveryConfig {
     /// Enable Datagram Packetization Layer Path MTU Discovery.
     pub enable_dplpmtud: bool,
 
+    /// The policy of using Explicit Congestion Notification.
+    pub ecn_policy: EcnPolicy,
+
+    /// The number of ECN validation failures before falling back.
+    pub ecn_max_failures: u32,
+
+    /// Whether to test new paths if the ECN validation failed on another path.
+    pub ecn_retest_on_migration: bool,
+
     /// The maximum size of outgoing UDP payloads.
     pub max_datagram_size: usize,
 
@@ -798,9 +1642,17 @@ pub struct RecoveryConfig {
     /// sending an acknowledgment.
     ack_eliciting_threshold: u64,
 
+    /// The ack-eliciting threshold and max_ack_delay requested from the peer
+    /// with an ACK_FREQUENCY frame.
+    ack_frequency: Option<(u64, Duration)>,
+
     /// The congestion control algorithm used for a path.
     pub congestion_control_algorithm: CongestionControlAlgorithm,
 
+    /// The factory of user-provided congestion controllers, which takes
+    /// precedence over `congestion_control_algorithm`.
+    pub congestion_controller_factory: Option<Arc<dyn CongestionControllerFactory>>,
+
     /// The minimal congestion window in packets.
     /// The RECOMMENDED value is 2 * max_datagram_size.
     /// See RFC 9002 Section 7.2
@@ -813,9 +1665,24 @@ pub struct RecoveryConfig {
     /// See RFC 9002 Section 7.2
     pub initial_congestion_window: u64,
 
+    /// The minimal congestion window in bytes, which takes precedence over
+    /// `min_congestion_window`.
+    pub min_congestion_window_bytes: Option<u64>,
+
+    /// The initial congestion window in bytes, which takes precedence over
+    /// `initial_congestion_window`.
+    pub initial_congestion_window_bytes: Option<u64>,
+
     /// The threshold for slow start in packets.
     pub slow_start_thresh: u64,
 
+    /// Enable HyStart++ in CUBIC slow start.
+    pub cubic_hystart_enabled: bool,
+
+    /// Enable Proportional Rate Reduction in the loss-based congestion
+    /// controllers.
+    pub prr_enabled: bool,
+
     /// The minimum duration for BBR ProbeRTT state
     pub bbr_probe_rtt_duration: Duration,
 
@@ -840,33 +1707,94 @@ pub struct RecoveryConfig {
     /// Use rtt standing instead of latest rtt to calculate queueing delay
     pub copa_use_standing_rtt: bool,
 
+    /// The target queueing delay for LEDBAT.
+    pub ledbat_target_delay: Duration,
+
+    /// The minimum target bitrate in bits per second for NADA.
+    pub nada_min_bitrate: u64,
+
+    /// The maximum target bitrate in bits per second for NADA.
+    pub nada_max_bitrate: u64,
+
     /// The initial rtt, used before real rtt is estimated.
     pub initial_rtt: Duration,
 
+    /// The probe timeout before the first RTT sample, as an initial RTT
+    /// multiplier.
+    pub initial_pto_factor: Option<f64>,
+
     /// Enable pacing to smooth the flow of packets sent onto the network.
     pub enable_pacing: bool,
 
     /// Clock granularity used by the pacer.
     pub pacing_granularity: Duration,
 
+    /// The minimum number of packets released back to back by the pacer.
+    pub pacing_min_burst: u64,
+
+    /// The maximum number of packets released back to back by the pacer.
+    pub pacing_max_burst: u64,
+
+    /// Upper limit of the pacing rate in bytes per second.
+    pub max_pacing_rate: Option<u64>,
+
+    /// Offload pacing to the kernel or hardware.
+    pub pacing_offload: bool,
+
+    /// Enable Careful Resume with the saved path capacity.
+    pub careful_resume: bool,
+
+    /// The delay for coalescing small stream data.
+    pub send_coalescing_delay: Duration,
+
     /// Linear factor for calculating the probe timeout.
     pub pto_linear_factor: u64,
 
     /// Upper limit of probe timeout.
     pub max_pto: Duration,
+
+    /// Multiplier of probe timeout upon each backoff.
+    pub pto_multiplier: u32,
+
+    /// Upper limit of the consecutive probe timeout backoffs.
+    pub max_pto_backoff: u32,
+
+    /// The initial packet threshold of loss detection.
+    pub packet_reordering_threshold: u64,
+
+    /// Whether to adapt the reordering thresholds upon spurious losses.
+    pub adaptive_reordering: bool,
+
+    /// The initial time threshold of loss detection, as an RTT multiplier.
+    pub time_reordering_threshold: f64,
+
+    /// The content of the probe packets sent upon a probe timeout.
+    pub pto_probe_strategy: PtoProbeStrategy,
+
+    /// Whether to send the two probe packets on different paths.
+    pub pto_probes_across_paths: bool,
 }
 
 impl Default for RecoveryConfig {
     fn default() -> RecoveryConfig {
         RecoveryConfig {
             enable_dplpmtud: true,
+            ecn_policy: EcnPolicy::Disabled,
+            ecn_max_failures: 1,
+            ecn_retest_on_migration: true,
             max_datagram_size: DEFAULT_SEND_UDP_PAYLOAD_SIZE, // The upper limit is determined by DPLPMTUD
             max_ack_delay: time::Duration::from_millis(0),
             ack_eliciting_threshold: 2,
+            ack_frequency: None,
             congestion_control_algorithm: CongestionControlAlgorithm::Bbr,
+            congestion_controller_factory: None,
             min_congestion_window: 2_u64,
             initial_congestion_window: 10_u64,
+            min_congestion_window_bytes: None,
+            initial_congestion_window_bytes: None,
             slow_start_thresh: u64::MAX,
+            cubic_hystart_enabled: true,
+            prr_enabled: false,
             bbr_probe_rtt_duration: Duration::from_millis(200),
             bbr_probe_rtt_based_on_bdp: false,
             bbr_probe_rtt_cwnd_gain: 0.75,
@@ -875,27 +1803,70 @@ impl Default for RecoveryConfig {
             copa_slow_start_delta: congestion_control::COPA_DELTA,
             copa_steady_delta: congestion_control::COPA_DELTA,
             copa_use_standing_rtt: true,
+            ledbat_target_delay: congestion_control::LEDBAT_TARGET_DELAY,
+            nada_min_bitrate: congestion_control::NADA_MIN_BITRATE,
+            nada_max_bitrate: congestion_control::NADA_MAX_BITRATE,
             initial_rtt: INITIAL_RTT,
+            initial_pto_factor: None,
             enable_pacing: true,
             pacing_granularity: time::Duration::from_millis(1),
+            pacing_min_burst: congestion_control::MIN_BURST_PACKET_NUM,
+            pacing_max_burst: congestion_control::MAX_BURST_PACKET_NUM,
+            max_pacing_rate: None,
+            pacing_offload: false,
+            careful_resume: false,
+            send_coalescing_delay: Duration::ZERO,
             pto_linear_factor: DEFAULT_PTO_LINEAR_FACTOR,
             max_pto: MAX_PTO,
+            pto_multiplier: DEFAULT_PTO_MULTIPLIER,
+            max_pto_backoff: DEFAULT_MAX_PTO_BACKOFF,
+            packet_reordering_threshold: DEFAULT_PACKET_THRESHOLD,
+            adaptive_reordering: false,
+            time_reordering_threshold: DEFAULT_TIME_THRESHOLD,
+            pto_probe_strategy: PtoProbeStrategy::OldestUnacked,
+            pto_probes_across_paths: false,
         }
     }
 }
 
+impl RecoveryConfig {
+    /// Return the minimal congestion window in bytes, which applies to all
+    /// congestion control algorithms.
+    pub fn min_cwnd(&self) -> u64 {
+        self.min_congestion_window_bytes.unwrap_or_else(|| {
+            self.min_congestion_window
+                .saturating_mul(self.max_datagram_size as u64)
+        })
+    }
+
+    /// Return the initial congestion window in bytes, which applies to all
+    /// congestion control algorithms. It is not less than the minimal
+    /// congestion window.
+    pub fn initial_cwnd(&self) -> u64 {
+        let cwnd = self.initial_congestion_window_bytes.unwrap_or_else(|| {
+            self.initial_congestion_window
+                .saturating_mul(self.max_datagram_size as u64)
+        });
+        cwnd.max(self.min_cwnd())
+    }
+}
+
 /// Configurations about multipath transport.
 #[doc(hidden)]
 #[derive(Debug, Clone)]
 pub struct MultipathConfig {
     /// Multipath scheduling algorithm.
     multipath_algorithm: MultipathAlgorithm,
+
+    /// Weights of paths indexed by path id.
+    path_weights: Vec<u32>,
 }
 
 impl Default for MultipathConfig {
     fn default() -> MultipathConfig {
         MultipathConfig {
             multipath_algorithm: MultipathAlgorithm::MinRtt,
+            path_weights: Vec::new(),
         }
     }
 }
@@ -929,6 +1900,22 @@ enum Event {
 
     /// The stream is closed.
     StreamClosed(u64),
+
+    /// The application probe with the given tag has been acknowledged.
+    PingAcked(u64, Duration),
+
+    /// The connection or the peer is blocked by flow control.
+    FlowControlBlocked(BlockedEvent),
+
+    /// The target bitrate in bits per second for the media encoder is
+    /// updated by the congestion controller.
+    TargetBitrateUpdated(u64),
+
+    /// A persistent congestion is declared on a path.
+    PersistentCongestion(PersistentCongestionEvent),
+
+    /// A stream event subscribed by the application occurred.
+    StreamNotified(u64, StreamEvent),
 }
 
 #[derive(Default)]
@@ -995,6 +1982,56 @@ impl ConnectionQueues {
     }
 }
 
+/// The receive window budget shared by all the connections of an endpoint.
+#[derive(Debug, Default)]
+struct RecvWindowBudget {
+    /// Maximum sum of the receive windows. `0` means no limit.
+    limit: u64,
+
+    /// Sum of the receive windows reserved by the connections.
+    used: u64,
+}
+
+impl RecvWindowBudget {
+    fn new(limit: u64) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    /// Reserve the initial receive window of a connection. The initial window
+    /// is always granted, since it has already been advertised to the peer.
+    fn reserve(&mut self, window: u64) {
+        self.used = self.used.saturating_add(window);
+    }
+
+    /// Release the receive window reserved by a connection.
+    fn release(&mut self, window: u64) {
+        self.used = self.used.saturating_sub(window);
+    }
+
+    /// Resize the reserved receive window of a connection from `old` to
+    /// `new`, and return the window actually granted.
+    fn resize(&mut self, old: u64, new: u64) -> u64 {
+        if new <= old {
+            self.release(old - new);
+            return new;
+        }
+
+        let growth = if self.limit == 0 {
+            new - old
+        } else {
+            cmp::min(new - old, self.limit.saturating_sub(self.used))
+        };
+        self.reserve(growth);
+        old + growth
+    }
+
+    /// Return true if most of the budget has been used, in which case window
+    /// autotuning should be suppressed.
+    fn is_tight(&self) -> bool {
+        self.limit > 0 && self.used.saturating_mul(4) >= self.limit.saturating_mul(3)
+    }
+}
+
 /// The TransportHandler lists the callbacks used by the endpoint to
 /// communicate with the user application code.
 pub trait TransportHandler {
@@ -1029,6 +2066,33 @@ pub trait TransportHandler {
 
     /// Called when client receives a token in NEW_TOKEN frame.
     fn on_new_token(&mut self, conn: &mut Connection, token: Vec<u8>);
+
+    /// Called when the Ping frame sent by `Connection::ping_with_tag()` is
+    /// acknowledged by the peer, with the RTT measured for the probe.
+    fn on_ping_acked(&mut self, _conn: &mut Connection, _tag: u64, _rtt: Duration) {}
+
+    /// Called when the connection is blocked by the flow control limits of
+    /// the peer, or the peer reports that it is blocked by the local limits.
+    fn on_flow_control_blocked(&mut self, _conn: &mut Connection, _event: BlockedEvent) {}
+
+    /// Called when the target bitrate in bits per second for the media
+    /// encoder is updated by a congestion controller designed for real-time
+    /// media, such as NADA.
+    fn on_target_bitrate_updated(&mut self, _conn: &mut Connection, _bitrate: u64) {}
+
+    /// Called when a persistent congestion is declared on a path, which
+    /// explains a sudden collapse of the throughput.
+    fn on_persistent_congestion(
+        &mut self,
+        _conn: &mut Connection,
+        _event: PersistentCongestionEvent,
+    ) {
+    }
+
+    /// Called when a stream event subscribed by `Connection::stream_want_reset()`,
+    /// `Connection::stream_want_stop_sending()` or `Connection::stream_want_acked()`
+    /// occurs. The subscription is cancelled once the event is reported.
+    fn on_stream_event(&mut self, _conn: &mut Connection, _stream_id: u64, _event: StreamEvent) {}
 }
 
 /// The PacketSendHandler lists the callbacks used by the endpoint to
@@ -1053,6 +2117,86 @@ pub enum Shutdown {
     Write = 1,
 }
 
+/// The decision about the stream data declared lost.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LostDataAction {
+    /// Retransmit the lost data.
+    Retransmit,
+
+    /// Skip the retransmission, and reset the send-side of the stream with the
+    /// given application error code. The final size of the stream is the
+    /// offset of all the data written, so the flow control state of both
+    /// endpoints stays consistent.
+    Reset(u64),
+}
+
+/// Application hook that decides whether the stream data declared lost is
+/// retransmitted. It enables partial reliability without any extension, for
+/// example, an outdated video frame sent on its own stream could be abandoned
+/// instead of delaying the following frames.
+pub trait StreamLossPolicy: Send + Sync {
+    /// Called when the data of `length` bytes at `offset` of the stream is
+    /// declared lost.
+    fn on_data_lost(&self, stream_id: u64, offset: u64, length: usize) -> LostDataAction;
+}
+
+/// Events about flow control blocking on a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockedEvent {
+    /// The connection is blocked by the connection-level flow control limit
+    /// of the peer, and a DATA_BLOCKED frame is sent.
+    DataBlocked { limit: u64 },
+
+    /// The stream is blocked by the stream-level flow control limit of the
+    /// peer, and a STREAM_DATA_BLOCKED frame is sent.
+    StreamDataBlocked { stream_id: u64, limit: u64 },
+
+    /// The peer is blocked by the local connection-level flow control limit,
+    /// as reported by a DATA_BLOCKED frame.
+    PeerDataBlocked { limit: u64 },
+
+    /// The peer is blocked by the local stream-level flow control limit, as
+    /// reported by a STREAM_DATA_BLOCKED frame.
+    PeerStreamDataBlocked { stream_id: u64, limit: u64 },
+}
+
+/// Stream events which can be subscribed by the application, in addition to
+/// the readable and writable events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// The peer abruptly terminated the sending part of the stream, as
+    /// reported by a RESET_STREAM frame.
+    Reset { error_code: u64 },
+
+    /// The peer requested to stop sending data on the stream, as reported by
+    /// a STOP_SENDING frame.
+    StopSending { error_code: u64 },
+
+    /// The data sent on the stream has been acknowledged contiguously up to
+    /// the given offset.
+    Acked { offset: u64 },
+}
+
+/// Diagnostics of a persistent congestion declared on a path, which collapses
+/// the congestion window to the minimum.
+/// See RFC 9002 Section 7.6.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PersistentCongestionEvent {
+    /// The address tuple of the path.
+    pub path: FourTuple,
+
+    /// The duration between the send time of the earliest and the latest
+    /// ack-eliciting packets declared lost.
+    pub duration: Duration,
+
+    /// The number of packets sent in the period, all of which are declared
+    /// lost.
+    pub lost_packets: u64,
+
+    /// The congestion window in bytes after the collapse.
+    pub congestion_window: u64,
+}
+
 /// Important events about path
 pub enum PathEvent {
     /// The path has been validated.
@@ -1114,6 +2258,20 @@ pub struct PathStats {
     /// Total duration of congestion windowlimited events in microseconds.
     pub cwnd_limited_duration: u64,
 
+    /// Total application limited events, during which there is no data to
+    /// send from the application.
+    pub app_limited_count: u64,
+
+    /// Total duration of application limited events in microseconds.
+    pub app_limited_duration: u64,
+
+    /// Total flow control limited events, during which sending is blocked by
+    /// the flow control of the peer.
+    pub flow_control_limited_count: u64,
+
+    /// Total duration of flow control limited events in microseconds.
+    pub flow_control_limited_duration: u64,
+
     /* Note: the following fields are lazily updated from Recovery */
     /// Minimum roundtrip time in microseconds.
     pub min_rtt: u64,
@@ -1132,6 +2290,60 @@ pub struct PathStats {
 
     /// Pacing rate estimated by congestion control algorithm.
     pub pacing_rate: u64,
+
+    /// Whether the path has been validated as ECN capable.
+    pub ecn_capable: bool,
+
+    /// Total number of packets with the ECN-CE codepoint reported by the peer.
+    pub ecn_ce_count: u64,
+
+    /// Total number of times the processing of ACK ranges is deferred since
+    /// there are too many ranges to process at once.
+    pub ack_deferred_count: u64,
+
+    /// Total number of ACK ranges dropped since too many ranges are pending.
+    pub ack_ranges_dropped: u64,
+
+    /// Bandwidth estimate in bytes per second, smoothed from the delivery
+    /// rate samples of the path. It is independent of the congestion control
+    /// algorithm, and could be used for adaptive bitrate decisions.
+    pub bandwidth: u64,
+
+    /// Confidence of the bandwidth estimate in percent, from `0` to `100`.
+    /// It grows with the number of samples, and drops with the variation of
+    /// the samples.
+    pub bandwidth_confidence: u8,
+
+    /// The latest delivery rate sample in bytes per second.
+    pub delivery_rate: u64,
+
+    /// Total number of packets acknowledged after being declared lost.
+    pub spurious_lost_count: u64,
+
+    /// The current packet threshold of loss detection.
+    pub packet_threshold: u64,
+
+    /// Total number of congestion events detected as spurious, whose window
+    /// reductions are undone.
+    pub spurious_congestion_count: u64,
+
+    /// Total number of probe timeouts.
+    pub pto_count: u64,
+
+    /// Total bytes of stream data retransmitted on the path.
+    pub retrans_stream_bytes: u64,
+
+    /// Total number of persistent congestions declared.
+    pub persistent_congestion_count: u64,
+
+    /// The number of packets lost in the Initial packet number space.
+    pub initial_lost_count: u64,
+
+    /// The number of packets lost in the Handshake packet number space.
+    pub handshake_lost_count: u64,
+
+    /// The number of packets lost in the Application Data packet number space.
+    pub app_data_lost_count: u64,
 }
 
 #[cfg(test)]
@@ -1160,6 +2372,21 @@ mod tests {
         assert_eq!(format!("{}", cid), "a8a8a8a8");
     }
 
+    #[test]
+    fn versions() -> Result<()> {
+        let mut config = Config::new()?;
+        assert_eq!(config.versions, vec![QUIC_VERSION_V1]);
+
+        config.set_versions(&[QUIC_VERSION_V2, QUIC_VERSION_V1, QUIC_VERSION_V2])?;
+        assert_eq!(config.versions, vec![QUIC_VERSION_V2, QUIC_VERSION_V1]);
+
+        assert!(config.set_versions(&[]).is_err());
+        assert!(config.set_versions(&[QUIC_VERSION_V1, 0x1a2a3a4a]).is_err());
+        assert_eq!(config.versions, vec![QUIC_VERSION_V2, QUIC_VERSION_V1]);
+
+        Ok(())
+    }
+
     #[test]
     fn initial_rtt() -> Result<()> {
         let mut config = Config::new()?;
@@ -1201,6 +2428,77 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn loss_detection_thresholds() -> Result<()> {
+        let mut config = Config::new()?;
+        assert_eq!(config.recovery.pto_multiplier, DEFAULT_PTO_MULTIPLIER);
+        assert_eq!(config.recovery.max_pto_backoff, DEFAULT_MAX_PTO_BACKOFF);
+        assert_eq!(
+            config.recovery.packet_reordering_threshold,
+            DEFAULT_PACKET_THRESHOLD
+        );
+        assert_eq!(
+            config.recovery.time_reordering_threshold,
+            DEFAULT_TIME_THRESHOLD
+        );
+
+        config.set_pto_multiplier(0);
+        assert_eq!(config.recovery.pto_multiplier, DEFAULT_PTO_MULTIPLIER);
+        config.set_pto_multiplier(1);
+        assert_eq!(config.recovery.pto_multiplier, 1);
+
+        config.set_max_pto_backoff(4);
+        assert_eq!(config.recovery.max_pto_backoff, 4);
+
+        config.set_packet_reordering_threshold(0);
+        assert_eq!(
+            config.recovery.packet_reordering_threshold,
+            DEFAULT_PACKET_THRESHOLD
+        );
+        config.set_packet_reordering_threshold(10);
+        assert_eq!(config.recovery.packet_reordering_threshold, 10);
+
+        config.set_time_reordering_threshold(0.5);
+        assert_eq!(
+            config.recovery.time_reordering_threshold,
+            DEFAULT_TIME_THRESHOLD
+        );
+        config.set_time_reordering_threshold(1.5);
+        assert_eq!(config.recovery.time_reordering_threshold, 1.5);
+
+        assert_eq!(config.recovery.initial_pto_factor, None);
+        config.set_initial_pto_factor(0.5);
+        assert_eq!(config.recovery.initial_pto_factor, None);
+        config.set_initial_pto_factor(1.5);
+        assert_eq!(config.recovery.initial_pto_factor, Some(1.5));
+        config.set_initial_pto_factor(f64::NAN);
+        assert_eq!(config.recovery.initial_pto_factor, Some(1.5));
+        config.set_initial_pto_factor(f64::INFINITY);
+        assert_eq!(
+            config.recovery.initial_pto_factor,
+            Some(MAX_INITIAL_PTO_FACTOR)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn pto_probe_strategy() -> Result<()> {
+        let mut config = Config::new()?;
+        assert_eq!(
+            config.recovery.pto_probe_strategy,
+            PtoProbeStrategy::OldestUnacked
+        );
+        assert!(!config.recovery.pto_probes_across_paths);
+
+        config.set_pto_probe_strategy(PtoProbeStrategy::Ping);
+        assert_eq!(config.recovery.pto_probe_strategy, PtoProbeStrategy::Ping);
+        config.enable_pto_probes_across_paths(true);
+        assert!(config.recovery.pto_probes_across_paths);
+
+        Ok(())
+    }
+
     #[test]
     fn initial_max_streams_bidi() -> Result<()> {
         let mut config = Config::new()?;
@@ -1212,15 +2510,108 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn flow_control_autotune() -> Result<()> {
+        let mut config = Config::new()?;
+        let srtt = Duration::from_millis(100);
+        assert_eq!(config.window_tuner().tune(10, 30, Some(srtt), srtt), 20);
+
+        config.set_flow_control_autotune_interval_rtts(1.0);
+        config.set_flow_control_autotune_factor(1.5);
+        let tuner = config.window_tuner();
+        assert_eq!(tuner.tune(10, 30, Some(srtt / 2), srtt), 15);
+        assert_eq!(tuner.tune(10, 30, Some(srtt), srtt), 10);
+
+        config.set_flow_control_autotune_factor(0.0);
+        assert_eq!(config.flow_control_autotune_factor, 1.0);
+
+        config.set_flow_control_autotune_interval_rtts(f64::NAN);
+        assert_eq!(config.flow_control_autotune_interval_rtts, 1.0);
+        config.set_flow_control_autotune_interval_rtts(f64::INFINITY);
+        assert_eq!(config.flow_control_autotune_interval_rtts, 100.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stream_scheduler() -> Result<()> {
+        struct TestFactory;
+        impl StreamSchedulerFactory for TestFactory {
+            fn build(&self) -> Box<dyn StreamScheduler> {
+                build_stream_scheduler(StreamSchedulingPolicy::Sequential)
+            }
+        }
+
+        let mut config = Config::new()?;
+        assert_eq!(config.stream_scheduler().name(), "priority");
+
+        config.set_stream_scheduling_policy(StreamSchedulingPolicy::WeightedFair);
+        assert_eq!(config.stream_scheduler().name(), "weightedfair");
+
+        config.set_stream_scheduler_factory(Arc::new(TestFactory));
+        assert_eq!(config.stream_scheduler().name(), "sequential");
+
+        Ok(())
+    }
+
+    #[test]
+    fn recv_window_budget() {
+        let mut budget = RecvWindowBudget::new(100);
+        budget.reserve(40);
+        assert!(!budget.is_tight());
+
+        // Growth is granted within the budget.
+        assert_eq!(budget.resize(40, 60), 60);
+        assert_eq!(budget.used, 60);
+
+        // Growth is limited to the remaining budget.
+        budget.reserve(30);
+        assert!(budget.is_tight());
+        assert_eq!(budget.resize(60, 80), 70);
+        assert_eq!(budget.used, 100);
+        assert_eq!(budget.resize(70, 90), 70);
+
+        // Shrinking always succeeds.
+        assert_eq!(budget.resize(70, 50), 50);
+        assert_eq!(budget.used, 80);
+        budget.release(50);
+        budget.release(30);
+        assert_eq!(budget.used, 0);
+
+        // No limit.
+        let mut budget = RecvWindowBudget::new(0);
+        assert_eq!(budget.resize(0, u64::MAX), u64::MAX);
+        assert!(!budget.is_tight());
+    }
 }
 
 pub use crate::congestion_control::CongestionControlAlgorithm;
+pub use crate::congestion_control::CongestionControlSelector;
+pub use crate::congestion_control::CongestionController;
+pub use crate::congestion_control::CongestionControllerFactory;
+pub use crate::congestion_control::CongestionStats;
+pub use crate::congestion_control::CoupledPaths;
+pub use crate::congestion_control::DeliveryRateEstimator;
+pub use crate::connection::flowcontrol::DefaultWindowTuner;
+pub use crate::connection::flowcontrol::WindowTuner;
 pub use crate::connection::path::Path;
+pub use crate::connection::rtt::RttEstimator;
+pub use crate::connection::space::SentPacket;
+pub use crate::connection::stream_scheduler::StreamScheduler;
+pub use crate::connection::stream_scheduler::StreamSchedulerFactory;
+pub use crate::connection::stream_scheduler::StreamSchedulingPolicy;
 pub use crate::connection::Connection;
 pub use crate::endpoint::Endpoint;
 pub use crate::error::Error;
 pub use crate::multipath_scheduler::MultipathAlgorithm;
 pub use crate::packet::PacketHeader;
+pub use crate::packet::PacketType;
+pub use crate::tls::set_crypto_provider;
+pub use crate::tls::Algorithm as AeadAlgorithm;
+pub use crate::tls::CryptoProvider;
+pub use crate::tls::HeaderKey;
+pub use crate::tls::PacketKey;
 pub use crate::tls::TlsConfig;
 pub use crate::tls::TlsConfigSelector;
 
@@ -1254,11 +2645,14 @@ mod h3_connection;
 mod codec;
 pub mod endpoint;
 pub mod error;
-mod frame;
+#[doc(hidden)]
+pub mod frame;
 mod packet;
-mod ranges;
+#[doc(hidden)]
+pub mod ranges;
 #[doc(hidden)]
 pub mod timer_queue;
 mod token;
-mod trans_param;
+#[doc(hidden)]
+pub mod trans_param;
 mod window;
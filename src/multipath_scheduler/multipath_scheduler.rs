@@ -110,14 +110,6 @@ pub(crate) fn build_multipath_scheduler(conf: &MultipathConfig) -> Box<dyn Multi
     }
 }
 
-pub(crate) fn buffer_required(algor: MultipathAlgorithm) -> bool {
-    match algor {
-        MultipathAlgorithm::MinRtt => false,
-        MultipathAlgorithm::Redundant => true,
-        MultipathAlgorithm::RoundRobin => false,
-    }
-}
-
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
@@ -27,17 +27,51 @@ use crate::Result;
 /// The simple scheduler aims to guarantee that the capacity of each path is
 /// fully utilized as the distribution across all path is equal. It is for
 /// testing purposes only.
+///
+/// If path weights are configured, a path with weight N is selected N times
+/// in a row before moving to the next path.
 pub struct RoundRobinScheduler {
     last: Option<usize>,
+
+    /// Weights of paths indexed by path id.
+    weights: Vec<u32>,
+
+    /// Number of consecutive selections of the last used path.
+    count: u32,
 }
 
 impl RoundRobinScheduler {
-    pub fn new(_conf: &MultipathConfig) -> RoundRobinScheduler {
-        RoundRobinScheduler { last: None }
+    pub fn new(conf: &MultipathConfig) -> RoundRobinScheduler {
+        RoundRobinScheduler {
+            last: None,
+            weights: conf.path_weights.clone(),
+            count: 0,
+        }
     }
 }
 
 impl RoundRobinScheduler {
+    /// Return the weight of the given path.
+    fn weight(&self, pid: usize) -> u32 {
+        self.weights.get(pid).copied().unwrap_or(1).max(1)
+    }
+
+    /// Try to select the last used path again if its weight is not used up.
+    fn select_last(&mut self, paths: &PathMap) -> Option<usize> {
+        let last = self.last?;
+        if self.count >= self.weight(last) {
+            return None;
+        }
+
+        let path = paths.get(last).ok()?;
+        if !path.active() || !path.recovery.can_send() {
+            return None;
+        }
+
+        self.count += 1;
+        Some(last)
+    }
+
     /// Iterate and find the last used path
     fn find_last(&self, iter: &mut slab::IterMut<Path>, last: usize) -> bool {
         for (pid, _) in iter.by_ref() {
@@ -58,6 +92,7 @@ impl RoundRobinScheduler {
             }
 
             self.last = Some(pid);
+            self.count = 1;
             return Some(pid);
         }
         None
@@ -72,6 +107,10 @@ impl MultipathScheduler for RoundRobinScheduler {
         spaces: &mut PacketNumSpaceMap,
         streams: &mut StreamMap,
     ) -> Result<usize> {
+        if let Some(pid) = self.select_last(paths) {
+            return Ok(pid);
+        }
+
         let mut iter = paths.iter_mut();
         let mut exist_last = false;
 
@@ -138,6 +177,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn round_robin_weighted_path() -> Result<()> {
+        let mut t = MultipathTester::new()?;
+        t.add_path("127.0.0.1:443", "127.0.0.2:8443", 50)?;
+        t.add_path("127.0.0.1:443", "127.0.0.3:8443", 150)?;
+
+        let mut conf = MultipathConfig::default();
+        conf.path_weights = vec![2, 0, 3];
+        let mut s = RoundRobinScheduler::new(&conf);
+        for pid in [0, 0, 1, 2, 2, 2, 0, 0, 1] {
+            assert_eq!(
+                s.on_select(&mut t.paths, &mut t.spaces, &mut t.streams)?,
+                pid
+            );
+        }
+
+        t.set_path_active(0, false)?;
+        for pid in [2, 2, 2, 1, 2] {
+            assert_eq!(
+                s.on_select(&mut t.paths, &mut t.spaces, &mut t.streams)?,
+                pid
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn round_robin_no_available_path() -> Result<()> {
         let mut t = MultipathTester::new()?;
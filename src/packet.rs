@@ -17,8 +17,6 @@ use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::time;
 
-use bytes::Bytes;
-use bytes::BytesMut;
 use rand::RngCore;
 use ring::aead;
 
@@ -42,7 +40,10 @@ use crate::MAX_CID_LEN;
 const HEADER_LONG_FORM_BIT: u8 = 0x80;
 
 /// The fixed bit of the first byte of packet header.
-const HEADER_FIXED_BIT: u8 = 0x40;
+pub(crate) const HEADER_FIXED_BIT: u8 = 0x40;
+
+/// The latency spin bit for 1RTT packets.
+pub(crate) const HEADER_SPIN_BIT: u8 = 0x20;
 
 /// The bit indicating the key phase for 1RTT packets.
 const HEADER_KEY_PHASE_BIT: u8 = 0x04;
@@ -74,6 +75,31 @@ const RETRY_INTEGRITY_NONCE_V1: [u8; aead::NONCE_LEN] = [
     0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
 ];
 
+/// The secret key for computing Retry Integrity Tag of QUIC version 2. It is
+/// 128 bits equal to 0x8fb4b01b56ac48e260fbcbcead7ccc92.
+/// See RFC 9369 Section 3.3.3
+const RETRY_INTEGRITY_KEY_V2: [u8; 16] = [
+    0x8f, 0xb4, 0xb0, 0x1b, 0x56, 0xac, 0x48, 0xe2, 0x60, 0xfb, 0xcb, 0xce, 0xad, 0x7c, 0xcc, 0x92,
+];
+
+/// The nonce for computing Retry Integrity Tag of QUIC version 2. It is 96
+/// bits equal to 0xd86969bc2d7c6d9990efb04a.
+const RETRY_INTEGRITY_NONCE_V2: [u8; aead::NONCE_LEN] = [
+    0xd8, 0x69, 0x69, 0xbc, 0x2d, 0x7c, 0x6d, 0x99, 0x90, 0xef, 0xb0, 0x4a,
+];
+
+lazy_static::lazy_static! {
+    /// The AEAD key for computing Retry Integrity Tag, which is expanded only
+    /// once since the secret key is fixed.
+    static ref RETRY_INTEGRITY_AEAD_KEY_V1: aead::LessSafeKey = aead::LessSafeKey::new(
+        aead::UnboundKey::new(&aead::AES_128_GCM, &RETRY_INTEGRITY_KEY_V1).unwrap(),
+    );
+
+    static ref RETRY_INTEGRITY_AEAD_KEY_V2: aead::LessSafeKey = aead::LessSafeKey::new(
+        aead::UnboundKey::new(&aead::AES_128_GCM, &RETRY_INTEGRITY_KEY_V2).unwrap(),
+    );
+}
+
 /// QUIC packet type.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PacketType {
@@ -223,11 +249,17 @@ impl PacketHeader {
         //   Type-Specific Payload (..),
         // }
         let mut first = HEADER_LONG_FORM_BIT | HEADER_FIXED_BIT;
-        let pkt_type: u8 = match self.pkt_type {
-            Initial => 0x00,
-            ZeroRTT => 0x01,
-            Handshake => 0x02,
-            Retry => 0x03,
+        let pkt_type: u8 = match (self.pkt_type, self.version) {
+            // The long header packet types of QUIC version 2 are different.
+            // See RFC 9369 Section 3.2
+            (Initial, crate::QUIC_VERSION_V2) => 0x01,
+            (ZeroRTT, crate::QUIC_VERSION_V2) => 0x02,
+            (Handshake, crate::QUIC_VERSION_V2) => 0x03,
+            (Retry, crate::QUIC_VERSION_V2) => 0x00,
+            (Initial, _) => 0x00,
+            (ZeroRTT, _) => 0x01,
+            (Handshake, _) => 0x02,
+            (Retry, _) => 0x03,
             _ => return Err(Error::InternalError),
         };
         first |= pkt_type << 4;
@@ -293,16 +325,17 @@ impl PacketHeader {
 
         // Decode in long header form.
         let version = buf.read_u32()?;
-        let pkt_type = if version == 0 {
-            VersionNegotiation
-        } else {
-            match (first & PKT_TYPE_MASK) >> 4 {
-                0x00 => Initial,
-                0x01 => ZeroRTT,
-                0x02 => Handshake,
-                0x03 => Retry,
-                _ => return Err(Error::InvalidPacket),
-            }
+        let pkt_type = match (version, (first & PKT_TYPE_MASK) >> 4) {
+            (0, _) => VersionNegotiation,
+            (crate::QUIC_VERSION_V2, 0x00) => Retry,
+            (crate::QUIC_VERSION_V2, 0x01) => Initial,
+            (crate::QUIC_VERSION_V2, 0x02) => ZeroRTT,
+            (crate::QUIC_VERSION_V2, 0x03) => Handshake,
+            (_, 0x00) => Initial,
+            (_, 0x01) => ZeroRTT,
+            (_, 0x02) => Handshake,
+            (_, 0x03) => Retry,
+            _ => return Err(Error::InvalidPacket),
         };
 
         let dcid_len = buf.read_u8()?;
@@ -450,6 +483,94 @@ pub(crate) fn encrypt_packet(
     Ok(payload_offset + ciphertext_len)
 }
 
+/// A QUIC packet written into a datagram buffer whose protection is pending.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PendingPacket {
+    /// The offset of the packet in the datagram buffer.
+    pub offset: usize,
+
+    /// The offset of packet payload relative to the start of the packet.
+    pub payload_offset: usize,
+
+    /// The length of packet payload in plaintext.
+    pub payload_len: usize,
+
+    /// The packet sequence number.
+    pub pkt_num: u64,
+
+    /// The encoded length of packet sequence number.
+    pub pkt_num_len: usize,
+
+    /// The sequence number of the destination connection id, used in
+    /// multipath mode.
+    pub cid_seq: Option<u32>,
+
+    /// The encryption level of the packet.
+    pub level: Level,
+}
+
+/// Encrypt payloads and header fields of QUIC packets written into the
+/// datagram buffer `buf`.
+///
+/// All the payloads are sealed in the first pass and header protection is
+/// applied to all the packets in the second pass, so that the operations of
+/// the same kind are performed back to back by the crypto backend rather than
+/// interleaved per packet.
+///
+/// The `seal` returns the AEAD key for the given encryption level.
+pub(crate) fn encrypt_packets<'a, F>(buf: &mut [u8], pkts: &[PendingPacket], seal: F) -> Result<()>
+where
+    F: Fn(Level) -> Option<&'a Seal>,
+{
+    // Most datagrams carry only one packet.
+    if let [pkt] = pkts {
+        let aead = seal(pkt.level).ok_or(Error::InternalError)?;
+        if buf.len() < pkt.offset {
+            return Err(Error::BufferTooShort);
+        }
+        encrypt_packet(
+            &mut buf[pkt.offset..],
+            pkt.cid_seq,
+            pkt.pkt_num,
+            pkt.pkt_num_len,
+            pkt.payload_len,
+            pkt.payload_offset,
+            None,
+            aead,
+        )?;
+        return Ok(());
+    }
+
+    for pkt in pkts {
+        let aead = seal(pkt.level).ok_or(Error::InternalError)?;
+        let pkt_len = pkt.payload_offset + pkt.payload_len + aead.algor().tag_len();
+        if buf.len() < pkt.offset + pkt_len {
+            return Err(Error::BufferTooShort);
+        }
+
+        let pkt_buf = &mut buf[pkt.offset..pkt.offset + pkt_len];
+        let (pkt_hdr, payload) = pkt_buf.split_at_mut(pkt.payload_offset);
+        aead.seal(
+            pkt.cid_seq,
+            pkt.pkt_num,
+            pkt_hdr,
+            payload,
+            pkt.payload_len,
+            None,
+        )?;
+    }
+
+    for pkt in pkts {
+        let aead = seal(pkt.level).ok_or(Error::InternalError)?;
+        let pkt_len = pkt.payload_offset + pkt.payload_len + aead.algor().tag_len();
+        let pkt_buf = &mut buf[pkt.offset..pkt.offset + pkt_len];
+        let (pkt_hdr, payload) = pkt_buf.split_at_mut(pkt.payload_offset);
+        encrypt_header(pkt_hdr, pkt.pkt_num_len, payload, aead)?;
+    }
+
+    Ok(())
+}
+
 /// Apply header protection for a QUIC packet.
 ///
 /// Header protection is applied after packet protection is applied.
@@ -495,6 +616,9 @@ fn encrypt_header(
 /// The `paylaod_offset` is the offset of packet payload in `pkt_buf`.
 /// The `payload_len` is the length of pacekt payload (other than the value of Length field).
 /// The `pkt_num` is the decrypted and decoded packet number.
+///
+/// The payload is decrypted in place, and the length of the plaintext written
+/// at `payload_offset` is returned.
 #[allow(unexpected_cfgs)]
 pub(crate) fn decrypt_payload(
     pkt_buf: &mut [u8],
@@ -503,29 +627,20 @@ pub(crate) fn decrypt_payload(
     cid_seq: Option<u32>,
     pkt_num: u64,
     aead: &Open,
-) -> Result<bytes::Bytes> {
+) -> Result<usize> {
     if pkt_buf.len() < payload_offset + payload_len {
         return Err(Error::BufferTooShort);
     }
 
     let (header_buf, payload_buf) = pkt_buf.split_at_mut(payload_offset);
     let payload_buf = &mut payload_buf[..payload_len];
-    let mut plaintext = BytesMut::zeroed(payload_len);
 
     if cfg!(feature = "fuzzing") {
         // Not touch payload for fuzz testing
-        return Ok(Bytes::copy_from_slice(payload_buf));
+        return Ok(payload_len);
     }
 
-    let payload_len = aead.open(
-        cid_seq,
-        pkt_num,
-        header_buf,
-        payload_buf,
-        &mut plaintext[..],
-    )?;
-    plaintext.truncate(payload_len);
-    Ok(plaintext.freeze())
+    aead.open_in_place(cid_seq, pkt_num, header_buf, payload_buf)
 }
 
 /// Remove header protection of a QUIC packet.
@@ -682,7 +797,13 @@ pub(crate) fn packet_num_len(pkt_num: u64, largest_acked: Option<u64>) -> usize
 ///
 /// The `scid` is the source CID of the Version Negotiation packet.
 /// The `dcid` is the destination CID of the Version Negotiation packet.
-pub fn version_negotiation(scid: &[u8], dcid: &[u8], mut buf: &mut [u8]) -> Result<usize> {
+/// The `versions` is the list of versions supported by the server.
+pub fn version_negotiation(
+    scid: &[u8],
+    dcid: &[u8],
+    versions: &[u32],
+    mut buf: &mut [u8],
+) -> Result<usize> {
     let len = buf.len();
 
     let first = rand::random::<u8>() | HEADER_LONG_FORM_BIT;
@@ -700,7 +821,9 @@ pub fn version_negotiation(scid: &[u8], dcid: &[u8], mut buf: &mut [u8]) -> Resu
 
     // The remainder of the Version Negotiation packet is a list of 32-bit
     // versions that the server supports
-    buf.write_u32(crate::QUIC_VERSION_V1)?;
+    for version in versions {
+        buf.write_u32(*version)?;
+    }
 
     Ok(len - buf.len())
 }
@@ -746,7 +869,7 @@ pub fn retry(
 /// Compute the Retry Packet Integrity Tag
 ///
 /// See RFC 9001 Section 5.8 Retry Packet Integrity.
-fn compute_retry_integrity_tag(retry_hdr: &[u8], odcid: &[u8], _version: u32) -> Result<aead::Tag> {
+fn compute_retry_integrity_tag(retry_hdr: &[u8], odcid: &[u8], version: u32) -> Result<aead::Tag> {
     // The Retry Pseudo-Packet is computed by taking the transmitted Retry
     // packet, removing the Retry Integrity Tag, and prepending the two
     // following fields: Original DCID Length, Original DCID
@@ -759,10 +882,10 @@ fn compute_retry_integrity_tag(retry_hdr: &[u8], odcid: &[u8], _version: u32) ->
     // The Retry Integrity Tag is a 128-bit field that is computed as the output
     // of AEAD_AES_128_GCM; The plaintext is empty; The associated data is the
     // contents of the Retry Pseudo-Packet
-    let (key, nonce) = (&RETRY_INTEGRITY_KEY_V1, RETRY_INTEGRITY_NONCE_V1);
-    let key = aead::LessSafeKey::new(
-        aead::UnboundKey::new(&aead::AES_128_GCM, key).map_err(|_| Error::CryptoFail)?,
-    );
+    let (key, nonce) = match version {
+        crate::QUIC_VERSION_V2 => (&*RETRY_INTEGRITY_AEAD_KEY_V2, RETRY_INTEGRITY_NONCE_V2),
+        _ => (&*RETRY_INTEGRITY_AEAD_KEY_V1, RETRY_INTEGRITY_NONCE_V1),
+    };
     let nonce = aead::Nonce::assume_unique_for_key(nonce);
     let aad = aead::Aad::from(&pseudo_pkt);
     key.seal_in_place_separate_tag(nonce, aad, &mut [])
@@ -1014,14 +1137,15 @@ mod tests {
         };
 
         let mut buf = [0; 128];
-        let len = version_negotiation(&scid, &dcid, &mut buf)?;
+        let versions = [crate::QUIC_VERSION_V2, crate::QUIC_VERSION_V1];
+        let len = version_negotiation(&scid, &dcid, &versions, &mut buf)?;
 
         let br = &buf[..len];
         let (hdr, hdr_len) = PacketHeader::from_bytes(br, 20)?;
         assert_eq!(hdr.pkt_type, PacketType::VersionNegotiation);
         assert_eq!(hdr.scid, scid);
         assert_eq!(hdr.dcid, dcid);
-        assert_eq!(len, hdr_len + 4);
+        assert_eq!(len, hdr_len + 8);
         assert_eq!(
             format!("{:?}", hdr),
             "VersionNegotiation ver=0 \
@@ -1033,8 +1157,8 @@ mod tests {
         assert_eq!(info, (true, hdr.version, hdr.dcid));
 
         let mut br = &buf[hdr_len..];
-        let ver = br.read_u32()?;
-        assert_eq!(ver, crate::QUIC_VERSION_V1);
+        assert_eq!(br.read_u32()?, crate::QUIC_VERSION_V2);
+        assert_eq!(br.read_u32()?, crate::QUIC_VERSION_V1);
 
         assert_eq!(hdr.to_bytes(&mut buf), Err(Error::InternalError));
         Ok(())
@@ -1092,6 +1216,68 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn long_header_pkt_types_v2() -> Result<()> {
+        let mut buf = [0; 128];
+        for (pkt_type, first) in [
+            (PacketType::Initial, 0xd0),
+            (PacketType::ZeroRTT, 0xe0),
+            (PacketType::Handshake, 0xf0),
+        ] {
+            let hdr = PacketHeader {
+                pkt_type,
+                version: crate::QUIC_VERSION_V2,
+                dcid: ConnectionId::new(&[1; 8]),
+                scid: ConnectionId::new(&[3; 8]),
+                pkt_num: 0,
+                pkt_num_len: 0,
+                token: None,
+                key_phase: false,
+            };
+            let len = hdr.to_bytes(&mut buf)?;
+            assert_eq!(buf[0], first);
+            assert_eq!((hdr, len), PacketHeader::from_bytes(&buf, 8)?);
+        }
+        Ok(())
+    }
+
+    /// Unit test for RFC 9369 Appendix A.4 Retry.
+    #[test]
+    fn retry_pkt_v2() -> Result<()> {
+        let mut pkt = [
+            0xcf, 0x6b, 0x33, 0x43, 0xcf, 0x00, 0x08, 0xf0, 0x67, 0xa5, 0x50, 0x2a, 0x42, 0x62,
+            0xb5, 0x74, 0x6f, 0x6b, 0x65, 0x6e, 0xc8, 0x64, 0x6c, 0xe8, 0xbf, 0xe3, 0x39, 0x52,
+            0xd9, 0x55, 0x54, 0x36, 0x65, 0xdc, 0xc7, 0xb6,
+        ];
+        let odcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+        let (hdr, _) = PacketHeader::from_bytes(&pkt, 8)?;
+        assert_eq!(hdr.pkt_type, PacketType::Retry);
+        assert_eq!(hdr.version, crate::QUIC_VERSION_V2);
+        assert_eq!(hdr.token, Some(b"token".to_vec()));
+        verify_retry_integrity_tag(&mut pkt, &odcid, crate::QUIC_VERSION_V2)?;
+
+        let mut buf = [0; 128];
+        let len = retry(
+            &hdr.scid,
+            &hdr.dcid,
+            &odcid,
+            b"token",
+            crate::QUIC_VERSION_V2,
+            &mut buf,
+        )?;
+        assert_eq!(buf[0] & 0xf0, 0xc0);
+        assert_eq!(&buf[1..len - 16], &pkt[1..pkt.len() - 16]);
+        verify_retry_integrity_tag(&mut buf[..len], &odcid, crate::QUIC_VERSION_V2)?;
+
+        // The tag of QUIC version 1 is different
+        assert_eq!(
+            verify_retry_integrity_tag(&mut pkt, &odcid, crate::QUIC_VERSION_V1),
+            Err(Error::CryptoFail)
+        );
+        Ok(())
+    }
+
     #[test]
     fn stateless_reset_pkt() -> Result<()> {
         let token = [0xc; crate::RESET_TOKEN_LEN];
@@ -1396,7 +1582,7 @@ mod tests {
         // Decrypt QUIC packet payload
         let payload_off = pkt_num_off + hdr.pkt_num_len;
         let payload_len = length - hdr.pkt_num_len;
-        let plaintext = decrypt_payload(
+        let plaintext_len = decrypt_payload(
             &mut pkt[..],
             payload_off,
             payload_len,
@@ -1404,7 +1590,11 @@ mod tests {
             hdr.pkt_num,
             &open,
         )?;
-        assert_eq!(plaintext[..crypto_frame.len()], crypto_frame);
+        assert!(plaintext_len >= crypto_frame.len());
+        assert_eq!(
+            pkt[payload_off..payload_off + crypto_frame.len()],
+            crypto_frame
+        );
 
         Ok(())
     }
@@ -1463,7 +1653,7 @@ mod tests {
         // Decrypt QUIC packet payload
         let payload_off = pkt_num_off + hdr.pkt_num_len;
         let payload_len = length - hdr.pkt_num_len;
-        let plaintext = decrypt_payload(
+        let plaintext_len = decrypt_payload(
             &mut pkt[..],
             payload_off,
             payload_len,
@@ -1471,7 +1661,11 @@ mod tests {
             hdr.pkt_num,
             &open,
         )?;
-        assert_eq!(plaintext[..crypto_frame.len()], crypto_frame);
+        assert!(plaintext_len >= crypto_frame.len());
+        assert_eq!(
+            pkt[payload_off..payload_off + crypto_frame.len()],
+            crypto_frame
+        );
 
         Ok(())
     }
@@ -1503,7 +1697,11 @@ mod tests {
         b.write(&pkt_hdr_data)?;
         b.write(&pkt_payload)?;
 
-        let aead = Seal::new_with_secret(tls::Algorithm::ChaCha20Poly1305, secret.to_vec())?;
+        let aead = Seal::new_with_secret(
+            tls::Algorithm::ChaCha20Poly1305,
+            secret.to_vec(),
+            crate::QUIC_VERSION_V1,
+        )?;
         let written = encrypt_packet(
             out.as_mut_slice(),
             None,
@@ -1519,6 +1717,60 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn coalesced_packets_protection() -> Result<()> {
+        // Coalesce two copies of the packet in RFC 9001 Section A.5
+        let pkt_hdr_data = [0x42, 0x00, 0xbf, 0xf4];
+        let pkt_num = 654_360_564;
+        let pkt_num_len = 3;
+        let pkt_payload = [01];
+
+        let secret = [
+            0x9a, 0xc3, 0x12, 0xa7, 0xf8, 0x77, 0x46, 0x8e, 0xbe, 0x69, 0x42, 0x27, 0x48, 0xad,
+            0x00, 0xa1, 0x54, 0x43, 0xf1, 0x82, 0x03, 0xa0, 0x7d, 0x60, 0x60, 0xf6, 0x88, 0xf3,
+            0x0f, 0x21, 0x63, 0x2b,
+        ];
+
+        let pkt_expected = [
+            0x4c, 0xfe, 0x41, 0x89, 0x65, 0x5e, 0x5c, 0xd5, 0x5c, 0x41, 0xf6, 0x90, 0x80, 0x57,
+            0x5d, 0x79, 0x99, 0xc2, 0x5a, 0x5b, 0xfb,
+        ];
+
+        let mut out = vec![0_u8; pkt_expected.len() * 2];
+        let mut pkts = vec![];
+        for i in 0..2 {
+            let offset = i * pkt_expected.len();
+            let mut b = &mut out[offset..];
+            b.write(&pkt_hdr_data)?;
+            b.write(&pkt_payload)?;
+            pkts.push(PendingPacket {
+                offset,
+                payload_offset: pkt_hdr_data.len(),
+                payload_len: pkt_payload.len(),
+                pkt_num,
+                pkt_num_len,
+                cid_seq: None,
+                level: Level::OneRTT,
+            });
+        }
+
+        let aead = Seal::new_with_secret(
+            tls::Algorithm::ChaCha20Poly1305,
+            secret.to_vec(),
+            crate::QUIC_VERSION_V1,
+        )?;
+        encrypt_packets(&mut out, &pkts, |_| Some(&aead))?;
+        assert_eq!(&out[..pkt_expected.len()], &pkt_expected[..]);
+        assert_eq!(&out[pkt_expected.len()..], &pkt_expected[..]);
+
+        // The key of the encryption level is not available
+        assert_eq!(
+            encrypt_packets(&mut out, &pkts, |_| None),
+            Err(Error::InternalError)
+        );
+        Ok(())
+    }
+
     #[test]
     fn multipath_protection() -> Result<()> {
         let mut out = vec![0_u8; 128];
@@ -1544,7 +1796,11 @@ mod tests {
         out[payload_off..payload_end].copy_from_slice(&pkt_payload);
 
         // encrypt the packet header and payload
-        let seal = Seal::new_with_secret(tls::Algorithm::ChaCha20Poly1305, secret.to_vec())?;
+        let seal = Seal::new_with_secret(
+            tls::Algorithm::ChaCha20Poly1305,
+            secret.to_vec(),
+            crate::QUIC_VERSION_V1,
+        )?;
         let written = encrypt_packet(
             out.as_mut_slice(),
             cid_seq,
@@ -1563,7 +1819,11 @@ mod tests {
         assert_eq!(hdr.dcid, pkt_hdr.dcid);
         assert_eq!(hdr.key_phase, pkt_hdr.key_phase);
 
-        let open = Open::new_with_secret(tls::Algorithm::ChaCha20Poly1305, secret.to_vec())?;
+        let open = Open::new_with_secret(
+            tls::Algorithm::ChaCha20Poly1305,
+            secret.to_vec(),
+            crate::QUIC_VERSION_V1,
+        )?;
         decrypt_header(&mut out, read, &mut hdr, &open, false)?;
         assert_eq!(hdr.pkt_num_len, pkt_hdr.pkt_num_len);
         assert_eq!(hdr.pkt_num, pkt_hdr.pkt_num);
@@ -1571,7 +1831,7 @@ mod tests {
         // decrypt packet payload
         let payload_off = read + hdr.pkt_num_len;
         let payload_len = out.len() - read - hdr.pkt_num_len;
-        let plaintext = decrypt_payload(
+        let plaintext_len = decrypt_payload(
             &mut out,
             payload_off,
             payload_len,
@@ -1579,7 +1839,10 @@ mod tests {
             hdr.pkt_num,
             &open,
         )?;
-        assert_eq!(&pkt_payload[..], &plaintext);
+        assert_eq!(
+            &pkt_payload[..],
+            &out[payload_off..payload_off + plaintext_len]
+        );
 
         Ok(())
     }
@@ -166,6 +166,13 @@ pub enum EventData {
         done: Option<bool>,
     },
 
+    /// This event indicates the anti-amplification budget of the server before
+    /// the peer address is validated. It is emitted after a datagram is sent,
+    /// and the `budget` is the number of bytes that can still be sent before
+    /// receiving more data from the peer.
+    #[serde(rename = "connectivity:anti_amplification_budget_updated")]
+    ConnectivityAntiAmplificationBudgetUpdated { sent: u64, budget: u64 },
+
     /// QUIC endpoints each have their own list of of QUIC versions they support.
     /// The client uses the most likely version in their first initial. If the
     /// server does support that version, it replies with a version_negotiation
@@ -411,6 +418,11 @@ pub enum EventData {
         trigger: Option<KeyUpdateOrRetiredTrigger>, // Needed for 1RTT key updates
     },
 
+    /// This event is emitted when the peer acknowledges a packet number that
+    /// was skipped on purpose, which indicates an optimistic ACK attack.
+    #[serde(rename = "security:optimistic_ack_detected")]
+    SecurityOptimisticAckDetected { packet_number: u64 },
+
     /// This event groups initial parameters from both loss detection and congestion
     /// control into a single event. All these settings are typically set once and
     /// never change. Implementation that do, for some reason, change these
@@ -479,6 +491,25 @@ pub enum EventData {
         trigger: Option<PacketLostTrigger>,
     },
 
+    /// This event is emitted when a packet declared lost is acknowledged
+    /// later, with the reordering thresholds after the detection.
+    #[serde(rename = "recovery:spurious_loss_detected")]
+    RecoverySpuriousLossDetected {
+        header: Option<PacketHeader>,
+        packet_threshold: Option<u64>,
+        time_threshold: Option<f32>,
+    },
+
+    /// This event is emitted when a persistent congestion is declared, with
+    /// the duration of the congestion period in milliseconds, the number of
+    /// packets lost in the period, and the resulting congestion window.
+    #[serde(rename = "recovery:persistent_congestion_detected")]
+    RecoveryPersistentCongestion {
+        duration: f32,
+        lost_packets: u64,
+        congestion_window: u64,
+    },
+
     /// This event indicates which data was marked for retransmit upon detecting a
     /// packet loss (see packet_lost). Similar to our reasoning for the
     /// "frames_processed" event, in order to keep the amount of different events
@@ -493,6 +524,28 @@ pub enum EventData {
         new: EcnState,
     },
 
+    /// This event indicates that the sender of a path becomes limited by the
+    /// application, the congestion window or the flow control of the peer, or
+    /// it is no longer limited.
+    #[serde(rename = "recovery:send_limited_updated")]
+    RecoverySendLimitedUpdated {
+        old: Option<SendLimitedState>,
+        new: SendLimitedState,
+    },
+
+    /// This event is emitted when a delivery rate sample is generated upon
+    /// receiving an ACK frame. The rates are in bytes per second, and the
+    /// intervals are in milliseconds.
+    /// See draft-cheng-iccrg-delivery-rate-estimation
+    #[serde(rename = "recovery:delivery_rate_sampled")]
+    RecoveryDeliveryRateSampled {
+        delivery_rate: u64,
+        delivered: u64,
+        interval: Option<f32>,
+        rtt: Option<f32>,
+        is_app_limited: bool,
+    },
+
     /// This event contains HTTP/3 and QPACK-level settings, mostly those received
     /// from the HTTP/3 SETTINGS frame. All these parameters are typically set once
     /// and never change. However, they are typically set at different times during
@@ -699,6 +752,7 @@ impl EventData {
             ConnectivitySpinBitUpdated { .. } => EventImportance::Base,
             ConnectivityConnectionStateUpdated { .. } => EventImportance::Base,
             ConnectivityMtuUpdated { .. } => EventImportance::Extra,
+            ConnectivityAntiAmplificationBudgetUpdated { .. } => EventImportance::Extra,
 
             QuicParametersSet { .. } => EventImportance::Core,
             QuicDatagramsReceived { .. } => EventImportance::Extra,
@@ -714,13 +768,18 @@ impl EventData {
 
             SecurityKeyUpdated { .. } => EventImportance::Base,
             SecurityKeyDiscarded { .. } => EventImportance::Base,
+            SecurityOptimisticAckDetected { .. } => EventImportance::Base,
 
             RecoveryParametersSet { .. } => EventImportance::Base,
             RecoveryMetricsUpdated { .. } => EventImportance::Core,
             RecoveryCongestionStateUpdated { .. } => EventImportance::Base,
             RecoveryLossTimerUpdated { .. } => EventImportance::Extra,
             RecoveryPacketLost { .. } => EventImportance::Core,
+            RecoverySpuriousLossDetected { .. } => EventImportance::Extra,
+            RecoveryPersistentCongestion { .. } => EventImportance::Base,
             RecoveryMarkedForRetransmit { .. } => EventImportance::Extra,
+            RecoverySendLimitedUpdated { .. } => EventImportance::Extra,
+            RecoveryDeliveryRateSampled { .. } => EventImportance::Extra,
 
             H3ParametersSet { .. } => EventImportance::Base,
             H3StreamTypeSet { .. } => EventImportance::Base,
@@ -1334,6 +1393,15 @@ pub enum EcnState {
     Capable,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SendLimitedState {
+    NotLimited,
+    ApplicationLimited,
+    CongestionLimited,
+    FlowControlLimited,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum Http3StreamType {
@@ -12,19 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cmp;
+use std::collections::vec_deque;
+use std::collections::VecDeque;
 use std::ops::Range;
 
-use std::collections::btree_map;
-use std::collections::BTreeMap;
-use std::collections::Bound::Excluded;
-use std::collections::Bound::Included;
-use std::collections::Bound::Unbounded;
-
 /// A set of u64 values, support range operations, like insert, remove, etc.
+///
+/// The ranges are kept sorted in a deque, which is optimized for the mostly
+/// contiguous insertions at the end of the set, such as the received packet
+/// numbers, and bounded number of ranges.
 #[derive(Clone, PartialEq, Eq, PartialOrd)]
 pub struct RangeSet {
-    /// The inner `RangeSet`.
-    set: BTreeMap<u64, u64>,
+    /// The sorted and non-overlapping ranges [start, end) in the set.
+    set: VecDeque<(u64, u64)>,
 
     /// The maximum items in the set.
     capacity: usize,
@@ -34,48 +35,48 @@ impl RangeSet {
     /// Create a new `RangeSet` with the given capacity.
     pub fn new(capacity: usize) -> Self {
         RangeSet {
-            set: BTreeMap::default(),
+            set: VecDeque::new(),
             capacity,
         }
     }
 
     /// Insert `range` into the set.
     /// Note that the range is [start, end), i.e. contains `start` but not `end`.
-    pub fn insert(&mut self, mut range: Range<u64>) {
+    pub fn insert(&mut self, range: Range<u64>) {
         // If the given range is empty, do nothing.
         if range.is_empty() {
             return;
         }
 
-        if let Some(r) = self.prev_to(range.start) {
-            if r.end >= range.end {
-                // Fully covered by preceding existing range, do nothing.
+        // Fast path: the new range overlaps with or follows the last range.
+        if let Some(last) = self.set.back_mut() {
+            if range.start >= last.0 && range.start <= last.1 {
+                last.1 = cmp::max(last.1, range.end);
                 return;
-            } else if r.end >= range.start {
-                // The new range overlaps with the preceding existing range, merge them into a single range.
-                self.set.remove(&r.start);
-                range.start = r.start;
             }
         }
 
-        while let Some(r) = self.next_to(range.start) {
-            // There is no overlap between the new range and the following existing range, break.
-            if r.start > range.end {
-                break;
-            }
-
-            // The new range overlaps with the following existing range, merge them into a single range.
-            self.set.remove(&r.start);
-            range.end = std::cmp::max(r.end, range.end);
+        // The ranges in [lo, hi) overlap with the new range, merge them into a
+        // single range.
+        let lo = self.set.partition_point(|&(_, e)| e < range.start);
+        let hi = self.set.partition_point(|&(s, _)| s <= range.end);
+        if lo < hi {
+            let start = cmp::min(self.set[lo].0, range.start);
+            let end = cmp::max(self.set[hi - 1].1, range.end);
+            self.set[lo] = (start, end);
+            self.set.drain(lo + 1..hi);
+            return;
         }
 
         // If the set is full, remove the first range.
+        let mut idx = lo;
         if self.len() >= self.capacity {
-            self.set.pop_first();
+            self.set.pop_front();
+            idx = idx.saturating_sub(1);
         }
 
         // Insert the new range.
-        self.set.insert(range.start, range.end);
+        self.set.insert(idx, (range.start, range.end));
     }
 
     /// Add `elem` to the set, i.e. insert range [elem, elem + 1) into the set.
@@ -90,38 +91,22 @@ impl RangeSet {
             return;
         }
 
-        // Check for any overlap between the given range and the preceding existing range.
-        if let Some(r) = self.prev_to(range.start) {
-            if r.end > range.start {
-                self.set.remove(&r.start);
-
-                if r.start < range.start {
-                    self.set.insert(r.start, range.start);
-                }
-
-                if r.end > range.end {
-                    self.set.insert(range.end, r.end);
-                }
-
-                // The following ranges would not overlap with the given range, return prematurely.
-                if r.end >= range.end {
-                    return;
-                }
-            }
+        // The ranges in [lo, hi) overlap with the given range.
+        let lo = self.set.partition_point(|&(_, e)| e <= range.start);
+        let hi = self.set.partition_point(|&(s, _)| s < range.end);
+        if lo >= hi {
+            return;
         }
 
-        // Check for any overlap between the given range and the following existing range.
-        while let Some(r) = self.next_to(range.start) {
-            // Following ranges would not overlap with the given range, break.
-            if r.start > range.end {
-                break;
-            }
-
-            self.set.remove(&r.start);
-            if r.end > range.end {
-                self.set.insert(range.end, r.end);
-                break;
-            }
+        // Keep the parts of the overlapped ranges out of the given range.
+        let start = self.set[lo].0;
+        let end = self.set[hi - 1].1;
+        self.set.drain(lo..hi);
+        if end > range.end {
+            self.set.insert(lo, (range.end, end));
+        }
+        if start < range.start {
+            self.set.insert(lo, (start, range.start));
         }
     }
 
@@ -132,18 +117,12 @@ impl RangeSet {
 
     /// Remove all ranges that are smaller or equal to `elem` from the set.
     pub fn remove_until(&mut self, elem: u64) {
-        let ranges: Vec<Range<u64>> = self
-            .set
-            .range((Unbounded, Included(&elem)))
-            .map(|(&s, &e)| (s..e))
-            .collect();
-
-        for r in ranges {
-            self.set.remove(&r.start);
+        let idx = self.set.partition_point(|&(_, e)| e <= elem + 1);
+        self.set.drain(..idx);
 
-            if r.end > elem + 1 {
-                let start = elem + 1;
-                self.insert(start..r.end);
+        if let Some(first) = self.set.front_mut() {
+            if first.0 <= elem {
+                first.0 = elem + 1;
             }
         }
     }
@@ -187,13 +166,12 @@ impl RangeSet {
 
     /// Return the minimum value in the set.
     pub fn min(&self) -> Option<u64> {
-        self.iter().next().map(|x| x.start)
+        self.set.front().map(|&(s, _)| s)
     }
 
     /// Return the maximum value in the set.
-    #[allow(clippy::manual_next_back)]
     pub fn max(&self) -> Option<u64> {
-        self.iter().rev().next().map(|x| x.end - 1)
+        self.set.back().map(|&(_, e)| e - 1)
     }
 
     /// Return the number of ranges in the set.
@@ -219,54 +197,50 @@ impl RangeSet {
 
     /// Find the closest range to `elem` that begins *at* or before it.
     fn prev_to(&self, elem: u64) -> Option<Range<u64>> {
-        self.set
-            .range((Unbounded, Included(elem)))
-            .map(|(&s, &e)| (s..e))
-            .next_back()
+        let idx = self.set.partition_point(|&(s, _)| s <= elem);
+        if idx == 0 {
+            return None;
+        }
+        let (s, e) = self.set[idx - 1];
+        Some(s..e)
     }
 
     /// Find the closest range to `elem` that begins *at* or after it.
     fn next_to(&self, elem: u64) -> Option<Range<u64>> {
-        self.set
-            .range((Included(elem), Unbounded))
-            .map(|(&s, &e)| (s..e))
-            .next()
+        let idx = self.set.partition_point(|&(s, _)| s < elem);
+        self.set.get(idx).map(|&(s, e)| (s..e))
     }
 
     /// Find the closest range to `elem` that begins after it.
     fn next_after(&self, elem: u64) -> Option<Range<u64>> {
-        self.set
-            .range((Excluded(elem), Unbounded))
-            .map(|(&s, &e)| (s..e))
-            .next()
+        let idx = self.set.partition_point(|&(s, _)| s <= elem);
+        self.set.get(idx).map(|&(s, e)| (s..e))
     }
 
     /// Check if the element exists or not
     pub fn contains(&self, elem: u64) -> bool {
-        if let Some(prev) = self.prev_to(elem) {
-            if prev.contains(&elem) {
-                return true;
-            }
-        }
-        if let Some(next) = self.next_to(elem) {
-            if next.contains(&elem) {
-                return true;
-            }
+        match self.prev_to(elem) {
+            Some(prev) => prev.contains(&elem),
+            None => false,
         }
-        false
     }
 
     /// Peek at the smallest range in the set.
     pub fn peek_min(&self) -> Option<Range<u64>> {
-        let (&start, &end) = self.set.iter().next()?;
+        let &(start, end) = self.set.front()?;
         Some(start..end)
     }
 
     /// Pop the smallest range in the set.
     pub fn pop_min(&mut self) -> Option<Range<u64>> {
-        let result = self.peek_min()?;
-        self.set.remove(&result.start);
-        Some(result)
+        let (start, end) = self.set.pop_front()?;
+        Some(start..end)
+    }
+
+    /// Pop the largest range in the set.
+    pub fn pop_max(&mut self) -> Option<Range<u64>> {
+        let (start, end) = self.set.pop_back()?;
+        Some(start..end)
     }
 }
 
@@ -304,21 +278,21 @@ impl std::fmt::Debug for RangeSet {
 }
 
 pub struct Iter<'a> {
-    set: btree_map::Iter<'a, u64, u64>,
+    set: vec_deque::Iter<'a, (u64, u64)>,
 }
 
 impl<'a> Iterator for Iter<'a> {
     type Item = Range<u64>;
 
     fn next(&mut self) -> Option<Range<u64>> {
-        let (&start, &end) = self.set.next()?;
+        let &(start, end) = self.set.next()?;
         Some(start..end)
     }
 }
 
 impl<'a> DoubleEndedIterator for Iter<'a> {
     fn next_back(&mut self) -> Option<Range<u64>> {
-        let (&start, &end) = self.set.next_back()?;
+        let &(start, end) = self.set.next_back()?;
         Some(start..end)
     }
 }
@@ -330,7 +304,7 @@ impl<'a> ExactSizeIterator for Iter<'a> {
 }
 
 pub struct Flatten<'a> {
-    set: btree_map::Iter<'a, u64, u64>,
+    set: vec_deque::Iter<'a, (u64, u64)>,
     next: u64,
     end: u64,
 }
@@ -340,7 +314,7 @@ impl<'a> Iterator for Flatten<'a> {
 
     fn next(&mut self) -> Option<u64> {
         if self.next == self.end {
-            let (&start, &end) = self.set.next()?;
+            let &(start, end) = self.set.next()?;
 
             self.next = start;
             self.end = end;
@@ -356,7 +330,7 @@ impl<'a> Iterator for Flatten<'a> {
 impl<'a> DoubleEndedIterator for Flatten<'a> {
     fn next_back(&mut self) -> Option<u64> {
         if self.next == self.end {
-            let (&start, &end) = self.set.next_back()?;
+            let &(start, end) = self.set.next_back()?;
 
             self.next = start;
             self.end = end;
@@ -1143,4 +1117,52 @@ mod tests {
 
         assert_eq!(r.pop_min(), None);
     }
+
+    #[test]
+    fn pop_max() {
+        let mut r = RangeSet::default();
+        assert_eq!(r.pop_max(), None);
+
+        for range in [(10..20), (50..60), (30..40)].iter() {
+            r.insert(range.clone());
+        }
+
+        for range in [(50..60), (30..40), (10..20)].iter() {
+            assert_eq!(r.pop_max(), Some(range.clone()));
+        }
+
+        assert_eq!(r.pop_max(), None);
+    }
+
+    #[test]
+    fn insert_mostly_contiguous() {
+        let mut r = RangeSet::new(crate::MAX_ACK_RANGES);
+
+        // Packet numbers received in order.
+        for i in 0..1000 {
+            r.add_elem(i);
+        }
+        assert_eq!(r.len(), 1);
+        assert_eq!(r.iter().next(), Some(0..1000));
+
+        // Packet numbers received with gaps and reordering.
+        for i in (1000..2000).step_by(10) {
+            r.add_elem(i + 1);
+            r.add_elem(i);
+        }
+        assert_eq!(r.len(), crate::MAX_ACK_RANGES);
+        assert_eq!(
+            r.min(),
+            Some(1000 + 10 * (100 - crate::MAX_ACK_RANGES as u64))
+        );
+        assert_eq!(r.max(), Some(1991));
+
+        // The missing packet numbers received later fill the gaps.
+        let start = r.min().unwrap();
+        for i in (start..1990).step_by(10) {
+            r.insert(i + 2..i + 10);
+        }
+        assert_eq!(r.len(), 1);
+        assert_eq!(r.iter().next(), Some(start..1992));
+    }
 }
@@ -20,9 +20,14 @@ use ring::aead;
 use ring::hkdf;
 
 use crate::tls::key;
+use crate::tls::provider;
+use crate::tls::provider::CryptoProvider;
+use crate::tls::provider::HeaderKey;
+use crate::tls::provider::PacketKey;
 use crate::Error;
 use crate::Result;
 
+/// AEAD algorithm used for packet protection.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Algorithm {
     Aes128Gcm,
@@ -79,41 +84,193 @@ impl Algorithm {
     }
 }
 
-struct HeaderKey {
-    key: aead::quic::HeaderProtectionKey,
-    raw: Vec<u8>,
-}
+/// The default crypto provider backed by BoringSSL, with header protection
+/// implemented by ring.
+pub struct BoringSslProvider;
 
-impl HeaderKey {
-    fn new(algor: Algorithm, hp_key: Vec<u8>) -> Result<Self> {
-        Ok(Self {
-            key: aead::quic::HeaderProtectionKey::new(algor.hp_algor(), &hp_key)
+impl CryptoProvider for BoringSslProvider {
+    fn new_packet_key(&self, algor: Algorithm, key: &[u8]) -> Result<Box<dyn PacketKey>> {
+        Ok(Box::new(BoringSslPacketKey {
+            ctx: new_aead_ctx(algor, key)?,
+            tag_len: algor.tag_len(),
+        }))
+    }
+
+    fn new_header_key(&self, algor: Algorithm, key: &[u8]) -> Result<Box<dyn HeaderKey>> {
+        Ok(Box::new(RingHeaderKey(
+            aead::quic::HeaderProtectionKey::new(algor.hp_algor(), key)
                 .map_err(|_| Error::CryptoFail)?,
-            raw: hp_key,
-        })
+        )))
     }
 }
 
-struct PacketKey {
+struct BoringSslPacketKey {
     ctx: EvpAeadCtx,
+    tag_len: usize,
+}
+
+impl PacketKey for BoringSslPacketKey {
+    fn seal(
+        &self,
+        nonce: &[u8],
+        ad: &[u8],
+        buf: &mut [u8],
+        in_len: usize,
+        extra_in: Option<&[u8]>,
+    ) -> Result<usize> {
+        let mut out_tag_len = self.tag_len;
+        let (extra_in_ptr, extra_in_len) = match extra_in {
+            Some(v) => (v.as_ptr(), v.len()),
+            None => (std::ptr::null(), 0),
+        };
+
+        let rc = unsafe {
+            EVP_AEAD_CTX_seal_scatter(
+                &self.ctx,
+                buf.as_mut_ptr(),
+                buf[in_len..].as_mut_ptr(),
+                &mut out_tag_len,
+                self.tag_len + extra_in_len,
+                nonce.as_ptr(),
+                nonce.len(),
+                buf.as_ptr(),
+                in_len,
+                extra_in_ptr,
+                extra_in_len,
+                ad.as_ptr(),
+                ad.len(),
+            )
+        };
+        if rc != 1 {
+            return Err(Error::CryptoFail);
+        }
+
+        Ok(in_len + out_tag_len)
+    }
+
+    fn open(
+        &self,
+        nonce: &[u8],
+        ad: &[u8],
+        ciphertext: &[u8],
+        plaintext: &mut [u8],
+    ) -> Result<usize> {
+        let mut out_len = match ciphertext.len().checked_sub(self.tag_len) {
+            Some(n) => n,
+            None => return Err(Error::CryptoFail),
+        };
+        if plaintext.len() < out_len {
+            return Err(Error::CryptoFail);
+        }
+
+        let max_out_len = out_len;
+        let rc = unsafe {
+            EVP_AEAD_CTX_open(
+                &self.ctx,
+                plaintext.as_mut_ptr(),
+                &mut out_len,
+                max_out_len,
+                nonce.as_ptr(),
+                nonce.len(),
+                ciphertext.as_ptr(),
+                ciphertext.len(),
+                ad.as_ptr(),
+                ad.len(),
+            )
+        };
+        if rc != 1 {
+            return Err(Error::CryptoFail);
+        }
+
+        Ok(out_len)
+    }
+
+    fn open_in_place(&self, nonce: &[u8], ad: &[u8], buf: &mut [u8]) -> Result<usize> {
+        let mut out_len = match buf.len().checked_sub(self.tag_len) {
+            Some(n) => n,
+            None => return Err(Error::CryptoFail),
+        };
+
+        let max_out_len = out_len;
+        let rc = unsafe {
+            EVP_AEAD_CTX_open(
+                &self.ctx,
+                buf.as_mut_ptr(),
+                &mut out_len,
+                max_out_len,
+                nonce.as_ptr(),
+                nonce.len(),
+                buf.as_ptr(),
+                buf.len(),
+                ad.as_ptr(),
+                ad.len(),
+            )
+        };
+        if rc != 1 {
+            return Err(Error::CryptoFail);
+        }
+
+        Ok(out_len)
+    }
+}
+
+struct RingHeaderKey(aead::quic::HeaderProtectionKey);
+
+impl HeaderKey for RingHeaderKey {
+    fn new_mask(&self, sample: &[u8]) -> Result<[u8; 5]> {
+        self.0.new_mask(sample).map_err(|_| Error::CryptoFail)
+    }
+}
+
+/// Keys created by the crypto provider in use.
+struct ProtectionKeys {
+    /// The raw header protection key, which is not updated by key updates.
+    hp_raw: Vec<u8>,
+    hdr_key: Box<dyn HeaderKey>,
+    pkt_key: Box<dyn PacketKey>,
     nonce: Vec<u8>,
 }
 
-impl PacketKey {
-    fn new(algor: Algorithm, key: Vec<u8>, iv: Vec<u8>) -> Result<Self> {
+impl ProtectionKeys {
+    fn new(algor: Algorithm, hp_key: Vec<u8>, key: Vec<u8>, iv: Vec<u8>) -> Result<Self> {
+        let provider = provider::crypto_provider();
         Ok(Self {
-            ctx: new_aead_ctx(algor, &key)?,
+            hdr_key: provider.new_header_key(algor, &hp_key)?,
+            pkt_key: provider.new_packet_key(algor, &key)?,
+            hp_raw: hp_key,
             nonce: iv,
         })
     }
+
+    fn new_with_secret(
+        algor: Algorithm,
+        secret: &[u8],
+        hp_key: Option<&[u8]>,
+        version: u32,
+    ) -> Result<Self> {
+        let mut key = vec![0; algor.key_len()];
+        let mut iv = vec![0; algor.nonce_len()];
+        key::derive_pkt_key(algor.hkdf_algor(), secret, version, &mut key)?;
+        key::derive_pkt_iv(algor.hkdf_algor(), secret, version, &mut iv)?;
+        let hp_key = match hp_key {
+            Some(v) => v.to_vec(),
+            None => {
+                let mut hp_key = vec![0; algor.key_len()];
+                key::derive_hdr_key(algor.hkdf_algor(), secret, version, &mut hp_key)?;
+                hp_key
+            }
+        };
+
+        Self::new(algor, hp_key, key, iv)
+    }
 }
 
 /// AEAD encryption.
 pub struct Seal {
     algor: Algorithm,
     secret: Vec<u8>,
-    hdr_key: HeaderKey,
-    pkt_key: PacketKey,
+    keys: ProtectionKeys,
+    version: u32,
 }
 
 impl Seal {
@@ -124,37 +281,44 @@ impl Seal {
         hp_key: Vec<u8>,
         key: Vec<u8>,
         iv: Vec<u8>,
+        version: u32,
     ) -> Result<Self> {
         Ok(Self {
             algor,
             secret,
-            hdr_key: HeaderKey::new(algor, hp_key)?,
-            pkt_key: PacketKey::new(algor, key, iv)?,
+            keys: ProtectionKeys::new(algor, hp_key, key, iv)?,
+            version,
         })
     }
 
     /// Create a new Seal with secret.
-    pub fn new_with_secret(algor: Algorithm, secret: Vec<u8>) -> Result<Self> {
-        let mut key = vec![0; algor.key_len()];
-        let mut iv = vec![0; algor.nonce_len()];
-        let mut hp_key = vec![0; algor.key_len()];
-        key::derive_pkt_key(algor.hkdf_algor(), &secret, &mut key)?;
-        key::derive_pkt_iv(algor.hkdf_algor(), &secret, &mut iv)?;
-        key::derive_hdr_key(algor.hkdf_algor(), &secret, &mut hp_key)?;
-
-        Self::new(algor, secret, hp_key, key, iv)
+    pub fn new_with_secret(algor: Algorithm, secret: Vec<u8>, version: u32) -> Result<Self> {
+        Ok(Self {
+            algor,
+            keys: ProtectionKeys::new_with_secret(algor, &secret, None, version)?,
+            secret,
+            version,
+        })
     }
 
     /// Derive next packet key.
     pub fn derive_next_packet_key(&self) -> Result<Self> {
         let mut next_secret = vec![0; self.secret.len()];
-        key::derive_next_packet_key(self.algor.hkdf_algor(), &self.secret, &mut next_secret)?;
-        let mut next_key = Self::new_with_secret(self.algor, next_secret)?;
+        key::derive_next_packet_key(
+            self.algor.hkdf_algor(),
+            &self.secret,
+            self.version,
+            &mut next_secret,
+        )?;
 
         // The header protection key is not updated.
-        next_key.hdr_key = HeaderKey::new(self.algor, self.hdr_key.raw.clone())?;
-
-        Ok(next_key)
+        let hp_key = Some(self.keys.hp_raw.as_slice());
+        Ok(Self {
+            algor: self.algor,
+            keys: ProtectionKeys::new_with_secret(self.algor, &next_secret, hp_key, self.version)?,
+            secret: next_secret,
+            version: self.version,
+        })
     }
 
     /// Encrypt the plaintext and authenticate it in place.
@@ -168,46 +332,18 @@ impl Seal {
         extra_in: Option<&[u8]>,
     ) -> Result<usize> {
         let tag_len = self.algor().tag_len();
-        let mut out_tag_len = tag_len;
-        let (extra_in_ptr, extra_in_len) = match extra_in {
-            Some(v) => (v.as_ptr(), v.len()),
-            None => (std::ptr::null(), 0),
-        };
+        let extra_in_len = extra_in.map_or(0, |v| v.len());
         if in_len + tag_len + extra_in_len > buf.len() {
             return Err(Error::CryptoFail);
         }
 
-        let nonce = build_nonce(&self.pkt_key.nonce, cid_seq, counter);
-        let rc = unsafe {
-            EVP_AEAD_CTX_seal_scatter(
-                &self.pkt_key.ctx,
-                buf.as_mut_ptr(),
-                buf[in_len..].as_mut_ptr(),
-                &mut out_tag_len,
-                tag_len + extra_in_len,
-                nonce.as_ptr(),
-                nonce.len(),
-                buf.as_ptr(),
-                in_len,
-                extra_in_ptr,
-                extra_in_len,
-                ad.as_ptr(),
-                ad.len(),
-            )
-        };
-        if rc != 1 {
-            return Err(Error::CryptoFail);
-        }
-
-        Ok(in_len + out_tag_len)
+        let nonce = build_nonce(&self.keys.nonce, cid_seq, counter);
+        self.keys.pkt_key.seal(&nonce, ad, buf, in_len, extra_in)
     }
 
     /// Generate header protection mask.
     pub fn new_mask(&self, sample: &[u8]) -> Result<[u8; 5]> {
-        self.hdr_key
-            .key
-            .new_mask(sample)
-            .map_err(|_| Error::CryptoFail)
+        self.keys.hdr_key.new_mask(sample)
     }
 
     pub fn algor(&self) -> Algorithm {
@@ -219,8 +355,8 @@ impl Seal {
 pub struct Open {
     algor: Algorithm,
     secret: Vec<u8>,
-    hdr_key: HeaderKey,
-    pkt_key: PacketKey,
+    keys: ProtectionKeys,
+    version: u32,
 }
 
 impl Open {
@@ -231,37 +367,44 @@ impl Open {
         hp_key: Vec<u8>,
         key: Vec<u8>,
         iv: Vec<u8>,
+        version: u32,
     ) -> Result<Self> {
         Ok(Self {
             algor,
             secret,
-            hdr_key: HeaderKey::new(algor, hp_key)?,
-            pkt_key: PacketKey::new(algor, key, iv)?,
+            keys: ProtectionKeys::new(algor, hp_key, key, iv)?,
+            version,
         })
     }
 
     /// Create a new Open with secret.
-    pub fn new_with_secret(algor: Algorithm, secret: Vec<u8>) -> Result<Self> {
-        let mut key = vec![0; algor.key_len()];
-        let mut iv = vec![0; algor.nonce_len()];
-        let mut hp_key = vec![0; algor.key_len()];
-        key::derive_pkt_key(algor.hkdf_algor(), &secret, &mut key)?;
-        key::derive_pkt_iv(algor.hkdf_algor(), &secret, &mut iv)?;
-        key::derive_hdr_key(algor.hkdf_algor(), &secret, &mut hp_key)?;
-
-        Self::new(algor, secret, hp_key, key, iv)
+    pub fn new_with_secret(algor: Algorithm, secret: Vec<u8>, version: u32) -> Result<Self> {
+        Ok(Self {
+            algor,
+            keys: ProtectionKeys::new_with_secret(algor, &secret, None, version)?,
+            secret,
+            version,
+        })
     }
 
     /// Derive next packet key.
     pub fn derive_next_packet_key(&self) -> Result<Self> {
         let mut next_secret = vec![0; self.secret.len()];
-        key::derive_next_packet_key(self.algor.hkdf_algor(), &self.secret, &mut next_secret)?;
-        let mut next_key = Self::new_with_secret(self.algor, next_secret)?;
+        key::derive_next_packet_key(
+            self.algor.hkdf_algor(),
+            &self.secret,
+            self.version,
+            &mut next_secret,
+        )?;
 
         // The header protection key is not updated.
-        next_key.hdr_key = HeaderKey::new(self.algor, self.hdr_key.raw.clone())?;
-
-        Ok(next_key)
+        let hp_key = Some(self.keys.hp_raw.as_slice());
+        Ok(Self {
+            algor: self.algor,
+            keys: ProtectionKeys::new_with_secret(self.algor, &next_secret, hp_key, self.version)?,
+            secret: next_secret,
+            version: self.version,
+        })
     }
 
     /// Decrypt the ciphertext into plaintext.
@@ -273,44 +416,26 @@ impl Open {
         ciphertext: &[u8],
         plaintext: &mut [u8],
     ) -> Result<usize> {
-        let tag_len = self.algor().tag_len();
-        let mut out_len = match ciphertext.len().checked_sub(tag_len) {
-            Some(n) => n,
-            None => return Err(Error::CryptoFail),
-        };
-        if plaintext.len() < out_len {
-            return Err(Error::CryptoFail);
-        }
-
-        let max_out_len = out_len;
-        let nonce = build_nonce(&self.pkt_key.nonce, cid_seq, counter);
-        let rc = unsafe {
-            EVP_AEAD_CTX_open(
-                &self.pkt_key.ctx,
-                plaintext.as_mut_ptr(),
-                &mut out_len,
-                max_out_len,
-                nonce.as_ptr(),
-                nonce.len(),
-                ciphertext.as_ptr(),
-                ciphertext.len(),
-                ad.as_ptr(),
-                ad.len(),
-            )
-        };
-        if rc != 1 {
-            return Err(Error::CryptoFail);
-        }
+        let nonce = build_nonce(&self.keys.nonce, cid_seq, counter);
+        self.keys.pkt_key.open(&nonce, ad, ciphertext, plaintext)
+    }
 
-        Ok(out_len)
+    /// Decrypt the ciphertext in place, and the plaintext is written to the
+    /// beginning of `buf`.
+    pub fn open_in_place(
+        &self,
+        cid_seq: Option<u32>,
+        counter: u64,
+        ad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        let nonce = build_nonce(&self.keys.nonce, cid_seq, counter);
+        self.keys.pkt_key.open_in_place(&nonce, ad, buf)
     }
 
     /// Generate header protection mask.
     pub fn new_mask(&self, sample: &[u8]) -> Result<[u8; 5]> {
-        self.hdr_key
-            .key
-            .new_mask(sample)
-            .map_err(|_| Error::CryptoFail)
+        self.keys.hdr_key.new_mask(sample)
     }
 
     /// Return the AEAD algorithm.
@@ -332,29 +457,57 @@ pub fn derive_initial_secrets(cid: &[u8], version: u32, is_server: bool) -> Resu
     let mut client_iv = vec![0; nonce_len];
     let mut client_hp_key = vec![0; key_len];
     key::derive_client_initial_secret(&initial_secret, &mut secret)?;
-    key::derive_pkt_key(aead.hkdf_algor(), &secret, &mut client_key)?;
-    key::derive_pkt_iv(aead.hkdf_algor(), &secret, &mut client_iv)?;
-    key::derive_hdr_key(aead.hkdf_algor(), &secret, &mut client_hp_key)?;
+    key::derive_pkt_key(aead.hkdf_algor(), &secret, version, &mut client_key)?;
+    key::derive_pkt_iv(aead.hkdf_algor(), &secret, version, &mut client_iv)?;
+    key::derive_hdr_key(aead.hkdf_algor(), &secret, version, &mut client_hp_key)?;
 
     // Derive server keys.
     let mut server_key = vec![0; key_len];
     let mut server_iv = vec![0; nonce_len];
     let mut server_hp_key = vec![0; key_len];
     key::derive_server_initial_secret(&initial_secret, &mut secret)?;
-    key::derive_pkt_key(aead.hkdf_algor(), &secret, &mut server_key)?;
-    key::derive_pkt_iv(aead.hkdf_algor(), &secret, &mut server_iv)?;
-    key::derive_hdr_key(aead.hkdf_algor(), &secret, &mut server_hp_key)?;
+    key::derive_pkt_key(aead.hkdf_algor(), &secret, version, &mut server_key)?;
+    key::derive_pkt_iv(aead.hkdf_algor(), &secret, version, &mut server_iv)?;
+    key::derive_hdr_key(aead.hkdf_algor(), &secret, version, &mut server_hp_key)?;
 
     if is_server {
         return Ok((
-            Open::new(aead, secret.to_vec(), client_hp_key, client_key, client_iv)?,
-            Seal::new(aead, secret.to_vec(), server_hp_key, server_key, server_iv)?,
+            Open::new(
+                aead,
+                secret.to_vec(),
+                client_hp_key,
+                client_key,
+                client_iv,
+                version,
+            )?,
+            Seal::new(
+                aead,
+                secret.to_vec(),
+                server_hp_key,
+                server_key,
+                server_iv,
+                version,
+            )?,
         ));
     }
 
     Ok((
-        Open::new(aead, secret.to_vec(), server_hp_key, server_key, server_iv)?,
-        Seal::new(aead, secret.to_vec(), client_hp_key, client_key, client_iv)?,
+        Open::new(
+            aead,
+            secret.to_vec(),
+            server_hp_key,
+            server_key,
+            server_iv,
+            version,
+        )?,
+        Seal::new(
+            aead,
+            secret.to_vec(),
+            client_hp_key,
+            client_key,
+            client_iv,
+            version,
+        )?,
     ))
 }
 
@@ -960,7 +960,8 @@ extern "C" fn set_read_secret(
     if level != tls::Level::ZeroRTT || session_data.is_server {
         let secret = unsafe { slice::from_raw_parts(secret, secret_len) };
 
-        let open = match crypto::Open::new_with_secret(aead, secret.to_vec()) {
+        let open = match crypto::Open::new_with_secret(aead, secret.to_vec(), session_data.version)
+        {
             Ok(v) => v,
             Err(_) => return 0,
         };
@@ -1001,7 +1002,8 @@ extern "C" fn set_write_secret(
     if level != tls::Level::ZeroRTT || !session_data.is_server {
         let secret = unsafe { slice::from_raw_parts(secret, secret_len) };
 
-        let seal = match crypto::Seal::new_with_secret(aead, secret.to_vec()) {
+        let seal = match crypto::Seal::new_with_secret(aead, secret.to_vec(), session_data.version)
+        {
             Ok(v) => v,
             Err(_) => return 0,
         };
@@ -1222,6 +1224,7 @@ extern "C" fn new_session(ssl: *mut Ssl, ssl_session: *mut SslSession) -> c_int
 
     let session = Session::new(ssl);
     let peer_params = session.quic_transport_params();
+    let alpn = session.alpn_protocol();
 
     // Get SSL session.
     let session_bytes = unsafe {
@@ -1236,7 +1239,32 @@ extern "C" fn new_session(ssl: *mut Ssl, ssl_session: *mut SslSession) -> c_int
         session_bytes
     };
 
-    let mut buffer = Vec::with_capacity(8 + peer_params.len() + 8 + session_bytes.len());
+    let mut buffer = Vec::with_capacity(
+        1 + 4 + 8 + alpn.len() + 8 + session_bytes.len() + 8 + peer_params.len(),
+    );
+
+    // Encode the format version of the session data.
+    if buffer.write(&[tls::SESSION_FORMAT_VERSION]).is_err() {
+        std::mem::forget(session);
+        return 0;
+    }
+
+    // Encode the negotiated QUIC version.
+    if buffer.write(&session_data.version.to_be_bytes()).is_err() {
+        std::mem::forget(session);
+        return 0;
+    }
+
+    // Encode the negotiated application protocol.
+    let alpn_len = alpn.len() as u64;
+    if buffer.write(&alpn_len.to_be_bytes()).is_err() {
+        std::mem::forget(session);
+        return 0;
+    }
+    if buffer.write(alpn).is_err() {
+        std::mem::forget(session);
+        return 0;
+    }
 
     // Encode SSL session data.
     let session_bytes_len = session_bytes.len() as u64;
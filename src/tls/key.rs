@@ -38,12 +38,45 @@ fn hkdf_expand_label(prk: &hkdf::Prk, label: &[u8], out: &mut [u8]) -> Result<()
         .map_err(|_| Error::CryptoFail)
 }
 
-pub fn derive_initial_secret(secret: &[u8], _version: u32) -> hkdf::Prk {
-    const INITIAL_SALT: [u8; 20] = [
-        0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c,
-        0xad, 0xcc, 0xbb, 0x7f, 0x0a,
-    ];
-    hkdf::Salt::new(hkdf::HKDF_SHA256, &INITIAL_SALT).extract(secret)
+/// The salt for deriving Initial secrets of QUIC version 1.
+/// See RFC 9001 Section 5.2
+const INITIAL_SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+
+/// The salt for deriving Initial secrets of QUIC version 2.
+/// See RFC 9369 Section 3.3.1
+const INITIAL_SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+
+lazy_static::lazy_static! {
+    /// The HKDF salt is fixed, so the HMAC key it holds is computed only once
+    /// instead of for each connection.
+    static ref INITIAL_HKDF_SALT_V1: hkdf::Salt =
+        hkdf::Salt::new(hkdf::HKDF_SHA256, &INITIAL_SALT_V1);
+
+    static ref INITIAL_HKDF_SALT_V2: hkdf::Salt =
+        hkdf::Salt::new(hkdf::HKDF_SHA256, &INITIAL_SALT_V2);
+}
+
+/// Return the HKDF label for the given QUIC version. The labels of QUIC
+/// version 2 use the prefix "quicv2" instead of "quic".
+/// See RFC 9369 Section 3.3.2
+fn version_label(version: u32, label_v1: &'static [u8], label_v2: &'static [u8]) -> &'static [u8] {
+    match version {
+        crate::QUIC_VERSION_V2 => label_v2,
+        _ => label_v1,
+    }
+}
+
+pub fn derive_initial_secret(secret: &[u8], version: u32) -> hkdf::Prk {
+    match version {
+        crate::QUIC_VERSION_V2 => INITIAL_HKDF_SALT_V2.extract(secret),
+        _ => INITIAL_HKDF_SALT_V1.extract(secret),
+    }
 }
 
 pub fn derive_client_initial_secret(prk: &hkdf::Prk, out: &mut [u8]) -> Result<()> {
@@ -54,24 +87,48 @@ pub fn derive_server_initial_secret(prk: &hkdf::Prk, out: &mut [u8]) -> Result<(
     hkdf_expand_label(prk, b"server in", out)
 }
 
-pub fn derive_pkt_key(algor: hkdf::Algorithm, secret: &[u8], out: &mut [u8]) -> Result<()> {
+pub fn derive_pkt_key(
+    algor: hkdf::Algorithm,
+    secret: &[u8],
+    version: u32,
+    out: &mut [u8],
+) -> Result<()> {
     let prk = hkdf::Prk::new_less_safe(algor, secret);
-    hkdf_expand_label(&prk, b"quic key", out)
+    hkdf_expand_label(
+        &prk,
+        version_label(version, b"quic key", b"quicv2 key"),
+        out,
+    )
 }
 
-pub fn derive_pkt_iv(algor: hkdf::Algorithm, secret: &[u8], out: &mut [u8]) -> Result<()> {
+pub fn derive_pkt_iv(
+    algor: hkdf::Algorithm,
+    secret: &[u8],
+    version: u32,
+    out: &mut [u8],
+) -> Result<()> {
     let prk = hkdf::Prk::new_less_safe(algor, secret);
-    hkdf_expand_label(&prk, b"quic iv", out)
+    hkdf_expand_label(&prk, version_label(version, b"quic iv", b"quicv2 iv"), out)
 }
 
-pub fn derive_hdr_key(algor: hkdf::Algorithm, secret: &[u8], out: &mut [u8]) -> Result<()> {
+pub fn derive_hdr_key(
+    algor: hkdf::Algorithm,
+    secret: &[u8],
+    version: u32,
+    out: &mut [u8],
+) -> Result<()> {
     let prk = hkdf::Prk::new_less_safe(algor, secret);
-    hkdf_expand_label(&prk, b"quic hp", out)
+    hkdf_expand_label(&prk, version_label(version, b"quic hp", b"quicv2 hp"), out)
 }
 
-pub fn derive_next_packet_key(algor: hkdf::Algorithm, secret: &[u8], out: &mut [u8]) -> Result<()> {
+pub fn derive_next_packet_key(
+    algor: hkdf::Algorithm,
+    secret: &[u8],
+    version: u32,
+    out: &mut [u8],
+) -> Result<()> {
     let prk = hkdf::Prk::new_less_safe(algor, secret);
-    hkdf_expand_label(&prk, b"quic ku", out)
+    hkdf_expand_label(&prk, version_label(version, b"quic ku", b"quicv2 ku"), out)
 }
 
 #[cfg(test)]
@@ -99,7 +156,7 @@ mod tests {
         assert_eq!(&secret, &expected_client_initial_secret);
 
         // Derive client packet key.
-        assert!(derive_pkt_key(algor, &secret, &mut pkt_key).is_ok());
+        assert!(derive_pkt_key(algor, &secret, crate::QUIC_VERSION_V1, &mut pkt_key).is_ok());
         let expected_client_pkt_key = [
             0x1f, 0x36, 0x96, 0x13, 0xdd, 0x76, 0xd5, 0x46, 0x77, 0x30, 0xef, 0xcb, 0xe3, 0xb1,
             0xa2, 0x2d,
@@ -107,14 +164,14 @@ mod tests {
         assert_eq!(&pkt_key, &expected_client_pkt_key);
 
         // Derive client packet iv.
-        assert!(derive_pkt_iv(algor, &secret, &mut pkt_iv).is_ok());
+        assert!(derive_pkt_iv(algor, &secret, crate::QUIC_VERSION_V1, &mut pkt_iv).is_ok());
         let expected_client_pkt_iv = [
             0xfa, 0x04, 0x4b, 0x2f, 0x42, 0xa3, 0xfd, 0x3b, 0x46, 0xfb, 0x25, 0x5c,
         ];
         assert_eq!(&pkt_iv, &expected_client_pkt_iv);
 
         // Derive client header protection key.
-        assert!(derive_hdr_key(algor, &secret, &mut hdr_key).is_ok());
+        assert!(derive_hdr_key(algor, &secret, crate::QUIC_VERSION_V1, &mut hdr_key).is_ok());
         let expected_client_hdr_key = [
             0x9f, 0x50, 0x44, 0x9e, 0x04, 0xa0, 0xe8, 0x10, 0x28, 0x3a, 0x1e, 0x99, 0x33, 0xad,
             0xed, 0xd2,
@@ -131,7 +188,7 @@ mod tests {
         assert_eq!(&secret, &expected_server_initial_secret);
 
         // Derive server packet key.
-        assert!(derive_pkt_key(algor, &secret, &mut pkt_key).is_ok());
+        assert!(derive_pkt_key(algor, &secret, crate::QUIC_VERSION_V1, &mut pkt_key).is_ok());
         let expected_server_pkt_key = [
             0xcf, 0x3a, 0x53, 0x31, 0x65, 0x3c, 0x36, 0x4c, 0x88, 0xf0, 0xf3, 0x79, 0xb6, 0x06,
             0x7e, 0x37,
@@ -139,14 +196,14 @@ mod tests {
         assert_eq!(&pkt_key, &expected_server_pkt_key);
 
         // Derive server packet iv.
-        assert!(derive_pkt_iv(algor, &secret, &mut pkt_iv).is_ok());
+        assert!(derive_pkt_iv(algor, &secret, crate::QUIC_VERSION_V1, &mut pkt_iv).is_ok());
         let expected_server_pkt_iv = [
             0x0a, 0xc1, 0x49, 0x3c, 0xa1, 0x90, 0x58, 0x53, 0xb0, 0xbb, 0xa0, 0x3e,
         ];
         assert_eq!(&pkt_iv, &expected_server_pkt_iv);
 
         // Derive server header protection key.
-        assert!(derive_hdr_key(algor, &secret, &mut hdr_key).is_ok());
+        assert!(derive_hdr_key(algor, &secret, crate::QUIC_VERSION_V1, &mut hdr_key).is_ok());
         let expected_server_hdr_key = [
             0xc2, 0x06, 0xb8, 0xd9, 0xb9, 0xf0, 0xf3, 0x76, 0x44, 0x43, 0x0b, 0x49, 0x0e, 0xea,
             0xa3, 0x14,
@@ -154,6 +211,77 @@ mod tests {
         assert_eq!(&hdr_key, &expected_server_hdr_key);
     }
 
+    /// Unit test for RFC 9369 Appendix A.1 Keys.
+    #[test]
+    fn derive_initial_secrets_v2() {
+        let dcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+        let version = crate::QUIC_VERSION_V2;
+        let mut secret = [0; 32];
+        let mut pkt_key = [0; 16];
+        let mut pkt_iv = [0; 12];
+        let mut hdr_key = [0; 16];
+        let algor = hkdf::HKDF_SHA256;
+        let initial_secret = derive_initial_secret(&dcid, version);
+
+        // Derive client keys.
+        assert!(derive_client_initial_secret(&initial_secret, &mut secret).is_ok());
+        let expected_client_initial_secret = [
+            0x14, 0xec, 0x9d, 0x6e, 0xb9, 0xfd, 0x7a, 0xf8, 0x3b, 0xf5, 0xa6, 0x68, 0xbc, 0x17,
+            0xa7, 0xe2, 0x83, 0x76, 0x6a, 0xad, 0xe7, 0xec, 0xd0, 0x89, 0x1f, 0x70, 0xf9, 0xff,
+            0x7f, 0x4b, 0xf4, 0x7b,
+        ];
+        assert_eq!(&secret, &expected_client_initial_secret);
+
+        assert!(derive_pkt_key(algor, &secret, version, &mut pkt_key).is_ok());
+        let expected_client_pkt_key = [
+            0x8b, 0x1a, 0x0b, 0xc1, 0x21, 0x28, 0x42, 0x90, 0xa2, 0x9e, 0x09, 0x71, 0xb5, 0xcd,
+            0x04, 0x5d,
+        ];
+        assert_eq!(&pkt_key, &expected_client_pkt_key);
+
+        assert!(derive_pkt_iv(algor, &secret, version, &mut pkt_iv).is_ok());
+        let expected_client_pkt_iv = [
+            0x91, 0xf7, 0x3e, 0x23, 0x51, 0xd8, 0xfa, 0x91, 0x66, 0x0e, 0x90, 0x9f,
+        ];
+        assert_eq!(&pkt_iv, &expected_client_pkt_iv);
+
+        assert!(derive_hdr_key(algor, &secret, version, &mut hdr_key).is_ok());
+        let expected_client_hdr_key = [
+            0x45, 0xb9, 0x5e, 0x15, 0x23, 0x5d, 0x6f, 0x45, 0xa6, 0xb1, 0x9c, 0xbc, 0xb0, 0x29,
+            0x4b, 0xa9,
+        ];
+        assert_eq!(&hdr_key, &expected_client_hdr_key);
+
+        // Derive server keys.
+        assert!(derive_server_initial_secret(&initial_secret, &mut secret).is_ok());
+        let expected_server_initial_secret = [
+            0x02, 0x63, 0xdb, 0x17, 0x82, 0x73, 0x1b, 0xf4, 0x58, 0x8e, 0x7e, 0x4d, 0x93, 0xb7,
+            0x46, 0x39, 0x07, 0xcb, 0x8c, 0xd8, 0x20, 0x0b, 0x5d, 0xa5, 0x5a, 0x8b, 0xd4, 0x88,
+            0xea, 0xfc, 0x37, 0xc1,
+        ];
+        assert_eq!(&secret, &expected_server_initial_secret);
+
+        assert!(derive_pkt_key(algor, &secret, version, &mut pkt_key).is_ok());
+        let expected_server_pkt_key = [
+            0x82, 0xdb, 0x63, 0x78, 0x61, 0xd5, 0x5e, 0x1d, 0x01, 0x1f, 0x19, 0xea, 0x71, 0xd5,
+            0xd2, 0xa7,
+        ];
+        assert_eq!(&pkt_key, &expected_server_pkt_key);
+
+        assert!(derive_pkt_iv(algor, &secret, version, &mut pkt_iv).is_ok());
+        let expected_server_pkt_iv = [
+            0xdd, 0x13, 0xc2, 0x76, 0x49, 0x9c, 0x02, 0x49, 0xd3, 0x31, 0x06, 0x52,
+        ];
+        assert_eq!(&pkt_iv, &expected_server_pkt_iv);
+
+        assert!(derive_hdr_key(algor, &secret, version, &mut hdr_key).is_ok());
+        let expected_server_hdr_key = [
+            0xed, 0xf6, 0xd0, 0x5c, 0x83, 0x12, 0x12, 0x01, 0xb4, 0x36, 0xe1, 0x68, 0x77, 0x59,
+            0x3c, 0x3a,
+        ];
+        assert_eq!(&hdr_key, &expected_server_hdr_key);
+    }
+
     /// Unit test for RFC 9001 Section A.5 ChaCha20-Poly1305 Short Header Packet.
     #[test]
     fn derive_chacha20_secrets() {
@@ -166,7 +294,7 @@ mod tests {
 
         // Derive packet key.
         let mut pkt_key = [0; 32];
-        assert!(derive_pkt_key(algor, &secret, &mut pkt_key).is_ok());
+        assert!(derive_pkt_key(algor, &secret, crate::QUIC_VERSION_V1, &mut pkt_key).is_ok());
         let expected_pkt_key = [
             0xc6, 0xd9, 0x8f, 0xf3, 0x44, 0x1c, 0x3f, 0xe1, 0xb2, 0x18, 0x20, 0x94, 0xf6, 0x9c,
             0xaa, 0x2e, 0xd4, 0xb7, 0x16, 0xb6, 0x54, 0x88, 0x96, 0x0a, 0x7a, 0x98, 0x49, 0x79,
@@ -176,7 +304,7 @@ mod tests {
 
         // Derive packet iv.
         let mut pkt_iv = [0; 12];
-        assert!(derive_pkt_iv(algor, &secret, &mut pkt_iv).is_ok());
+        assert!(derive_pkt_iv(algor, &secret, crate::QUIC_VERSION_V1, &mut pkt_iv).is_ok());
         let expected_pkt_iv = [
             0xe0, 0x45, 0x9b, 0x34, 0x74, 0xbd, 0xd0, 0xe4, 0x4a, 0x41, 0xc1, 0x44,
         ];
@@ -184,7 +312,7 @@ mod tests {
 
         // Derive header protection key.
         let mut hdr_key = [0; 32];
-        assert!(derive_hdr_key(algor, &secret, &mut hdr_key).is_ok());
+        assert!(derive_hdr_key(algor, &secret, crate::QUIC_VERSION_V1, &mut hdr_key).is_ok());
         let expected_hdr_key = [
             0x25, 0xa2, 0x82, 0xb9, 0xe8, 0x2f, 0x06, 0xf2, 0x1f, 0x48, 0x89, 0x17, 0xa4, 0xfc,
             0x8f, 0x1b, 0x73, 0x57, 0x36, 0x85, 0x60, 0x85, 0x97, 0xd0, 0xef, 0xcb, 0x07, 0x6b,
@@ -194,7 +322,13 @@ mod tests {
 
         // Update packet key.
         let mut updated_pkt_key = [0; 32];
-        assert!(derive_next_packet_key(algor, &secret, &mut updated_pkt_key).is_ok());
+        assert!(derive_next_packet_key(
+            algor,
+            &secret,
+            crate::QUIC_VERSION_V1,
+            &mut updated_pkt_key
+        )
+        .is_ok());
         let expected_updated_pkt_key = [
             0x12, 0x23, 0x50, 0x47, 0x55, 0x03, 0x6d, 0x55, 0x63, 0x42, 0xee, 0x93, 0x61, 0xd2,
             0x53, 0x42, 0x1a, 0x82, 0x6c, 0x9e, 0xcd, 0xf3, 0xc7, 0x14, 0x86, 0x84, 0xb3, 0x6b,
@@ -0,0 +1,146 @@
+// Copyright (c) 2024 The TQUIC Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pluggable crypto provider for QUIC packet protection.
+//!
+//! The packet protection keys are created by a process-wide crypto provider,
+//! which could be replaced with alternative implementations, such as hardware
+//! offload engines or FIPS modules. The BoringSSL-backed provider is used by
+//! default.
+
+use std::sync::OnceLock;
+
+use crate::tls::Algorithm;
+use crate::Error;
+use crate::Result;
+
+/// The crypto provider in use.
+static CRYPTO_PROVIDER: OnceLock<Box<dyn CryptoProvider>> = OnceLock::new();
+
+/// A crypto provider creates the keys for QUIC packet protection.
+/// See RFC 9001 Section 5
+pub trait CryptoProvider: Send + Sync {
+    /// Create a packet protection key for the AEAD algorithm.
+    fn new_packet_key(&self, algor: Algorithm, key: &[u8]) -> Result<Box<dyn PacketKey>>;
+
+    /// Create a header protection key for the AEAD algorithm.
+    fn new_header_key(&self, algor: Algorithm, key: &[u8]) -> Result<Box<dyn HeaderKey>>;
+}
+
+/// A packet protection key.
+/// See RFC 9001 Section 5.3
+pub trait PacketKey: Send + Sync {
+    /// Encrypt the first `in_len` bytes of `buf` in place, and write the
+    /// ciphertext of `extra_in` and the authentication tag after it.
+    ///
+    /// The caller guarantees that `buf` is large enough. Return the total
+    /// length of the output.
+    fn seal(
+        &self,
+        nonce: &[u8],
+        ad: &[u8],
+        buf: &mut [u8],
+        in_len: usize,
+        extra_in: Option<&[u8]>,
+    ) -> Result<usize>;
+
+    /// Decrypt the `ciphertext` into `plaintext`, and return the length of the
+    /// plaintext.
+    fn open(
+        &self,
+        nonce: &[u8],
+        ad: &[u8],
+        ciphertext: &[u8],
+        plaintext: &mut [u8],
+    ) -> Result<usize>;
+
+    /// Decrypt the ciphertext in place, and return the length of the plaintext
+    /// written to the beginning of `buf`.
+    fn open_in_place(&self, nonce: &[u8], ad: &[u8], buf: &mut [u8]) -> Result<usize>;
+}
+
+/// A header protection key.
+/// See RFC 9001 Section 5.4
+pub trait HeaderKey: Send + Sync {
+    /// Generate header protection mask for the sample.
+    fn new_mask(&self, sample: &[u8]) -> Result<[u8; 5]>;
+}
+
+/// Set the crypto provider for packet protection.
+///
+/// It should be called before any endpoint is created, and it fails if a
+/// crypto provider has already been set or used.
+pub fn set_crypto_provider(provider: Box<dyn CryptoProvider>) -> Result<()> {
+    CRYPTO_PROVIDER
+        .set(provider)
+        .map_err(|_| Error::InvalidOperation("crypto provider already in use".into()))
+}
+
+/// Return the crypto provider in use.
+pub(crate) fn crypto_provider() -> &'static dyn CryptoProvider {
+    CRYPTO_PROVIDER
+        .get_or_init(|| Box::new(super::boringssl::crypto::BoringSslProvider))
+        .as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_provider() -> Result<()> {
+        let provider = crypto_provider();
+        let algor = Algorithm::Aes128Gcm;
+        let key = provider.new_packet_key(algor, &[0x01; 16])?;
+        let nonce = [0x02; 12];
+        let ad = b"header";
+
+        let plaintext = b"tquic crypto provider";
+        let mut buf = [0; 64];
+        buf[..plaintext.len()].copy_from_slice(plaintext);
+        let len = key.seal(&nonce, ad, &mut buf, plaintext.len(), None)?;
+        assert_eq!(len, plaintext.len() + algor.tag_len());
+
+        let mut out = [0; 64];
+        assert_eq!(
+            key.open(&nonce, ad, &buf[..len], &mut out)?,
+            plaintext.len()
+        );
+        assert_eq!(&out[..plaintext.len()], plaintext);
+
+        // Tampered associated data
+        assert_eq!(
+            key.open(&nonce, b"tampered", &buf[..len], &mut out),
+            Err(Error::CryptoFail)
+        );
+        assert_eq!(
+            key.open_in_place(&nonce, ad, &mut buf[..len])?,
+            plaintext.len()
+        );
+        assert_eq!(&buf[..plaintext.len()], plaintext);
+
+        let hdr_key = provider.new_header_key(algor, &[0x03; 16])?;
+        assert!(hdr_key.new_mask(&[0x04; 16]).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn set_provider_after_use() {
+        let _ = crypto_provider();
+        assert!(
+            set_crypto_provider(Box::new(crate::tls::boringssl::crypto::BoringSslProvider))
+                .is_err()
+        );
+    }
+}
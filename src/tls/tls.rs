@@ -41,6 +41,10 @@ pub use boringssl::crypto::Algorithm;
 pub use boringssl::crypto::Open;
 pub use boringssl::crypto::Seal;
 pub use boringssl::tls::SslCtx;
+pub use provider::set_crypto_provider;
+pub use provider::CryptoProvider;
+pub use provider::HeaderKey;
+pub use provider::PacketKey;
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, EnumCount)]
@@ -216,6 +220,7 @@ impl TlsConfig {
                 write_method: None,
                 conf_selector: None,
                 early_data_rejected: false,
+                version: crate::QUIC_VERSION_V1,
             },
             current_key_phase: false,
             prev_key: None,
@@ -258,6 +263,11 @@ pub struct Keys {
 pub type WriteMethod = Box<dyn FnMut(Level, &[u8]) -> Result<()>>;
 type KeyLog = Box<dyn std::io::Write + Send + Sync>;
 
+/// The format version of the session data. It should be bumped whenever the
+/// encoding of the session data is changed, so that the session data saved by
+/// an older release is ignored instead of being misinterpreted.
+pub(crate) const SESSION_FORMAT_VERSION: u8 = 1;
+
 pub struct TlsSessionData {
     key_collection: [Keys; Level::COUNT],
     session: Option<Vec<u8>>,
@@ -268,6 +278,7 @@ pub struct TlsSessionData {
     write_method: Option<WriteMethod>,
     conf_selector: Option<Arc<dyn TlsConfigSelector>>,
     early_data_rejected: bool,
+    version: u32,
 }
 
 pub(crate) struct TlsSession {
@@ -323,6 +334,9 @@ impl TlsSession {
     pub fn derive_initial_secrets(&mut self, cid: &ConnectionId, version: u32) -> Result<()> {
         let (open, seal) =
             boringssl::crypto::derive_initial_secrets(cid, version, self.data.is_server)?;
+        // The keys of the other encryption levels are derived using the same
+        // QUIC version.
+        self.data.version = version;
         self.data.key_collection[Level::Initial] = Keys {
             open: Some(open),
             seal: Some(seal),
@@ -835,6 +849,11 @@ pub(crate) mod tests {
             if out[..out_len] != plaintext[..data_len] {
                 return Err(Error::CryptoFail);
             }
+
+            let out_len = open.open_in_place(None, counter, b"ad", &mut ciphertext[..rsize])?;
+            if ciphertext[..out_len] != plaintext[..data_len] {
+                return Err(Error::CryptoFail);
+            }
             Ok(())
         }
 
@@ -1274,3 +1293,4 @@ pub(crate) mod tests {
 mod boringssl;
 
 mod key;
+mod provider;
@@ -18,7 +18,6 @@ use std::net::SocketAddr;
 use std::time;
 use std::time::Duration;
 
-use ring::aead;
 use ring::hmac;
 
 use self::AddressTokenType::*;
@@ -41,8 +40,68 @@ pub enum AddressTokenType {
     ResumeToken = 1,
 }
 
+/// The length of the authentication tag of the address token, which is the
+/// truncated output of HMAC-SHA256.
+const ADDRESS_TOKEN_TAG_LEN: usize = 16;
+
+/// The key for generating and validating address tokens.
+///
+/// The key is identified by a Key ID derived from the key material. The
+/// servers of a fleet behind a load balancer could share the same keys, so
+/// that a token issued by one server validates on the others. A new key may
+/// be added in front of the old ones for rotation, and the key of a received
+/// token is selected by the Key ID instead of trying all of the keys.
+#[derive(Clone)]
+pub struct AddressTokenKey {
+    /// Key ID carried in the tokens.
+    id: u8,
+
+    /// Key for computing the authentication tag.
+    key: hmac::Key,
+}
+
+impl AddressTokenKey {
+    /// Create a key with the given key material.
+    pub fn new(secret: &[u8]) -> Self {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+        let id = hmac::sign(&key, b"tquic address token key id").as_ref()[0];
+        Self { id, key }
+    }
+
+    /// Return the Key ID.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+}
+
 /// QUIC uses an address token in the Initial packet to provide address validation
 /// prior to completing the handshake.
+///
+/// The address token is encoded as follows:
+///
+/// ```text
+/// Address Token {
+///   Label (32) = "quic",
+///   Token Type (8),
+///   Key ID (8),
+///   Issued Time (64),
+///   [Original Destination Connection ID Length (8)],
+///   [Original Destination Connection ID (0..160)],
+///   [Saved Capacity (64)],
+///   [Saved RTT (64)],
+///   Tag (128),
+/// }
+/// ```
+///
+/// The Issued Time is the number of seconds since the UNIX epoch. The original
+/// destination connection ID is only present in a Retry token. The saved
+/// capacity in bytes and the saved RTT in microseconds of the path are
+/// optional in a Resume token, which are used for Careful Resume.
+///
+/// The Tag is HMAC-SHA256 truncated to 128 bits, using the key identified by
+/// the Key ID, over the fields preceding the Tag followed by the client IP
+/// address (4 or 16 bytes). For a Retry token, the client port (16) and the
+/// Retry Source Connection ID are also appended.
 #[derive(Debug)]
 pub struct AddressToken {
     /// Type of the address token.
@@ -59,6 +118,10 @@ pub struct AddressToken {
 
     /// Retry source cid
     pub rscid: Option<ConnectionId>,
+
+    /// The saved capacity in bytes and RTT of the path for Careful Resume.
+    /// It is only applicable to a Resume token.
+    pub capacity: Option<(u64, Duration)>,
 }
 
 impl AddressToken {
@@ -74,6 +137,7 @@ impl AddressToken {
             address,
             odcid: Some(odcid),
             rscid: Some(rscid),
+            capacity: None,
         }
     }
 
@@ -85,21 +149,28 @@ impl AddressToken {
             address,
             odcid: None,
             rscid: None,
+            capacity: None,
+        }
+    }
+
+    /// Generate a Resume token carrying the saved capacity and RTT of the path
+    pub fn new_capacity_token(address: SocketAddr, capacity: u64, rtt: Duration) -> AddressToken {
+        AddressToken {
+            capacity: Some((capacity, rtt)),
+            ..AddressToken::new_resume_token(address)
         }
     }
 
     /// Encode the address token.
-    pub fn encode(&self, key: &aead::LessSafeKey) -> Result<Vec<u8>> {
-        let max_len = AddressToken::max_token_len(key);
+    pub fn encode(&self, key: &AddressTokenKey) -> Result<Vec<u8>> {
+        let max_len = AddressToken::max_token_len();
         let mut token = vec![0u8; max_len];
-        let nonce = rand::random::<[u8; aead::NONCE_LEN]>();
 
-        // Write token header: label/token type/nonce
+        // Write token header: label/token type/key id
         let mut buf = token.as_mut_slice();
         buf.write(b"quic")?;
         buf.write_u8(self.token_type as u8)?;
-        buf.write(&nonce)?;
-        let hdr_len = max_len - buf.len();
+        buf.write_u8(key.id)?;
 
         // Write token body: issued time/original dcid
         let seconds = self.issue_time()?;
@@ -111,49 +182,53 @@ impl AddressToken {
             } else {
                 return Err(Error::InternalError);
             }
+        } else if let Some((capacity, rtt)) = self.capacity {
+            buf.write_u64(capacity)?;
+            buf.write_u64(rtt.as_micros() as u64)?;
         }
         let token_len = max_len - buf.len();
+        token.truncate(token_len);
 
-        // Encrypt the token body with additional authenticated data:
+        // Append the authentication tag of the token and the additional data:
         // client ip address, optional port/retry scid
-        let nonce = aead::Nonce::assume_unique_for_key(nonce);
         let aad =
             AddressToken::additional_data(self.token_type, &self.address, self.rscid.as_ref())?;
-        let aad = aead::Aad::from(&aad);
-        token.truncate(token_len);
-        let mut buf = token.split_off(hdr_len);
-
-        key.seal_in_place_append_tag(nonce, aad, &mut buf)
-            .map_err(|_| Error::InternalError)?;
-        token.append(&mut buf);
+        let tag = AddressToken::compute_tag(key, &token, &aad);
+        token.extend_from_slice(&tag);
 
         Ok(token)
     }
 
     /// Decode and validate the address token from the client Initial packet.
     ///
+    /// The `keys` are the keys for validating the token, and the one matching
+    /// the Key ID of the token is used.
     /// The `token` is the data of token field in the Initial packet.
     /// The `address` is the source address of the Initial packet.
     /// The `pkt_dcid` is the destination cid in the Initial packet.
     ///
     /// Note: The decoded AddressToken also carries odcid/rscid to facilitate
     /// the server to authenticate cids.
-    /// - RetryToken: the odcid is decrypted and extracted from the token;
-    ///   the rscid is copied from the dcid of the Initial packet with retry
-    ///   token.
+    /// - RetryToken: the odcid is extracted from the token; the rscid is
+    ///   copied from the dcid of the Initial packet with retry token.
     /// - ResumeToken: the odcid is copied from the dcid of the Initial packet
     ///   with resume token; the rscid is None and not applicable.
     ///
     /// See RFC 9000 Figure 7/8: Use of Connection IDs in a Handshake.
     pub fn decode(
-        key: &aead::LessSafeKey,
-        token: &mut [u8],
+        keys: &[AddressTokenKey],
+        token: &[u8],
         address: &SocketAddr,
         pkt_dcid: &ConnectionId,
         lifetime: Duration,
     ) -> Result<AddressToken> {
+        if token.len() < ADDRESS_TOKEN_TAG_LEN {
+            return Err(Error::InvalidToken);
+        }
+        let (body, tag) = token.split_at(token.len() - ADDRESS_TOKEN_TAG_LEN);
+
         // Decode the token header
-        let mut buf: &[u8] = token;
+        let mut buf = body;
         let label = buf.read(4)?;
         if label != b"quic" {
             return Err(Error::InvalidToken);
@@ -165,9 +240,7 @@ impl AddressToken {
             1 => ResumeToken,
             _ => return Err(Error::InvalidToken),
         };
-
-        let nonce = buf.read(aead::NONCE_LEN)?;
-        let hdr_len = token.len() - buf.len();
+        let key_id = buf.read_u8()?;
 
         // When the handshake includes a Retry, the DCID of client Initial
         // packet with retry token is the retry source connection id created by
@@ -178,17 +251,18 @@ impl AddressToken {
             None
         };
 
-        // Decrypt the token body and authenticate the additional data
-        let nonce =
-            aead::Nonce::try_assume_unique_for_key(&nonce).map_err(|_| Error::InternalError)?;
+        // Authenticate the token and the additional data with the key
+        // identified by the Key ID. Different keys may have the same ID.
         let aad = AddressToken::additional_data(token_type, address, rscid)?;
-        let aad = aead::Aad::from(&aad);
-        let buf = &mut token[hdr_len..];
-        key.open_in_place(nonce, aad, buf)
-            .map_err(|_| Error::InvalidToken)?;
+        let authenticated = keys.iter().filter(|k| k.id == key_id).any(|k| {
+            let expected = AddressToken::compute_tag(k, body, &aad);
+            ring::constant_time::verify_slices_are_equal(&expected, tag).is_ok()
+        });
+        if !authenticated {
+            return Err(Error::InvalidToken);
+        }
 
         // Extract and check the timestamp
-        let mut buf = &token[hdr_len..];
         let issued = buf.read_u64()?;
         let issued = match time::UNIX_EPOCH.checked_add(Duration::from_secs(issued)) {
             Some(v) => v,
@@ -220,12 +294,25 @@ impl AddressToken {
             Some(*pkt_dcid)
         };
 
+        // Extract the saved capacity of the path
+        let capacity = if token_type == ResumeToken && !buf.is_empty() {
+            let capacity = buf.read_u64()?;
+            let rtt = Duration::from_micros(buf.read_u64()?);
+            Some((capacity, rtt))
+        } else {
+            None
+        };
+        if !buf.is_empty() {
+            return Err(Error::InvalidToken);
+        }
+
         Ok(AddressToken {
             token_type,
             issued,
             address: *address,
             odcid,
             rscid: rscid.copied(),
+            capacity,
         })
     }
 
@@ -237,6 +324,18 @@ impl AddressToken {
             .map_err(|_| Error::InternalError)
     }
 
+    /// Compute the authentication tag over the token fields and the
+    /// additional data.
+    fn compute_tag(key: &AddressTokenKey, token: &[u8], aad: &[u8]) -> [u8; ADDRESS_TOKEN_TAG_LEN] {
+        let mut ctx = hmac::Context::with_key(&key.key);
+        ctx.update(token);
+        ctx.update(aad);
+
+        let mut tag = [0; ADDRESS_TOKEN_TAG_LEN];
+        tag.copy_from_slice(&ctx.sign().as_ref()[..ADDRESS_TOKEN_TAG_LEN]);
+        tag
+    }
+
     /// Return the additional data to be authenticated.
     fn additional_data(
         token_type: AddressTokenType,
@@ -270,8 +369,8 @@ impl AddressToken {
     }
 
     /// Return the max length of the encoded token.
-    fn max_token_len(key: &aead::LessSafeKey) -> usize {
-        4 + 1 + aead::NONCE_LEN + 8 + 21 + key.algorithm().tag_len()
+    fn max_token_len() -> usize {
+        4 + 1 + 1 + 8 + 21 + ADDRESS_TOKEN_TAG_LEN
     }
 
     /// Return the type of the address token.
@@ -356,8 +455,6 @@ impl fmt::Debug for ResetToken {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ring::aead::LessSafeKey;
-    use ring::aead::UnboundKey;
     use std::net::IpAddr;
     use std::net::Ipv4Addr;
     use std::net::Ipv6Addr;
@@ -383,7 +480,7 @@ mod tests {
     fn address_token_normal() -> Result<()> {
         let ip4 = Ipv4Addr::new(192, 168, 1, 1);
         let ip6 = Ipv6Addr::new(0x26, 0, 0x1c9, 0, 0, 0xafc8, 0x10, 0x1);
-        let key = LessSafeKey::new(UnboundKey::new(&aead::AES_128_GCM, &[1; 16]).unwrap());
+        let key = AddressTokenKey::new(&[1; 16]);
         let cid0 = ConnectionId {
             len: 0,
             data: [0; 20],
@@ -414,12 +511,12 @@ mod tests {
             AddressToken::new_retry_token(SocketAddr::new(IpAddr::V6(ip6), 8888), cid0, cid0),
         ];
         for token in retry_token_tests {
-            let mut buf = token.encode(&key)?;
+            let buf = token.encode(&key)?;
             cmp_address_token(
                 &token,
                 &AddressToken::decode(
-                    &key,
-                    &mut buf,
+                    &[key.clone()],
+                    &buf,
                     &token.address,
                     &token.rscid.unwrap(),
                     lifetime,
@@ -430,19 +527,23 @@ mod tests {
         let resume_token_tests = [
             AddressToken::new_resume_token(SocketAddr::new(IpAddr::V4(ip4), 0)),
             AddressToken::new_resume_token(SocketAddr::new(IpAddr::V6(ip6), 0)),
+            AddressToken::new_capacity_token(
+                SocketAddr::new(IpAddr::V4(ip4), 0),
+                1_000_000,
+                Duration::from_millis(50),
+            ),
         ];
         for token in resume_token_tests {
-            let mut buf = token.encode(&key)?;
-            cmp_address_token(
-                &token,
-                &AddressToken::decode(
-                    &key,
-                    &mut buf,
-                    &token.address,
-                    &ConnectionId::random(),
-                    lifetime,
-                )?,
-            );
+            let buf = token.encode(&key)?;
+            let decoded = AddressToken::decode(
+                &[key.clone()],
+                &buf,
+                &token.address,
+                &ConnectionId::random(),
+                lifetime,
+            )?;
+            cmp_address_token(&token, &decoded);
+            assert_eq!(decoded.capacity, token.capacity);
         }
 
         Ok(())
@@ -450,7 +551,7 @@ mod tests {
 
     #[test]
     fn address_token_invalid() -> Result<()> {
-        let key = LessSafeKey::new(UnboundKey::new(&aead::AES_128_GCM, &[1; 16]).unwrap());
+        let key = AddressTokenKey::new(&[1; 16]);
         let ip4 = Ipv4Addr::new(192, 168, 1, 1);
         let lifetime = Duration::from_secs(86400);
 
@@ -461,6 +562,7 @@ mod tests {
                 address: SocketAddr::new(IpAddr::V4(ip4), 8888),
                 odcid: None,
                 rscid: Some(ConnectionId::random()),
+                capacity: None,
             },
             AddressToken {
                 token_type: RetryToken,
@@ -468,6 +570,7 @@ mod tests {
                 address: SocketAddr::new(IpAddr::V4(ip4), 8888),
                 odcid: Some(ConnectionId::random()),
                 rscid: None,
+                capacity: None,
             },
             AddressToken {
                 token_type: RetryToken,
@@ -475,12 +578,13 @@ mod tests {
                 address: SocketAddr::new(IpAddr::V4(ip4), 8888),
                 odcid: None,
                 rscid: None,
+                capacity: None,
             },
         ] {
             assert!(token.encode(&key).is_err());
         }
 
-        for (mut buf, ip) in [
+        for (buf, ip) in [
             // unexpected label is `quiu`
             (
                 [
@@ -515,12 +619,87 @@ mod tests {
                 SocketAddr::new(IpAddr::V4(ip4), 8889),
             ),
         ] {
-            assert!(
-                AddressToken::decode(&key, &mut buf, &ip, &ConnectionId::random(), lifetime)
-                    .is_err()
-            );
+            assert!(AddressToken::decode(
+                &[key.clone()],
+                &buf,
+                &ip,
+                &ConnectionId::random(),
+                lifetime
+            )
+            .is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn address_token_format() -> Result<()> {
+        let key = AddressTokenKey::new(&[1; 16]);
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8888);
+        let (odcid, rscid) = (ConnectionId::new(&[0xe; 8]), ConnectionId::random());
+        let lifetime = Duration::from_secs(86400);
+
+        // Resume token
+        let token = AddressToken::new_resume_token(addr);
+        let buf = token.encode(&key)?;
+        assert_eq!(buf.len(), 4 + 1 + 1 + 8 + ADDRESS_TOKEN_TAG_LEN);
+        assert_eq!(&buf[..4], b"quic");
+        assert_eq!(buf[4], ResumeToken as u8);
+        assert_eq!(buf[5], key.id());
+        assert_eq!(&buf[6..14], &token.issue_time()?.to_be_bytes());
+
+        // Retry token
+        let token = AddressToken::new_retry_token(addr, odcid, rscid);
+        let buf = token.encode(&key)?;
+        assert_eq!(buf.len(), 4 + 1 + 1 + 8 + 1 + 8 + ADDRESS_TOKEN_TAG_LEN);
+        assert_eq!(buf[4], RetryToken as u8);
+        assert_eq!(buf[5], key.id());
+        assert_eq!(buf[14], 8);
+        assert_eq!(&buf[15..23], &odcid[..]);
+
+        // Tampered token
+        for i in 0..buf.len() {
+            let mut buf = buf.clone();
+            buf[i] ^= 0x01;
+            assert!(AddressToken::decode(&[key.clone()], &buf, &addr, &rscid, lifetime).is_err());
         }
+        assert!(AddressToken::decode(&[key.clone()], &buf[..10], &addr, &rscid, lifetime).is_err());
+        Ok(())
+    }
 
+    #[test]
+    fn address_token_fleet_keys() -> Result<()> {
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8888);
+        let lifetime = Duration::from_secs(86400);
+        let dcid = ConnectionId::random();
+        let old_key = [1; 16];
+        let new_key = [2; 16];
+
+        // The key ID is derived from the key material.
+        assert_eq!(
+            AddressTokenKey::new(&old_key).id(),
+            AddressTokenKey::new(&old_key).id()
+        );
+
+        // Server A has not rotated the keys, and server B has.
+        let keys_a = vec![AddressTokenKey::new(&old_key)];
+        let keys_b = vec![
+            AddressTokenKey::new(&new_key),
+            AddressTokenKey::new(&old_key),
+        ];
+
+        // Token issued by server A validates on server B.
+        let token = AddressToken::new_resume_token(addr).encode(&keys_a[0])?;
+        assert!(AddressToken::decode(&keys_b, &token, &addr, &dcid, lifetime).is_ok());
+
+        // Token issued by server B with the new key doesn't validate on
+        // server A which is unaware of the new key.
+        let token = AddressToken::new_resume_token(addr).encode(&keys_b[0])?;
+        assert!(AddressToken::decode(&keys_b, &token, &addr, &dcid, lifetime).is_ok());
+        assert_eq!(
+            AddressToken::decode(&keys_a, &token, &addr, &dcid, lifetime).err(),
+            Some(Error::InvalidToken)
+        );
         Ok(())
     }
 
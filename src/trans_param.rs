@@ -119,11 +119,41 @@ pub struct TransportParams {
     /// completely trust the path between themselves.
     /// See draft-banks-quic-disable-encryption-00.
     pub disable_encryption: bool,
+
+    /// The parameter (0xbdf0fa, provisional) is included if the endpoint
+    /// supports the BDP_FRAME extension. This parameter has a zero-length
+    /// value.
+    /// See draft-kuhn-quic-bdpframe-extension.
+    pub enable_bdp: bool,
+
+    /// The parameter is included if the endpoint supports the address
+    /// discovery extension. The value 0 indicates that the endpoint is willing
+    /// to provide address observations, 1 indicates that it wants to receive
+    /// address observations, and 2 indicates both.
+    /// See draft-ietf-quic-address-discovery-00.
+    pub address_discovery: Option<u64>,
+
+    /// The parameter is included if the endpoint supports the ACK frequency
+    /// extension. It is the minimum amount of time in microseconds by which
+    /// the endpoint is able to delay an acknowledgement.
+    /// See draft-ietf-quic-ack-frequency.
+    pub min_ack_delay: Option<u64>,
+
+    /// The parameter is included if the endpoint accepts packets with the
+    /// QUIC Bit set to 0. This parameter has a zero-length value.
+    /// See RFC 9287 Section 3.
+    pub grease_quic_bit: bool,
+
+    /// The parameter carries the version used in the packets and the versions
+    /// supported by the endpoint, which protects the version negotiation
+    /// against downgrade attacks.
+    /// See RFC 9368 Section 3.
+    pub version_information: Option<VersionInformation>,
 }
 
 impl TransportParams {
     // Decode transport parameters from the given buffer.
-    pub(crate) fn decode(mut buf: &[u8], is_server: bool) -> Result<(TransportParams, usize)> {
+    pub fn decode(mut buf: &[u8], is_server: bool) -> Result<(TransportParams, usize)> {
         let len = buf.len();
         let mut tp = TransportParams::default();
         let mut found_params = HashSet::new();
@@ -256,19 +286,50 @@ impl TransportParams {
                     tp.retry_source_connection_id = Some(ConnectionId::new(val));
                 }
 
+                0x0011 => {
+                    tp.version_information = Some(VersionInformation::from_bytes(val)?);
+                }
+
                 0x0f739bbc1b666d05 => {
                     tp.enable_multipath = true;
                 }
 
+                0x2ab2 => {
+                    tp.grease_quic_bit = true;
+                }
+
                 0xbaad => {
                     tp.disable_encryption = true;
                 }
 
+                0xbdf0fa => {
+                    tp.enable_bdp = true;
+                }
+
+                0x9f81a176 => {
+                    let v = val.read_varint()?;
+                    if v > 2 {
+                        return Err(Error::TransportParameterError);
+                    }
+                    tp.address_discovery = Some(v);
+                }
+
+                0xff04de1b => {
+                    tp.min_ack_delay = Some(val.read_varint()?);
+                }
+
                 // Ignore unknown parameters.
                 _ => (),
             }
         }
 
+        // The min_ack_delay parameter MUST NOT be greater than max_ack_delay.
+        if let Some(v) = tp.min_ack_delay {
+            if v > tp.max_ack_delay.saturating_mul(1000) {
+                return Err(Error::TransportParameterError);
+            }
+        }
+
         Ok((tp, len - buf.len()))
     }
 
@@ -392,16 +453,45 @@ impl TransportParams {
             }
         }
 
+        if let Some(ref version_information) = tp.version_information {
+            buf.write_varint(0x0011)?;
+            buf.write_varint(version_information.wire_len() as u64)?;
+            let len = version_information.to_bytes(buf)?;
+            buf = &mut buf[len..];
+        }
+
         if tp.enable_multipath {
             buf.write_varint(0x0f739bbc1b666d05)?;
             buf.write_varint(0)?;
         }
 
+        if tp.grease_quic_bit {
+            buf.write_varint(0x2ab2)?;
+            buf.write_varint(0)?;
+        }
+
         if tp.disable_encryption {
             buf.write_varint(0xbaad)?;
             buf.write_varint(0)?;
         }
 
+        if tp.enable_bdp {
+            buf.write_varint(0xbdf0fa)?;
+            buf.write_varint(0)?;
+        }
+
+        if let Some(v) = tp.address_discovery {
+            buf.write_varint(0x9f81a176)?;
+            buf.write_varint(codec::encode_varint_len(v) as u64)?;
+            buf.write_varint(v)?;
+        }
+
+        if let Some(v) = tp.min_ack_delay {
+            buf.write_varint(0xff04de1b)?;
+            buf.write_varint(codec::encode_varint_len(v) as u64)?;
+            buf.write_varint(v)?;
+        }
+
         Ok(len - buf.len())
     }
 
@@ -480,6 +570,11 @@ impl Default for TransportParams {
 
             enable_multipath: false,
             disable_encryption: false,
+            enable_bdp: false,
+            address_discovery: None,
+            min_ack_delay: None,
+            grease_quic_bit: false,
+            version_information: None,
         }
     }
 }
@@ -553,6 +648,57 @@ impl PreferredAddress {
     }
 }
 
+/// The version information of an endpoint.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VersionInformation {
+    /// The version used in the packet that carries the transport parameters.
+    pub chosen_version: u32,
+
+    /// The versions supported by the endpoint in the order of preference.
+    pub available_versions: Vec<u32>,
+}
+
+impl VersionInformation {
+    pub fn wire_len(&self) -> usize {
+        4 + 4 * self.available_versions.len()
+    }
+
+    pub fn to_bytes(&self, mut buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len();
+        buf.write_u32(self.chosen_version)?;
+        for version in &self.available_versions {
+            buf.write_u32(*version)?;
+        }
+        Ok(len - buf.len())
+    }
+
+    pub fn from_bytes(mut buf: &[u8]) -> Result<VersionInformation> {
+        // The Chosen Version field MUST NOT be zero, and the length of the
+        // parameter must be a multiple of 4 bytes.
+        if buf.is_empty() || buf.len() % 4 != 0 {
+            return Err(Error::TransportParameterError);
+        }
+        let chosen_version = buf.read_u32()?;
+        if chosen_version == 0 {
+            return Err(Error::TransportParameterError);
+        }
+
+        let mut available_versions = Vec::with_capacity(buf.len() / 4);
+        while !buf.is_empty() {
+            let version = buf.read_u32()?;
+            if version == 0 {
+                return Err(Error::TransportParameterError);
+            }
+            available_versions.push(version);
+        }
+
+        Ok(Self {
+            chosen_version,
+            available_versions,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -580,6 +726,14 @@ mod tests {
             retry_source_connection_id: None,
             enable_multipath: true,
             disable_encryption: false,
+            enable_bdp: true,
+            address_discovery: Some(1),
+            min_ack_delay: Some(1000),
+            grease_quic_bit: true,
+            version_information: Some(VersionInformation {
+                chosen_version: crate::QUIC_VERSION_V2,
+                available_versions: vec![crate::QUIC_VERSION_V2, crate::QUIC_VERSION_V1],
+            }),
         };
 
         // encode on the client side
@@ -624,6 +778,14 @@ mod tests {
             retry_source_connection_id: Some(ConnectionId::random()),
             enable_multipath: false,
             disable_encryption: true,
+            enable_bdp: false,
+            address_discovery: Some(2),
+            min_ack_delay: None,
+            grease_quic_bit: false,
+            version_information: Some(VersionInformation {
+                chosen_version: crate::QUIC_VERSION_V1,
+                available_versions: vec![crate::QUIC_VERSION_V1],
+            }),
         };
 
         // encode on the server side
@@ -675,4 +837,61 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn version_information() -> Result<()> {
+        let info = VersionInformation {
+            chosen_version: crate::QUIC_VERSION_V1,
+            available_versions: vec![crate::QUIC_VERSION_V1, crate::QUIC_VERSION_V2],
+        };
+        let len = info.wire_len();
+        let mut buf = vec![0; len];
+        assert_eq!(info.to_bytes(&mut buf)?, len);
+        assert_eq!(VersionInformation::from_bytes(&buf)?, info);
+
+        // Invalid length
+        assert_eq!(
+            VersionInformation::from_bytes(&buf[..len - 1]),
+            Err(Error::TransportParameterError)
+        );
+        assert_eq!(
+            VersionInformation::from_bytes(&[]),
+            Err(Error::TransportParameterError)
+        );
+
+        // Invalid chosen version
+        assert_eq!(
+            VersionInformation::from_bytes(&[0, 0, 0, 0]),
+            Err(Error::TransportParameterError)
+        );
+
+        // Invalid available version
+        assert_eq!(
+            VersionInformation::from_bytes(&[0, 0, 0, 1, 0, 0, 0, 0]),
+            Err(Error::TransportParameterError)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn min_ack_delay() -> Result<()> {
+        let mut tp = TransportParams {
+            max_ack_delay: 25,
+            min_ack_delay: Some(25_000),
+            ..TransportParams::default()
+        };
+        let mut raw_params = [0; 128];
+        let len = TransportParams::encode(&tp, true, &mut raw_params)?;
+        let (tp2, _) = TransportParams::decode(&raw_params[..len], false)?;
+        assert_eq!(tp2.min_ack_delay, Some(25_000));
+
+        // The min_ack_delay is greater than max_ack_delay
+        tp.min_ack_delay = Some(25_001);
+        let len = TransportParams::encode(&tp, true, &mut raw_params)?;
+        assert_eq!(
+            TransportParams::decode(&raw_params[..len], false),
+            Err(Error::TransportParameterError)
+        );
+        Ok(())
+    }
 }
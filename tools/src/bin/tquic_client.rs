@@ -15,6 +15,7 @@
 use std::cell::RefCell;
 use std::cell::RefMut;
 use std::cmp::max;
+use std::collections::BTreeMap;
 use std::fs::create_dir_all;
 use std::fs::File;
 use std::io::BufWriter;
@@ -31,7 +32,9 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
+use std::time::Duration;
 use std::time::Instant;
+use std::time::SystemTime;
 
 use bytes::Bytes;
 use clap::error::ErrorKind;
@@ -55,17 +58,24 @@ use url::Url;
 use tquic::connection::ConnectionStats;
 use tquic::error::Error;
 use tquic::h3::connection::Http3Connection;
+use tquic::h3::connection::Http3Priority;
 use tquic::h3::Header;
 use tquic::h3::Http3Config;
+use tquic::BlockedEvent;
 use tquic::Config;
 use tquic::CongestionControlAlgorithm;
 use tquic::Connection;
+use tquic::Ecn;
 use tquic::Endpoint;
 use tquic::MultipathAlgorithm;
 use tquic::PacketInfo;
+use tquic::PathStats;
+use tquic::PersistentCongestionEvent;
+use tquic::StreamEvent;
 use tquic::TlsConfig;
 use tquic::TransportHandler;
 use tquic_tools::ApplicationProto;
+use tquic_tools::NetEmulation;
 use tquic_tools::QuicSocket;
 use tquic_tools::Result;
 
@@ -84,10 +94,29 @@ pub struct ClientOpt {
     #[clap(long, value_delimiter = ',', value_name = "ADDR")]
     pub local_addresses: Vec<IpAddr>,
 
+    /// Optional local network interfaces for client. e.g eth0,wlan0
+    /// The address of each interface is appended to the local addresses.
+    #[clap(long, value_delimiter = ',', value_name = "NAME")]
+    pub local_interfaces: Vec<String>,
+
     /// Request URLs. The host of the first url is used as TLS SNI.
+    /// In perf mode, the path of the url is the number of bytes requested from
+    /// the server, e.g. https://example.org/1000000
     #[clap(value_delimiter = ' ')]
     pub urls: Vec<Url>,
 
+    /// File of request URLs, one per line, which are appended to the URLs.
+    /// Each line is "<URL> [u=<0-7>] [i] [delay=<TIME>]", where "u" and "i"
+    /// are the urgency and incremental of the request (h3 only), and "delay"
+    /// is the time in milliseconds after the connection is established before
+    /// the request is issued. Requests are issued in the order of the file.
+    #[clap(long, value_name = "FILE")]
+    pub url_file: Option<String>,
+
+    /// Attributes of the request URLs, which are not command-line options.
+    #[clap(skip)]
+    pub url_attrs: Vec<UrlAttrs>,
+
     /// Number of threads.
     #[clap(
         short,
@@ -146,6 +175,31 @@ pub struct ClientOpt {
     )]
     pub duration: u64,
 
+    /// Enable benchmark mode. Response bodies are discarded, and the requests
+    /// per second and goodput are reported every second during the run.
+    #[clap(long, conflicts_with_all = ["print_res", "dump_dir"], help_heading = "Benchmark")]
+    pub bench: bool,
+
+    /// Maximum number of requests sent per second per thread. "0" means unlimited.
+    #[clap(
+        long,
+        default_value = "0",
+        value_name = "NUM",
+        help_heading = "Benchmark"
+    )]
+    pub request_rate: u64,
+
+    /// Size of the request body in bytes. Requests with body are sent with the
+    /// POST method in h3 mode, and the body is uploaded to the server in perf
+    /// mode. Not applicable to http/0.9.
+    #[clap(
+        long,
+        default_value = "0",
+        value_name = "NUM",
+        help_heading = "Benchmark"
+    )]
+    pub body_size: usize,
+
     /// ALPN, separated by ",".
     #[clap(
         short,
@@ -199,6 +253,16 @@ pub struct ClientOpt {
     #[clap(long, default_value = "MINRTT", help_heading = "Protocol")]
     pub multipath_algor: MultipathAlgorithm,
 
+    /// Weights of paths in the order of local addresses, which are used by the
+    /// ROUNDROBIN scheduler. e.g 1,3
+    #[clap(
+        long,
+        value_delimiter = ',',
+        value_name = "NUM",
+        help_heading = "Protocol"
+    )]
+    pub path_weights: Vec<u32>,
+
     /// Set active_connection_id_limit transport parameter. Values lower than 2 will be ignored.
     #[clap(
         long,
@@ -289,6 +353,11 @@ pub struct ClientOpt {
     #[clap(long, value_name = "DIR", help_heading = "Output")]
     pub dump_dir: Option<String>,
 
+    /// Resume interrupted downloads in the dump directory using range requests.
+    /// A partially downloaded file is retried over a fresh connection on failure.
+    #[clap(long, requires = "dump_dir", help_heading = "Output")]
+    pub resume: bool,
+
     /// Log level, support OFF/ERROR/WARN/INFO/DEBUG/TRACE.
     #[clap(
         long,
@@ -310,7 +379,13 @@ pub struct ClientOpt {
     #[clap(long, value_name = "DIR", help_heading = "Output")]
     pub qlog_dir: Option<String>,
 
-    /// Client will exit if consecutive failure reaches the threshold at the beginning.
+    /// Save a HAR-like JSON report with per-request timings and transport
+    /// statistics into the given file.
+    #[clap(long, value_name = "FILE", help_heading = "Output")]
+    pub har_file: Option<String>,
+
+    /// Client will exit if consecutive failure reaches the threshold at the beginning,
+    /// or at any time when resuming downloads.
     #[clap(long, default_value = "10", value_name = "NUM", help_heading = "Misc")]
     pub connection_failure_threshold: u64,
 
@@ -322,6 +397,29 @@ pub struct ClientOpt {
     #[clap(long, help_heading = "Misc")]
     pub disable_encryption: bool,
 
+    /// Emulate loss of outgoing packets with the given percentage.
+    #[clap(
+        long,
+        default_value = "0",
+        value_name = "PERCENT",
+        help_heading = "Misc"
+    )]
+    pub emulate_loss: f64,
+
+    /// Emulate delay of outgoing packets in milliseconds.
+    #[clap(long, default_value = "0", value_name = "TIME", help_heading = "Misc")]
+    pub emulate_delay: u64,
+
+    /// Emulate reordering by sending the given percentage of outgoing packets
+    /// without the emulated delay. It takes effect only if the delay is set.
+    #[clap(
+        long,
+        default_value = "0",
+        value_name = "PERCENT",
+        help_heading = "Misc"
+    )]
+    pub emulate_reorder: f64,
+
     /// Number of max samples per thread used for request time statistics.
     #[clap(
         long,
@@ -330,10 +428,62 @@ pub struct ClientOpt {
         help_heading = "Misc"
     )]
     pub max_sample: usize,
+
+    /// Time spent resolving the server address, which is not a command-line option.
+    #[clap(skip)]
+    pub dns_time: Option<Duration>,
+}
+
+/// Attributes of a request URL given in the URL file.
+#[derive(Debug, Clone, Default)]
+pub struct UrlAttrs {
+    /// Extensible priority of the request, in the form of (urgency, incremental).
+    pub priority: Option<(u8, bool)>,
+
+    /// Delay before issuing the request since the connection is established.
+    pub delay: Duration,
+}
+
+impl UrlAttrs {
+    /// Parse a line of the URL file. Return None for comments and blank lines.
+    fn parse(line: &str) -> std::result::Result<Option<(Url, UrlAttrs)>, String> {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut fields = line.split_whitespace();
+        let url = match fields.next() {
+            Some(v) => Url::parse(v).map_err(|e| format!("invalid url {}: {}", v, e))?,
+            None => return Ok(None),
+        };
+
+        let mut attrs = UrlAttrs::default();
+        let mut urgency = None;
+        let mut incremental = false;
+        for field in fields {
+            match field.split_once('=') {
+                Some(("u", v)) => match v.parse::<u8>() {
+                    Ok(u) if u <= 7 => urgency = Some(u),
+                    _ => return Err(format!("invalid urgency {}", v)),
+                },
+                Some(("delay", v)) => {
+                    let ms = v.parse().map_err(|_| format!("invalid delay {}", v))?;
+                    attrs.delay = Duration::from_millis(ms);
+                }
+                None if field == "i" => incremental = true,
+                _ => return Err(format!("unknown attribute {}", field)),
+            }
+        }
+        if urgency.is_some() || incremental {
+            attrs.priority = Some((urgency.unwrap_or(3), incremental));
+        }
+
+        Ok(Some((url, attrs)))
+    }
 }
 
 const MAX_BUF_SIZE: usize = 65536;
 
+/// Interval for reporting progress in benchmark mode.
+const BENCH_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Multi-threads QUIC client.
 struct Client {
     /// Client option.
@@ -379,6 +529,10 @@ impl Client {
             threads.push(thread);
         }
 
+        if self.option.bench {
+            self.report_progress(&threads);
+        }
+
         for thread in threads {
             thread.join().unwrap();
         }
@@ -386,17 +540,65 @@ impl Client {
         self.finish();
     }
 
+    /// Report the benchmark progress every second until all workers exit.
+    fn report_progress(&self, threads: &[thread::JoinHandle<()>]) {
+        let mut last_report = Instant::now();
+        while !threads.iter().all(|t| t.is_finished()) {
+            thread::sleep(Duration::from_millis(100));
+
+            let now = Instant::now();
+            let elapsed = now - last_report;
+            if elapsed < BENCH_REPORT_INTERVAL {
+                continue;
+            }
+            last_report = now;
+
+            let mut context = self.context.lock().unwrap();
+            let (requests, bytes) = (context.interval_requests, context.interval_bytes);
+            context.interval_requests = 0;
+            context.interval_bytes = 0;
+            println!(
+                "[{:>6.1}s] {:.2} req/s, goodput {:.2} Mbps",
+                (now - self.start_time).as_secs_f64(),
+                requests as f64 / elapsed.as_secs_f64(),
+                bytes as f64 * 8.0 / elapsed.as_secs_f64() / 1e6
+            );
+        }
+    }
+
     fn finish(&self) {
         // Print stats.
         self.stats();
 
         // Write session resumption file.
-        let context = self.context.lock().unwrap();
+        let mut context = self.context.lock().unwrap();
         if let Some(session) = &context.session {
             if let Some(session_file) = &self.option.session_file {
                 std::fs::write(session_file, session).ok();
             }
         }
+
+        // Write HAR file.
+        if let Some(har_file) = &self.option.har_file {
+            let har = serde_json::json!({
+                "log": {
+                    "version": "1.2",
+                    "creator": {
+                        "name": "tquic_client",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                    "entries": std::mem::take(&mut context.har_entries),
+                }
+            });
+            match serde_json::to_string_pretty(&har) {
+                Ok(har) => {
+                    if let Err(e) = std::fs::write(har_file, har) {
+                        error!("write har file {} error {:?}", har_file, e);
+                    }
+                }
+                Err(e) => error!("serialize har error {:?}", e),
+            }
+        }
     }
 
     fn stats(&self) {
@@ -421,6 +623,11 @@ impl Client {
             "requests: sent {}, finish {}, success {}",
             context.request_sent, context.request_done, context.request_success,
         );
+        println!(
+            "goodput: recv {:.2} Mbps, sent {:.2} Mbps",
+            context.response_bytes as f64 * 8.0 / d.as_secs_f64() / 1e6,
+            context.request_body_bytes as f64 * 8.0 / d.as_secs_f64() / 1e6,
+        );
 
         let mut s = Data::new(context.request_time_samples.clone());
         println!("time for request(µs):");
@@ -438,6 +645,13 @@ impl Client {
             s.percentile(90),
             s.percentile(99),
         );
+        if self.option.bench {
+            println!(
+                "\tp95: {:.2}, p99.9: {:.2}",
+                s.percentile(95),
+                s.quantile(0.999),
+            );
+        }
 
         println!(
             "recv pkts: {}, sent pkts: {}, lost pkts: {}",
@@ -451,6 +665,28 @@ impl Client {
             context.conn_stats.sent_bytes,
             context.conn_stats.lost_bytes
         );
+        println!(
+            "flow control blocked: conn {} times {:.2}ms, stream {} times {:.2}ms",
+            context.conn_stats.data_blocked_count,
+            context.conn_stats.data_blocked_duration as f64 / 1000.0,
+            context.conn_stats.stream_data_blocked_count,
+            context.conn_stats.stream_data_blocked_duration as f64 / 1000.0
+        );
+
+        if self.option.enable_multipath {
+            println!("paths:");
+            for (local, p) in &context.path_stats {
+                println!(
+                    "\t{}: sent pkts {}, lost pkts {}, sent bytes {}, recv bytes {}, avg srtt {:.2}ms",
+                    local,
+                    p.sent_count,
+                    p.lost_count,
+                    p.sent_bytes,
+                    p.recv_bytes,
+                    p.srtt as f64 / max(p.count, 1) as f64 / 1000.0,
+                );
+            }
+        }
         println!();
     }
 }
@@ -470,6 +706,12 @@ struct ClientContext {
     conn_finish_failed: u64,
     end_time: Option<Instant>,
     conn_stats: ConnectionStats,
+    response_bytes: u64,
+    request_body_bytes: u64,
+    interval_requests: u64,
+    interval_bytes: u64,
+    path_stats: BTreeMap<IpAddr, PathSummary>,
+    har_entries: Vec<serde_json::Value>,
 }
 
 fn update_conn_stats(total: &mut ConnectionStats, one: &ConnectionStats) {
@@ -479,6 +721,43 @@ fn update_conn_stats(total: &mut ConnectionStats, one: &ConnectionStats) {
     total.recv_bytes += one.recv_bytes;
     total.sent_bytes += one.sent_bytes;
     total.lost_bytes += one.lost_bytes;
+    total.data_blocked_count += one.data_blocked_count;
+    total.data_blocked_duration += one.data_blocked_duration;
+    total.stream_data_blocked_count += one.stream_data_blocked_count;
+    total.stream_data_blocked_duration += one.stream_data_blocked_duration;
+}
+
+/// Statistics of the paths with the same local IP address.
+#[derive(Default)]
+struct PathSummary {
+    /// Number of paths.
+    count: u64,
+    sent_count: u64,
+    lost_count: u64,
+    sent_bytes: u64,
+    recv_bytes: u64,
+    /// Sum of smoothed RTT in microseconds.
+    srtt: u64,
+}
+
+impl PathSummary {
+    fn update(&mut self, one: &PathStats) {
+        self.count += 1;
+        self.sent_count += one.sent_count;
+        self.lost_count += one.lost_count;
+        self.sent_bytes += one.sent_bytes;
+        self.recv_bytes += one.recv_bytes;
+        self.srtt += one.srtt;
+    }
+
+    fn merge(&mut self, other: &PathSummary) {
+        self.count += other.count;
+        self.sent_count += other.sent_count;
+        self.lost_count += other.lost_count;
+        self.sent_bytes += other.sent_bytes;
+        self.recv_bytes += other.recv_bytes;
+        self.srtt += other.srtt;
+    }
 }
 
 /// Client worker with single thread.
@@ -516,6 +795,15 @@ struct Worker {
     /// Worker end time.
     end_time: Option<Instant>,
 
+    /// Last time of reporting the benchmark progress.
+    last_report_time: Instant,
+
+    /// Number of successful requests already reported.
+    reported_requests: u64,
+
+    /// Number of response bytes already reported.
+    reported_bytes: u64,
+
     /// If terminated by system signal.
     terminated: Arc<AtomicBool>,
 }
@@ -545,6 +833,7 @@ impl Worker {
         config.set_min_congestion_window(option.min_congestion_window);
         config.enable_multipath(option.enable_multipath);
         config.set_multipath_algorithm(option.multipath_algor);
+        config.set_multipath_path_weights(option.path_weights.clone());
         config.set_active_connection_id_limit(option.active_cid_limit);
         config.enable_encryption(!option.disable_encryption);
         let tls_config = TlsConfig::new_client_config(
@@ -569,6 +858,11 @@ impl Worker {
             }
         };
         let mut sock = QuicSocket::new(&local, registry)?;
+        sock.set_emulation(NetEmulation::new(
+            option.emulate_loss,
+            option.emulate_delay,
+            option.emulate_reorder,
+        ));
 
         let mut assigned_addrs = Vec::new();
         assigned_addrs.push(sock.local_addr());
@@ -599,6 +893,9 @@ impl Worker {
             recv_buf: vec![0u8; MAX_BUF_SIZE],
             start_time: Instant::now(),
             end_time: None,
+            last_report_time: Instant::now(),
+            reported_requests: 0,
+            reported_bytes: 0,
             terminated,
         })
     }
@@ -615,7 +912,7 @@ impl Worker {
                 break;
             }
 
-            self.poll.poll(&mut events, self.endpoint.timeout())?;
+            self.poll.poll(&mut events, self.timeout())?;
 
             // Process IO events
             for event in events.iter() {
@@ -628,6 +925,9 @@ impl Worker {
             // Note: Since `poll()` doesn't clearly tell if there was a timeout when it returns,
             // it is up to the endpoint to check for a timeout and deal with it.
             self.endpoint.on_timeout(Instant::now());
+
+            // Send packets delayed by the network emulation.
+            self.sock.flush_delayed()?;
         }
 
         self.finish();
@@ -635,6 +935,44 @@ impl Worker {
         Ok(())
     }
 
+    /// Return the amount of time until the next timeout event of the worker.
+    fn timeout(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let deadlines = [
+            self.worker_ctx.borrow().next_request_time,
+            self.worker_ctx.borrow().next_issue_time,
+            self.option
+                .bench
+                .then(|| self.last_report_time + BENCH_REPORT_INTERVAL),
+        ];
+
+        let mut timeout = self.endpoint.timeout();
+        if let Some(t) = self.sock.timeout() {
+            timeout = Some(timeout.map_or(t, |v| v.min(t)));
+        }
+        for deadline in deadlines.into_iter().flatten() {
+            let d = deadline.saturating_duration_since(now);
+            timeout = Some(timeout.map_or(d, |t| t.min(d)));
+        }
+        timeout
+    }
+
+    /// Report the progress since last report to the client in benchmark mode.
+    fn report_progress(&mut self) {
+        let now = Instant::now();
+        if !self.option.bench || now < self.last_report_time + BENCH_REPORT_INTERVAL {
+            return;
+        }
+        self.last_report_time = now;
+
+        let worker_ctx = self.worker_ctx.borrow();
+        let mut client_ctx = self.client_ctx.lock().unwrap();
+        client_ctx.interval_requests += worker_ctx.request_success - self.reported_requests;
+        client_ctx.interval_bytes += worker_ctx.response_bytes - self.reported_bytes;
+        self.reported_requests = worker_ctx.request_success;
+        self.reported_bytes = worker_ctx.response_bytes;
+    }
+
     fn should_exit(&self) -> bool {
         if self.terminated.load(Ordering::Relaxed) {
             info!("worker terminated by system signal and waiting for tasks to finish.");
@@ -654,6 +992,17 @@ impl Worker {
             return true;
         }
 
+        if self.option.resume
+            && worker_ctx.conn_consecutive_failed >= self.option.connection_failure_threshold
+        {
+            error!(
+                "download from server[{:?}] failed after {} retries",
+                self.option.connect_to.unwrap(),
+                worker_ctx.conn_consecutive_failed
+            );
+            return true;
+        }
+
         if (self.option.duration > 0
             && (Instant::now() - self.start_time).as_secs() > self.option.duration)
             || (self.option.total_requests_per_thread > 0
@@ -673,6 +1022,9 @@ impl Worker {
         // Process connections.
         self.endpoint.process_connections()?;
 
+        // Report benchmark progress.
+        self.report_progress();
+
         // Check exit.
         if self.should_exit() {
             // Close endpoint.
@@ -733,6 +1085,7 @@ impl Worker {
     }
 
     fn try_send_requests(&mut self) {
+        self.worker_ctx.borrow_mut().next_issue_time = None;
         let mut senders = self.senders.borrow_mut();
         for (index, sender) in senders.iter_mut() {
             let conn = self.endpoint.conn_get_mut(*index).unwrap();
@@ -762,6 +1115,9 @@ impl Worker {
                 src: remote,
                 dst: local,
                 time: Instant::now(),
+                ecn: Ecn::NotEct,
+                dscp: 0,
+                segment_size: 0,
             };
 
             // Process the incoming packet.
@@ -791,13 +1147,19 @@ impl Worker {
         client_ctx.conn_finish += worker_ctx.conn_finish;
         client_ctx.conn_finish_success += worker_ctx.conn_finish_success;
         client_ctx.conn_finish_failed += worker_ctx.conn_finish_failed;
+        client_ctx.response_bytes += worker_ctx.response_bytes;
+        client_ctx.request_body_bytes += worker_ctx.request_body_bytes;
         client_ctx
             .request_time_samples
             .append(&mut worker_ctx.request_time_samples);
+        client_ctx.har_entries.append(&mut worker_ctx.har_entries);
         if self.end_time > client_ctx.end_time {
             client_ctx.end_time = self.end_time;
         }
         update_conn_stats(&mut client_ctx.conn_stats, &worker_ctx.conn_stats);
+        for (local, p) in &worker_ctx.path_stats {
+            client_ctx.path_stats.entry(*local).or_default().merge(p);
+        }
     }
 }
 
@@ -815,9 +1177,17 @@ struct WorkerContext {
     conn_finish: u64,
     conn_finish_success: u64,
     conn_finish_failed: u64,
+    conn_consecutive_failed: u64,
     concurrent_conns: u32,
     conn_stats: ConnectionStats,
+    path_stats: BTreeMap<IpAddr, PathSummary>,
+    har_entries: Vec<serde_json::Value>,
     connected: bool,
+    response_bytes: u64,
+    request_body_bytes: u64,
+    request_interval: Option<Duration>,
+    next_request_time: Option<Instant>,
+    next_issue_time: Option<Instant>,
 }
 
 impl WorkerContext {
@@ -827,6 +1197,11 @@ impl WorkerContext {
             ..Default::default()
         };
 
+        if option.request_rate > 0 {
+            worker_ctx.request_interval =
+                Some(Duration::from_secs(1).div_f64(option.request_rate as f64));
+        }
+
         if let Some(session_file) = &option.session_file {
             if let Ok(session_data) = std::fs::read(session_file) {
                 worker_ctx.session = Some(session_data);
@@ -837,51 +1212,158 @@ impl WorkerContext {
 
         worker_ctx
     }
+
+    /// Try to acquire a quota for sending a new request under the request rate
+    /// limit. Return true if the request is allowed to be sent right now.
+    fn acquire_request_quota(&mut self, now: Instant) -> bool {
+        let interval = match self.request_interval {
+            Some(v) => v,
+            None => return true,
+        };
+
+        match self.next_request_time {
+            Some(t) if t > now => false,
+            Some(t) => {
+                self.next_request_time = Some(max(t + interval, now));
+                true
+            }
+            None => {
+                self.next_request_time = Some(now + interval);
+                true
+            }
+        }
+    }
 }
 
 struct Request {
     url: Url,
+    method: String,
     line: String,         // Used in http/0.9.
     headers: Vec<Header>, // Used in h3.
+    body: Option<Bytes>,  // Used in h3.
+    body_written: usize,
+    priority: Option<(u8, bool)>, // Used in h3.
+    response_path: Option<String>,
     response_writer: Option<std::io::BufWriter<std::fs::File>>,
     start_time: Option<Instant>,
+    resume: Option<ResumeState>, // Used in h3.
+    timing: RequestTiming,
+}
+
+/// Timings and response information of a request, used for the HAR report.
+#[derive(Default)]
+struct RequestTiming {
+    /// Wall-clock time when the request is sent.
+    sent_at: Option<SystemTime>,
+
+    /// Time spent resolving the server address, only for the first request
+    /// on a connection.
+    dns: Option<Duration>,
+
+    /// Handshake time of the connection, only for the first request on the
+    /// connection.
+    connect: Option<Duration>,
+
+    /// Time when the first byte of the response is received.
+    first_byte_time: Option<Instant>,
+
+    /// Response status, only used in h3.
+    status: u16,
+
+    /// Response headers, only used in h3.
+    headers: Vec<(String, String)>,
+
+    /// Size of the response body.
+    body_size: u64,
+}
+
+/// State of a resumable download.
+///
+/// The validator (ETag or Last-Modified) of a partially downloaded file is
+/// saved in a state file alongside the file, and the state file is removed
+/// once the download is completed.
+struct ResumeState {
+    /// Size of the partially downloaded file.
+    offset: u64,
+
+    /// Path of the state file.
+    state_path: String,
+}
+
+impl ResumeState {
+    /// Suffix of the state file name.
+    const STATE_FILE_SUFFIX: &'static str = ".tquic-resume";
+
+    fn new(response_path: &str) -> Self {
+        let offset = std::fs::metadata(response_path).map_or(0, |m| m.len());
+        Self {
+            offset,
+            state_path: format!("{}{}", response_path, Self::STATE_FILE_SUFFIX),
+        }
+    }
+
+    /// Return the saved validator of the partially downloaded file.
+    fn validator(&self) -> Option<String> {
+        let validator = std::fs::read_to_string(&self.state_path).ok()?;
+        let validator = validator.trim();
+        match validator.is_empty() {
+            true => None,
+            false => Some(validator.to_string()),
+        }
+    }
+
+    /// Save the validator of the file being downloaded.
+    fn save(&self, validator: &[u8]) {
+        if let Err(e) = std::fs::write(&self.state_path, validator) {
+            error!("write state file {} error {:?}", self.state_path, e);
+        }
+    }
+
+    /// Remove the state file after the download is completed.
+    fn finish(&self) {
+        _ = std::fs::remove_file(&self.state_path);
+    }
 }
 
 impl Request {
-    /// Make a response body writer.
+    /// Make the path of the file for dumping response body.
     /// The name of file is same as the URL's last path segment.
-    fn make_response_writer(url: &Url, target_path: &Option<String>) -> Option<BufWriter<File>> {
-        if let Some(target_path) = target_path {
-            let f = match url.path_segments().map(|c| c.collect::<Vec<_>>()) {
-                Some(f) => f,
-                None => {
-                    error!("make response writer failed, url {:?}", url);
-                    return None;
-                }
-            };
-            let f = match f.iter().last() {
-                Some(f) => f,
-                None => {
-                    error!("make response writer failed, url {:?}", url);
-                    return None;
-                }
-            };
+    fn make_response_path(url: &Url, target_path: &Option<String>) -> Option<String> {
+        let target_path = target_path.as_ref()?;
+        let f = match url.path_segments().and_then(|mut c| c.next_back()) {
+            Some(f) => f,
+            None => {
+                error!("make response writer failed, url {:?}", url);
+                return None;
+            }
+        };
 
-            let path = format!("{}/{}", target_path, f,);
-            match File::create(path) {
-                Ok(f) => Some(BufWriter::new(f)),
-                Err(e) => {
-                    error!("create file error {:?}, url {:?}", e, url);
-                    None
-                }
+        Some(format!("{}/{}", target_path, f))
+    }
+
+    /// Make a response body writer.
+    fn make_response_writer(path: &str, append: bool) -> Option<BufWriter<File>> {
+        let file = match append {
+            true => std::fs::OpenOptions::new().append(true).open(path),
+            false => File::create(path),
+        };
+        match file {
+            Ok(f) => Some(BufWriter::new(f)),
+            Err(e) => {
+                error!("create file error {:?}, path {:?}", e, path);
+                None
             }
-        } else {
-            None
         }
     }
 
     // TODO: support custom headers.
-    fn new(method: &str, url: &Url, body: &Option<Vec<u8>>, dump_dir: &Option<String>) -> Self {
+    fn new(
+        method: &str,
+        url: &Url,
+        body: Option<Bytes>,
+        dump_dir: &Option<String>,
+        resume: bool,
+    ) -> Self {
         let authority = match url.port() {
             Some(port) => format!("{}:{}", url.host_str().unwrap(), port),
             None => url.host_str().unwrap().to_string(),
@@ -894,18 +1376,226 @@ impl Request {
             tquic::h3::Header::new(b":path", url[url::Position::BeforePath..].as_bytes()),
             tquic::h3::Header::new(b"user-agent", b"tquic"),
         ];
-        if body.is_some() {
+        if let Some(body) = &body {
             headers.push(tquic::h3::Header::new(
                 b"content-length",
-                body.as_ref().unwrap().len().to_string().as_bytes(),
+                body.len().to_string().as_bytes(),
             ));
         }
+
+        let response_path = Self::make_response_path(url, dump_dir);
+        let mut response_writer = None;
+        let mut resume_state = None;
+        match &response_path {
+            // The response writer is made when the response headers arrive.
+            Some(path) if resume => {
+                let state = ResumeState::new(path);
+                if state.offset > 0 {
+                    headers.push(tquic::h3::Header::new(
+                        b"range",
+                        format!("bytes={}-", state.offset).as_bytes(),
+                    ));
+                    if let Some(validator) = state.validator() {
+                        headers.push(tquic::h3::Header::new(b"if-range", validator.as_bytes()));
+                    }
+                }
+                resume_state = Some(state);
+            }
+            Some(path) => response_writer = Self::make_response_writer(path, false),
+            None => (),
+        }
+
         Self {
             url: url.clone(),
+            method: method.to_string(),
             line: format!("GET {}\r\n", url.path()),
             headers,
-            response_writer: Self::make_response_writer(url, dump_dir),
+            body,
+            body_written: 0,
+            priority: None,
+            response_path,
+            response_writer,
             start_time: None,
+            resume: resume_state,
+            timing: RequestTiming::default(),
+        }
+    }
+
+    /// Record the response headers for the HAR report.
+    fn record_response_headers(&mut self, headers: &[Header]) {
+        for header in headers {
+            let name = String::from_utf8_lossy(header.name()).to_string();
+            let value = String::from_utf8_lossy(header.value()).to_string();
+            if name == ":status" {
+                self.timing.status = value.parse().unwrap_or(0);
+                continue;
+            }
+            self.timing.headers.push((name, value));
+        }
+    }
+
+    /// Record the received response data for the HAR report.
+    fn record_response_data(&mut self, len: usize) {
+        if self.timing.first_byte_time.is_none() {
+            self.timing.first_byte_time = Some(Instant::now());
+        }
+        self.timing.body_size += len as u64;
+    }
+
+    /// Build a HAR entry of the finished request.
+    /// See http://www.softwareishard.com/blog/har-12-spec/#entries
+    fn har_entry(&self, conn: &mut Connection, app_proto: ApplicationProto) -> serde_json::Value {
+        let now = Instant::now();
+        let ms = |d: Option<Duration>| d.map_or(-1.0, |d| d.as_secs_f64() * 1000.0);
+        let start_time = self.start_time.unwrap_or(now);
+        let first_byte_time = self.timing.first_byte_time.unwrap_or(now);
+        let wait = first_byte_time.saturating_duration_since(start_time);
+        let receive = now.saturating_duration_since(first_byte_time);
+        let total = self.timing.dns.unwrap_or_default()
+            + self.timing.connect.unwrap_or_default()
+            + wait
+            + receive;
+
+        let (version, status) = match app_proto {
+            ApplicationProto::H3 => ("HTTP/3", self.timing.status),
+            ApplicationProto::Perf => ("perf", 200),
+            _ => ("HTTP/0.9", 200),
+        };
+        let request_headers: Vec<serde_json::Value> = self
+            .headers
+            .iter()
+            .map(|h| {
+                serde_json::json!({
+                    "name": String::from_utf8_lossy(h.name()),
+                    "value": String::from_utf8_lossy(h.value()),
+                })
+            })
+            .collect();
+        let response_headers: Vec<serde_json::Value> = self
+            .timing
+            .headers
+            .iter()
+            .map(|(name, value)| serde_json::json!({"name": name, "value": value}))
+            .collect();
+        let mime_type = self
+            .timing
+            .headers
+            .iter()
+            .find(|(name, _)| name == "content-type")
+            .map_or("", |(_, value)| value.as_str());
+
+        let stats = conn.stats();
+        let mut transport = serde_json::json!({
+            "connection": conn.trace_id(),
+            "recvPackets": stats.recv_count,
+            "sentPackets": stats.sent_count,
+            "lostPackets": stats.lost_count,
+            "recvBytes": stats.recv_bytes,
+            "sentBytes": stats.sent_bytes,
+            "lostBytes": stats.lost_bytes,
+        });
+        if let Some(addr) = conn.paths_iter().next() {
+            if let Ok(path) = conn.get_path_stats(addr.local, addr.remote) {
+                transport["srtt"] = (path.srtt as f64 / 1000.0).into();
+                transport["minRtt"] = (path.min_rtt as f64 / 1000.0).into();
+                transport["cwnd"] = path.final_cwnd.into();
+            }
+        }
+
+        serde_json::json!({
+            "startedDateTime": tquic_tools::format_iso8601(
+                self.timing.sent_at.unwrap_or_else(SystemTime::now)
+            ),
+            "time": total.as_secs_f64() * 1000.0,
+            "request": {
+                "method": self.method,
+                "url": self.url.as_str(),
+                "httpVersion": version,
+                "cookies": [],
+                "headers": request_headers,
+                "queryString": [],
+                "headersSize": -1,
+                "bodySize": self.body.as_ref().map_or(0, |b| b.len()),
+            },
+            "response": {
+                "status": status,
+                "statusText": "",
+                "httpVersion": version,
+                "cookies": [],
+                "headers": response_headers,
+                "content": {
+                    "size": self.timing.body_size,
+                    "mimeType": mime_type,
+                },
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": self.timing.body_size,
+            },
+            "cache": {},
+            "timings": {
+                "blocked": -1,
+                "dns": ms(self.timing.dns),
+                "connect": ms(self.timing.connect),
+                "send": 0,
+                "wait": ms(Some(wait)),
+                "receive": ms(Some(receive)),
+                "ssl": -1,
+            },
+            "connection": conn.trace_id(),
+            "_transport": transport,
+        })
+    }
+
+    /// Process the response headers of a resumable download.
+    fn on_response_headers(&mut self, headers: &[Header]) {
+        let (path, state) = match (&self.response_path, &mut self.resume) {
+            (Some(path), Some(state)) => (path, state),
+            _ => return,
+        };
+
+        let mut status = None;
+        let mut validator = None;
+        for header in headers {
+            match header.name() {
+                b":status" => status = Some(header.value()),
+                b"etag" => validator = Some(header.value()),
+                b"last-modified" if validator.is_none() => validator = Some(header.value()),
+                _ => (),
+            }
+        }
+
+        match status {
+            // The server resumes the download from the given offset.
+            Some(b"206") if state.offset > 0 => {
+                self.response_writer = Self::make_response_writer(path, true);
+            }
+            // The file was downloaded completely.
+            Some(b"416") if state.offset > 0 => {
+                debug!("file {} was already downloaded", path);
+                state.finish();
+                return;
+            }
+            // The server sends the whole file.
+            Some(b"200") => {
+                self.response_writer = Self::make_response_writer(path, false);
+            }
+            _ => return,
+        }
+
+        if let Some(validator) = validator {
+            state.save(validator);
+        }
+    }
+
+    /// Process the end of the response.
+    fn on_response_finished(&mut self) {
+        if let Some(writer) = &mut self.response_writer {
+            _ = writer.flush();
+        }
+        if let Some(state) = &self.resume {
+            if self.response_writer.is_some() {
+                state.finish();
+            }
         }
     }
 }
@@ -944,6 +1634,15 @@ struct RequestSender {
 
     /// H3 connection, used in h3 mode.
     h3_conn: Option<Http3Connection>,
+
+    /// Request body, used in h3 mode.
+    body: Option<Bytes>,
+
+    /// Handshake time of the connection, reported with the first request.
+    handshake_time: Option<Duration>,
+
+    /// Creation time of the sender, which the URL delays are relative to.
+    start_time: Instant,
 }
 
 impl RequestSender {
@@ -952,6 +1651,7 @@ impl RequestSender {
         option: &ClientOpt,
         conn: &mut Connection,
         worker_ctx: Rc<RefCell<WorkerContext>>,
+        handshake_time: Option<Duration>,
     ) -> Self {
         let mut sender = Self {
             option: option.clone(),
@@ -965,8 +1665,15 @@ impl RequestSender {
             app_proto: ApplicationProto::from_slice(conn.application_proto()),
             next_stream_id: 0,
             h3_conn: None,
+            body: None,
+            handshake_time,
+            start_time: Instant::now(),
         };
 
+        if option.body_size > 0 {
+            sender.body = Some(Bytes::from(vec![0; option.body_size]));
+        }
+
         if sender.app_proto == ApplicationProto::H3 {
             sender.h3_conn = Some(
                 Http3Connection::new_with_quic_conn(conn, &Http3Config::new().unwrap()).unwrap(),
@@ -991,6 +1698,26 @@ impl RequestSender {
             && (self.option.max_requests_per_conn == 0
                 || self.request_sent < self.option.max_requests_per_conn)
         {
+            // Requests with delays are only deferred in the first round of URLs.
+            let now = Instant::now();
+            if let Some(attrs) = self.option.url_attrs.get(self.current_url_idx) {
+                let issue_time = self.start_time + attrs.delay;
+                if self.request_sent < self.option.urls.len() as u64 && issue_time > now {
+                    let mut worker_ctx = self.worker_ctx.borrow_mut();
+                    worker_ctx.next_issue_time = Some(
+                        worker_ctx
+                            .next_issue_time
+                            .map_or(issue_time, |t| t.min(issue_time)),
+                    );
+                    break;
+                }
+            }
+
+            if !self.worker_ctx.borrow_mut().acquire_request_quota(now) {
+                debug!("{} request rate limit reached", conn.trace_id());
+                break;
+            }
+
             if let Err(e) = self.send_request(conn) {
                 error!("{} send request error {}", conn.trace_id(), e);
                 break;
@@ -1008,7 +1735,7 @@ impl RequestSender {
         _ = conn.stream_want_read(stream_id, true);
 
         match self.app_proto {
-            ApplicationProto::Interop | ApplicationProto::Http09 => {
+            ApplicationProto::Interop | ApplicationProto::Http09 | ApplicationProto::Perf => {
                 self.recv_http09_responses(conn, stream_id)
             }
             ApplicationProto::H3 => self.recv_h3_responses(conn, stream_id),
@@ -1017,7 +1744,17 @@ impl RequestSender {
 
     fn send_request(&mut self, conn: &mut Connection) -> Result<()> {
         let url = &self.option.urls[self.current_url_idx];
-        let mut request = Request::new("GET", url, &None, &self.option.dump_dir);
+        let (method, body) = match &self.body {
+            Some(body) if self.app_proto == ApplicationProto::H3 => ("POST", Some(body.clone())),
+            _ => ("GET", None),
+        };
+        let resume = self.option.resume && self.app_proto == ApplicationProto::H3;
+        let mut request = Request::new(method, url, body, &self.option.dump_dir, resume);
+        request.priority = self
+            .option
+            .url_attrs
+            .get(self.current_url_idx)
+            .and_then(|attrs| attrs.priority);
         debug!(
             "{} send request {} current index {}",
             conn.trace_id(),
@@ -1029,10 +1766,18 @@ impl RequestSender {
             ApplicationProto::Interop | ApplicationProto::Http09 => {
                 self.send_http09_request(conn, &request)?
             }
-            ApplicationProto::H3 => self.send_h3_request(conn, &request)?,
+            ApplicationProto::H3 => self.send_h3_request(conn, &mut request)?,
+            ApplicationProto::Perf => self.send_perf_request(conn, &mut request)?,
         };
 
         request.start_time = Some(Instant::now());
+        if self.option.har_file.is_some() {
+            request.timing.sent_at = Some(SystemTime::now());
+            request.timing.connect = self.handshake_time.take();
+            if request.timing.connect.is_some() {
+                request.timing.dns = self.option.dns_time;
+            }
+        }
         self.streams.insert(s, request);
         self.current_url_idx += 1;
         if self.current_url_idx == self.option.urls.len() {
@@ -1067,8 +1812,23 @@ impl RequestSender {
         Ok(s)
     }
 
-    fn send_h3_request(&mut self, conn: &mut Connection, request: &Request) -> Result<u64> {
-        let s = match self.h3_conn.as_mut().unwrap().stream_new(conn) {
+    fn send_h3_request(&mut self, conn: &mut Connection, request: &mut Request) -> Result<u64> {
+        let h3_conn = self.h3_conn.as_mut().unwrap();
+        let s = match request.priority {
+            Some((urgency, incremental)) => {
+                // Signal the priority to the server with the Priority header.
+                let mut value = format!("u={}", urgency);
+                if incremental {
+                    value.push_str(", i");
+                }
+                request
+                    .headers
+                    .push(tquic::h3::Header::new(b"priority", value.as_bytes()));
+                h3_conn.stream_new_with_priority(conn, &Http3Priority::new(urgency, incremental))
+            }
+            None => h3_conn.stream_new(conn),
+        };
+        let s = match s {
             Ok(v) => v,
             Err(tquic::h3::Http3Error::TransportError(Error::StreamLimitError)) => {
                 return Err("stream limit reached".to_string().into());
@@ -1080,12 +1840,12 @@ impl RequestSender {
             }
         };
 
-        match self
-            .h3_conn
-            .as_mut()
-            .unwrap()
-            .send_headers(conn, s, &request.headers, true)
-        {
+        match self.h3_conn.as_mut().unwrap().send_headers(
+            conn,
+            s,
+            &request.headers,
+            request.body.is_none(),
+        ) {
             Ok(v) => v,
             Err(tquic::h3::Http3Error::StreamBlocked) => {
                 return Err("stream is blocked".to_string().into());
@@ -1097,9 +1857,126 @@ impl RequestSender {
             }
         };
 
+        Self::send_h3_body(
+            self.h3_conn.as_mut().unwrap(),
+            conn,
+            s,
+            request,
+            &self.worker_ctx,
+        )?;
+
         Ok(s)
     }
 
+    /// Send the perf request, which consists of the number of bytes requested
+    /// from the server and the data uploaded to the server.
+    /// See draft-banks-quic-performance Section 2.3
+    fn send_perf_request(&mut self, conn: &mut Connection, request: &mut Request) -> Result<u64> {
+        let size: u64 = match request.url.path().trim_start_matches('/').parse() {
+            Ok(v) => v,
+            Err(_) => {
+                return Err(format!("invalid perf request size {:?}", request.url).into());
+            }
+        };
+        let mut data = size.to_be_bytes().to_vec();
+        data.resize(data.len() + self.option.body_size, 0);
+        request.body = Some(Bytes::from(data));
+
+        let s = self.next_stream_id;
+        Self::send_perf_body(conn, s, request, &self.worker_ctx)?;
+        self.next_stream_id += 4;
+        Ok(s)
+    }
+
+    /// Continue sending the request body when the stream becomes writable.
+    pub fn send_request_body(&mut self, conn: &mut Connection, stream_id: u64) {
+        let request = match self.streams.get_mut(&stream_id) {
+            Some(request) => request,
+            None => return,
+        };
+
+        let ret = match (self.app_proto, self.h3_conn.as_mut()) {
+            (ApplicationProto::H3, Some(h3_conn)) => {
+                Self::send_h3_body(h3_conn, conn, stream_id, request, &self.worker_ctx)
+            }
+            (ApplicationProto::Perf, _) => {
+                Self::send_perf_body(conn, stream_id, request, &self.worker_ctx)
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = ret {
+            error!("{} send request body error {}", conn.trace_id(), e);
+        }
+    }
+
+    fn send_perf_body(
+        conn: &mut Connection,
+        stream_id: u64,
+        request: &mut Request,
+        worker_ctx: &Rc<RefCell<WorkerContext>>,
+    ) -> Result<()> {
+        let body = match &request.body {
+            Some(body) if request.body_written < body.len() => body.slice(request.body_written..),
+            _ => return Ok(()),
+        };
+        let body_len = body.len();
+
+        let written = match conn.stream_write(stream_id, body, true) {
+            Ok(v) => v,
+            Err(tquic::error::Error::Done) => 0,
+            Err(tquic::error::Error::StreamLimitError) => {
+                return Err("stream limit reached".to_string().into());
+            }
+            Err(e) => {
+                return Err(format!(
+                    "failed to send request body {:?}, error: {:?}",
+                    request.url, e
+                )
+                .into());
+            }
+        };
+        request.body_written += written;
+        worker_ctx.borrow_mut().request_body_bytes += written as u64;
+
+        if written < body_len {
+            _ = conn.stream_want_write(stream_id, true);
+        }
+        Ok(())
+    }
+
+    fn send_h3_body(
+        h3_conn: &mut Http3Connection,
+        conn: &mut Connection,
+        stream_id: u64,
+        request: &mut Request,
+        worker_ctx: &Rc<RefCell<WorkerContext>>,
+    ) -> Result<()> {
+        let body = match &request.body {
+            Some(body) if request.body_written < body.len() => body.slice(request.body_written..),
+            _ => return Ok(()),
+        };
+        let body_len = body.len();
+
+        let written = match h3_conn.send_body(conn, stream_id, body, true) {
+            Ok(v) => v,
+            Err(tquic::h3::Http3Error::Done) | Err(tquic::h3::Http3Error::NoError) => 0,
+            Err(e) => {
+                return Err(format!(
+                    "failed to send request body {:?}, error: {:?}",
+                    request.url, e
+                )
+                .into());
+            }
+        };
+        request.body_written += written;
+        worker_ctx.borrow_mut().request_body_bytes += written as u64;
+
+        if written < body_len {
+            _ = conn.stream_want_write(stream_id, true);
+        }
+        Ok(())
+    }
+
     fn sample_request_time(request: &Request, worker_ctx: &mut RefMut<WorkerContext>) {
         if let Some(start_time) = request.start_time {
             let request_time = Instant::now() - start_time;
@@ -1150,7 +2027,9 @@ impl RequestSender {
                 fin
             );
 
+            worker_ctx.response_bytes += read as u64;
             let request = self.streams.get_mut(&stream_id).unwrap();
+            request.record_response_data(read);
             if let Some(writer) = &mut request.response_writer {
                 _ = writer.write_all(&self.buf[..read]);
             }
@@ -1170,7 +2049,12 @@ impl RequestSender {
                 self.concurrent_requests -= 1;
                 worker_ctx.request_success += 1;
                 worker_ctx.request_done += 1;
+                worker_ctx.conn_consecutive_failed = 0;
                 Self::sample_request_time(request, &mut worker_ctx);
+                if self.option.har_file.is_some() {
+                    let entry = request.har_entry(conn, self.app_proto);
+                    worker_ctx.har_entries.push(entry);
+                }
                 self.streams.remove(&stream_id);
             }
         }
@@ -1188,6 +2072,12 @@ impl RequestSender {
                         headers,
                         stream_id
                     );
+                    if let Some(request) = self.streams.get_mut(&stream_id) {
+                        request.on_response_headers(&headers);
+                        if self.option.har_file.is_some() {
+                            request.record_response_headers(&headers);
+                        }
+                    }
                     if self.option.print_res {
                         Self::print_headers(&headers);
                     }
@@ -1201,7 +2091,9 @@ impl RequestSender {
                             stream_id
                         );
 
+                        worker_ctx.response_bytes += read as u64;
                         let request = self.streams.get_mut(&stream_id).unwrap();
+                        request.record_response_data(read);
                         if let Some(writer) = &mut request.response_writer {
                             _ = writer.write_all(&self.buf[..read]);
                         }
@@ -1222,8 +2114,14 @@ impl RequestSender {
                     self.concurrent_requests -= 1;
                     worker_ctx.request_success += 1;
                     worker_ctx.request_done += 1;
+                    worker_ctx.conn_consecutive_failed = 0;
                     let request = self.streams.get_mut(&stream_id).unwrap();
+                    request.on_response_finished();
                     Self::sample_request_time(request, &mut worker_ctx);
+                    if self.option.har_file.is_some() {
+                        let entry = request.har_entry(conn, self.app_proto);
+                        worker_ctx.har_entries.push(entry);
+                    }
                     self.streams.remove(&stream_id);
                 }
                 Ok((stream_id, tquic::h3::Http3Event::Reset(e))) => {
@@ -1284,6 +2182,9 @@ struct WorkerHandler {
 
     /// Local address list
     local_addresses: Vec<SocketAddr>,
+
+    /// Mapping connection index to creation time, used for handshake timing.
+    conn_start_times: FxHashMap<u64, Instant>,
 }
 
 impl WorkerHandler {
@@ -1299,6 +2200,7 @@ impl WorkerHandler {
             senders,
             remote: option.connect_to.unwrap(),
             local_addresses: local_addresses.to_owned(),
+            conn_start_times: FxHashMap::default(),
         }
     }
 
@@ -1310,7 +2212,9 @@ impl WorkerHandler {
             return;
         }
 
-        let sender = RequestSender::new(&self.option, conn, self.worker_ctx.clone());
+        let handshake_time = self.conn_start_times.remove(&index).map(|t| t.elapsed());
+        let sender =
+            RequestSender::new(&self.option, conn, self.worker_ctx.clone(), handshake_time);
         senders.insert(index, sender);
     }
 
@@ -1351,6 +2255,9 @@ impl WorkerHandler {
 impl TransportHandler for WorkerHandler {
     fn on_conn_created(&mut self, conn: &mut Connection) {
         debug!("{} connection is created", conn.trace_id());
+        if let Some(index) = conn.index() {
+            self.conn_start_times.insert(index, Instant::now());
+        }
 
         if let Some(keylog_file) = &self.option.keylog_file {
             if let Ok(file) = std::fs::OpenOptions::new()
@@ -1425,9 +2332,20 @@ impl TransportHandler for WorkerHandler {
 
     fn on_conn_closed(&mut self, conn: &mut Connection) {
         debug!("{} connection is closed", conn.trace_id());
+        if let Some(index) = conn.index() {
+            self.conn_start_times.remove(&index);
+        }
 
         let mut worker_ctx = self.worker_ctx.borrow_mut();
         update_conn_stats(&mut worker_ctx.conn_stats, conn.stats());
+        if self.option.enable_multipath {
+            for addr in conn.paths_iter() {
+                if let Ok(stats) = conn.get_path_stats(addr.local, addr.remote) {
+                    let summary = worker_ctx.path_stats.entry(addr.local.ip()).or_default();
+                    summary.update(stats);
+                }
+            }
+        }
 
         let mut senders = self.senders.borrow_mut();
         senders.remove(&conn.index().unwrap());
@@ -1437,8 +2355,8 @@ impl TransportHandler for WorkerHandler {
                 "{} session resumption enabled, save session to context",
                 conn.trace_id()
             );
-            if let Some(session) = conn.session() {
-                worker_ctx.session = Some(session.to_vec());
+            if let Some(session) = conn.session_with_bdp() {
+                worker_ctx.session = Some(session);
             }
         }
         worker_ctx.conn_finish += 1;
@@ -1465,6 +2383,7 @@ impl TransportHandler for WorkerHandler {
             conn.is_handshake_timeout()
         );
         worker_ctx.conn_finish_failed += 1;
+        worker_ctx.conn_consecutive_failed += 1;
         worker_ctx.concurrent_conns -= 1;
     }
 
@@ -1487,6 +2406,12 @@ impl TransportHandler for WorkerHandler {
 
     fn on_stream_writable(&mut self, conn: &mut Connection, stream_id: u64) {
         _ = conn.stream_want_write(stream_id, false);
+
+        let index = conn.index().unwrap();
+        let mut senders = self.senders.borrow_mut();
+        if let Some(s) = senders.get_mut(&index) {
+            s.send_request_body(conn, stream_id);
+        }
     }
 
     fn on_stream_closed(&mut self, conn: &mut Connection, stream_id: u64) {
@@ -1497,6 +2422,33 @@ impl TransportHandler for WorkerHandler {
     }
 
     fn on_new_token(&mut self, _conn: &mut Connection, _token: Vec<u8>) {}
+
+    fn on_ping_acked(&mut self, _conn: &mut Connection, _tag: u64, _rtt: Duration) {}
+
+    fn on_flow_control_blocked(&mut self, conn: &mut Connection, event: BlockedEvent) {
+        debug!("{} flow control blocked: {:?}", conn.trace_id(), event);
+    }
+
+    fn on_target_bitrate_updated(&mut self, conn: &mut Connection, bitrate: u64) {
+        debug!("{} target bitrate updated: {}", conn.trace_id(), bitrate);
+    }
+
+    fn on_persistent_congestion(
+        &mut self,
+        conn: &mut Connection,
+        event: PersistentCongestionEvent,
+    ) {
+        warn!("{} persistent congestion: {:?}", conn.trace_id(), event);
+    }
+
+    fn on_stream_event(&mut self, conn: &mut Connection, stream_id: u64, event: StreamEvent) {
+        debug!(
+            "{} stream {} event: {:?}",
+            conn.trace_id(),
+            stream_id,
+            event
+        );
+    }
 }
 
 fn process_connect_address(option: &mut ClientOpt) {
@@ -1515,6 +2467,32 @@ fn process_connect_address(option: &mut ClientOpt) {
 fn parse_option() -> std::result::Result<ClientOpt, clap::error::Error> {
     let mut option = ClientOpt::parse();
 
+    if let Some(url_file) = &option.url_file {
+        let content = std::fs::read_to_string(url_file).map_err(|e| {
+            ClientOpt::command().error(
+                ErrorKind::Io,
+                format!("Failed to read url file {}: {}", url_file, e),
+            )
+        })?;
+
+        option.url_attrs = vec![UrlAttrs::default(); option.urls.len()];
+        for (i, line) in content.lines().enumerate() {
+            match UrlAttrs::parse(line) {
+                Ok(Some((url, attrs))) => {
+                    option.urls.push(url);
+                    option.url_attrs.push(attrs);
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    return Err(ClientOpt::command().error(
+                        ErrorKind::InvalidValue,
+                        format!("Invalid url file {} at line {}: {}", url_file, i + 1, e),
+                    ));
+                }
+            }
+        }
+    }
+
     if option.urls.is_empty() {
         return Err(ClientOpt::command().error(
             ErrorKind::MissingRequiredArgument,
@@ -1555,7 +2533,24 @@ fn process_option(option: &mut ClientOpt) -> Result<()> {
         }
     }
 
+    let start = Instant::now();
+    let resolved = option.connect_to.is_none();
     process_connect_address(option);
+    if resolved {
+        option.dns_time = Some(start.elapsed());
+    }
+
+    // Resolve local interfaces with the same address family as the server.
+    let is_ipv4 = option.connect_to.unwrap().is_ipv4();
+    for name in &option.local_interfaces {
+        match tquic_tools::interface_addresses(name)?
+            .into_iter()
+            .find(|ip| ip.is_ipv4() == is_ipv4)
+        {
+            Some(ip) => option.local_addresses.push(ip),
+            None => return Err(format!("no address found on interface {}", name).into()),
+        }
+    }
     Ok(())
 }
 
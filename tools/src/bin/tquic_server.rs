@@ -13,36 +13,60 @@
 // limitations under the License.
 
 //! An QUIC server based on the high level endpoint API.
+//!
+//! The server serves files from the document root, or acts as a reverse proxy
+//! which forwards h3 requests to an HTTP/1.1 upstream server over TCP. The
+//! perf protocol is also supported for throughput benchmarking.
 
+use std::cell::RefCell;
+use std::cmp;
 use std::collections::HashMap;
 use std::fs::create_dir_all;
 use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::path;
 use std::path::Path;
 use std::rc::Rc;
+use std::time::Duration;
 use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use bytes::Bytes;
 use clap::Parser;
 use log::*;
 use mio::event::Event;
+use mio::net::TcpStream;
+use mio::Interest;
+use mio::Registry;
+use mio::Token;
 use rustc_hash::FxHashMap;
+use slab::Slab;
 
 use tquic::h3::connection::Http3Connection;
 use tquic::h3::Header;
 use tquic::h3::Http3Config;
 use tquic::h3::NameValue;
+use tquic::BlockedEvent;
 use tquic::Config;
 use tquic::CongestionControlAlgorithm;
 use tquic::Connection;
+use tquic::Ecn;
 use tquic::Endpoint;
 use tquic::Error;
 use tquic::MultipathAlgorithm;
 use tquic::PacketInfo;
+use tquic::PersistentCongestionEvent;
+use tquic::StreamEvent;
 use tquic::TlsConfig;
 use tquic::TransportHandler;
 use tquic_tools::ApplicationProto;
+use tquic_tools::NetEmulation;
 use tquic_tools::QuicSocket;
 use tquic_tools::Result;
 
@@ -74,6 +98,11 @@ pub struct ServerOpt {
     #[clap(short, long, default_value = "./", value_name = "DIR")]
     pub root: String,
 
+    /// Forward h3 requests to the given HTTP/1.1 upstream server, instead of
+    /// serving files from the document root.
+    #[clap(long, value_name = "ADDR")]
+    pub upstream: Option<SocketAddr>,
+
     /// Session ticket key.
     #[clap(
         short,
@@ -248,10 +277,61 @@ pub struct ServerOpt {
     /// Disable encryption on 1-RTT packets.
     #[clap(long, help_heading = "Misc")]
     pub disable_encryption: bool,
+
+    /// Emulate loss of outgoing packets with the given percentage.
+    #[clap(
+        long,
+        default_value = "0",
+        value_name = "PERCENT",
+        help_heading = "Misc"
+    )]
+    pub emulate_loss: f64,
+
+    /// Emulate delay of outgoing packets in milliseconds.
+    #[clap(long, default_value = "0", value_name = "TIME", help_heading = "Misc")]
+    pub emulate_delay: u64,
+
+    /// Emulate reordering by sending the given percentage of outgoing packets
+    /// without the emulated delay. It takes effect only if the delay is set.
+    #[clap(
+        long,
+        default_value = "0",
+        value_name = "PERCENT",
+        help_heading = "Misc"
+    )]
+    pub emulate_reorder: f64,
+
+    /// Maximum number of concurrent connections. "0" means unlimited.
+    #[clap(long, default_value = "0", value_name = "NUM", help_heading = "Limits")]
+    pub max_conns: usize,
+
+    /// Maximum number of concurrent connections per client IP. "0" means unlimited.
+    #[clap(long, default_value = "0", value_name = "NUM", help_heading = "Limits")]
+    pub max_conns_per_ip: usize,
+
+    /// Maximum number of new connections per second per client IP. "0" means unlimited.
+    #[clap(long, default_value = "0", value_name = "NUM", help_heading = "Limits")]
+    pub max_new_conns_per_ip: u64,
+
+    /// Maximum sending rate of each connection path in bytes per second.
+    /// "0" means unlimited.
+    #[clap(
+        long,
+        default_value = "0",
+        value_name = "RATE",
+        help_heading = "Limits"
+    )]
+    pub max_pacing_rate: u64,
 }
 
 const MAX_BUF_SIZE: usize = 65536;
 
+/// HTTP/3 error code H3_INTERNAL_ERROR.
+const H3_INTERNAL_ERROR: u64 = 0x102;
+
+/// QUIC transport error code CONNECTION_REFUSED.
+const CONNECTION_REFUSED: u64 = 0x2;
+
 /// An HTTP file Server which support HTTP/3 and HTTP/0.9 over QUIC.
 struct Server {
     /// QUIC endpoint
@@ -265,6 +345,9 @@ struct Server {
 
     /// Packet read buffer
     recv_buf: Vec<u8>,
+
+    /// Reverse proxy
+    proxy: Option<Rc<RefCell<Proxy>>>,
 }
 
 impl Server {
@@ -291,13 +374,19 @@ impl Server {
         config.set_multipath_algorithm(option.multipath_algor);
         config.set_active_connection_id_limit(option.active_cid_limit);
         config.enable_encryption(!option.disable_encryption);
+        config.set_max_pacing_rate(option.max_pacing_rate);
 
         if let Some(address_token_key) = &option.address_token_key {
             let address_token_key = convert_address_token_key(address_token_key);
             config.set_address_token_key(vec![address_token_key])?;
         }
 
-        let application_protos = vec![b"h3".to_vec(), b"http/0.9".to_vec(), b"hq-interop".to_vec()];
+        let application_protos = vec![
+            b"h3".to_vec(),
+            b"http/0.9".to_vec(),
+            b"hq-interop".to_vec(),
+            b"perf".to_vec(),
+        ];
         let mut tls_config = TlsConfig::new_server_config(
             &option.cert_file,
             &option.key_file,
@@ -312,17 +401,46 @@ impl Server {
         let poll = mio::Poll::new()?;
         let registry = poll.registry();
 
-        let handlers = ServerHandler::new(option)?;
-        let sock = Rc::new(QuicSocket::new(&option.listen, registry)?);
+        let proxy = match option.upstream {
+            Some(upstream) => Some(Rc::new(RefCell::new(Proxy::new(
+                upstream,
+                registry.try_clone()?,
+            )))),
+            None => None,
+        };
+
+        let handlers = ServerHandler::new(option, proxy.clone())?;
+        let mut sock = QuicSocket::new(&option.listen, registry)?;
+        sock.set_emulation(NetEmulation::new(
+            option.emulate_loss,
+            option.emulate_delay,
+            option.emulate_reorder,
+        ));
+        let sock = Rc::new(sock);
 
         Ok(Server {
             endpoint: Endpoint::new(Box::new(config), true, Box::new(handlers), sock.clone()),
             poll,
             sock,
             recv_buf: vec![0u8; MAX_BUF_SIZE],
+            proxy,
         })
     }
 
+    fn process_upstream_event(&mut self, event: &Event) {
+        let proxy = match &self.proxy {
+            Some(proxy) => proxy,
+            None => return,
+        };
+
+        // Wake up the request stream to forward data.
+        if let Some((index, stream_id)) = proxy.borrow_mut().on_event(event) {
+            if let Some(conn) = self.endpoint.conn_get_mut(index) {
+                _ = conn.stream_want_write(stream_id, true);
+            }
+        }
+    }
+
     fn process_read_event(&mut self, event: &Event) -> Result<()> {
         loop {
             // Read datagram from the socket.
@@ -345,6 +463,9 @@ impl Server {
                 src: remote,
                 dst: local,
                 time: Instant::now(),
+                ecn: Ecn::NotEct,
+                dscp: 0,
+                segment_size: 0,
             };
 
             // Process the incoming packet.
@@ -370,10 +491,713 @@ fn convert_address_token_key(key: &str) -> [u8; 16] {
     token_key
 }
 
+/// Size of the chunk read from the file each time.
+const FILE_CHUNK_SIZE: usize = 65536;
+
+/// Chunk of zeros used as the response body in perf mode.
+static PERF_CHUNK: [u8; FILE_CHUNK_SIZE] = [0; FILE_CHUNK_SIZE];
+
+/// Length of the perf request header, which is the number of bytes requested
+/// from the server encoded as a big-endian 64-bit integer.
+const PERF_HEADER_LEN: usize = 8;
+
 struct Response {
+    /// Response headers, only used in h3 mode.
     headers: Option<Vec<tquic::h3::Header>>,
+
+    /// Buffered body data to be written.
     body: Bytes,
+
+    /// Number of bytes of the buffered body already written.
     body_written: usize,
+
+    /// File from which the remaining body is read.
+    file: Option<File>,
+
+    /// Number of bytes remaining to be read from the file, or to be generated
+    /// if there is no file.
+    file_remaining: u64,
+}
+
+impl Response {
+    /// Create a response with an in-memory body.
+    fn new(headers: Option<Vec<Header>>, body: Bytes) -> Self {
+        Self {
+            headers,
+            body,
+            body_written: 0,
+            file: None,
+            file_remaining: 0,
+        }
+    }
+
+    /// Create a response with a body streamed from the file.
+    fn with_file(headers: Option<Vec<Header>>, file: File, len: u64) -> Self {
+        Self {
+            headers,
+            body: Bytes::new(),
+            body_written: 0,
+            file: Some(file),
+            file_remaining: len,
+        }
+    }
+
+    /// Create a response with a body of zeros, used in perf mode.
+    fn with_zeros(len: u64) -> Self {
+        Self {
+            headers: None,
+            body: Bytes::new(),
+            body_written: 0,
+            file: None,
+            file_remaining: len,
+        }
+    }
+
+    /// Read the next chunk from the file if all buffered data has been written.
+    fn refill(&mut self) -> std::io::Result<()> {
+        if self.body_written < self.body.len() || self.file_remaining == 0 {
+            return Ok(());
+        }
+
+        let len = cmp::min(self.file_remaining, FILE_CHUNK_SIZE as u64) as usize;
+        self.body = match self.file.as_mut() {
+            Some(file) => {
+                let mut buf = vec![0; len];
+                file.read_exact(&mut buf)?;
+                Bytes::from(buf)
+            }
+            None => Bytes::from_static(&PERF_CHUNK[..len]),
+        };
+        self.file_remaining -= len as u64;
+        self.body_written = 0;
+        Ok(())
+    }
+
+    /// Return the unwritten body data and whether it is the end of the body.
+    fn pending(&self) -> (Bytes, bool) {
+        (
+            self.body.slice(self.body_written..),
+            self.file_remaining == 0,
+        )
+    }
+
+    /// Check whether the whole body has been written.
+    fn is_finished(&self) -> bool {
+        self.body_written == self.body.len() && self.file_remaining == 0
+    }
+}
+
+/// A byte range requested by the client.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ByteRange {
+    /// The whole representation is requested.
+    Full,
+
+    /// The byte range with the first and the last byte position.
+    Partial(u64, u64),
+
+    /// The range is not satisfiable.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Parse the value of Range header for a representation of `len` bytes.
+    /// Only a single range is supported, and the header is ignored otherwise.
+    /// See RFC 9110 Section 14.1.2
+    fn parse(value: &str, len: u64) -> Self {
+        let spec = match value.trim().strip_prefix("bytes=") {
+            Some(spec) => spec.trim(),
+            None => return ByteRange::Full,
+        };
+        if spec.contains(',') {
+            return ByteRange::Full;
+        }
+        let (first, last) = match spec.split_once('-') {
+            Some((first, last)) => (first.trim(), last.trim()),
+            None => return ByteRange::Full,
+        };
+
+        // The suffix range, e.g. "bytes=-500".
+        if first.is_empty() {
+            let suffix = match last.parse::<u64>() {
+                Ok(v) => v,
+                Err(_) => return ByteRange::Full,
+            };
+            if suffix == 0 || len == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            return ByteRange::Partial(len - cmp::min(suffix, len), len - 1);
+        }
+
+        let first = match first.parse::<u64>() {
+            Ok(v) => v,
+            Err(_) => return ByteRange::Full,
+        };
+        let last = match last {
+            "" => u64::MAX,
+            _ => match last.parse::<u64>() {
+                Ok(v) => v,
+                Err(_) => return ByteRange::Full,
+            },
+        };
+        if last < first {
+            return ByteRange::Full;
+        }
+        if first >= len {
+            return ByteRange::Unsatisfiable;
+        }
+        ByteRange::Partial(first, cmp::min(last, len - 1))
+    }
+}
+
+/// Request headers used for serving files.
+#[derive(Default)]
+struct FileRequest<'a> {
+    path: &'a str,
+    range: Option<&'a str>,
+    if_range: Option<&'a str>,
+    if_none_match: Option<&'a str>,
+    if_modified_since: Option<&'a str>,
+}
+
+impl<'a> FileRequest<'a> {
+    fn from_headers(headers: &'a [Header]) -> Self {
+        let mut request = FileRequest::default();
+        for header in headers {
+            let value = match std::str::from_utf8(header.value()) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            match header.name() {
+                b":path" => request.path = value,
+                b"range" => request.range = Some(value),
+                b"if-range" => request.if_range = Some(value),
+                b"if-none-match" => request.if_none_match = Some(value),
+                b"if-modified-since" => request.if_modified_since = Some(value),
+                _ => (),
+            }
+        }
+        request
+    }
+}
+
+/// Validators of a file used for conditional requests.
+/// See RFC 9110 Section 8.8
+struct FileValidators {
+    /// Entity tag of the file, which is derived from the modification time and
+    /// the length of the file.
+    etag: String,
+
+    /// Modification time of the file.
+    last_modified: Option<SystemTime>,
+}
+
+impl FileValidators {
+    fn new(metadata: &std::fs::Metadata) -> Self {
+        let last_modified = metadata.modified().ok();
+        let mtime = last_modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+
+        Self {
+            etag: format!("\"{:x}-{:x}\"", mtime, metadata.len()),
+            last_modified,
+        }
+    }
+
+    /// Check whether the If-None-Match header matches the file, using the weak
+    /// comparison. See RFC 9110 Section 13.1.2
+    fn none_match(&self, value: &str) -> bool {
+        let etag = self.etag.trim_start_matches("W/");
+        value
+            .split(',')
+            .map(|v| v.trim())
+            .any(|v| v == "*" || v.trim_start_matches("W/") == etag)
+    }
+
+    /// Check whether the file is not modified since the given HTTP date.
+    fn not_modified_since(&self, value: &str) -> bool {
+        match (self.last_modified, tquic_tools::parse_http_date(value)) {
+            (Some(t), Some(since)) => tquic_tools::unix_secs(t) <= tquic_tools::unix_secs(since),
+            _ => false,
+        }
+    }
+
+    /// Check whether the If-Range header matches the file, using the strong
+    /// comparison for entity tags. See RFC 9110 Section 13.1.5
+    fn range_match(&self, value: &str) -> bool {
+        let value = value.trim();
+        if value.starts_with('"') {
+            return value == self.etag;
+        }
+        match (self.last_modified, tquic_tools::parse_http_date(value)) {
+            (Some(t), Some(date)) => tquic_tools::unix_secs(t) == tquic_tools::unix_secs(date),
+            _ => false,
+        }
+    }
+
+    /// Append the validator headers.
+    fn append_headers(&self, headers: &mut Vec<Header>) {
+        headers.push(Header::new(b"etag", self.etag.as_bytes()));
+        if let Some(t) = self.last_modified {
+            headers.push(Header::new(
+                b"last-modified",
+                tquic_tools::format_http_date(t).as_bytes(),
+            ));
+        }
+    }
+}
+
+/// Token offset of the upstream connections, which distinguishes them from
+/// the UDP sockets.
+const UPSTREAM_TOKEN_BASE: usize = 1 << 20;
+
+/// Limit of the data buffered in each direction of an upstream connection.
+const UPSTREAM_BUF_LIMIT: usize = 256 * 1024;
+
+/// Hop-by-hop headers which are not forwarded. See RFC 9110 Section 7.6.1
+const HOP_BY_HOP_HEADERS: [&[u8]; 7] = [
+    b"connection",
+    b"keep-alive",
+    b"proxy-connection",
+    b"transfer-encoding",
+    b"upgrade",
+    b"te",
+    b"trailer",
+];
+
+/// Framing of the HTTP/1.1 response body. See RFC 9112 Section 6.3
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BodyFraming {
+    /// The body is completely received.
+    Done,
+
+    /// The body with the given number of remaining bytes.
+    Length(u64),
+
+    /// The body is delimited by closing the connection.
+    Close,
+
+    /// The chunked body.
+    Chunked(ChunkState),
+}
+
+/// Decoding state of the chunked body. See RFC 9112 Section 7.1
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChunkState {
+    /// Waiting for the chunk size line.
+    Size,
+
+    /// The chunk data with the given number of remaining bytes.
+    Data(u64),
+
+    /// Waiting for the CRLF after the chunk data.
+    DataEnd,
+
+    /// Waiting for the end of the trailer section.
+    Trailers,
+}
+
+impl BodyFraming {
+    /// Decode the body data in `buf` and append it to `out`. Return the number
+    /// of bytes consumed.
+    fn decode(&mut self, buf: &[u8], out: &mut Vec<u8>) -> Result<usize> {
+        let mut pos = 0;
+        loop {
+            let remaining = &buf[pos..];
+            match *self {
+                BodyFraming::Done => return Ok(buf.len()),
+                BodyFraming::Close => {
+                    out.extend_from_slice(remaining);
+                    return Ok(buf.len());
+                }
+                BodyFraming::Length(n) | BodyFraming::Chunked(ChunkState::Data(n)) => {
+                    let len = cmp::min(n, remaining.len() as u64) as usize;
+                    out.extend_from_slice(&remaining[..len]);
+                    pos += len;
+                    *self = match (*self, n - len as u64) {
+                        (BodyFraming::Length(_), 0) => BodyFraming::Done,
+                        (BodyFraming::Length(_), n) => BodyFraming::Length(n),
+                        (_, 0) => BodyFraming::Chunked(ChunkState::DataEnd),
+                        (_, n) => BodyFraming::Chunked(ChunkState::Data(n)),
+                    };
+                    if len == 0 {
+                        return Ok(pos);
+                    }
+                }
+                BodyFraming::Chunked(state) => {
+                    let line_len = match remaining.windows(2).position(|w| w == b"\r\n") {
+                        Some(v) => v,
+                        None => return Ok(pos),
+                    };
+                    let line = &remaining[..line_len];
+                    pos += line_len + 2;
+
+                    *self = match state {
+                        ChunkState::Size => {
+                            // Chunk extensions are ignored.
+                            let size = std::str::from_utf8(line)?;
+                            let size = size.split(';').next().unwrap_or("").trim();
+                            match u64::from_str_radix(size, 16)? {
+                                0 => BodyFraming::Chunked(ChunkState::Trailers),
+                                n => BodyFraming::Chunked(ChunkState::Data(n)),
+                            }
+                        }
+                        ChunkState::DataEnd if line.is_empty() => {
+                            BodyFraming::Chunked(ChunkState::Size)
+                        }
+                        ChunkState::DataEnd => return Err("invalid chunk".into()),
+                        // Trailer fields are discarded.
+                        ChunkState::Trailers if line.is_empty() => BodyFraming::Done,
+                        ChunkState::Trailers => BodyFraming::Chunked(ChunkState::Trailers),
+                        ChunkState::Data(_) => unreachable!(),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// An HTTP/1.1 connection to the upstream server, which carries a request
+/// forwarded from an h3 request stream.
+struct Upstream {
+    /// Index of the QUIC connection.
+    conn_index: u64,
+
+    /// Id of the h3 request stream.
+    stream_id: u64,
+
+    /// TCP connection to the upstream server.
+    tcp: TcpStream,
+
+    /// Whether the TCP connection is established.
+    connected: bool,
+
+    /// Request data to be written to the upstream server.
+    send_buf: Vec<u8>,
+
+    /// Whether the request body is sent with the chunked transfer coding.
+    chunked: bool,
+
+    /// Whether the request is a HEAD request.
+    head: bool,
+
+    /// Response data read from the upstream server but not processed yet.
+    recv_buf: Vec<u8>,
+
+    /// Whether the upstream server has closed the connection.
+    eof: bool,
+
+    /// Response headers converted to h3 headers.
+    headers: Option<Vec<Header>>,
+
+    /// Whether the response headers have been sent to the client.
+    headers_sent: bool,
+
+    /// Framing of the response body, available once the headers are parsed.
+    framing: Option<BodyFraming>,
+
+    /// Decoded response body to be sent to the client.
+    body: Vec<u8>,
+}
+
+impl Upstream {
+    /// Write the buffered request data to the upstream server.
+    fn flush(&mut self) -> Result<()> {
+        while self.connected && !self.send_buf.is_empty() {
+            match self.tcp.write(&self.send_buf) {
+                Ok(written) => {
+                    self.send_buf.drain(..written);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the response data from the upstream server.
+    fn fill(&mut self) -> Result<()> {
+        let mut buf = [0; 16384];
+        while self.connected
+            && !self.eof
+            && self.recv_buf.len() + self.body.len() < UPSTREAM_BUF_LIMIT
+        {
+            match self.tcp.read(&mut buf) {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.recv_buf.extend_from_slice(&buf[..read]),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether more request data can be buffered.
+    fn has_send_capacity(&self) -> bool {
+        self.send_buf.len() < UPSTREAM_BUF_LIMIT
+    }
+
+    /// Buffer the request body data.
+    fn push_request_body(&mut self, data: &[u8]) {
+        if self.chunked {
+            self.send_buf
+                .extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+            self.send_buf.extend_from_slice(data);
+            self.send_buf.extend_from_slice(b"\r\n");
+        } else {
+            self.send_buf.extend_from_slice(data);
+        }
+    }
+
+    /// Buffer the end of the request body.
+    fn finish_request(&mut self) {
+        if self.chunked {
+            self.send_buf.extend_from_slice(b"0\r\n\r\n");
+            self.chunked = false;
+        }
+    }
+
+    /// Process the response data read from the upstream server.
+    fn process(&mut self) -> Result<()> {
+        while self.framing.is_none() {
+            let head_len = match self.recv_buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                Some(v) => v + 4,
+                None if self.eof => return Err("upstream closed before response".into()),
+                None => return Ok(()),
+            };
+            let head: Vec<u8> = self.recv_buf.drain(..head_len).collect();
+            self.parse_head(&head)?;
+        }
+
+        let mut framing = self.framing.unwrap();
+        let consumed = framing.decode(&self.recv_buf, &mut self.body)?;
+        self.recv_buf.drain(..consumed);
+        if self.eof && framing != BodyFraming::Done {
+            if framing != BodyFraming::Close {
+                return Err("upstream closed before the end of response".into());
+            }
+            framing = BodyFraming::Done;
+        }
+        self.framing = Some(framing);
+        Ok(())
+    }
+
+    /// Parse the response head and convert it to h3 headers. Informational
+    /// responses are skipped.
+    fn parse_head(&mut self, head: &[u8]) -> Result<()> {
+        let head = std::str::from_utf8(head)?;
+        let mut lines = head.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|v| v.parse::<u16>().ok())
+            .ok_or("invalid status line")?;
+        if (100..200).contains(&status) {
+            return Ok(());
+        }
+
+        let mut headers = vec![Header::new(b":status", status.to_string().as_bytes())];
+        let mut chunked = false;
+        let mut content_length = None;
+        for line in lines.filter(|line| !line.is_empty()) {
+            let (name, value) = line.split_once(':').ok_or("invalid header line")?;
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim();
+            match name.as_str() {
+                "transfer-encoding" => chunked = value.to_ascii_lowercase().ends_with("chunked"),
+                "content-length" => content_length = Some(value.parse::<u64>()?),
+                _ => (),
+            }
+            if !HOP_BY_HOP_HEADERS.contains(&name.as_bytes()) {
+                headers.push(Header::new(name.as_bytes(), value.as_bytes()));
+            }
+        }
+
+        let framing = match (chunked, content_length) {
+            _ if self.head || status == 204 || status == 304 => BodyFraming::Done,
+            (true, _) => BodyFraming::Chunked(ChunkState::Size),
+            (false, Some(0)) => BodyFraming::Done,
+            (false, Some(n)) => BodyFraming::Length(n),
+            (false, None) => BodyFraming::Close,
+        };
+        if chunked {
+            // The content length is ignored if the chunked coding is used.
+            headers.retain(|h| h.name() != b"content-length");
+        }
+        self.headers = Some(headers);
+        self.framing = Some(framing);
+        Ok(())
+    }
+
+    /// Whether the whole response body has been received.
+    fn body_done(&self) -> bool {
+        self.framing == Some(BodyFraming::Done)
+    }
+}
+
+/// A reverse proxy which forwards h3 requests to an HTTP/1.1 upstream server.
+/// Each request is forwarded over a new TCP connection.
+struct Proxy {
+    /// Address of the upstream server.
+    upstream: SocketAddr,
+
+    /// Registry of the event poll.
+    registry: Registry,
+
+    /// Connections to the upstream server.
+    upstreams: Slab<Upstream>,
+
+    /// Mapping QUIC connection index and stream id to upstream connection.
+    streams: FxHashMap<(u64, u64), usize>,
+}
+
+impl Proxy {
+    fn new(upstream: SocketAddr, registry: Registry) -> Self {
+        Self {
+            upstream,
+            registry,
+            upstreams: Slab::new(),
+            streams: FxHashMap::default(),
+        }
+    }
+
+    /// Build the HTTP/1.1 request head from the h3 request headers. Return
+    /// the request head and whether the request body should be chunked.
+    fn build_request(headers: &[Header], fin: bool) -> Result<(Vec<u8>, bool)> {
+        let mut method = None;
+        let mut path = None;
+        let mut authority = None;
+        let mut fields = Vec::new();
+        let mut content_length = false;
+        for header in headers {
+            match header.name() {
+                b":method" => method = Some(header.value()),
+                b":path" => path = Some(header.value()),
+                b":authority" => authority = Some(header.value()),
+                b"host" if authority.is_none() => authority = Some(header.value()),
+                b"host" => (),
+                name if name.starts_with(b":") || HOP_BY_HOP_HEADERS.contains(&name) => (),
+                name => {
+                    content_length |= name == b"content-length";
+                    fields.push((name, header.value()));
+                }
+            }
+        }
+
+        let mut head = Vec::new();
+        head.extend_from_slice(method.ok_or("missing method")?);
+        head.push(b' ');
+        head.extend_from_slice(path.ok_or("missing path")?);
+        head.extend_from_slice(b" HTTP/1.1\r\nhost: ");
+        head.extend_from_slice(authority.ok_or("missing authority")?);
+        head.extend_from_slice(b"\r\n");
+        for (name, value) in fields {
+            head.extend_from_slice(name);
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(value);
+            head.extend_from_slice(b"\r\n");
+        }
+
+        let chunked = !fin && !content_length;
+        if chunked {
+            head.extend_from_slice(b"transfer-encoding: chunked\r\n");
+        }
+        head.extend_from_slice(b"connection: close\r\n\r\n");
+        Ok((head, chunked))
+    }
+
+    /// Forward the h3 request to the upstream server over a new connection.
+    fn open(
+        &mut self,
+        conn_index: u64,
+        stream_id: u64,
+        headers: &[Header],
+        fin: bool,
+    ) -> Result<()> {
+        let (head, chunked) = Self::build_request(headers, fin)?;
+        let head_only = headers
+            .iter()
+            .any(|h| h.name() == b":method" && h.value() == b"HEAD");
+
+        let mut tcp = TcpStream::connect(self.upstream)?;
+        let entry = self.upstreams.vacant_entry();
+        self.registry.register(
+            &mut tcp,
+            Token(UPSTREAM_TOKEN_BASE + entry.key()),
+            Interest::READABLE | Interest::WRITABLE,
+        )?;
+        self.streams.insert((conn_index, stream_id), entry.key());
+        entry.insert(Upstream {
+            conn_index,
+            stream_id,
+            tcp,
+            connected: false,
+            send_buf: head,
+            chunked,
+            head: head_only,
+            recv_buf: Vec::new(),
+            eof: false,
+            headers: None,
+            headers_sent: false,
+            framing: None,
+            body: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Return the upstream connection of the given request stream.
+    fn get_mut(&mut self, conn_index: u64, stream_id: u64) -> Option<&mut Upstream> {
+        let id = self.streams.get(&(conn_index, stream_id))?;
+        self.upstreams.get_mut(*id)
+    }
+
+    /// Close the upstream connection of the given request stream.
+    fn close(&mut self, conn_index: u64, stream_id: u64) {
+        if let Some(id) = self.streams.remove(&(conn_index, stream_id)) {
+            let mut upstream = self.upstreams.remove(id);
+            _ = self.registry.deregister(&mut upstream.tcp);
+        }
+    }
+
+    /// Close all upstream connections of the given QUIC connection.
+    fn close_conn(&mut self, conn_index: u64) {
+        let streams: Vec<u64> = self
+            .streams
+            .keys()
+            .filter(|(index, _)| *index == conn_index)
+            .map(|(_, stream_id)| *stream_id)
+            .collect();
+        for stream_id in streams {
+            self.close(conn_index, stream_id);
+        }
+    }
+
+    /// Process an IO event of the upstream connection. Return the request
+    /// stream to be woken up.
+    fn on_event(&mut self, event: &Event) -> Option<(u64, u64)> {
+        let upstream = self
+            .upstreams
+            .get_mut(event.token().0 - UPSTREAM_TOKEN_BASE)?;
+
+        if event.is_writable() && !upstream.connected {
+            match (upstream.tcp.take_error(), upstream.tcp.peer_addr()) {
+                (Ok(None), Ok(_)) => upstream.connected = true,
+                (Ok(None), Err(e)) if e.kind() == std::io::ErrorKind::NotConnected => (),
+                (Ok(Some(e)), _) | (Err(e), _) | (_, Err(e)) => {
+                    error!("connect upstream {:?} failed {:?}", self.upstream, e);
+                    upstream.eof = true;
+                }
+            }
+        }
+        if let Err(e) = upstream.flush() {
+            error!("write to upstream {:?} failed {:?}", self.upstream, e);
+            upstream.eof = true;
+        }
+
+        // The request stream reads the response and the remaining request body.
+        Some((upstream.conn_index, upstream.stream_id))
+    }
 }
 
 #[derive(Default)]
@@ -390,11 +1214,17 @@ struct ConnectionHandler {
     /// Mapping stream id to http/0.9 request line data, only used in http/0.9 mode.
     http09_requests: HashMap<u64, Vec<u8>>,
 
+    /// Mapping stream id to perf request header data, only used in perf mode.
+    perf_requests: HashMap<u64, Vec<u8>>,
+
     /// H3 connection, only used in h3 mode.
     h3_conn: Option<Http3Connection>,
 
     /// Mapping stream id to response.
     responses: HashMap<u64, Response>,
+
+    /// Reverse proxy, only used in h3 mode.
+    proxy: Option<Rc<RefCell<Proxy>>>,
 }
 
 impl ConnectionHandler {
@@ -433,36 +1263,30 @@ impl ConnectionHandler {
             stream_id
         );
 
-        let body = std::fs::read(path.as_path()).unwrap_or_else(|_| b"Not Found!\r\n".to_vec());
+        let response = match Self::open_file(&path) {
+            Some((file, metadata)) => Response::with_file(None, file, metadata.len()),
+            None => Response::new(None, Bytes::from_static(b"Not Found!\r\n")),
+        };
         debug!(
             "{} sending response of size {} on stream {}",
             conn.trace_id(),
-            body.len(),
+            response.body.len() as u64 + response.file_remaining,
             stream_id
         );
-        let body = Bytes::from(body);
-
-        let written = match conn.stream_write(stream_id, body.clone(), true) {
-            Ok(v) => v,
-            Err(tquic::error::Error::Done) => 0,
-            Err(e) => {
-                error!("{} stream write failed {:?}", conn.trace_id(), e);
-                return Ok(());
-            }
-        };
-        if written < body.len() {
-            _ = conn.stream_want_write(stream_id, true);
 
-            let response = Response {
-                headers: None,
-                body,
-                body_written: written,
-            };
+        self.responses.insert(stream_id, response);
+        self.send_http09_response(conn, stream_id);
+        Ok(())
+    }
 
-            self.responses.insert(stream_id, response);
+    /// Open the regular file with the given path.
+    fn open_file(path: &Path) -> Option<(File, std::fs::Metadata)> {
+        let file = File::open(path).ok()?;
+        let metadata = file.metadata().ok()?;
+        if !metadata.is_file() {
+            return None;
         }
-
-        Ok(())
+        Some((file, metadata))
     }
 
     fn recv_http09_request(&mut self, buf: &mut [u8], conn: &mut Connection, stream_id: u64) {
@@ -514,78 +1338,112 @@ impl ConnectionHandler {
         }
     }
 
-    fn build_h3_response(&self, headers: &[Header]) -> (Vec<Header>, Bytes) {
-        let mut path = "";
-        for header in headers {
-            if header.name() == b":path" {
-                path = std::str::from_utf8(header.value()).unwrap();
+    fn build_h3_headers(status: u16, content_length: u64) -> Vec<Header> {
+        vec![
+            tquic::h3::Header::new(b":status", status.to_string().as_bytes()),
+            tquic::h3::Header::new(b"server", b"tquic"),
+            tquic::h3::Header::new(b"content-length", content_length.to_string().as_bytes()),
+        ]
+    }
+
+    fn build_h3_response(&self, headers: &[Header]) -> Response {
+        let request = FileRequest::from_headers(headers);
+        let path = Self::generate_file_path(request.path, &self.root);
+
+        let (mut file, metadata) = match Self::open_file(&path) {
+            Some(v) => v,
+            None => {
+                let body = Bytes::from_static(b"Not Found!");
+                let headers = Self::build_h3_headers(404, body.len() as u64);
+                return Response::new(Some(headers), body);
             }
+        };
+        let len = metadata.len();
+        let validators = FileValidators::new(&metadata);
+
+        // Evaluate the preconditions. See RFC 9110 Section 13.2.2
+        let not_modified = match (request.if_none_match, request.if_modified_since) {
+            (Some(v), _) => validators.none_match(v),
+            (None, Some(v)) => validators.not_modified_since(v),
+            (None, None) => false,
+        };
+        if not_modified {
+            let mut headers = vec![
+                tquic::h3::Header::new(b":status", b"304"),
+                tquic::h3::Header::new(b"server", b"tquic"),
+            ];
+            validators.append_headers(&mut headers);
+            return Response::new(Some(headers), Bytes::new());
         }
-        let path = Self::generate_file_path(path, &self.root);
 
-        let (status, body) = {
-            match std::fs::read(path.as_path()) {
-                Ok(data) => (200, data),
-                Err(_) => (404, b"Not Found!".to_vec()),
-            }
+        let range = match (request.range, request.if_range) {
+            (Some(_), Some(if_range)) if !validators.range_match(if_range) => ByteRange::Full,
+            (Some(range), _) => ByteRange::parse(range, len),
+            (None, _) => ByteRange::Full,
         };
 
-        let headers = vec![
-            tquic::h3::Header::new(b":status", status.to_string().as_bytes()),
-            tquic::h3::Header::new(b"server", b"tquic"),
-            tquic::h3::Header::new(b"content-length", body.len().to_string().as_bytes()),
-        ];
+        let mut headers = match range {
+            ByteRange::Full => Self::build_h3_headers(200, len),
+            ByteRange::Partial(first, last) => {
+                if file.seek(SeekFrom::Start(first)).is_err() {
+                    let headers = Self::build_h3_headers(500, 0);
+                    return Response::new(Some(headers), Bytes::new());
+                }
+                let mut headers = Self::build_h3_headers(206, last - first + 1);
+                headers.push(tquic::h3::Header::new(
+                    b"content-range",
+                    format!("bytes {}-{}/{}", first, last, len).as_bytes(),
+                ));
+                headers
+            }
+            ByteRange::Unsatisfiable => {
+                let mut headers = Self::build_h3_headers(416, 0);
+                headers.push(tquic::h3::Header::new(
+                    b"content-range",
+                    format!("bytes */{}", len).as_bytes(),
+                ));
+                return Response::new(Some(headers), Bytes::new());
+            }
+        };
+        headers.push(tquic::h3::Header::new(b"accept-ranges", b"bytes"));
+        validators.append_headers(&mut headers);
 
-        (headers, Bytes::from(body))
+        let body_len = match range {
+            ByteRange::Partial(first, last) => last - first + 1,
+            _ => len,
+        };
+        Response::with_file(Some(headers), file, body_len)
     }
 
     fn process_h3_request(
         &mut self,
         headers: &[Header],
+        fin: bool,
         conn: &mut Connection,
         stream_id: u64,
     ) -> Result<()> {
-        conn.stream_shutdown(stream_id, tquic::Shutdown::Read, 0)?;
-        self.processed_requests = std::cmp::max(self.processed_requests, stream_id);
-
-        let (headers, body) = self.build_h3_response(headers);
-        let h3_conn = self.h3_conn.as_mut().unwrap();
-        match h3_conn.send_headers(conn, stream_id, &headers, false) {
-            Ok(v) => v,
-            Err(tquic::h3::Http3Error::StreamBlocked) => {
-                let response = Response {
-                    headers: Some(headers),
-                    body,
-                    body_written: 0,
-                };
-
-                self.responses.insert(stream_id, response);
-                return Ok(());
-            }
-            Err(e) => {
-                return Err(format!("{} stream send failed {:?}", conn.trace_id(), e).into());
-            }
+        // The request body, if any, is read and discarded later.
+        if fin {
+            conn.stream_shutdown(stream_id, tquic::Shutdown::Read, 0)?;
         }
+        self.processed_requests = std::cmp::max(self.processed_requests, stream_id);
 
-        let written = match h3_conn.send_body(conn, stream_id, body.clone(), true) {
-            Ok(v) => v,
-            Err(tquic::h3::Http3Error::Done) => 0,
-            Err(e) => {
-                return Err(format!("{} stream send failed {:?}", conn.trace_id(), e).into());
+        if let Some(proxy) = &self.proxy {
+            let index = conn.index().unwrap();
+            if let Err(e) = proxy.borrow_mut().open(index, stream_id, headers, fin) {
+                error!("{} forward request failed {:?}", conn.trace_id(), e);
+                let body = Bytes::from_static(b"Bad Gateway!");
+                let headers = Self::build_h3_headers(502, body.len() as u64);
+                self.responses
+                    .insert(stream_id, Response::new(Some(headers), body));
+                self.send_h3_response(conn, stream_id);
             }
-        };
-        if written < body.len() {
-            _ = conn.stream_want_write(stream_id, true);
-
-            let response = Response {
-                headers: None,
-                body,
-                body_written: written,
-            };
-
-            self.responses.insert(stream_id, response);
+            return Ok(());
         }
 
+        let response = self.build_h3_response(headers);
+        self.responses.insert(stream_id, response);
+        self.send_h3_response(conn, stream_id);
         Ok(())
     }
 
@@ -595,26 +1453,50 @@ impl ConnectionHandler {
         _ = h3_conn.send_goaway(conn, self.processed_requests);
     }
 
-    fn recv_h3_request(&mut self, conn: &mut Connection) {
+    fn recv_h3_request(&mut self, buf: &mut [u8], conn: &mut Connection) {
         loop {
             match self.h3_conn.as_mut().unwrap().poll(conn) {
-                Ok((stream_id, tquic::h3::Http3Event::Headers { headers, .. })) => {
+                Ok((stream_id, tquic::h3::Http3Event::Headers { headers, fin })) => {
                     debug!(
                         "{} got request {:?} on stream id {}",
                         conn.trace_id(),
                         headers,
                         stream_id
                     );
-                    if let Err(e) = self.process_h3_request(&headers, conn, stream_id) {
+                    if let Err(e) = self.process_h3_request(&headers, fin, conn, stream_id) {
                         error!("{:?}", e);
                         break;
                     }
                 }
+                Ok((stream_id, tquic::h3::Http3Event::Data)) if self.proxy.is_some() => {
+                    self.recv_proxy_request_body(buf, conn, stream_id);
+                }
                 Ok((stream_id, tquic::h3::Http3Event::Data)) => {
                     debug!("{} got data on stream id {}", conn.trace_id(), stream_id);
+                    let h3_conn = self.h3_conn.as_mut().unwrap();
+                    while let Ok(read) = h3_conn.recv_body(conn, stream_id, buf) {
+                        debug!(
+                            "{} discard {} bytes of request body on stream {}",
+                            conn.trace_id(),
+                            read,
+                            stream_id
+                        );
+                    }
+                }
+                Ok((stream_id, tquic::h3::Http3Event::Finished)) => {
+                    if let Some(proxy) = &self.proxy {
+                        let index = conn.index().unwrap();
+                        if let Some(upstream) = proxy.borrow_mut().get_mut(index, stream_id) {
+                            upstream.finish_request();
+                            _ = upstream.flush();
+                        }
+                    }
+                }
+                Ok((stream_id, tquic::h3::Http3Event::Reset { .. })) => {
+                    if let Some(proxy) = &self.proxy {
+                        proxy.borrow_mut().close(conn.index().unwrap(), stream_id);
+                    }
                 }
-                Ok((_, tquic::h3::Http3Event::Finished)) => (),
-                Ok((_, tquic::h3::Http3Event::Reset { .. })) => (),
                 Ok((_, tquic::h3::Http3Event::PriorityUpdate)) => (),
                 Ok((goaway_id, tquic::h3::Http3Event::GoAway)) => {
                     self.process_goaway(conn, goaway_id);
@@ -630,33 +1512,94 @@ impl ConnectionHandler {
         }
     }
 
+    /// Receive the perf request, and send the requested number of bytes after
+    /// the request is finished. The data following the header is discarded.
+    /// See draft-banks-quic-performance Section 2.3
+    fn recv_perf_request(&mut self, buf: &mut [u8], conn: &mut Connection, stream_id: u64) {
+        let header = match self.perf_requests.get_mut(&stream_id) {
+            Some(v) => v,
+            None => {
+                debug!("{} stream {} not exists", conn.trace_id(), stream_id);
+                return;
+            }
+        };
+
+        let mut finished = false;
+        while let Ok((read, fin)) = conn.stream_read(stream_id, buf) {
+            let len = cmp::min(PERF_HEADER_LEN - header.len(), read);
+            header.extend_from_slice(&buf[..len]);
+            if fin {
+                finished = true;
+                break;
+            }
+        }
+        if !finished {
+            return;
+        }
+
+        let header = self.perf_requests.remove(&stream_id).unwrap();
+        let size = match <[u8; PERF_HEADER_LEN]>::try_from(header.as_slice()) {
+            Ok(v) => u64::from_be_bytes(v),
+            Err(_) => {
+                error!(
+                    "{} perf request[{}] format error",
+                    conn.trace_id(),
+                    stream_id
+                );
+                _ = conn.stream_shutdown(stream_id, tquic::Shutdown::Write, 0);
+                return;
+            }
+        };
+        debug!(
+            "{} sending perf response of size {} on stream {}",
+            conn.trace_id(),
+            size,
+            stream_id
+        );
+
+        self.responses.insert(stream_id, Response::with_zeros(size));
+        self.send_http09_response(conn, stream_id);
+    }
+
     fn recv_request(&mut self, buf: &mut [u8], conn: &mut Connection, stream_id: u64) {
         match self.app_proto {
             ApplicationProto::Interop | ApplicationProto::Http09 => {
                 self.recv_http09_request(buf, conn, stream_id)
             }
-            ApplicationProto::H3 => self.recv_h3_request(conn),
+            ApplicationProto::H3 => self.recv_h3_request(buf, conn),
+            ApplicationProto::Perf => self.recv_perf_request(buf, conn, stream_id),
         }
     }
 
     fn send_http09_response(&mut self, conn: &mut Connection, stream_id: u64) {
         let response = self.responses.get_mut(&stream_id).unwrap();
-        let written = match conn.stream_write(
-            stream_id,
-            response.body.slice(response.body_written..),
-            true,
-        ) {
-            Ok(v) => v,
-            Err(tquic::error::Error::Done) => 0,
-            Err(e) => {
+        loop {
+            if let Err(e) = response.refill() {
                 self.responses.remove(&stream_id);
-                error!("{} stream write failed {:?}", conn.trace_id(), e);
+                error!("{} read file failed {:?}", conn.trace_id(), e);
+                _ = conn.stream_shutdown(stream_id, tquic::Shutdown::Write, 0);
+                return;
+            }
+
+            let (body, fin) = response.pending();
+            let written = match conn.stream_write(stream_id, body.clone(), fin) {
+                Ok(v) => v,
+                Err(tquic::error::Error::Done) => 0,
+                Err(e) => {
+                    self.responses.remove(&stream_id);
+                    error!("{} stream write failed {:?}", conn.trace_id(), e);
+                    return;
+                }
+            };
+            response.body_written += written;
+            if response.is_finished() {
+                self.responses.remove(&stream_id);
+                return;
+            }
+            if written < body.len() {
+                _ = conn.stream_want_write(stream_id, true);
                 return;
             }
-        };
-        response.body_written += written;
-        if response.body_written == response.body.len() {
-            self.responses.remove(&stream_id);
         }
     }
 
@@ -664,41 +1607,183 @@ impl ConnectionHandler {
         let h3_conn = self.h3_conn.as_mut().unwrap();
         let response = self.responses.get_mut(&stream_id).unwrap();
         if let Some(ref headers) = response.headers {
-            match h3_conn.send_headers(conn, stream_id, headers, false) {
+            // Responses without body are finished with the headers.
+            let fin = response.is_finished();
+            match h3_conn.send_headers(conn, stream_id, headers, fin) {
                 Ok(_) => (),
                 Err(tquic::h3::Http3Error::StreamBlocked) => {
                     debug!("{} stream blocked", conn.trace_id());
                     return;
                 }
                 Err(e) => {
+                    self.responses.remove(&stream_id);
                     error!("{} stream send failed {:?}", conn.trace_id(), e);
                     return;
                 }
             }
+            if fin {
+                self.responses.remove(&stream_id);
+                return;
+            }
         }
         response.headers = None;
 
-        let written = match h3_conn.send_body(
-            conn,
-            stream_id,
-            response.body.slice(response.body_written..),
-            true,
-        ) {
-            Ok(v) => v,
-            Err(tquic::h3::Http3Error::Done) => 0,
-            Err(e) => {
+        loop {
+            if let Err(e) = response.refill() {
+                self.responses.remove(&stream_id);
+                error!("{} read file failed {:?}", conn.trace_id(), e);
+                _ = conn.stream_shutdown(stream_id, tquic::Shutdown::Write, H3_INTERNAL_ERROR);
+                return;
+            }
+
+            let (body, fin) = response.pending();
+            let written = match h3_conn.send_body(conn, stream_id, body.clone(), fin) {
+                Ok(v) => v,
+                Err(tquic::h3::Http3Error::Done) => 0,
+                Err(e) => {
+                    self.responses.remove(&stream_id);
+                    error!("{} stream send failed {:?}", conn.trace_id(), e);
+                    return;
+                }
+            };
+            response.body_written += written;
+            if response.is_finished() {
                 self.responses.remove(&stream_id);
-                error!("{} stream send failed {:?}", conn.trace_id(), e);
                 return;
             }
+            if written < body.len() {
+                _ = conn.stream_want_write(stream_id, true);
+                return;
+            }
+        }
+    }
+
+    /// Forward the request body received from the client to the upstream.
+    fn recv_proxy_request_body(&mut self, buf: &mut [u8], conn: &mut Connection, stream_id: u64) {
+        let h3_conn = self.h3_conn.as_mut().unwrap();
+        let mut proxy = self.proxy.as_ref().unwrap().borrow_mut();
+        let upstream = match proxy.get_mut(conn.index().unwrap(), stream_id) {
+            Some(upstream) => upstream,
+            None => {
+                while h3_conn.recv_body(conn, stream_id, buf).is_ok() {}
+                return;
+            }
+        };
+
+        // Stop reading if too much data is buffered, and continue when the
+        // buffered data is written to the upstream.
+        while upstream.has_send_capacity() {
+            match h3_conn.recv_body(conn, stream_id, buf) {
+                Ok(read) => upstream.push_request_body(&buf[..read]),
+                Err(_) => break,
+            }
+        }
+        if let Err(e) = upstream.flush() {
+            error!("{} write to upstream failed {:?}", conn.trace_id(), e);
+            upstream.eof = true;
+        }
+    }
+
+    /// Forward the response from the upstream to the client. Return true if
+    /// the response is finished.
+    fn forward_proxy_response(
+        h3_conn: &mut Http3Connection,
+        conn: &mut Connection,
+        stream_id: u64,
+        upstream: &mut Upstream,
+    ) -> Result<bool> {
+        loop {
+            upstream.fill()?;
+            upstream.process()?;
+
+            if !upstream.headers_sent {
+                let headers = match &upstream.headers {
+                    Some(headers) => headers,
+                    None => return Ok(false),
+                };
+                let fin = upstream.body_done() && upstream.body.is_empty();
+                match h3_conn.send_headers(conn, stream_id, headers, fin) {
+                    Ok(_) => upstream.headers_sent = true,
+                    Err(tquic::h3::Http3Error::StreamBlocked) => return Ok(false),
+                    Err(e) => return Err(format!("send headers failed {:?}", e).into()),
+                }
+                if fin {
+                    return Ok(true);
+                }
+            }
+
+            let fin = upstream.body_done();
+            if upstream.body.is_empty() && !fin {
+                return Ok(false);
+            }
+            let body = Bytes::copy_from_slice(&upstream.body);
+            let written = match h3_conn.send_body(conn, stream_id, body, fin) {
+                Ok(v) => v,
+                Err(tquic::h3::Http3Error::Done) => 0,
+                Err(e) => return Err(format!("send body failed {:?}", e).into()),
+            };
+            let len = upstream.body.len();
+            upstream.body.drain(..written);
+            if written < len {
+                _ = conn.stream_want_write(stream_id, true);
+                return Ok(false);
+            }
+            if fin {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Forward data between the client and the upstream.
+    fn send_proxy_response(&mut self, buf: &mut [u8], conn: &mut Connection, stream_id: u64) {
+        self.recv_proxy_request_body(buf, conn, stream_id);
+
+        let h3_conn = self.h3_conn.as_mut().unwrap();
+        let index = conn.index().unwrap();
+        let mut proxy = self.proxy.as_ref().unwrap().borrow_mut();
+        let upstream = match proxy.get_mut(index, stream_id) {
+            Some(upstream) => upstream,
+            None => return,
         };
-        response.body_written += written;
-        if response.body_written == response.body.len() {
-            self.responses.remove(&stream_id);
+
+        match Self::forward_proxy_response(h3_conn, conn, stream_id, upstream) {
+            Ok(false) => return,
+            Ok(true) => debug!("{} proxy stream {} finished", conn.trace_id(), stream_id),
+            Err(e) if !upstream.headers_sent => {
+                error!(
+                    "{} proxy stream {} failed {:?}",
+                    conn.trace_id(),
+                    stream_id,
+                    e
+                );
+                let body = Bytes::from_static(b"Bad Gateway!");
+                let headers = Self::build_h3_headers(502, body.len() as u64);
+                self.responses
+                    .insert(stream_id, Response::new(Some(headers), body));
+            }
+            Err(e) => {
+                error!(
+                    "{} proxy stream {} failed {:?}",
+                    conn.trace_id(),
+                    stream_id,
+                    e
+                );
+                _ = conn.stream_shutdown(stream_id, tquic::Shutdown::Write, H3_INTERNAL_ERROR);
+            }
+        }
+        proxy.close(index, stream_id);
+        drop(proxy);
+
+        if self.responses.contains_key(&stream_id) {
+            self.send_h3_response(conn, stream_id);
         }
     }
 
-    fn send_responses(&mut self, conn: &mut Connection, stream_id: u64) {
+    fn send_responses(&mut self, buf: &mut [u8], conn: &mut Connection, stream_id: u64) {
+        if self.proxy.is_some() && !self.responses.contains_key(&stream_id) {
+            self.send_proxy_response(buf, conn, stream_id);
+            return;
+        }
         if !self.responses.contains_key(&stream_id) {
             return;
         }
@@ -706,7 +1791,7 @@ impl ConnectionHandler {
         _ = conn.stream_want_write(stream_id, true);
 
         match self.app_proto {
-            ApplicationProto::Interop | ApplicationProto::Http09 => {
+            ApplicationProto::Interop | ApplicationProto::Http09 | ApplicationProto::Perf => {
                 self.send_http09_response(conn, stream_id)
             }
             ApplicationProto::H3 => self.send_h3_response(conn, stream_id),
@@ -714,6 +1799,112 @@ impl ConnectionHandler {
     }
 }
 
+/// Number of client records which triggers the cleanup of idle records.
+const CLIENT_LIMITER_PRUNE_SIZE: usize = 4096;
+
+/// Per client state used for connection limits.
+struct ClientState {
+    /// Number of concurrent connections.
+    conns: usize,
+
+    /// Available tokens for new connections.
+    tokens: f64,
+
+    /// Last time the tokens are refilled.
+    last_refill_time: Instant,
+}
+
+/// Limits of concurrent connections and new connection rate per client IP.
+struct ClientLimiter {
+    /// Maximum number of concurrent connections.
+    max_conns: usize,
+
+    /// Maximum number of concurrent connections per client IP.
+    max_conns_per_ip: usize,
+
+    /// Maximum number of new connections per second per client IP.
+    max_new_conns_per_ip: u64,
+
+    /// Client states.
+    clients: HashMap<IpAddr, ClientState>,
+
+    /// Mapping admitted connection index to client IP.
+    conns: FxHashMap<u64, IpAddr>,
+}
+
+impl ClientLimiter {
+    fn new(option: &ServerOpt) -> Self {
+        Self {
+            max_conns: option.max_conns,
+            max_conns_per_ip: option.max_conns_per_ip,
+            max_new_conns_per_ip: option.max_new_conns_per_ip,
+            clients: HashMap::new(),
+            conns: FxHashMap::default(),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.max_conns > 0 || self.max_conns_per_ip > 0 || self.max_new_conns_per_ip > 0
+    }
+
+    /// Try to admit a new connection from the client. Return the reason if
+    /// the connection should be refused.
+    fn admit(&mut self, index: u64, ip: IpAddr, now: Instant) -> Option<&'static str> {
+        if self.max_conns > 0 && self.conns.len() >= self.max_conns {
+            return Some("too many connections");
+        }
+
+        // Remove idle clients whose token bucket is already full.
+        let rate = self.max_new_conns_per_ip as f64;
+        if self.clients.len() >= CLIENT_LIMITER_PRUNE_SIZE {
+            self.clients.retain(|_, c| {
+                let elapsed = now.saturating_duration_since(c.last_refill_time);
+                c.conns > 0 || c.tokens + rate * elapsed.as_secs_f64() < rate
+            });
+        }
+
+        let client = self.clients.entry(ip).or_insert(ClientState {
+            conns: 0,
+            tokens: rate,
+            last_refill_time: now,
+        });
+
+        if self.max_conns_per_ip > 0 && client.conns >= self.max_conns_per_ip {
+            return Some("too many connections from the client");
+        }
+
+        if rate > 0.0 {
+            // The bucket holds at most one second worth of tokens.
+            let elapsed = now.saturating_duration_since(client.last_refill_time);
+            client.tokens = (client.tokens + rate * elapsed.as_secs_f64()).min(rate);
+            client.last_refill_time = now;
+            if client.tokens < 1.0 {
+                return Some("connection rate limit reached");
+            }
+            client.tokens -= 1.0;
+        }
+
+        client.conns += 1;
+        self.conns.insert(index, ip);
+        None
+    }
+
+    /// Release the connection if it was admitted.
+    fn release(&mut self, index: u64) {
+        let ip = match self.conns.remove(&index) {
+            Some(ip) => ip,
+            None => return,
+        };
+
+        if let Some(client) = self.clients.get_mut(&ip) {
+            client.conns = client.conns.saturating_sub(1);
+            if client.conns == 0 && self.max_new_conns_per_ip == 0 {
+                self.clients.remove(&ip);
+            }
+        }
+    }
+}
+
 struct ServerHandler {
     /// File root directory.
     root: String,
@@ -729,10 +1920,16 @@ struct ServerHandler {
 
     /// Qlog directory
     qlog_dir: Option<String>,
+
+    /// Reverse proxy
+    proxy: Option<Rc<RefCell<Proxy>>>,
+
+    /// Connection limits per client
+    limiter: ClientLimiter,
 }
 
 impl ServerHandler {
-    fn new(option: &ServerOpt) -> Result<Self> {
+    fn new(option: &ServerOpt, proxy: Option<Rc<RefCell<Proxy>>>) -> Result<Self> {
         let keylog = match &option.keylog_file {
             Some(keylog_file) => Some(
                 std::fs::OpenOptions::new()
@@ -749,6 +1946,8 @@ impl ServerHandler {
             conns: FxHashMap::default(),
             keylog,
             qlog_dir: option.qlog_dir.clone(),
+            proxy,
+            limiter: ClientLimiter::new(option),
         })
     }
 
@@ -769,6 +1968,7 @@ impl ServerHandler {
             conn_handler.h3_conn = Some(
                 Http3Connection::new_with_quic_conn(conn, &Http3Config::new().unwrap()).unwrap(),
             );
+            conn_handler.proxy = self.proxy.clone();
         }
 
         self.conns.insert(index, conn_handler);
@@ -778,6 +1978,23 @@ impl ServerHandler {
 impl TransportHandler for ServerHandler {
     fn on_conn_created(&mut self, conn: &mut Connection) {
         debug!("{} connection is created", conn.trace_id());
+        if self.limiter.is_enabled() {
+            let index = conn.index().unwrap();
+            if let Some(path) = conn.paths_iter().next() {
+                let ip = path.remote.ip();
+                if let Some(reason) = self.limiter.admit(index, ip, Instant::now()) {
+                    warn!(
+                        "{} refuse connection from {}: {}",
+                        conn.trace_id(),
+                        ip,
+                        reason
+                    );
+                    _ = conn.close(false, CONNECTION_REFUSED, reason.as_bytes());
+                    return;
+                }
+            }
+        }
+
         if let Some(keylog) = &mut self.keylog {
             if let Ok(keylog) = keylog.try_clone() {
                 conn.set_keylog(Box::new(keylog));
@@ -831,6 +2048,10 @@ impl TransportHandler for ServerHandler {
 
         let index = conn.index().unwrap();
         self.conns.remove(&index);
+        self.limiter.release(index);
+        if let Some(proxy) = &self.proxy {
+            proxy.borrow_mut().close_conn(index);
+        }
     }
 
     fn on_stream_created(&mut self, conn: &mut Connection, stream_id: u64) {
@@ -845,6 +2066,8 @@ impl TransportHandler for ServerHandler {
             || conn_handler.app_proto == ApplicationProto::Http09
         {
             conn_handler.http09_requests.insert(stream_id, b"".to_vec());
+        } else if conn_handler.app_proto == ApplicationProto::Perf {
+            conn_handler.perf_requests.insert(stream_id, Vec::new());
         }
     }
 
@@ -859,7 +2082,7 @@ impl TransportHandler for ServerHandler {
 
         let index = conn.index().unwrap();
         let conn_handler = self.conns.get_mut(&index).unwrap();
-        conn_handler.send_responses(conn, stream_id);
+        conn_handler.send_responses(&mut self.buf, conn, stream_id);
     }
 
     fn on_stream_closed(&mut self, conn: &mut Connection, stream_id: u64) {
@@ -867,6 +2090,33 @@ impl TransportHandler for ServerHandler {
     }
 
     fn on_new_token(&mut self, _conn: &mut Connection, _token: Vec<u8>) {}
+
+    fn on_ping_acked(&mut self, _conn: &mut Connection, _tag: u64, _rtt: Duration) {}
+
+    fn on_flow_control_blocked(&mut self, conn: &mut Connection, event: BlockedEvent) {
+        debug!("{} flow control blocked: {:?}", conn.trace_id(), event);
+    }
+
+    fn on_target_bitrate_updated(&mut self, conn: &mut Connection, bitrate: u64) {
+        debug!("{} target bitrate updated: {}", conn.trace_id(), bitrate);
+    }
+
+    fn on_persistent_congestion(
+        &mut self,
+        conn: &mut Connection,
+        event: PersistentCongestionEvent,
+    ) {
+        warn!("{} persistent congestion: {:?}", conn.trace_id(), event);
+    }
+
+    fn on_stream_event(&mut self, conn: &mut Connection, stream_id: u64, event: StreamEvent) {
+        debug!(
+            "{} stream {} event: {:?}",
+            conn.trace_id(),
+            stream_id,
+            event
+        );
+    }
 }
 
 fn process_option(option: &mut ServerOpt) -> Result<()> {
@@ -905,7 +2155,10 @@ fn main() -> Result<()> {
             error!("process connections error: {:?}", e);
         }
 
-        let timeout = server.endpoint.timeout();
+        let timeout = match (server.endpoint.timeout(), server.sock.timeout()) {
+            (Some(t1), Some(t2)) => Some(t1.min(t2)),
+            (t1, t2) => t1.or(t2),
+        };
         debug!(
             "{} wait for io events, timeout: {:?}",
             server.endpoint.trace_id(),
@@ -915,7 +2168,9 @@ fn main() -> Result<()> {
 
         // Process IO events
         for event in events.iter() {
-            if event.is_readable() {
+            if event.token().0 >= UPSTREAM_TOKEN_BASE {
+                server.process_upstream_event(event);
+            } else if event.is_readable() {
                 server.process_read_event(event)?;
             }
         }
@@ -924,5 +2179,8 @@ fn main() -> Result<()> {
         // Note: Since `poll()` doesn't clearly tell if there was a timeout when it returns,
         // it is up to the endpoint to check for a timeout and deal with it.
         server.endpoint.on_timeout(Instant::now());
+
+        // Send packets delayed by the network emulation.
+        server.sock.flush_delayed()?;
     }
 }
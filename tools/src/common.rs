@@ -12,8 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
 use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use clap::builder::PossibleValue;
 use clap::ValueEnum;
@@ -23,6 +32,7 @@ use mio::net::UdpSocket;
 use mio::Interest;
 use mio::Registry;
 use mio::Token;
+use rand::Rng;
 use rustc_hash::FxHashMap;
 use slab::Slab;
 
@@ -43,6 +53,9 @@ pub enum ApplicationProto {
     /// HTTP/3, see https://www.rfc-editor.org/rfc/rfc9114.html
     #[default]
     H3,
+
+    /// QUIC performance protocol, see https://datatracker.ietf.org/doc/html/draft-banks-quic-performance
+    Perf,
 }
 
 impl ApplicationProto {
@@ -52,6 +65,7 @@ impl ApplicationProto {
             b"hq-interop" => Self::Interop,
             b"http/0.9" => Self::Http09,
             b"h3" => Self::H3,
+            b"perf" => Self::Perf,
             _ => unreachable!(),
         }
     }
@@ -62,6 +76,7 @@ impl ApplicationProto {
             Self::Interop => b"hq-interop",
             Self::Http09 => b"http/0.9",
             Self::H3 => b"h3",
+            Self::Perf => b"perf",
         }
     }
 
@@ -80,14 +95,61 @@ impl ValueEnum for ApplicationProto {
             Self::Interop => PossibleValue::new("hq-interop"),
             Self::Http09 => PossibleValue::new("http/0.9"),
             Self::H3 => PossibleValue::new("h3"),
+            Self::Perf => PossibleValue::new("perf"),
         })
     }
 
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::Interop, Self::Http09, Self::H3]
+        &[Self::Interop, Self::Http09, Self::H3, Self::Perf]
     }
 }
 
+/// Network impairments emulated on outgoing packets.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct NetEmulation {
+    /// Percentage of packets to be dropped.
+    pub loss: f64,
+
+    /// Delay added to each packet.
+    pub delay: Duration,
+
+    /// Percentage of packets to be sent immediately without the delay, so
+    /// that they overtake the delayed packets. It takes effect only if the
+    /// delay is not zero.
+    pub reorder: f64,
+}
+
+impl NetEmulation {
+    /// Create a new NetEmulation with percentages and delay in milliseconds.
+    pub fn new(loss: f64, delay: u64, reorder: f64) -> Self {
+        Self {
+            loss: loss.clamp(0.0, 100.0),
+            delay: Duration::from_millis(delay),
+            reorder: reorder.clamp(0.0, 100.0),
+        }
+    }
+
+    /// Whether any impairment is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.loss > 0.0 || !self.delay.is_zero()
+    }
+}
+
+/// Packet held back by the network emulation.
+struct DelayedPacket {
+    /// Time to send the packet.
+    deadline: Instant,
+
+    /// Packet data.
+    buf: Vec<u8>,
+
+    /// Source address of the packet.
+    src: SocketAddr,
+
+    /// Destination address of the packet.
+    dst: SocketAddr,
+}
+
 /// UDP socket wrapper for QUIC
 pub struct QuicSocket {
     /// The underlying UDP sockets for QUIC Endpoint.
@@ -98,6 +160,12 @@ pub struct QuicSocket {
 
     /// Local address of the initial socket.
     local_addr: SocketAddr,
+
+    /// Network impairments emulated on outgoing packets.
+    emulation: NetEmulation,
+
+    /// Packets delayed by the network emulation, in the order of deadline.
+    delayed: RefCell<VecDeque<DelayedPacket>>,
 }
 
 impl QuicSocket {
@@ -117,9 +185,73 @@ impl QuicSocket {
             socks,
             addrs,
             local_addr,
+            emulation: NetEmulation::default(),
+            delayed: RefCell::new(VecDeque::new()),
         })
     }
 
+    /// Set the network impairments emulated on outgoing packets.
+    pub fn set_emulation(&mut self, emulation: NetEmulation) {
+        if emulation.is_enabled() {
+            info!("emulate network impairments {:?}", emulation);
+        }
+        self.emulation = emulation;
+    }
+
+    /// Return the amount of time until the next delayed packet should be sent.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.delayed
+            .borrow()
+            .front()
+            .map(|p| p.deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Send the delayed packets whose deadline has been reached.
+    pub fn flush_delayed(&self) -> std::io::Result<()> {
+        let now = Instant::now();
+        let mut delayed = self.delayed.borrow_mut();
+        while let Some(p) = delayed.front() {
+            if p.deadline > now {
+                break;
+            }
+            match self.send_to(&p.buf, p.src, p.dst) {
+                Ok(_) => (),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    debug!("socket send would block");
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+            delayed.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Apply the network emulation on the outgoing packet. Return true if the
+    /// packet was dropped or delayed.
+    fn emulate(&self, buf: &[u8], info: &PacketInfo) -> bool {
+        if !self.emulation.is_enabled() {
+            return false;
+        }
+
+        let mut rng = rand::thread_rng();
+        if rng.gen_bool(self.emulation.loss / 100.0) {
+            debug!("emulation drop {} bytes to {:?}", buf.len(), info.dst);
+            return true;
+        }
+        if self.emulation.delay.is_zero() || rng.gen_bool(self.emulation.reorder / 100.0) {
+            return false;
+        }
+
+        self.delayed.borrow_mut().push_back(DelayedPacket {
+            deadline: Instant::now() + self.emulation.delay,
+            buf: buf.to_vec(),
+            src: info.src,
+            dst: info.dst,
+        });
+        true
+    }
+
     /// Return the local address of the initial socket.
     pub fn local_addr(&self) -> SocketAddr {
         self.local_addr
@@ -196,6 +328,10 @@ impl PacketSendHandler for QuicSocket {
     fn on_packets_send(&self, pkts: &[(Vec<u8>, PacketInfo)]) -> tquic::Result<usize> {
         let mut count = 0;
         for (pkt, info) in pkts {
+            if self.emulate(pkt, info) {
+                count += 1;
+                continue;
+            }
             if let Err(e) = self.send_to(pkt, info.src, info.dst) {
                 if e.kind() == std::io::ErrorKind::WouldBlock {
                     debug!("socket send would block");
@@ -228,3 +364,160 @@ pub fn log_target(log_file: &Option<String>) -> Result<Target> {
 
     Ok(Target::Stderr)
 }
+
+/// Return the IP addresses assigned to the given network interface.
+/// Note: IPv6 link-local addresses are ignored.
+#[cfg(unix)]
+pub fn interface_addresses(name: &str) -> Result<Vec<IpAddr>> {
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifap) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let mut addrs = Vec::new();
+    let mut ifa = ifap;
+    while !ifa.is_null() {
+        let entry = unsafe { &*ifa };
+        ifa = entry.ifa_next;
+        if entry.ifa_addr.is_null() {
+            continue;
+        }
+        let ifname = unsafe { std::ffi::CStr::from_ptr(entry.ifa_name) };
+        if ifname.to_bytes() != name.as_bytes() {
+            continue;
+        }
+
+        match unsafe { (*entry.ifa_addr).sa_family } as libc::c_int {
+            libc::AF_INET => {
+                let sin = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in) };
+                let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+                addrs.push(IpAddr::V4(ip));
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in6) };
+                let ip = Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+                if ip.segments()[0] & 0xffc0 != 0xfe80 {
+                    addrs.push(IpAddr::V6(ip));
+                }
+            }
+            _ => (),
+        }
+    }
+    unsafe { libc::freeifaddrs(ifap) };
+
+    Ok(addrs)
+}
+
+/// Return the IP addresses assigned to the given network interface.
+#[cfg(not(unix))]
+pub fn interface_addresses(name: &str) -> Result<Vec<IpAddr>> {
+    Err(format!("resolve interface {} is not supported", name).into())
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Return the number of seconds since the UNIX epoch.
+pub fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Format the time as an HTTP date in IMF-fixdate format,
+/// e.g. "Sun, 06 Nov 1994 08:49:37 GMT". See RFC 9110 Section 5.6.7
+pub fn format_http_date(t: SystemTime) -> String {
+    let secs = unix_secs(t);
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        // 1970-01-01 was a Thursday.
+        WEEKDAYS[((days + 4) % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Format the time in ISO 8601 format with milliseconds in UTC,
+/// e.g. "1994-11-06T08:49:37.000Z".
+pub fn format_iso8601(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let secs_of_day = secs % 86400;
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        d.subsec_millis()
+    )
+}
+
+/// Parse an HTTP date in IMF-fixdate format. The obsolete formats are not
+/// supported.
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    if parts.len() != 6 || !parts[0].ends_with(',') || parts[5] != "GMT" {
+        return None;
+    }
+
+    let day: u32 = parts[1].parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == parts[2])? as u32 + 1;
+    let year: i64 = parts[3].parse().ok()?;
+    let time: Vec<u64> = parts[4]
+        .split(':')
+        .map(|v| v.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if day == 0 || day > 31 || time.len() != 3 || time[0] > 23 || time[1] > 59 || time[2] > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    let secs = days as u64 * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Convert a date in the proleptic Gregorian calendar to the number of days
+/// since the UNIX epoch.
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let (month, day) = (month as i64, day as i64);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Convert the number of days since the UNIX epoch to a date in the proleptic
+/// Gregorian calendar.
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}